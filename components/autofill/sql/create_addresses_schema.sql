@@ -0,0 +1,37 @@
+-- This Source Code Form is subject to the terms of the Mozilla Public
+-- License, v. 2.0. If a copy of the MPL was not distributed with this
+-- file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+-- Postal addresses, in their own file since they were added in version 3.
+
+CREATE TABLE IF NOT EXISTS addresses_data (
+    guid TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    given_name TEXT NOT NULL,
+    additional_name TEXT NOT NULL,
+    family_name TEXT NOT NULL,
+    organization TEXT NOT NULL,
+    -- Lines are separated by newlines.
+    street_address TEXT NOT NULL,
+    -- What each level means depends on the country: for example, the
+    -- state, city and (rarely) neighborhood in the US, or the prefecture,
+    -- city and district in Japan.
+    address_level3 TEXT NOT NULL,
+    address_level2 TEXT NOT NULL,
+    address_level1 TEXT NOT NULL,
+    postal_code TEXT NOT NULL,
+    -- An ISO 3166-1 alpha-2 code, like "US", or empty.
+    country TEXT NOT NULL,
+    tel TEXT NOT NULL,
+    email TEXT NOT NULL,
+    time_created INTEGER NOT NULL,
+    time_last_used INTEGER,
+    time_last_modified INTEGER NOT NULL,
+    times_used INTEGER NOT NULL DEFAULT 0,
+    sync_change_counter INTEGER NOT NULL DEFAULT 1,
+    sync_status INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS addresses_tombstones (
+    guid TEXT PRIMARY KEY CHECK(length(guid) != 0),
+    time_deleted INTEGER NOT NULL
+) WITHOUT ROWID;
@@ -0,0 +1,40 @@
+-- This Source Code Form is subject to the terms of the Mozilla Public
+-- License, v. 2.0. If a copy of the MPL was not distributed with this
+-- file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+CREATE TABLE IF NOT EXISTS credit_cards_data (
+    guid TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    cc_name TEXT NOT NULL,
+    -- The card number, encrypted with the key the store was opened with.
+    cc_number_enc TEXT NOT NULL CHECK(length(cc_number_enc) != 0),
+    -- The last 4 digits and the type (like "visa") aren't encrypted, so that
+    -- cards can be shown without the key.
+    cc_number_last_4 TEXT NOT NULL CHECK(length(cc_number_last_4) <= 4),
+    cc_exp_month INTEGER,
+    cc_exp_year INTEGER,
+    cc_type TEXT NOT NULL,
+    -- Timestamps are in milliseconds since the unix epoch.
+    time_created INTEGER NOT NULL,
+    time_last_used INTEGER,
+    time_last_modified INTEGER NOT NULL,
+    times_used INTEGER NOT NULL DEFAULT 0,
+    -- Bumped for every local change, so that sync can tell which cards
+    -- changed since they were last uploaded.
+    sync_change_counter INTEGER NOT NULL DEFAULT 1,
+    -- 0 if the card has never been uploaded or downloaded, 1 if the server
+    -- knows about it. Only cards the server doesn't know about yet are
+    -- candidates for deduping against incoming cards.
+    sync_status INTEGER NOT NULL DEFAULT 0
+);
+
+-- Cards which were deleted locally after being synced, so that the deletion
+-- can be uploaded.
+CREATE TABLE IF NOT EXISTS credit_cards_tombstones (
+    guid TEXT PRIMARY KEY CHECK(length(guid) != 0),
+    time_deleted INTEGER NOT NULL
+) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS moz_meta (
+    key TEXT PRIMARY KEY,
+    value NOT NULL
+) WITHOUT ROWID;
@@ -0,0 +1,396 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::db::{now_ms, AutofillDb};
+use crate::error::*;
+use rusqlite::{named_params, Row};
+use sql_support::ConnExt;
+use sync_guid::Guid;
+
+/// The fields of a postal address which are provided when it's added or
+/// updated. The fields are the same as desktop's, which follow the
+/// [HTML autocomplete names](https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#autofill),
+/// so that addresses from any country fit:
+///
+/// - `street_address` has one line of the street address per line, above
+///   the administrative levels.
+/// - `address_level1` to `address_level3` are the administrative areas, from
+///   the broadest to the narrowest, and their meaning depends on the country.
+///   In the US, `address_level1` is the state and `address_level2` the city;
+///   in Japan, they're the prefecture and the city, and `address_level3` is
+///   the district.
+/// - `country` is an ISO 3166-1 alpha-2 code, like `US`.
+///
+/// Leading and trailing whitespace is removed from every field, and the
+/// country is upper-cased.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdatableAddressFields {
+    pub given_name: String,
+    pub additional_name: String,
+    pub family_name: String,
+    pub organization: String,
+    pub street_address: String,
+    pub address_level3: String,
+    pub address_level2: String,
+    pub address_level1: String,
+    pub postal_code: String,
+    pub country: String,
+    pub tel: String,
+    pub email: String,
+}
+
+/// A postal address, as returned by the store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub guid: Guid,
+    pub fields: UpdatableAddressFields,
+    // Timestamps are in milliseconds since the unix epoch.
+    pub time_created: i64,
+    pub time_last_used: Option<i64>,
+    pub time_last_modified: i64,
+    pub times_used: i64,
+}
+
+impl Address {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            guid: row.get("guid")?,
+            fields: UpdatableAddressFields {
+                given_name: row.get("given_name")?,
+                additional_name: row.get("additional_name")?,
+                family_name: row.get("family_name")?,
+                organization: row.get("organization")?,
+                street_address: row.get("street_address")?,
+                address_level3: row.get("address_level3")?,
+                address_level2: row.get("address_level2")?,
+                address_level1: row.get("address_level1")?,
+                postal_code: row.get("postal_code")?,
+                country: row.get("country")?,
+                tel: row.get("tel")?,
+                email: row.get("email")?,
+            },
+            time_created: row.get("time_created")?,
+            time_last_used: row.get("time_last_used")?,
+            time_last_modified: row.get("time_last_modified")?,
+            times_used: row.get("times_used")?,
+        })
+    }
+}
+
+const ADDRESS_COLS: &str = "
+    guid,
+    given_name,
+    additional_name,
+    family_name,
+    organization,
+    street_address,
+    address_level3,
+    address_level2,
+    address_level1,
+    postal_code,
+    country,
+    tel,
+    email,
+    time_created,
+    time_last_used,
+    time_last_modified,
+    times_used";
+
+impl UpdatableAddressFields {
+    // Returns the fields with their whitespace trimmed (and, for the street
+    // address, the whitespace of each line, leaving out empty ones), or an
+    // error if they're not a plausible address.
+    fn normalize(self) -> Result<Self> {
+        let trim = |s: String| s.trim().to_owned();
+        let fields = Self {
+            given_name: trim(self.given_name),
+            additional_name: trim(self.additional_name),
+            family_name: trim(self.family_name),
+            organization: trim(self.organization),
+            street_address: self
+                .street_address
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            address_level3: trim(self.address_level3),
+            address_level2: trim(self.address_level2),
+            address_level1: trim(self.address_level1),
+            postal_code: trim(self.postal_code),
+            country: self.country.trim().to_ascii_uppercase(),
+            tel: trim(self.tel),
+            email: trim(self.email),
+        };
+        if !fields.country.is_empty()
+            && (fields.country.len() != 2
+                || !fields.country.chars().all(|c| c.is_ascii_uppercase()))
+        {
+            return Err(ErrorKind::InvalidAddress(format!(
+                "{:?} isn't an ISO 3166-1 country code",
+                fields.country
+            ))
+            .into());
+        }
+        if fields == Self::default() {
+            return Err(ErrorKind::InvalidAddress("Every field is empty".into()).into());
+        }
+        Ok(fields)
+    }
+}
+
+impl AutofillDb {
+    pub fn add_address(&self, fields: UpdatableAddressFields) -> Result<Address> {
+        let now = now_ms();
+        let address = Address {
+            guid: Guid::random(),
+            fields: fields.normalize()?,
+            time_created: now,
+            time_last_used: None,
+            time_last_modified: now,
+            times_used: 0,
+        };
+        self.insert_address(&address, 1, false)?;
+        Ok(address)
+    }
+
+    // Inserts or replaces an address, with the given sync state.
+    pub(crate) fn insert_address(
+        &self,
+        address: &Address,
+        sync_change_counter: i64,
+        synced: bool,
+    ) -> Result<()> {
+        let fields = &address.fields;
+        self.execute_named_cached(
+            &format!(
+                "INSERT OR REPLACE INTO addresses_data ({cols}, sync_change_counter, sync_status)
+                 VALUES (:guid, :given_name, :additional_name, :family_name, :organization,
+                         :street_address, :address_level3, :address_level2, :address_level1,
+                         :postal_code, :country, :tel, :email, :time_created, :time_last_used,
+                         :time_last_modified, :times_used, :sync_change_counter, :sync_status)",
+                cols = ADDRESS_COLS
+            ),
+            named_params! {
+                ":guid": address.guid,
+                ":given_name": fields.given_name,
+                ":additional_name": fields.additional_name,
+                ":family_name": fields.family_name,
+                ":organization": fields.organization,
+                ":street_address": fields.street_address,
+                ":address_level3": fields.address_level3,
+                ":address_level2": fields.address_level2,
+                ":address_level1": fields.address_level1,
+                ":postal_code": fields.postal_code,
+                ":country": fields.country,
+                ":tel": fields.tel,
+                ":email": fields.email,
+                ":time_created": address.time_created,
+                ":time_last_used": address.time_last_used,
+                ":time_last_modified": address.time_last_modified,
+                ":times_used": address.times_used,
+                ":sync_change_counter": sync_change_counter,
+                ":sync_status": synced,
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn get_address(&self, guid: &Guid) -> Result<Option<Address>> {
+        self.try_query_row(
+            &format!(
+                "SELECT {cols} FROM addresses_data WHERE guid = :guid",
+                cols = ADDRESS_COLS
+            ),
+            named_params! { ":guid": guid },
+            Address::from_row,
+            true,
+        )
+    }
+
+    pub fn get_all_addresses(&self) -> Result<Vec<Address>> {
+        self.query_rows_and_then_named_cached(
+            &format!(
+                "SELECT {cols} FROM addresses_data ORDER BY time_created",
+                cols = ADDRESS_COLS
+            ),
+            &[],
+            Address::from_row,
+        )
+    }
+
+    /// Replaces the fields of the address with the given GUID. Its usage
+    /// counters are kept.
+    pub fn update_address(&self, guid: &Guid, fields: UpdatableAddressFields) -> Result<()> {
+        let fields = fields.normalize()?;
+        let changed = self.execute_named(
+            "UPDATE addresses_data
+             SET given_name = :given_name,
+                 additional_name = :additional_name,
+                 family_name = :family_name,
+                 organization = :organization,
+                 street_address = :street_address,
+                 address_level3 = :address_level3,
+                 address_level2 = :address_level2,
+                 address_level1 = :address_level1,
+                 postal_code = :postal_code,
+                 country = :country,
+                 tel = :tel,
+                 email = :email,
+                 time_last_modified = :now,
+                 sync_change_counter = sync_change_counter + 1
+             WHERE guid = :guid",
+            named_params! {
+                ":guid": guid,
+                ":given_name": fields.given_name,
+                ":additional_name": fields.additional_name,
+                ":family_name": fields.family_name,
+                ":organization": fields.organization,
+                ":street_address": fields.street_address,
+                ":address_level3": fields.address_level3,
+                ":address_level2": fields.address_level2,
+                ":address_level1": fields.address_level1,
+                ":postal_code": fields.postal_code,
+                ":country": fields.country,
+                ":tel": fields.tel,
+                ":email": fields.email,
+                ":now": now_ms(),
+            },
+        )?;
+        if changed == 0 {
+            return Err(ErrorKind::NoSuchRecord(guid.to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// Deletes the address with the given GUID, returning whether it
+    /// existed. If the address was synced, a tombstone is kept so that the
+    /// deletion can be uploaded.
+    pub fn delete_address(&self, guid: &Guid) -> Result<bool> {
+        let tx = self.unchecked_transaction()?;
+        tx.execute_named(
+            "INSERT OR IGNORE INTO addresses_tombstones (guid, time_deleted)
+             SELECT guid, :now FROM addresses_data
+             WHERE guid = :guid AND sync_status = 1",
+            named_params! {
+                ":guid": guid,
+                ":now": now_ms(),
+            },
+        )?;
+        let deleted = tx.execute_named(
+            "DELETE FROM addresses_data WHERE guid = :guid",
+            named_params! { ":guid": guid },
+        )?;
+        tx.commit()?;
+        Ok(deleted != 0)
+    }
+
+    /// Records that the address with the given GUID was just used to fill a
+    /// form.
+    pub fn touch_address(&self, guid: &Guid) -> Result<()> {
+        let changed = self.execute_named(
+            "UPDATE addresses_data
+             SET times_used = times_used + 1,
+                 time_last_used = :now,
+                 sync_change_counter = sync_change_counter + 1
+             WHERE guid = :guid",
+            named_params! {
+                ":guid": guid,
+                ":now": now_ms(),
+            },
+        )?;
+        if changed == 0 {
+            return Err(ErrorKind::NoSuchRecord(guid.to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::create_key;
+
+    fn japanese_address() -> UpdatableAddressFields {
+        UpdatableAddressFields {
+            given_name: "Taro".into(),
+            family_name: "Yamada".into(),
+            street_address: " 1-2-3 Marunouchi \n\n Building 4 ".into(),
+            address_level3: "Marunouchi".into(),
+            address_level2: "Chiyoda-ku".into(),
+            address_level1: "Tokyo".into(),
+            postal_code: "100-0005".into(),
+            country: "jp".into(),
+            tel: "+81 3-1234-5678".into(),
+            ..UpdatableAddressFields::default()
+        }
+    }
+
+    #[test]
+    fn test_crud() {
+        let db = AutofillDb::new_in_memory(&create_key().unwrap()).unwrap();
+        let address = db.add_address(japanese_address()).unwrap();
+        assert_eq!(
+            address.fields.street_address,
+            "1-2-3 Marunouchi\nBuilding 4"
+        );
+        assert_eq!(address.fields.country, "JP");
+        assert_eq!(
+            db.get_address(&address.guid).unwrap(),
+            Some(address.clone())
+        );
+
+        db.update_address(
+            &address.guid,
+            UpdatableAddressFields {
+                email: "taro@example.com".into(),
+                ..japanese_address()
+            },
+        )
+        .unwrap();
+        db.touch_address(&address.guid).unwrap();
+        let updated = db.get_address(&address.guid).unwrap().unwrap();
+        assert_eq!(updated.fields.email, "taro@example.com");
+        assert_eq!(updated.times_used, 1);
+        assert_eq!(updated.time_created, address.time_created);
+
+        assert_eq!(db.get_all_addresses().unwrap().len(), 1);
+        assert!(db.delete_address(&address.guid).unwrap());
+        assert!(!db.delete_address(&address.guid).unwrap());
+        // It was never synced, so there's no tombstone.
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM addresses_tombstones")
+                .unwrap(),
+            0
+        );
+        for err in &[
+            db.update_address(&address.guid, japanese_address())
+                .unwrap_err(),
+            db.touch_address(&address.guid).unwrap_err(),
+        ] {
+            assert!(matches!(err.kind(), ErrorKind::NoSuchRecord(_)));
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        let db = AutofillDb::new_in_memory(&create_key().unwrap()).unwrap();
+        for fields in vec![
+            UpdatableAddressFields::default(),
+            UpdatableAddressFields {
+                given_name: "  ".into(),
+                street_address: "\n \n".into(),
+                ..UpdatableAddressFields::default()
+            },
+            UpdatableAddressFields {
+                country: "Japan".into(),
+                ..japanese_address()
+            },
+        ] {
+            let err = db.add_address(fields).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::InvalidAddress(_)));
+        }
+        assert!(db.get_all_addresses().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,408 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::db::{now_ms, AutofillDb};
+use crate::error::*;
+use rusqlite::{named_params, Row};
+use sql_support::ConnExt;
+use sync_guid::Guid;
+
+/// The fields of a credit card which are provided when it's added or
+/// updated. The number may contain spaces and dashes, which are removed.
+/// If `cc_type` is empty, it's worked out from the number.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdatableCreditCardFields {
+    pub cc_name: String,
+    pub cc_number: String,
+    pub cc_exp_month: Option<i64>,
+    pub cc_exp_year: Option<i64>,
+    pub cc_type: String,
+}
+
+/// A credit card, as returned by the store. The number isn't included, only
+/// its last 4 digits: use `AutofillDb::get_credit_card_number` to fill it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreditCard {
+    pub guid: Guid,
+    pub cc_name: String,
+    pub cc_number_last_4: String,
+    pub cc_exp_month: Option<i64>,
+    pub cc_exp_year: Option<i64>,
+    pub cc_type: String,
+    // Timestamps are in milliseconds since the unix epoch.
+    pub time_created: i64,
+    pub time_last_used: Option<i64>,
+    pub time_last_modified: i64,
+    pub times_used: i64,
+}
+
+impl CreditCard {
+    pub(crate) fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            guid: row.get("guid")?,
+            cc_name: row.get("cc_name")?,
+            cc_number_last_4: row.get("cc_number_last_4")?,
+            cc_exp_month: row.get("cc_exp_month")?,
+            cc_exp_year: row.get("cc_exp_year")?,
+            cc_type: row.get("cc_type")?,
+            time_created: row.get("time_created")?,
+            time_last_used: row.get("time_last_used")?,
+            time_last_modified: row.get("time_last_modified")?,
+            times_used: row.get("times_used")?,
+        })
+    }
+}
+
+pub(crate) const CREDIT_CARD_COLS: &str = "
+    guid,
+    cc_name,
+    cc_number_last_4,
+    cc_exp_month,
+    cc_exp_year,
+    cc_type,
+    time_created,
+    time_last_used,
+    time_last_modified,
+    times_used";
+
+// A credit card number which passed validation, without separators.
+pub(crate) struct ValidatedCardNumber {
+    pub number: String,
+    pub last_4: String,
+    pub cc_type: String,
+}
+
+/// Removes the separators people commonly type in card numbers, and checks
+/// that the result is a plausible card number (12 to 19 digits, with a valid
+/// Luhn checksum). `cc_type` is used as the type if it isn't empty.
+pub(crate) fn validate_card_number(number: &str, cc_type: &str) -> Result<ValidatedCardNumber> {
+    let number = number
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>();
+    if !number.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ErrorKind::InvalidCreditCard("Number contains non-digits".into()).into());
+    }
+    if number.len() < 12 || number.len() > 19 {
+        return Err(ErrorKind::InvalidCreditCard("Number has the wrong length".into()).into());
+    }
+    if !passes_luhn_check(&number) {
+        return Err(ErrorKind::InvalidCreditCard("Number fails the checksum".into()).into());
+    }
+    let cc_type = if cc_type.is_empty() {
+        detect_card_type(&number).to_owned()
+    } else {
+        cc_type.to_owned()
+    };
+    Ok(ValidatedCardNumber {
+        last_4: number[number.len() - 4..].to_owned(),
+        cc_type,
+        number,
+    })
+}
+
+fn passes_luhn_check(number: &str) -> bool {
+    let sum: u32 = number
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, b)| {
+            let digit = u32::from(b - b'0');
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+// Works out the network from the number's prefix, using the same names as
+// desktop's form autofill. Returns an empty string if it's not recognized.
+fn detect_card_type(number: &str) -> &'static str {
+    let prefix = |len: usize| number[..len].parse::<u32>().unwrap_or_default();
+    match (prefix(1), prefix(2), prefix(4)) {
+        (4, _, _) => "visa",
+        (_, 34, _) | (_, 37, _) => "amex",
+        (_, 51..=55, _) | (_, _, 2221..=2720) => "mastercard",
+        (_, 65, _) | (_, _, 6011) => "discover",
+        (_, 36, _) | (_, 38, _) | (_, 39, _) | (_, _, 3000..=3059) => "diners",
+        (_, _, 3528..=3589) => "jcb",
+        (_, 62, _) => "unionpay",
+        _ => "",
+    }
+}
+
+impl AutofillDb {
+    pub fn add_credit_card(&self, fields: UpdatableCreditCardFields) -> Result<CreditCard> {
+        let validated = validate_card_number(&fields.cc_number, &fields.cc_type)?;
+        let now = now_ms();
+        let card = CreditCard {
+            guid: Guid::random(),
+            cc_name: fields.cc_name,
+            cc_number_last_4: validated.last_4,
+            cc_exp_month: fields.cc_exp_month,
+            cc_exp_year: fields.cc_exp_year,
+            cc_type: validated.cc_type,
+            time_created: now,
+            time_last_used: None,
+            time_last_modified: now,
+            times_used: 0,
+        };
+        self.execute_named(
+            &format!(
+                "INSERT INTO credit_cards_data ({cols}, cc_number_enc, sync_change_counter)
+                 VALUES (:guid, :cc_name, :cc_number_last_4, :cc_exp_month, :cc_exp_year,
+                         :cc_type, :time_created, :time_last_used, :time_last_modified,
+                         :times_used, :cc_number_enc, 1)",
+                cols = CREDIT_CARD_COLS
+            ),
+            named_params! {
+                ":guid": card.guid,
+                ":cc_name": card.cc_name,
+                ":cc_number_last_4": card.cc_number_last_4,
+                ":cc_exp_month": card.cc_exp_month,
+                ":cc_exp_year": card.cc_exp_year,
+                ":cc_type": card.cc_type,
+                ":time_created": card.time_created,
+                ":time_last_used": card.time_last_used,
+                ":time_last_modified": card.time_last_modified,
+                ":times_used": card.times_used,
+                ":cc_number_enc": self.encdec.encrypt(&validated.number)?,
+            },
+        )?;
+        Ok(card)
+    }
+
+    pub fn get_credit_card(&self, guid: &Guid) -> Result<Option<CreditCard>> {
+        self.try_query_row(
+            &format!(
+                "SELECT {cols} FROM credit_cards_data WHERE guid = :guid",
+                cols = CREDIT_CARD_COLS
+            ),
+            named_params! { ":guid": guid },
+            CreditCard::from_row,
+            true,
+        )
+    }
+
+    pub fn get_all_credit_cards(&self) -> Result<Vec<CreditCard>> {
+        self.query_rows_and_then_named_cached(
+            &format!(
+                "SELECT {cols} FROM credit_cards_data ORDER BY time_created",
+                cols = CREDIT_CARD_COLS
+            ),
+            &[],
+            CreditCard::from_row,
+        )
+    }
+
+    /// Decrypts the full number of the card with the given GUID, for filling
+    /// it in a form.
+    pub fn get_credit_card_number(&self, guid: &Guid) -> Result<String> {
+        let ciphertext = self
+            .try_query_one::<String>(
+                "SELECT cc_number_enc FROM credit_cards_data WHERE guid = :guid",
+                named_params! { ":guid": guid },
+                true,
+            )?
+            .ok_or_else(|| ErrorKind::NoSuchRecord(guid.to_string()))?;
+        self.encdec.decrypt(&ciphertext)
+    }
+
+    /// Replaces the fields of the card with the given GUID. Its usage
+    /// counters are kept.
+    pub fn update_credit_card(&self, guid: &Guid, fields: UpdatableCreditCardFields) -> Result<()> {
+        let validated = validate_card_number(&fields.cc_number, &fields.cc_type)?;
+        let changed = self.execute_named(
+            "UPDATE credit_cards_data
+             SET cc_name = :cc_name,
+                 cc_number_enc = :cc_number_enc,
+                 cc_number_last_4 = :cc_number_last_4,
+                 cc_exp_month = :cc_exp_month,
+                 cc_exp_year = :cc_exp_year,
+                 cc_type = :cc_type,
+                 time_last_modified = :now,
+                 sync_change_counter = sync_change_counter + 1
+             WHERE guid = :guid",
+            named_params! {
+                ":guid": guid,
+                ":cc_name": fields.cc_name,
+                ":cc_number_enc": self.encdec.encrypt(&validated.number)?,
+                ":cc_number_last_4": validated.last_4,
+                ":cc_exp_month": fields.cc_exp_month,
+                ":cc_exp_year": fields.cc_exp_year,
+                ":cc_type": validated.cc_type,
+                ":now": now_ms(),
+            },
+        )?;
+        if changed == 0 {
+            return Err(ErrorKind::NoSuchRecord(guid.to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// Deletes the card with the given GUID, returning whether it existed.
+    /// If the card was synced, the deletion is uploaded on the next sync.
+    pub fn delete_credit_card(&self, guid: &Guid) -> Result<bool> {
+        let tx = self.unchecked_transaction()?;
+        tx.execute_named(
+            "INSERT OR IGNORE INTO credit_cards_tombstones (guid, time_deleted)
+             SELECT guid, :now FROM credit_cards_data
+             WHERE guid = :guid AND sync_status = 1",
+            named_params! {
+                ":guid": guid,
+                ":now": now_ms(),
+            },
+        )?;
+        let deleted = tx.execute_named(
+            "DELETE FROM credit_cards_data WHERE guid = :guid",
+            named_params! { ":guid": guid },
+        )?;
+        tx.commit()?;
+        Ok(deleted != 0)
+    }
+
+    /// Records that the card with the given GUID was just used to fill a
+    /// form.
+    pub fn touch_credit_card(&self, guid: &Guid) -> Result<()> {
+        let changed = self.execute_named(
+            "UPDATE credit_cards_data
+             SET times_used = times_used + 1,
+                 time_last_used = :now,
+                 sync_change_counter = sync_change_counter + 1
+             WHERE guid = :guid",
+            named_params! {
+                ":guid": guid,
+                ":now": now_ms(),
+            },
+        )?;
+        if changed == 0 {
+            return Err(ErrorKind::NoSuchRecord(guid.to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::create_key;
+
+    fn visa() -> UpdatableCreditCardFields {
+        UpdatableCreditCardFields {
+            cc_name: "Jane Doe".into(),
+            cc_number: "4111 1111 1111 1111".into(),
+            cc_exp_month: Some(3),
+            cc_exp_year: Some(2025),
+            cc_type: "".into(),
+        }
+    }
+
+    #[test]
+    fn test_crud() {
+        let db = AutofillDb::new_in_memory(&create_key().unwrap()).unwrap();
+        let card = db.add_credit_card(visa()).unwrap();
+        assert_eq!(card.cc_number_last_4, "1111");
+        assert_eq!(card.cc_type, "visa");
+        assert_eq!(db.get_credit_card(&card.guid).unwrap(), Some(card.clone()));
+        assert_eq!(
+            db.get_credit_card_number(&card.guid).unwrap(),
+            "4111111111111111"
+        );
+
+        // The number isn't stored in the clear.
+        let stored = db
+            .query_one::<String>("SELECT cc_number_enc FROM credit_cards_data")
+            .unwrap();
+        assert!(!stored.contains("4111"));
+
+        db.update_credit_card(
+            &card.guid,
+            UpdatableCreditCardFields {
+                cc_number: "5555-5555-5555-4444".into(),
+                ..visa()
+            },
+        )
+        .unwrap();
+        let updated = db.get_credit_card(&card.guid).unwrap().unwrap();
+        assert_eq!(updated.cc_number_last_4, "4444");
+        assert_eq!(updated.cc_type, "mastercard");
+        assert_eq!(updated.time_created, card.time_created);
+
+        db.touch_credit_card(&card.guid).unwrap();
+        let touched = db.get_credit_card(&card.guid).unwrap().unwrap();
+        assert_eq!(touched.times_used, 1);
+        assert!(touched.time_last_used.is_some());
+
+        assert_eq!(db.get_all_credit_cards().unwrap().len(), 1);
+        assert!(db.delete_credit_card(&card.guid).unwrap());
+        assert!(!db.delete_credit_card(&card.guid).unwrap());
+        assert_eq!(db.get_credit_card(&card.guid).unwrap(), None);
+
+        for err in &[
+            db.update_credit_card(&card.guid, visa()).unwrap_err(),
+            db.touch_credit_card(&card.guid).unwrap_err(),
+            db.get_credit_card_number(&card.guid).unwrap_err(),
+        ] {
+            assert!(matches!(err.kind(), ErrorKind::NoSuchRecord(_)));
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        let db = AutofillDb::new_in_memory(&create_key().unwrap()).unwrap();
+        for number in &["4111 1111 1111 1112", "4111", "4111x1111x1111x1111", ""] {
+            let err = db
+                .add_credit_card(UpdatableCreditCardFields {
+                    cc_number: (*number).into(),
+                    ..visa()
+                })
+                .unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::InvalidCreditCard(_)));
+        }
+        assert!(db.get_all_credit_cards().unwrap().is_empty());
+
+        // An explicit type is kept.
+        let card = db
+            .add_credit_card(UpdatableCreditCardFields {
+                cc_type: "cartebancaire".into(),
+                ..visa()
+            })
+            .unwrap();
+        assert_eq!(card.cc_type, "cartebancaire");
+    }
+
+    #[test]
+    fn test_detect_card_type() {
+        assert_eq!(detect_card_type("378282246310005"), "amex");
+        assert_eq!(detect_card_type("2221000000000009"), "mastercard");
+        assert_eq!(detect_card_type("6011111111111117"), "discover");
+        assert_eq!(detect_card_type("3530111333300000"), "jcb");
+        assert_eq!(detect_card_type("9999999999999995"), "");
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("autofill.db");
+        let guid = {
+            let db = AutofillDb::new(&path, &create_key().unwrap()).unwrap();
+            db.add_credit_card(visa()).unwrap().guid
+        };
+        let db = AutofillDb::new(&path, &create_key().unwrap()).unwrap();
+        // Cards can still be listed, but not filled.
+        assert_eq!(db.get_all_credit_cards().unwrap().len(), 1);
+        assert!(matches!(
+            db.get_credit_card_number(&guid).unwrap_err().kind(),
+            ErrorKind::DecryptionFailed
+        ));
+    }
+}
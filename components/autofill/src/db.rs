@@ -0,0 +1,83 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::schema::AutofillConnectionInitializer;
+use interrupt_support::Interruptable;
+use rusqlite::Connection;
+use sql_support::{open_database, SqlInterruptHandle};
+use std::cell::Cell;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{atomic::AtomicUsize, Arc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sync15::MemoryCachedState;
+
+/// The autofill database, which stores and syncs credit cards. Card numbers are
+/// encrypted with the key the database is opened with, which the embedding
+/// application is responsible for keeping (see `create_key`). The rest of
+/// each card, including the last 4 digits of the number, isn't encrypted.
+pub struct AutofillDb {
+    pub(crate) conn: Connection,
+    pub(crate) encdec: EncryptorDecryptor,
+    pub(crate) mem_cached_state: Cell<MemoryCachedState>,
+    // Also registered with `interrupt_support::shutdown`, which only holds a
+    // weak reference to it.
+    interrupt_handle: Arc<SqlInterruptHandle>,
+}
+
+impl AutofillDb {
+    /// Opens, creating it if needed, the database at `path`. Every card in
+    /// the database must have been added with the same `encryption_key`.
+    pub fn new(path: impl AsRef<Path>, encryption_key: &str) -> Result<Self> {
+        let encdec = EncryptorDecryptor::new(encryption_key)?;
+        let conn = open_database::open_database(path, &AutofillConnectionInitializer)?;
+        Ok(Self::with_connection(conn, encdec))
+    }
+
+    /// Opens a new database which only lives in memory, for tests.
+    pub fn new_in_memory(encryption_key: &str) -> Result<Self> {
+        let encdec = EncryptorDecryptor::new(encryption_key)?;
+        let conn = open_database::open_memory_database(&AutofillConnectionInitializer)?;
+        Ok(Self::with_connection(conn, encdec))
+    }
+
+    fn with_connection(conn: Connection, encdec: EncryptorDecryptor) -> Self {
+        let interrupt_handle = Arc::new(SqlInterruptHandle::new(
+            conn.get_interrupt_handle(),
+            Arc::new(AtomicUsize::new(0)),
+        ));
+        interrupt_support::register_interrupt(
+            Arc::downgrade(&interrupt_handle) as Weak<dyn Interruptable>
+        );
+        Self {
+            conn,
+            encdec,
+            mem_cached_state: Cell::default(),
+            interrupt_handle,
+        }
+    }
+
+    /// Returns a handle which interrupts the statement running on this
+    /// database, from another thread.
+    pub fn interrupt_handle(&self) -> Arc<SqlInterruptHandle> {
+        Arc::clone(&self.interrupt_handle)
+    }
+}
+
+impl Deref for AutofillDb {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+pub(crate) fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
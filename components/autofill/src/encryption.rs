@@ -0,0 +1,104 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Encryption of credit card numbers, with a key the embedding application
+//! manages (typically one held in the platform keystore). Numbers are
+//! encrypted with AES-256-GCM, and stored as the base64 of a random nonce
+//! followed by the ciphertext.
+
+use crate::error::*;
+use rc_crypto::{aead, rand};
+
+const KEY_LEN: usize = 32;
+
+/// Creates a new, random key to open an `AutofillDb` with. Keys are
+/// URL-safe base64 strings, so they can be stored anywhere a string can.
+pub fn create_key() -> Result<String> {
+    let mut key = vec![0u8; KEY_LEN];
+    rand::fill(&mut key)?;
+    Ok(base64::encode_config(&key, base64::URL_SAFE_NO_PAD))
+}
+
+pub(crate) struct EncryptorDecryptor {
+    key: Vec<u8>,
+}
+
+impl EncryptorDecryptor {
+    pub fn new(key: &str) -> Result<Self> {
+        let key = base64::decode_config(key, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| ErrorKind::InvalidKey)?;
+        if key.len() != KEY_LEN {
+            return Err(ErrorKind::InvalidKey.into());
+        }
+        Ok(Self { key })
+    }
+
+    pub fn encrypt(&self, cleartext: &str) -> Result<String> {
+        let algorithm = &aead::AES_256_GCM;
+        let sealing_key = aead::SealingKey::new(algorithm, &self.key)?;
+        let mut nonce = vec![0u8; algorithm.nonce_len()];
+        rand::fill(&mut nonce)?;
+        let ciphertext = aead::seal(
+            &sealing_key,
+            aead::Nonce::try_assume_unique_for_key(algorithm, &nonce)?,
+            aead::Aad::empty(),
+            cleartext.as_bytes(),
+        )?;
+        nonce.extend(ciphertext);
+        Ok(base64::encode(&nonce))
+    }
+
+    pub fn decrypt(&self, ciphertext: &str) -> Result<String> {
+        let algorithm = &aead::AES_256_GCM;
+        let data = base64::decode(ciphertext).map_err(|_| ErrorKind::DecryptionFailed)?;
+        if data.len() < algorithm.nonce_len() {
+            return Err(ErrorKind::DecryptionFailed.into());
+        }
+        let (nonce, ciphertext) = data.split_at(algorithm.nonce_len());
+        let opening_key = aead::OpeningKey::new(algorithm, &self.key)?;
+        let cleartext = aead::open(
+            &opening_key,
+            aead::Nonce::try_assume_unique_for_key(algorithm, nonce)?,
+            aead::Aad::empty(),
+            ciphertext,
+        )
+        .map_err(|_| ErrorKind::DecryptionFailed)?;
+        Ok(String::from_utf8(cleartext).map_err(|_| ErrorKind::DecryptionFailed)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let encdec = EncryptorDecryptor::new(&create_key().unwrap()).unwrap();
+        let ciphertext = encdec.encrypt("4111111111111111").unwrap();
+        assert!(!ciphertext.contains("4111"));
+        assert_eq!(encdec.decrypt(&ciphertext).unwrap(), "4111111111111111");
+        // The nonce is random, so the same number never encrypts the same way.
+        assert_ne!(encdec.encrypt("4111111111111111").unwrap(), ciphertext);
+
+        let other = EncryptorDecryptor::new(&create_key().unwrap()).unwrap();
+        assert!(matches!(
+            other.decrypt(&ciphertext).unwrap_err().kind(),
+            ErrorKind::DecryptionFailed
+        ));
+        assert!(matches!(
+            encdec.decrypt("not base64!").unwrap_err().kind(),
+            ErrorKind::DecryptionFailed
+        ));
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        for key in &["", "not a key!", "c2hvcnQ"] {
+            assert!(matches!(
+                EncryptorDecryptor::new(key).err().unwrap().kind(),
+                ErrorKind::InvalidKey
+            ));
+        }
+    }
+}
@@ -0,0 +1,98 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use failure::Fail;
+use sql_support::{open_database, MaybeBusy};
+
+#[derive(Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "No record with guid exists: {}", _0)]
+    NoSuchRecord(String),
+
+    #[fail(display = "Invalid credit card: {}", _0)]
+    InvalidCreditCard(String),
+
+    #[fail(display = "Invalid address: {}", _0)]
+    InvalidAddress(String),
+
+    #[fail(display = "The encryption key is invalid")]
+    InvalidKey,
+
+    #[fail(display = "Failed to decrypt a card number, the key may be wrong")]
+    DecryptionFailed,
+
+    #[fail(display = "Error synchronizing: {}", _0)]
+    SyncAdapterError(#[fail(cause)] sync15::Error),
+
+    #[fail(display = "Crypto error: {}", _0)]
+    CryptoError(#[fail(cause)] rc_crypto::Error),
+
+    #[fail(display = "Error parsing JSON data: {}", _0)]
+    JsonError(#[fail(cause)] serde_json::Error),
+
+    #[fail(display = "Error executing SQL: {}", _0)]
+    SqlError(#[fail(cause)] rusqlite::Error),
+
+    #[fail(display = "Error opening database: {}", _0)]
+    OpenDatabaseError(#[fail(cause)] open_database::Error),
+
+    /// Another connection holds a lock on the database, so the operation can
+    /// be tried again later.
+    #[fail(display = "The database is busy: {}", _0)]
+    DatabaseBusy(#[fail(cause)] rusqlite::Error),
+}
+
+error_support::define_error! {
+    ErrorKind {
+        (SyncAdapterError, sync15::Error),
+        (CryptoError, rc_crypto::Error),
+        (JsonError, serde_json::Error),
+    }
+}
+
+// SQL errors are converted by hand, so that lock contention becomes
+// `DatabaseBusy`.
+impl From<rusqlite::Error> for ErrorKind {
+    #[cold]
+    fn from(e: rusqlite::Error) -> ErrorKind {
+        if e.is_busy() {
+            ErrorKind::DatabaseBusy(e)
+        } else {
+            ErrorKind::SqlError(e)
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    #[cold]
+    fn from(e: rusqlite::Error) -> Self {
+        ErrorKind::from(e).into()
+    }
+}
+
+impl From<open_database::Error> for ErrorKind {
+    #[cold]
+    fn from(e: open_database::Error) -> ErrorKind {
+        match e {
+            open_database::Error::SqlError(e) => e.into(),
+            e => ErrorKind::OpenDatabaseError(e),
+        }
+    }
+}
+
+impl From<open_database::Error> for Error {
+    #[cold]
+    fn from(e: open_database::Error) -> Self {
+        ErrorKind::from(e).into()
+    }
+}
+
+impl MaybeBusy for Error {
+    fn is_busy(&self) -> bool {
+        match self.kind() {
+            ErrorKind::DatabaseBusy(_) => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![allow(unknown_lints)]
+#![warn(rust_2018_idioms)]
+
+//! Storage for the records used to fill forms: credit cards, whose numbers
+//! are encrypted with a key the embedding application provides, and postal
+//! addresses. Credit cards are synced with the same collection as desktop.
+
+mod addresses;
+mod credit_cards;
+mod db;
+mod encryption;
+pub mod error;
+mod schema;
+mod sync;
+
+pub use crate::addresses::{Address, UpdatableAddressFields};
+pub use crate::credit_cards::{CreditCard, UpdatableCreditCardFields};
+pub use crate::db::AutofillDb;
+pub use crate::encryption::create_key;
+pub use crate::sync::CreditCardsStore;
+pub use error::{Error, ErrorKind, Result};
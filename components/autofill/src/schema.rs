@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use rusqlite::Transaction;
+use sql_support::open_database::{ConnectionInitializer, Error, Result};
+
+const CREATE_SCHEMA_SQL: &str = include_str!("../sql/create_schema.sql");
+const CREATE_ADDRESSES_SQL: &str = include_str!("../sql/create_addresses_schema.sql");
+
+pub const LAST_SYNC_META_KEY: &str = "credit_cards_last_sync_time";
+pub const GLOBAL_STATE_META_KEY: &str = "global_state_v2";
+pub const GLOBAL_SYNCID_META_KEY: &str = "credit_cards_global_sync_id";
+pub const COLLECTION_SYNCID_META_KEY: &str = "credit_cards_sync_id";
+
+pub struct AutofillConnectionInitializer;
+
+impl ConnectionInitializer for AutofillConnectionInitializer {
+    const NAME: &'static str = "autofill";
+    const END_VERSION: u32 = 3;
+
+    fn init(&self, tx: &Transaction<'_>) -> Result<()> {
+        tx.execute_batch(CREATE_SCHEMA_SQL)?;
+        tx.execute_batch(CREATE_ADDRESSES_SQL)?;
+        Ok(())
+    }
+
+    fn upgrade_from(&self, tx: &Transaction<'_>, version: u32) -> Result<()> {
+        match version {
+            // Version 2 added syncing.
+            1 => {
+                tx.execute_batch(
+                    "ALTER TABLE credit_cards_data
+                         ADD COLUMN sync_status INTEGER NOT NULL DEFAULT 0;
+                     CREATE TABLE IF NOT EXISTS credit_cards_tombstones (
+                         guid TEXT PRIMARY KEY CHECK(length(guid) != 0),
+                         time_deleted INTEGER NOT NULL
+                     ) WITHOUT ROWID;
+                     CREATE TABLE IF NOT EXISTS moz_meta (
+                         key TEXT PRIMARY KEY,
+                         value NOT NULL
+                     ) WITHOUT ROWID;",
+                )?;
+                Ok(())
+            }
+            // Version 3 added addresses.
+            2 => {
+                tx.execute_batch(CREATE_ADDRESSES_SQL)?;
+                Ok(())
+            }
+            _ => Err(Error::IncompatibleVersion(version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use sql_support::{open_database, ConnExt};
+
+    #[test]
+    fn test_upgrade_from_v1() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("autofill.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE credit_cards_data (
+                     guid TEXT NOT NULL PRIMARY KEY,
+                     cc_name TEXT NOT NULL,
+                     cc_number_enc TEXT NOT NULL,
+                     cc_number_last_4 TEXT NOT NULL,
+                     cc_exp_month INTEGER,
+                     cc_exp_year INTEGER,
+                     cc_type TEXT NOT NULL,
+                     time_created INTEGER NOT NULL,
+                     time_last_used INTEGER,
+                     time_last_modified INTEGER NOT NULL,
+                     times_used INTEGER NOT NULL DEFAULT 0,
+                     sync_change_counter INTEGER NOT NULL DEFAULT 1
+                 );
+                 INSERT INTO credit_cards_data
+                     (guid, cc_name, cc_number_enc, cc_number_last_4, cc_type,
+                      time_created, time_last_modified)
+                 VALUES ('card', 'Jane Doe', 'enc', '1111', 'visa', 1, 1);
+                 PRAGMA user_version = 1;",
+            )
+            .unwrap();
+        }
+        let conn = open_database::open_database(&path, &AutofillConnectionInitializer).unwrap();
+        assert_eq!(conn.query_one::<u32>("PRAGMA user_version").unwrap(), 3);
+        assert_eq!(
+            conn.query_one::<i64>("SELECT sync_status FROM credit_cards_data")
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM credit_cards_tombstones")
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM addresses_data")
+                .unwrap(),
+            0
+        );
+    }
+}
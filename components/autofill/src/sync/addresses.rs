@@ -0,0 +1,161 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The format of address records in the `addresses` collection, which is
+//! the same as desktop's form autofill. There isn't an addresses store yet;
+//! this is the mapping one would use, so that the local schema and the
+//! records already agree.
+
+use crate::addresses::{Address, UpdatableAddressFields};
+use serde_derive::{Deserialize, Serialize};
+use sync_guid::Guid;
+
+/// The version of desktop's address records we understand.
+pub(crate) const ADDRESS_SCHEMA_VERSION: u32 = 1;
+
+/// An address record, as stored on the server by desktop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AddressRecord {
+    pub id: Guid,
+    pub entry: AddressEntry,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AddressEntry {
+    #[serde(rename = "given-name", default)]
+    pub given_name: String,
+    #[serde(rename = "additional-name", default)]
+    pub additional_name: String,
+    #[serde(rename = "family-name", default)]
+    pub family_name: String,
+    #[serde(default)]
+    pub organization: String,
+    #[serde(rename = "street-address", default)]
+    pub street_address: String,
+    #[serde(rename = "address-level3", default)]
+    pub address_level3: String,
+    #[serde(rename = "address-level2", default)]
+    pub address_level2: String,
+    #[serde(rename = "address-level1", default)]
+    pub address_level1: String,
+    #[serde(rename = "postal-code", default)]
+    pub postal_code: String,
+    #[serde(default)]
+    pub country: String,
+    #[serde(default)]
+    pub tel: String,
+    #[serde(default)]
+    pub email: String,
+    // As for credit cards, desktop uses 0 for an address which was never
+    // used.
+    #[serde(default)]
+    pub time_created: i64,
+    #[serde(default)]
+    pub time_last_used: i64,
+    #[serde(default)]
+    pub time_last_modified: i64,
+    #[serde(default)]
+    pub times_used: i64,
+    pub version: u32,
+}
+
+impl From<&Address> for AddressRecord {
+    fn from(address: &Address) -> Self {
+        let fields = address.fields.clone();
+        AddressRecord {
+            id: address.guid.clone(),
+            entry: AddressEntry {
+                given_name: fields.given_name,
+                additional_name: fields.additional_name,
+                family_name: fields.family_name,
+                organization: fields.organization,
+                street_address: fields.street_address,
+                address_level3: fields.address_level3,
+                address_level2: fields.address_level2,
+                address_level1: fields.address_level1,
+                postal_code: fields.postal_code,
+                country: fields.country,
+                tel: fields.tel,
+                email: fields.email,
+                time_created: address.time_created,
+                time_last_used: address.time_last_used.unwrap_or(0),
+                time_last_modified: address.time_last_modified,
+                times_used: address.times_used,
+                version: ADDRESS_SCHEMA_VERSION,
+            },
+        }
+    }
+}
+
+impl From<AddressRecord> for Address {
+    fn from(record: AddressRecord) -> Self {
+        let entry = record.entry;
+        Address {
+            guid: record.id,
+            fields: UpdatableAddressFields {
+                given_name: entry.given_name,
+                additional_name: entry.additional_name,
+                family_name: entry.family_name,
+                organization: entry.organization,
+                street_address: entry.street_address,
+                address_level3: entry.address_level3,
+                address_level2: entry.address_level2,
+                address_level1: entry.address_level1,
+                postal_code: entry.postal_code,
+                country: entry.country,
+                tel: entry.tel,
+                email: entry.email,
+            },
+            time_created: entry.time_created,
+            time_last_used: Some(entry.time_last_used).filter(|&t| t != 0),
+            time_last_modified: entry.time_last_modified,
+            times_used: entry.times_used,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_desktop_record() {
+        // An address as desktop uploads it.
+        let payload = json!({
+            "id": "HwjWxN7ItdqJ",
+            "entry": {
+                "given-name": "Jane",
+                "family-name": "Doe",
+                "street-address": "32 Vassar Street\nMIT Room 32-G524",
+                "address-level2": "Cambridge",
+                "address-level1": "MA",
+                "postal-code": "02139",
+                "country": "US",
+                "tel": "+16172535702",
+                "timeCreated": 1_590_000_000_000i64,
+                "timeLastUsed": 0,
+                "timeLastModified": 1_590_000_001_000i64,
+                "timesUsed": 0,
+                "version": 1,
+            },
+        });
+        let record: AddressRecord = serde_json::from_value(payload.clone()).unwrap();
+        let address = Address::from(record);
+        assert_eq!(address.guid, "HwjWxN7ItdqJ");
+        assert_eq!(address.fields.address_level2, "Cambridge");
+        assert_eq!(address.fields.organization, "");
+        assert_eq!(address.time_last_used, None);
+
+        let uploaded = serde_json::to_value(AddressRecord::from(&address)).unwrap();
+        assert_eq!(
+            uploaded["entry"]["street-address"],
+            payload["entry"]["street-address"]
+        );
+        assert_eq!(uploaded["entry"]["postal-code"], "02139");
+        assert_eq!(uploaded["entry"]["timeLastUsed"], 0);
+        assert_eq!(uploaded["entry"]["version"], ADDRESS_SCHEMA_VERSION);
+    }
+}
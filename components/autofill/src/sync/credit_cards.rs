@@ -0,0 +1,876 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Syncing credit cards with the `creditcards` collection, in the same
+//! format as desktop's form autofill.
+//!
+//! Local changes are tracked with a change counter, and conflicting changes
+//! are resolved by keeping the side which was modified most recently. An
+//! incoming card which has a new GUID, but the same number as a local card
+//! the server doesn't know about yet, is the same card added on two
+//! devices: the local card is merged into it, rather than uploaded as a
+//! duplicate.
+
+use crate::credit_cards::{validate_card_number, ValidatedCardNumber};
+use crate::db::{now_ms, AutofillDb};
+use crate::error::*;
+use crate::schema;
+use rc_crypto::digest;
+use rusqlite::{named_params, Row};
+use serde_derive::{Deserialize, Serialize};
+use sql_support::ConnExt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::result;
+use sync15::{
+    telemetry, CollSyncIds, CollectionRequest, IncomingChangeset, OutgoingChangeset, Payload,
+    ServerTimestamp, Store, StoreSyncAssociation,
+};
+use sync_guid::Guid;
+
+const COLLECTION_NAME: &str = "creditcards";
+
+/// The version of desktop's credit card records we understand. Records with
+/// a newer version may have fields we'd lose, so they're skipped.
+const CREDIT_CARD_SCHEMA_VERSION: u32 = 3;
+
+/// A credit card record, as stored on the server by desktop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CreditCardRecord {
+    pub id: Guid,
+    pub entry: CreditCardEntry,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreditCardEntry {
+    #[serde(rename = "cc-name", default)]
+    pub cc_name: String,
+    #[serde(rename = "cc-number")]
+    pub cc_number: String,
+    #[serde(rename = "cc-exp-month", default)]
+    pub cc_exp_month: Option<i64>,
+    #[serde(rename = "cc-exp-year", default)]
+    pub cc_exp_year: Option<i64>,
+    #[serde(rename = "cc-type", default)]
+    pub cc_type: String,
+    // Timestamps are in milliseconds since the unix epoch. Desktop uses 0
+    // for a card which was never used.
+    #[serde(default)]
+    pub time_created: i64,
+    #[serde(default)]
+    pub time_last_used: i64,
+    #[serde(default)]
+    pub time_last_modified: i64,
+    #[serde(default)]
+    pub times_used: i64,
+    pub version: u32,
+}
+
+// The sync state and metadata of a local card.
+#[derive(Debug)]
+struct LocalCard {
+    guid: Guid,
+    time_created: i64,
+    time_last_used: Option<i64>,
+    time_last_modified: i64,
+    times_used: i64,
+    sync_change_counter: i64,
+}
+
+impl LocalCard {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            guid: row.get("guid")?,
+            time_created: row.get("time_created")?,
+            time_last_used: row.get("time_last_used")?,
+            time_last_modified: row.get("time_last_modified")?,
+            times_used: row.get("times_used")?,
+            sync_change_counter: row.get("sync_change_counter")?,
+        })
+    }
+}
+
+// What happened to an incoming record.
+enum IncomingOutcome {
+    Applied,
+    // The record conflicted with a local change, or was merged with a local
+    // duplicate.
+    Reconciled,
+}
+
+// Identifies a card by its number, without keeping the number around.
+fn fingerprint(number: &ValidatedCardNumber) -> Result<Vec<u8>> {
+    Ok(digest::digest(&digest::SHA256, number.number.as_bytes())?
+        .as_ref()
+        .to_vec())
+}
+
+pub struct CreditCardsStore<'a> {
+    db: &'a AutofillDb,
+    // The change counters of the cards in the outgoing changeset. They're
+    // subtracted once the cards are uploaded, so that changes made during
+    // the sync are uploaded by the next one.
+    uploaded_counters: RefCell<HashMap<Guid, i64>>,
+    // What happened to the incoming cards applied by `stage_incoming`, which
+    // is recorded once they've all been applied.
+    incoming_telemetry: RefCell<telemetry::EngineIncoming>,
+}
+
+impl<'a> CreditCardsStore<'a> {
+    pub fn new(db: &'a AutofillDb) -> Self {
+        Self {
+            db,
+            uploaded_counters: RefCell::default(),
+            incoming_telemetry: RefCell::new(telemetry::EngineIncoming::new()),
+        }
+    }
+
+    fn get_local(&self, guid: &Guid) -> Result<Option<LocalCard>> {
+        self.db.try_query_row(
+            "SELECT guid, time_created, time_last_used, time_last_modified, times_used,
+                    sync_change_counter
+             FROM credit_cards_data
+             WHERE guid = :guid",
+            named_params! { ":guid": guid },
+            LocalCard::from_row,
+            true,
+        )
+    }
+
+    // Returns the fingerprints of the cards the server doesn't know about
+    // yet, which incoming cards are deduped against.
+    fn unsynced_fingerprints(&self) -> Result<HashMap<Vec<u8>, Guid>> {
+        let rows = self.db.query_rows_and_then_named_cached(
+            "SELECT guid, cc_number_enc FROM credit_cards_data WHERE sync_status = 0",
+            &[],
+            |row| -> Result<(Guid, String)> { Ok((row.get(0)?, row.get(1)?)) },
+        )?;
+        let mut fingerprints = HashMap::with_capacity(rows.len());
+        for (guid, cc_number_enc) in rows {
+            // A card we can't decrypt can't be deduped.
+            let number = match self
+                .db
+                .encdec
+                .decrypt(&cc_number_enc)
+                .and_then(|number| validate_card_number(&number, ""))
+            {
+                Ok(number) => number,
+                Err(e) => {
+                    log::warn!("Can't dedupe local card {}: {}", guid, e);
+                    continue;
+                }
+            };
+            fingerprints.insert(fingerprint(&number)?, guid);
+        }
+        Ok(fingerprints)
+    }
+
+    fn apply_tombstone(&self, guid: &Guid) -> Result<IncomingOutcome> {
+        self.db.execute_named_cached(
+            "DELETE FROM credit_cards_tombstones WHERE guid = :guid",
+            named_params! { ":guid": guid },
+        )?;
+        Ok(match self.get_local(guid)? {
+            Some(local) if local.sync_change_counter > 0 => {
+                // The card changed locally since it was deleted on another
+                // device. Keep it, and upload it again.
+                IncomingOutcome::Reconciled
+            }
+            Some(_) => {
+                self.db.execute_named_cached(
+                    "DELETE FROM credit_cards_data WHERE guid = :guid",
+                    named_params! { ":guid": guid },
+                )?;
+                IncomingOutcome::Applied
+            }
+            None => IncomingOutcome::Applied,
+        })
+    }
+
+    fn apply_record(
+        &self,
+        record: CreditCardRecord,
+        number: ValidatedCardNumber,
+        unsynced: &mut HashMap<Vec<u8>, Guid>,
+    ) -> Result<IncomingOutcome> {
+        let guid = record.id.clone();
+        // A card that was deleted locally, but changed on another device, is
+        // restored.
+        self.db.execute_named_cached(
+            "DELETE FROM credit_cards_tombstones WHERE guid = :guid",
+            named_params! { ":guid": guid },
+        )?;
+
+        if let Some(local) = self.get_local(&guid)? {
+            return if local.sync_change_counter == 0 {
+                self.put_remote(&record, &number, &local, false)?;
+                Ok(IncomingOutcome::Applied)
+            } else {
+                self.reconcile(&record, &number, &local, false)?;
+                Ok(IncomingOutcome::Reconciled)
+            };
+        }
+
+        let fp = fingerprint(&number)?;
+        if let Some(dupe_guid) = unsynced.remove(&fp) {
+            log::info!(
+                "Merging local card {} into incoming card {}",
+                dupe_guid,
+                guid
+            );
+            self.db.execute_named_cached(
+                "UPDATE credit_cards_data SET guid = :guid WHERE guid = :dupe_guid",
+                named_params! {
+                    ":guid": guid,
+                    ":dupe_guid": dupe_guid,
+                },
+            )?;
+            let local = self
+                .get_local(&guid)?
+                .ok_or_else(|| ErrorKind::NoSuchRecord(guid.to_string()))?;
+            self.reconcile(&record, &number, &local, true)?;
+            return Ok(IncomingOutcome::Reconciled);
+        }
+
+        // A new card.
+        let now = now_ms();
+        let local = LocalCard {
+            guid,
+            time_created: now,
+            time_last_used: None,
+            time_last_modified: now,
+            times_used: 0,
+            sync_change_counter: 0,
+        };
+        self.put_remote(&record, &number, &local, false)?;
+        Ok(IncomingOutcome::Applied)
+    }
+
+    // Resolves an incoming record which conflicts with a changed local card
+    // (or a local duplicate, if `is_dupe`) by keeping the most recently
+    // modified one.
+    fn reconcile(
+        &self,
+        record: &CreditCardRecord,
+        number: &ValidatedCardNumber,
+        local: &LocalCard,
+        is_dupe: bool,
+    ) -> Result<()> {
+        if record.entry.time_last_modified > local.time_last_modified {
+            self.put_remote(record, number, local, is_dupe)
+        } else {
+            // Keep the local card, which is uploaded because its change
+            // counter is set, but with the combined usage.
+            let (times_used, time_last_used, time_created) =
+                merge_usage(&record.entry, local, is_dupe);
+            self.db.execute_named_cached(
+                "UPDATE credit_cards_data
+                 SET times_used = :times_used,
+                     time_last_used = :time_last_used,
+                     time_created = :time_created,
+                     sync_change_counter = max(sync_change_counter, 1),
+                     sync_status = 1
+                 WHERE guid = :guid",
+                named_params! {
+                    ":guid": local.guid,
+                    ":times_used": times_used,
+                    ":time_last_used": time_last_used,
+                    ":time_created": time_created,
+                },
+            )?;
+            Ok(())
+        }
+    }
+
+    // Replaces the local card with the incoming record, keeping the combined
+    // usage. The card is only uploaded again if it's a merged duplicate,
+    // since the usage changed.
+    fn put_remote(
+        &self,
+        record: &CreditCardRecord,
+        number: &ValidatedCardNumber,
+        local: &LocalCard,
+        is_dupe: bool,
+    ) -> Result<()> {
+        let (times_used, time_last_used, time_created) = merge_usage(&record.entry, local, is_dupe);
+        let entry = &record.entry;
+        self.db.execute_named_cached(
+            "INSERT OR REPLACE INTO credit_cards_data (
+                 guid, cc_name, cc_number_enc, cc_number_last_4, cc_exp_month, cc_exp_year,
+                 cc_type, time_created, time_last_used, time_last_modified, times_used,
+                 sync_change_counter, sync_status
+             ) VALUES (
+                 :guid, :cc_name, :cc_number_enc, :cc_number_last_4, :cc_exp_month,
+                 :cc_exp_year, :cc_type, :time_created, :time_last_used,
+                 :time_last_modified, :times_used, :sync_change_counter, 1
+             )",
+            named_params! {
+                ":guid": record.id,
+                ":cc_name": entry.cc_name,
+                ":cc_number_enc": self.db.encdec.encrypt(&number.number)?,
+                ":cc_number_last_4": number.last_4,
+                ":cc_exp_month": entry.cc_exp_month,
+                ":cc_exp_year": entry.cc_exp_year,
+                ":cc_type": number.cc_type,
+                ":time_created": time_created,
+                ":time_last_used": time_last_used,
+                ":time_last_modified": entry.time_last_modified,
+                ":times_used": times_used,
+                ":sync_change_counter": if is_dupe { 1 } else { 0 },
+            },
+        )?;
+        Ok(())
+    }
+
+    fn build_outgoing(&self, timestamp: ServerTimestamp) -> Result<OutgoingChangeset> {
+        let mut outgoing = OutgoingChangeset::new(COLLECTION_NAME, timestamp);
+        let mut uploaded_counters = self.uploaded_counters.borrow_mut();
+        uploaded_counters.clear();
+
+        let changed = self.db.query_rows_and_then_named_cached(
+            "SELECT guid, cc_name, cc_number_enc, cc_exp_month, cc_exp_year, cc_type,
+                    time_created, time_last_used, time_last_modified, times_used,
+                    sync_change_counter
+             FROM credit_cards_data
+             WHERE sync_change_counter > 0",
+            &[],
+            |row| -> Result<(CreditCardRecord, String, i64)> {
+                let record = CreditCardRecord {
+                    id: row.get("guid")?,
+                    entry: CreditCardEntry {
+                        cc_name: row.get("cc_name")?,
+                        cc_number: String::new(), // Filled in below.
+                        cc_exp_month: row.get("cc_exp_month")?,
+                        cc_exp_year: row.get("cc_exp_year")?,
+                        cc_type: row.get("cc_type")?,
+                        time_created: row.get("time_created")?,
+                        time_last_used: row
+                            .get::<_, Option<i64>>("time_last_used")?
+                            .unwrap_or_default(),
+                        time_last_modified: row.get("time_last_modified")?,
+                        times_used: row.get("times_used")?,
+                        version: CREDIT_CARD_SCHEMA_VERSION,
+                    },
+                };
+                Ok((
+                    record,
+                    row.get("cc_number_enc")?,
+                    row.get("sync_change_counter")?,
+                ))
+            },
+        )?;
+        for (mut record, cc_number_enc, counter) in changed {
+            // Uploading a card we can't decrypt would replace it with one
+            // without a number on every other device.
+            record.entry.cc_number = match self.db.encdec.decrypt(&cc_number_enc) {
+                Ok(number) => number,
+                Err(e) => {
+                    log::warn!("Not uploading card {}: {}", record.id, e);
+                    continue;
+                }
+            };
+            uploaded_counters.insert(record.id.clone(), counter);
+            outgoing.changes.push(Payload::from_record(record)?);
+        }
+
+        let tombstones = self.db.query_rows_and_then_named_cached(
+            "SELECT guid FROM credit_cards_tombstones",
+            &[],
+            |row| -> Result<Guid> { Ok(row.get(0)?) },
+        )?;
+        outgoing
+            .changes
+            .extend(tombstones.into_iter().map(Payload::new_tombstone));
+        Ok(outgoing)
+    }
+
+    // Applies a page of incoming cards in its own transaction. Cards from a
+    // download which started again are applied twice, which leaves them the
+    // same as applying them once.
+    fn apply_page(&self, changes: Vec<(Payload, ServerTimestamp)>) -> Result<()> {
+        let mut incoming_telemetry = self.incoming_telemetry.borrow_mut();
+        let tx = self.db.unchecked_transaction()?;
+        let mut unsynced = self.unsynced_fingerprints()?;
+        for (payload, _) in changes {
+            let outcome = if payload.is_tombstone() {
+                self.apply_tombstone(&payload.id)?
+            } else {
+                let record = match payload.into_record::<CreditCardRecord>() {
+                    Ok(record) if record.entry.version <= CREDIT_CARD_SCHEMA_VERSION => record,
+                    Ok(record) => {
+                        log::warn!(
+                            "Skipping card {} with unknown version {}",
+                            record.id,
+                            record.entry.version
+                        );
+                        incoming_telemetry.failed(1);
+                        continue;
+                    }
+                    Err(e) => {
+                        log::warn!("Error deserializing incoming card: {}", e);
+                        incoming_telemetry.failed(1);
+                        continue;
+                    }
+                };
+                let number =
+                    match validate_card_number(&record.entry.cc_number, &record.entry.cc_type) {
+                        Ok(number) => number,
+                        Err(e) => {
+                            log::warn!("Skipping invalid card {}: {}", record.id, e);
+                            incoming_telemetry.failed(1);
+                            continue;
+                        }
+                    };
+                self.apply_record(record, number, &mut unsynced)?
+            };
+            match outcome {
+                IncomingOutcome::Applied => incoming_telemetry.applied(1),
+                IncomingOutcome::Reconciled => incoming_telemetry.reconciled(1),
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn do_apply_incoming(
+        &self,
+        inbound: IncomingChangeset,
+        telem: &mut telemetry::Engine,
+    ) -> Result<OutgoingChangeset> {
+        self.apply_page(inbound.changes)?;
+        let tx = self.db.unchecked_transaction()?;
+        let outgoing = self.build_outgoing(inbound.timestamp)?;
+        tx.commit()?;
+        telem.incoming(
+            self.incoming_telemetry
+                .replace(telemetry::EngineIncoming::new()),
+        );
+        Ok(outgoing)
+    }
+
+    fn do_sync_finished(
+        &self,
+        new_timestamp: ServerTimestamp,
+        records_synced: Vec<Guid>,
+    ) -> Result<()> {
+        let uploaded_counters = self.uploaded_counters.replace(HashMap::new());
+        let tx = self.db.unchecked_transaction()?;
+        for guid in &records_synced {
+            self.db.execute_named_cached(
+                "DELETE FROM credit_cards_tombstones WHERE guid = :guid",
+                named_params! { ":guid": guid },
+            )?;
+            if let Some(counter) = uploaded_counters.get(guid) {
+                self.db.execute_named_cached(
+                    "UPDATE credit_cards_data
+                     SET sync_change_counter = max(sync_change_counter - :counter, 0),
+                         sync_status = 1
+                     WHERE guid = :guid",
+                    named_params! {
+                        ":guid": guid,
+                        ":counter": counter,
+                    },
+                )?;
+            }
+        }
+        self.db.set_last_sync(new_timestamp)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn do_reset(&self, assoc: &StoreSyncAssociation) -> Result<()> {
+        let tx = self.db.unchecked_transaction()?;
+        // Everything needs to be uploaded again, and can be deduped against
+        // what's on the server.
+        self.db.execute_batch(
+            "UPDATE credit_cards_data SET sync_change_counter = 1, sync_status = 0;
+             DELETE FROM credit_cards_tombstones;",
+        )?;
+        self.db.delete_meta(schema::LAST_SYNC_META_KEY)?;
+        match assoc {
+            StoreSyncAssociation::Disconnected => {
+                self.db.delete_meta(schema::GLOBAL_SYNCID_META_KEY)?;
+                self.db.delete_meta(schema::COLLECTION_SYNCID_META_KEY)?;
+            }
+            StoreSyncAssociation::Connected(ids) => {
+                self.db
+                    .put_meta(schema::GLOBAL_SYNCID_META_KEY, &ids.global)?;
+                self.db
+                    .put_meta(schema::COLLECTION_SYNCID_META_KEY, &ids.coll)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// Returns the `(times_used, time_last_used, time_created)` of a card after
+// an incoming record is reconciled with the local one. The usage of a local
+// duplicate was never uploaded, so it's added to the incoming card's.
+fn merge_usage(
+    entry: &CreditCardEntry,
+    local: &LocalCard,
+    is_dupe: bool,
+) -> (i64, Option<i64>, i64) {
+    let times_used = if is_dupe {
+        entry.times_used + local.times_used
+    } else {
+        entry.times_used.max(local.times_used)
+    };
+    let remote_last_used = Some(entry.time_last_used).filter(|t| *t > 0);
+    let time_last_used = remote_last_used.max(local.time_last_used);
+    let time_created = if entry.time_created > 0 {
+        entry.time_created.min(local.time_created)
+    } else {
+        local.time_created
+    };
+    (times_used, time_last_used, time_created)
+}
+
+impl<'a> Store for CreditCardsStore<'a> {
+    fn collection_name(&self) -> std::borrow::Cow<'static, str> {
+        COLLECTION_NAME.into()
+    }
+
+    fn stage_incoming(
+        &self,
+        page: IncomingChangeset,
+    ) -> result::Result<Option<IncomingChangeset>, failure::Error> {
+        self.apply_page(page.changes)?;
+        Ok(None)
+    }
+
+    fn apply_incoming(
+        &self,
+        inbound: Vec<IncomingChangeset>,
+        telem: &mut telemetry::Engine,
+    ) -> result::Result<OutgoingChangeset, failure::Error> {
+        assert_eq!(inbound.len(), 1, "credit cards only requests one item");
+        let inbound = inbound.into_iter().next().unwrap();
+        Ok(self.do_apply_incoming(inbound, telem)?)
+    }
+
+    fn sync_finished(
+        &self,
+        new_timestamp: ServerTimestamp,
+        records_synced: Vec<Guid>,
+    ) -> result::Result<(), failure::Error> {
+        Ok(self.do_sync_finished(new_timestamp, records_synced)?)
+    }
+
+    fn get_collection_requests(
+        &self,
+        server_timestamp: ServerTimestamp,
+    ) -> result::Result<Vec<CollectionRequest>, failure::Error> {
+        let since = self.db.get_last_sync()?.unwrap_or_default();
+        Ok(if since == server_timestamp {
+            vec![]
+        } else {
+            vec![CollectionRequest::new(COLLECTION_NAME)
+                .full()
+                .newer_than(since)]
+        })
+    }
+
+    fn get_sync_assoc(&self) -> result::Result<StoreSyncAssociation, failure::Error> {
+        let global = self.db.get_meta(schema::GLOBAL_SYNCID_META_KEY)?;
+        let coll = self.db.get_meta(schema::COLLECTION_SYNCID_META_KEY)?;
+        Ok(if let (Some(global), Some(coll)) = (global, coll) {
+            StoreSyncAssociation::Connected(CollSyncIds { global, coll })
+        } else {
+            StoreSyncAssociation::Disconnected
+        })
+    }
+
+    fn reset(&self, assoc: &StoreSyncAssociation) -> result::Result<(), failure::Error> {
+        Ok(self.do_reset(assoc)?)
+    }
+
+    fn wipe(&self) -> result::Result<(), failure::Error> {
+        self.db.execute_batch(
+            "DELETE FROM credit_cards_data;
+             DELETE FROM credit_cards_tombstones;",
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credit_cards::UpdatableCreditCardFields;
+    use crate::encryption::create_key;
+    use serde_json::json;
+
+    fn new_db() -> AutofillDb {
+        AutofillDb::new_in_memory(&create_key().unwrap()).unwrap()
+    }
+
+    fn visa() -> UpdatableCreditCardFields {
+        UpdatableCreditCardFields {
+            cc_name: "Jane Doe".into(),
+            cc_number: "4111111111111111".into(),
+            cc_exp_month: Some(3),
+            cc_exp_year: Some(2025),
+            cc_type: "".into(),
+        }
+    }
+
+    fn incoming_card(guid: &str, name: &str, time_last_modified: i64) -> Payload {
+        Payload::from_json(json!({
+            "id": guid,
+            "entry": {
+                "cc-name": name,
+                "cc-number": "4111111111111111",
+                "cc-exp-month": 3,
+                "cc-exp-year": 2025,
+                "cc-type": "visa",
+                "timeCreated": 1000,
+                "timeLastUsed": 0,
+                "timeLastModified": time_last_modified,
+                "timesUsed": 2,
+                "version": 3,
+            },
+        }))
+        .unwrap()
+    }
+
+    fn apply_incoming(store: &CreditCardsStore<'_>, changes: Vec<Payload>) -> OutgoingChangeset {
+        let mut inbound = IncomingChangeset::new(COLLECTION_NAME, ServerTimestamp(0));
+        inbound.changes = changes
+            .into_iter()
+            .map(|payload| (payload, ServerTimestamp(0)))
+            .collect();
+        let mut telem = telemetry::Engine::new(COLLECTION_NAME);
+        store.apply_incoming(vec![inbound], &mut telem).unwrap()
+    }
+
+    fn outgoing_ids(outgoing: &OutgoingChangeset) -> Vec<&str> {
+        outgoing.changes.iter().map(Payload::id).collect()
+    }
+
+    fn sync_finished(store: &CreditCardsStore<'_>, outgoing: &OutgoingChangeset) {
+        let synced = outgoing.changes.iter().map(|p| p.id.clone()).collect();
+        store
+            .sync_finished(ServerTimestamp::from_millis(1000), synced)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_outgoing() {
+        let db = new_db();
+        let card = db.add_credit_card(visa()).unwrap();
+        let store = CreditCardsStore::new(&db);
+
+        let outgoing = apply_incoming(&store, vec![]);
+        assert_eq!(outgoing_ids(&outgoing), vec![card.guid.as_str()]);
+        let record: CreditCardRecord = outgoing.changes[0].clone().into_record().unwrap();
+        assert_eq!(record.entry.cc_number, "4111111111111111");
+        assert_eq!(record.entry.cc_type, "visa");
+        assert_eq!(record.entry.version, CREDIT_CARD_SCHEMA_VERSION);
+
+        // A change made during the sync is uploaded by the next one.
+        db.touch_credit_card(&card.guid).unwrap();
+        sync_finished(&store, &outgoing);
+        assert_eq!(
+            outgoing_ids(&apply_incoming(&store, vec![])),
+            vec![card.guid.as_str()]
+        );
+        sync_finished(&store, &apply_incoming(&store, vec![]));
+        assert!(apply_incoming(&store, vec![]).changes.is_empty());
+        assert_eq!(
+            db.get_last_sync().unwrap(),
+            Some(ServerTimestamp::from_millis(1000))
+        );
+
+        // Deleting a synced card uploads a tombstone.
+        assert!(db.delete_credit_card(&card.guid).unwrap());
+        let outgoing = apply_incoming(&store, vec![]);
+        assert!(outgoing.changes[0].is_tombstone());
+        sync_finished(&store, &outgoing);
+        assert!(apply_incoming(&store, vec![]).changes.is_empty());
+    }
+
+    #[test]
+    fn test_incoming() {
+        let db = new_db();
+        let store = CreditCardsStore::new(&db);
+        let guid = Guid::new("card-aaaaaaa");
+
+        let outgoing = apply_incoming(&store, vec![incoming_card("card-aaaaaaa", "Jane", 2000)]);
+        assert!(outgoing.changes.is_empty());
+        let card = db.get_credit_card(&guid).unwrap().unwrap();
+        assert_eq!(card.cc_name, "Jane");
+        assert_eq!(card.cc_number_last_4, "1111");
+        assert_eq!(card.times_used, 2);
+        assert_eq!(card.time_last_used, None);
+        assert_eq!(
+            db.get_credit_card_number(&guid).unwrap(),
+            "4111111111111111"
+        );
+
+        // An unchanged local card takes the incoming changes.
+        apply_incoming(
+            &store,
+            vec![incoming_card("card-aaaaaaa", "Jane Doe", 3000)],
+        );
+        assert_eq!(
+            db.get_credit_card(&guid).unwrap().unwrap().cc_name,
+            "Jane Doe"
+        );
+
+        // An incoming tombstone deletes an unchanged card.
+        apply_incoming(&store, vec![Payload::new_tombstone("card-aaaaaaa")]);
+        assert_eq!(db.get_credit_card(&guid).unwrap(), None);
+
+        // Records we can't understand are skipped.
+        let mut newer = incoming_card("card-bbbbbbb", "Jane", 2000);
+        newer.data["entry"]["version"] = json!(CREDIT_CARD_SCHEMA_VERSION + 1);
+        let mut invalid = incoming_card("card-ccccccc", "Jane", 2000);
+        invalid.data["entry"]["cc-number"] = json!("1234");
+        apply_incoming(&store, vec![newer, invalid]);
+        assert!(db.get_all_credit_cards().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_staged_pages() {
+        let db = new_db();
+        let store = CreditCardsStore::new(&db);
+        let page = |cards: Vec<Payload>| {
+            let mut page = IncomingChangeset::new(COLLECTION_NAME, ServerTimestamp(0));
+            page.changes = cards
+                .into_iter()
+                .map(|payload| (payload, ServerTimestamp(0)))
+                .collect();
+            page
+        };
+
+        // The first page is staged twice, as if the download started again.
+        for _ in 0..2 {
+            let staged = store
+                .stage_incoming(page(vec![incoming_card("card-aaaaaaa", "Jane", 2000)]))
+                .unwrap();
+            assert!(staged.is_none());
+        }
+        store
+            .stage_incoming(page(vec![incoming_card("card-bbbbbbb", "John", 2000)]))
+            .unwrap();
+
+        let outgoing = apply_incoming(&store, vec![]);
+        assert!(outgoing.changes.is_empty());
+        let cards = db.get_all_credit_cards().unwrap();
+        assert_eq!(cards.len(), 2);
+        assert!(cards.iter().all(|card| card.times_used == 2));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let db = new_db();
+        let store = CreditCardsStore::new(&db);
+        let guid = Guid::new("card-aaaaaaa");
+        apply_incoming(&store, vec![incoming_card("card-aaaaaaa", "Jane", 2000)]);
+        db.update_credit_card(
+            &guid,
+            UpdatableCreditCardFields {
+                cc_name: "Local".into(),
+                ..visa()
+            },
+        )
+        .unwrap();
+
+        // An older incoming change loses, and the local card is uploaded.
+        let outgoing = apply_incoming(&store, vec![incoming_card("card-aaaaaaa", "Remote", 3000)]);
+        assert_eq!(db.get_credit_card(&guid).unwrap().unwrap().cc_name, "Local");
+        assert_eq!(outgoing_ids(&outgoing), vec!["card-aaaaaaa"]);
+
+        // A newer one wins.
+        let outgoing = apply_incoming(
+            &store,
+            vec![incoming_card("card-aaaaaaa", "Remote", now_ms() + 1000)],
+        );
+        assert_eq!(
+            db.get_credit_card(&guid).unwrap().unwrap().cc_name,
+            "Remote"
+        );
+        assert!(outgoing.changes.is_empty());
+
+        // A card which changed locally isn't deleted by an incoming tombstone.
+        db.touch_credit_card(&guid).unwrap();
+        let outgoing = apply_incoming(&store, vec![Payload::new_tombstone("card-aaaaaaa")]);
+        assert!(db.get_credit_card(&guid).unwrap().is_some());
+        assert_eq!(outgoing_ids(&outgoing), vec!["card-aaaaaaa"]);
+
+        // A card which was deleted locally is restored if it changes remotely.
+        sync_finished(&store, &outgoing);
+        db.delete_credit_card(&guid).unwrap();
+        let outgoing = apply_incoming(&store, vec![incoming_card("card-aaaaaaa", "Jane", 4000)]);
+        assert!(db.get_credit_card(&guid).unwrap().is_some());
+        assert!(outgoing.changes.is_empty());
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let db = new_db();
+        let store = CreditCardsStore::new(&db);
+        let local = db
+            .add_credit_card(UpdatableCreditCardFields {
+                cc_number: "4111 1111 1111 1111".into(),
+                ..visa()
+            })
+            .unwrap();
+        db.touch_credit_card(&local.guid).unwrap();
+
+        // The same number, formatted differently, with a new GUID, is the
+        // same card added on another device.
+        let mut incoming = incoming_card("card-aaaaaaa", "Jane", 2000);
+        incoming.data["entry"]["cc-number"] = json!("4111-1111-1111-1111");
+        let outgoing = apply_incoming(&store, vec![incoming]);
+
+        let cards = db.get_all_credit_cards().unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].guid, "card-aaaaaaa");
+        // The local card was modified more recently, so its fields win. The
+        // usage of both is combined.
+        assert_eq!(cards[0].cc_name, "Jane Doe");
+        assert_eq!(cards[0].times_used, 3);
+        assert_eq!(cards[0].time_created, 1000);
+        // The merged card is uploaded under the incoming GUID.
+        assert_eq!(outgoing_ids(&outgoing), vec!["card-aaaaaaa"]);
+        sync_finished(&store, &outgoing);
+
+        // Once the server knows about a card, it's not a candidate for
+        // deduping, so a different card with the same number is kept.
+        apply_incoming(&store, vec![incoming_card("card-bbbbbbb", "Jane", 3000)]);
+        assert_eq!(db.get_all_credit_cards().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_reset() {
+        let db = new_db();
+        let store = CreditCardsStore::new(&db);
+        apply_incoming(&store, vec![incoming_card("card-aaaaaaa", "Jane", 2000)]);
+        sync_finished(&store, &apply_incoming(&store, vec![]));
+        let ids = CollSyncIds {
+            global: Guid::random(),
+            coll: Guid::random(),
+        };
+        store
+            .reset(&StoreSyncAssociation::Connected(ids.clone()))
+            .unwrap();
+        assert_eq!(
+            store.get_sync_assoc().unwrap(),
+            StoreSyncAssociation::Connected(ids)
+        );
+        assert_eq!(db.get_last_sync().unwrap(), None);
+        // Everything is uploaded again.
+        assert_eq!(
+            outgoing_ids(&apply_incoming(&store, vec![])),
+            vec!["card-aaaaaaa"]
+        );
+
+        store.wipe().unwrap();
+        assert!(db.get_all_credit_cards().unwrap().is_empty());
+    }
+}
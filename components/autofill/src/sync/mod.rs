@@ -0,0 +1,92 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#[allow(dead_code)]
+pub(crate) mod addresses;
+pub(crate) mod credit_cards;
+
+use crate::db::AutofillDb;
+use crate::error::*;
+use crate::schema;
+use interrupt_support::ShutdownInterruptee;
+use rusqlite::named_params;
+use rusqlite::types::{FromSql, ToSql};
+use sql_support::ConnExt;
+use sync15::{sync_multiple, telemetry, KeyBundle, ServerTimestamp, Sync15StorageClientInit};
+
+pub use credit_cards::CreditCardsStore;
+
+impl AutofillDb {
+    pub(crate) fn put_meta(&self, key: &str, value: &dyn ToSql) -> Result<()> {
+        self.execute_named_cached(
+            "REPLACE INTO moz_meta (key, value) VALUES (:key, :value)",
+            named_params! { ":key": key, ":value": value },
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn get_meta<T: FromSql>(&self, key: &str) -> Result<Option<T>> {
+        Ok(self.try_query_row(
+            "SELECT value FROM moz_meta WHERE key = :key",
+            named_params! { ":key": key },
+            |row| Ok::<_, Error>(row.get(0)?),
+            true,
+        )?)
+    }
+
+    pub(crate) fn delete_meta(&self, key: &str) -> Result<()> {
+        self.execute_named_cached(
+            "DELETE FROM moz_meta WHERE key = :key",
+            named_params! { ":key": key },
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn get_last_sync(&self) -> Result<Option<ServerTimestamp>> {
+        Ok(self
+            .get_meta::<i64>(schema::LAST_SYNC_META_KEY)?
+            .map(ServerTimestamp::from_millis))
+    }
+
+    pub(crate) fn set_last_sync(&self, last_sync: ServerTimestamp) -> Result<()> {
+        self.put_meta(schema::LAST_SYNC_META_KEY, &last_sync.as_millis())
+    }
+
+    /// A convenience wrapper around `sync_multiple`, which syncs the credit
+    /// cards.
+    pub fn sync_credit_cards(
+        &self,
+        storage_init: &Sync15StorageClientInit,
+        root_sync_key: &KeyBundle,
+    ) -> Result<telemetry::SyncTelemetryPing> {
+        let mut disk_cached_state = self.get_meta::<String>(schema::GLOBAL_STATE_META_KEY)?;
+        let mut mem_cached_state = self.mem_cached_state.take();
+        let store = CreditCardsStore::new(self);
+
+        let mut result = sync_multiple(
+            &[&store],
+            &mut disk_cached_state,
+            &mut mem_cached_state,
+            storage_init,
+            root_sync_key,
+            &ShutdownInterruptee,
+            None,
+        );
+        // We always update the state - sync_multiple leaves it empty if it
+        // needs to be dropped.
+        match disk_cached_state {
+            Some(state) => self.put_meta(schema::GLOBAL_STATE_META_KEY, &state)?,
+            None => self.delete_meta(schema::GLOBAL_STATE_META_KEY)?,
+        }
+        self.mem_cached_state.replace(mem_cached_state);
+
+        if let Err(e) = result.result {
+            return Err(e.into());
+        }
+        match result.engine_results.remove("creditcards") {
+            None | Some(Ok(())) => Ok(result.telemetry),
+            Some(Err(e)) => Err(e.into()),
+        }
+    }
+}
@@ -145,7 +145,7 @@ fn main() -> Result<(), failure::Error> {
                 let url: String = prompt_string("URL").unwrap();
                 acct.lock()
                     .unwrap()
-                    .send_tab(&target.id, &title, &url)
+                    .send_single_tab(&target.id, &title, &url)
                     .unwrap();
                 println!("Tab sent!");
             }
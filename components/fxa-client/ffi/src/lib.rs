@@ -585,7 +585,7 @@ pub extern "C" fn fxa_send_tab(
     let target = target_device_id.as_str();
     let title = title.as_str();
     let url = url.as_str();
-    ACCOUNTS.call_with_result_mut(error, handle, |fxa| fxa.send_tab(target, title, url))
+    ACCOUNTS.call_with_result_mut(error, handle, |fxa| fxa.send_single_tab(target, title, url))
 }
 
 define_handle_map_deleter!(ACCOUNTS, fxa_free);
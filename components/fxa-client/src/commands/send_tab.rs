@@ -218,3 +218,66 @@ fn extract_oldsync_key_components(oldsync_key: &ScopedKey) -> Result<(Vec<u8>, V
     let ksync = oldsync_key.key_bytes()?;
     Ok((ksync, kxcs))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn oldsync_key() -> ScopedKey {
+        ScopedKey {
+            kty: "oct".to_string(),
+            scope: scopes::OLD_SYNC.to_string(),
+            k: "kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg".to_string(),
+            kid: "1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ".to_string(),
+        }
+    }
+
+    fn device(available_commands: serde_json::Value) -> Device {
+        serde_json::from_value(json!({
+            "id": "device1",
+            "name": "Device 1",
+            "type": "mobile",
+            "availableCommands": available_commands,
+            "pushEndpointExpired": false,
+            "isCurrentDevice": false,
+            "location": {},
+            "lastAccessTime": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_send_tab_round_trip() {
+        let scoped_key = oldsync_key();
+        let keys = PrivateSendTabKeys::from_random().unwrap();
+        // The keys survive being persisted.
+        let keys = PrivateSendTabKeys::deserialize(&keys.serialize().unwrap()).unwrap();
+        let public_keys: PublicSendTabKeys = keys.clone().into();
+        let command_data = public_keys.as_command_data(&scoped_key).unwrap();
+        let target = device(json!({ COMMAND_NAME: command_data }));
+
+        let payload = SendTabPayload::single_tab("Example", "https://www.example.com/");
+        let command = build_send_command(&scoped_key, &target, &payload).unwrap();
+        // The tab isn't readable without the target's private key.
+        assert!(!command.to_string().contains("example.com"));
+        let encrypted: EncryptedSendTabPayload = serde_json::from_value(command).unwrap();
+        let decrypted = encrypted.decrypt(&keys).unwrap();
+        assert_eq!(decrypted.entries.len(), 1);
+        assert_eq!(decrypted.entries[0].title, "Example");
+        assert_eq!(decrypted.entries[0].url, "https://www.example.com/");
+    }
+
+    #[test]
+    fn test_send_tab_unsupported_target() {
+        let target = device(json!({}));
+        let payload = SendTabPayload::single_tab("Example", "https://www.example.com/");
+        match build_send_command(&oldsync_key(), &target, &payload) {
+            Err(e) => match e.kind() {
+                ErrorKind::UnsupportedCommand(_) => {}
+                _ => panic!("Wrong error: {}", e),
+            },
+            Ok(_) => panic!("Should fail for a device without Send Tab"),
+        }
+    }
+}
@@ -321,7 +321,12 @@ impl FirefoxAccount {
             .client
             .update_device(&self.state.config, refresh_token, update);
         match res {
-            Ok(resp) => Ok(resp),
+            Ok(resp) => {
+                // The cached device list (and the attached clients, which
+                // include device names) is now out of date.
+                self.clear_devices_and_attached_clients_cache();
+                Ok(resp)
+            }
             Err(err) => {
                 // We failed to write an update to the server.
                 // Clear local state so that we'll be sure to retry later.
@@ -738,6 +743,61 @@ mod tests {
         assert_eq!(cached_devices[0].id, cached_devices2[0].id);
     }
 
+    #[test]
+    fn test_update_device_clears_devices_cache() {
+        let mut fxa = setup();
+        let device = |name: &str| Device {
+            common: DeviceResponseCommon {
+                id: "device1".into(),
+                display_name: name.to_string(),
+                device_type: DeviceType::Desktop,
+                push_subscription: None,
+                available_commands: HashMap::new(),
+                push_endpoint_expired: false,
+            },
+            is_current_device: true,
+            location: DeviceLocation {
+                city: None,
+                country: None,
+                state: None,
+                state_code: None,
+            },
+            last_access_time: None,
+        };
+        let mut client = FxAClientMock::new();
+        client
+            .expect_devices(mockiato::Argument::any, mockiato::Argument::any)
+            .times(1)
+            .returns_once(Ok(vec![device("old name")]));
+        client
+            .expect_update_device(
+                mockiato::Argument::any,
+                |arg| arg.partial_eq("refreshtok"),
+                mockiato::Argument::any,
+            )
+            .times(1)
+            .returns_once(Ok(UpdateDeviceResponse {
+                id: "device1".to_string(),
+                display_name: "new name".to_string(),
+                device_type: DeviceType::Desktop,
+                push_subscription: None,
+                available_commands: HashMap::default(),
+                push_endpoint_expired: false,
+            }));
+        client
+            .expect_devices(mockiato::Argument::any, mockiato::Argument::any)
+            .times(1)
+            .returns_once(Ok(vec![device("new name")]));
+        client.expect_devices_calls_in_order();
+        fxa.set_client(Arc::new(client));
+
+        assert_eq!(fxa.get_devices(false).unwrap()[0].display_name, "old name");
+        fxa.set_device_name("new name").unwrap();
+        assert!(fxa.devices_cache.is_none());
+        // The renamed device is fetched again rather than read from the cache.
+        assert_eq!(fxa.get_devices(false).unwrap()[0].display_name, "new name");
+    }
+
     #[test]
     fn test_get_devices_network_errors() {
         let mut fxa = setup();
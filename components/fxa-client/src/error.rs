@@ -80,6 +80,12 @@ pub enum ErrorKind {
     #[fail(display = "Origin mismatch")]
     OriginMismatch,
 
+    #[fail(display = "Invalid pairing URL: {}", _0)]
+    InvalidPairingUrl(&'static str),
+
+    #[fail(display = "Unknown persisted state schema version: {}", _0)]
+    UnknownStateSchemaVersion(String),
+
     #[fail(display = "JWT signature validation failed")]
     JWTSignatureValidationFailed,
 
@@ -45,6 +45,10 @@ pub struct MigrationData {
     k_sync: String,
     copy_session_token: bool,
     session_token: String,
+    // The duplicate of `session_token`, once we've made it, so that retries
+    // don't create yet another session.
+    #[serde(default)]
+    copied_session_token: Option<String>,
 }
 
 impl FirefoxAccount {
@@ -79,6 +83,7 @@ impl FirefoxAccount {
             k_xcs: k_xcs.to_string(),
             copy_session_token,
             session_token: session_token.to_string(),
+            copied_session_token: None,
         });
 
         self.try_migration()
@@ -144,15 +149,21 @@ impl FirefoxAccount {
         };
 
         // If we need to copy the sessionToken, do that first so we can use it
-        // for subsequent requests. TODO: we should store the duplicated token
-        // in the account state in case we fail in later steps, but need to remember
-        // the original value of `copy_session_token` if we do so.
+        // for subsequent requests. The duplicated token is remembered in the
+        // account state in case we fail in later steps.
         let migration_session_token = if migration_data.copy_session_token {
-            let duplicate_session = self
-                .client
-                .duplicate_session(&self.state.config, &migration_data.session_token)?;
-
-            duplicate_session.session_token
+            match migration_data.copied_session_token {
+                Some(copied_session_token) => copied_session_token,
+                None => {
+                    let duplicate_session = self
+                        .client
+                        .duplicate_session(&self.state.config, &migration_data.session_token)?;
+                    if let Some(ref mut data) = self.state.in_flight_migration {
+                        data.copied_session_token = Some(duplicate_session.session_token.clone());
+                    }
+                    duplicate_session.session_token
+                }
+            }
         } else {
             migration_data.session_token.to_string()
         };
@@ -301,6 +312,75 @@ mod tests {
         assert_match!(fxa.is_in_migration_state(), MigrationState::None);
     }
 
+    #[test]
+    fn test_migration_retry_reuses_copied_session_token() {
+        let mut fxa = setup();
+
+        // Copying the session token succeeds, but the next step fails.
+        let mut client = FxAClientMock::new();
+        client
+            .expect_duplicate_session(mockiato::Argument::any, |arg| arg.partial_eq("session"))
+            .times(1)
+            .returns_once(Ok(DuplicateTokenResponse {
+                uid: "userid".to_string(),
+                session_token: "dup_session".to_string(),
+                verified: true,
+                auth_at: 12345,
+            }));
+        client
+            .expect_scoped_key_data(
+                mockiato::Argument::any,
+                |arg| arg.partial_eq("dup_session"),
+                |arg| arg.partial_eq(scopes::OLD_SYNC),
+            )
+            .returns_once(Err(ErrorKind::RemoteError {
+                code: 503,
+                errno: 999,
+                error: "server error".to_string(),
+                message: "there was a server error".to_string(),
+                info: "fyi, there was a server error".to_string(),
+            }
+            .into()));
+        fxa.set_client(Arc::new(client));
+
+        let err = fxa
+            .migrate_from_session_token("session", "aabbcc", "ddeeff", true)
+            .unwrap_err();
+        assert_match!(err.kind(), ErrorKind::RemoteError { code: 503, .. });
+        assert_match!(
+            fxa.is_in_migration_state(),
+            MigrationState::CopySessionToken
+        );
+
+        // The copied token survives persisting the state...
+        let mut fxa = FirefoxAccount::from_json(&fxa.to_json().unwrap()).unwrap();
+
+        // ...and retrying uses it rather than making another copy.
+        let mut client = FxAClientMock::new();
+        client
+            .expect_scoped_key_data(
+                mockiato::Argument::any,
+                |arg| arg.partial_eq("dup_session"),
+                |arg| arg.partial_eq(scopes::OLD_SYNC),
+            )
+            .returns_once(Err(ErrorKind::RemoteError {
+                code: 503,
+                errno: 999,
+                error: "server error".to_string(),
+                message: "there was a server error".to_string(),
+                info: "fyi, there was a server error".to_string(),
+            }
+            .into()));
+        fxa.set_client(Arc::new(client));
+
+        let err = fxa.try_migration().unwrap_err();
+        assert_match!(err.kind(), ErrorKind::RemoteError { code: 503, .. });
+        assert_match!(
+            fxa.is_in_migration_state(),
+            MigrationState::CopySessionToken
+        );
+    }
+
     #[test]
     fn test_migration_cannot_retry_after_other_errors() {
         let mut fxa = setup();
@@ -13,7 +13,7 @@ use crate::{
 use rc_crypto::digest;
 use serde_derive::*;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     iter::FromIterator,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -77,6 +77,12 @@ impl FirefoxAccount {
             key: self.state.scoped_keys.get(scope).cloned(),
             expires_at,
         };
+        // Drop any other tokens that have expired while we're here, so they
+        // don't linger in the persisted state.
+        let now = since_epoch.as_secs();
+        self.state
+            .access_token_cache
+            .retain(|_, token| token.expires_at > now);
         self.state
             .access_token_cache
             .insert(scope.to_string(), token_info.clone());
@@ -107,14 +113,16 @@ impl FirefoxAccount {
     /// Initiate a pairing flow and return a URL that should be navigated to.
     ///
     /// * `pairing_url` - A pairing URL obtained by scanning a QR code produced by
-    /// the pairing authority.
+    /// the pairing authority. Its fragment holds the ID and key of the channel
+    /// the supplicant page uses to talk to the authority.
     /// * `scopes` - Space-separated list of requested scopes by the pairing supplicant.
     pub fn begin_pairing_flow(&mut self, pairing_url: &str, scopes: &[&str]) -> Result<String> {
         let mut url = self.state.config.pair_supp_url()?;
         let pairing_url = Url::parse(pairing_url)?;
-        if url.host_str() != pairing_url.host_str() {
+        if url.origin() != pairing_url.origin() {
             return Err(ErrorKind::OriginMismatch.into());
         }
+        validate_pairing_channel(&pairing_url)?;
         url.set_fragment(pairing_url.fragment());
         self.oauth_flow(url, scopes)
     }
@@ -356,6 +364,29 @@ impl FirefoxAccount {
     }
 }
 
+// The pairing channel key is a 256-bit key, URL safe base 64 encoded.
+const PAIRING_CHANNEL_KEY_LEN: usize = 32;
+
+// Checks that the fragment of a scanned pairing URL has the channel the
+// supplicant needs, so a bad QR code fails here rather than in the web page.
+fn validate_pairing_channel(pairing_url: &Url) -> Result<()> {
+    let fragment = pairing_url
+        .fragment()
+        .ok_or_else(|| ErrorKind::InvalidPairingUrl("No channel fragment"))?;
+    let params: HashMap<_, _> = url::form_urlencoded::parse(fragment.as_bytes()).collect();
+    match params.get("channel_id") {
+        Some(id) if !id.is_empty() => {}
+        _ => return Err(ErrorKind::InvalidPairingUrl("No channel ID").into()),
+    }
+    let key = params
+        .get("channel_key")
+        .ok_or_else(|| ErrorKind::InvalidPairingUrl("No channel key"))?;
+    match base64::decode_config(key.as_bytes(), base64::URL_SAFE_NO_PAD) {
+        Ok(key) if key.len() == PAIRING_CHANNEL_KEY_LEN => Ok(()),
+        _ => Err(ErrorKind::InvalidPairingUrl("Bad channel key").into()),
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct RefreshToken {
     pub token: String,
@@ -623,6 +654,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_pairing_flow_invalid_channel() {
+        const CHANNEL_KEY: &str = "1hIDzTj5oY2HDeSg_jA2DhcOcAn5Uqq0cAYlZRNUIo4";
+        let config = Config::new(
+            "https://accounts.firefox.com",
+            "12345678",
+            "https://foo.bar",
+        );
+        let mut fxa = FirefoxAccount::with_config(config);
+        let bad_urls = [
+            "https://accounts.firefox.com/pair".to_owned(),
+            format!(
+                "https://accounts.firefox.com/pair#channel_key={}",
+                CHANNEL_KEY
+            ),
+            format!(
+                "https://accounts.firefox.com/pair#channel_id=&channel_key={}",
+                CHANNEL_KEY
+            ),
+            "https://accounts.firefox.com/pair#channel_id=658db7fe98b249a5".to_owned(),
+            "https://accounts.firefox.com/pair#channel_id=658db7fe98b249a5&channel_key=bar"
+                .to_owned(),
+        ];
+        for pairing_url in bad_urls.iter() {
+            match fxa
+                .begin_pairing_flow(pairing_url, &["https://identity.mozilla.com/apps/oldsync"])
+            {
+                Ok(_) => panic!("should have error for {}", pairing_url),
+                Err(err) => match err.kind() {
+                    ErrorKind::InvalidPairingUrl(_) => {}
+                    _ => panic!("error not InvalidPairingUrl for {}", pairing_url),
+                },
+            }
+        }
+        // The scheme has to match too.
+        let http_url = format!(
+            "http://accounts.firefox.com/pair#channel_id=658db7fe98b249a5&channel_key={}",
+            CHANNEL_KEY
+        );
+        match fxa.begin_pairing_flow(&http_url, &["https://identity.mozilla.com/apps/oldsync"]) {
+            Err(err) => match err.kind() {
+                ErrorKind::OriginMismatch => {}
+                _ => panic!("error not OriginMismatch"),
+            },
+            Ok(_) => panic!("should have error"),
+        }
+    }
+
     #[test]
     fn test_check_authorization_status() {
         let config = Config::stable_dev("12345678", "https://foo.bar");
@@ -646,4 +725,75 @@ mod tests {
         let auth_status = fxa.check_authorization_status().unwrap();
         assert_eq!(auth_status.active, true);
     }
+
+    #[test]
+    fn test_get_access_token_cache() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.state.refresh_token = Some(RefreshToken {
+            token: "refresh_token".to_owned(),
+            scopes: HashSet::from_iter(vec!["profile".to_owned(), "sync".to_owned()]),
+        });
+        let now = util::now_secs();
+        fxa.add_cached_token(
+            "profile",
+            AccessTokenInfo {
+                scope: "profile".to_owned(),
+                token: "fresh_token".to_owned(),
+                key: None,
+                expires_at: now + 3600,
+            },
+        );
+        // About to expire, so it should be refreshed.
+        fxa.add_cached_token(
+            "sync",
+            AccessTokenInfo {
+                scope: "sync".to_owned(),
+                token: "stale_token".to_owned(),
+                key: None,
+                expires_at: now + OAUTH_MIN_TIME_LEFT / 2,
+            },
+        );
+        fxa.add_cached_token(
+            "expired",
+            AccessTokenInfo {
+                scope: "expired".to_owned(),
+                token: "expired_token".to_owned(),
+                key: None,
+                expires_at: now - 1,
+            },
+        );
+
+        let mut client = FxAClientMock::new();
+        client
+            .expect_access_token_with_refresh_token(
+                mockiato::Argument::any,
+                |token| token.partial_eq("refresh_token"),
+                |ttl| ttl.partial_eq(Some(86400)),
+                mockiato::Argument::any,
+            )
+            .times(1)
+            .returns_once(Ok(OAuthTokenResponse {
+                keys_jwe: None,
+                refresh_token: None,
+                expires_in: 86400,
+                scope: "sync".to_owned(),
+                access_token: "new_token".to_owned(),
+                session_token: None,
+            }));
+        fxa.set_client(Arc::new(client));
+
+        let profile = fxa.get_access_token("profile", None).unwrap();
+        assert_eq!(profile.token, "fresh_token");
+        let sync = fxa.get_access_token("sync", Some(86400)).unwrap();
+        assert_eq!(sync.token, "new_token");
+        assert!(sync.expires_at >= now + 86400);
+        // Served from the cache now.
+        let sync = fxa.get_access_token("sync", Some(86400)).unwrap();
+        assert_eq!(sync.token, "new_token");
+
+        let mut cached: Vec<_> = fxa.state.access_token_cache.keys().cloned().collect();
+        cached.sort();
+        assert_eq!(cached, vec!["profile", "sync"]);
+    }
 }
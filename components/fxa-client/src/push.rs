@@ -47,6 +47,8 @@ impl FirefoxAccount {
                 if is_local_device {
                     // Note: self.disconnect calls self.start_over which clears the state for the FirefoxAccount instance
                     self.disconnect();
+                } else {
+                    self.clear_devices_and_attached_clients_cache();
                 }
                 Ok(vec![AccountEvent::DeviceDisconnected {
                     device_id,
@@ -182,8 +184,15 @@ mod tests {
     fn test_push_device_disconnected_remote() {
         let mut fxa =
             FirefoxAccount::with_config(crate::Config::stable_dev("12345678", "https://foo.bar"));
+        fxa.devices_cache = Some(crate::CachedResponse {
+            response: vec![],
+            cached_at: crate::util::now(),
+            etag: "".into(),
+        });
         let json = "{\"version\":1,\"command\":\"fxaccounts:device_disconnected\",\"data\":{\"id\":\"remote_id\"}}";
         let events = fxa.handle_push_message(json).unwrap();
+        // The disconnected device shouldn't be listed from the cache anymore.
+        assert!(fxa.devices_cache.is_none());
         assert_eq!(events.len(), 1);
         match &events[0] {
             AccountEvent::DeviceDisconnected {
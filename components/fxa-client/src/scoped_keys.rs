@@ -71,14 +71,8 @@ impl ScopedKeysFlow {
     }
 
     pub fn generate_keys_jwk(&self) -> Result<String> {
-        let pub_key_bytes = self.key_pair.public_key().to_bytes()?;
-        // Uncompressed form (see SECG SEC1 section 2.3.3).
-        // First byte is 4, then 32 bytes for x, and 32 bytes for y.
-        assert_eq!(pub_key_bytes.len(), 1 + 32 + 32);
-        assert_eq!(pub_key_bytes[0], 0x04);
-        let x = Vec::from(&pub_key_bytes[1..33]);
+        let (x, y) = self.key_pair.public_key().to_coordinates()?;
         let x = base64::encode_config(&x, base64::URL_SAFE_NO_PAD);
-        let y = Vec::from(&pub_key_bytes[33..]);
         let y = base64::encode_config(&y, base64::URL_SAFE_NO_PAD);
         Ok(json!({
             "crv": "P-256",
@@ -120,15 +114,8 @@ impl ScopedKeysFlow {
                 .ok_or_else(|| ErrorKind::UnrecoverableServerError("y is not a string."))?,
             base64::URL_SAFE_NO_PAD,
         )?;
-        if x.len() != (256 / 8) {
-            return Err(ErrorKind::UnrecoverableServerError("X must be 32 bytes long.").into());
-        }
-        if y.len() != (256 / 8) {
-            return Err(ErrorKind::UnrecoverableServerError("Y must be 32 bytes long.").into());
-        }
-        let mut peer_pub_key: Vec<u8> = vec![0x04];
-        peer_pub_key.extend_from_slice(&x);
-        peer_pub_key.extend_from_slice(&y);
+        let peer_pub_key = agreement::public_key_from_coordinates(&agreement::ECDH_P256, &x, &y)
+            .map_err(|_| ErrorKind::UnrecoverableServerError("X and Y must be 32 bytes long."))?;
         let (private_key, _) = self.key_pair.split();
         let ikm = private_key.agree(&agreement::ECDH_P256, &peer_pub_key)?;
         let secret = ikm.derive(|z| {
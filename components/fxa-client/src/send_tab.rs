@@ -38,7 +38,14 @@ impl FirefoxAccount {
     }
 
     /// Send a single tab to another device designated by its device ID.
-    pub fn send_tab(&mut self, target_device_id: &str, title: &str, url: &str) -> Result<()> {
+    /// The tab is encrypted with the public key from the target's Send Tab
+    /// command, so only the target device can read it.
+    pub fn send_single_tab(
+        &mut self,
+        target_device_id: &str,
+        title: &str,
+        url: &str,
+    ) -> Result<()> {
         let devices = self.get_devices(false)?;
         let target = devices
             .iter()
@@ -50,6 +57,11 @@ impl FirefoxAccount {
         self.invoke_command(send_tab::COMMAND_NAME, target, &command_payload)
     }
 
+    #[deprecated(since = "0.60.0", note = "Please use send_single_tab instead")]
+    pub fn send_tab(&mut self, target_device_id: &str, title: &str, url: &str) -> Result<()> {
+        self.send_single_tab(target_device_id, title, url)
+    }
+
     pub(crate) fn handle_send_tab_command(
         &mut self,
         sender: Option<GetDeviceResponse>,
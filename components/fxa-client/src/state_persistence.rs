@@ -34,6 +34,7 @@ use std::{
 use crate::{
     config::Config,
     device::Capability as DeviceCapability,
+    error::ErrorKind,
     migrator::MigrationData,
     oauth::{AccessTokenInfo, RefreshToken},
     profile::Profile,
@@ -46,7 +47,21 @@ use crate::{
 pub(crate) type State = StateV2;
 
 pub(crate) fn state_from_json(data: &str) -> Result<State> {
-    let stored_state: PersistedState = serde_json::from_str(data)?;
+    let value: serde_json::Value = serde_json::from_str(data)?;
+    // State written by a newer version of this component (which the user
+    // then downgraded from) would otherwise fail with an unhelpful JSON
+    // error, so report it explicitly.
+    match value
+        .get("schema_version")
+        .and_then(serde_json::Value::as_str)
+    {
+        Some("V1") | Some("V2") => {}
+        Some(version) => {
+            return Err(ErrorKind::UnknownStateSchemaVersion(version.to_owned()).into());
+        }
+        None => return Err(ErrorKind::UnknownStateSchemaVersion("(missing)".to_owned()).into()),
+    }
+    let stored_state: PersistedState = serde_json::from_value(value)?;
     upgrade_state(stored_state)
 }
 
@@ -290,6 +305,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_round_trip_keeps_session_and_device() {
+        let config = Config::new(
+            "https://accounts.firefox.com",
+            "12345678",
+            "https://foo.bar",
+        );
+        let state = StateV2 {
+            config,
+            current_device_id: Some("device1".to_owned()),
+            refresh_token: None,
+            scoped_keys: HashMap::new(),
+            last_handled_command: Some(12),
+            commands_data: HashMap::from_iter(vec![(
+                "https://identity.mozilla.com/cmd/open-uri".to_owned(),
+                "{}".to_owned(),
+            )]),
+            device_capabilities: HashSet::from_iter(vec![DeviceCapability::SendTab]),
+            access_token_cache: HashMap::new(),
+            session_token: Some("abcdef".to_owned()),
+            last_seen_profile: None,
+            in_flight_migration: None,
+        };
+
+        let json = state_to_json(&state).unwrap();
+        assert!(json.contains("\"schema_version\":\"V2\""));
+        let restored = state_from_json(&json).unwrap();
+        assert_eq!(restored.current_device_id.as_deref(), Some("device1"));
+        assert_eq!(restored.session_token.as_deref(), Some("abcdef"));
+        assert_eq!(restored.last_handled_command, Some(12));
+        assert!(restored
+            .device_capabilities
+            .contains(&DeviceCapability::SendTab));
+        assert_eq!(restored.commands_data, state.commands_data);
+        assert_eq!(restored.config.client_id, "12345678");
+    }
+
+    #[test]
+    fn test_unknown_schema_version() {
+        for json in &["{\"schema_version\":\"V3\"}", "{\"config\":{}}"] {
+            match state_from_json(json) {
+                Err(e) => match e.kind() {
+                    ErrorKind::UnknownStateSchemaVersion(_) => {}
+                    _ => panic!("Wrong error: {}", e),
+                },
+                Ok(_) => panic!("Should fail for {}", json),
+            }
+        }
+        assert!(state_from_json("not json").is_err());
+    }
+
     #[test]
     fn test_v2_creates_an_empty_access_token_cache_if_its_missing() {
         let state_v2_json = "{\"schema_version\":\"V2\",\"config\":{\"client_id\":\"98adfa37698f255b\",\"redirect_uri\":\"https://lockbox.firefox.com/fxa/ios-redirect.html\",\"content_url\":\"https://accounts.firefox.com\"},\"refresh_token\":{\"token\":\"bed5532f4fea7e39c5c4f609f53603ee7518fd1c103cc4034da3618f786ed188\",\"scopes\":[\"https://identity.mozilla.com/apps/oldysnc\"]},\"scoped_keys\":{\"https://identity.mozilla.com/apps/oldsync\":{\"kty\":\"oct\",\"scope\":\"https://identity.mozilla.com/apps/oldsync\",\"k\":\"kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg\",\"kid\":\"1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ\"}},\"login_state\":{\"Unknown\":null}}";
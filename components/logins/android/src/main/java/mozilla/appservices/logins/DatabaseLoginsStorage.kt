@@ -11,6 +11,7 @@ import mozilla.appservices.logins.rust.RustError
 import mozilla.appservices.support.native.toNioDirectBuffer
 import mozilla.appservices.sync15.SyncTelemetryPing
 import java.util.concurrent.atomic.AtomicLong
+import org.json.JSONArray
 import org.json.JSONObject
 import org.mozilla.appservices.logins.GleanMetrics.LoginsStore as LoginsStoreMetrics
 
@@ -104,6 +105,43 @@ class DatabaseLoginsStorage(private val dbPath: String) : AutoCloseable, LoginsS
         }
     }
 
+    @Synchronized
+    @Throws(LoginsStorageException::class)
+    override fun unlockReadOnly(encryptionKey: String) {
+        return unlockCounters.measure {
+            rustCall {
+                if (!isLocked()) {
+                    throw MismatchedLockException("Unlock called when we are already unlocked")
+                }
+                LoginsStoreMetrics.unlockTime.measure {
+                    raw.set(PasswordSyncAdapter.INSTANCE.sync15_passwords_state_new_readonly(
+                            dbPath,
+                            encryptionKey,
+                            null,
+                            it))
+                }
+            }
+        }
+    }
+
+    /**
+     * Unlock a new, empty store which only lives in memory, instead of the database at
+     * `dbPath`. Its logins are lost when it's locked. This is intended for tests, which get
+     * the same behavior as a store on disk without touching the file system.
+     *
+     * @throws [MismatchedLockException] if the database is already unlocked
+     */
+    @Synchronized
+    @Throws(LoginsStorageException::class)
+    fun unlockInMemory(encryptionKey: String? = null) {
+        rustCall {
+            if (!isLocked()) {
+                throw MismatchedLockException("Unlock called when we are already unlocked")
+            }
+            raw.set(PasswordSyncAdapter.INSTANCE.sync15_passwords_state_new_in_memory(encryptionKey, it))
+        }
+    }
+
     @Synchronized
     @Throws(LoginsStorageException::class)
     override fun ensureUnlocked(encryptionKey: String) {
@@ -175,6 +213,29 @@ class DatabaseLoginsStorage(private val dbPath: String) : AutoCloseable, LoginsS
         }
     }
 
+    @Throws(LoginsStorageException::class)
+    override fun deleteWithUndo(id: String): Boolean {
+        return writeQueryCounters.measure {
+            rustCallWithLock { raw, error ->
+                val deleted = LoginsStoreMetrics.writeQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_delete_with_undo(raw, id, error)
+                }
+                deleted.toInt() != 0
+            }
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun undoDelete(id: String) {
+        return writeQueryCounters.measure {
+            rustCallWithLock { raw, error ->
+                LoginsStoreMetrics.writeQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_undo_delete(raw, id, error)
+                }
+            }
+        }
+    }
+
     @Throws(LoginsStorageException::class)
     override fun get(id: String): ServerPassword? {
         return readQueryCounters.measure {
@@ -332,6 +393,173 @@ class DatabaseLoginsStorage(private val dbPath: String) : AutoCloseable, LoginsS
         }
     }
 
+    @Throws(LoginsStorageException::class)
+    override fun search(query: String): List<ServerPassword> {
+        return readQueryCounters.measure {
+            val rustBuf = rustCallWithLock { raw, error ->
+                LoginsStoreMetrics.readQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_search(raw, query, error)
+                }
+            }
+            try {
+                ServerPassword.fromCollectionMessage(MsgTypes.PasswordInfos.parseFrom(rustBuf.asCodedInputStream()!!))
+            } finally {
+                PasswordSyncAdapter.INSTANCE.sync15_passwords_destroy_buffer(rustBuf)
+            }
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun runMaintenance(tombstoneMaxAgeSecs: Long): JSONObject {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_run_maintenance(raw, tombstoneMaxAgeSecs, error)
+        }.getAndConsumeRustString()
+        return JSONObject(json)
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun updatePassword(id: String, newPassword: String) {
+        writeQueryCounters.measure {
+            rustCallWithLock { raw, error ->
+                LoginsStoreMetrics.writeQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_update_password(raw, id, newPassword, error)
+                }
+            }
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun deleteBetween(startMs: Long, endMs: Long): Long {
+        return writeQueryCounters.measure {
+            rustCallWithLock { raw, error ->
+                LoginsStoreMetrics.writeQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_delete_between(raw, startMs, endMs, error)
+                }
+            }
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun quarantinedDatabasePath(): String? {
+        return nullableRustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_quarantined_database_path(raw, error)
+        }?.getAndConsumeRustString()
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun stats(): JSONObject {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_stats(raw, error)
+        }.getAndConsumeRustString()
+        return JSONObject(json)
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun applyBatch(operations: List<LoginOperation>): List<LoginOperationResult> {
+        return writeQueryCounters.measure {
+            val opsJson = JSONArray(operations.map { it.toJSON() }).toString()
+            val json = rustCallWithLock { raw, error ->
+                LoginsStoreMetrics.writeQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_apply_batch(raw, opsJson, error)
+                }
+            }.getAndConsumeRustString()
+            val results = JSONArray(json)
+            (0 until results.length()).map { LoginOperationResult.fromJSON(results.getJSONObject(it)) }
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun dedupeAndMerge(): JSONObject {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_dedupe_and_merge(raw, error)
+        }.getAndConsumeRustString()
+        return JSONObject(json)
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun setMergePolicy(policy: MergePolicy) {
+        rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_set_merge_policy(raw, policy.value, error)
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun setLocalOnly(id: String, localOnly: Boolean) {
+        writeQueryCounters.measure {
+            rustCallWithLock { raw, error ->
+                val localOnlyByte: Byte = if (localOnly) 1 else 0
+                LoginsStoreMetrics.writeQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_set_local_only(raw, id, localOnlyByte, error)
+                }
+            }
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun isLocalOnly(id: String): Boolean {
+        return readQueryCounters.measure {
+            rustCallWithLock { raw, error ->
+                LoginsStoreMetrics.readQueryTime.measure {
+                    PasswordSyncAdapter.INSTANCE.sync15_passwords_is_local_only(raw, id, error)
+                }
+            }.toInt() != 0
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun setHistoryLimit(limit: Int) {
+        rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_set_history_limit(raw, limit, error)
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun getHistory(id: String): JSONArray {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_get_history(raw, id, error)
+        }.getAndConsumeRustString()
+        return JSONArray(json)
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun passwordHealthReport(): JSONObject {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_password_health_report(raw, error)
+        }.getAndConsumeRustString()
+        return JSONObject(json)
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun upgradeHttpOrigins(): JSONObject {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_upgrade_http_origins(raw, error)
+        }.getAndConsumeRustString()
+        return JSONObject(json)
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun setAccessLogging(caller: String?) {
+        rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_set_access_logging(raw, caller, error)
+        }
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun getAccessLog(sinceMs: Long, caller: String?): JSONArray {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_get_access_log(raw, sinceMs, caller, error)
+        }.getAndConsumeRustString()
+        return JSONArray(json)
+    }
+
+    @Throws(LoginsStorageException::class)
+    override fun findSuspiciousAccess(windowSecs: Long, maxLogins: Long): JSONArray {
+        val json = rustCallWithLock { raw, error ->
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_find_suspicious_access(raw, windowSecs, maxLogins, error)
+        }.getAndConsumeRustString()
+        return JSONArray(json)
+    }
+
     @Synchronized
     @Throws(LoginsStorageException::class)
     override fun close() {
@@ -395,6 +623,18 @@ class DatabaseLoginsStorage(private val dbPath: String) : AutoCloseable, LoginsS
             LoginsStoreMetrics.writeQueryErrorCount
         )
     }
+
+    companion object {
+        /**
+         * Interrupt every unlocked store, and every store unlocked from now on, so that
+         * an in-flight sync or query doesn't block the application from shutting down.
+         * Interrupted operations throw [InterruptedException]. This also interrupts the
+         * other components' databases, and can't be undone.
+         */
+        fun shutdown() {
+            PasswordSyncAdapter.INSTANCE.sync15_passwords_shutdown()
+        }
+    }
 }
 
 /**
@@ -0,0 +1,71 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.logins
+
+import org.json.JSONObject
+
+/**
+ * A single change to apply with [LoginsStorage.applyBatch]. Each has the same effect as
+ * the corresponding call to [LoginsStorage.add], [LoginsStorage.update] or
+ * [LoginsStorage.delete].
+ */
+sealed class LoginOperation {
+    data class Add(val login: ServerPassword) : LoginOperation()
+    data class Update(val login: ServerPassword) : LoginOperation()
+    data class Delete(val id: String) : LoginOperation()
+
+    internal fun toJSON(): JSONObject {
+        return when (this) {
+            is Add -> JSONObject().put("op", "add").put("login", login.toJSON())
+            is Update -> JSONObject().put("op", "update").put("login", login.toJSON())
+            is Delete -> JSONObject().put("op", "delete").put("id", id)
+        }
+    }
+}
+
+/**
+ * The outcome of one of the operations passed to [LoginsStorage.applyBatch].
+ */
+sealed class LoginOperationResult {
+    /**
+     * The operation was applied. `id` is the ID of the affected login, which may have been
+     * generated for an [LoginOperation.Add].
+     */
+    data class Success(val id: String) : LoginOperationResult()
+
+    /**
+     * The operation failed, and wasn't applied. `error` names the kind of error, such as
+     * `NoSuchRecord` or `DuplicateGuid`.
+     */
+    data class Failure(val error: String, val message: String) : LoginOperationResult()
+
+    companion object {
+        internal fun fromJSON(obj: JSONObject): LoginOperationResult {
+            return if (obj.has("id")) {
+                Success(obj.getString("id"))
+            } else {
+                Failure(obj.getString("error"), obj.getString("message"))
+            }
+        }
+    }
+}
+
+// The JSON representation of a login, as the Rust code serializes it.
+internal fun ServerPassword.toJSON(): JSONObject {
+    return JSONObject()
+        .put("id", id)
+        .put("hostname", hostname)
+        .put("username", username)
+        .put("password", password)
+        .put("httpRealm", httpRealm)
+        .put("formSubmitURL", formSubmitURL)
+        .put("timesUsed", timesUsed)
+        .put("timeCreated", timeCreated)
+        .put("timeLastUsed", timeLastUsed)
+        .put("timePasswordChanged", timePasswordChanged)
+        .put("usernameField", usernameField)
+        .put("passwordField", passwordField)
+        .put("notes", notes)
+}
@@ -4,6 +4,7 @@
 
 package mozilla.appservices.logins
 import mozilla.appservices.sync15.SyncTelemetryPing
+import org.json.JSONArray
 import org.json.JSONObject
 
 class SyncUnlockInfo(
@@ -120,6 +121,28 @@ interface LoginsStorage : AutoCloseable {
     @Throws(LoginsStorageException::class)
     fun delete(id: String): Boolean
 
+    /**
+     * Like [delete], but the deletion can be undone with [undoDelete] for a
+     * short time afterwards, as long as it hasn't been synced. The login is
+     * restored with its original ID and sync state.
+     *
+     * Returns true if the deletion did anything, false if no such record exists.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun deleteWithUndo(id: String): Boolean
+
+    /**
+     * Restores a login deleted by [deleteWithUndo].
+     *
+     * @throws [CannotUndoDeleteException] If the deletion has been synced, the
+     * grace period has passed, or the login wasn't deleted by [deleteWithUndo].
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun undoDelete(id: String)
+
     /**
      * Fetch a password from the underlying storage layer by ID.
      *
@@ -150,6 +173,10 @@ interface LoginsStorage : AutoCloseable {
     /**
      * Fetch the list of passwords for some base domain from the underlying storage layer.
      *
+     * The base domain (eTLD+1) of [baseDomain] is found using the public suffix list, so
+     * `"www.example.co.uk"` returns the passwords for `example.co.uk` and all its subdomains,
+     * and a public suffix like `"co.uk"` returns nothing.
+     *
      * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
      */
     @Throws(LoginsStorageException::class)
@@ -257,4 +284,220 @@ interface LoginsStorage : AutoCloseable {
      */
     @Throws(LoginsStorageException::class)
     fun potentialDupesIgnoringUsername(login: ServerPassword): List<ServerPassword>
+
+    /**
+     * Find the logins whose hostname, username or HTTP realm contain `query`, ignoring case.
+     * Queries containing non-ascii domain names also match the punycode form hostnames are
+     * stored in.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun search(query: String): List<ServerPassword>
+
+    /**
+     * Housekeeping intended to be run while the application is idle: deletes synced local
+     * tombstones older than `tombstoneMaxAgeSecs`, reclaims free pages, rebuilds indices
+     * and checks the integrity of the database.
+     *
+     * Returns a report with the number of `tombstones_pruned`, the `integrity_errors` found
+     * (empty if the database is intact), and the `total_duration` in milliseconds.
+     *
+     * Tombstones for deletions which haven't been uploaded yet are never pruned.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun runMaintenance(tombstoneMaxAgeSecs: Long): JSONObject
+
+    /**
+     * Change just the password of the login with the given ID, leaving every other field
+     * as it is. Unlike [update], this doesn't clobber fields which may have been changed
+     * by other clients since the login was fetched.
+     *
+     * @throws [NoSuchRecordException] if the login does not exist.
+     * @throws [InvalidRecordException] if the password is empty.
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun updatePassword(id: String, newPassword: String)
+
+    /**
+     * Delete every login created at or after `startMs`, and before `endMs` (both in
+     * milliseconds from the unix epoch), for "clear recent data". The deletions will be
+     * synced to the server on the next call to sync.
+     *
+     * Returns the number of logins deleted.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun deleteBetween(startMs: Long, endMs: Long): Long
+
+    /**
+     * Get summary statistics about the store: `num_logins`, `num_tombstones`,
+     * `num_never_used`, `logins_per_base_domain` (an object mapping each base domain,
+     * like `example.com`, to its number of logins) and `db_size` (the size of the
+     * database file, in bytes).
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun stats(): JSONObject
+
+    /**
+     * If the database was corrupt when it was unlocked, it's moved aside, and an empty one
+     * is created in its place. This returns the path it was moved to, or null if it wasn't
+     * corrupt. A database which can't be decrypted with the key isn't treated as corrupt.
+     * The lost logins are downloaded again on the next sync.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun quarantinedDatabasePath(): String?
+
+    /**
+     * Unlock (open) an existing database without ever writing to it, for diagnostic tools
+     * and app extensions which must not modify the file. Schema migrations are skipped,
+     * and methods which would write to the database throw a [LoginsStorageException].
+     *
+     * @throws [MismatchedLockException] if the database is already unlocked
+     * @throws [InvalidKeyException] if the encryption key is wrong, or the db is corrupt
+     * @throws [LoginsStorageException] if there was some other error opening the database
+     */
+    @Throws(LoginsStorageException::class)
+    fun unlockReadOnly(encryptionKey: String)
+
+    /**
+     * Apply `operations` in order, in a single transaction, returning the result of each.
+     * An operation which fails, for example because the login doesn't exist, doesn't prevent
+     * the others from being applied.
+     *
+     * @throws [InterruptedException] if the batch was interrupted, in which case nothing was
+     * applied.
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun applyBatch(operations: List<LoginOperation>): List<LoginOperationResult>
+
+    /**
+     * Merge logins with the same (normalized) origin and username, which are commonly left
+     * behind by imports from several sources. In each group, the login whose password
+     * changed most recently is kept, and gets the group's combined usage counters. The
+     * others are deleted.
+     *
+     * Returns a report with the number of logins deleted (`num_deleted`), and `merged`, an
+     * object mapping the ID of each login which was kept to the IDs merged into it.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun dedupeAndMerge(): JSONObject
+
+    /**
+     * Change how conflicting changes are resolved when syncing. Defaults to
+     * [MergePolicy.PREFER_NEWER]. This should be set before the first sync.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun setMergePolicy(policy: MergePolicy)
+
+    /**
+     * Mark the login with the given ID as local-only, meaning it's never uploaded and
+     * incoming changes to it are ignored, or make it a normal login again (in which case
+     * it's uploaded on the next sync). A login which was synced before being made local-only
+     * is left as it was on the server.
+     *
+     * @throws [NoSuchRecordException] if the login does not exist.
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun setLocalOnly(id: String, localOnly: Boolean)
+
+    /**
+     * Returns true if the login with the given ID is local-only. See [setLocalOnly].
+     *
+     * @throws [NoSuchRecordException] if the login does not exist.
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun isLocalOnly(id: String): Boolean
+
+    /**
+     * Start recording which fields of each login are changed, and when, keeping at most
+     * `limit` entries, or stop recording if `limit` is 0 (the default). Existing entries are
+     * kept when recording is stopped, and removed by [wipeLocal].
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun setHistoryLimit(limit: Int)
+
+    /**
+     * Get the recorded changes to the login with the given ID, oldest first. Each entry has
+     * the `guid`, the `field` which changed (or `created` or `deleted`), the `timestamp` and
+     * the `source` of the change, `local` or `sync`. Values are never recorded.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun getHistory(id: String): JSONArray
+
+    /**
+     * Check the stored passwords without them leaving the Rust code. The report has
+     * `reused`, a list of the groups of logins (with their `guids` and `hosts`) which share
+     * a password across more than one site, and `strength`, the `guid`, `score` (from 0, very
+     * weak, to 4, very strong) and estimated `entropy_bits` of each login's password.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun passwordHealthReport(): JSONObject
+
+    /**
+     * Change the origin of every `http://` login to `https://`, or merge it into an existing
+     * `https` login for the same host and user with the same password. Changed logins are
+     * uploaded on the next sync.
+     *
+     * Returns a report with the IDs of the `upgraded` logins, `merged` (an object mapping the
+     * ID of each merged `http` login to the `https` login it was merged into), and the IDs of
+     * the `conflicting` logins which were left alone because the passwords differ.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun upgradeHttpOrigins(): JSONObject
+
+    /**
+     * Start logging which logins are read, tagged with `caller` (which identifies the API
+     * consumer, such as the app or its autofill service), or stop if `caller` is null (the
+     * default). This should be called right after unlocking. Reads of the same login are
+     * logged at most once a minute. Existing entries are kept when logging is stopped, and
+     * removed by [wipeLocal].
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun setAccessLogging(caller: String?)
+
+    /**
+     * Get the logged reads made at or after `sinceMs`, by `caller` if it's not null, oldest
+     * first. Each entry has the `guid` of the login, the `caller` and the `timestamp`.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun getAccessLog(sinceMs: Long, caller: String? = null): JSONArray
+
+    /**
+     * Get the callers which read more than `maxLogins` different logins in the last
+     * `windowSecs` seconds, such as an autofill service reading every login instead of only
+     * those for the site being filled. Each entry has the `caller`, `num_logins`, and the
+     * `first_access` and `last_access` times.
+     *
+     * @throws [LoginsStorageException] On unexpected errors (IO failure, rust panics, etc)
+     */
+    @Throws(LoginsStorageException::class)
+    fun findSuspiciousAccess(windowSecs: Long, maxLogins: Long): JSONArray
 }
@@ -54,6 +54,18 @@ class RequestFailedException(msg: String) : LoginsStorageException(msg)
  */
 class InterruptedException(msg: String) : LoginsStorageException(msg)
 
+/**
+ * This error is emitted if the database stayed locked by another connection
+ * for too long. The operation may succeed if it's retried later.
+ */
+class DatabaseBusyException(msg: String) : LoginsStorageException(msg)
+
+/**
+ * This is thrown by `undoDelete()` if the deletion has been synced, the
+ * grace period has passed, or the login wasn't deleted by `deleteWithUndo()`.
+ */
+class CannotUndoDeleteException(msg: String) : LoginsStorageException(msg)
+
 /**
  * A reason a login may be invalid
  */
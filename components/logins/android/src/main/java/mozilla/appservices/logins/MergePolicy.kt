@@ -0,0 +1,20 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.logins
+
+/**
+ * How conflicting changes to a login are resolved when syncing. See
+ * [LoginsStorage.setMergePolicy].
+ */
+enum class MergePolicy(internal val value: Int) {
+    /** Local changes always win. */
+    PREFER_LOCAL(0),
+    /** Changes from the server always win. */
+    PREFER_REMOTE(1),
+    /** The most recent change wins. This is the default. */
+    PREFER_NEWER(2),
+    /** The side whose password was changed most recently wins. */
+    PASSWORD_NEWEST_TIMESTAMP(3),
+}
@@ -65,7 +65,13 @@ data class ServerPassword(
     val timePasswordChanged: Long = 0L,
 
     val usernameField: String,
-    val passwordField: String
+    val passwordField: String,
+
+    /**
+     * Free-form notes about this login. These are encrypted along with the
+     * username and password, and synced.
+     */
+    val notes: String? = null
 ) {
 
     fun toProtobuf(): MsgTypes.PasswordInfo {
@@ -82,6 +88,7 @@ data class ServerPassword(
                 .setTimePasswordChanged(this.timePasswordChanged)
         this.formSubmitURL?.let { builder.setFormSubmitURL(it) }
         this.httpRealm?.let { builder.setHttpRealm(it) }
+        this.notes?.let { builder.setNotes(it) }
         return builder.build()
     }
 
@@ -100,7 +107,8 @@ data class ServerPassword(
                 timeLastUsed = msg.timeLastUsed,
                 timePasswordChanged = msg.timePasswordChanged,
                 usernameField = msg.usernameField,
-                passwordField = msg.passwordField
+                passwordField = msg.passwordField,
+                notes = if (msg.hasNotes()) msg.notes else null
             )
         }
 
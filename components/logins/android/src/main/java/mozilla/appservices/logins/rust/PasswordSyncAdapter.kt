@@ -34,6 +34,8 @@ internal interface PasswordSyncAdapter : Library {
 
     fun sync15_passwords_state_destroy(handle: LoginsDbHandle, error: RustError.ByReference)
 
+    fun sync15_passwords_shutdown()
+
     // Important: strings returned from rust as *char must be Pointers on this end, returning a
     // String will work but either force us to leak them, or cause us to corrupt the heap (when we
     // free them).
@@ -68,6 +70,8 @@ internal interface PasswordSyncAdapter : Library {
     // This is 1 for true and 0 for false, it would be a boolean but we need to return a value with
     // a known size.
     fun sync15_passwords_delete(handle: LoginsDbHandle, id: String, error: RustError.ByReference): Byte
+    fun sync15_passwords_delete_with_undo(handle: LoginsDbHandle, id: String, error: RustError.ByReference): Byte
+    fun sync15_passwords_undo_delete(handle: LoginsDbHandle, id: String, error: RustError.ByReference)
     // Note: returns guid of new login entry (unless one was specifically requested)
     fun sync15_passwords_add(handle: LoginsDbHandle, data: Pointer, len: Int, error: RustError.ByReference): Pointer?
     fun sync15_passwords_update(handle: LoginsDbHandle, data: Pointer, len: Int, error: RustError.ByReference)
@@ -95,6 +99,61 @@ internal interface PasswordSyncAdapter : Library {
         new_encryption_key_len: Int,
         error: RustError.ByReference
     )
+
+    // return protocol buffer
+    fun sync15_passwords_search(handle: LoginsDbHandle, query: String, error: RustError.ByReference): RustBuffer.ByValue
+
+    // Returns a JSON string containing the maintenance report.
+    fun sync15_passwords_run_maintenance(handle: LoginsDbHandle, tombstone_max_age_secs: Long, error: RustError.ByReference): Pointer?
+
+    fun sync15_passwords_update_password(handle: LoginsDbHandle, id: String, new_password: String, error: RustError.ByReference)
+
+    // Returns the number of logins deleted.
+    fun sync15_passwords_delete_between(handle: LoginsDbHandle, start_ms: Long, end_ms: Long, error: RustError.ByReference): Long
+
+    // Returns a JSON string containing the store's statistics.
+    fun sync15_passwords_stats(handle: LoginsDbHandle, error: RustError.ByReference): Pointer?
+
+    // Returns null if the database wasn't corrupt when it was opened.
+    fun sync15_passwords_quarantined_database_path(handle: LoginsDbHandle, error: RustError.ByReference): Pointer?
+
+    fun sync15_passwords_state_new_readonly(
+        db_path: String,
+        encryption_key: String,
+        salt: String?,
+        error: RustError.ByReference
+    ): LoginsDbHandle
+
+    fun sync15_passwords_state_new_in_memory(encryption_key: String?, error: RustError.ByReference): LoginsDbHandle
+
+    // Takes and returns JSON arrays; see `sync15_passwords_apply_batch`.
+    fun sync15_passwords_apply_batch(handle: LoginsDbHandle, ops_json: String, error: RustError.ByReference): Pointer?
+
+    // Returns a JSON string containing the merge report.
+    fun sync15_passwords_dedupe_and_merge(handle: LoginsDbHandle, error: RustError.ByReference): Pointer?
+
+    fun sync15_passwords_set_merge_policy(handle: LoginsDbHandle, policy: Int, error: RustError.ByReference)
+
+    // `local_only` is 1 for true and 0 for false, as is the return value of `is_local_only`.
+    fun sync15_passwords_set_local_only(handle: LoginsDbHandle, id: String, local_only: Byte, error: RustError.ByReference)
+    fun sync15_passwords_is_local_only(handle: LoginsDbHandle, id: String, error: RustError.ByReference): Byte
+
+    // `limit` is the number of entries to keep, or 0 to stop recording.
+    fun sync15_passwords_set_history_limit(handle: LoginsDbHandle, limit: Int, error: RustError.ByReference)
+    // Returns a JSON string containing the history entries.
+    fun sync15_passwords_get_history(handle: LoginsDbHandle, id: String, error: RustError.ByReference): Pointer?
+
+    // Returns a JSON string containing the password health report.
+    fun sync15_passwords_password_health_report(handle: LoginsDbHandle, error: RustError.ByReference): Pointer?
+
+    // Returns a JSON string containing the upgrade report.
+    fun sync15_passwords_upgrade_http_origins(handle: LoginsDbHandle, error: RustError.ByReference): Pointer?
+
+    fun sync15_passwords_set_access_logging(handle: LoginsDbHandle, caller: String?, error: RustError.ByReference)
+    // Returns a JSON string containing the access log entries.
+    fun sync15_passwords_get_access_log(handle: LoginsDbHandle, since_ms: Long, caller: String?, error: RustError.ByReference): Pointer?
+    // Returns a JSON string containing the suspicious callers.
+    fun sync15_passwords_find_suspicious_access(handle: LoginsDbHandle, window_secs: Long, max_logins: Long, error: RustError.ByReference): Pointer?
 }
 
 internal typealias LoginsDbHandle = Long
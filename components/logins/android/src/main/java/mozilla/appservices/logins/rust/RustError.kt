@@ -6,6 +6,8 @@ package mozilla.appservices.logins.rust
 
 import com.sun.jna.Pointer
 import com.sun.jna.Structure
+import mozilla.appservices.logins.CannotUndoDeleteException
+import mozilla.appservices.logins.DatabaseBusyException
 import mozilla.appservices.logins.IdCollisionException
 import mozilla.appservices.logins.InvalidKeyException
 import mozilla.appservices.logins.InvalidRecordException
@@ -51,6 +53,8 @@ open class RustError : Structure() {
             4 -> return InvalidKeyException(message)
             5 -> return RequestFailedException(message)
             6 -> return InterruptedException(message)
+            8 -> return DatabaseBusyException(message)
+            9 -> return CannotUndoDeleteException(message)
 
             64 -> return InvalidRecordException(message, InvalidLoginReason.EMPTY_ORIGIN)
             65 -> return InvalidRecordException(message, InvalidLoginReason.EMPTY_PASSWORD)
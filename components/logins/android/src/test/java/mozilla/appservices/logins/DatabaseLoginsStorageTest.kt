@@ -309,6 +309,27 @@ class DatabaseLoginsStorageTest {
         finishAndClose(test)
     }
 
+    @Test
+    fun testDeleteWithUndo() {
+        val test = getTestStore()
+
+        test.unlock(encryptionKey)
+        val login = test.get("aaaaaaaaaaaa")
+        assertNotNull(login)
+        assertTrue(test.deleteWithUndo("aaaaaaaaaaaa"))
+        assertNull(test.get("aaaaaaaaaaaa"))
+        assertFalse(test.deleteWithUndo("aaaaaaaaaaaa"))
+
+        test.undoDelete("aaaaaaaaaaaa")
+        assertEquals(login, test.get("aaaaaaaaaaaa"))
+
+        expectException(CannotUndoDeleteException::class.java) {
+            test.undoDelete("aaaaaaaaaaaa")
+        }
+
+        finishAndClose(test)
+    }
+
     @Test
     fun testListWipe() {
         val test = getTestStore()
@@ -549,6 +570,160 @@ class DatabaseLoginsStorageTest {
         finishAndClose(test)
     }
 
+    @Test
+    fun testSearch() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+
+        assertEquals(2, test.search("foobar").size)
+        assertEquals(listOf("bbbbbbbbbbbb"), test.search("EXAMPLE.ORG").map { it.id })
+        assertEquals(listOf("aaaaaaaaaaaa"), test.search("something").map { it.id })
+        assertEquals(0, test.search("mozilla").size)
+
+        finishAndClose(test)
+    }
+
+    @Test
+    fun testUpdatePassword() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+
+        test.updatePassword("aaaaaaaaaaaa", "hunter3")
+        val updated = test.get("aaaaaaaaaaaa")!!
+        assertEquals("hunter3", updated.password)
+        assertEquals("users_name", updated.usernameField)
+
+        expectException(NoSuchRecordException::class.java) { test.updatePassword("abcdabcdabcd", "hunter3") }
+        expectException(InvalidRecordException::class.java) { test.updatePassword("aaaaaaaaaaaa", "") }
+
+        finishAndClose(test)
+    }
+
+    @Test
+    fun testDeleteBetween() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+        val now = System.currentTimeMillis()
+
+        assertEquals(0L, test.deleteBetween(0, 1))
+        assertEquals(2L, test.deleteBetween(0, now + 1000))
+        assertEquals(0, test.list().size)
+
+        finishAndClose(test)
+    }
+
+    @Test
+    fun testStats() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+
+        val stats = test.stats()
+        assertEquals(2, stats.getInt("num_logins"))
+        assertEquals(0, stats.getInt("num_tombstones"))
+        assertEquals(1, stats.getJSONObject("logins_per_base_domain").getInt("example.com"))
+        assertNull(test.quarantinedDatabasePath())
+
+        finishAndClose(test)
+    }
+
+    @Test
+    fun testUnlockInMemory() {
+        val store = createTestStore()
+        store.unlockInMemory()
+        store.add(ServerPassword(
+                id = "aaaaaaaaaaaa",
+                hostname = "https://www.example.com",
+                httpRealm = "Something",
+                username = "Foobar2000",
+                password = "hunter2",
+                usernameField = "users_name",
+                passwordField = "users_password"
+        ))
+        assertEquals(1, store.list().size)
+        expectException(MismatchedLockException::class.java) { store.unlockInMemory() }
+
+        // Nothing was written to disk.
+        store.lock()
+        store.unlock(encryptionKey)
+        assertEquals(0, store.list().size)
+
+        finishAndClose(store)
+    }
+
+    @Test
+    fun testApplyBatch() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+        val a = test.get("aaaaaaaaaaaa")!!
+
+        val results = test.applyBatch(listOf(
+                LoginOperation.Update(a.copy(password = "hunter3")),
+                LoginOperation.Delete("bbbbbbbbbbbb"),
+                LoginOperation.Delete("abcdabcdabcd")
+        ))
+        assertEquals(LoginOperationResult.Success("aaaaaaaaaaaa"), results[0])
+        assertEquals(LoginOperationResult.Success("bbbbbbbbbbbb"), results[1])
+        assertEquals("NoSuchRecord", (results[2] as LoginOperationResult.Failure).error)
+
+        assertEquals("hunter3", test.get("aaaaaaaaaaaa")!!.password)
+        assertNull(test.get("bbbbbbbbbbbb"))
+
+        finishAndClose(test)
+    }
+
+    @Test
+    fun testLocalOnly() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+
+        assertFalse(test.isLocalOnly("aaaaaaaaaaaa"))
+        test.setLocalOnly("aaaaaaaaaaaa", true)
+        assertTrue(test.isLocalOnly("aaaaaaaaaaaa"))
+        assertFalse(test.isLocalOnly("bbbbbbbbbbbb"))
+        test.setLocalOnly("aaaaaaaaaaaa", false)
+        assertFalse(test.isLocalOnly("aaaaaaaaaaaa"))
+
+        expectException(NoSuchRecordException::class.java) { test.setLocalOnly("abcdabcdabcd", true) }
+
+        finishAndClose(test)
+    }
+
+    @Test
+    fun testHistory() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+
+        test.setHistoryLimit(100)
+        test.updatePassword("aaaaaaaaaaaa", "hunter3")
+        val history = test.getHistory("aaaaaaaaaaaa")
+        assertEquals(1, history.length())
+        assertEquals("password", history.getJSONObject(0).getString("field"))
+        assertEquals("local", history.getJSONObject(0).getString("source"))
+        assertEquals(0, test.getHistory("bbbbbbbbbbbb").length())
+
+        finishAndClose(test)
+    }
+
+    @Test
+    fun testAccessLog() {
+        val test = getTestStore()
+        test.unlock(encryptionKey)
+
+        test.setAccessLogging("autofill")
+        test.get("aaaaaaaaaaaa")
+        test.get("bbbbbbbbbbbb")
+        val log = test.getAccessLog(0)
+        assertEquals(2, log.length())
+        assertEquals("autofill", log.getJSONObject(0).getString("caller"))
+        assertEquals(0, test.getAccessLog(0, "another app").length())
+
+        val suspicious = test.findSuspiciousAccess(60, 1)
+        assertEquals(1, suspicious.length())
+        assertEquals(2, suspicious.getJSONObject(0).getInt("num_logins"))
+
+        finishAndClose(test)
+    }
+
     @Test
     @Suppress("DEPRECATION")
     fun testUnlockAfterError() {
@@ -14,9 +14,10 @@ use ffi_support::{
     define_string_destructor, ByteBuffer, ExternError, FfiStr,
 };
 use logins::msg_types::{PasswordInfo, PasswordInfos};
-use logins::{Login, LoginDb, PasswordEngine, Result};
+use logins::{Login, LoginDb, LoginOperation, MergePolicy, PasswordEngine, Result};
 use std::os::raw::c_char;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 lazy_static::lazy_static! {
     // TODO: this is basically a RwLock<HandleMap<Mutex<Arc<Mutex<...>>>>.
@@ -57,11 +58,56 @@ pub extern "C" fn sync15_passwords_state_new_with_salt(
     })
 }
 
+/// Opens an existing database without writing to it. `salt` may be null, for
+/// databases which store their salt in the header.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_state_new_readonly(
+    db_path: FfiStr<'_>,
+    encryption_key: FfiStr<'_>,
+    salt: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u64 {
+    log::debug!("sync15_passwords_state_new_readonly");
+    ENGINES.insert_with_result(error, || -> logins::Result<_> {
+        let path = db_path.as_str();
+        let key = encryption_key.as_str();
+        Ok(Arc::new(Mutex::new(PasswordEngine::open_readonly(
+            path,
+            Some(key),
+            salt.as_opt_str(),
+        )?)))
+    })
+}
+
+/// Creates a store which only lives in memory, for tests. `encryption_key`
+/// may be null.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_state_new_in_memory(
+    encryption_key: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u64 {
+    log::debug!("sync15_passwords_state_new_in_memory");
+    ENGINES.insert_with_result(error, || -> logins::Result<_> {
+        Ok(Arc::new(Mutex::new(PasswordEngine::new_in_memory(
+            encryption_key.as_opt_str(),
+        )?)))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn sync15_passwords_num_open_connections(error: &mut ExternError) -> u64 {
     ffi_support::call_with_output(error, || ENGINES.len() as u64)
 }
 
+/// Interrupts every open store, and every store opened from now on, with
+/// `interrupt_support::shutdown`. Since that's process-wide, this also
+/// interrupts the other components' databases. Safe to call from any thread.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_shutdown() {
+    log::debug!("sync15_passwords_shutdown");
+    interrupt_support::shutdown();
+}
+
 unsafe fn bytes_to_key_string(key_bytes: *const u8, len: usize) -> Option<String> {
     if len == 0 {
         log::info!("Opening/Creating unencrypted database!");
@@ -217,6 +263,34 @@ pub extern "C" fn sync15_passwords_sync(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn sync15_passwords_set_local_only(
+    handle: u64,
+    id: FfiStr<'_>,
+    local_only: u8,
+    error: &mut ExternError,
+) {
+    log::debug!("sync15_passwords_set_local_only");
+    ENGINES.call_with_result(error, handle, |state| {
+        state
+            .lock()
+            .unwrap()
+            .set_local_only(id.as_str(), local_only != 0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sync15_passwords_is_local_only(
+    handle: u64,
+    id: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("sync15_passwords_is_local_only");
+    ENGINES.call_with_result(error, handle, |state| {
+        state.lock().unwrap().is_local_only(id.as_str())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn sync15_passwords_touch(handle: u64, id: FfiStr<'_>, error: &mut ExternError) {
     log::debug!("sync15_passwords_touch");
@@ -225,6 +299,22 @@ pub extern "C" fn sync15_passwords_touch(handle: u64, id: FfiStr<'_>, error: &mu
     })
 }
 
+#[no_mangle]
+pub extern "C" fn sync15_passwords_update_password(
+    handle: u64,
+    id: FfiStr<'_>,
+    new_password: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("sync15_passwords_update_password");
+    ENGINES.call_with_result(error, handle, |state| {
+        state
+            .lock()
+            .unwrap()
+            .update_password(id.as_str(), new_password.as_str())
+    })
+}
+
 // Should we put this function in ffi_support as a `unsafe pub fn`?
 unsafe fn get_buffer<'a>(data: *const u8, len: i32) -> &'a [u8] {
     assert!(len >= 0, "Bad buffer len: {}", len);
@@ -269,18 +359,114 @@ pub extern "C" fn sync15_passwords_delete(
     })
 }
 
+/// Like `sync15_passwords_delete`, but the deletion can be undone with
+/// `sync15_passwords_undo_delete` for a short time afterwards.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_delete_with_undo(
+    handle: u64,
+    id: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("sync15_passwords_delete_with_undo");
+    ENGINES.call_with_result(error, handle, |state| {
+        state.lock().unwrap().delete_with_undo(id.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sync15_passwords_undo_delete(
+    handle: u64,
+    id: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("sync15_passwords_undo_delete");
+    ENGINES.call_with_result(error, handle, |state| {
+        state.lock().unwrap().undo_delete(id.as_str())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn sync15_passwords_wipe(handle: u64, error: &mut ExternError) {
     log::debug!("sync15_passwords_wipe");
     ENGINES.call_with_result(error, handle, |state| state.lock().unwrap().wipe())
 }
 
+#[no_mangle]
+pub extern "C" fn sync15_passwords_delete_between(
+    handle: u64,
+    start_ms: i64,
+    end_ms: i64,
+    error: &mut ExternError,
+) -> u64 {
+    log::debug!("sync15_passwords_delete_between");
+    ENGINES.call_with_result(error, handle, |state| -> Result<u64> {
+        Ok(state.lock().unwrap().delete_between(start_ms, end_ms)? as u64)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn sync15_passwords_wipe_local(handle: u64, error: &mut ExternError) {
     log::debug!("sync15_passwords_wipe_local");
     ENGINES.call_with_result(error, handle, |state| state.lock().unwrap().wipe_local())
 }
 
+/// Returns `PasswordEngine::stats` as JSON.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_stats(handle: u64, error: &mut ExternError) -> *mut c_char {
+    log::debug!("sync15_passwords_stats");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let stats = state.lock().unwrap().stats()?;
+        Ok(serde_json::to_string(&stats)?)
+    })
+}
+
+/// Returns the path the database was moved to if it was corrupt when the
+/// store was opened, or null if it wasn't.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_quarantined_database_path(
+    handle: u64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_quarantined_database_path");
+    ENGINES.call_with_output(error, handle, |state| {
+        state
+            .lock()
+            .unwrap()
+            .quarantined_database()
+            .map(|quarantined| quarantined.path.to_string_lossy().into_owned())
+    })
+}
+
+/// Returns `PasswordEngine::password_health_report` as JSON.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_password_health_report(
+    handle: u64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_password_health_report");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let report = state.lock().unwrap().password_health_report()?;
+        Ok(serde_json::to_string(&report)?)
+    })
+}
+
+/// Runs `PasswordEngine::run_maintenance`, returning the report as JSON.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_run_maintenance(
+    handle: u64,
+    tombstone_max_age_secs: u64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_run_maintenance");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let report = state
+            .lock()
+            .unwrap()
+            .run_maintenance(Duration::from_secs(tombstone_max_age_secs))?;
+        Ok(serde_json::to_string(&report)?)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn sync15_passwords_reset(handle: u64, error: &mut ExternError) {
     log::debug!("sync15_passwords_reset");
@@ -341,6 +527,25 @@ pub extern "C" fn sync15_passwords_get_by_base_domain(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn sync15_passwords_search(
+    handle: u64,
+    query: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("sync15_passwords_search");
+    ENGINES.call_with_result(error, handle, |state| -> Result<_> {
+        let infos = state
+            .lock()
+            .unwrap()
+            .search(query.as_str())?
+            .into_iter()
+            .map(Login::into)
+            .collect();
+        Ok(PasswordInfos { infos })
+    })
+}
+
 /// # Safety
 /// Deref pointer, thus unsafe
 #[no_mangle]
@@ -414,6 +619,164 @@ pub unsafe extern "C" fn sync15_passwords_import(
     })
 }
 
+/// Starts recording the history of changes to logins, keeping at most `limit`
+/// entries, or stops if `limit` is 0.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_set_history_limit(
+    handle: u64,
+    limit: u32,
+    error: &mut ExternError,
+) {
+    log::debug!("sync15_passwords_set_history_limit");
+    ENGINES.call_with_output(error, handle, |state| {
+        let limit = if limit == 0 {
+            None
+        } else {
+            Some(limit as usize)
+        };
+        state.lock().unwrap().set_history_limit(limit);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sync15_passwords_get_history(
+    handle: u64,
+    id: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_get_history");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let history = state.lock().unwrap().get_history(id.as_str())?;
+        Ok(serde_json::to_string(&history)?)
+    })
+}
+
+/// Starts logging reads tagged with `caller`, or stops if it's null.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_set_access_logging(
+    handle: u64,
+    caller: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("sync15_passwords_set_access_logging");
+    ENGINES.call_with_output(error, handle, |state| {
+        state
+            .lock()
+            .unwrap()
+            .set_access_logging(caller.as_opt_str());
+    })
+}
+
+/// Returns `PasswordEngine::get_access_log` as JSON. A null `caller` returns
+/// the reads made by every caller.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_get_access_log(
+    handle: u64,
+    since_ms: i64,
+    caller: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_get_access_log");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let log = state
+            .lock()
+            .unwrap()
+            .get_access_log(since_ms, caller.as_opt_str())?;
+        Ok(serde_json::to_string(&log)?)
+    })
+}
+
+/// Returns `PasswordEngine::find_suspicious_access` as JSON.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_find_suspicious_access(
+    handle: u64,
+    window_secs: u64,
+    max_logins: u64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_find_suspicious_access");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let found = state
+            .lock()
+            .unwrap()
+            .find_suspicious_access(Duration::from_secs(window_secs), max_logins)?;
+        Ok(serde_json::to_string(&found)?)
+    })
+}
+
+/// Sets how conflicts are resolved when syncing: 0 prefers local changes, 1
+/// prefers remote changes, 2 prefers the newer change (the default), and 3
+/// prefers the side whose password changed most recently.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_set_merge_policy(
+    handle: u64,
+    policy: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("sync15_passwords_set_merge_policy");
+    ENGINES.call_with_output(error, handle, |state| {
+        let policy = match policy {
+            0 => MergePolicy::PreferLocal,
+            1 => MergePolicy::PreferRemote,
+            3 => MergePolicy::PasswordNewestTimestamp,
+            _ => MergePolicy::PreferNewer,
+        };
+        state.lock().unwrap().set_merge_policy(policy);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sync15_passwords_dedupe_and_merge(
+    handle: u64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_dedupe_and_merge");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let report = state.lock().unwrap().dedupe_and_merge()?;
+        Ok(serde_json::to_string(&report)?)
+    })
+}
+
+/// Runs `PasswordEngine::upgrade_http_origins`, returning the report as JSON.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_upgrade_http_origins(
+    handle: u64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_upgrade_http_origins");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let report = state.lock().unwrap().upgrade_http_origins()?;
+        Ok(serde_json::to_string(&report)?)
+    })
+}
+
+/// Applies a JSON array of `LoginOperation`s in one transaction, returning a
+/// JSON array with an `{"id": ...}` or `{"error": ..., "message": ...}` object
+/// for each operation.
+#[no_mangle]
+pub extern "C" fn sync15_passwords_apply_batch(
+    handle: u64,
+    ops_json: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("sync15_passwords_apply_batch");
+    ENGINES.call_with_result(error, handle, |state| -> Result<String> {
+        let ops: Vec<LoginOperation> = serde_json::from_str(ops_json.as_str())?;
+        let results = state.lock().unwrap().apply_batch(ops)?;
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|result| match result {
+                Ok(guid) => serde_json::json!({ "id": guid }),
+                Err(e) => serde_json::json!({
+                    "error": e.label(),
+                    "message": e.to_string(),
+                }),
+            })
+            .collect();
+        Ok(serde_json::to_string(&results)?)
+    })
+}
+
 /// # Safety
 /// Deref pointer, thus unsafe
 #[no_mangle]
@@ -45,6 +45,15 @@ public enum LoginsStoreError: LocalizedError {
     /// database was invalid.
     case invalidSalt(message: String)
 
+    /// This error is emitted if the database stayed locked by another
+    /// connection for too long. The operation may succeed if it's retried.
+    case databaseBusy(message: String)
+
+    /// This error is emitted by `undoDelete` if the deletion has been
+    /// synced, the grace period has passed, or the login wasn't deleted by
+    /// `deleteWithUndo`.
+    case cannotUndoDelete(message: String)
+
     /// Our implementation of the localizedError protocol -- (This shows up in Sentry)
     public var errorDescription: String? {
         switch self {
@@ -68,6 +77,10 @@ public enum LoginsStoreError: LocalizedError {
             return "LoginsStoreError.interrupted: \(message)"
         case let .invalidSalt(message):
             return "LoginsStoreError.invalidSalt: \(message)"
+        case let .databaseBusy(message):
+            return "LoginsStoreError.databaseBusy: \(message)"
+        case let .cannotUndoDelete(message):
+            return "LoginsStoreError.cannotUndoDelete: \(message)"
         }
     }
 
@@ -126,6 +139,12 @@ public enum LoginsStoreError: LocalizedError {
         case Sync15Passwords_InvalidSaltError:
             return .invalidSalt(message: String(freeingRustString: message!))
 
+        case Sync15Passwords_DatabaseBusyError:
+            return .databaseBusy(message: String(freeingRustString: message!))
+
+        case Sync15Passwords_CannotUndoDeleteError:
+            return .cannotUndoDelete(message: String(freeingRustString: message!))
+
         default:
             return .unspecified(message: String(freeingRustString: message!))
         }
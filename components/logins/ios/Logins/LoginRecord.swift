@@ -72,6 +72,10 @@ open class LoginRecord {
     /// HTML field name of the password, if known.
     public var passwordField: String
 
+    /// Free-form notes about this login. These are encrypted along with the
+    /// username and password, and synced.
+    public var notes: String?
+
     open func toJSONDict() -> [String: Any] {
         var dict: [String: Any] = [
             "id": id,
@@ -96,6 +100,10 @@ open class LoginRecord {
             dict["formSubmitURL"] = formSubmitURL
         }
 
+        if let notes = self.notes {
+            dict["notes"] = notes
+        }
+
         return dict
     }
 
@@ -117,6 +125,9 @@ open class LoginRecord {
         if let f = formSubmitURL {
             buf.formSubmitURL = f
         }
+        if let n = notes {
+            buf.notes = n
+        }
 
         return buf
     }
@@ -141,7 +152,8 @@ open class LoginRecord {
             timePasswordChanged: (dict["timePasswordChanged"] as? Int64) ?? 0,
 
             usernameField: dict["usernameField"] as? String ?? "",
-            passwordField: dict["passwordField"] as? String ?? ""
+            passwordField: dict["passwordField"] as? String ?? "",
+            notes: dict["notes"] as? String
         )
     }
 
@@ -156,7 +168,8 @@ open class LoginRecord {
          timeCreated: Int64?,
          timePasswordChanged: Int64?,
          usernameField: String,
-         passwordField: String) {
+         passwordField: String,
+         notes: String? = nil) {
         self.id = id
         self.password = password
         self.hostname = hostname
@@ -169,6 +182,7 @@ open class LoginRecord {
         self.timePasswordChanged = timePasswordChanged ?? 0
         self.usernameField = usernameField
         self.passwordField = passwordField
+        self.notes = notes
     }
 
     public convenience init(fromJSONString json: String) throws {
@@ -201,7 +215,8 @@ internal func unpackProtobufInfo(msg: MsgTypes_PasswordInfo) -> LoginRecord {
         timeCreated: msg.timeCreated,
         timePasswordChanged: msg.timePasswordChanged,
         usernameField: msg.usernameField,
-        passwordField: msg.passwordField
+        passwordField: msg.passwordField,
+        notes: msg.hasNotes ? msg.notes : nil
     )
 }
 
@@ -210,3 +225,39 @@ internal func unpackProtobufInfoList(msgList: MsgTypes_PasswordInfos) -> [LoginR
         unpackProtobufInfo(msg: info)
     }
 }
+
+/// A single change to apply with `LoginsStorage.applyBatch`.
+public enum LoginOperation {
+    case add(login: LoginRecord)
+    case update(login: LoginRecord)
+    case delete(id: String)
+
+    internal func toJSONDict() -> [String: Any] {
+        switch self {
+        case let .add(login):
+            return ["op": "add", "login": login.toJSONDict()]
+        case let .update(login):
+            return ["op": "update", "login": login.toJSONDict()]
+        case let .delete(id):
+            return ["op": "delete", "id": id]
+        }
+    }
+}
+
+/// The outcome of one of the operations passed to `LoginsStorage.applyBatch`.
+public enum LoginOperationResult {
+    /// The operation was applied to the record with this id (which may have
+    /// been generated for an `add`).
+    case success(id: String)
+    /// The operation failed, and wasn't applied. `error` names the kind of
+    /// error, such as `NoSuchRecord`.
+    case failure(error: String, message: String)
+
+    internal init(fromJSONDict dict: [String: Any]) {
+        if let id = dict["id"] as? String {
+            self = .success(id: id)
+        } else {
+            self = .failure(error: dict["error"] as? String ?? "", message: dict["message"] as? String ?? "")
+        }
+    }
+}
@@ -32,6 +32,14 @@ open class LoginsStorage {
         }
     }
 
+    /// Interrupt every unlocked store, and every store unlocked from now on,
+    /// so that an in-flight sync or query doesn't block the application from
+    /// shutting down. This also interrupts the other components' databases,
+    /// and can't be undone.
+    public static func shutdown() {
+        sync15_passwords_shutdown()
+    }
+
     private func doDestroy() {
         let raw = self.raw
         self.raw = 0
@@ -123,7 +131,10 @@ open class LoginsStorage {
                 sync15_passwords_state_new(self.dbPath, key, err)
             }
         }
+        try openInterruptHandle()
+    }
 
+    private func openInterruptHandle() throws {
         do {
             interruptHandleLock.lock()
             defer { self.interruptHandleLock.unlock() }
@@ -155,6 +166,45 @@ open class LoginsStorage {
         }
     }
 
+    /// Unlock an existing database without ever writing to it, for app
+    /// extensions (like a share extension) which must not modify the file.
+    /// Schema migrations are skipped, and methods which would write to the
+    /// database throw. `salt` may be nil if the database stores its salt in
+    /// the header.
+    ///
+    /// Throws `LockError.mismatched` if the database is already unlocked.
+    ///
+    /// Throws a `LoginStoreError.InvalidKey` if the key is incorrect, or if dbPath does not point
+    /// to a database, (may also throw `LoginStoreError.Unspecified` or `.Panic`).
+    open func unlockReadOnly(key: String, salt: String?) throws {
+        try queue.sync {
+            if self.raw != 0 {
+                throw LockError.mismatched
+            }
+            self.raw = try LoginsStoreError.unwrap { err in
+                sync15_passwords_state_new_readonly(self.dbPath, key, salt, err)
+            }
+            try self.openInterruptHandle()
+        }
+    }
+
+    /// Unlock a new, empty store which only lives in memory, instead of the
+    /// database at `dbPath`. Its records are lost when it's locked. This is
+    /// intended for tests.
+    ///
+    /// Throws `LockError.mismatched` if the database is already unlocked.
+    open func unlockInMemory(key: String?) throws {
+        try queue.sync {
+            if self.raw != 0 {
+                throw LockError.mismatched
+            }
+            self.raw = try LoginsStoreError.unwrap { err in
+                sync15_passwords_state_new_in_memory(key, err)
+            }
+            try self.openInterruptHandle()
+        }
+    }
+
     /// Equivalent to `unlockWithKeyAndSalt(key:, salt:)`, but does not throw if the
     /// database is already unlocked.
     open func ensureUnlockedWithKeyAndSalt(key: String, salt: String) throws {
@@ -285,6 +335,31 @@ open class LoginsStorage {
         }
     }
 
+    /// Like `delete`, but the deletion can be undone with `undoDelete` for a
+    /// short time afterwards, as long as it hasn't been synced. Returns false
+    /// if no such record existed.
+    open func deleteWithUndo(id: String) throws -> Bool {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let boolAsU8 = try LoginsStoreError.unwrap { err in
+                sync15_passwords_delete_with_undo(engine, id, err)
+            }
+            return boolAsU8 != 0
+        }
+    }
+
+    /// Restore a record deleted by `deleteWithUndo`, with its original ID and
+    /// sync state. Throws `LoginsStoreError.cannotUndoDelete` if that's no
+    /// longer possible.
+    open func undoDelete(id: String) throws {
+        try queue.sync {
+            let engine = try self.getUnlocked()
+            try LoginsStoreError.unwrap { err in
+                sync15_passwords_undo_delete(engine, id, err)
+            }
+        }
+    }
+
     /// Ensure that the record is valid and a duplicate record doesn't exist.
     open func ensureValid(login: LoginRecord) throws {
         let data = try! login.toProtobuf().serializedData()
@@ -392,7 +467,10 @@ open class LoginsStorage {
         }
     }
 
-    /// Get the list of records for some base domain.
+    /// Get the list of records for some base domain. The base domain (eTLD+1)
+    /// of `baseDomain` is found using the public suffix list, so
+    /// "www.example.co.uk" returns the records for example.co.uk and all its
+    /// subdomains, and a public suffix like "co.uk" returns nothing.
     open func getByBaseDomain(baseDomain: String) throws -> [LoginRecord] {
         return try queue.sync {
             let engine = try self.getUnlocked()
@@ -405,6 +483,239 @@ open class LoginsStorage {
         }
     }
 
+    /// Get the records whose hostname, username or HTTP realm contain `query`,
+    /// ignoring case.
+    open func search(query: String) throws -> [LoginRecord] {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let buffer = try LoginsStoreError.unwrap { err in
+                sync15_passwords_search(engine, query, err)
+            }
+            defer { sync15_passwords_destroy_buffer(buffer) }
+            let msgList = try MsgTypes_PasswordInfos(serializedData: Data(loginsRustBuffer: buffer))
+            return unpackProtobufInfoList(msgList: msgList)
+        }
+    }
+
+    /// Deletes synced local tombstones older than `tombstoneMaxAgeSecs`, reclaims
+    /// free pages, rebuilds indices and checks the integrity of the database.
+    /// Deletions which haven't been uploaded yet are never pruned. This is
+    /// intended to be run while the application is idle.
+    ///
+    /// Returns the maintenance report as a JSON string.
+    open func runMaintenance(tombstoneMaxAgeSecs: UInt64) throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_run_maintenance(engine, tombstoneMaxAgeSecs, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
+    /// Change just the password of the record with the given id, leaving every
+    /// other field as it is.
+    ///
+    /// Throws `LoginStoreError.NoSuchRecord` if there was no such record.
+    open func updatePassword(id: String, newPassword: String) throws {
+        try queue.sync {
+            let engine = try self.getUnlocked()
+            try LoginsStoreError.unwrap { err in
+                sync15_passwords_update_password(engine, id, newPassword, err)
+            }
+        }
+    }
+
+    /// Delete every record created in `[startMs, endMs)`, in milliseconds from
+    /// the unix epoch. Returns the number of records deleted.
+    open func deleteBetween(startMs: Int64, endMs: Int64) throws -> UInt64 {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            return try LoginsStoreError.unwrap { err in
+                sync15_passwords_delete_between(engine, startMs, endMs, err)
+            }
+        }
+    }
+
+    /// Get summary statistics about the store, as a JSON string with the
+    /// `num_logins`, `num_tombstones`, `num_never_used`,
+    /// `logins_per_base_domain` and `db_size`.
+    open func stats() throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_stats(engine, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
+    /// If the database was corrupt when it was unlocked, it's moved aside,
+    /// and an empty one is created in its place. This returns the path it was
+    /// moved to, or nil if it wasn't corrupt. A database which can't be
+    /// decrypted with the key isn't treated as corrupt.
+    open func quarantinedDatabasePath() throws -> String? {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.tryUnwrap { err in
+                sync15_passwords_quarantined_database_path(engine, err)
+            }
+            return ptr.map { String(freeingRustString: $0) }
+        }
+    }
+
+    /// Apply `operations` in order, in a single transaction, returning the
+    /// result of each. An operation which fails doesn't prevent the others
+    /// from being applied.
+    open func applyBatch(operations: [LoginOperation]) throws -> [LoginOperationResult] {
+        let opsData = try! JSONSerialization.data(withJSONObject: operations.map { $0.toJSONDict() })
+        let opsJSON = String(data: opsData, encoding: .utf8)!
+        let resultsJSON = try queue.sync { () -> String in
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_apply_batch(engine, opsJSON, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+        let results = try JSONSerialization.jsonObject(with: resultsJSON.data(using: .utf8)!) as! [[String: Any]]
+        return results.map { LoginOperationResult(fromJSONDict: $0) }
+    }
+
+    /// Merge records with the same origin and username, keeping the one whose
+    /// password changed most recently. Returns the merge report as a JSON
+    /// string.
+    open func dedupeAndMerge() throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_dedupe_and_merge(engine, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
+    /// Change how conflicting changes are resolved when syncing. Defaults to
+    /// `.preferNewer`. This should be set before the first sync.
+    open func setMergePolicy(policy: MergePolicy) throws {
+        try queue.sync {
+            let engine = try self.getUnlocked()
+            try LoginsStoreError.unwrap { err in
+                sync15_passwords_set_merge_policy(engine, policy.rawValue, err)
+            }
+        }
+    }
+
+    /// Mark the record with the given id as local-only, meaning it's never
+    /// uploaded and incoming changes to it are ignored, or make it a normal
+    /// record again.
+    ///
+    /// Throws `LoginStoreError.NoSuchRecord` if there was no such record.
+    open func setLocalOnly(id: String, localOnly: Bool) throws {
+        try queue.sync {
+            let engine = try self.getUnlocked()
+            try LoginsStoreError.unwrap { err in
+                sync15_passwords_set_local_only(engine, id, localOnly ? 1 : 0, err)
+            }
+        }
+    }
+
+    /// Returns true if the record with the given id is local-only.
+    ///
+    /// Throws `LoginStoreError.NoSuchRecord` if there was no such record.
+    open func isLocalOnly(id: String) throws -> Bool {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let boolAsU8 = try LoginsStoreError.unwrap { err in
+                sync15_passwords_is_local_only(engine, id, err)
+            }
+            return boolAsU8 != 0
+        }
+    }
+
+    /// Start recording which fields of each record are changed, and when,
+    /// keeping at most `limit` entries, or stop recording if `limit` is 0.
+    open func setHistoryLimit(limit: UInt32) throws {
+        try queue.sync {
+            let engine = try self.getUnlocked()
+            try LoginsStoreError.unwrap { err in
+                sync15_passwords_set_history_limit(engine, limit, err)
+            }
+        }
+    }
+
+    /// Get the recorded changes to the record with the given id, oldest first,
+    /// as a JSON array.
+    open func getHistory(id: String) throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_get_history(engine, id, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
+    /// Get the reused and weak passwords, as a JSON string with the `reused`
+    /// groups of records and the `strength` of each record's password.
+    open func passwordHealthReport() throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_password_health_report(engine, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
+    /// Change the origin of every `http://` record to `https://`, or merge it
+    /// into a matching `https` record. Returns the upgrade report as a JSON
+    /// string.
+    open func upgradeHttpOrigins() throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_upgrade_http_origins(engine, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
+    /// Start logging which records are read, tagged with `caller` (which
+    /// identifies the API consumer, such as the app or its autofill
+    /// extension), or stop if `caller` is nil.
+    open func setAccessLogging(caller: String?) throws {
+        try queue.sync {
+            let engine = try self.getUnlocked()
+            try LoginsStoreError.unwrap { err in
+                sync15_passwords_set_access_logging(engine, caller, err)
+            }
+        }
+    }
+
+    /// Get the logged reads made at or after `sinceMs`, by `caller` if it's not
+    /// nil, oldest first, as a JSON array.
+    open func getAccessLog(sinceMs: Int64, caller: String?) throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_get_access_log(engine, sinceMs, caller, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
+    /// Get the callers which read more than `maxLogins` different records in
+    /// the last `windowSecs` seconds, as a JSON array.
+    open func findSuspiciousAccess(windowSecs: UInt64, maxLogins: UInt64) throws -> String {
+        return try queue.sync {
+            let engine = try self.getUnlocked()
+            let ptr = try LoginsStoreError.unwrap { err in
+                sync15_passwords_find_suspicious_access(engine, windowSecs, maxLogins, err)
+            }
+            return String(freeingRustString: ptr)
+        }
+    }
+
     /// Interrupt a pending operation on another thread, causing it to fail with
     /// `LoginsStoreError.interrupted`.
     ///
@@ -441,3 +752,15 @@ private class LoginsInterruptHandle {
         }
     }
 }
+
+/// How conflicting changes to a record are resolved when syncing.
+public enum MergePolicy: Int32 {
+    /// Local changes always win.
+    case preferLocal = 0
+    /// Changes from the server always win.
+    case preferRemote = 1
+    /// The most recent change wins. This is the default.
+    case preferNewer = 2
+    /// The side whose password was changed most recently wins.
+    case passwordNewestTimestamp = 3
+}
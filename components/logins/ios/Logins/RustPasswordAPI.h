@@ -16,6 +16,8 @@ typedef enum Sync15PasswordsErrorCode {
     Sync15Passwords_NetworkError     = 5,
     Sync15Passwords_InterruptedError = 6,
     Sync15Passwords_InvalidSaltError = 7,
+    Sync15Passwords_DatabaseBusyError = 8,
+    Sync15Passwords_CannotUndoDeleteError = 9,
 
     Sync15Passwords_InvalidLogin_EmptyOrigin = 64 + 0,
     Sync15Passwords_InvalidLogin_EmptyPassword = 64 + 1,
@@ -42,6 +44,8 @@ typedef struct Sync15PasswordsRustBuffer {
 
 uint64_t sync15_passwords_num_open_connections(Sync15PasswordsError *_Nonnull error_out);
 
+void sync15_passwords_shutdown(void);
+
 Sync15PasswordEngineHandle sync15_passwords_state_new(char const *_Nonnull db_path,
                                                       char const *_Nonnull encryption_key,
                                                       Sync15PasswordsError *_Nonnull error_out);
@@ -56,6 +60,14 @@ Sync15PasswordEngineHandle sync15_passwords_state_new_with_hex_key(char const *_
                                                                    uint32_t encryption_key_len,
                                                                    Sync15PasswordsError *_Nonnull error_out);
 
+Sync15PasswordEngineHandle sync15_passwords_state_new_readonly(char const *_Nonnull db_path,
+                                                               char const *_Nonnull encryption_key,
+                                                               char const *_Nullable salt,
+                                                               Sync15PasswordsError *_Nonnull error_out);
+
+Sync15PasswordEngineHandle sync15_passwords_state_new_in_memory(char const *_Nullable encryption_key,
+                                                                Sync15PasswordsError *_Nonnull error_out);
+
 void sync15_passwords_state_destroy(Sync15PasswordEngineHandle handle,
                                     Sync15PasswordsError *_Nonnull error_out);
 
@@ -120,6 +132,14 @@ uint8_t sync15_passwords_delete(Sync15PasswordEngineHandle handle,
                                 char const *_Nonnull id,
                                 Sync15PasswordsError *_Nonnull error);
 
+uint8_t sync15_passwords_delete_with_undo(Sync15PasswordEngineHandle handle,
+                                          char const *_Nonnull id,
+                                          Sync15PasswordsError *_Nonnull error);
+
+void sync15_passwords_undo_delete(Sync15PasswordEngineHandle handle,
+                                  char const *_Nonnull id,
+                                  Sync15PasswordsError *_Nonnull error);
+
 void sync15_passwords_check_valid(Sync15PasswordEngineHandle handle,
                                   uint8_t const *_Nonnull data,
                                   int32_t len,
@@ -147,3 +167,75 @@ void sync15_passwords_interrupt(Sync15PasswordsInterruptHandle *_Nonnull handle,
                                 Sync15PasswordsError *_Nonnull error);
 
 void sync15_passwords_interrupt_handle_destroy(Sync15PasswordsInterruptHandle *_Nonnull handle);
+
+Sync15PasswordsRustBuffer sync15_passwords_search(Sync15PasswordEngineHandle handle,
+                                                  char const *_Nonnull query,
+                                                  Sync15PasswordsError *_Nonnull error_out);
+
+char *_Nullable sync15_passwords_run_maintenance(Sync15PasswordEngineHandle handle,
+                                                 uint64_t tombstone_max_age_secs,
+                                                 Sync15PasswordsError *_Nonnull error);
+
+void sync15_passwords_update_password(Sync15PasswordEngineHandle handle,
+                                      char const *_Nonnull id,
+                                      char const *_Nonnull new_password,
+                                      Sync15PasswordsError *_Nonnull error);
+
+uint64_t sync15_passwords_delete_between(Sync15PasswordEngineHandle handle,
+                                         int64_t start_ms,
+                                         int64_t end_ms,
+                                         Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_stats(Sync15PasswordEngineHandle handle,
+                                       Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_quarantined_database_path(Sync15PasswordEngineHandle handle,
+                                                           Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_apply_batch(Sync15PasswordEngineHandle handle,
+                                             char const *_Nonnull ops_json,
+                                             Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_dedupe_and_merge(Sync15PasswordEngineHandle handle,
+                                                  Sync15PasswordsError *_Nonnull error);
+
+void sync15_passwords_set_merge_policy(Sync15PasswordEngineHandle handle,
+                                       int32_t policy,
+                                       Sync15PasswordsError *_Nonnull error);
+
+void sync15_passwords_set_local_only(Sync15PasswordEngineHandle handle,
+                                     char const *_Nonnull id,
+                                     uint8_t local_only,
+                                     Sync15PasswordsError *_Nonnull error);
+
+uint8_t sync15_passwords_is_local_only(Sync15PasswordEngineHandle handle,
+                                       char const *_Nonnull id,
+                                       Sync15PasswordsError *_Nonnull error);
+
+void sync15_passwords_set_history_limit(Sync15PasswordEngineHandle handle,
+                                        uint32_t limit,
+                                        Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_get_history(Sync15PasswordEngineHandle handle,
+                                             char const *_Nonnull id,
+                                             Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_password_health_report(Sync15PasswordEngineHandle handle,
+                                                        Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_upgrade_http_origins(Sync15PasswordEngineHandle handle,
+                                                      Sync15PasswordsError *_Nonnull error);
+
+void sync15_passwords_set_access_logging(Sync15PasswordEngineHandle handle,
+                                         char const *_Nullable caller,
+                                         Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_get_access_log(Sync15PasswordEngineHandle handle,
+                                                int64_t since_ms,
+                                                char const *_Nullable caller,
+                                                Sync15PasswordsError *_Nonnull error);
+
+char *_Nullable sync15_passwords_find_suspicious_access(Sync15PasswordEngineHandle handle,
+                                                        uint64_t window_secs,
+                                                        uint64_t max_logins,
+                                                        Sync15PasswordsError *_Nonnull error);
@@ -0,0 +1,305 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Checking whether stored passwords have appeared in a known breach.
+//!
+//! This uses a k-anonymity "range" query, like Have I Been Pwned's Pwned
+//! Passwords API: only the first five hex digits of the SHA-1 of a password
+//! are sent, and the server returns the rest of every breached hash starting
+//! with them. Whether or not our hash is in there is decided locally, so
+//! neither the password nor its full hash ever leave the device.
+//!
+//! Responses are cached, and the number of requests made is limited, so
+//! checking a large store leaves it partially checked rather than hammering
+//! the server; records which couldn't be checked are treated as not breached
+//! until a later check.
+
+use crate::engine::PasswordEngine;
+use crate::error::*;
+use crate::login::Login;
+use rc_crypto::digest;
+use sql_support::retry_if_busy;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// The number of hex digits of the hash sent to the server.
+pub const HASH_PREFIX_LEN: usize = 5;
+
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_MAX_REQUESTS: usize = 100;
+const DEFAULT_RATE_LIMIT_PERIOD: Duration = Duration::from_secs(60);
+
+/// Fetches the breached hashes starting with a prefix. `ViaductRangeFetcher`
+/// uses the network; tests and embedders with their own networking can
+/// provide another implementation.
+pub trait BreachRangeFetcher: Send {
+    /// Returns the response for `prefix` (`HASH_PREFIX_LEN` uppercase hex
+    /// digits): a line of the form `SUFFIX:COUNT` for each breached hash.
+    fn fetch_range(&self, prefix: &str) -> Result<String>;
+}
+
+/// Fetches ranges using viaduct, from `{endpoint}{prefix}`. For the Pwned
+/// Passwords API, the endpoint is `https://api.pwnedpasswords.com/range/`.
+pub struct ViaductRangeFetcher {
+    endpoint: Url,
+}
+
+impl ViaductRangeFetcher {
+    pub fn new(endpoint: &str) -> Result<Self> {
+        Ok(Self {
+            endpoint: Url::parse(endpoint)?,
+        })
+    }
+}
+
+impl BreachRangeFetcher for ViaductRangeFetcher {
+    fn fetch_range(&self, prefix: &str) -> Result<String> {
+        let url = self.endpoint.join(prefix)?;
+        // Padding makes every response a similar size, so the prefix can't
+        // be guessed from the length of the (encrypted) response.
+        let response = viaduct::Request::get(url)
+            .header("Add-Padding", "true")?
+            .send()?;
+        if !response.is_success() {
+            throw!(ErrorKind::BreachCheckFailed(format!(
+                "unexpected status {}",
+                response.status
+            )));
+        }
+        Ok(response.text().into_owned())
+    }
+}
+
+struct CachedRange {
+    fetched_at: Instant,
+    suffixes: HashSet<String>,
+}
+
+pub struct BreachChecker {
+    fetcher: Box<dyn BreachRangeFetcher>,
+    cache_ttl: Duration,
+    max_requests: usize,
+    rate_limit_period: Duration,
+    cache: RefCell<HashMap<String, CachedRange>>,
+    // When each request in the current rate limit period was made.
+    requests: RefCell<VecDeque<Instant>>,
+}
+
+impl BreachChecker {
+    pub fn new(fetcher: Box<dyn BreachRangeFetcher>) -> Self {
+        Self {
+            fetcher,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            max_requests: DEFAULT_MAX_REQUESTS,
+            rate_limit_period: DEFAULT_RATE_LIMIT_PERIOD,
+            cache: RefCell::default(),
+            requests: RefCell::default(),
+        }
+    }
+
+    /// How long a response is used for before it's fetched again. Defaults
+    /// to a day.
+    pub fn set_cache_ttl(&mut self, ttl: Duration) {
+        self.cache_ttl = ttl;
+    }
+
+    /// Allows at most `max_requests` to be made in any `period`. Defaults
+    /// to 100 a minute.
+    pub fn set_rate_limit(&mut self, max_requests: usize, period: Duration) {
+        self.max_requests = max_requests;
+        self.rate_limit_period = period;
+    }
+
+    /// Whether `password` has appeared in a breach. Fails with
+    /// `BreachCheckRateLimited` if it needs a request, but too many have
+    /// been made recently.
+    pub fn is_breached(&self, password: &str) -> Result<bool> {
+        let hash = sha1_hex(password)?;
+        let (prefix, suffix) = hash.split_at(HASH_PREFIX_LEN);
+        let now = Instant::now();
+        if let Some(cached) = self.cache.borrow().get(prefix) {
+            if now.duration_since(cached.fetched_at) < self.cache_ttl {
+                return Ok(cached.suffixes.contains(suffix));
+            }
+        }
+        self.start_request(now)?;
+        let suffixes = parse_range(&self.fetcher.fetch_range(prefix)?);
+        let breached = suffixes.contains(suffix);
+        self.cache.borrow_mut().insert(
+            prefix.to_owned(),
+            CachedRange {
+                fetched_at: now,
+                suffixes,
+            },
+        );
+        Ok(breached)
+    }
+
+    /// Returns the logins in `logins` whose passwords have appeared in a
+    /// breach. Logins which couldn't be checked because of the rate limit
+    /// are left out.
+    pub fn get_breached_records(&self, logins: Vec<Login>) -> Result<Vec<Login>> {
+        let mut breached = Vec::new();
+        let mut unchecked = 0;
+        for login in logins {
+            match self.is_breached(&login.password) {
+                Ok(true) => breached.push(login),
+                Ok(false) => {}
+                Err(e) => match e.kind() {
+                    ErrorKind::BreachCheckRateLimited => unchecked += 1,
+                    _ => return Err(e),
+                },
+            }
+        }
+        if unchecked > 0 {
+            log::info!(
+                "Rate limited; {} logins weren't checked for breaches",
+                unchecked
+            );
+        }
+        Ok(breached)
+    }
+
+    fn start_request(&self, now: Instant) -> Result<()> {
+        let mut requests = self.requests.borrow_mut();
+        while let Some(&oldest) = requests.front() {
+            if now.duration_since(oldest) < self.rate_limit_period {
+                break;
+            }
+            requests.pop_front();
+        }
+        if requests.len() >= self.max_requests {
+            throw!(ErrorKind::BreachCheckRateLimited);
+        }
+        requests.push_back(now);
+        Ok(())
+    }
+}
+
+fn sha1_hex(password: &str) -> Result<String> {
+    rc_crypto::ensure_initialized();
+    let hash = digest::digest(&digest::SHA1, password.as_bytes())
+        .map_err(|e| ErrorKind::BreachCheckFailed(e.to_string()))?;
+    Ok(hash.as_ref().iter().map(|b| format!("{:02X}", b)).collect())
+}
+
+// Padding entries have a count of 0, and aren't real breaches.
+fn parse_range(body: &str) -> HashSet<String> {
+    body.lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, ':');
+            let suffix = parts.next()?;
+            match parts.next()?.trim().parse::<u64>() {
+                Ok(count) if count > 0 => Some(suffix.to_ascii_uppercase()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+impl PasswordEngine {
+    /// Enable checking passwords against known breaches with `checker`, or
+    /// disable it if `checker` is `None`. Checking is disabled by default.
+    pub fn set_breach_checker(&mut self, checker: Option<BreachChecker>) {
+        self.breach_checker = checker;
+    }
+
+    /// Returns the logins whose passwords have appeared in a breach. See
+    /// `BreachChecker::get_breached_records`.
+    pub fn get_breached_records(&self) -> Result<Vec<Login>> {
+        let checker = self.breach_checker()?;
+        checker.get_breached_records(self.list_unlogged()?)
+    }
+
+    pub fn is_potentially_breached(&self, id: &str) -> Result<bool> {
+        let checker = self.breach_checker()?;
+        match retry_if_busy(|| self.reader().get_by_id(id))? {
+            Some(login) => checker.is_breached(&login.password),
+            None => throw!(ErrorKind::NoSuchRecord(id.to_owned())),
+        }
+    }
+
+    fn breach_checker(&self) -> Result<&BreachChecker> {
+        match &self.breach_checker {
+            Some(checker) => Ok(checker),
+            None => throw!(ErrorKind::BreachCheckFailed(
+                "breach checking isn't enabled".into()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // SHA-1("password") is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8.
+    const BREACHED: &str = "password";
+
+    #[derive(Default)]
+    struct TestFetcher {
+        prefixes: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl BreachRangeFetcher for TestFetcher {
+        fn fetch_range(&self, prefix: &str) -> Result<String> {
+            self.prefixes.lock().unwrap().push(prefix.to_owned());
+            Ok(match prefix {
+                "5BAA6" => "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+                            1E4C9B93F3F0682250B6CF8331B7EE68FD8:3861493\r\n\
+                            01330C689E5D64F660D6947A93AD634EF8F:0"
+                    .into(),
+                _ => "".into(),
+            })
+        }
+    }
+
+    fn login(password: &str) -> Login {
+        Login {
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            username: "user".into(),
+            password: password.into(),
+            ..Login::default()
+        }
+    }
+
+    #[test]
+    fn test_breach_checker() {
+        let fetcher = TestFetcher::default();
+        let prefixes = fetcher.prefixes.clone();
+        let mut checker = BreachChecker::new(Box::new(fetcher));
+        assert!(checker.is_breached(BREACHED).unwrap());
+        assert!(!checker.is_breached("correct horse battery staple").unwrap());
+        // The response for a prefix is cached.
+        assert!(checker.is_breached(BREACHED).unwrap());
+        assert_eq!(prefixes.lock().unwrap().len(), 2);
+        assert!(prefixes
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|p| p.len() == HASH_PREFIX_LEN));
+
+        checker.set_cache_ttl(Duration::from_secs(0));
+        checker.set_rate_limit(3, Duration::from_secs(60 * 60));
+        assert!(checker.is_breached(BREACHED).unwrap());
+        let err = checker.is_breached(BREACHED).unwrap_err();
+        assert_eq!(err.label(), "BreachCheckRateLimited");
+        assert_eq!(prefixes.lock().unwrap().len(), 3);
+
+        let breached = checker
+            .get_breached_records(vec![login(BREACHED), login("hunter2")])
+            .unwrap();
+        assert!(breached.is_empty());
+        checker.set_rate_limit(10, Duration::from_secs(60 * 60));
+        let breached = checker
+            .get_breached_records(vec![login(BREACHED), login("hunter2")])
+            .unwrap();
+        assert_eq!(breached.len(), 1);
+        assert_eq!(breached[0].password, BREACHED);
+    }
+}
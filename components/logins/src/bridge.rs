@@ -0,0 +1,264 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Lets Desktop's Sync implementation drive the logins engine, through the
+//! `BridgedEngine` trait, instead of `sync_multiple`.
+//!
+//! Incoming records are staged in `loginsStaging` and applied in chunks,
+//! exactly as they are when syncing with `sync_multiple`. Outgoing records
+//! are read from `loginsL` when they're needed, so nothing needs to be
+//! staged for them.
+
+use crate::db::LoginDb;
+use crate::error::*;
+use crate::observer::{LoginChangeEvent, Observers};
+use crate::schema;
+use crate::util;
+use std::time::SystemTime;
+use sync15::{telemetry, ServerTimestamp, StoreSyncAssociation};
+use sync15_traits::{self, ApplyResults, IncomingEnvelope, OutgoingEnvelope};
+use sync_guid::Guid;
+
+/// A bridged engine for the logins store. See `PasswordEngine::bridged_engine`.
+pub struct BridgedEngine<'a> {
+    db: &'a LoginDb,
+    observers: Option<&'a Observers>,
+}
+
+impl<'a> BridgedEngine<'a> {
+    /// Creates a bridged engine for syncing.
+    pub fn new(db: &'a LoginDb) -> Self {
+        BridgedEngine {
+            db,
+            observers: None,
+        }
+    }
+
+    /// Like `new`, but the engine tells `observers` about the changes it
+    /// makes, as `PasswordEngine::sync` does.
+    pub(crate) fn with_observers(db: &'a LoginDb, observers: &'a Observers) -> Self {
+        BridgedEngine {
+            db,
+            observers: Some(observers),
+        }
+    }
+
+    fn notify(&self, event: LoginChangeEvent) {
+        if let Some(observers) = self.observers {
+            observers.notify(event);
+        }
+    }
+
+    // Resets the sync state and, unlike `LoginDb::reset`, only stores the
+    // collection sync ID, as Desktop manages the global one.
+    fn reset_with_sync_id(&self, sync_id: Option<&str>) -> Result<()> {
+        self.db.check_writable()?;
+        let tx = self.db.unchecked_transaction()?;
+        self.db
+            .reset_in_transaction(&StoreSyncAssociation::Disconnected)?;
+        if let Some(sync_id) = sync_id {
+            self.db
+                .put_meta(schema::COLLECTION_SYNCID_META_KEY, &sync_id)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl<'a> sync15_traits::BridgedEngine for BridgedEngine<'a> {
+    type Error = Error;
+
+    fn last_sync(&self) -> Result<i64> {
+        Ok(self.db.get_last_sync()?.unwrap_or_default().as_millis())
+    }
+
+    fn set_last_sync(&self, last_sync_millis: i64) -> Result<()> {
+        self.db.check_writable()?;
+        self.db
+            .set_last_sync(ServerTimestamp::from_millis(last_sync_millis))
+    }
+
+    fn sync_id(&self) -> Result<Option<String>> {
+        self.db.get_meta(schema::COLLECTION_SYNCID_META_KEY)
+    }
+
+    fn reset_sync_id(&self) -> Result<String> {
+        let new_id = Guid::random().into_string();
+        self.reset_with_sync_id(Some(&new_id))?;
+        Ok(new_id)
+    }
+
+    fn ensure_current_sync_id(&self, new_sync_id: &str) -> Result<String> {
+        let current: Option<String> = self.db.get_meta(schema::COLLECTION_SYNCID_META_KEY)?;
+        if current.as_deref() != Some(new_sync_id) {
+            self.reset_with_sync_id(Some(new_sync_id))?;
+        }
+        Ok(new_sync_id.to_owned())
+    }
+
+    fn sync_started(&self) -> Result<()> {
+        // Anything left in `loginsStaging` by a sync which didn't finish is
+        // applied along with this sync's records.
+        Ok(())
+    }
+
+    fn store_incoming(&self, incoming_envelopes: &[IncomingEnvelope]) -> Result<()> {
+        self.db.check_writable()?;
+        let scope = self.db.begin_interrupt_scope();
+        let mut records = Vec::with_capacity(incoming_envelopes.len());
+        for envelope in incoming_envelopes {
+            scope.err_if_interrupted()?;
+            records.push((envelope.payload()?, envelope.modified));
+        }
+        self.db.stage_incoming(&records)
+    }
+
+    fn apply(&self) -> Result<ApplyResults> {
+        self.db.check_writable()?;
+        let scope = self.db.begin_interrupt_scope();
+        // Desktop doesn't tell us the server time, so use ours to decide
+        // how old the incoming changes are.
+        let now = ServerTimestamp::from_millis(util::system_time_ms_i64(SystemTime::now()));
+        let mut telem = telemetry::Engine::new(self.db.sync_config().collection_name.as_str());
+        let mut applied = Vec::new();
+        let result = self.db.apply_staged(now, &mut telem, &scope, &mut applied);
+        // The chunks which were committed are reported even if a later one
+        // failed.
+        if !applied.is_empty() {
+            self.notify(LoginChangeEvent::SyncApplied(applied));
+        }
+        let num_reconciled = result?;
+        let outgoing = self.db.fetch_outgoing(now, &scope)?;
+        Ok(ApplyResults::new(
+            outgoing
+                .changes
+                .into_iter()
+                .map(OutgoingEnvelope::from)
+                .collect(),
+            num_reconciled as usize,
+        ))
+    }
+
+    fn set_uploaded(&self, server_modified_millis: i64, ids: &[Guid]) -> Result<()> {
+        let scope = self.db.begin_interrupt_scope();
+        self.db.mark_as_synchronized(
+            &ids.iter().map(Guid::as_str).collect::<Vec<_>>(),
+            ServerTimestamp::from_millis(server_modified_millis),
+            &scope,
+        )
+    }
+
+    fn sync_finished(&self) -> Result<()> {
+        self.db.prune_tombstones(self.db.tombstone_retention())?;
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<()> {
+        self.reset_with_sync_id(None)
+    }
+
+    fn wipe(&self) -> Result<()> {
+        self.db.wipe_local()?;
+        self.notify(LoginChangeEvent::Wiped);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::login::Login;
+    use sync15_traits::BridgedEngine as _;
+
+    fn envelope(id: &str, modified_secs: f64, cleartext: serde_json::Value) -> IncomingEnvelope {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "modified": modified_secs,
+            "cleartext": cleartext.to_string(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sync_ids() -> Result<()> {
+        let db = LoginDb::open_in_memory(Some("testing"))?;
+        let engine = BridgedEngine::new(&db);
+        engine.set_last_sync(1000)?;
+        assert_eq!(engine.sync_id()?, None);
+
+        // A new sync ID resets.
+        assert_eq!(engine.ensure_current_sync_id("sync-id")?, "sync-id");
+        assert_eq!(engine.sync_id()?, Some("sync-id".to_string()));
+        assert_eq!(engine.last_sync()?, 0);
+
+        // The same one doesn't.
+        engine.set_last_sync(1000)?;
+        engine.ensure_current_sync_id("sync-id")?;
+        assert_eq!(engine.last_sync()?, 1000);
+
+        let new_id = engine.reset_sync_id()?;
+        assert_ne!(new_id, "sync-id");
+        assert_eq!(engine.sync_id()?, Some(new_id));
+        assert_eq!(engine.last_sync()?, 0);
+
+        engine.reset()?;
+        assert_eq!(engine.sync_id()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync() -> Result<()> {
+        let db = LoginDb::open_in_memory(Some("testing"))?;
+        let local = db.add(Login {
+            hostname: "https://local.example.com".into(),
+            form_submit_url: Some("https://local.example.com".into()),
+            username: "local".into(),
+            password: "password".into(),
+            ..Login::default()
+        })?;
+        let engine = BridgedEngine::new(&db);
+        engine.ensure_current_sync_id("sync-id")?;
+        engine.sync_started()?;
+        engine.store_incoming(&[envelope(
+            "remote_00001",
+            1.0,
+            serde_json::json!({
+                "id": "remote_00001",
+                "hostname": "https://remote.example.com",
+                "formSubmitURL": "https://remote.example.com",
+                "username": "remote",
+                "password": "password",
+            }),
+        )])?;
+        // The ID in the envelope must match the record's.
+        assert!(engine
+            .store_incoming(&[envelope(
+                "remote_00002",
+                1.0,
+                serde_json::json!({ "id": "remote_00003" }),
+            )])
+            .is_err());
+
+        let results = engine.apply()?;
+        assert_eq!(results.envelopes.len(), 1);
+        assert_eq!(results.num_reconciled, Some(0));
+        let remote = db.get_by_id("remote_00001")?.unwrap();
+        assert_eq!(remote.username, "remote");
+
+        engine.set_uploaded(2000, &[local.guid])?;
+        engine.sync_finished()?;
+        engine.set_last_sync(2000)?;
+
+        // Everything's uploaded, so there's nothing to do next time.
+        engine.sync_started()?;
+        assert!(engine.apply()?.envelopes.is_empty());
+        engine.sync_finished()?;
+        assert_eq!(db.get_all(&db.begin_interrupt_scope())?.len(), 2);
+
+        // Wiping removes everything locally.
+        engine.wipe()?;
+        assert!(db.get_all(&db.begin_interrupt_scope())?.is_empty());
+        Ok(())
+    }
+}
@@ -2,25 +2,34 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::encryption::{EncryptorDecryptor, KeyStatus};
 use crate::error::*;
-use crate::login::{LocalLogin, Login, MirrorLogin, SyncLoginData, SyncStatus};
+use crate::login::{LocalLogin, Login, MergePolicy, MirrorLogin, SyncLoginData, SyncStatus};
+use crate::origin::{normalize_origin, origins_match, OriginMode};
+use crate::psl;
+use crate::query::LoginQuery;
 use crate::schema;
+use crate::sync_config::SyncConfig;
 use crate::update_plan::UpdatePlan;
 use crate::util;
+use interrupt_support::Interruptable;
 use lazy_static::lazy_static;
 use rusqlite::{
+    backup::Backup,
     named_params,
     types::{FromSql, ToSql},
     Connection, OpenFlags, NO_PARAMS,
 };
 use serde_derive::*;
+use sql_support::open_database::{self, QuarantinedDatabase};
 use sql_support::{self, ConnExt};
 use sql_support::{SqlInterruptHandle, SqlInterruptScope};
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Deref;
 use std::path::Path;
 use std::result;
-use std::sync::{atomic::AtomicUsize, Arc};
+use std::sync::{atomic::AtomicUsize, Arc, Weak};
 use std::time::{Duration, Instant, SystemTime};
 use sync15::{
     extract_v1_state, telemetry, CollSyncIds, CollectionRequest, IncomingChangeset,
@@ -49,9 +58,244 @@ pub struct MigrationMetrics {
     errors: Vec<String>,
 }
 
+/// The result of `LoginDb::run_maintenance`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct MaintenanceReport {
+    pub tombstones_pruned: u64,
+    /// The problems reported by `PRAGMA integrity_check`. Empty if the
+    /// database is intact.
+    pub integrity_errors: Vec<String>,
+    pub total_duration: u128,
+}
+
+// Encrypted with the `EncryptorDecryptor` whenever the fields are (re)keyed,
+// so the key can be checked without relying on there being any logins.
+const FIELDS_CANARY: &str = "a-s logins canary";
+
+/// How long local tombstones are kept by default. See
+/// `LoginDb::set_tombstone_retention`.
+pub const DEFAULT_TOMBSTONE_RETENTION: Duration = Duration::from_secs(180 * 24 * 60 * 60);
+
+/// Summary statistics about the store, returned by `LoginDb::stats`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct LoginStats {
+    pub num_logins: u64,
+    pub num_tombstones: u64,
+    /// Logins which haven't been used since they were saved.
+    pub num_never_used: u64,
+    /// The number of logins for each base domain (eTLD+1), so that
+    /// "www.example.com" and "accounts.example.com" are counted together.
+    /// Hosts without a base domain, like IP addresses, are counted as they
+    /// are.
+    pub logins_per_base_domain: BTreeMap<String, u64>,
+    /// The size of the database file, in bytes.
+    pub db_size: u64,
+}
+
+/// Where a change recorded in the history came from.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSource {
+    Local = 1,
+    Sync = 2,
+}
+
+/// A change to a single field of a login, as returned by
+/// `LoginDb::get_history`. The `field` is one of the fields of `Login` (in
+/// `camelCase`, as in JSON), or `created` or `deleted`. Changes to the usage
+/// counters aren't recorded, and values are never recorded.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HistoryEntry {
+    pub guid: String,
+    pub field: String,
+    pub timestamp: i64,
+    pub source: ChangeSource,
+}
+
+/// A read of a login, as returned by `LoginDb::get_access_log`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct AccessLogEntry {
+    pub guid: String,
+    pub caller: String,
+    pub timestamp: i64,
+}
+
+/// A caller which read more logins than expected, as returned by
+/// `LoginDb::find_suspicious_access`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SuspiciousAccess {
+    pub caller: String,
+    /// The number of different logins read.
+    pub num_logins: u64,
+    pub first_access: i64,
+    pub last_access: i64,
+}
+
+/// Returned by `LoginDb::validate`. Each list holds the GUIDs of the affected
+/// logins.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// Mirror records marked as overridden by a local record that doesn't
+    /// exist, which hides any future changes from the server.
+    pub orphaned_mirror: Vec<String>,
+    /// GUIDs used by more than one of the server records.
+    pub duplicate_guids: Vec<String>,
+    /// Live server records which aren't in the mirror and have no local
+    /// record.
+    pub client_missing: Vec<String>,
+    /// Mirror records which aren't on the server.
+    pub server_missing: Vec<String>,
+    /// Mirror records which differ from the server record.
+    pub differences: Vec<String>,
+    /// Whether the problems which can be repaired were.
+    pub repaired: bool,
+}
+
+impl ValidationReport {
+    pub fn is_empty(&self) -> bool {
+        self.orphaned_mirror.is_empty()
+            && self.duplicate_guids.is_empty()
+            && self.client_missing.is_empty()
+            && self.server_missing.is_empty()
+            && self.differences.is_empty()
+    }
+}
+
+/// Returned by `LoginDb::dedupe_and_merge`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct MergeReport {
+    /// The number of logins which were deleted after being merged into
+    /// another.
+    pub num_deleted: u64,
+    /// Maps the GUID of each login that was kept to the GUIDs of the logins
+    /// merged into it.
+    pub merged: BTreeMap<String, Vec<String>>,
+}
+
+/// Returned by `LoginDb::upgrade_http_origins`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct OriginUpgradeReport {
+    /// The GUIDs of the logins whose origins were changed to `https`.
+    pub upgraded: Vec<String>,
+    /// Maps the GUID of each `http` login which was merged into an existing
+    /// `https` one to the GUID of that login.
+    pub merged: BTreeMap<String, String>,
+    /// The GUIDs of `http` logins which were left alone, because there's an
+    /// `https` login for the same user with a different password.
+    pub conflicting: Vec<String>,
+}
+
+/// A single change to apply with `LoginDb::apply_batch`. In JSON, these are
+/// objects with an `op` of `add`, `update` or `delete`, and either a `login`
+/// or an `id`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(tag = "op", rename_all = "lowercase")]
+#[allow(clippy::large_enum_variant)]
+pub enum LoginOperation {
+    Add { login: Login },
+    Update { login: Login },
+    Delete { id: String },
+}
+
+/// How long after `LoginDb::delete_with_undo` the deletion can be undone.
+pub const UNDO_DELETE_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Returned by `LoginDb::delete_with_undo`, and holds what's needed to
+/// restore the login with `LoginDb::undo_delete`.
+#[derive(Debug, Clone)]
+pub struct DeletedLogin {
+    pub login: Login,
+    deleted_at: i64,
+    // The `sync_status` and `local_modified` of the local record, if any.
+    local_state: Option<(u8, Option<i64>)>,
+    // The `is_overridden` flag of the mirror record, if any.
+    mirror_overridden: Option<bool>,
+}
+
+impl DeletedLogin {
+    /// Whether the grace period for undoing the deletion has passed.
+    pub fn is_expired(&self) -> bool {
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        now_ms - self.deleted_at > UNDO_DELETE_GRACE_PERIOD.as_millis() as i64
+    }
+}
+
+// Problems noticed while reconciling incoming records, which are reported in
+// the `validation` section of the sync ping.
+#[derive(Debug, Default)]
+struct SyncProblems {
+    // Incoming records which fail `Login::check_valid`.
+    invalid_incoming: usize,
+    // Incoming records with a new GUID which duplicate a local login.
+    content_dupes: usize,
+    // Incoming records which conflict with a local login that was never
+    // synced, so there's no common ancestor to merge against.
+    parentless_conflicts: usize,
+}
+
+impl SyncProblems {
+    fn into_validation(self) -> telemetry::Validation {
+        let mut validation = telemetry::Validation::with_version(1);
+        validation
+            .problem("invalidIncoming", self.invalid_incoming)
+            .problem("contentDupes", self.content_dupes)
+            .problem("parentlessConflicts", self.parentless_conflicts);
+        validation
+    }
+}
+
+// How long each phase of applying incoming records took, summed over every
+// chunk, for logging.
+#[derive(Debug, Default)]
+struct IncomingTimings {
+    chunks: usize,
+    fetch: Duration,
+    reconcile: Duration,
+    apply: Duration,
+}
+
+// The number of incoming records applied in each transaction.
+const INCOMING_CHUNK_SIZE: usize = 1000;
+
+// How much of the database `backup_to` and `restore_from` copy at a time, and
+// how long they wait in between, so other connections can use it meanwhile.
+const BACKUP_PAGES_PER_STEP: i32 = 128;
+const BACKUP_STEP_PAUSE: Duration = Duration::from_millis(10);
+
+// Repeated reads of a login by the same caller within this long are only
+// logged once, so that callers which read often don't fill the log.
+const ACCESS_LOG_INTERVAL_MS: i64 = 60 * 1000;
+
+/// How long entries in the access log are kept for.
+pub const ACCESS_LOG_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
 pub struct LoginDb {
     pub db: Connection,
     interrupt_counter: Arc<AtomicUsize>,
+    // An `Arc` so that it can be shared with a reader connection.
+    encdec: Option<Arc<dyn EncryptorDecryptor>>,
+    // Whether the `username` and `password` columns hold ciphertext.
+    fields_encrypted: bool,
+    tombstone_retention: Duration,
+    merge_policy: MergePolicy,
+    // The maximum number of history entries to keep, or `None` if history
+    // isn't recorded.
+    history_limit: Option<usize>,
+    // Set by `open_readonly`, in which case every mutating method fails.
+    read_only: bool,
+    sync_config: SyncConfig,
+    // The tag reads are logged with, or `None` if they aren't logged.
+    access_log_caller: Option<String>,
+    // When each login was last logged as read, in milliseconds.
+    last_logged_access: RefCell<HashMap<String, i64>>,
+    // Set if the database was corrupt when it was opened, and was recreated.
+    quarantined_db: Option<QuarantinedDatabase>,
+    // False if this is a read-only connection to a database from before
+    // `rev_host` was added, which `get_by_base_domain` has to scan instead.
+    has_rev_host: bool,
+    // Registered with `interrupt_support::register_interrupt`, which only
+    // keeps a weak reference, so this keeps it alive as long as we are.
+    shutdown_handle: Arc<SqlInterruptHandle>,
 }
 
 impl LoginDb {
@@ -60,47 +304,79 @@ impl LoginDb {
         encryption_key: Option<&str>,
         salt: Option<&str>,
     ) -> Result<Self> {
+        let initializer = schema::LoginsConnectionInitializer {
+            encryption_key,
+            salt,
+        };
+        Self::from_connection(open_database::init_connection(db, &initializer)?, false)
+    }
+
+    fn open_with_flags(
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+        salt: Option<&str>,
+        flags: OpenFlags,
+    ) -> Result<Self> {
+        let initializer = schema::LoginsConnectionInitializer {
+            encryption_key,
+            salt,
+        };
+        let opened = open_database::open_database_with_recovery(path, flags, &initializer)?;
+        let mut logins = Self::from_connection(
+            opened.conn,
+            flags.contains(OpenFlags::SQLITE_OPEN_READ_ONLY),
+        )?;
+        logins.quarantined_db = opened.quarantined;
+        Ok(logins)
+    }
+
+    // Sets up a connection whose schema `open_database` has already created
+    // or migrated (or, if it's read-only, left alone).
+    fn from_connection(db: Connection, read_only: bool) -> Result<Self> {
         #[cfg(test)]
         {
             util::init_test_logging();
         }
 
-        if let Some(key) = encryption_key {
-            db.set_pragma("key", key)?
-                .set_pragma("secure_delete", true)?;
-
-            sqlcipher_3_compat(&db)?;
-
-            if let Some(s) = salt {
-                // If a salt is also provided, this means the consumer does not want the salt stored
-                // in the database header. Currently only iOS uses this.
-                db.set_pragma("cipher_plaintext_header_size", 32)?;
-                db.set_pragma("cipher_salt", format!("x'{}'", s))?;
-            }
-        }
-
-        // `temp_store = 2` is required on Android to force the DB to keep temp
-        // files in memory, since on Android there's no tmp partition. See
-        // https://github.com/mozilla/mentat/issues/505. Ideally we'd only
-        // do this on Android, or allow caller to configure it.
-        db.set_pragma("temp_store", 2)?;
+        db.set_prepared_statement_cache_capacity(128);
+        let interrupt_counter = Arc::new(AtomicUsize::new(0));
+        let shutdown_handle = register_shutdown_handle(&db, &interrupt_counter);
 
         let mut logins = Self {
             db,
-            interrupt_counter: Arc::new(AtomicUsize::new(0)),
+            interrupt_counter,
+            encdec: None,
+            fields_encrypted: false,
+            tombstone_retention: DEFAULT_TOMBSTONE_RETENTION,
+            merge_policy: MergePolicy::default(),
+            history_limit: None,
+            read_only,
+            sync_config: SyncConfig::default(),
+            access_log_caller: None,
+            last_logged_access: RefCell::default(),
+            quarantined_db: None,
+            has_rev_host: true,
+            shutdown_handle,
         };
-        let tx = logins.db.transaction()?;
-        schema::init(&tx)?;
-        tx.commit()?;
+        if read_only {
+            // We can't run migrations, so the schema must already be one we
+            // can read.
+            let user_version = logins.db.query_one::<i64>("PRAGMA user_version")?;
+            if user_version < schema::MIN_READ_ONLY_VERSION {
+                throw!(ErrorKind::UnsupportedDatabaseVersion(user_version));
+            }
+            logins.has_rev_host = user_version >= schema::REV_HOST_VERSION;
+        } else {
+            schema::prepare_writer(&logins.db)?;
+        }
+        logins.fields_encrypted = logins
+            .get_meta::<bool>(schema::FIELDS_ENCRYPTED_META_KEY)?
+            .unwrap_or(false);
         Ok(logins)
     }
 
     pub fn open(path: impl AsRef<Path>, encryption_key: Option<&str>) -> Result<Self> {
-        Ok(Self::with_connection(
-            Connection::open(path)?,
-            encryption_key,
-            None,
-        )?)
+        Self::open_with_flags(path, encryption_key, None, OpenFlags::default())
     }
 
     pub fn open_with_salt(
@@ -109,19 +385,122 @@ impl LoginDb {
         salt: &str,
     ) -> Result<Self> {
         ensure_valid_salt(salt)?;
-        Ok(Self::with_connection(
-            Connection::open(path)?,
-            Some(encryption_key),
-            Some(salt),
-        )?)
+        Self::open_with_flags(path, Some(encryption_key), Some(salt), OpenFlags::default())
     }
 
     pub fn open_in_memory(encryption_key: Option<&str>) -> Result<Self> {
-        Ok(Self::with_connection(
-            Connection::open_in_memory()?,
+        let initializer = schema::LoginsConnectionInitializer {
             encryption_key,
-            None,
-        )?)
+            salt: None,
+        };
+        Self::from_connection(open_database::open_memory_database(&initializer)?, false)
+    }
+
+    /// Opens an existing database without ever writing to it, for diagnostic
+    /// tools and for embedders (such as the iOS share extension) which must
+    /// not modify the file. Schema migrations are skipped, so this fails if
+    /// the database was created by an old version of this component that
+    /// hasn't since opened it for writing. Mutating methods fail with
+    /// `ErrorKind::ReadOnly`.
+    pub fn open_readonly(
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+        salt: Option<&str>,
+    ) -> Result<Self> {
+        if let Some(s) = salt {
+            ensure_valid_salt(s)?;
+        }
+        Self::open_with_flags(path, encryption_key, salt, OpenFlags::SQLITE_OPEN_READ_ONLY)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns where the database was moved to if it was corrupt when it was
+    /// opened, and had to be recreated. A database which can't be decrypted
+    /// with the key it was opened with is never treated as corrupt.
+    pub fn quarantined_database(&self) -> Option<&QuarantinedDatabase> {
+        self.quarantined_db.as_ref()
+    }
+
+    /// Writes a consistent copy of the database to `path`, replacing whatever
+    /// database was there, using SQLite's online backup API. The copy is
+    /// encrypted with `encryption_key` and `salt`, which must be the ones
+    /// this database was opened with. Other connections can keep using the
+    /// database while the copy is made.
+    pub fn backup_to(
+        &self,
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+        salt: Option<&str>,
+    ) -> Result<()> {
+        if let Some(s) = salt {
+            ensure_valid_salt(s)?;
+        }
+        let mut dest = Connection::open(path)?;
+        schema::set_key_pragmas(&dest, encryption_key, salt)?;
+        let backup = Backup::new(&self.db, &mut dest)?;
+        backup.run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_STEP_PAUSE, None)?;
+        Ok(())
+    }
+
+    /// Replaces the contents of this database with a copy written by
+    /// `backup_to`, which is opened with `encryption_key` and `salt`. The
+    /// copy is migrated to the current schema if it was made by an older
+    /// version. Field encryption state comes from the copy, so an
+    /// `EncryptorDecryptor` may be needed afterwards.
+    pub fn restore_from(
+        &mut self,
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+        salt: Option<&str>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        if let Some(s) = salt {
+            ensure_valid_salt(s)?;
+        }
+        let src = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        schema::set_key_pragmas(&src, encryption_key, salt)?;
+        // Fails if the key is wrong, or the file isn't a database, before we
+        // overwrite anything.
+        src.query_one::<i64>("SELECT count(*) FROM sqlite_master")?;
+        {
+            let backup = Backup::new(&src, &mut self.db)?;
+            backup.run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_STEP_PAUSE, None)?;
+        }
+        open_database::migrate_schema(
+            &mut self.db,
+            &schema::LoginsConnectionInitializer {
+                encryption_key,
+                salt,
+            },
+        )?;
+        schema::prepare_writer(&self.db)?;
+        self.fields_encrypted = self
+            .get_meta::<bool>(schema::FIELDS_ENCRYPTED_META_KEY)?
+            .unwrap_or(false);
+        Ok(())
+    }
+
+    /// Makes `reader`, a read-only connection to the same database, decrypt
+    /// fields the same way this connection does, and be interrupted by this
+    /// connection's interrupt handles. This must be called again whenever the
+    /// field encryption of this connection changes.
+    pub(crate) fn share_state_with_reader(&self, reader: &mut LoginDb) {
+        debug_assert!(reader.read_only);
+        reader.interrupt_counter = self.interrupt_counter.clone();
+        reader.shutdown_handle = register_shutdown_handle(&reader.db, &self.interrupt_counter);
+        reader.encdec = self.encdec.clone();
+        reader.fields_encrypted = self.fields_encrypted;
+    }
+
+    #[inline]
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            throw!(ErrorKind::ReadOnly);
+        }
+        Ok(())
     }
 
     /// Opens an existing database and fetches the salt.
@@ -133,7 +512,7 @@ impl LoginDb {
         // Open the connection defensively without attempting to create a db if it doesn't exist.
         let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
         db.set_pragma("key", encryption_key)?;
-        sqlcipher_3_compat(&db)?;
+        schema::sqlcipher_3_compat(&db)?;
         let salt = db.query_one::<String>("PRAGMA cipher_salt")?;
         Ok(salt)
     }
@@ -147,7 +526,7 @@ impl LoginDb {
         // Open the connection defensively without attempting to create a db if it doesn't exist.
         let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
         db.set_pragma("key", encryption_key)?;
-        sqlcipher_3_compat(&db)?;
+        schema::sqlcipher_3_compat(&db)?;
         db.set_pragma("cipher_salt", format!("x'{}'", salt))?;
         // This tricks the `cipher_plaintext_header_size` command to work properly.
         let user_version = db.query_one::<i64>("PRAGMA user_version")?;
@@ -170,6 +549,7 @@ impl LoginDb {
     /// can be used to re-encrypt every page in the database with a new key.
     /// https://www.zetetic.net/sqlcipher/sqlcipher-api/#Changing_Key
     pub fn rekey_database(&self, new_encryption_key: &str) -> Result<()> {
+        self.check_writable()?;
         self.conn().set_pragma("rekey", new_encryption_key)?;
         Ok(())
     }
@@ -181,10 +561,302 @@ impl LoginDb {
         )
     }
 
+    /// Like `new_interrupt_handle`, but the handle also interrupts `reader`,
+    /// which must have been set up with `share_state_with_reader`.
+    pub(crate) fn new_interrupt_handle_with_reader(&self, reader: &LoginDb) -> SqlInterruptHandle {
+        debug_assert!(Arc::ptr_eq(
+            &self.interrupt_counter,
+            &reader.interrupt_counter
+        ));
+        self.new_interrupt_handle()
+            .with_connection(reader.db.get_interrupt_handle())
+    }
+
     #[inline]
     pub fn begin_interrupt_scope(&self) -> SqlInterruptScope {
         SqlInterruptScope::new(self.interrupt_counter.clone())
     }
+
+    /// Use `encdec` to encrypt the `username` and `password` fields of
+    /// logins. The first time this is called for a database, the existing
+    /// plaintext fields are encrypted in place. To change the key used by an
+    /// already encrypted database, use `rekey_fields`.
+    pub fn set_encryptor_decryptor(&mut self, encdec: Box<dyn EncryptorDecryptor>) -> Result<()> {
+        if !self.fields_encrypted {
+            return self.rekey_fields(encdec);
+        }
+        self.encdec = Some(encdec.into());
+        Ok(())
+    }
+
+    /// Re-encrypt the fields of every login with `new_encdec`, which is then
+    /// used for all future reads and writes. The existing fields are
+    /// decrypted with the current `EncryptorDecryptor`, so that must still be
+    /// able to decrypt them. This happens in a single transaction; if it fails
+    /// or is interrupted, the database and the current `EncryptorDecryptor`
+    /// are left as they were.
+    pub fn rekey_fields(&mut self, new_encdec: Box<dyn EncryptorDecryptor>) -> Result<()> {
+        self.check_writable()?;
+        let scope = self.begin_interrupt_scope();
+        let tx = self.unchecked_transaction()?;
+        for table in &["loginsL", "loginsM"] {
+            let rows = self.query_rows_and_then_named(
+                &format!("SELECT guid, username, password, notes FROM {}", table),
+                &[],
+                |row| -> Result<(String, Option<String>, String, Option<String>)> {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                },
+            )?;
+            let reencrypt = |value: &str| -> Result<String> {
+                let cleartext = self.decrypt_field(value)?;
+                if cleartext.is_empty() {
+                    return Ok(cleartext);
+                }
+                new_encdec
+                    .encrypt(&cleartext)
+                    .map_err(|e| ErrorKind::EncryptionFailed(e.to_string()).into())
+            };
+            for (guid, username, password, notes) in rows {
+                scope.err_if_interrupted()?;
+                self.execute_named(
+                    &format!(
+                        "UPDATE {} SET username = :username, password = :password,
+                                       notes = :notes
+                         WHERE guid = :guid",
+                        table
+                    ),
+                    named_params! {
+                        ":username": username.map(|u| reencrypt(&u)).transpose()?,
+                        ":password": reencrypt(&password)?,
+                        ":notes": notes.map(|n| reencrypt(&n)).transpose()?,
+                        ":guid": guid,
+                    },
+                )?;
+            }
+        }
+        self.put_meta(schema::FIELDS_ENCRYPTED_META_KEY, &true)?;
+        self.put_canary(&*new_encdec)?;
+        tx.commit()?;
+        self.encdec = Some(new_encdec.into());
+        self.fields_encrypted = true;
+        Ok(())
+    }
+
+    fn put_canary(&self, encdec: &dyn EncryptorDecryptor) -> Result<()> {
+        let canary = encdec
+            .encrypt(FIELDS_CANARY)
+            .map_err(|e| ErrorKind::EncryptionFailed(e.to_string()))?;
+        self.put_meta(schema::FIELDS_CANARY_META_KEY, &canary)
+    }
+
+    /// Checks whether `encdec` can decrypt the fields of this database,
+    /// without changing anything, so the embedder can tell a wrong key from
+    /// a corrupt database before attempting other operations.
+    ///
+    /// Databases whose fields were encrypted before the canary was added are
+    /// checked against their logins instead: the key is wrong if none of them
+    /// can be decrypted, and the database is corrupt if only some can.
+    pub fn check_encryption_key(&self, encdec: &dyn EncryptorDecryptor) -> Result<KeyStatus> {
+        if !self.fields_encrypted {
+            return Ok(KeyStatus::NotEncrypted);
+        }
+        let quick_check: String = self.query_one("PRAGMA quick_check")?;
+        if quick_check != "ok" {
+            log::error!("Logins quick check failed: {}", quick_check);
+            return Ok(KeyStatus::Corrupt);
+        }
+        if let Some(canary) = self.get_meta::<String>(schema::FIELDS_CANARY_META_KEY)? {
+            return Ok(match encdec.decrypt(&canary) {
+                Ok(cleartext) if cleartext == FIELDS_CANARY => KeyStatus::Valid,
+                Ok(_) => KeyStatus::Corrupt,
+                Err(_) => KeyStatus::WrongKey,
+            });
+        }
+        let passwords = self.query_rows_and_then_named(
+            "SELECT password FROM loginsL WHERE password <> ''
+             UNION ALL
+             SELECT password FROM loginsM WHERE password <> ''",
+            &[],
+            |row| row.get::<_, String>(0),
+        )?;
+        let num_decrypted = passwords
+            .iter()
+            .filter(|password| encdec.decrypt(password).is_ok())
+            .count();
+        Ok(if num_decrypted == passwords.len() {
+            KeyStatus::Valid
+        } else if num_decrypted == 0 {
+            KeyStatus::WrongKey
+        } else {
+            KeyStatus::Corrupt
+        })
+    }
+
+    /// The recovery path for when the key used to encrypt the fields is
+    /// unrecoverable: deletes every login and all sync state, as
+    /// `wipe_local` does, and uses `encdec` from now on. Any logins which
+    /// were synced are downloaded again by the next sync.
+    pub fn reset_encrypted_data(&mut self, encdec: Box<dyn EncryptorDecryptor>) -> Result<()> {
+        self.check_writable()?;
+        log::warn!("Resetting encrypted logins data");
+        let tx = self.unchecked_transaction()?;
+        self.wipe_local_in_transaction()?;
+        self.put_meta(schema::FIELDS_ENCRYPTED_META_KEY, &true)?;
+        self.put_canary(&*encdec)?;
+        tx.commit()?;
+        self.encdec = Some(encdec.into());
+        self.fields_encrypted = true;
+        Ok(())
+    }
+
+    /// Returns the GUIDs of any logins whose fields can't be decrypted, which
+    /// typically means the key used by the `EncryptorDecryptor` was lost.
+    pub fn find_undecryptable_records(&self) -> Result<Vec<Guid>> {
+        let mut guids = vec![];
+        for table in &["loginsL", "loginsM"] {
+            let rows = self.query_rows_and_then_named(
+                &format!("SELECT guid, username, password, notes FROM {}", table),
+                &[],
+                |row| -> Result<(Guid, Option<String>, String, Option<String>)> {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                },
+            )?;
+            for (guid, username, password, notes) in rows {
+                let decrypts = self.decrypt_field(&username.unwrap_or_default()).is_ok()
+                    && self.decrypt_field(&password).is_ok()
+                    && self.decrypt_field(&notes.unwrap_or_default()).is_ok();
+                if !decrypts && !guids.contains(&guid) {
+                    guids.push(guid);
+                }
+            }
+        }
+        Ok(guids)
+    }
+
+    /// Deletes the logins returned by `find_undecryptable_records`, and resets
+    /// the last sync time so that any of them which exist on the server are
+    /// downloaded again. Local changes to those logins are lost. Returns the
+    /// GUIDs of the deleted logins.
+    pub fn delete_undecryptable_records(&self) -> Result<Vec<Guid>> {
+        self.check_writable()?;
+        let guids = self.find_undecryptable_records()?;
+        if guids.is_empty() {
+            return Ok(guids);
+        }
+        log::warn!("Deleting {} undecryptable logins", guids.len());
+        let tx = self.unchecked_transaction()?;
+        sql_support::each_chunk(&guids, |chunk, _| -> Result<()> {
+            for table in &["loginsL", "loginsM"] {
+                self.execute(
+                    &format!(
+                        "DELETE FROM {} WHERE guid IN ({vars})",
+                        table,
+                        vars = sql_support::repeat_sql_vars(chunk.len())
+                    ),
+                    chunk,
+                )?;
+            }
+            Ok(())
+        })?;
+        self.set_last_sync(ServerTimestamp(0))?;
+        tx.commit()?;
+        Ok(guids)
+    }
+
+    pub(crate) fn encrypt_field(&self, cleartext: &str) -> Result<String> {
+        if cleartext.is_empty() || !self.fields_encrypted {
+            return Ok(cleartext.to_owned());
+        }
+        match &self.encdec {
+            Some(encdec) => encdec
+                .encrypt(cleartext)
+                .map_err(|e| ErrorKind::EncryptionFailed(e.to_string()).into()),
+            None => throw!(ErrorKind::EncryptionFailed(
+                "No EncryptorDecryptor was provided".into()
+            )),
+        }
+    }
+
+    pub(crate) fn encrypt_optional_field(&self, cleartext: Option<&str>) -> Result<Option<String>> {
+        cleartext.map(|c| self.encrypt_field(c)).transpose()
+    }
+
+    fn decrypt_field(&self, ciphertext: &str) -> Result<String> {
+        if ciphertext.is_empty() || !self.fields_encrypted {
+            return Ok(ciphertext.to_owned());
+        }
+        match &self.encdec {
+            Some(encdec) => encdec
+                .decrypt(ciphertext)
+                .map_err(|e| ErrorKind::DecryptionFailed(e.to_string()).into()),
+            None => throw!(ErrorKind::DecryptionFailed(
+                "No EncryptorDecryptor was provided".into()
+            )),
+        }
+    }
+
+    fn decrypt_login(&self, mut login: Login) -> Result<Login> {
+        login.username = self.decrypt_field(&login.username)?;
+        login.password = self.decrypt_field(&login.password)?;
+        login.notes = login.notes.map(|n| self.decrypt_field(&n)).transpose()?;
+        Ok(login)
+    }
+
+    // Use this in place of `Login::from_row` everywhere we read logins.
+    fn login_from_row(&self, row: &rusqlite::Row<'_>) -> Result<Login> {
+        self.decrypt_login(Login::from_row(row)?)
+    }
+}
+
+// The names of the fields which differ between two versions of a login, for
+// the history.
+fn changed_fields(before: Option<&Login>, after: Option<&Login>) -> Vec<&'static str> {
+    let (before, after) = match (before, after) {
+        (None, None) => return vec![],
+        (None, Some(_)) => return vec!["created"],
+        (Some(_), None) => return vec!["deleted"],
+        (Some(before), Some(after)) => (before, after),
+    };
+    let mut fields = vec![];
+    if before.hostname != after.hostname {
+        fields.push("hostname");
+    }
+    if before.http_realm != after.http_realm {
+        fields.push("httpRealm");
+    }
+    if before.form_submit_url != after.form_submit_url {
+        fields.push("formSubmitURL");
+    }
+    if before.username_field != after.username_field {
+        fields.push("usernameField");
+    }
+    if before.password_field != after.password_field {
+        fields.push("passwordField");
+    }
+    if before.username != after.username {
+        fields.push("username");
+    }
+    if before.password != after.password {
+        fields.push("password");
+    }
+    if before.notes != after.notes {
+        fields.push("notes");
+    }
+    fields
+}
+
+// Registers a handle which interrupts `db`, and the scopes using `counter`,
+// with `interrupt_support::shutdown`.
+fn register_shutdown_handle(
+    db: &Connection,
+    counter: &Arc<AtomicUsize>,
+) -> Arc<SqlInterruptHandle> {
+    let handle = Arc::new(SqlInterruptHandle::new(
+        db.get_interrupt_handle(),
+        counter.clone(),
+    ));
+    interrupt_support::register_interrupt(Arc::downgrade(&handle) as Weak<dyn Interruptable>);
+    handle
 }
 
 // Checks if the provided string is a 32 len hex string.
@@ -202,19 +874,6 @@ fn ensure_valid_salt(salt: &str) -> Result<()> {
     Err(ErrorKind::InvalidSalt.into())
 }
 
-fn sqlcipher_3_compat(conn: &Connection) -> Result<()> {
-    // SQLcipher pre-4.0.0 compatibility. Using SHA1 still
-    // is less than ideal, but should be fine. Real uses of
-    // this (lockwise, etc) use a real random string for the
-    // encryption key, so the reduced KDF iteration count
-    // is fine.
-    conn.set_pragma("cipher_page_size", 1024)?
-        .set_pragma("kdf_iter", 64000)?
-        .set_pragma("cipher_hmac_algorithm", "HMAC_SHA1")?
-        .set_pragma("cipher_kdf_algorithm", "PBKDF2_HMAC_SHA1")?;
-    Ok(())
-}
-
 impl ConnExt for LoginDb {
     #[inline]
     fn conn(&self) -> &Connection {
@@ -233,12 +892,13 @@ impl Deref for LoginDb {
 // login specific stuff.
 
 impl LoginDb {
-    fn mark_as_synchronized(
+    pub(crate) fn mark_as_synchronized(
         &self,
         guids: &[&str],
         ts: ServerTimestamp,
         scope: &SqlInterruptScope,
     ) -> Result<()> {
+        self.check_writable()?;
         let tx = self.unchecked_transaction()?;
         sql_support::each_chunk(guids, |chunk, _| -> Result<()> {
             self.db.execute(
@@ -277,6 +937,7 @@ impl LoginDb {
             Ok(())
         })?;
         self.set_last_sync(ts)?;
+        self.delete_meta(schema::PENDING_SYNC_META_KEY)?;
         tx.commit()?;
         Ok(())
     }
@@ -298,7 +959,10 @@ impl LoginDb {
                     throw!(ErrorKind::DuplicateGuid(incoming.0.id.to_string()))
                 }
                 seen_ids.insert(incoming.0.id.clone());
-                match SyncLoginData::from_payload(incoming.0.clone(), incoming.1) {
+                match SyncLoginData::from_payload(
+                    self.sync_config.to_local(incoming.0.clone()),
+                    incoming.1,
+                ) {
                     Ok(v) => sync_data.push(v),
                     Err(e) => {
                         log::error!("Failed to deserialize record {:?}: {}", incoming.0.id, e);
@@ -328,6 +992,7 @@ impl LoginDb {
                          NULL as local_modified,
                          NULL as is_deleted,
                          NULL as sync_status,
+                         NULL as field_modified,
                          1 as is_mirror,
                          to_fetch.guid_idx as guid_idx
                      FROM loginsM
@@ -343,6 +1008,7 @@ impl LoginDb {
                          local_modified,
                          is_deleted,
                          sync_status,
+                         field_modified,
                          0 as is_mirror,
                          to_fetch.guid_idx as guid_idx
                      FROM loginsL
@@ -363,9 +1029,13 @@ impl LoginDb {
                     let guid_idx = guid_idx_i as usize;
                     let is_mirror: bool = row.get("is_mirror")?;
                     if is_mirror {
-                        sync_data[guid_idx].set_mirror(MirrorLogin::from_row(row)?)?;
+                        let mut mirror = MirrorLogin::from_row(row)?;
+                        mirror.login = self.decrypt_login(mirror.login)?;
+                        sync_data[guid_idx].set_mirror(mirror)?;
                     } else {
-                        sync_data[guid_idx].set_local(LocalLogin::from_row(row)?)?;
+                        let mut local = LocalLogin::from_row(row)?;
+                        local.login = self.decrypt_login(local.login)?;
+                        sync_data[guid_idx].set_local(local)?;
                     }
                     scope.err_if_interrupted()?;
                     Ok(())
@@ -378,9 +1048,16 @@ impl LoginDb {
         Ok(sync_data)
     }
 
+    // Finds a local login with the same content as the incoming `l`, which
+    // can take on the GUID of `l`. Only logins which have never been synced
+    // are candidates, as the server already has a record for any other, and
+    // logins whose own GUID is incoming are reconciled with that instead.
+    // After a reset (for example, because the sync ID changed), every login
+    // is a candidate, so logins already on the server aren't duplicated.
+    //
     // It would be nice if this were a batch-ish api (e.g. takes a slice of records and finds dupes
     // for each one if they exist)... I can't think of how to write that query, though.
-    fn find_dupe(&self, l: &Login) -> Result<Option<Login>> {
+    fn find_dupe(&self, l: &Login, claimed: &HashSet<Guid>) -> Result<Option<Login>> {
         let form_submit_host_port = l
             .form_submit_url
             .as_ref()
@@ -388,16 +1065,20 @@ impl LoginDb {
         let args = named_params! {
             ":hostname": l.hostname,
             ":http_realm": l.http_realm,
-            ":username": l.username,
             ":form_submit": form_submit_host_port,
         };
+        // The username is compared below, as it may be encrypted.
         let mut query = format!(
             "SELECT {common}
              FROM loginsL
              WHERE hostname IS :hostname
                AND httpRealm IS :http_realm
-               AND username IS :username",
+               AND sync_status = {new}
+               AND is_deleted = 0
+               AND local_only = 0
+               AND guid NOT IN (SELECT guid FROM loginsStaging)",
             common = schema::COMMON_COLS,
+            new = SyncStatus::New as u8,
         );
         if form_submit_host_port.is_some() {
             // Stolen from iOS
@@ -405,62 +1086,155 @@ impl LoginDb {
         } else {
             query += " AND formSubmitURL IS :form_submit"
         }
-        Ok(self.try_query_row(&query, args, |row| Login::from_row(row), false)?)
+        let candidates: Vec<Login> =
+            self.query_rows_and_then_named_cached(&query, args, |row| self.login_from_row(row))?;
+        Ok(candidates
+            .into_iter()
+            .find(|c| c.username == l.username && !claimed.contains(&c.guid)))
+    }
+
+    pub fn get_all(&self, scope: &SqlInterruptScope) -> Result<Vec<Login>> {
+        let mut stmt = self.db.prepare_cached(&GET_ALL_SQL)?;
+        let rows = stmt.query_and_then(NO_PARAMS, |row| {
+            scope.err_if_interrupted()?;
+            self.login_from_row(row)
+        })?;
+        rows.collect::<Result<_>>()
+    }
+
+    pub fn query(&self, query: &LoginQuery, scope: &SqlInterruptScope) -> Result<Vec<Login>> {
+        let sql = format!(
+            "SELECT * FROM ({get_all})
+             ORDER BY {order_by}
+             LIMIT :limit OFFSET :offset",
+            get_all = &*GET_ALL_SQL,
+            order_by = query.sort_order.order_by_sql(),
+        );
+        let mut stmt = self.db.prepare_cached(&sql)?;
+        let rows = stmt.query_and_then_named(
+            named_params! {
+                // A negative limit means "no limit" to SQLite.
+                ":limit": query.limit.map(i64::from).unwrap_or(-1),
+                ":offset": i64::from(query.offset),
+            },
+            |row| {
+                scope.err_if_interrupted()?;
+                self.login_from_row(row)
+            },
+        )?;
+        rows.collect::<Result<_>>()
     }
 
-    pub fn get_all(&self) -> Result<Vec<Login>> {
+    /// Find logins whose hostname, username or http realm contain `query`,
+    /// ignoring case. Queries containing non-ascii domain names also match
+    /// the punycode form we store hostnames in.
+    pub fn search(&self, query: &str, scope: &SqlInterruptScope) -> Result<Vec<Login>> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+        // Hostnames are always stored as punycode, so a query for eg,
+        // "bücher.example" needs to look for "xn--bcher-kva.example" too.
+        let ascii_query = match Host::parse(&query) {
+            Ok(Host::Domain(d)) if d != query => Some(d),
+            _ => None,
+        };
+        // Queries which look like an origin also match logins for that
+        // origin exactly, so eg "Example.com:443" finds "https://example.com".
+        let origin_query = normalize_origin(&query, OriginMode::Loose).ok();
+        let matches = |field: &str| {
+            let field = field.to_lowercase();
+            field.contains(&query)
+                || ascii_query
+                    .as_ref()
+                    .map_or(false, |ascii| field.contains(ascii.as_str()))
+        };
+        // We just do a linear scan rather than maintaining a full-text index -
+        // record counts are expected to be low enough that it doesn't matter.
         let mut stmt = self.db.prepare_cached(&GET_ALL_SQL)?;
-        let rows = stmt.query_and_then(NO_PARAMS, Login::from_row)?;
+        let rows = stmt
+            .query_and_then(NO_PARAMS, |row| {
+                scope.err_if_interrupted()?;
+                self.login_from_row(row)
+            })?
+            .filter(|r| match r {
+                Ok(login) => {
+                    matches(&login.hostname)
+                        || origin_query.as_ref() == Some(&login.hostname)
+                        || matches(&login.username)
+                        || login.http_realm.as_ref().map_or(false, |r| matches(r))
+                }
+                Err(_) => true,
+            });
         rows.collect::<Result<_>>()
     }
 
-    pub fn get_by_base_domain(&self, base_domain: &str) -> Result<Vec<Login>> {
+    /// Returns every login whose host has the same base domain (eTLD+1) as
+    /// `base_domain`, using the public suffix list. So "example.co.uk" and
+    /// "www.example.co.uk" both find the logins for "example.co.uk" and all
+    /// its subdomains, while "co.uk", a public suffix, finds nothing. IP
+    /// addresses only match themselves.
+    pub fn get_by_base_domain(
+        &self,
+        base_domain: &str,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<Login>> {
         // We first parse the input string as a host so it is normalized.
-        let base_host = match Host::parse(base_domain) {
-            Ok(d) => d,
+        let host = match Host::parse(base_domain) {
+            Ok(Host::Domain(domain)) => match psl::base_domain(&domain) {
+                Some(base) => base.to_owned(),
+                None => return Ok(vec![]),
+            },
+            Ok(host) => host.to_string(),
             Err(e) => {
                 // don't log the input string as it's PII.
                 log::warn!("get_by_base_domain was passed an invalid domain: {}", e);
                 return Ok(vec![]);
             }
         };
-        // We just do a linear scan. Another option is to have an indexed
-        // reverse-host column or similar, but current thinking is that it's
-        // extra complexity for (probably) zero actual benefit given the record
-        // counts are expected to be so low.
-        // A regex would probably make this simpler, but we don't want to drag
-        // in a regex lib just for this.
+        // The reversed hosts of a domain and its subdomains all start with
+        // the reversed domain and its trailing dot, and '/' sorts right after
+        // '.', so this is the range of `rev_host`s to look for. IP addresses
+        // can't have subdomains, so the only match for them is themselves.
+        let rev_host = schema::reverse_host(&host);
+        let rev_host_end = format!("{}/", &rev_host[..rev_host.len() - 1]);
+        if !self.has_rev_host {
+            return self.get_by_rev_host_range_unindexed(&rev_host, &rev_host_end, scope);
+        }
+        let mut stmt = self.db.prepare_cached(&GET_BY_REV_HOST_RANGE_SQL)?;
+        let rows = stmt.query_and_then_named(
+            named_params! {
+                ":rev_host": rev_host,
+                ":rev_host_end": rev_host_end,
+            },
+            |row| {
+                scope.err_if_interrupted()?;
+                self.login_from_row(row)
+            },
+        )?;
+        rows.collect::<Result<_>>()
+    }
+
+    // Like `get_by_base_domain`, for read-only connections to databases
+    // which don't have the `rev_host` column yet.
+    fn get_by_rev_host_range_unindexed(
+        &self,
+        rev_host: &str,
+        rev_host_end: &str,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<Login>> {
         let mut stmt = self.db.prepare_cached(&GET_ALL_SQL)?;
         let rows = stmt
-            .query_and_then(NO_PARAMS, Login::from_row)?
-            .filter(|r| {
-                let login = r
-                    .as_ref()
-                    .ok()
-                    .and_then(|login| Url::parse(&login.hostname).ok());
-                let this_host = login.as_ref().and_then(|url| url.host());
-                match (&base_host, this_host) {
-                    (Host::Domain(base), Some(Host::Domain(look))) => {
-                        // a fairly long-winded way of saying
-                        // `login.hostname == base_domain ||
-                        //  login.hostname.ends_with('.' + base_domain);`
-                        let mut rev_input = base.chars().rev();
-                        let mut rev_host = look.chars().rev();
-                        loop {
-                            match (rev_input.next(), rev_host.next()) {
-                                (Some(ref a), Some(ref b)) if a == b => continue,
-                                (None, None) => return true, // exactly equal
-                                (None, Some(ref h)) => return *h == '.',
-                                _ => return false,
-                            }
-                        }
-                    }
-                    // ip addresses must match exactly.
-                    (Host::Ipv4(base), Some(Host::Ipv4(look))) => *base == look,
-                    (Host::Ipv6(base), Some(Host::Ipv6(look))) => *base == look,
-                    // all "mismatches" in domain types are false.
-                    _ => false,
-                }
+            .query_and_then(NO_PARAMS, |row| {
+                scope.err_if_interrupted()?;
+                self.login_from_row(row)
+            })?
+            .filter(|r| match r {
+                Ok(login) => schema::reverse_host_of_origin(&login.hostname).map_or(false, |rev| {
+                    rev.as_str() >= rev_host && rev.as_str() < rev_host_end
+                }),
+                // Let errors (including interruption) through to `collect`.
+                Err(_) => true,
             });
         rows.collect::<Result<_>>()
     }
@@ -469,23 +1243,43 @@ impl LoginDb {
         self.try_query_row(
             &GET_BY_GUID_SQL,
             &[(":guid", &id as &dyn ToSql)],
-            Login::from_row,
+            |row| self.login_from_row(row),
+            true,
+        )
+    }
+
+    /// Like `get_by_id`, but fails with `Interrupted` if `scope` is
+    /// interrupted before the login is decrypted.
+    pub fn get_by_id_in_scope(&self, id: &str, scope: &SqlInterruptScope) -> Result<Option<Login>> {
+        scope.err_if_interrupted()?;
+        self.try_query_row(
+            &GET_BY_GUID_SQL,
+            &[(":guid", &id as &dyn ToSql)],
+            |row| {
+                scope.err_if_interrupted()?;
+                self.login_from_row(row)
+            },
             true,
         )
     }
 
     pub fn touch(&self, id: &str) -> Result<()> {
+        self.check_writable()?;
         let tx = self.unchecked_transaction()?;
         self.ensure_local_overlay_exists(id)?;
         self.mark_mirror_overridden(id)?;
         let now_ms = util::system_time_ms_i64(SystemTime::now());
-        // As on iOS, just using a record doesn't flip it's status to changed.
-        // TODO: this might be wrong for lockbox!
+        // The usage counters are synced, so bump them in place rather than
+        // requiring a fetch-modify-update which could race with a sync. As on
+        // desktop, this only schedules a weak upload: the record isn't marked
+        // as changed, so if another device changed it first the incoming
+        // record wins instead of conflicting with our usage bump.
         self.execute_named_cached(
             "UPDATE loginsL
              SET timeLastUsed = :now_millis,
                  timesUsed = timesUsed + 1,
-                 local_modified = :now_millis
+                 local_modified = :now_millis,
+                 weak_upload = 1
              WHERE guid = :guid
                  AND is_deleted = 0",
             named_params! {
@@ -497,19 +1291,110 @@ impl LoginDb {
         Ok(())
     }
 
-    pub fn add(&self, login: Login) -> Result<Login> {
-        let mut login = self.fixup_and_check_for_dupes(login)?;
-
+    /// Marks a login as local-only, meaning it's never uploaded and incoming
+    /// changes to it are ignored, or makes it a normal login again (in which
+    /// case it's uploaded on the next sync). A login which was synced before
+    /// being made local-only is left as it was on the server; delete it and
+    /// add a copy to stop the server knowing about it.
+    pub fn set_local_only(&self, id: &str, local_only: bool) -> Result<()> {
+        self.check_writable()?;
         let tx = self.unchecked_transaction()?;
-        let now_ms = util::system_time_ms_i64(SystemTime::now());
-
-        // Allow an empty GUID to be passed to indicate that we should generate
-        // one. (Note that the FFI, does not require that the `id` field be
-        // present in the JSON, and replaces it with an empty string if missing).
-        if login.guid.is_empty() {
-            login.guid = Guid::random()
+        if !self.exists(id)? {
+            throw!(ErrorKind::NoSuchRecord(id.to_owned()));
         }
-
+        self.ensure_local_overlay_exists(id)?;
+        self.mark_mirror_overridden(id)?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        self.execute_named_cached(
+            &format!(
+                "UPDATE loginsL
+                 SET local_only = :local_only,
+                     local_modified = :now_millis,
+                     sync_status = max(sync_status, {changed})
+                 WHERE guid = :guid",
+                changed = SyncStatus::Changed as u8
+            ),
+            named_params! {
+                ":local_only": local_only,
+                ":now_millis": now_ms,
+                ":guid": id,
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn is_local_only(&self, id: &str) -> Result<bool> {
+        Ok(self.query_row_and_then_named(
+            "SELECT EXISTS(
+                 SELECT 1 FROM loginsL
+                 WHERE guid = :guid AND local_only = 1
+             )",
+            named_params! { ":guid": id },
+            |row| row.get(0),
+            true,
+        )?)
+    }
+
+    /// Change just the password of a login, leaving every other field as it
+    /// is. This avoids clobbering fields which may have been changed by other
+    /// clients since the caller fetched the login.
+    pub fn update_password(&self, id: &str, new_password: &str) -> Result<()> {
+        self.check_writable()?;
+        if new_password.is_empty() {
+            throw!(InvalidLogin::EmptyPassword);
+        }
+        let tx = self.unchecked_transaction()?;
+        match self.get_by_id(id)? {
+            None => throw!(ErrorKind::NoSuchRecord(id.to_owned())),
+            Some(existing) if existing.password == new_password => return Ok(()),
+            Some(_) => {}
+        }
+        self.record_history(id, &["password"], ChangeSource::Local)?;
+        self.ensure_local_overlay_exists(id)?;
+        self.mark_mirror_overridden(id)?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        self.execute_named_cached(
+            &format!(
+                "UPDATE loginsL
+                 SET password = :password,
+                     timePasswordChanged = :now_millis,
+                     local_modified = :now_millis,
+                     sync_status = max(sync_status, {changed})
+                 WHERE guid = :guid
+                     AND is_deleted = 0",
+                changed = SyncStatus::Changed as u8
+            ),
+            named_params! {
+                ":password": self.encrypt_field(new_password)?,
+                ":now_millis": now_ms,
+                ":guid": id,
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn add(&self, login: Login) -> Result<Login> {
+        self.check_writable()?;
+        let tx = self.unchecked_transaction()?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let login = self.add_in_transaction(login, now_ms)?;
+        tx.commit()?;
+        Ok(login)
+    }
+
+    // The guts of `add`, which expects to be called in a transaction.
+    fn add_in_transaction(&self, login: Login, now_ms: i64) -> Result<Login> {
+        let mut login = self.fixup_and_check_for_dupes(login)?;
+
+        // Allow an empty GUID to be passed to indicate that we should generate
+        // one. (Note that the FFI, does not require that the `id` field be
+        // present in the JSON, and replaces it with an empty string if missing).
+        if login.guid.is_empty() {
+            login.guid = Guid::random()
+        }
+
         // Fill in default metadata.
         if login.time_created == 0 {
             login.time_created = now_ms;
@@ -534,6 +1419,8 @@ impl LoginDb {
                 timesUsed,
                 username,
                 password,
+                notes,
+                unknown_fields,
                 guid,
                 timeCreated,
                 timeLastUsed,
@@ -550,6 +1437,8 @@ impl LoginDb {
                 :times_used,
                 :username,
                 :password,
+                :notes,
+                :unknown_fields,
                 :guid,
                 :time_created,
                 :time_last_used,
@@ -561,7 +1450,7 @@ impl LoginDb {
             new = SyncStatus::New as u8
         );
 
-        let rows_changed = self.execute_named(
+        let rows_changed = self.execute_named_cached(
             &sql,
             named_params! {
                 ":hostname": login.hostname,
@@ -569,8 +1458,10 @@ impl LoginDb {
                 ":form_submit_url": login.form_submit_url,
                 ":username_field": login.username_field,
                 ":password_field": login.password_field,
-                ":username": login.username,
-                ":password": login.password,
+                ":username": self.encrypt_field(&login.username)?,
+                ":password": self.encrypt_field(&login.password)?,
+                ":notes": self.encrypt_optional_field(login.notes.as_deref())?,
+                ":unknown_fields": login.unknown_fields,
                 ":guid": login.guid,
                 ":time_created": login.time_created,
                 ":times_used": login.times_used,
@@ -586,11 +1477,22 @@ impl LoginDb {
             );
             throw!(ErrorKind::DuplicateGuid(login.guid.into_string()));
         }
-        tx.commit()?;
+        self.record_history(login.guid_str(), &["created"], ChangeSource::Local)?;
         Ok(login)
     }
 
     pub fn import_multiple(&self, logins: &[Login]) -> Result<MigrationMetrics> {
+        self.import_multiple_recording(logins, &mut Vec::new())
+    }
+
+    // Like `import_multiple`, but also adds the GUIDs of the imported logins
+    // to `imported`, so the engine can tell observers about them.
+    pub(crate) fn import_multiple_recording(
+        &self,
+        logins: &[Login],
+        imported: &mut Vec<Guid>,
+    ) -> Result<MigrationMetrics> {
+        self.check_writable()?;
         // Check if the logins table is empty first.
         let mut num_existing_logins =
             self.query_row::<i64, _, _>("SELECT COUNT(*) FROM loginsL", NO_PARAMS, |r| r.get(0))?;
@@ -612,6 +1514,8 @@ impl LoginDb {
                 timesUsed,
                 username,
                 password,
+                notes,
+                unknown_fields,
                 guid,
                 timeCreated,
                 timeLastUsed,
@@ -628,6 +1532,8 @@ impl LoginDb {
                 :times_used,
                 :username,
                 :password,
+                :notes,
+                :unknown_fields,
                 :guid,
                 :time_created,
                 :time_last_used,
@@ -685,8 +1591,10 @@ impl LoginDb {
                     ":form_submit_url": login.form_submit_url,
                     ":username_field": login.username_field,
                     ":password_field": login.password_field,
-                    ":username": login.username,
-                    ":password": login.password,
+                    ":username": self.encrypt_field(&login.username)?,
+                    ":password": self.encrypt_field(&login.password)?,
+                    ":notes": self.encrypt_optional_field(login.notes.as_deref())?,
+                    ":unknown_fields": login.unknown_fields,
                     ":guid": guid,
                     ":time_created": login.time_created,
                     ":times_used": login.times_used,
@@ -695,7 +1603,11 @@ impl LoginDb {
                     ":local_modified": now_ms,
                 },
             ) {
-                Ok(_) => log::info!("Imported {} (new GUID {}) successfully.", old_guid, guid),
+                Ok(0) => log::info!("Skipped {}, as its GUID {} is in use.", old_guid, guid),
+                Ok(_) => {
+                    log::info!("Imported {} (new GUID {}) successfully.", old_guid, guid);
+                    imported.push(guid);
+                }
                 Err(e) => {
                     log::warn!("Could not import {} ({}).", old_guid, e);
                     insert_errors.push(Error::from(e).label().into());
@@ -747,14 +1659,29 @@ impl LoginDb {
     }
 
     pub fn update(&self, login: Login) -> Result<()> {
-        let login = self.fixup_and_check_for_dupes(login)?;
-
+        self.check_writable()?;
         let tx = self.unchecked_transaction()?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        self.update_in_transaction(login, now_ms)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // The guts of `update`, which expects to be called in a transaction.
+    fn update_in_transaction(&self, login: Login, now_ms: i64) -> Result<()> {
+        let login = self.fixup_and_check_for_dupes(login)?;
         // Note: These fail with DuplicateGuid if the record doesn't exist.
         self.ensure_local_overlay_exists(login.guid_str())?;
+        // Passwords may be encrypted, so this can't be checked in SQL.
+        let existing = self.get_by_id(login.guid_str())?;
+        let password_changed = existing
+            .as_ref()
+            .map_or(true, |existing| existing.password != login.password);
+        let changed = changed_fields(existing.as_ref(), Some(&login));
+        self.record_history(login.guid_str(), &changed, ChangeSource::Local)?;
         self.mark_mirror_overridden(login.guid_str())?;
-
-        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let field_modified =
+            self.field_modified_after_change(login.guid_str(), &changed, now_ms)?;
 
         let sql = format!(
             "UPDATE loginsL
@@ -762,9 +1689,9 @@ impl LoginDb {
                  timeLastUsed        = :now_millis,
                  -- Only update timePasswordChanged if, well, the password changed.
                  timePasswordChanged = (CASE
-                     WHEN password = :password
-                     THEN timePasswordChanged
-                     ELSE :now_millis
+                     WHEN :password_changed
+                     THEN :now_millis
+                     ELSE timePasswordChanged
                  END),
                  httpRealm           = :http_realm,
                  formSubmitURL       = :form_submit_url,
@@ -773,31 +1700,62 @@ impl LoginDb {
                  timesUsed           = timesUsed + 1,
                  username            = :username,
                  password            = :password,
+                 notes               = :notes,
                  hostname            = :hostname,
+                 field_modified      = :field_modified,
                  -- leave New records as they are, otherwise update them to `changed`
                  sync_status         = max(sync_status, {changed})
              WHERE guid = :guid",
             changed = SyncStatus::Changed as u8
         );
 
-        self.db.execute_named(
+        self.execute_named_cached(
             &sql,
             named_params! {
                 ":hostname": login.hostname,
-                ":username": login.username,
-                ":password": login.password,
+                ":username": self.encrypt_field(&login.username)?,
+                ":password": self.encrypt_field(&login.password)?,
+                ":notes": self.encrypt_optional_field(login.notes.as_deref())?,
                 ":http_realm": login.http_realm,
                 ":form_submit_url": login.form_submit_url,
                 ":username_field": login.username_field,
                 ":password_field": login.password_field,
                 ":guid": login.guid,
                 ":now_millis": now_ms,
+                ":password_changed": password_changed,
+                ":field_modified": field_modified,
             },
         )?;
-        tx.commit()?;
         Ok(())
     }
 
+    // Returns the new value of the `field_modified` column of the local login
+    // with the given GUID, after `fields` changed at `now_ms`.
+    fn field_modified_after_change(
+        &self,
+        guid: &str,
+        fields: &[&str],
+        now_ms: i64,
+    ) -> Result<Option<String>> {
+        let existing: Option<String> = self.query_row_and_then_named(
+            "SELECT field_modified FROM loginsL WHERE guid = :guid",
+            named_params! { ":guid": guid },
+            |row| row.get(0),
+            true,
+        )?;
+        if fields.is_empty() {
+            return Ok(existing);
+        }
+        let mut field_modified: HashMap<String, i64> = match existing {
+            Some(json) => serde_json::from_str(&json)?,
+            None => HashMap::new(),
+        };
+        for field in fields {
+            field_modified.insert((*field).to_owned(), now_ms);
+        }
+        Ok(Some(serde_json::to_string(&field_modified)?))
+    }
+
     pub fn check_valid_with_no_dupes(&self, login: &Login) -> Result<()> {
         login.check_valid()?;
         self.check_for_dupes(login)
@@ -819,41 +1777,49 @@ impl LoginDb {
     pub fn dupe_exists(&self, login: &Login) -> Result<bool> {
         // Note: the query below compares the guids of the given login with existing logins
         //  to prevent a login from being considered a duplicate of itself (e.g. during updates).
-        Ok(self.db.query_row_named(
-            "SELECT EXISTS(
-                SELECT 1 FROM loginsL
-                WHERE is_deleted = 0
-                    AND guid <> :guid
-                    AND hostname = :hostname
-                    AND NULLIF(username, '') = :username
-                    AND (
-                        formSubmitURL = :form_submit
-                        OR
-                        httpRealm = :http_realm
-                    )
-
-                UNION ALL
-
-                SELECT 1 FROM loginsM
-                WHERE is_overridden = 0
-                    AND guid <> :guid
-                    AND hostname = :hostname
-                    AND NULLIF(username, '') = :username
-                    AND (
-                        formSubmitURL = :form_submit
-                        OR
-                        httpRealm = :http_realm
-                    )
-             )",
+        // Usernames may be encrypted, so they're compared after decrypting.
+        let usernames = self.query_rows_and_then_named_cached(
+            "SELECT username FROM loginsL
+             WHERE is_deleted = 0
+                AND guid <> :guid
+                AND hostname = :hostname
+                AND (
+                    formSubmitURL = :form_submit
+                    OR
+                    httpRealm = :http_realm
+                )
+
+             UNION ALL
+
+             SELECT username FROM loginsM
+             WHERE is_overridden = 0
+                AND guid <> :guid
+                AND hostname = :hostname
+                AND (
+                    formSubmitURL = :form_submit
+                    OR
+                    httpRealm = :http_realm
+                )",
             named_params! {
                 ":guid": &login.guid,
                 ":hostname": &login.hostname,
-                ":username": &login.username,
                 ":http_realm": login.http_realm.as_ref(),
                 ":form_submit": login.form_submit_url.as_ref(),
             },
-            |row| row.get(0),
-        )?)
+            |row| -> Result<Option<String>> { Ok(row.get(0)?) },
+        )?;
+        for username in usernames {
+            // An empty (or NULL) username is never considered a dupe.
+            match username {
+                Some(u) if !u.is_empty() => {
+                    if self.decrypt_field(&u)? == login.username {
+                        return Ok(true);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(false)
     }
 
     pub fn potential_dupes_ignoring_username(&self, login: &Login) -> Result<Vec<Login>> {
@@ -890,12 +1856,12 @@ impl LoginDb {
             ":form_submit": login.form_submit_url.as_ref(),
         };
         // Needs to be two lines for borrow checker
-        let rows = stmt.query_and_then_named(params, Login::from_row)?;
+        let rows = stmt.query_and_then_named(params, |row| self.login_from_row(row))?;
         rows.collect()
     }
 
     pub fn exists(&self, id: &str) -> Result<bool> {
-        Ok(self.db.query_row_named(
+        Ok(self.query_row_and_then_named(
             "SELECT EXISTS(
                  SELECT 1 FROM loginsL
                  WHERE guid = :guid AND is_deleted = 0
@@ -905,15 +1871,513 @@ impl LoginDb {
              )",
             named_params! { ":guid": id },
             |row| row.get(0),
+            true,
         )?)
     }
 
     /// Delete the record with the provided id. Returns true if the record
     /// existed already.
     pub fn delete(&self, id: &str) -> Result<bool> {
+        self.check_writable()?;
+        let tx = self.unchecked_transaction_imm()?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let exists = self.delete_in_transaction(id, now_ms)?;
+        tx.commit()?;
+        Ok(exists)
+    }
+
+    /// Applies `ops` in order, in a single transaction. Each operation has
+    /// the same effect (and fails in the same ways) as the corresponding call
+    /// to `add`, `update` or `delete`, except that deleting a login which
+    /// doesn't exist fails with `NoSuchRecord`. An operation which fails
+    /// doesn't prevent the others from being applied; its result holds the
+    /// error. Otherwise, each result holds the GUID of the affected login,
+    /// which for an `add` may have been generated.
+    ///
+    /// An error is returned (and nothing is applied) only if the transaction
+    /// itself fails, or the batch is interrupted.
+    pub fn apply_batch(&self, ops: Vec<LoginOperation>) -> Result<Vec<Result<Guid>>> {
+        self.check_writable()?;
+        let scope = self.begin_interrupt_scope();
+        let tx = self.unchecked_transaction_imm()?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            scope.err_if_interrupted()?;
+            // Each operation gets a savepoint, so one which fails part way
+            // through doesn't leave anything behind.
+            self.execute_all(&["SAVEPOINT apply_batch_op"])?;
+            let result = match op {
+                LoginOperation::Add { login } => self
+                    .add_in_transaction(login, now_ms)
+                    .map(|login| login.guid),
+                LoginOperation::Update { login } => {
+                    let guid = login.guid.clone();
+                    self.update_in_transaction(login, now_ms).map(|_| guid)
+                }
+                LoginOperation::Delete { id } => match self.delete_in_transaction(&id, now_ms) {
+                    Ok(true) => Ok(Guid::from(id)),
+                    Ok(false) => Err(ErrorKind::NoSuchRecord(id).into()),
+                    Err(e) => Err(e),
+                },
+            };
+            if result.is_err() {
+                self.execute_all(&["ROLLBACK TO apply_batch_op"])?;
+            }
+            self.execute_all(&["RELEASE apply_batch_op"])?;
+            results.push(result);
+        }
+        tx.commit()?;
+        Ok(results)
+    }
+
+    /// Checks the local and mirror records for consistency with each other,
+    /// and, if `server_records` are given, with the server, in the manner of
+    /// desktop's sync validators. `server_records` should contain every
+    /// record in the server's `passwords` collection, with `None` for
+    /// tombstones.
+    ///
+    /// If `repair` is true, orphaned mirror records are un-overridden, logins
+    /// missing from the server are marked to be uploaded on the next sync,
+    /// and, if anything is missing locally or differs from the server, the
+    /// last sync time is reset so that the next sync downloads everything.
+    /// Duplicate server GUIDs can't be repaired from here.
+    pub fn validate(
+        &self,
+        server_records: Option<&[(Guid, Option<Login>)]>,
+        repair: bool,
+    ) -> Result<ValidationReport> {
+        if repair {
+            self.check_writable()?;
+        }
+        let scope = self.begin_interrupt_scope();
+        let tx = self.unchecked_transaction()?;
+        let mut report = ValidationReport::default();
+
+        let local_guids: HashSet<Guid> = self
+            .query_rows_and_then_named("SELECT guid FROM loginsL", &[], |row| row.get(0))?
+            .into_iter()
+            .collect();
+        let mut mirror: BTreeMap<Guid, (Login, bool)> = BTreeMap::new();
+        for (login, is_overridden) in self.query_rows_and_then_named(
+            "SELECT * FROM loginsM",
+            &[],
+            |row| -> Result<(Login, bool)> {
+                Ok((self.login_from_row(row)?, row.get("is_overridden")?))
+            },
+        )? {
+            mirror.insert(login.guid.clone(), (login, is_overridden));
+        }
+        scope.err_if_interrupted()?;
+
+        for (guid, (_, is_overridden)) in &mirror {
+            if *is_overridden && !local_guids.contains(guid) {
+                report.orphaned_mirror.push(guid.to_string());
+            }
+        }
+
+        if let Some(server_records) = server_records {
+            let mut server: BTreeMap<&Guid, Option<&Login>> = BTreeMap::new();
+            for (guid, record) in server_records {
+                if server.insert(guid, record.as_ref()).is_some() {
+                    report.duplicate_guids.push(guid.to_string());
+                }
+            }
+            report.duplicate_guids.sort();
+            report.duplicate_guids.dedup();
+            for (guid, record) in &server {
+                match (record, mirror.get(*guid)) {
+                    (Some(_), None) if !local_guids.contains(*guid) => {
+                        report.client_missing.push(guid.to_string())
+                    }
+                    (Some(record), Some((mirrored, _))) if *record != mirrored => {
+                        report.differences.push(guid.to_string())
+                    }
+                    _ => {}
+                }
+            }
+            for guid in mirror.keys() {
+                if server.get(guid).map_or(true, Option::is_none) {
+                    report.server_missing.push(guid.to_string());
+                }
+            }
+        }
+        scope.err_if_interrupted()?;
+
+        if repair && !report.is_empty() {
+            log::warn!("Repairing logins: {:?}", report);
+            for guid in &report.orphaned_mirror {
+                self.execute_named_cached(
+                    "UPDATE loginsM SET is_overridden = 0 WHERE guid = :guid",
+                    named_params! { ":guid": guid },
+                )?;
+            }
+            let now_ms = util::system_time_ms_i64(SystemTime::now());
+            for guid in &report.server_missing {
+                self.ensure_local_overlay_exists(guid)?;
+                self.mark_mirror_overridden(guid)?;
+                self.execute_named_cached(
+                    &format!(
+                        "UPDATE loginsL
+                         SET local_modified = :now_millis,
+                             sync_status = max(sync_status, {changed})
+                         WHERE guid = :guid",
+                        changed = SyncStatus::Changed as u8
+                    ),
+                    named_params! { ":now_millis": now_ms, ":guid": guid },
+                )?;
+            }
+            if !report.client_missing.is_empty() || !report.differences.is_empty() {
+                self.set_last_sync(ServerTimestamp(0))?;
+            }
+            report.repaired = true;
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Merges logins with the same (normalized) origin and username, which
+    /// are commonly left behind by imports from several sources. In each
+    /// group, the login whose password changed most recently is kept, and
+    /// gets the sum of the group's use counts, and its earliest creation and
+    /// latest use times. The others are deleted, leaving tombstones.
+    pub fn dedupe_and_merge(&self) -> Result<MergeReport> {
+        self.check_writable()?;
+        let scope = self.begin_interrupt_scope();
+        let tx = self.unchecked_transaction_imm()?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let mut groups: BTreeMap<(String, String), Vec<Login>> = BTreeMap::new();
+        for login in self.get_all(&scope)? {
+            // Stored origins should already be normalized, but older records
+            // may not be.
+            let origin = normalize_origin(&login.hostname, OriginMode::Strict)
+                .unwrap_or_else(|_| login.hostname.clone());
+            groups
+                .entry((origin, login.username.clone()))
+                .or_default()
+                .push(login);
+        }
+        let mut report = MergeReport::default();
+        for mut logins in groups.into_iter().map(|(_, logins)| logins) {
+            if logins.len() < 2 {
+                continue;
+            }
+            scope.err_if_interrupted()?;
+            logins.sort_by(|a, b| {
+                b.time_password_changed
+                    .cmp(&a.time_password_changed)
+                    .then_with(|| b.time_last_used.cmp(&a.time_last_used))
+                    .then_with(|| a.guid.cmp(&b.guid))
+            });
+            let winner = logins.remove(0);
+            let times_used = winner.times_used + logins.iter().map(|l| l.times_used).sum::<i64>();
+            let time_created = logins
+                .iter()
+                .map(|l| l.time_created)
+                .fold(winner.time_created, i64::min);
+            let time_last_used = logins
+                .iter()
+                .map(|l| l.time_last_used)
+                .fold(winner.time_last_used, i64::max);
+            for loser in &logins {
+                self.delete_in_transaction(loser.guid_str(), now_ms)?;
+            }
+            self.ensure_local_overlay_exists(winner.guid_str())?;
+            self.mark_mirror_overridden(winner.guid_str())?;
+            self.execute_named_cached(
+                &format!(
+                    "UPDATE loginsL
+                     SET timesUsed = :times_used,
+                         timeCreated = :time_created,
+                         timeLastUsed = :time_last_used,
+                         local_modified = :now_millis,
+                         sync_status = max(sync_status, {changed})
+                     WHERE guid = :guid",
+                    changed = SyncStatus::Changed as u8
+                ),
+                named_params! {
+                    ":times_used": times_used,
+                    ":time_created": time_created,
+                    ":time_last_used": time_last_used,
+                    ":now_millis": now_ms,
+                    ":guid": winner.guid,
+                },
+            )?;
+            report.num_deleted += logins.len() as u64;
+            report.merged.insert(
+                winner.guid.into_string(),
+                logins.into_iter().map(|l| l.guid.into_string()).collect(),
+            );
+        }
+        tx.commit()?;
+        log::info!(
+            "Merged {} duplicate logins into {}",
+            report.num_deleted,
+            report.merged.len()
+        );
+        Ok(report)
+    }
+
+    /// Changes the origin of every `http://` login to `https://`. If there's
+    /// already an `https` login for the same host and user, the `http` one is
+    /// merged into it instead, as `dedupe_and_merge` does, as long as their
+    /// passwords match. Origins with a port are left alone, as a different
+    /// service probably runs on that port with `https`. Changed logins are
+    /// uploaded on the next sync.
+    pub fn upgrade_http_origins(&self) -> Result<OriginUpgradeReport> {
+        self.check_writable()?;
+        let scope = self.begin_interrupt_scope();
+        let tx = self.unchecked_transaction_imm()?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let (http, https): (Vec<Login>, Vec<Login>) = self
+            .get_all(&scope)?
+            .into_iter()
+            .partition(|login| login.hostname.starts_with("http://"));
+        let mut by_origin: HashMap<(String, String), Login> = https
+            .into_iter()
+            .map(|login| ((login.hostname.clone(), login.username.clone()), login))
+            .collect();
+        let mut report = OriginUpgradeReport::default();
+        for login in http {
+            scope.err_if_interrupted()?;
+            // `port()` is also `None` for an explicit `:80`, so the origin is
+            // rebuilt from the parsed URL, which drops it, instead of being
+            // patched as a string.
+            let mut url = match Url::parse(&login.hostname) {
+                Ok(url) if url.port().is_none() => url,
+                _ => continue,
+            };
+            if url.set_scheme("https").is_err() {
+                continue;
+            }
+            let upgraded = normalize_origin(url.as_str(), OriginMode::Strict)?;
+            let key = (upgraded.clone(), login.username.clone());
+            match by_origin.get(&key) {
+                Some(existing) if existing.password != login.password => {
+                    report.conflicting.push(login.guid.into_string());
+                }
+                Some(existing) => {
+                    self.delete_in_transaction(login.guid_str(), now_ms)?;
+                    self.ensure_local_overlay_exists(existing.guid_str())?;
+                    self.mark_mirror_overridden(existing.guid_str())?;
+                    self.execute_named_cached(
+                        &format!(
+                            "UPDATE loginsL
+                             SET timesUsed = timesUsed + :times_used,
+                                 timeCreated = min(timeCreated, :time_created),
+                                 timeLastUsed = max(timeLastUsed, :time_last_used),
+                                 local_modified = :now_millis,
+                                 sync_status = max(sync_status, {changed})
+                             WHERE guid = :guid",
+                            changed = SyncStatus::Changed as u8
+                        ),
+                        named_params! {
+                            ":times_used": login.times_used,
+                            ":time_created": login.time_created,
+                            ":time_last_used": login.time_last_used,
+                            ":now_millis": now_ms,
+                            ":guid": existing.guid,
+                        },
+                    )?;
+                    report
+                        .merged
+                        .insert(login.guid.into_string(), existing.guid.to_string());
+                }
+                None => {
+                    // Only upgrade the form's origin if it's the same as the
+                    // login's; otherwise we can't know it supports `https`.
+                    let form_submit_url = match &login.form_submit_url {
+                        Some(url) if origins_match(url, &login.hostname, OriginMode::Strict) => {
+                            Some(upgraded.clone())
+                        }
+                        other => other.clone(),
+                    };
+                    self.ensure_local_overlay_exists(login.guid_str())?;
+                    self.mark_mirror_overridden(login.guid_str())?;
+                    self.execute_named_cached(
+                        &format!(
+                            "UPDATE loginsL
+                             SET hostname = :hostname,
+                                 formSubmitURL = :form_submit_url,
+                                 local_modified = :now_millis,
+                                 sync_status = max(sync_status, {changed})
+                             WHERE guid = :guid",
+                            changed = SyncStatus::Changed as u8
+                        ),
+                        named_params! {
+                            ":hostname": upgraded,
+                            ":form_submit_url": form_submit_url,
+                            ":now_millis": now_ms,
+                            ":guid": login.guid,
+                        },
+                    )?;
+                    report.upgraded.push(login.guid.to_string());
+                    let login = Login {
+                        hostname: upgraded,
+                        form_submit_url,
+                        ..login
+                    };
+                    by_origin.insert(key, login);
+                }
+            }
+        }
+        tx.commit()?;
+        log::info!(
+            "Upgraded {} http origins, merged {} and left {} with conflicting passwords",
+            report.upgraded.len(),
+            report.merged.len(),
+            report.conflicting.len()
+        );
+        Ok(report)
+    }
+
+    /// Deletes every login created in `[start_ms, end_ms)`, writing tombstones
+    /// as `delete` does, and returns their GUIDs.
+    pub fn delete_between(&self, start_ms: i64, end_ms: i64) -> Result<Vec<Guid>> {
+        self.check_writable()?;
         let tx = self.unchecked_transaction_imm()?;
-        let exists = self.exists(id)?;
         let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let guids = self.query_rows_and_then_named(
+            "SELECT guid FROM loginsL
+             WHERE is_deleted = 0
+                AND timeCreated >= :start AND timeCreated < :end
+             UNION
+             SELECT guid FROM loginsM
+             WHERE is_overridden = 0
+                AND timeCreated >= :start AND timeCreated < :end",
+            named_params! { ":start": start_ms, ":end": end_ms },
+            |row| -> Result<Guid> { Ok(row.get(0)?) },
+        )?;
+        for guid in &guids {
+            self.delete_in_transaction(guid.as_str(), now_ms)?;
+        }
+        tx.commit()?;
+        Ok(guids)
+    }
+
+    /// Like `delete`, but returns the state needed to undo the deletion with
+    /// `undo_delete`, or None if the login didn't exist.
+    pub fn delete_with_undo(&self, id: &str) -> Result<Option<DeletedLogin>> {
+        self.check_writable()?;
+        let tx = self.unchecked_transaction_imm()?;
+        let login = match self.get_by_id(id)? {
+            Some(login) => login,
+            None => return Ok(None),
+        };
+        let local_state = self.try_query_row(
+            "SELECT sync_status, local_modified FROM loginsL WHERE guid = :guid",
+            named_params! { ":guid": id },
+            |row| -> Result<(u8, Option<i64>)> { Ok((row.get(0)?, row.get(1)?)) },
+            true,
+        )?;
+        let mirror_overridden = self.try_query_row(
+            "SELECT is_overridden FROM loginsM WHERE guid = :guid",
+            named_params! { ":guid": id },
+            |row| -> Result<bool> { Ok(row.get(0)?) },
+            true,
+        )?;
+        let deleted_at = util::system_time_ms_i64(SystemTime::now());
+        self.delete_in_transaction(id, deleted_at)?;
+        tx.commit()?;
+        Ok(Some(DeletedLogin {
+            login,
+            deleted_at,
+            local_state,
+            mirror_overridden,
+        }))
+    }
+
+    /// Restores a login deleted by `delete_with_undo`, with its original GUID
+    /// and sync state. This fails if the deletion has since been synced, the
+    /// login has been changed again, or `UNDO_DELETE_GRACE_PERIOD` has
+    /// passed.
+    pub fn undo_delete(&self, deleted: DeletedLogin) -> Result<()> {
+        self.check_writable()?;
+        let guid = deleted.login.guid_str();
+        if deleted.is_expired() {
+            throw!(ErrorKind::CannotUndoDelete(guid.to_owned()));
+        }
+        let tx = self.unchecked_transaction()?;
+        let tombstone_args = named_params! {
+            ":guid": guid,
+            ":deleted_at": deleted.deleted_at,
+        };
+        let still_deleted: bool = self.query_row_named(
+            "SELECT EXISTS(
+                 SELECT 1 FROM loginsL
+                 WHERE guid = :guid AND is_deleted = 1 AND local_modified = :deleted_at
+             )",
+            tombstone_args,
+            |row| row.get(0),
+        )?;
+        if !still_deleted {
+            throw!(ErrorKind::CannotUndoDelete(guid.to_owned()));
+        }
+        match deleted.local_state {
+            None => {
+                self.execute_named(
+                    "DELETE FROM loginsL WHERE guid = :guid AND local_modified = :deleted_at",
+                    tombstone_args,
+                )?;
+            }
+            Some((sync_status, local_modified)) => {
+                let login = &deleted.login;
+                self.execute_named(
+                    "UPDATE loginsL
+                     SET hostname            = :hostname,
+                         httpRealm           = :http_realm,
+                         formSubmitURL       = :form_submit_url,
+                         usernameField       = :username_field,
+                         passwordField       = :password_field,
+                         timesUsed           = :times_used,
+                         username            = :username,
+                         password            = :password,
+                         notes               = :notes,
+                         unknown_fields      = :unknown_fields,
+                         timeCreated         = :time_created,
+                         timeLastUsed        = :time_last_used,
+                         timePasswordChanged = :time_password_changed,
+                         local_modified      = :local_modified,
+                         sync_status         = :sync_status,
+                         is_deleted          = 0
+                     WHERE guid = :guid",
+                    named_params! {
+                        ":hostname": login.hostname,
+                        ":http_realm": login.http_realm,
+                        ":form_submit_url": login.form_submit_url,
+                        ":username_field": login.username_field,
+                        ":password_field": login.password_field,
+                        ":times_used": login.times_used,
+                        ":username": self.encrypt_field(&login.username)?,
+                        ":password": self.encrypt_field(&login.password)?,
+                        ":notes": self.encrypt_optional_field(login.notes.as_deref())?,
+                        ":unknown_fields": login.unknown_fields,
+                        ":time_created": login.time_created,
+                        ":time_last_used": login.time_last_used,
+                        ":time_password_changed": login.time_password_changed,
+                        ":local_modified": local_modified,
+                        ":sync_status": sync_status,
+                        ":guid": guid,
+                    },
+                )?;
+            }
+        }
+        if let Some(overridden) = deleted.mirror_overridden {
+            self.execute_named(
+                "UPDATE loginsM SET is_overridden = :overridden WHERE guid = :guid",
+                named_params! { ":overridden": overridden, ":guid": guid },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // The guts of `delete`, which expects to be called in a transaction.
+    fn delete_in_transaction(&self, id: &str, now_ms: i64) -> Result<bool> {
+        let exists = self.exists(id)?;
+        if exists {
+            self.record_history(id, &["deleted"], ChangeSource::Local)?;
+        }
 
         // For IDs that have, mark is_deleted and clear sensitive fields
         self.execute_named(
@@ -924,7 +2388,9 @@ impl LoginDb {
                      is_deleted = 1,
                      password = '',
                      hostname = '',
-                     username = ''
+                     username = '',
+                     notes = NULL,
+                     unknown_fields = NULL
                  WHERE guid = :guid",
                 status_changed = SyncStatus::Changed as u8
             ),
@@ -932,7 +2398,7 @@ impl LoginDb {
         )?;
 
         // Mark the mirror as overridden
-        self.execute_named(
+        self.execute_named_cached(
             "UPDATE loginsM SET is_overridden = 1 WHERE guid = :guid",
             named_params! { ":guid": id },
         )?;
@@ -947,7 +2413,6 @@ impl LoginDb {
             WHERE guid = :guid",
             changed = SyncStatus::Changed as u8),
             named_params! { ":now_ms": now_ms, ":guid": id })?;
-        tx.commit()?;
         Ok(exists)
     }
 
@@ -960,10 +2425,11 @@ impl LoginDb {
     }
 
     fn ensure_local_overlay_exists(&self, guid: &str) -> Result<()> {
-        let already_have_local: bool = self.db.query_row_named(
+        let already_have_local: bool = self.query_row_and_then_named(
             "SELECT EXISTS(SELECT 1 FROM loginsL WHERE guid = :guid)",
             named_params! { ":guid": guid },
             |row| row.get(0),
+            true,
         )?;
 
         if already_have_local {
@@ -985,11 +2451,20 @@ impl LoginDb {
     }
 
     pub fn reset(&self, assoc: &StoreSyncAssociation) -> Result<()> {
-        log::info!("Executing reset on password store!");
+        self.check_writable()?;
         let tx = self.db.unchecked_transaction()?;
+        self.reset_in_transaction(assoc)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // The guts of `reset`, which expects to be called in a transaction.
+    pub(crate) fn reset_in_transaction(&self, assoc: &StoreSyncAssociation) -> Result<()> {
+        log::info!("Executing reset on password store!");
         self.execute_all(&[
             &*CLONE_ENTIRE_MIRROR_SQL,
             "DELETE FROM loginsM",
+            "DELETE FROM loginsStaging",
             &format!("UPDATE loginsL SET sync_status = {}", SyncStatus::New as u8),
         ])?;
         self.set_last_sync(ServerTimestamp(0))?;
@@ -1004,11 +2479,11 @@ impl LoginDb {
             }
         };
         self.delete_meta(schema::GLOBAL_STATE_META_KEY)?;
-        tx.commit()?;
         Ok(())
     }
 
     pub fn wipe(&self, scope: &SqlInterruptScope) -> Result<()> {
+        self.check_writable()?;
         let tx = self.unchecked_transaction()?;
         log::info!("Executing wipe on password store!");
         let now_ms = util::system_time_ms_i64(SystemTime::now());
@@ -1022,7 +2497,9 @@ impl LoginDb {
                     is_deleted = 1,
                     password = '',
                     hostname = '',
-                    username = ''
+                    username = '',
+                    notes = NULL,
+                    unknown_fields = NULL
                 WHERE is_deleted = 0",
                 changed = SyncStatus::Changed as u8
             ),
@@ -1046,30 +2523,375 @@ impl LoginDb {
         Ok(())
     }
 
+    /// Deletes all local logins and sync metadata, without writing tombstones,
+    /// so the logins stay on the server and on other devices. Unlike `wipe`,
+    /// this is intended for disconnecting from sync while keeping the data
+    /// there intact.
     pub fn wipe_local(&self) -> Result<()> {
+        self.check_writable()?;
         log::info!("Executing wipe_local on password store!");
         let tx = self.unchecked_transaction()?;
+        self.wipe_local_in_transaction()?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // The guts of `wipe_local`, which expects to be called in a transaction.
+    fn wipe_local_in_transaction(&self) -> Result<()> {
         self.execute_all(&[
             "DELETE FROM loginsL",
             "DELETE FROM loginsM",
-            "DELETE FROM loginsSyncMeta",
+            "DELETE FROM loginsHistory",
+            "DELETE FROM loginsStaging",
+            "DELETE FROM loginsAccessLog",
         ])?;
+        // The encryption state isn't sync metadata, and must outlive the
+        // logins so that new logins are read back correctly.
+        self.execute_named(
+            "DELETE FROM loginsSyncMeta WHERE key NOT IN (:fields_encrypted, :fields_canary)",
+            named_params! {
+                ":fields_encrypted": schema::FIELDS_ENCRYPTED_META_KEY,
+                ":fields_canary": schema::FIELDS_CANARY_META_KEY,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Change how long synced local tombstones are kept before they're pruned
+    /// after a successful sync. Defaults to `DEFAULT_TOMBSTONE_RETENTION`. See
+    /// `prune_tombstones`.
+    pub fn set_tombstone_retention(&mut self, retention: Duration) {
+        self.tombstone_retention = retention;
+    }
+
+    pub(crate) fn tombstone_retention(&self) -> Duration {
+        self.tombstone_retention
+    }
+
+    /// Starts recording which fields of each login are changed, and when, or
+    /// stops if `limit` is `None` (the default). At most `limit` entries are
+    /// kept; the oldest are removed first. Existing entries are kept when
+    /// recording is stopped, and removed by `wipe_local`.
+    pub fn set_history_limit(&mut self, limit: Option<usize>) {
+        self.history_limit = limit;
+    }
+
+    /// Returns the recorded changes to the login with the given GUID, oldest
+    /// first.
+    pub fn get_history(&self, id: &str) -> Result<Vec<HistoryEntry>> {
+        self.query_rows_and_then_named(
+            "SELECT guid, field, timestamp, source FROM loginsHistory
+             WHERE guid = :guid
+             ORDER BY timestamp, id",
+            named_params! { ":guid": id },
+            |row| -> Result<HistoryEntry> {
+                Ok(HistoryEntry {
+                    guid: row.get("guid")?,
+                    field: row.get("field")?,
+                    timestamp: row.get("timestamp")?,
+                    source: match row.get::<_, u8>("source")? {
+                        2 => ChangeSource::Sync,
+                        _ => ChangeSource::Local,
+                    },
+                })
+            },
+        )
+    }
+
+    // Records that `fields` of the login with the given GUID just changed, if
+    // history is enabled. Expects to be called in a transaction.
+    fn record_history(&self, guid: &str, fields: &[&str], source: ChangeSource) -> Result<()> {
+        let limit = match self.history_limit {
+            Some(limit) if !fields.is_empty() => limit,
+            _ => return Ok(()),
+        };
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        for field in fields {
+            self.execute_named_cached(
+                "INSERT INTO loginsHistory (guid, field, timestamp, source)
+                 VALUES (:guid, :field, :timestamp, :source)",
+                named_params! {
+                    ":guid": guid,
+                    ":field": field,
+                    ":timestamp": now_ms,
+                    ":source": source as u8,
+                },
+            )?;
+        }
+        self.execute_named_cached(
+            "DELETE FROM loginsHistory
+             WHERE id NOT IN (SELECT id FROM loginsHistory ORDER BY id DESC LIMIT :limit)",
+            named_params! { ":limit": limit as i64 },
+        )?;
+        Ok(())
+    }
+
+    /// Starts logging which logins are read, tagged with `caller` (which
+    /// identifies the API consumer, such as the app or its autofill service),
+    /// or stops if `caller` is `None` (the default). This should be set right
+    /// after opening the store. Reads of the same login are logged at most
+    /// once a minute, and entries are kept for `ACCESS_LOG_RETENTION`.
+    /// Existing entries are kept when logging is stopped, and removed by
+    /// `wipe_local`.
+    pub fn set_access_logging(&mut self, caller: Option<&str>) {
+        self.access_log_caller = caller.map(str::to_owned);
+        self.last_logged_access.borrow_mut().clear();
+    }
+
+    /// Records that `logins` were read, if access logging is enabled.
+    pub(crate) fn record_access(&self, logins: &[Login]) -> Result<()> {
+        let caller = match &self.access_log_caller {
+            Some(caller) if !self.read_only && !logins.is_empty() => caller,
+            _ => return Ok(()),
+        };
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let mut last_logged = self.last_logged_access.borrow_mut();
+        let unlogged = logins
+            .iter()
+            .filter(|login| match last_logged.get(login.guid_str()) {
+                Some(&logged) => now_ms - logged >= ACCESS_LOG_INTERVAL_MS,
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        if unlogged.is_empty() {
+            return Ok(());
+        }
+        let tx = self.unchecked_transaction()?;
+        for login in unlogged {
+            self.execute_named_cached(
+                "INSERT INTO loginsAccessLog (guid, caller, timestamp)
+                 VALUES (:guid, :caller, :timestamp)",
+                named_params! {
+                    ":guid": login.guid,
+                    ":caller": caller,
+                    ":timestamp": now_ms,
+                },
+            )?;
+            last_logged.insert(login.guid.to_string(), now_ms);
+        }
+        self.execute_named_cached(
+            "DELETE FROM loginsAccessLog WHERE timestamp < :cutoff",
+            named_params! {
+                ":cutoff": now_ms - ACCESS_LOG_RETENTION.as_millis() as i64,
+            },
+        )?;
         tx.commit()?;
         Ok(())
     }
 
+    /// Returns the logged reads made at or after `since_ms`, by `caller` if
+    /// it's given, oldest first.
+    pub fn get_access_log(
+        &self,
+        since_ms: i64,
+        caller: Option<&str>,
+    ) -> Result<Vec<AccessLogEntry>> {
+        self.query_rows_and_then_named(
+            "SELECT guid, caller, timestamp FROM loginsAccessLog
+             WHERE timestamp >= :since
+                AND (:caller IS NULL OR caller = :caller)
+             ORDER BY timestamp, id",
+            named_params! { ":since": since_ms, ":caller": caller },
+            |row| -> Result<AccessLogEntry> {
+                Ok(AccessLogEntry {
+                    guid: row.get("guid")?,
+                    caller: row.get("caller")?,
+                    timestamp: row.get("timestamp")?,
+                })
+            },
+        )
+    }
+
+    /// Returns the callers which read more than `max_logins` different logins
+    /// in the last `window`, such as an autofill service reading every login
+    /// instead of only those for the site being filled.
+    pub fn find_suspicious_access(
+        &self,
+        window: Duration,
+        max_logins: u64,
+    ) -> Result<Vec<SuspiciousAccess>> {
+        let since_ms = util::system_time_ms_i64(SystemTime::now() - window);
+        self.query_rows_and_then_named(
+            "SELECT caller,
+                    COUNT(DISTINCT guid) AS num_logins,
+                    MIN(timestamp) AS first_access,
+                    MAX(timestamp) AS last_access
+             FROM loginsAccessLog
+             WHERE timestamp >= :since
+             GROUP BY caller
+             HAVING num_logins > :max_logins
+             ORDER BY caller",
+            named_params! { ":since": since_ms, ":max_logins": max_logins as i64 },
+            |row| -> Result<SuspiciousAccess> {
+                Ok(SuspiciousAccess {
+                    caller: row.get("caller")?,
+                    num_logins: row.get::<_, i64>("num_logins")? as u64,
+                    first_access: row.get("first_access")?,
+                    last_access: row.get("last_access")?,
+                })
+            },
+        )
+    }
+
+    /// Change which collection logins are synced to, and how their fields are
+    /// named in the server's records. Defaults to `SyncConfig::default()`,
+    /// which is what Firefox uses. This should be set before the first sync.
+    pub fn set_sync_config(&mut self, config: SyncConfig) -> Result<()> {
+        config.check_valid()?;
+        self.sync_config = config;
+        Ok(())
+    }
+
+    pub(crate) fn sync_config(&self) -> &SyncConfig {
+        &self.sync_config
+    }
+
+    /// Change how conflicting changes are resolved when syncing. Defaults to
+    /// `MergePolicy::PreferNewer`. This should be set before the first sync.
+    pub fn set_merge_policy(&mut self, policy: MergePolicy) {
+        self.merge_policy = policy;
+    }
+
+    /// Deletes local tombstones older than `max_age` which have already been
+    /// synced, returning how many were deleted.
+    ///
+    /// `mark_as_synchronized` removes tombstones once they're uploaded, so
+    /// this only cleans up ones left behind by older versions, or by a sync
+    /// which was interrupted before it finished. Tombstones which haven't been
+    /// uploaded are never pruned, as the deletion would be lost, and neither
+    /// are ones which still hide a mirror record, as it would reappear.
+    pub fn prune_tombstones(&self, max_age: Duration) -> Result<usize> {
+        self.check_writable()?;
+        let cutoff = util::system_time_ms_i64(SystemTime::now() - max_age);
+        let pruned = self.execute_named_cached(
+            &format!(
+                "DELETE FROM loginsL
+                 WHERE is_deleted = 1
+                   AND sync_status = {synced}
+                   AND local_modified < :cutoff
+                   AND guid NOT IN (SELECT guid FROM loginsM)",
+                synced = SyncStatus::Synced as u8
+            ),
+            named_params! { ":cutoff": cutoff },
+        )?;
+        if pruned > 0 {
+            log::info!("Pruned {} expired tombstones", pruned);
+        }
+        Ok(pruned)
+    }
+
+    pub fn count_tombstones(&self) -> Result<u64> {
+        Ok(self.query_one::<i64>("SELECT COUNT(*) FROM loginsL WHERE is_deleted = 1")? as u64)
+    }
+
+    pub fn stats(&self) -> Result<LoginStats> {
+        // A login saved locally is used once when it's added, and synced
+        // logins from older clients may not record any uses.
+        let (num_logins, num_never_used) = self.query_row(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(timesUsed <= 1), 0) FROM ({})",
+                &*GET_ALL_SQL
+            ),
+            NO_PARAMS,
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )?;
+        let mut logins_per_base_domain = BTreeMap::new();
+        let hostnames = self.query_rows_and_then_named(
+            &format!(
+                "SELECT hostname, COUNT(*) FROM ({}) GROUP BY hostname",
+                &*GET_ALL_SQL
+            ),
+            &[],
+            |row| -> Result<(String, i64)> { Ok((row.get(0)?, row.get(1)?)) },
+        )?;
+        for (hostname, count) in hostnames {
+            let key = match Url::parse(&hostname) {
+                Ok(url) => match url.host() {
+                    Some(Host::Domain(domain)) => {
+                        psl::base_domain(domain).unwrap_or(domain).to_owned()
+                    }
+                    Some(host) => host.to_string(),
+                    None => hostname,
+                },
+                Err(_) => hostname,
+            };
+            *logins_per_base_domain.entry(key).or_default() += count as u64;
+        }
+        let page_count: i64 = self.query_one("PRAGMA page_count")?;
+        let page_size: i64 = self.query_one("PRAGMA page_size")?;
+        Ok(LoginStats {
+            num_logins: num_logins as u64,
+            num_tombstones: self.count_tombstones()?,
+            num_never_used: num_never_used as u64,
+            logins_per_base_domain,
+            db_size: (page_count * page_size) as u64,
+        })
+    }
+
+    /// Housekeeping intended to be run while the application is idle:
+    /// deletes synced local tombstones older than `tombstone_max_age` (see
+    /// `prune_tombstones`), reclaims free pages, rebuilds indices and checks
+    /// the integrity of the database.
+    pub fn run_maintenance(&self, tombstone_max_age: Duration) -> Result<MaintenanceReport> {
+        self.check_writable()?;
+        let start = Instant::now();
+        let tombstones_pruned = self.prune_tombstones(tombstone_max_age)? as u64;
+
+        // Incremental vacuuming only works once `auto_vacuum` is enabled, and
+        // enabling it on an existing database requires a full vacuum.
+        let auto_vacuum: i64 = self.query_one("PRAGMA auto_vacuum")?;
+        if auto_vacuum == 2 {
+            self.execute_batch("PRAGMA incremental_vacuum")?;
+        } else {
+            self.execute_all(&["PRAGMA auto_vacuum = INCREMENTAL", "VACUUM"])?;
+        }
+        self.execute_all(&["REINDEX", "PRAGMA optimize"])?;
+
+        let integrity_errors = self
+            .query_rows_and_then_named("PRAGMA integrity_check", &[], |row| {
+                row.get::<_, String>(0)
+            })?
+            .into_iter()
+            .filter(|msg| msg != "ok")
+            .collect::<Vec<_>>();
+        if !integrity_errors.is_empty() {
+            log::error!("Logins integrity check failed: {:?}", integrity_errors);
+        }
+        Ok(MaintenanceReport {
+            tombstones_pruned,
+            integrity_errors,
+            total_duration: start.elapsed().as_millis(),
+        })
+    }
+
     fn reconcile(
         &self,
         records: Vec<SyncLoginData>,
         server_now: ServerTimestamp,
         telem: &mut telemetry::EngineIncoming,
+        problems: &mut SyncProblems,
         scope: &SqlInterruptScope,
     ) -> Result<UpdatePlan> {
         let mut plan = UpdatePlan::default();
+        let local_only: HashSet<Guid> = self
+            .query_rows_and_then_named(
+                "SELECT guid FROM loginsL WHERE local_only = 1",
+                &[],
+                |row| row.get(0),
+            )?
+            .into_iter()
+            .collect();
+        // Local logins which have already been matched with an incoming
+        // record by content.
+        let mut claimed: HashSet<Guid> = HashSet::new();
 
         for mut record in records {
             scope.err_if_interrupted()?;
             log::debug!("Processing remote change {}", record.guid());
+            if local_only.contains(&record.guid) {
+                log::debug!("  Ignoring change to local-only record");
+                continue;
+            }
             let upstream = if let Some(inbound) = record.inbound.0.take() {
                 inbound
             } else {
@@ -1078,10 +2900,25 @@ impl LoginDb {
                 continue;
             };
             let upstream_time = record.inbound.1;
+            if upstream.check_valid().is_err() {
+                problems.invalid_incoming += 1;
+            }
             match (record.mirror.take(), record.local.take()) {
+                (Some(_mirror), Some(local)) if local.sync_status == SyncStatus::Synced => {
+                    log::debug!("  Dropping weak upload of usage changes, using remote");
+                    plan.plan_replace_weak_local(upstream, upstream_time);
+                    telem.applied(1);
+                }
                 (Some(mirror), Some(local)) => {
                     log::debug!("  Conflict between remote and local, Resolving with 3WM");
-                    plan.plan_three_way_merge(local, mirror, upstream, upstream_time, server_now);
+                    plan.plan_three_way_merge(
+                        local,
+                        mirror,
+                        upstream,
+                        upstream_time,
+                        server_now,
+                        self.merge_policy,
+                    );
                     telem.reconciled(1);
                 }
                 (Some(_mirror), None) => {
@@ -1091,17 +2928,28 @@ impl LoginDb {
                 }
                 (None, Some(local)) => {
                     log::debug!("  Conflicting record without shared parent, using newer");
-                    plan.plan_two_way_merge(&local.login, (upstream, upstream_time));
+                    problems.parentless_conflicts += 1;
+                    plan.plan_two_way_merge(
+                        &local.login,
+                        (upstream, upstream_time),
+                        self.merge_policy,
+                    );
                     telem.reconciled(1);
                 }
                 (None, None) => {
-                    if let Some(dupe) = self.find_dupe(&upstream)? {
+                    if let Some(dupe) = self.find_dupe(&upstream, &claimed)? {
                         log::debug!(
                             "  Incoming record {} was is a dupe of local record {}",
                             upstream.guid,
                             dupe.guid
                         );
-                        plan.plan_two_way_merge(&dupe, (upstream, upstream_time));
+                        problems.content_dupes += 1;
+                        claimed.insert(dupe.guid.clone());
+                        plan.plan_content_merge(
+                            &dupe,
+                            (upstream, upstream_time),
+                            self.merge_policy,
+                        );
                     } else {
                         log::debug!("  No dupe found, inserting into mirror");
                         plan.plan_mirror_insert(upstream, upstream_time, false);
@@ -1113,16 +2961,6 @@ impl LoginDb {
         Ok(plan)
     }
 
-    fn execute_plan(&self, plan: UpdatePlan, scope: &SqlInterruptScope) -> Result<()> {
-        // Because rusqlite want a mutable reference to create a transaction
-        // (as a way to save us from ourselves), we side-step that by creating
-        // it manually.
-        let tx = self.db.unchecked_transaction()?;
-        plan.execute(&tx, scope)?;
-        tx.commit()?;
-        Ok(())
-    }
-
     pub fn fetch_outgoing(
         &self,
         st: ServerTimestamp,
@@ -1132,9 +2970,11 @@ impl LoginDb {
         // process deletions first can; for us it doesn't matter.
         const TOMBSTONE_SORTINDEX: i32 = 5_000_000;
         const DEFAULT_SORTINDEX: i32 = 1;
-        let mut outgoing = OutgoingChangeset::new("passwords", st);
+        let mut outgoing = OutgoingChangeset::new(self.sync_config.collection_name.clone(), st);
         let mut stmt = self.db.prepare_cached(&format!(
-            "SELECT * FROM loginsL WHERE sync_status IS NOT {synced}",
+            "SELECT * FROM loginsL
+             WHERE (sync_status IS NOT {synced} OR weak_upload = 1)
+                 AND local_only = 0",
             synced = SyncStatus::Synced as u8
         ))?;
         let rows = stmt.query_and_then(NO_PARAMS, |row| {
@@ -1143,8 +2983,10 @@ impl LoginDb {
                 Payload::new_tombstone(row.get::<_, String>("guid")?)
                     .with_sortindex(TOMBSTONE_SORTINDEX)
             } else {
-                let login = Login::from_row(row)?;
-                Payload::from_record(login)?.with_sortindex(DEFAULT_SORTINDEX)
+                let login = self.login_from_row(row)?;
+                self.sync_config
+                    .to_server(Payload::from_record(login)?)
+                    .with_sortindex(DEFAULT_SORTINDEX)
             })
         })?;
         outgoing.changes = rows.collect::<Result<_>>()?;
@@ -1152,24 +2994,182 @@ impl LoginDb {
         Ok(outgoing)
     }
 
-    fn do_apply_incoming(
+    #[cfg(test)]
+    pub(crate) fn do_apply_incoming(
+        &self,
+        inbound: IncomingChangeset,
+        telem: &mut telemetry::Engine,
+        scope: &SqlInterruptScope,
+    ) -> Result<OutgoingChangeset> {
+        self.apply_incoming_recording(inbound, telem, scope, &mut Vec::new())
+    }
+
+    // Like `do_apply_incoming`, but also adds the GUIDs of the incoming
+    // records to `applied` as each chunk of them is committed, so that they're
+    // there even if a later chunk fails.
+    pub(crate) fn apply_incoming_recording(
         &self,
         inbound: IncomingChangeset,
         telem: &mut telemetry::Engine,
         scope: &SqlInterruptScope,
+        applied: &mut Vec<Guid>,
     ) -> Result<OutgoingChangeset> {
+        self.check_writable()?;
+        self.stage_incoming(&inbound.changes)?;
+        let already_applied = applied.len();
+        self.apply_staged(inbound.timestamp, telem, scope, applied)?;
+        let outgoing = self.fetch_outgoing(inbound.timestamp, scope)?;
+        log::info!(
+            "Applied {} incoming logins, {} outgoing",
+            applied.len() - already_applied,
+            outgoing.changes.len()
+        );
+        Ok(outgoing)
+    }
+
+    // Applies everything in `loginsStaging`, a chunk at a time, returning how
+    // many records were merged with local changes. The telemetry for the
+    // chunks which were applied is recorded, and their GUIDs are added to
+    // `applied`, even if a later one fails.
+    pub(crate) fn apply_staged(
+        &self,
+        server_now: ServerTimestamp,
+        telem: &mut telemetry::Engine,
+        scope: &SqlInterruptScope,
+        applied: &mut Vec<Guid>,
+    ) -> Result<u32> {
         let mut incoming_telemetry = telemetry::EngineIncoming::new();
-        let data = self.fetch_login_data(&inbound.changes, &mut incoming_telemetry, scope)?;
-        let plan = {
-            let result = self.reconcile(data, inbound.timestamp, &mut incoming_telemetry, scope);
-            telem.incoming(incoming_telemetry);
-            result
-        }?;
-        self.execute_plan(plan, scope)?;
-        Ok(self.fetch_outgoing(inbound.timestamp, scope)?)
+        let mut problems = SyncProblems::default();
+        let mut timings = IncomingTimings::default();
+        let result = loop {
+            match self.apply_staged_chunk(
+                server_now,
+                &mut incoming_telemetry,
+                &mut problems,
+                &mut timings,
+                scope,
+                applied,
+            ) {
+                Ok(true) => continue,
+                Ok(false) => break Ok(incoming_telemetry.get_reconciled()),
+                Err(e) => break Err(e),
+            }
+        };
+        telem.incoming(incoming_telemetry);
+        telem.validation(problems.into_validation());
+        log::info!(
+            "Applied staged logins in {} chunks (fetch: {}ms, reconcile: {}ms, apply: {}ms)",
+            timings.chunks,
+            timings.fetch.as_millis(),
+            timings.reconcile.as_millis(),
+            timings.apply.as_millis(),
+        );
+        result
+    }
+
+    // Writes the incoming records to `loginsStaging`, replacing any left over
+    // from an earlier sync which didn't finish, or staged from an earlier page
+    // of a download which started again.
+    pub(crate) fn stage_incoming(&self, records: &[(Payload, ServerTimestamp)]) -> Result<()> {
+        let mut seen_ids: HashSet<&Guid> = HashSet::with_capacity(records.len());
+        let tx = self.unchecked_transaction()?;
+        for (payload, server_modified) in records {
+            if !seen_ids.insert(&payload.id) {
+                throw!(ErrorKind::DuplicateGuid(payload.id.to_string()))
+            }
+            self.execute_named_cached(
+                "INSERT OR REPLACE INTO loginsStaging (guid, payload, server_modified)
+                 VALUES (:guid, :payload, :server_modified)",
+                named_params! {
+                    ":guid": payload.id,
+                    ":payload": payload.clone().into_json_string(),
+                    ":server_modified": server_modified.as_millis() as i64,
+                },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Applies up to `INCOMING_CHUNK_SIZE` staged records in a single
+    // transaction, so that readers aren't blocked for the whole sync, and adds
+    // their GUIDs to `applied` once it's committed. Returns false once there's
+    // nothing left to apply.
+    fn apply_staged_chunk(
+        &self,
+        server_now: ServerTimestamp,
+        telem: &mut telemetry::EngineIncoming,
+        problems: &mut SyncProblems,
+        timings: &mut IncomingTimings,
+        scope: &SqlInterruptScope,
+        applied: &mut Vec<Guid>,
+    ) -> Result<bool> {
+        scope.err_if_interrupted()?;
+        let start = Instant::now();
+        let tx = self.unchecked_transaction()?;
+        let records = self.query_rows_and_then_named(
+            "SELECT payload, server_modified FROM loginsStaging
+             ORDER BY rowid
+             LIMIT :limit",
+            named_params! { ":limit": INCOMING_CHUNK_SIZE as i64 },
+            |row| -> Result<(Payload, ServerTimestamp)> {
+                let payload: String = row.get(0)?;
+                Ok((
+                    Payload::from_json(serde_json::from_str(&payload)?)?,
+                    ServerTimestamp(row.get(1)?),
+                ))
+            },
+        )?;
+        if records.is_empty() {
+            return Ok(false);
+        }
+        let data = self.fetch_login_data(&records, telem, scope)?;
+        let fetched = Instant::now();
+        let plan = self.reconcile(data, server_now, telem, problems, scope)?;
+        let reconciled = Instant::now();
+        // Only look up what the records were before the sync if we need to
+        // record what changed.
+        let before = if self.history_limit.is_some() {
+            records
+                .iter()
+                .map(|(payload, _)| Ok((payload.id.clone(), self.get_by_id(&payload.id)?)))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            vec![]
+        };
+        plan.execute(self, scope)?;
+        for (guid, before) in before {
+            let after = self.get_by_id(&guid)?;
+            self.record_history(
+                &guid,
+                &changed_fields(before.as_ref(), after.as_ref()),
+                ChangeSource::Sync,
+            )?;
+        }
+        sql_support::each_chunk_mapped(
+            &records,
+            |r| r.0.id.as_str(),
+            |chunk, _| -> Result<()> {
+                self.execute(
+                    &format!(
+                        "DELETE FROM loginsStaging WHERE guid IN ({vars})",
+                        vars = sql_support::repeat_sql_vars(chunk.len())
+                    ),
+                    chunk,
+                )?;
+                Ok(())
+            },
+        )?;
+        tx.commit()?;
+        applied.extend(records.into_iter().map(|(payload, _)| payload.id));
+        timings.fetch += fetched.duration_since(start);
+        timings.reconcile += reconciled.duration_since(fetched);
+        timings.apply += reconciled.elapsed();
+        timings.chunks += 1;
+        Ok(true)
     }
 
-    fn put_meta(&self, key: &str, value: &dyn ToSql) -> Result<()> {
+    pub(crate) fn put_meta(&self, key: &str, value: &dyn ToSql) -> Result<()> {
         self.execute_named_cached(
             "REPLACE INTO loginsSyncMeta (key, value) VALUES (:key, :value)",
             named_params! { ":key": key, ":value": value },
@@ -1177,7 +3177,7 @@ impl LoginDb {
         Ok(())
     }
 
-    fn get_meta<T: FromSql>(&self, key: &str) -> Result<Option<T>> {
+    pub(crate) fn get_meta<T: FromSql>(&self, key: &str) -> Result<Option<T>> {
         Ok(self.try_query_row(
             "SELECT value FROM loginsSyncMeta WHERE key = :key",
             named_params! { ":key": key },
@@ -1194,18 +3194,61 @@ impl LoginDb {
         Ok(())
     }
 
-    fn set_last_sync(&self, last_sync: ServerTimestamp) -> Result<()> {
+    pub(crate) fn set_last_sync(&self, last_sync: ServerTimestamp) -> Result<()> {
         log::debug!("Updating last sync to {}", last_sync);
         let last_sync_millis = last_sync.as_millis() as i64;
         self.put_meta(schema::LAST_SYNC_META_KEY, &last_sync_millis)
     }
 
-    fn get_last_sync(&self) -> Result<Option<ServerTimestamp>> {
-        let millis = self.get_meta::<i64>(schema::LAST_SYNC_META_KEY)?.unwrap();
-        Ok(Some(ServerTimestamp(millis)))
+    pub(crate) fn get_last_sync(&self) -> Result<Option<ServerTimestamp>> {
+        Ok(self
+            .get_meta::<i64>(schema::LAST_SYNC_META_KEY)?
+            .map(ServerTimestamp))
+    }
+
+    /// Records that a sync was attempted but couldn't reach the server, so
+    /// that `needs_sync_before` knows one is still owed. The time of the
+    /// earliest such attempt is kept until a sync succeeds.
+    pub fn record_pending_sync(&self) -> Result<()> {
+        self.check_writable()?;
+        if self
+            .get_meta::<i64>(schema::PENDING_SYNC_META_KEY)?
+            .is_none()
+        {
+            let now_ms = util::system_time_ms_i64(SystemTime::now());
+            self.put_meta(schema::PENDING_SYNC_META_KEY, &now_ms)?;
+        }
+        Ok(())
+    }
+
+    /// Whether a sync is needed to upload the local changes made at or before
+    /// `before`, or to make up for a sync which was attempted by then but
+    /// failed to reach the server.
+    pub fn needs_sync_before(&self, before: SystemTime) -> Result<bool> {
+        let before_ms = util::system_time_ms_i64(before);
+        if let Some(pending_ms) = self.get_meta::<i64>(schema::PENDING_SYNC_META_KEY)? {
+            if pending_ms <= before_ms {
+                return Ok(true);
+            }
+        }
+        Ok(self.query_row_and_then_named(
+            &format!(
+                "SELECT EXISTS(
+                     SELECT 1 FROM loginsL
+                     WHERE local_only = 0
+                         AND (sync_status IS NOT {synced} OR weak_upload = 1)
+                         AND local_modified <= :before
+                 )",
+                synced = SyncStatus::Synced as u8
+            ),
+            named_params! { ":before": before_ms },
+            |row| row.get(0),
+            false,
+        )?)
     }
 
     pub fn set_global_state(&self, state: &Option<String>) -> Result<()> {
+        self.check_writable()?;
         let to_write = match state {
             Some(ref s) => s,
             None => "",
@@ -1242,6 +3285,9 @@ impl LoginDb {
 pub struct LoginStore<'a> {
     pub db: &'a LoginDb,
     pub scope: sql_support::SqlInterruptScope,
+    // The GUIDs of incoming records we've applied, so the engine can tell
+    // observers about them once the sync is done.
+    applied_guids: RefCell<Vec<Guid>>,
 }
 
 impl<'a> LoginStore<'a> {
@@ -1249,13 +3295,27 @@ impl<'a> LoginStore<'a> {
         Self {
             db,
             scope: db.begin_interrupt_scope(),
+            applied_guids: RefCell::default(),
         }
     }
+
+    pub(crate) fn take_applied_guids(&self) -> Vec<Guid> {
+        self.applied_guids.replace(Vec::new())
+    }
 }
 
 impl<'a> Store for LoginStore<'a> {
     fn collection_name(&self) -> std::borrow::Cow<'static, str> {
-        "passwords".into()
+        self.db.sync_config.collection_name.clone().into()
+    }
+
+    fn stage_incoming(
+        &self,
+        page: IncomingChangeset,
+    ) -> result::Result<Option<IncomingChangeset>, failure::Error> {
+        self.db.check_writable()?;
+        self.db.stage_incoming(&page.changes)?;
+        Ok(None)
     }
 
     fn apply_incoming(
@@ -1265,7 +3325,12 @@ impl<'a> Store for LoginStore<'a> {
     ) -> result::Result<OutgoingChangeset, failure::Error> {
         assert_eq!(inbound.len(), 1, "logins only requests one item");
         let inbound = inbound.into_iter().next().unwrap();
-        Ok(self.db.do_apply_incoming(inbound, telem, &self.scope)?)
+        Ok(self.db.apply_incoming_recording(
+            inbound,
+            telem,
+            &self.scope,
+            &mut self.applied_guids.borrow_mut(),
+        )?)
     }
 
     fn sync_finished(
@@ -1278,6 +3343,7 @@ impl<'a> Store for LoginStore<'a> {
             new_timestamp,
             &self.scope,
         )?;
+        self.db.prune_tombstones(self.db.tombstone_retention)?;
         Ok(())
     }
 
@@ -1289,7 +3355,11 @@ impl<'a> Store for LoginStore<'a> {
         Ok(if since == server_timestamp {
             vec![]
         } else {
-            vec![CollectionRequest::new("passwords").full().newer_than(since)]
+            vec![
+                CollectionRequest::new(self.db.sync_config.collection_name.clone())
+                    .full()
+                    .newer_than(since),
+            ]
         })
     }
 
@@ -1321,6 +3391,16 @@ lazy_static! {
          SELECT {common_cols} FROM loginsM WHERE is_overridden = 0",
         common_cols = schema::COMMON_COLS,
     );
+    static ref GET_BY_REV_HOST_RANGE_SQL: String = format!(
+        "SELECT {common_cols} FROM loginsL
+         WHERE is_deleted = 0
+           AND rev_host >= :rev_host AND rev_host < :rev_host_end
+         UNION ALL
+         SELECT {common_cols} FROM loginsM
+         WHERE is_overridden = 0
+           AND rev_host >= :rev_host AND rev_host < :rev_host_end",
+        common_cols = schema::COMMON_COLS,
+    );
     static ref GET_BY_GUID_SQL: String = format!(
         "SELECT {common_cols}
          FROM loginsL
@@ -1351,6 +3431,8 @@ lazy_static! {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::login::UnknownFields;
+    use crate::query::LoginSortOrder;
     #[test]
     fn test_bad_record() {
         let db = LoginDb::open_in_memory(Some("testing")).unwrap();
@@ -1475,100 +3557,1908 @@ mod tests {
             },
         ];
 
-        for tc in &test_cases {
-            let login_check = db.check_valid_with_no_dupes(&tc.login);
-            if tc.should_err {
-                assert!(&login_check.is_err());
-                assert_eq!(&login_check.unwrap_err().to_string(), tc.expected_err)
-            } else {
-                assert!(&login_check.is_ok())
-            }
-        }
+        for tc in &test_cases {
+            let login_check = db.check_valid_with_no_dupes(&tc.login);
+            if tc.should_err {
+                assert!(&login_check.is_err());
+                assert_eq!(&login_check.unwrap_err().to_string(), tc.expected_err)
+            } else {
+                assert!(&login_check.is_ok())
+            }
+        }
+    }
+
+    #[test]
+    fn test_unicode_submit() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.add(Login {
+            guid: "dummy_000001".into(),
+            form_submit_url: Some("http://😍.com".into()),
+            hostname: "http://😍.com".into(),
+            http_realm: None,
+            username: "😍".into(),
+            username_field: "😍".into(),
+            password: "😍".into(),
+            password_field: "😍".into(),
+            ..Login::default()
+        })
+        .unwrap();
+        let fetched = db
+            .get_by_id("dummy_000001")
+            .expect("should work")
+            .expect("should get a record");
+        assert_eq!(fetched.hostname, "http://xn--r28h.com");
+        assert_eq!(fetched.form_submit_url.unwrap(), "http://xn--r28h.com");
+        assert_eq!(fetched.username, "😍");
+        assert_eq!(fetched.username_field, "😍");
+        assert_eq!(fetched.password, "😍");
+        assert_eq!(fetched.password_field, "😍");
+    }
+
+    #[test]
+    fn test_unicode_realm() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.add(Login {
+            guid: "dummy_000001".into(),
+            form_submit_url: None,
+            hostname: "http://😍.com".into(),
+            http_realm: Some("😍😍".into()),
+            username: "😍".into(),
+            password: "😍".into(),
+            ..Login::default()
+        })
+        .unwrap();
+        let fetched = db
+            .get_by_id("dummy_000001")
+            .expect("should work")
+            .expect("should get a record");
+        assert_eq!(fetched.hostname, "http://xn--r28h.com");
+        assert_eq!(fetched.http_realm.unwrap(), "😍😍");
+    }
+
+    fn check_matches(db: &LoginDb, query: &str, expected: &[&str]) {
+        let mut results = db
+            .get_by_base_domain(query, &db.begin_interrupt_scope())
+            .unwrap()
+            .into_iter()
+            .map(|l| l.hostname)
+            .collect::<Vec<String>>();
+        results.sort();
+        let mut sorted = expected.to_owned();
+        sorted.sort();
+        assert_eq!(sorted, results);
+    }
+
+    fn check_good_bad(
+        good: Vec<&str>,
+        bad: Vec<&str>,
+        good_queries: Vec<&str>,
+        zero_queries: Vec<&str>,
+    ) {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        for h in good.iter().chain(bad.iter()) {
+            db.add(Login {
+                hostname: (*h).into(),
+                http_realm: Some((*h).into()),
+                password: "test".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        }
+        for query in good_queries {
+            check_matches(&db, query, &good);
+        }
+        for query in zero_queries {
+            check_matches(&db, query, &[]);
+        }
+    }
+
+    #[test]
+    fn test_get_interrupted() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.add(Login {
+            hostname: "https://www.example.com".into(),
+            http_realm: Some("https://www.example.com".into()),
+            password: "test".into(),
+            ..Login::default()
+        })
+        .unwrap();
+        let scope = db.begin_interrupt_scope();
+        assert_eq!(db.get_all(&scope).unwrap().len(), 1);
+        db.new_interrupt_handle().interrupt();
+        match db.get_all(&scope).unwrap_err().kind() {
+            ErrorKind::Interrupted(_) => {}
+            e => panic!("Expected interruption, got {:?}", e),
+        }
+        match db
+            .get_by_base_domain("example.com", &scope)
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::Interrupted(_) => {}
+            e => panic!("Expected interruption, got {:?}", e),
+        }
+        // A new scope isn't affected by the earlier interruption.
+        let scope = db.begin_interrupt_scope();
+        assert_eq!(db.get_all(&scope).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_query() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        for (i, host) in ["https://c.com", "https://a.com", "https://b.com"]
+            .iter()
+            .enumerate()
+        {
+            db.add(Login {
+                hostname: (*host).into(),
+                http_realm: Some("realm".into()),
+                password: "test".into(),
+                times_used: (i + 1) as i64,
+                time_last_used: (10 - i) as i64,
+                ..Login::default()
+            })
+            .unwrap();
+        }
+        let scope = db.begin_interrupt_scope();
+        let hosts = |q: LoginQuery| {
+            db.query(&q, &scope)
+                .unwrap()
+                .into_iter()
+                .map(|l| l.hostname)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            hosts(LoginQuery::default()),
+            vec!["https://a.com", "https://b.com", "https://c.com"]
+        );
+        assert_eq!(
+            hosts(LoginQuery {
+                sort_order: LoginSortOrder::TimesUsed,
+                ..LoginQuery::default()
+            }),
+            vec!["https://b.com", "https://a.com", "https://c.com"]
+        );
+        assert_eq!(
+            hosts(LoginQuery {
+                sort_order: LoginSortOrder::TimeLastUsed,
+                limit: Some(2),
+                offset: 0,
+            }),
+            vec!["https://c.com", "https://a.com"]
+        );
+        assert_eq!(
+            hosts(LoginQuery {
+                limit: Some(2),
+                offset: 2,
+                ..LoginQuery::default()
+            }),
+            vec!["https://c.com"]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        for (host, username, realm) in &[
+            ("https://www.example.com", "SomeUser", None),
+            ("https://bücher.example", "reader", None),
+            ("https://other.com", "", Some("Example Realm")),
+        ] {
+            let realm = realm.map(str::to_owned);
+            db.add(Login {
+                hostname: (*host).into(),
+                username: (*username).into(),
+                form_submit_url: if realm.is_none() {
+                    Some((*host).into())
+                } else {
+                    None
+                },
+                http_realm: realm,
+                password: "test".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        }
+        let scope = db.begin_interrupt_scope();
+        let search = |q: &str| {
+            let mut hosts = db
+                .search(q, &scope)
+                .unwrap()
+                .into_iter()
+                .map(|l| l.hostname)
+                .collect::<Vec<_>>();
+            hosts.sort();
+            hosts
+        };
+        assert_eq!(search("EXAMPLE.COM"), vec!["https://www.example.com"]);
+        assert_eq!(search("someuser"), vec!["https://www.example.com"]);
+        assert_eq!(search("realm"), vec!["https://other.com"]);
+        assert_eq!(search("bücher"), vec!["https://xn--bcher-kva.example"]);
+        assert_eq!(search("xn--bcher"), vec!["https://xn--bcher-kva.example"]);
+        assert_eq!(
+            search("example"),
+            vec![
+                "https://other.com",
+                "https://www.example.com",
+                "https://xn--bcher-kva.example"
+            ]
+        );
+        assert_eq!(
+            search("https://www.example.com:443/login"),
+            vec!["https://www.example.com"]
+        );
+        assert!(search("  ").is_empty());
+        assert!(search("nothing").is_empty());
+    }
+
+    // Reverses fields and adds a prefix, so ciphertext is easy to spot in the
+    // raw tables. Fails to decrypt anything without the expected prefix.
+    struct TestEncDec(&'static str);
+
+    impl EncryptorDecryptor for TestEncDec {
+        fn encrypt(&self, cleartext: &str) -> Result<String, failure::Error> {
+            Ok(format!(
+                "{}{}",
+                self.0,
+                cleartext.chars().rev().collect::<String>()
+            ))
+        }
+        fn decrypt(&self, ciphertext: &str) -> Result<String, failure::Error> {
+            if ciphertext.starts_with(self.0) {
+                Ok(ciphertext[self.0.len()..].chars().rev().collect())
+            } else {
+                Err(failure::err_msg("wrong key"))
+            }
+        }
+    }
+
+    fn raw_fields(db: &LoginDb, guid: &str) -> (String, String) {
+        db.query_row_named(
+            "SELECT username, password FROM loginsL WHERE guid = :guid",
+            named_params! { ":guid": guid },
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_field_encryption() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let make_login = |username: &str, password: &str| Login {
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            username: username.into(),
+            password: password.into(),
+            ..Login::default()
+        };
+        let before = db.add(make_login("before", "hunter2")).unwrap();
+        let guid_before = before.guid_str().to_owned();
+        assert_eq!(
+            raw_fields(&db, &guid_before),
+            ("before".into(), "hunter2".into())
+        );
+
+        // Providing an EncryptorDecryptor encrypts existing logins.
+        db.set_encryptor_decryptor(Box::new(TestEncDec("k1:")))
+            .unwrap();
+        assert_eq!(
+            raw_fields(&db, &guid_before),
+            ("k1:erofeb".into(), "k1:2retnuh".into())
+        );
+        let after = db.add(make_login("", "secret")).unwrap();
+        let guid_after = after.guid_str().to_owned();
+        // Empty values are left alone.
+        assert_eq!(
+            raw_fields(&db, &guid_after),
+            ("".into(), "k1:terces".into())
+        );
+        let fetched = db.get_by_id(&guid_before).unwrap().unwrap();
+        assert_eq!(fetched.username, "before");
+        assert_eq!(fetched.password, "hunter2");
+
+        // Dupe checks still work against encrypted usernames.
+        assert!(db.add(make_login("before", "other")).is_err());
+
+        // Changing the password is detected even though it's encrypted.
+        let mut updated = fetched;
+        updated.password = "hunter3".into();
+        db.update(updated).unwrap();
+        let fetched = db.get_by_id(&guid_before).unwrap().unwrap();
+        assert_eq!(fetched.password, "hunter3");
+        assert!(fetched.time_password_changed > before.time_password_changed);
+
+        assert!(db.find_undecryptable_records().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rekey_fields() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.set_encryptor_decryptor(Box::new(TestEncDec("k1:")))
+            .unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        db.rekey_fields(Box::new(TestEncDec("k2:"))).unwrap();
+        assert_eq!(
+            raw_fields(&db, login.guid_str()),
+            ("k2:resu".into(), "k2:2retnuh".into())
+        );
+        let fetched = db.get_by_id(login.guid_str()).unwrap().unwrap();
+        assert_eq!(fetched.password, "hunter2");
+
+        // A failed rekey leaves everything as it was.
+        db.set_encryptor_decryptor(Box::new(TestEncDec("wrong:")))
+            .unwrap();
+        assert!(db.rekey_fields(Box::new(TestEncDec("k3:"))).is_err());
+        assert_eq!(
+            raw_fields(&db, login.guid_str()),
+            ("k2:resu".into(), "k2:2retnuh".into())
+        );
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                notes: Some("PIN 1234\nsecurity question: blue".into()),
+                ..Login::default()
+            })
+            .unwrap();
+        let guid = login.guid_str().to_owned();
+        let fetched = db.get_by_id(&guid).unwrap().unwrap();
+        assert_eq!(fetched.notes, login.notes);
+
+        let raw_notes = |db: &LoginDb| -> Option<String> {
+            db.query_row_named(
+                "SELECT notes FROM loginsL WHERE guid = :guid",
+                named_params! { ":guid": guid },
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        db.set_encryptor_decryptor(Box::new(TestEncDec("k1:")))
+            .unwrap();
+        assert_eq!(
+            raw_notes(&db).as_deref(),
+            Some("k1:eulb :noitseuq ytiruces\n4321 NIP")
+        );
+        let fetched = db.get_by_id(&guid).unwrap().unwrap();
+        assert_eq!(fetched.notes, login.notes);
+
+        let mut updated = fetched;
+        updated.notes = None;
+        db.update(updated).unwrap();
+        assert_eq!(raw_notes(&db), None);
+        assert_eq!(db.get_by_id(&guid).unwrap().unwrap().notes, None);
+
+        // Notes are sent to the server as part of the record.
+        let payload = Payload::from_record(login).unwrap();
+        assert_eq!(payload.data["notes"], "PIN 1234\nsecurity question: blue");
+    }
+
+    #[test]
+    fn test_chunked_incoming() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let make_payload = |i: usize| {
+            Payload::from_record(Login {
+                guid: format!("dummy_{:06}", i).into(),
+                hostname: format!("https://{}.example.com", i),
+                form_submit_url: Some(format!("https://{}.example.com", i)),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap()
+        };
+        // Simulate a crash part way through applying an earlier sync.
+        db.stage_incoming(&[(make_payload(0), ServerTimestamp(1000))])
+            .unwrap();
+
+        let num_records = INCOMING_CHUNK_SIZE * 2 + 1;
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+        for i in 1..=num_records {
+            inbound
+                .changes
+                .push((make_payload(i), ServerTimestamp(10000)));
+        }
+        db.do_apply_incoming(inbound, &mut telemetry::Engine::new("passwords"), &scope)
+            .unwrap();
+        assert_eq!(
+            db.get_all(&scope).unwrap().len(),
+            num_records + 1,
+            "Left over records should be applied too"
+        );
+        let num_staged: i64 = db.query_one("SELECT COUNT(*) FROM loginsStaging").unwrap();
+        assert_eq!(num_staged, 0);
+
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(20000));
+        inbound
+            .changes
+            .push((make_payload(1), ServerTimestamp(20000)));
+        inbound
+            .changes
+            .push((make_payload(1), ServerTimestamp(20000)));
+        match db
+            .do_apply_incoming(inbound, &mut telemetry::Engine::new("passwords"), &scope)
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::DuplicateGuid(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_chunked_incoming_failure() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let records = (0..INCOMING_CHUNK_SIZE)
+            .map(|i| {
+                let payload = Payload::from_record(Login {
+                    guid: format!("dummy_{:06}", i).into(),
+                    hostname: format!("https://{}.example.com", i),
+                    form_submit_url: Some(format!("https://{}.example.com", i)),
+                    username: "user".into(),
+                    password: "hunter2".into(),
+                    ..Login::default()
+                })
+                .unwrap();
+                (payload, ServerTimestamp(10000))
+            })
+            .collect::<Vec<_>>();
+        db.stage_incoming(&records).unwrap();
+        // A record in the second chunk which can't be read.
+        db.execute_all(
+            &["INSERT INTO loginsStaging (guid, payload, server_modified)
+             VALUES ('bad_00000001', 'not json', 10000)"],
+        )
+        .unwrap();
+
+        let mut applied = Vec::new();
+        db.apply_staged(
+            ServerTimestamp(10000),
+            &mut telemetry::Engine::new("passwords"),
+            &scope,
+            &mut applied,
+        )
+        .unwrap_err();
+        // The first chunk was committed, so its records are reported.
+        assert_eq!(applied.len(), INCOMING_CHUNK_SIZE);
+        assert_eq!(applied[0], "dummy_000000");
+        assert_eq!(db.get_all(&scope).unwrap().len(), INCOMING_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_staged_pages() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let store = LoginStore::new(&db);
+        let page = |guid: &str, password: &str| {
+            let mut page = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+            let login = Login {
+                guid: guid.into(),
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: guid.into(),
+                password: password.into(),
+                ..Login::default()
+            };
+            page.changes
+                .push((Payload::from_record(login).unwrap(), ServerTimestamp(10000)));
+            page
+        };
+
+        // The first page is staged again, with a newer password, when the
+        // download starts again.
+        assert!(store
+            .stage_incoming(page("dummy_000001", "old"))
+            .unwrap()
+            .is_none());
+        assert!(store
+            .stage_incoming(page("dummy_000001", "new"))
+            .unwrap()
+            .is_none());
+        assert!(store
+            .stage_incoming(page("dummy_000002", "new"))
+            .unwrap()
+            .is_none());
+
+        let inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+        store
+            .apply_incoming(vec![inbound], &mut telemetry::Engine::new("passwords"))
+            .unwrap();
+        assert_eq!(store.take_applied_guids().len(), 2);
+        let logins = db.get_all(&store.scope).unwrap();
+        assert_eq!(logins.len(), 2);
+        assert!(logins.iter().all(|login| login.password == "new"));
+    }
+
+    #[test]
+    fn test_reconcile_by_content() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let login = |guid: &str, host: &str, password: &str, changed: i64| Login {
+            guid: guid.into(),
+            hostname: host.into(),
+            form_submit_url: Some(host.into()),
+            username: "user".into(),
+            password: password.into(),
+            time_password_changed: changed,
+            ..Login::default()
+        };
+        let apply = |logins: Vec<Login>| {
+            let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+            for l in logins {
+                inbound
+                    .changes
+                    .push((Payload::from_record(l).unwrap(), ServerTimestamp(10000)));
+            }
+            db.do_apply_incoming(inbound, &mut telemetry::Engine::new("passwords"), &scope)
+                .unwrap()
+                .changes
+                .into_iter()
+                .map(|p| p.id.into_string())
+                .collect::<Vec<_>>()
+        };
+
+        // A synced login isn't matched with an incoming record by content.
+        db.add(login("synced_0001", "https://a.example.com", "a", 1000))
+            .unwrap();
+        db.mark_as_synchronized(&["synced_0001"], ServerTimestamp(5000), &scope)
+            .unwrap();
+        apply(vec![login(
+            "remote_0001",
+            "https://a.example.com",
+            "b",
+            2000,
+        )]);
+        assert!(db.get_by_id("synced_0001").unwrap().is_some());
+        assert!(db.get_by_id("remote_0001").unwrap().is_some());
+
+        // After a reset, though, everything is.
+        db.reset(&StoreSyncAssociation::Disconnected).unwrap();
+        db.add(login("local_00001", "https://b.example.com", "local", 1000))
+            .unwrap();
+        db.add(login("local_00002", "https://c.example.com", "local", 3000))
+            .unwrap();
+        let outgoing = apply(vec![
+            // The same GUID, so it's not a content match.
+            login("synced_0001", "https://a.example.com", "a", 1000),
+            // Newer than the local login, so it replaces it.
+            login("remote_0003", "https://b.example.com", "remote", 2000),
+            // Older, so the local login wins, and takes on its GUID.
+            login("remote_0004", "https://c.example.com", "remote", 2000),
+            // A local login is only matched with one incoming record.
+            login("remote_0005", "https://b.example.com", "other", 2000),
+        ]);
+        assert!(db.get_by_id("local_00001").unwrap().is_none());
+        assert!(db.get_by_id("local_00002").unwrap().is_none());
+        assert_eq!(
+            db.get_by_id("remote_0003").unwrap().unwrap().password,
+            "remote"
+        );
+        assert_eq!(
+            db.get_by_id("remote_0004").unwrap().unwrap().password,
+            "local"
+        );
+        assert!(db.get_by_id("remote_0005").unwrap().is_some());
+        let mut outgoing = outgoing;
+        outgoing.sort();
+        assert_eq!(outgoing, vec!["remote_0001", "remote_0004"]);
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let guid = "dummy_000001";
+        let incoming = |password: &str, extra: serde_json::Value, ts: i64| {
+            let mut payload = Payload::from_record(Login {
+                guid: guid.into(),
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: password.into(),
+                ..Login::default()
+            })
+            .unwrap();
+            payload
+                .data
+                .extend(extra.as_object().unwrap().clone().into_iter());
+            let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(ts));
+            inbound.changes.push((payload, ServerTimestamp(ts)));
+            inbound
+        };
+        db.do_apply_incoming(
+            incoming(
+                "hunter2",
+                serde_json::json!({ "futureField": [1, 2] }),
+                1000,
+            ),
+            &mut telemetry::Engine::new("passwords"),
+            &scope,
+        )
+        .unwrap();
+        let login = db.get_by_id(guid).unwrap().unwrap();
+        assert_eq!(
+            login.unknown_fields.0["futureField"],
+            serde_json::json!([1, 2])
+        );
+
+        // Local changes keep them, even though the embedder doesn't know
+        // about them, and they're uploaded with the record.
+        db.update(Login {
+            password: "hunter3".into(),
+            unknown_fields: UnknownFields::default(),
+            ..login
+        })
+        .unwrap();
+        let outgoing = db.fetch_outgoing(ServerTimestamp(1000), &scope).unwrap();
+        assert_eq!(outgoing.changes.len(), 1);
+        assert_eq!(outgoing.changes[0].data["password"], "hunter3");
+        assert_eq!(
+            outgoing.changes[0].data["futureField"],
+            serde_json::json!([1, 2])
+        );
+
+        // And when merging with an incoming change, the server's unknown
+        // fields are kept along with the local change.
+        let outgoing = db
+            .do_apply_incoming(
+                incoming(
+                    "hunter2",
+                    serde_json::json!({ "futureField": [3], "otherField": true }),
+                    2000,
+                ),
+                &mut telemetry::Engine::new("passwords"),
+                &scope,
+            )
+            .unwrap();
+        let login = db.get_by_id(guid).unwrap().unwrap();
+        assert_eq!(login.password, "hunter3");
+        assert_eq!(
+            login.unknown_fields.0["futureField"],
+            serde_json::json!([3])
+        );
+        assert_eq!(login.unknown_fields.0["otherField"], true);
+        assert_eq!(outgoing.changes.len(), 1);
+        assert_eq!(outgoing.changes[0].data["otherField"], true);
+    }
+
+    #[test]
+    fn test_touch() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        let guid = login.guid_str();
+        db.mark_as_synchronized(&[guid], ServerTimestamp(1000), &scope)
+            .unwrap();
+        assert!(db
+            .fetch_outgoing(ServerTimestamp(1000), &scope)
+            .unwrap()
+            .changes
+            .is_empty());
+
+        // Touching a login only schedules a weak upload.
+        db.touch(guid).unwrap();
+        let touched = db.get_by_id(guid).unwrap().unwrap();
+        assert_eq!(touched.times_used, login.times_used + 1);
+        assert!(touched.time_last_used >= login.time_last_used);
+        let (status, weak_upload): (u8, bool) = db
+            .query_row_named(
+                "SELECT sync_status, weak_upload FROM loginsL WHERE guid = :guid",
+                named_params! { ":guid": guid },
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(status, SyncStatus::Synced as u8);
+        assert!(weak_upload);
+        let outgoing = db.fetch_outgoing(ServerTimestamp(1000), &scope).unwrap();
+        assert_eq!(outgoing.changes.len(), 1);
+
+        // An incoming change wins without a conflict, and the weak upload is
+        // dropped.
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+        inbound.changes.push((
+            Payload::from_record(Login {
+                password: "remote".into(),
+                ..login.clone()
+            })
+            .unwrap(),
+            ServerTimestamp(10000),
+        ));
+        let mut telem = telemetry::Engine::new("passwords");
+        let outgoing = db.do_apply_incoming(inbound, &mut telem, &scope).unwrap();
+        assert!(outgoing.changes.is_empty());
+        let fetched = db.get_by_id(guid).unwrap().unwrap();
+        assert_eq!(fetched.password, "remote");
+        assert_eq!(fetched.times_used, login.times_used);
+        let local_exists: bool = db
+            .query_row_named(
+                "SELECT EXISTS(SELECT 1 FROM loginsL WHERE guid = :guid)",
+                named_params! { ":guid": guid },
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!local_exists);
+    }
+
+    #[test]
+    fn test_update_password() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                username_field: "user_input".into(),
+                password_field: "pass_input".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        db.update_password(login.guid_str(), "hunter3").unwrap();
+        let updated = db.get_by_id(login.guid_str()).unwrap().unwrap();
+        assert_eq!(updated.password, "hunter3");
+        assert_eq!(updated.username_field, "user_input");
+        assert_eq!(updated.password_field, "pass_input");
+        assert_eq!(updated.times_used, login.times_used);
+        assert!(updated.time_password_changed >= login.time_password_changed);
+
+        match db.update_password(login.guid_str(), "").unwrap_err().kind() {
+            ErrorKind::InvalidLogin(InvalidLogin::EmptyPassword) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+        match db.update_password("missing", "hunter4").unwrap_err().kind() {
+            ErrorKind::NoSuchRecord(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_run_maintenance() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        let scope = db.begin_interrupt_scope();
+        db.mark_as_synchronized(&[login.guid_str()], ServerTimestamp(1000), &scope)
+            .unwrap();
+        assert!(db.delete(login.guid_str()).unwrap());
+
+        let report = db.run_maintenance(Duration::from_secs(3600)).unwrap();
+        assert_eq!(report.tombstones_pruned, 0);
+        assert!(report.integrity_errors.is_empty());
+
+        // The deletion hasn't been uploaded, so its tombstone is kept.
+        db.execute_batch("UPDATE loginsL SET local_modified = 1")
+            .unwrap();
+        let report = db.run_maintenance(Duration::from_secs(3600)).unwrap();
+        assert_eq!(report.tombstones_pruned, 0);
+        assert!(db.get_by_id(login.guid_str()).unwrap().is_none());
+
+        // Once it has been, it's gone anyway, and only leftover synced
+        // tombstones are pruned.
+        db.mark_as_synchronized(&[login.guid_str()], ServerTimestamp(2000), &scope)
+            .unwrap();
+        db.execute_named(
+            &format!(
+                "INSERT INTO loginsL (guid, local_modified, is_deleted, sync_status, hostname,
+                                      timeCreated, timePasswordChanged, password, username)
+                 VALUES ('leftover0000', 1, 1, {synced}, '', 0, 0, '', '')",
+                synced = SyncStatus::Synced as u8
+            ),
+            &[],
+        )
+        .unwrap();
+        let report = db.run_maintenance(Duration::from_secs(3600)).unwrap();
+        assert_eq!(report.tombstones_pruned, 1);
+        let auto_vacuum: i64 = db.query_one("PRAGMA auto_vacuum").unwrap();
+        assert_eq!(auto_vacuum, 2);
+    }
+
+    #[test]
+    fn test_tombstone_retention() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let mut guids = vec![];
+        for host in &["https://www.example.com", "https://www.example.org"] {
+            let login = db
+                .add(Login {
+                    hostname: (*host).into(),
+                    form_submit_url: Some((*host).into()),
+                    username: "user".into(),
+                    password: "hunter2".into(),
+                    ..Login::default()
+                })
+                .unwrap();
+            guids.push(login.guid);
+        }
+        let scope = db.begin_interrupt_scope();
+        db.mark_as_synchronized(
+            &guids.iter().map(Guid::as_str).collect::<Vec<_>>(),
+            ServerTimestamp(1000),
+            &scope,
+        )
+        .unwrap();
+        for guid in &guids {
+            db.delete(guid.as_str()).unwrap();
+        }
+        assert_eq!(db.count_tombstones().unwrap(), 2);
+
+        let set_deleted_at = |guid: &Guid, ms: i64| {
+            db.execute_named(
+                "UPDATE loginsL SET local_modified = :ms WHERE guid = :guid",
+                named_params! { ":ms": ms, ":guid": guid },
+            )
+            .unwrap();
+        };
+        // Deletions which haven't been uploaded are kept however old they
+        // are, and the logins stay deleted.
+        set_deleted_at(&guids[0], 1);
+        set_deleted_at(&guids[1], 1);
+        assert_eq!(db.prune_tombstones(DEFAULT_TOMBSTONE_RETENTION).unwrap(), 0);
+        assert_eq!(db.count_tombstones().unwrap(), 2);
+        assert!(db.get_all(&scope).unwrap().is_empty());
+        let outgoing = db.fetch_outgoing(ServerTimestamp(1000), &scope).unwrap();
+        assert_eq!(outgoing.changes.len(), 2);
+        assert!(outgoing.changes.iter().all(|change| change.deleted));
+
+        // Uploading them removes the tombstones and the mirror records.
+        db.mark_as_synchronized(&[guids[0].as_str()], ServerTimestamp(2000), &scope)
+            .unwrap();
+        assert_eq!(db.count_tombstones().unwrap(), 1);
+
+        // A synced tombstone can only be left behind by an older version, or
+        // an interrupted sync. Those are pruned once they expire, when a sync
+        // finishes.
+        db.execute_named(
+            &format!(
+                "INSERT INTO loginsL (guid, local_modified, is_deleted, sync_status, hostname,
+                                      timeCreated, timePasswordChanged, password, username)
+                 VALUES ('leftover0000', 1, 1, {synced}, '', 0, 0, '', '')",
+                synced = SyncStatus::Synced as u8
+            ),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(db.count_tombstones().unwrap(), 2);
+        db.set_tombstone_retention(Duration::from_secs(60));
+        let store = LoginStore::new(&db);
+        store.sync_finished(ServerTimestamp(3000), vec![]).unwrap();
+        assert_eq!(db.count_tombstones().unwrap(), 1);
+        assert!(db.get_all(&scope).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wipe_local() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.set_encryptor_decryptor(Box::new(TestEncDec("k1:")))
+            .unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        db.execute_batch("UPDATE loginsL SET sync_status = 0")
+            .unwrap();
+        db.set_last_sync(ServerTimestamp(1000)).unwrap();
+
+        db.wipe_local().unwrap();
+        // Nothing is left to upload, and the sync state is gone...
+        assert_eq!(db.count_tombstones().unwrap(), 0);
+        assert!(db.get_by_id(login.guid_str()).unwrap().is_none());
+        assert_eq!(db.get_last_sync().unwrap(), None);
+        // ...but the fields are still encrypted.
+        assert_eq!(
+            db.get_meta::<bool>(schema::FIELDS_ENCRYPTED_META_KEY)
+                .unwrap(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_delete_between() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let mut guids = vec![];
+        for (host, time_created) in &[
+            ("https://www.example.com", 1000),
+            ("https://www.example.org", 2000),
+            ("https://www.example.net", 3000),
+        ] {
+            let login = db
+                .add(Login {
+                    hostname: (*host).into(),
+                    form_submit_url: Some((*host).into()),
+                    username: "user".into(),
+                    password: "hunter2".into(),
+                    time_created: *time_created,
+                    ..Login::default()
+                })
+                .unwrap();
+            guids.push(login.guid);
+        }
+        // Move the second login to the mirror, as though it had been synced.
+        db.mark_as_synchronized(
+            &[guids[1].as_str()],
+            ServerTimestamp(1000),
+            &db.begin_interrupt_scope(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.delete_between(1500, 3000).unwrap(),
+            vec![guids[1].clone()]
+        );
+        assert_eq!(db.count_tombstones().unwrap(), 1);
+        assert!(db.get_by_id(guids[1].as_str()).unwrap().is_none());
+        assert!(db.get_by_id(guids[0].as_str()).unwrap().is_some());
+        assert!(db.get_by_id(guids[2].as_str()).unwrap().is_some());
+
+        let mut deleted = db.delete_between(0, i64::max_value()).unwrap();
+        deleted.sort();
+        let mut expected = vec![guids[0].clone(), guids[2].clone()];
+        expected.sort();
+        assert_eq!(deleted, expected);
+        assert!(db.get_all(&db.begin_interrupt_scope()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let existing = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        let new_login = Login {
+            hostname: "https://www.example.org".into(),
+            form_submit_url: Some("https://www.example.org".into()),
+            username: "user".into(),
+            password: "hunter2".into(),
+            ..Login::default()
+        };
+        let results = db
+            .apply_batch(vec![
+                LoginOperation::Add {
+                    login: new_login.clone(),
+                },
+                // A duplicate of the login we just added.
+                LoginOperation::Add { login: new_login },
+                LoginOperation::Update {
+                    login: Login {
+                        password: "new password".into(),
+                        ..existing.clone()
+                    },
+                },
+                LoginOperation::Delete {
+                    id: "nonexistent".into(),
+                },
+            ])
+            .unwrap();
+        assert_eq!(results.len(), 4);
+        let added = results[0].as_ref().unwrap().clone();
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), &existing.guid);
+        match results[3].as_ref().unwrap_err().kind() {
+            ErrorKind::NoSuchRecord(id) => assert_eq!(id, "nonexistent"),
+            e => panic!("Expected NoSuchRecord, got {:?}", e),
+        }
+        assert_eq!(db.get_all(&db.begin_interrupt_scope()).unwrap().len(), 2);
+        assert_eq!(
+            db.get_by_id(existing.guid_str()).unwrap().unwrap().password,
+            "new password"
+        );
+
+        let results = db
+            .apply_batch(vec![
+                LoginOperation::Delete {
+                    id: added.clone().into_string(),
+                },
+                LoginOperation::Delete {
+                    id: existing.guid.clone().into_string(),
+                },
+            ])
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &added);
+        assert_eq!(results[1].as_ref().unwrap(), &existing.guid);
+        assert!(db.get_all(&db.begin_interrupt_scope()).unwrap().is_empty());
+        assert_eq!(db.count_tombstones().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_dedupe_and_merge() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let mut guids = vec![];
+        // The second login is only a duplicate of the first according to
+        // `dedupe_and_merge`, as it has a different `formSubmitURL`.
+        for (host, form_submit_url, username, password, changed, last_used, times_used) in &[
+            (
+                "https://www.example.com",
+                "https://www.example.com",
+                "user",
+                "old",
+                1000,
+                5000,
+                2,
+            ),
+            (
+                "https://www.example.com",
+                "https://login.example.com",
+                "user",
+                "new",
+                2000,
+                3000,
+                3,
+            ),
+            (
+                "https://www.example.com",
+                "https://www.example.com",
+                "other",
+                "pass",
+                1000,
+                1000,
+                1,
+            ),
+            (
+                "https://www.example.org",
+                "https://www.example.org",
+                "user",
+                "pass",
+                1000,
+                1000,
+                1,
+            ),
+        ] {
+            let login = db
+                .add(Login {
+                    hostname: (*host).into(),
+                    form_submit_url: Some((*form_submit_url).into()),
+                    username: (*username).into(),
+                    password: (*password).into(),
+                    time_created: *changed,
+                    time_password_changed: *changed,
+                    time_last_used: *last_used,
+                    times_used: *times_used,
+                    ..Login::default()
+                })
+                .unwrap();
+            guids.push(login.guid);
+        }
+        // Make the duplicate look like it came from a sloppy importer.
+        db.execute_named(
+            "UPDATE loginsL SET hostname = 'HTTPS://WWW.EXAMPLE.COM:443/' WHERE guid = :guid",
+            named_params! { ":guid": guids[1] },
+        )
+        .unwrap();
+
+        let report = db.dedupe_and_merge().unwrap();
+        assert_eq!(report.num_deleted, 1);
+        assert_eq!(report.merged.len(), 1);
+        assert_eq!(
+            report.merged[guids[1].as_str()],
+            vec![guids[0].clone().into_string()]
+        );
+        assert!(db.get_by_id(guids[0].as_str()).unwrap().is_none());
+        let merged = db.get_by_id(guids[1].as_str()).unwrap().unwrap();
+        assert_eq!(merged.password, "new");
+        assert_eq!(merged.times_used, 5);
+        assert_eq!(merged.time_created, 1000);
+        assert_eq!(merged.time_last_used, 5000);
+        assert_eq!(db.get_all(&db.begin_interrupt_scope()).unwrap().len(), 3);
+        assert_eq!(db.count_tombstones().unwrap(), 1);
+
+        // Nothing is left to merge.
+        assert_eq!(db.dedupe_and_merge().unwrap(), MergeReport::default());
+    }
+
+    #[test]
+    fn test_access_log() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let logins = (0..3)
+            .map(|i| {
+                db.add(Login {
+                    hostname: format!("https://{}.example.com", i),
+                    form_submit_url: Some(format!("https://{}.example.com", i)),
+                    username: "user".into(),
+                    password: "hunter2".into(),
+                    ..Login::default()
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        // Nothing is logged until it's enabled.
+        db.record_access(&logins).unwrap();
+        assert!(db.get_access_log(0, None).unwrap().is_empty());
+
+        db.set_access_logging(Some("autofill"));
+        db.record_access(&logins).unwrap();
+        // Reading the same login again soon after isn't logged again.
+        db.record_access(&logins[..1]).unwrap();
+        db.set_access_logging(Some("app"));
+        db.record_access(&logins[..1]).unwrap();
+
+        let log = db.get_access_log(0, None).unwrap();
+        assert_eq!(log.len(), 4);
+        assert_eq!(log[3].caller, "app");
+        assert_eq!(log[3].guid, logins[0].guid);
+        let log = db.get_access_log(0, Some("autofill")).unwrap();
+        assert_eq!(
+            log.iter().map(|e| e.guid.as_str()).collect::<Vec<_>>(),
+            logins.iter().map(|l| l.guid_str()).collect::<Vec<_>>()
+        );
+
+        let hour = Duration::from_secs(60 * 60);
+        let suspicious = db.find_suspicious_access(hour, 2).unwrap();
+        assert_eq!(suspicious.len(), 1);
+        assert_eq!(suspicious[0].caller, "autofill");
+        assert_eq!(suspicious[0].num_logins, 3);
+        assert!(db.find_suspicious_access(hour, 3).unwrap().is_empty());
+
+        db.wipe_local().unwrap();
+        assert!(db.get_access_log(0, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_upgrade_http_origins() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let mut guids = vec![];
+        for (host, username, password, times_used) in &[
+            ("http://www.example.com", "user", "hunter2", 2),
+            ("https://www.example.com", "user", "hunter2", 3),
+            ("http://www.example.org", "user", "hunter2", 1),
+            ("https://www.example.org", "other", "hunter2", 1),
+            ("http://www.example.net", "user", "old", 1),
+            ("https://www.example.net", "user", "new", 1),
+            ("http://www.example.net:8080", "user", "hunter2", 1),
+            ("http://www.example.com:80", "other", "hunter2", 1),
+        ] {
+            let login = db
+                .add(Login {
+                    hostname: (*host).into(),
+                    form_submit_url: Some((*host).into()),
+                    username: (*username).into(),
+                    password: (*password).into(),
+                    times_used: *times_used,
+                    ..Login::default()
+                })
+                .unwrap();
+            guids.push(login.guid.into_string());
+        }
+        db.mark_as_synchronized(
+            &guids.iter().map(String::as_str).collect::<Vec<_>>(),
+            ServerTimestamp(10000),
+            &db.begin_interrupt_scope(),
+        )
+        .unwrap();
+
+        // Bypass the fixups, which would drop the explicit default port.
+        db.execute_named(
+            "UPDATE loginsL SET hostname = 'http://www.example.com:80' WHERE guid = :guid",
+            named_params! { ":guid": guids[7] },
+        )
+        .unwrap();
+
+        let mut report = db.upgrade_http_origins().unwrap();
+        report.upgraded.sort();
+        let mut expected_upgraded = vec![guids[2].clone(), guids[7].clone()];
+        expected_upgraded.sort();
+        assert_eq!(report.upgraded, expected_upgraded);
+        assert_eq!(report.merged.len(), 1);
+        assert_eq!(report.merged[&guids[0]], guids[1]);
+        assert_eq!(report.conflicting, vec![guids[4].clone()]);
+
+        let upgraded = db.get_by_id(&guids[2]).unwrap().unwrap();
+        assert_eq!(upgraded.hostname, "https://www.example.org");
+        assert_eq!(
+            upgraded.form_submit_url.as_deref(),
+            Some("https://www.example.org")
+        );
+        let upgraded = db.get_by_id(&guids[7]).unwrap().unwrap();
+        assert_eq!(upgraded.hostname, "https://www.example.com");
+        assert_eq!(
+            upgraded.form_submit_url.as_deref(),
+            Some("https://www.example.com")
+        );
+        assert!(db.get_by_id(&guids[0]).unwrap().is_none());
+        assert_eq!(db.get_by_id(&guids[1]).unwrap().unwrap().times_used, 5);
+        assert_eq!(
+            db.get_by_id(&guids[6]).unwrap().unwrap().hostname,
+            "http://www.example.net:8080"
+        );
+        // The changes are uploaded on the next sync.
+        let outgoing = db
+            .fetch_outgoing(ServerTimestamp(10000), &db.begin_interrupt_scope())
+            .unwrap();
+        let mut changed = outgoing
+            .changes
+            .iter()
+            .map(|p| p.id.to_string())
+            .collect::<Vec<_>>();
+        changed.sort();
+        let mut expected = vec![
+            guids[0].clone(),
+            guids[1].clone(),
+            guids[2].clone(),
+            guids[7].clone(),
+        ];
+        expected.sort();
+        assert_eq!(changed, expected);
+
+        // Running it again changes nothing.
+        let report = db.upgrade_http_origins().unwrap();
+        assert!(report.upgraded.is_empty() && report.merged.is_empty());
+    }
+
+    #[test]
+    fn test_merge_policy() {
+        for (policy, expected_password) in &[
+            (MergePolicy::PreferNewer, "local"),
+            (MergePolicy::PasswordNewestTimestamp, "local"),
+            (MergePolicy::PreferLocal, "local"),
+            (MergePolicy::PreferRemote, "remote"),
+        ] {
+            let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+            db.set_merge_policy(*policy);
+            let local = db
+                .add(Login {
+                    hostname: "https://www.example.com".into(),
+                    form_submit_url: Some("https://www.example.com".into()),
+                    username: "user".into(),
+                    password: "local".into(),
+                    time_password_changed: 2000,
+                    ..Login::default()
+                })
+                .unwrap();
+            // An incoming record with the same GUID, and no mirror record to
+            // merge against.
+            let remote = Login {
+                password: "remote".into(),
+                time_password_changed: 1000,
+                ..local.clone()
+            };
+            let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+            inbound.changes.push((
+                Payload::from_record(remote).unwrap(),
+                ServerTimestamp(10000),
+            ));
+            db.do_apply_incoming(
+                inbound,
+                &mut telemetry::Engine::new("passwords"),
+                &db.begin_interrupt_scope(),
+            )
+            .unwrap();
+            assert_eq!(
+                db.get_by_id(local.guid_str()).unwrap().unwrap().password,
+                *expected_password,
+                "Unexpected result for {:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_uses_field_modified() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let synced = Login {
+            guid: "dummy_000001".into(),
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            username: "user".into(),
+            password: "password".into(),
+            ..Login::default()
+        };
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(now_ms - 7_200_000));
+        inbound.changes.push((
+            Payload::from_record(synced.clone()).unwrap(),
+            ServerTimestamp(now_ms - 7_200_000),
+        ));
+        db.do_apply_incoming(inbound, &mut telemetry::Engine::new("passwords"), &scope)
+            .unwrap();
+
+        // Change the password, then the username, while offline.
+        db.update(Login {
+            password: "local password".into(),
+            ..synced.clone()
+        })
+        .unwrap();
+        db.update(Login {
+            username: "local user".into(),
+            password: "local password".into(),
+            ..synced.clone()
+        })
+        .unwrap();
+        let field_modified: HashMap<String, i64> = serde_json::from_str(
+            &db.query_one::<String>("SELECT field_modified FROM loginsL")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(field_modified.len(), 2);
+        assert!(field_modified.contains_key("password"));
+        assert!(field_modified.contains_key("username"));
+
+        // The password was changed an hour ago, and the username just now.
+        db.execute_named(
+            "UPDATE loginsL SET field_modified = :field_modified",
+            named_params! {
+                ":field_modified": serde_json::json!({
+                    "password": now_ms - 3_600_000,
+                    "username": now_ms,
+                })
+                .to_string(),
+            },
+        )
+        .unwrap();
+
+        // Both were changed remotely ten minutes ago, so the remote password
+        // is newer, but the local username is.
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(now_ms));
+        inbound.changes.push((
+            Payload::from_record(Login {
+                username: "remote user".into(),
+                password: "remote password".into(),
+                ..synced
+            })
+            .unwrap(),
+            ServerTimestamp(now_ms - 600_000),
+        ));
+        db.do_apply_incoming(inbound, &mut telemetry::Engine::new("passwords"), &scope)
+            .unwrap();
+        let merged = db.get_by_id("dummy_000001").unwrap().unwrap();
+        assert_eq!(merged.username, "local user");
+        assert_eq!(merged.password, "remote password");
+    }
+
+    #[test]
+    fn test_needs_sync_before() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let start = SystemTime::now() - Duration::from_secs(60);
+        assert!(!db.needs_sync_before(SystemTime::now()).unwrap());
+
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        assert!(!db.needs_sync_before(start).unwrap());
+        assert!(db.needs_sync_before(SystemTime::now()).unwrap());
+
+        let scope = db.begin_interrupt_scope();
+        db.mark_as_synchronized(&[login.guid_str()], ServerTimestamp(1000), &scope)
+            .unwrap();
+        assert!(!db.needs_sync_before(SystemTime::now()).unwrap());
+
+        // A failed sync is owed until one succeeds, even without local
+        // changes.
+        db.record_pending_sync().unwrap();
+        assert!(!db.needs_sync_before(start).unwrap());
+        assert!(db.needs_sync_before(SystemTime::now()).unwrap());
+        db.mark_as_synchronized(&[], ServerTimestamp(2000), &scope)
+            .unwrap();
+        assert!(!db.needs_sync_before(SystemTime::now()).unwrap());
+    }
+
+    #[test]
+    fn test_sync_validation_telemetry() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.add(Login {
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            username: "user".into(),
+            password: "password".into(),
+            ..Login::default()
+        })
+        .unwrap();
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+        for login in vec![
+            // A duplicate of the local login, with a different GUID.
+            Login {
+                guid: Guid::random(),
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Login::default()
+            },
+            // Invalid, as it has no password.
+            Login {
+                guid: Guid::random(),
+                hostname: "https://www.example.org".into(),
+                form_submit_url: Some("https://www.example.org".into()),
+                ..Login::default()
+            },
+        ] {
+            inbound
+                .changes
+                .push((Payload::from_record(login).unwrap(), ServerTimestamp(10000)));
+        }
+        let mut telem = telemetry::Engine::new("passwords");
+        db.do_apply_incoming(inbound, &mut telem, &db.begin_interrupt_scope())
+            .unwrap();
+        let mut sync = telemetry::SyncTelemetry::new();
+        sync.engine(telem);
+        sync.finished();
+        let sync = serde_json::to_value(&sync).unwrap();
+        let telem = &sync["engines"][0];
+        assert_eq!(
+            telem["validation"]["problems"],
+            serde_json::json!([
+                { "name": "invalidIncoming", "count": 1 },
+                { "name": "contentDupes", "count": 1 },
+            ])
+        );
+        assert_eq!(telem["incoming"]["applied"], 2);
+    }
+
+    #[test]
+    fn test_validate() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let mut logins = vec![];
+        for host in &[
+            "https://www.example.com",
+            "https://www.example.org",
+            "https://www.example.net",
+        ] {
+            logins.push(
+                db.add(Login {
+                    hostname: (*host).into(),
+                    form_submit_url: Some((*host).into()),
+                    username: "user".into(),
+                    password: "password".into(),
+                    ..Login::default()
+                })
+                .unwrap(),
+            );
+        }
+        let guids: Vec<&str> = logins.iter().map(|l| l.guid_str()).collect();
+        db.mark_as_synchronized(&guids, ServerTimestamp(1000), &scope)
+            .unwrap();
+        db.set_last_sync(ServerTimestamp(1000)).unwrap();
+        // Orphan the first login's mirror record.
+        db.execute_named(
+            "UPDATE loginsM SET is_overridden = 1 WHERE guid = :guid",
+            named_params! { ":guid": guids[0] },
+        )
+        .unwrap();
+        assert_eq!(
+            db.validate(None, false).unwrap(),
+            ValidationReport {
+                orphaned_mirror: vec![guids[0].to_owned()],
+                ..ValidationReport::default()
+            }
+        );
+
+        let missing = Login {
+            guid: Guid::random(),
+            hostname: "https://missing.example.com".into(),
+            form_submit_url: Some("https://missing.example.com".into()),
+            username: "user".into(),
+            password: "password".into(),
+            ..Login::default()
+        };
+        let server = vec![
+            (logins[0].guid.clone(), Some(logins[0].clone())),
+            (
+                logins[1].guid.clone(),
+                Some(Login {
+                    password: "changed".into(),
+                    ..logins[1].clone()
+                }),
+            ),
+            // The third login is missing from the server.
+            (missing.guid.clone(), Some(missing.clone())),
+            (missing.guid.clone(), Some(missing.clone())),
+        ];
+        let report = db.validate(Some(&server), true).unwrap();
+        assert_eq!(report.orphaned_mirror, vec![guids[0].to_owned()]);
+        assert_eq!(report.duplicate_guids, vec![missing.guid.to_string()]);
+        assert_eq!(report.client_missing, vec![missing.guid.to_string()]);
+        assert_eq!(report.differences, vec![guids[1].to_owned()]);
+        assert_eq!(report.server_missing, vec![guids[2].to_owned()]);
+        assert!(report.repaired);
+
+        // The orphan was fixed, the login missing from the server will be
+        // uploaded, and the next sync will download everything.
+        assert_eq!(db.get_last_sync().unwrap(), Some(ServerTimestamp(0)));
+        let outgoing = db.fetch_outgoing(ServerTimestamp(0), &scope).unwrap();
+        assert_eq!(outgoing.changes.len(), 1);
+        assert_eq!(outgoing.changes[0].id, logins[2].guid);
+        let report = db.validate(None, false).unwrap();
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_local_only() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let scope = db.begin_interrupt_scope();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        assert!(!db.is_local_only(login.guid_str()).unwrap());
+        db.set_local_only(login.guid_str(), true).unwrap();
+        assert!(db.is_local_only(login.guid_str()).unwrap());
+        assert!(db.set_local_only("nonexistent", true).is_err());
+
+        // It's not uploaded, and incoming changes are ignored.
+        let outgoing = db.fetch_outgoing(ServerTimestamp(0), &scope).unwrap();
+        assert!(outgoing.changes.is_empty());
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+        inbound.changes.push((
+            Payload::from_record(Login {
+                password: "remote".into(),
+                ..login.clone()
+            })
+            .unwrap(),
+            ServerTimestamp(10000),
+        ));
+        let outgoing = db
+            .do_apply_incoming(inbound, &mut telemetry::Engine::new("passwords"), &scope)
+            .unwrap();
+        assert!(outgoing.changes.is_empty());
+        assert_eq!(
+            db.get_by_id(login.guid_str()).unwrap().unwrap().password,
+            "password"
+        );
+
+        // Until it's a normal login again.
+        db.set_local_only(login.guid_str(), false).unwrap();
+        let outgoing = db.fetch_outgoing(ServerTimestamp(0), &scope).unwrap();
+        assert_eq!(outgoing.changes.len(), 1);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let login = Login {
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            username: "user".into(),
+            password: "password".into(),
+            ..Login::default()
+        };
+        // Nothing is recorded until history is enabled.
+        let login = db.add(login).unwrap();
+        let guid = login.guid_str();
+        db.update_password(guid, "password2").unwrap();
+        assert!(db.get_history(guid).unwrap().is_empty());
+
+        db.set_history_limit(Some(100));
+        db.update_password(guid, "password3").unwrap();
+        db.update(Login {
+            username: "user2".into(),
+            password_field: "pass".into(),
+            password: "password3".into(),
+            ..login.clone()
+        })
+        .unwrap();
+        db.touch(guid).unwrap();
+
+        // And an incoming change from another device.
+        let mut inbound = IncomingChangeset::new("passwords", ServerTimestamp(10000));
+        inbound.changes.push((
+            Payload::from_record(Login {
+                password: "remote".into(),
+                time_password_changed: util::system_time_ms_i64(SystemTime::now()) + 10000,
+                ..db.get_by_id(guid).unwrap().unwrap()
+            })
+            .unwrap(),
+            ServerTimestamp(10000),
+        ));
+        db.do_apply_incoming(
+            inbound,
+            &mut telemetry::Engine::new("passwords"),
+            &db.begin_interrupt_scope(),
+        )
+        .unwrap();
+        db.delete(guid).unwrap();
+
+        let history: Vec<(String, ChangeSource)> = db
+            .get_history(guid)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.field, e.source))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                ("password".to_owned(), ChangeSource::Local),
+                ("passwordField".to_owned(), ChangeSource::Local),
+                ("username".to_owned(), ChangeSource::Local),
+                ("password".to_owned(), ChangeSource::Sync),
+                ("deleted".to_owned(), ChangeSource::Local),
+            ]
+        );
+
+        // Only the newest entries are kept.
+        db.set_history_limit(Some(2));
+        let other = db
+            .add(Login {
+                hostname: "https://www.example.org".into(),
+                form_submit_url: Some("https://www.example.org".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        assert_eq!(db.get_history(guid).unwrap().len(), 1);
+        assert_eq!(db.get_history(other.guid_str()).unwrap().len(), 1);
+
+        db.wipe_local().unwrap();
+        assert!(db.get_history(other.guid_str()).unwrap().is_empty());
     }
 
     #[test]
-    fn test_unicode_submit() {
+    fn test_undo_delete() {
         let db = LoginDb::open_in_memory(Some("testing")).unwrap();
-        db.add(Login {
-            guid: "dummy_000001".into(),
-            form_submit_url: Some("http://😍.com".into()),
-            hostname: "http://😍.com".into(),
-            http_realm: None,
-            username: "😍".into(),
-            username_field: "😍".into(),
-            password: "😍".into(),
-            password_field: "😍".into(),
-            ..Login::default()
-        })
+        let scope = db.begin_interrupt_scope();
+        let mut guids = vec![];
+        for host in &["https://www.example.com", "https://www.example.org"] {
+            let login = db
+                .add(Login {
+                    hostname: (*host).into(),
+                    form_submit_url: Some((*host).into()),
+                    username: "user".into(),
+                    password: "hunter2".into(),
+                    ..Login::default()
+                })
+                .unwrap();
+            guids.push(login.guid);
+        }
+        // The second login has been synced, so only exists in the mirror.
+        db.mark_as_synchronized(&[guids[1].as_str()], ServerTimestamp(1000), &scope)
+            .unwrap();
+        let before = db.get_all(&scope).unwrap();
+
+        let first = db.delete_with_undo(guids[0].as_str()).unwrap().unwrap();
+        let second = db.delete_with_undo(guids[1].as_str()).unwrap().unwrap();
+        assert!(db.delete_with_undo(guids[1].as_str()).unwrap().is_none());
+        assert!(db.get_all(&scope).unwrap().is_empty());
+
+        db.undo_delete(first).unwrap();
+        db.undo_delete(second).unwrap();
+        let mut after = db.get_all(&scope).unwrap();
+        after.sort_by(|a, b| a.guid.cmp(&b.guid));
+        let mut before = before;
+        before.sort_by(|a, b| a.guid.cmp(&b.guid));
+        assert_eq!(after, before);
+        // Nothing needs to be uploaded for the synced login.
+        assert_eq!(db.count_tombstones().unwrap(), 0);
+        let outgoing = db.fetch_outgoing(ServerTimestamp(1000), &scope).unwrap();
+        assert_eq!(outgoing.changes.len(), 1);
+        assert_eq!(outgoing.changes[0].id, guids[0]);
+
+        // Once the deletion has been uploaded, it can't be undone.
+        let deleted = db.delete_with_undo(guids[1].as_str()).unwrap().unwrap();
+        db.mark_as_synchronized(&[guids[1].as_str()], ServerTimestamp(2000), &scope)
+            .unwrap();
+        match db.undo_delete(deleted).unwrap_err().kind() {
+            ErrorKind::CannotUndoDelete(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+
+        // Nor can it once the grace period has passed.
+        let mut deleted = db.delete_with_undo(guids[0].as_str()).unwrap().unwrap();
+        deleted.deleted_at -= UNDO_DELETE_GRACE_PERIOD.as_millis() as i64 + 1;
+        db.execute_named(
+            "UPDATE loginsL SET local_modified = :deleted_at WHERE guid = :guid",
+            named_params! {
+                ":deleted_at": deleted.deleted_at,
+                ":guid": guids[0],
+            },
+        )
         .unwrap();
-        let fetched = db
-            .get_by_id("dummy_000001")
-            .expect("should work")
-            .expect("should get a record");
-        assert_eq!(fetched.hostname, "http://xn--r28h.com");
-        assert_eq!(fetched.form_submit_url.unwrap(), "http://xn--r28h.com");
-        assert_eq!(fetched.username, "😍");
-        assert_eq!(fetched.username_field, "😍");
-        assert_eq!(fetched.password, "😍");
-        assert_eq!(fetched.password_field, "😍");
+        assert!(deleted.is_expired());
+        match db.undo_delete(deleted).unwrap_err().kind() {
+            ErrorKind::CannotUndoDelete(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+        assert!(db.get_by_id(guids[0].as_str()).unwrap().is_none());
     }
 
     #[test]
-    fn test_unicode_realm() {
+    fn test_stats() {
         let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.num_logins, 0);
+        assert!(stats.logins_per_base_domain.is_empty());
+        assert!(stats.db_size > 0);
+
+        let mut guids = vec![];
+        for (host, username) in &[
+            ("https://www.example.com", "a"),
+            ("http://www.example.com", "b"),
+            ("https://www.example.org", "c"),
+            ("https://www.example.net", "d"),
+            ("https://accounts.example.com", "e"),
+            ("https://login.example.co.uk", "f"),
+            ("http://127.0.0.1:8080", "g"),
+        ] {
+            let login = db
+                .add(Login {
+                    hostname: (*host).into(),
+                    form_submit_url: Some((*host).into()),
+                    username: (*username).into(),
+                    password: "hunter2".into(),
+                    ..Login::default()
+                })
+                .unwrap();
+            guids.push(login.guid);
+        }
+        db.touch(guids[0].as_str()).unwrap();
+        db.execute_batch("UPDATE loginsL SET sync_status = 0")
+            .unwrap();
+        db.delete(guids[3].as_str()).unwrap();
+
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.num_logins, 6);
+        assert_eq!(stats.num_tombstones, 1);
+        assert_eq!(stats.num_never_used, 5);
+        let expected: BTreeMap<String, u64> = vec![
+            ("example.com".to_owned(), 3),
+            ("example.org".to_owned(), 1),
+            ("example.co.uk".to_owned(), 1),
+            ("127.0.0.1".to_owned(), 1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(stats.logins_per_base_domain, expected);
+    }
+
+    #[test]
+    fn test_undecryptable_records() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.set_encryptor_decryptor(Box::new(TestEncDec("k1:")))
+            .unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        db.set_last_sync(ServerTimestamp(1000)).unwrap();
+
+        // Simulate the key being lost.
+        db.set_encryptor_decryptor(Box::new(TestEncDec("k2:")))
+            .unwrap();
+        let scope = db.begin_interrupt_scope();
+        match db.get_all(&scope).unwrap_err().kind() {
+            ErrorKind::DecryptionFailed(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+        assert_eq!(
+            db.find_undecryptable_records().unwrap(),
+            vec![login.guid.clone()]
+        );
+        assert_eq!(db.delete_undecryptable_records().unwrap(), vec![login.guid]);
+        assert!(db.get_all(&scope).unwrap().is_empty());
+        assert_eq!(db.get_last_sync().unwrap(), Some(ServerTimestamp(0)));
+    }
+
+    #[test]
+    fn test_check_encryption_key() {
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k1:")).unwrap(),
+            KeyStatus::NotEncrypted
+        );
+        // The canary is written even when there are no logins to check.
+        db.set_encryptor_decryptor(Box::new(TestEncDec("k1:")))
+            .unwrap();
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k1:")).unwrap(),
+            KeyStatus::Valid
+        );
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k2:")).unwrap(),
+            KeyStatus::WrongKey
+        );
         db.add(Login {
-            guid: "dummy_000001".into(),
-            form_submit_url: None,
-            hostname: "http://😍.com".into(),
-            http_realm: Some("😍😍".into()),
-            username: "😍".into(),
-            password: "😍".into(),
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            username: "user".into(),
+            password: "hunter2".into(),
             ..Login::default()
         })
         .unwrap();
-        let fetched = db
-            .get_by_id("dummy_000001")
-            .expect("should work")
-            .expect("should get a record");
-        assert_eq!(fetched.hostname, "http://xn--r28h.com");
-        assert_eq!(fetched.http_realm.unwrap(), "😍😍");
-    }
+        db.set_last_sync(ServerTimestamp(1000)).unwrap();
 
-    fn check_matches(db: &LoginDb, query: &str, expected: &[&str]) {
-        let mut results = db
-            .get_by_base_domain(query)
-            .unwrap()
-            .into_iter()
-            .map(|l| l.hostname)
-            .collect::<Vec<String>>();
-        results.sort();
-        let mut sorted = expected.to_owned();
-        sorted.sort();
-        assert_eq!(sorted, results);
-    }
+        db.put_meta(schema::FIELDS_CANARY_META_KEY, &"k1:egabrag")
+            .unwrap();
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k1:")).unwrap(),
+            KeyStatus::Corrupt
+        );
 
-    fn check_good_bad(
-        good: Vec<&str>,
-        bad: Vec<&str>,
-        good_queries: Vec<&str>,
-        zero_queries: Vec<&str>,
-    ) {
-        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
-        for h in good.iter().chain(bad.iter()) {
-            db.add(Login {
-                hostname: (*h).into(),
-                http_realm: Some((*h).into()),
-                password: "test".into(),
+        // Without a canary, the logins are checked instead.
+        db.delete_meta(schema::FIELDS_CANARY_META_KEY).unwrap();
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k1:")).unwrap(),
+            KeyStatus::Valid
+        );
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k2:")).unwrap(),
+            KeyStatus::WrongKey
+        );
+
+        // The key was lost, so start again with a new one.
+        db.reset_encrypted_data(Box::new(TestEncDec("k2:")))
+            .unwrap();
+        let scope = db.begin_interrupt_scope();
+        assert!(db.get_all(&scope).unwrap().is_empty());
+        assert_eq!(db.get_last_sync().unwrap(), None);
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k2:")).unwrap(),
+            KeyStatus::Valid
+        );
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
                 ..Login::default()
             })
             .unwrap();
-        }
-        for query in good_queries {
-            check_matches(&db, query, &good);
-        }
-        for query in zero_queries {
-            check_matches(&db, query, &[]);
-        }
+        assert_eq!(
+            raw_fields(&db, login.guid_str()),
+            ("k2:resu".into(), "k2:2retnuh".into())
+        );
+
+        // wipe_local keeps the canary along with the encryption state.
+        db.wipe_local().unwrap();
+        assert_eq!(
+            db.check_encryption_key(&TestEncDec("k2:")).unwrap(),
+            KeyStatus::Valid
+        );
     }
 
     #[test]
@@ -1602,6 +5492,32 @@ mod tests {
             vec!["example.com"],
             vec!["foo.com"],
         );
+        // Multi-label public suffixes.
+        check_good_bad(
+            vec![
+                "https://example.co.uk",
+                "https://www.example.co.uk",
+                "https://login.www.example.co.uk",
+            ],
+            vec!["https://other.co.uk", "https://example.uk"],
+            // Subdomains find the logins for their base domain.
+            vec!["example.co.uk", "EXAMPLE.CO.UK", "other.example.co.uk"],
+            // Public suffixes don't have a base domain.
+            vec!["co.uk", "uk"],
+        );
+        // Wildcard and private rules.
+        check_good_bad(
+            vec!["https://foo.example.mm", "https://www.foo.example.mm"],
+            vec!["https://bar.example.mm"],
+            vec!["foo.example.mm", "www.foo.example.mm"],
+            vec!["example.mm", "mm"],
+        );
+        check_good_bad(
+            vec!["https://example.github.io"],
+            vec!["https://other.github.io"],
+            vec!["example.github.io", "www.example.github.io"],
+            vec!["github.io"],
+        );
         // punycode! This is likely to need adjusting once we normalize
         // on insert.
         check_good_bad(
@@ -1624,6 +5540,94 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_by_base_domain_rev_host() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                http_realm: Some("https://www.example.com".into()),
+                password: "test".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        let scope = db.begin_interrupt_scope();
+        let find = |base_domain| {
+            db.get_by_base_domain(base_domain, &scope)
+                .unwrap()
+                .into_iter()
+                .map(|l| l.guid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(find("example.com"), vec![login.guid.clone()]);
+
+        // Changing the hostname updates `rev_host`.
+        db.update(Login {
+            hostname: "https://www.example.org".into(),
+            ..login.clone()
+        })
+        .unwrap();
+        assert!(find("example.com").is_empty());
+        assert_eq!(find("example.org"), vec![login.guid.clone()]);
+
+        // Mirror rows are found too.
+        db.execute_batch(&format!(
+            "INSERT INTO loginsM ({common_cols}, server_modified)
+             SELECT {common_cols}, 0 FROM loginsL;
+             DELETE FROM loginsL;",
+            common_cols = schema::COMMON_COLS,
+        ))
+        .unwrap();
+        assert_eq!(find("example.org"), vec![login.guid.clone()]);
+
+        // Values which are out of date, as if an older version had changed
+        // the row, are fixed when a writer opens the database.
+        db.execute_batch("UPDATE loginsM SET rev_host = NULL")
+            .unwrap();
+        assert!(find("example.org").is_empty());
+        schema::prepare_writer(&db).unwrap();
+        assert_eq!(find("example.org"), vec![login.guid]);
+    }
+
+    #[test]
+    fn test_get_by_base_domain_without_rev_host() {
+        let dir = tempdir::TempDir::new("base_domain_without_rev_host").unwrap();
+        let dbpath = dir.path().join("logins.sqlite");
+        let db = LoginDb::open(&dbpath, Some("testing")).unwrap();
+        db.add(Login {
+            hostname: "https://www.example.com".into(),
+            http_realm: Some("https://www.example.com".into()),
+            password: "test".into(),
+            ..Login::default()
+        })
+        .unwrap();
+        db.execute_all(&[
+            "DROP INDEX idx_loginsL_rev_host",
+            "DROP INDEX idx_loginsM_rev_host",
+            "ALTER TABLE loginsL DROP COLUMN rev_host",
+            "ALTER TABLE loginsM DROP COLUMN rev_host",
+            "PRAGMA user_version = 13",
+        ])
+        .unwrap();
+        drop(db);
+
+        // A read-only connection can't add the column, so it scans instead.
+        let reader = LoginDb::open_readonly(&dbpath, Some("testing"), None).unwrap();
+        assert!(!reader.has_rev_host);
+        let scope = reader.begin_interrupt_scope();
+        assert_eq!(
+            reader
+                .get_by_base_domain("www.example.com", &scope)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(reader
+            .get_by_base_domain("example.org", &scope)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_get_by_base_domain_ipv6() {
         check_good_bad(
@@ -1989,6 +5993,215 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_schema_versions() {
+        let dir = tempdir::TempDir::new("schema_versions").unwrap();
+        let dbpath = dir.path().join("logins.sqlite");
+        let user_version = |db: &LoginDb| db.query_one::<i64>("PRAGMA user_version").unwrap();
+        let db = LoginDb::open(&dbpath, Some("testing")).unwrap();
+        assert_eq!(user_version(&db), schema::VERSION);
+        assert_eq!(db.query_one::<i64>("PRAGMA busy_timeout").unwrap(), 5000);
+        db.add(Login {
+            hostname: "https://www.example.com".into(),
+            http_realm: Some("https://www.example.com".into()),
+            username: "user".into(),
+            password: "pass".into(),
+            ..Login::default()
+        })
+        .unwrap();
+
+        // Migrations run from the version the database was at.
+        db.execute_all(&[
+            "DROP TABLE loginsAccessLog",
+            "DROP INDEX idx_loginsL_rev_host",
+            "DROP INDEX idx_loginsM_rev_host",
+            "ALTER TABLE loginsL DROP COLUMN rev_host",
+            "ALTER TABLE loginsM DROP COLUMN rev_host",
+            "PRAGMA user_version = 12",
+        ])
+        .unwrap();
+        drop(db);
+        let db = LoginDb::open(&dbpath, Some("testing")).unwrap();
+        assert_eq!(user_version(&db), schema::VERSION);
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM loginsAccessLog")
+                .unwrap(),
+            0
+        );
+        // Existing logins get a `rev_host`.
+        assert_eq!(
+            db.query_one::<String>("SELECT rev_host FROM loginsL")
+                .unwrap(),
+            "moc.elpmaxe.www."
+        );
+        let scope = db.begin_interrupt_scope();
+        assert_eq!(
+            db.get_by_base_domain("example.com", &scope).unwrap().len(),
+            1
+        );
+
+        // A database from a newer version is used as it is.
+        db.execute_all(&["PRAGMA user_version = 100"]).unwrap();
+        drop(db);
+        let db = LoginDb::open(&dbpath, Some("testing")).unwrap();
+        assert_eq!(user_version(&db), 100);
+    }
+
+    #[test]
+    fn test_quarantine() {
+        use sql_support::open_database::ConnectionInitializer;
+        let sqlite_error =
+            |code| rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None);
+        let not_a_database = sqlite_error(rusqlite::ffi::SQLITE_NOTADB);
+        let corrupt = sqlite_error(rusqlite::ffi::SQLITE_CORRUPT);
+        // A wrong key looks like a file which isn't a database to SQLCipher,
+        // so an encrypted database is only moved aside if it's corrupt.
+        let encrypted = schema::LoginsConnectionInitializer {
+            encryption_key: Some("testing"),
+            salt: None,
+        };
+        assert!(!encrypted.is_corruption_error(&not_a_database));
+        assert!(encrypted.is_corruption_error(&corrupt));
+        let unencrypted = schema::LoginsConnectionInitializer {
+            encryption_key: None,
+            salt: None,
+        };
+        assert!(unencrypted.is_corruption_error(&not_a_database));
+
+        let dir = tempdir::TempDir::new("quarantine").unwrap();
+        let dbpath = dir.path().join("logins.sqlite");
+        let db = LoginDb::open(&dbpath, None).unwrap();
+        assert!(db.quarantined_database().is_none());
+        drop(db);
+
+        std::fs::write(&dbpath, vec![0xaa; 4096]).unwrap();
+        let db = LoginDb::open(&dbpath, None).unwrap();
+        let quarantine_path = open_database::quarantine_path(&dbpath);
+        assert_eq!(db.quarantined_database().unwrap().path, quarantine_path);
+        assert_eq!(std::fs::read(&quarantine_path).unwrap(), vec![0xaa; 4096]);
+        assert_eq!(db.get_all(&db.begin_interrupt_scope()).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_open_readonly() {
+        let dir = tempdir::TempDir::new("open_readonly").unwrap();
+        let dbpath = dir.path().join("logins.sqlite");
+        assert!(LoginDb::open_readonly(&dbpath, Some("testing"), None).is_err());
+
+        let db = LoginDb::open(&dbpath, Some("testing")).unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        drop(db);
+
+        let db = LoginDb::open_readonly(&dbpath, Some("testing"), None).unwrap();
+        assert!(db.is_read_only());
+        let scope = db.begin_interrupt_scope();
+        assert_eq!(db.get_all(&scope).unwrap().len(), 1);
+        assert!(db.get_by_id(login.guid_str()).unwrap().is_some());
+        for result in &[
+            db.touch(login.guid_str()),
+            db.update_password(login.guid_str(), "new password"),
+            db.delete(login.guid_str()).map(|_| ()),
+            db.wipe_local(),
+        ] {
+            match result.as_ref().unwrap_err().kind() {
+                ErrorKind::ReadOnly => {}
+                e => panic!("Expected ReadOnly, got {:?}", e),
+            }
+        }
+        assert!(db.add(Login::default()).is_err());
+        assert_eq!(db.get_all(&scope).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_connection_pragmas() {
+        let dir = tempdir::TempDir::new("connection_pragmas").unwrap();
+        let dbpath = dir.path().join("logins.sqlite");
+        let db = LoginDb::open(&dbpath, Some("testing")).unwrap();
+        assert_eq!(
+            db.query_one::<String>("PRAGMA journal_mode").unwrap(),
+            "wal"
+        );
+        assert_eq!(db.query_one::<i64>("PRAGMA foreign_keys").unwrap(), 1);
+        assert_eq!(db.query_one::<i64>("PRAGMA busy_timeout").unwrap(), 5000);
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Login::default()
+            })
+            .unwrap();
+
+        // Rekeying must still work with the WAL.
+        db.rekey_database("new key").unwrap();
+        drop(db);
+        let db = LoginDb::open(&dbpath, Some("new key")).unwrap();
+        assert!(db.get_by_id(login.guid_str()).unwrap().is_some());
+
+        // A read-only connection can read the database while it's open for
+        // writing.
+        let reader = LoginDb::open_readonly(&dbpath, Some("new key"), None).unwrap();
+        assert!(reader.exists(login.guid_str()).unwrap());
+        db.delete(login.guid_str()).unwrap();
+        assert!(!reader.exists(login.guid_str()).unwrap());
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = tempdir::TempDir::new("logins_backup").unwrap();
+        let path = dir.path().join("logins.sqlite");
+        let backup_path = dir.path().join("backup.sqlite");
+        let mut db = LoginDb::open(&path, Some("testing")).unwrap();
+        let login = db
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        db.backup_to(&backup_path, Some("testing"), None).unwrap();
+
+        // The copy is a complete database in its own right.
+        let copy = LoginDb::open(&backup_path, Some("testing")).unwrap();
+        assert_eq!(
+            copy.get_by_id(login.guid_str()).unwrap().unwrap().password,
+            "password"
+        );
+        drop(copy);
+
+        db.delete(login.guid_str()).unwrap();
+        db.add(Login {
+            hostname: "https://other.example.com".into(),
+            form_submit_url: Some("https://other.example.com".into()),
+            username: "user".into(),
+            password: "password".into(),
+            ..Login::default()
+        })
+        .unwrap();
+        db.restore_from(&backup_path, Some("testing"), None)
+            .unwrap();
+        let scope = db.begin_interrupt_scope();
+        let logins = db.get_all(&scope).unwrap();
+        assert_eq!(logins.len(), 1);
+        assert_eq!(logins[0].guid, login.guid);
+
+        assert!(db
+            .restore_from(dir.path().join("missing.sqlite"), Some("testing"), None)
+            .is_err());
+        assert_eq!(db.get_all(&scope).unwrap().len(), 1);
+    }
+
     #[test]
     fn test_ensure_valid_salt() {
         assert!(ensure_valid_salt("bobo").is_err());
@@ -0,0 +1,90 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Optional encryption of the sensitive fields (`username` and `password`) of
+//! every login, using a key the embedding application manages (typically one
+//! held in the platform keystore).
+//!
+//! This is in addition to the SQLCipher encryption of the database as a
+//! whole. Once a database has had its fields encrypted (which happens the
+//! first time an `EncryptorDecryptor` is provided) it must always be opened
+//! with one, and any attempt to read or write logins without it will fail.
+//!
+//! Empty strings are never encrypted, as (for example) an empty username has
+//! meaning to the store, and tombstones store empty values.
+
+use crate::engine::PasswordEngine;
+use crate::error::*;
+use crate::observer::LoginChangeEvent;
+use sync_guid::Guid;
+
+/// Implemented by the embedding application. Implementations are expected to
+/// produce ciphertext that can round-trip through a string column. The same
+/// instance may be used by more than one connection to the database.
+pub trait EncryptorDecryptor: Send + Sync {
+    fn encrypt(&self, cleartext: &str) -> std::result::Result<String, failure::Error>;
+    fn decrypt(&self, ciphertext: &str) -> std::result::Result<String, failure::Error>;
+}
+
+/// The result of `LoginDb::check_encryption_key`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyStatus {
+    /// The key can decrypt the fields of this database.
+    Valid,
+    /// The key can't decrypt the fields of this database. If the right key
+    /// is lost, `LoginDb::reset_encrypted_data` discards the fields it
+    /// protected.
+    WrongKey,
+    /// The key can decrypt the canary, but what it decrypts to is wrong, or
+    /// the database fails an integrity check. A different key won't help.
+    Corrupt,
+    /// The fields of this database haven't been encrypted, so any key will
+    /// do.
+    NotEncrypted,
+}
+
+impl PasswordEngine {
+    /// See `LoginDb::set_encryptor_decryptor`.
+    pub fn set_encryptor_decryptor(&mut self, encdec: Box<dyn EncryptorDecryptor>) -> Result<()> {
+        let result = self.db.set_encryptor_decryptor(encdec);
+        self.share_state_with_reader();
+        result
+    }
+
+    /// See `LoginDb::rekey_fields`.
+    pub fn rekey_fields(&mut self, new_encdec: Box<dyn EncryptorDecryptor>) -> Result<()> {
+        let result = self.db.rekey_fields(new_encdec);
+        self.share_state_with_reader();
+        result
+    }
+
+    /// See `LoginDb::check_encryption_key`.
+    pub fn check_encryption_key(&self, encdec: &dyn EncryptorDecryptor) -> Result<KeyStatus> {
+        self.db.check_encryption_key(encdec)
+    }
+
+    /// See `LoginDb::reset_encrypted_data`.
+    pub fn reset_encrypted_data(&mut self, encdec: Box<dyn EncryptorDecryptor>) -> Result<()> {
+        let result = self.db.reset_encrypted_data(encdec);
+        self.share_state_with_reader();
+        result?;
+        self.observers.notify(LoginChangeEvent::Wiped);
+        Ok(())
+    }
+
+    pub fn find_undecryptable_records(&self) -> Result<Vec<Guid>> {
+        self.db.find_undecryptable_records()
+    }
+
+    /// Deletes any logins which can't be decrypted, returning how many were
+    /// deleted. See `LoginDb::delete_undecryptable_records`.
+    pub fn delete_undecryptable_records(&self) -> Result<usize> {
+        let deleted = self.db.delete_undecryptable_records()?;
+        for guid in &deleted {
+            self.observers
+                .notify(LoginChangeEvent::Deleted(guid.clone()));
+        }
+        Ok(deleted.len())
+    }
+}
@@ -1,108 +1,545 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
-use crate::db::{LoginDb, LoginStore, MigrationMetrics};
+use crate::breach::BreachChecker;
+use crate::bridge::BridgedEngine;
+use crate::db::{
+    AccessLogEntry, DeletedLogin, HistoryEntry, LoginDb, LoginOperation, LoginStats, LoginStore,
+    MaintenanceReport, MergeReport, MigrationMetrics, OriginUpgradeReport, SuspiciousAccess,
+    ValidationReport,
+};
 use crate::error::*;
-use crate::login::Login;
-use std::cell::Cell;
-use std::path::Path;
+use crate::login::{Login, MergePolicy};
+use crate::metrics::{Metrics, MetricsSink, Operation};
+use crate::observer::{LoginChangeEvent, LoginChangeObserver, Observers};
+use crate::query::LoginQuery;
+use crate::sync_config::SyncConfig;
+use sql_support::open_database::QuarantinedDatabase;
+use sql_support::retry_if_busy;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use sync15::{
     sync_multiple, telemetry, KeyBundle, MemoryCachedState, StoreSyncAssociation,
     Sync15StorageClientInit,
 };
+use sync_guid::Guid;
 
 // This isn't really an engine in the firefox sync15 desktop sense -- it's
 // really a bundle of state that contains the sync storage client, the sync
 // state, and the login DB.
+//
+// Like places, a store backed by a file has a single writer connection (`db`)
+// and a separate read-only connection which `list`, `search`, `get` and
+// friends use. Because the database is in WAL mode, these see the last
+// committed state instead of waiting for a sync or other write transaction to
+// finish.
+//
+// The methods for breach checking, password health, export and field
+// encryption are in `impl` blocks in those modules.
 pub struct PasswordEngine {
     pub db: LoginDb,
+    reader: Option<LoginDb>,
+    // Where the database is, and its salt, so that the reader can be
+    // reopened after the writer rekeys the database.
+    reader_path: Option<PathBuf>,
+    reader_salt: Option<String>,
     pub mem_cached_state: Cell<MemoryCachedState>,
+    pub(crate) observers: Observers,
+    pub(crate) metrics: Metrics,
+    pub(crate) breach_checker: Option<BreachChecker>,
+    // Deletions made by `delete_with_undo` which can still be undone.
+    pending_deletes: RefCell<HashMap<Guid, DeletedLogin>>,
 }
 
 impl PasswordEngine {
     pub fn new(path: impl AsRef<Path>, encryption_key: Option<&str>) -> Result<Self> {
+        let path = path.as_ref();
         let db = LoginDb::open(path, encryption_key)?;
-        Ok(Self {
-            db,
-            mem_cached_state: Cell::default(),
-        })
+        let reader = LoginDb::open_readonly(path, encryption_key, None)?;
+        Ok(Self::with_reader(db, reader, path, None))
     }
 
     pub fn new_with_salt(path: impl AsRef<Path>, encryption_key: &str, salt: &str) -> Result<Self> {
+        let path = path.as_ref();
         let db = LoginDb::open_with_salt(path, encryption_key, salt)?;
+        let reader = LoginDb::open_readonly(path, Some(encryption_key), Some(salt))?;
+        Ok(Self::with_reader(db, reader, path, Some(salt)))
+    }
+
+    fn with_reader(db: LoginDb, mut reader: LoginDb, path: &Path, salt: Option<&str>) -> Self {
+        db.share_state_with_reader(&mut reader);
+        Self {
+            db,
+            reader: Some(reader),
+            reader_path: Some(path.to_owned()),
+            reader_salt: salt.map(ToOwned::to_owned),
+            mem_cached_state: Cell::default(),
+            observers: Observers::default(),
+            metrics: Metrics::default(),
+            breach_checker: None,
+            pending_deletes: RefCell::default(),
+        }
+    }
+
+    /// Returns where the database was moved to if it was corrupt when the
+    /// store was opened, and had to be recreated. The logins stored in it are
+    /// lost, but a store which syncs downloads them again on the next sync.
+    pub fn quarantined_database(&self) -> Option<&QuarantinedDatabase> {
+        self.db.quarantined_database()
+    }
+
+    // The connection used for reads. In-memory and read-only stores only
+    // have one connection.
+    #[inline]
+    pub(crate) fn reader(&self) -> &LoginDb {
+        self.reader.as_ref().unwrap_or(&self.db)
+    }
+
+    pub(crate) fn share_state_with_reader(&mut self) {
+        if let Some(reader) = self.reader.as_mut() {
+            self.db.share_state_with_reader(reader);
+        }
+    }
+
+    /// Opens an existing database read-only. See `LoginDb::open_readonly`.
+    pub fn open_readonly(
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+        salt: Option<&str>,
+    ) -> Result<Self> {
+        let db = LoginDb::open_readonly(path, encryption_key, salt)?;
         Ok(Self {
             db,
+            reader: None,
+            reader_path: None,
+            reader_salt: None,
             mem_cached_state: Cell::default(),
+            observers: Observers::default(),
+            metrics: Metrics::default(),
+            breach_checker: None,
+            pending_deletes: RefCell::default(),
         })
     }
 
+    /// Creates a store which is never written to disk, and which is lost when
+    /// it's dropped. Intended for embedders' tests, which get the same
+    /// behavior as a store opened with `new`.
     pub fn new_in_memory(encryption_key: Option<&str>) -> Result<Self> {
         let db = LoginDb::open_in_memory(encryption_key)?;
         Ok(Self {
             db,
+            reader: None,
+            reader_path: None,
+            reader_salt: None,
             mem_cached_state: Cell::default(),
+            observers: Observers::default(),
+            metrics: Metrics::default(),
+            breach_checker: None,
+            pending_deletes: RefCell::default(),
         })
     }
 
     pub fn list(&self) -> Result<Vec<Login>> {
-        self.db.get_all()
+        let logins = self.list_unlogged()?;
+        self.log_access(&logins);
+        Ok(logins)
+    }
+
+    // Like `list`, but for reads made by the engine itself, which don't hand
+    // the logins to the caller, and so aren't in the access log.
+    pub(crate) fn list_unlogged(&self) -> Result<Vec<Login>> {
+        self.metrics.measure(Operation::Read, || {
+            let scope = self.reader().begin_interrupt_scope();
+            retry_if_busy(|| self.reader().get_all(&scope))
+        })
+    }
+
+    /// Like `list`, but returns a single, sorted page of logins.
+    pub fn query(&self, query: &LoginQuery) -> Result<Vec<Login>> {
+        let logins = self.metrics.measure(Operation::Read, || {
+            let scope = self.reader().begin_interrupt_scope();
+            retry_if_busy(|| self.reader().query(query, &scope))
+        })?;
+        self.log_access(&logins);
+        Ok(logins)
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<Login>> {
+        let logins = self.metrics.measure(Operation::Read, || {
+            let scope = self.reader().begin_interrupt_scope();
+            retry_if_busy(|| self.reader().search(query, &scope))
+        })?;
+        self.log_access(&logins);
+        Ok(logins)
     }
 
     pub fn get(&self, id: &str) -> Result<Option<Login>> {
-        self.db.get_by_id(id)
+        let login = self.metrics.measure(Operation::Read, || {
+            let scope = self.reader().begin_interrupt_scope();
+            retry_if_busy(|| self.reader().get_by_id_in_scope(id, &scope))
+        })?;
+        if let Some(login) = &login {
+            self.log_access(std::slice::from_ref(login));
+        }
+        Ok(login)
     }
 
     pub fn get_by_base_domain(&self, base_domain: &str) -> Result<Vec<Login>> {
-        self.db.get_by_base_domain(base_domain)
+        let logins = self.metrics.measure(Operation::Read, || {
+            let scope = self.reader().begin_interrupt_scope();
+            retry_if_busy(|| self.reader().get_by_base_domain(base_domain, &scope))
+        })?;
+        self.log_access(&logins);
+        Ok(logins)
     }
 
     pub fn potential_dupes_ignoring_username(&self, login: Login) -> Result<Vec<Login>> {
-        self.db.potential_dupes_ignoring_username(&login)
+        let logins = self.metrics.measure(Operation::Read, || {
+            retry_if_busy(|| self.reader().potential_dupes_ignoring_username(&login))
+        })?;
+        self.log_access(&logins);
+        Ok(logins)
+    }
+
+    /// See `LoginDb::set_access_logging`.
+    pub fn set_access_logging(&mut self, caller: Option<&str>) {
+        self.db.set_access_logging(caller);
+    }
+
+    pub fn get_access_log(
+        &self,
+        since_ms: i64,
+        caller: Option<&str>,
+    ) -> Result<Vec<AccessLogEntry>> {
+        self.db.get_access_log(since_ms, caller)
+    }
+
+    pub fn find_suspicious_access(
+        &self,
+        window: Duration,
+        max_logins: u64,
+    ) -> Result<Vec<SuspiciousAccess>> {
+        self.db.find_suspicious_access(window, max_logins)
+    }
+
+    // Failing to log a read doesn't fail the read, as the log is only for
+    // auditing, and the writer may be busy (for example, syncing).
+    fn log_access(&self, logins: &[Login]) {
+        if let Err(e) = self.db.record_access(logins) {
+            log::warn!("Failed to log access to {} logins: {}", logins.len(), e);
+        }
+    }
+
+    /// Set the sink which operations performed through this engine are
+    /// reported to, or stop reporting them if `sink` is `None`.
+    pub fn set_metrics_sink(&self, sink: Option<Box<dyn MetricsSink>>) {
+        self.metrics.set_sink(sink);
+    }
+
+    /// Register an observer to be notified of changes made through this
+    /// engine. Observers live as long as the engine does.
+    pub fn register_observer(&self, observer: Box<dyn LoginChangeObserver>) {
+        self.observers.register(observer);
     }
 
     pub fn touch(&self, id: &str) -> Result<()> {
-        self.db.touch(id)
+        self.metrics
+            .measure(Operation::Write, || self.db.touch(id))?;
+        self.observers
+            .notify(LoginChangeEvent::Updated(id.to_owned().into()));
+        Ok(())
     }
 
     pub fn delete(&self, id: &str) -> Result<bool> {
-        self.db.delete(id)
+        let existed = self
+            .metrics
+            .measure(Operation::Write, || self.db.delete(id))?;
+        if existed {
+            self.observers
+                .notify(LoginChangeEvent::Deleted(id.to_owned().into()));
+        }
+        Ok(existed)
+    }
+
+    /// Like `delete`, but the deletion can be undone with `undo_delete` until
+    /// `UNDO_DELETE_GRACE_PERIOD` has passed. See `LoginDb::delete_with_undo`.
+    pub fn delete_with_undo(&self, id: &str) -> Result<bool> {
+        self.purge_pending_deletes();
+        let deleted = self
+            .metrics
+            .measure(Operation::Write, || self.db.delete_with_undo(id))?;
+        Ok(match deleted {
+            Some(deleted) => {
+                let guid = deleted.login.guid.clone();
+                self.pending_deletes
+                    .borrow_mut()
+                    .insert(guid.clone(), deleted);
+                self.observers.notify(LoginChangeEvent::Deleted(guid));
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Restores a login deleted by `delete_with_undo`. Fails with
+    /// `CannotUndoDelete` if the grace period has passed, the deletion has
+    /// been synced, or the login wasn't deleted with `delete_with_undo`.
+    pub fn undo_delete(&self, id: &str) -> Result<()> {
+        self.purge_pending_deletes();
+        let deleted = match self.pending_deletes.borrow_mut().remove(&Guid::new(id)) {
+            Some(deleted) => deleted,
+            None => throw!(ErrorKind::CannotUndoDelete(id.to_owned())),
+        };
+        self.metrics
+            .measure(Operation::Write, || self.db.undo_delete(deleted))?;
+        self.observers
+            .notify(LoginChangeEvent::Added(Guid::new(id)));
+        Ok(())
+    }
+
+    // Forgets the deletions which can no longer be undone.
+    fn purge_pending_deletes(&self) {
+        self.pending_deletes
+            .borrow_mut()
+            .retain(|_, deleted| !deleted.is_expired());
+    }
+
+    /// See `LoginDb::validate`.
+    pub fn validate(
+        &self,
+        server_records: Option<&[(Guid, Option<Login>)]>,
+        repair: bool,
+    ) -> Result<ValidationReport> {
+        self.db.validate(server_records, repair)
+    }
+
+    /// Merges duplicate logins. See `LoginDb::dedupe_and_merge`.
+    pub fn dedupe_and_merge(&self) -> Result<MergeReport> {
+        let report = self
+            .metrics
+            .measure(Operation::Write, || self.db.dedupe_and_merge())?;
+        for (kept, merged) in &report.merged {
+            for guid in merged {
+                self.observers
+                    .notify(LoginChangeEvent::Deleted(Guid::new(guid)));
+            }
+            self.observers
+                .notify(LoginChangeEvent::Updated(Guid::new(kept)));
+        }
+        Ok(report)
+    }
+
+    pub fn upgrade_http_origins(&self) -> Result<OriginUpgradeReport> {
+        let report = self
+            .metrics
+            .measure(Operation::Write, || self.db.upgrade_http_origins())?;
+        for guid in &report.upgraded {
+            self.observers
+                .notify(LoginChangeEvent::Updated(Guid::new(guid)));
+        }
+        for (merged, kept) in &report.merged {
+            self.observers
+                .notify(LoginChangeEvent::Deleted(Guid::new(merged)));
+            self.observers
+                .notify(LoginChangeEvent::Updated(Guid::new(kept)));
+        }
+        Ok(report)
+    }
+
+    /// Deletes every login created in `[start_ms, end_ms)`, returning how many
+    /// were deleted.
+    pub fn delete_between(&self, start_ms: i64, end_ms: i64) -> Result<usize> {
+        let deleted = self.metrics.measure(Operation::Write, || {
+            self.db.delete_between(start_ms, end_ms)
+        })?;
+        for guid in &deleted {
+            self.observers
+                .notify(LoginChangeEvent::Deleted(guid.clone()));
+        }
+        Ok(deleted.len())
     }
 
     pub fn wipe(&self) -> Result<()> {
-        let scope = self.db.begin_interrupt_scope();
-        self.db.wipe(&scope)?;
+        self.metrics.measure(Operation::Write, || {
+            let scope = self.db.begin_interrupt_scope();
+            self.db.wipe(&scope)
+        })?;
+        self.pending_deletes.borrow_mut().clear();
+        self.observers.notify(LoginChangeEvent::Wiped);
         Ok(())
     }
 
     pub fn wipe_local(&self) -> Result<()> {
-        self.db.wipe_local()?;
+        self.metrics
+            .measure(Operation::Write, || self.db.wipe_local())?;
+        self.pending_deletes.borrow_mut().clear();
+        self.observers.notify(LoginChangeEvent::Wiped);
         Ok(())
     }
 
+    /// See `LoginDb::set_tombstone_retention`.
+    pub fn set_tombstone_retention(&mut self, retention: Duration) {
+        self.db.set_tombstone_retention(retention)
+    }
+
+    /// See `LoginDb::set_history_limit`.
+    pub fn set_history_limit(&mut self, limit: Option<usize>) {
+        self.db.set_history_limit(limit)
+    }
+
+    pub fn get_history(&self, id: &str) -> Result<Vec<HistoryEntry>> {
+        retry_if_busy(|| self.reader().get_history(id))
+    }
+
+    /// See `LoginDb::set_sync_config`.
+    pub fn set_sync_config(&mut self, config: SyncConfig) -> Result<()> {
+        self.db.set_sync_config(config)
+    }
+
+    /// See `LoginDb::set_merge_policy`.
+    pub fn set_merge_policy(&mut self, policy: MergePolicy) {
+        self.db.set_merge_policy(policy)
+    }
+
+    pub fn stats(&self) -> Result<LoginStats> {
+        retry_if_busy(|| self.reader().stats())
+    }
+
+    pub fn count_tombstones(&self) -> Result<u64> {
+        self.db.count_tombstones()
+    }
+
+    pub fn run_maintenance(&self, tombstone_max_age: Duration) -> Result<MaintenanceReport> {
+        self.metrics.measure(Operation::Write, || {
+            self.db.run_maintenance(tombstone_max_age)
+        })
+    }
+
     pub fn reset(&self) -> Result<()> {
         self.db.reset(&StoreSyncAssociation::Disconnected)?;
         Ok(())
     }
 
     pub fn update(&self, login: Login) -> Result<()> {
-        self.db.update(login)
+        let guid = login.guid.clone();
+        self.metrics
+            .measure(Operation::Write, || self.db.update(login))?;
+        self.observers.notify(LoginChangeEvent::Updated(guid));
+        Ok(())
+    }
+
+    /// See `LoginDb::set_local_only`.
+    pub fn set_local_only(&self, id: &str, local_only: bool) -> Result<()> {
+        self.metrics
+            .measure(Operation::Write, || self.db.set_local_only(id, local_only))?;
+        self.observers
+            .notify(LoginChangeEvent::Updated(Guid::new(id)));
+        Ok(())
+    }
+
+    pub fn is_local_only(&self, id: &str) -> Result<bool> {
+        retry_if_busy(|| self.reader().is_local_only(id))
+    }
+
+    pub fn update_password(&self, id: &str, new_password: &str) -> Result<()> {
+        self.metrics.measure(Operation::Write, || {
+            self.db.update_password(id, new_password)
+        })?;
+        self.observers
+            .notify(LoginChangeEvent::Updated(Guid::new(id)));
+        Ok(())
     }
 
     pub fn add(&self, login: Login) -> Result<String> {
+        let record = self
+            .metrics
+            .measure(Operation::Write, || self.db.add(login))?;
+        self.observers
+            .notify(LoginChangeEvent::Added(record.guid.clone()));
         // Just return the record's ID (which we may have generated).
-        self.db.add(login).map(|record| record.guid.into_string())
+        Ok(record.guid.into_string())
+    }
+
+    /// Applies `ops` in a single transaction. See `LoginDb::apply_batch`.
+    pub fn apply_batch(&self, ops: Vec<LoginOperation>) -> Result<Vec<Result<Guid>>> {
+        let events: Vec<fn(Guid) -> LoginChangeEvent> = ops
+            .iter()
+            .map(|op| match op {
+                LoginOperation::Add { .. } => LoginChangeEvent::Added,
+                LoginOperation::Update { .. } => LoginChangeEvent::Updated,
+                LoginOperation::Delete { .. } => LoginChangeEvent::Deleted,
+            })
+            .collect();
+        let results = self
+            .metrics
+            .measure(Operation::Write, || self.db.apply_batch(ops))?;
+        for (event, result) in events.into_iter().zip(&results) {
+            if let Ok(guid) = result {
+                self.observers.notify(event(guid.clone()));
+            }
+        }
+        Ok(results)
     }
 
     pub fn import_multiple(&self, logins: &[Login]) -> Result<MigrationMetrics> {
-        self.db.import_multiple(logins)
+        let mut imported = Vec::new();
+        let metrics = self.metrics.measure(Operation::Write, || {
+            self.db.import_multiple_recording(logins, &mut imported)
+        })?;
+        self.notify_added(imported);
+        Ok(metrics)
+    }
+
+    pub(crate) fn notify_added(&self, guids: Vec<Guid>) {
+        for guid in guids {
+            self.observers.notify(LoginChangeEvent::Added(guid));
+        }
+    }
+
+    /// See `LoginDb::backup_to`.
+    pub fn backup_to(
+        &self,
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+        salt: Option<&str>,
+    ) -> Result<()> {
+        self.db.backup_to(path, encryption_key, salt)
+    }
+
+    /// See `LoginDb::restore_from`.
+    pub fn restore_from(
+        &mut self,
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+        salt: Option<&str>,
+    ) -> Result<()> {
+        let result = self.db.restore_from(path, encryption_key, salt);
+        self.share_state_with_reader();
+        result?;
+        self.observers.notify(LoginChangeEvent::Wiped);
+        Ok(())
     }
 
     pub fn disable_mem_security(&self) -> Result<()> {
         self.db.disable_mem_security()
     }
 
-    pub fn rekey_database(&self, new_encryption_key: &str) -> Result<()> {
-        self.db.rekey_database(new_encryption_key)
+    /// Re-encrypts the database with `new_encryption_key`. The reader can't
+    /// read the re-encrypted pages with the old key, so it's reopened with
+    /// the new one.
+    pub fn rekey_database(&mut self, new_encryption_key: &str) -> Result<()> {
+        self.db.rekey_database(new_encryption_key)?;
+        if let Some(path) = &self.reader_path {
+            self.reader = Some(LoginDb::open_readonly(
+                path,
+                Some(new_encryption_key),
+                self.reader_salt.as_deref(),
+            )?);
+            self.share_state_with_reader();
+        }
+        Ok(())
     }
 
     // This is basically exposed just for sync_pass_sql, but it doesn't seem
@@ -111,8 +548,19 @@ impl PasswordEngine {
         &self.db.db
     }
 
+    /// Returns a bridged sync engine for Desktop for this store. As with
+    /// `sync`, observers are told about the records it applies.
+    pub fn bridged_engine(&self) -> BridgedEngine<'_> {
+        BridgedEngine::with_observers(&self.db, &self.observers)
+    }
+
+    /// Returns a handle which interrupts whatever the engine is doing, on
+    /// both the writer and the read-only connection.
     pub fn new_interrupt_handle(&self) -> sql_support::SqlInterruptHandle {
-        self.db.new_interrupt_handle()
+        match &self.reader {
+            Some(reader) => self.db.new_interrupt_handle_with_reader(reader),
+            None => self.db.new_interrupt_handle(),
+        }
     }
 
     /// A convenience wrapper around sync_multiple.
@@ -120,6 +568,16 @@ impl PasswordEngine {
         &self,
         storage_init: &Sync15StorageClientInit,
         root_sync_key: &KeyBundle,
+    ) -> Result<telemetry::SyncTelemetryPing> {
+        self.metrics.measure(Operation::Sync, || {
+            self.do_sync(storage_init, root_sync_key)
+        })
+    }
+
+    fn do_sync(
+        &self,
+        storage_init: &Sync15StorageClientInit,
+        root_sync_key: &KeyBundle,
     ) -> Result<telemetry::SyncTelemetryPing> {
         // migrate our V1 state - this needn't live for long.
         self.db.migrate_global_state()?;
@@ -141,16 +599,58 @@ impl PasswordEngine {
         // if it needs to be dropped (ie, they will be None or contain Nones etc)
         self.db.set_global_state(&disk_cached_state)?;
 
+        // Records may have been applied even if the sync later failed.
+        self.notify_sync_applied(&store);
+
         // for b/w compat reasons, we do some dances with the result.
         // XXX - note that this means telemetry isn't going to be reported back
         // to the app - we need to check with lockwise about whether they really
         // need these failures to be reported or whether we can loosen this.
-        if let Err(e) = result.result {
-            return Err(e.into());
+        let outcome: Result<()> = match result.result {
+            Err(e) => Err(e.into()),
+            Ok(()) => match result
+                .engine_results
+                .remove(self.db.sync_config().collection_name.as_str())
+            {
+                None | Some(Ok(())) => Ok(()),
+                Some(Err(e)) => Err(e.into()),
+            },
+        };
+        if let Err(e) = outcome {
+            if is_network_error(&e) {
+                // Our local changes are still waiting in `loginsL`, and will
+                // be merged with whatever's on the server whenever the next
+                // sync gets through.
+                self.db.record_pending_sync()?;
+            }
+            return Err(e);
         }
-        match result.engine_results.remove("passwords") {
-            None | Some(Ok(())) => Ok(result.telemetry),
-            Some(Err(e)) => Err(e.into()),
+        Ok(result.telemetry)
+    }
+
+    /// Syncs if there are local changes made at or before `before` which
+    /// haven't been uploaded, or a sync which was attempted by then failed
+    /// to reach the server. Returns `None` if no sync was needed.
+    pub fn ensure_synced_before(
+        &self,
+        storage_init: &Sync15StorageClientInit,
+        root_sync_key: &KeyBundle,
+        before: SystemTime,
+    ) -> Result<Option<telemetry::SyncTelemetryPing>> {
+        if !self.db.needs_sync_before(before)? {
+            return Ok(None);
+        }
+        self.sync(storage_init, root_sync_key).map(Some)
+    }
+
+    /// Tells observers about the incoming records `store` applied. Embedders
+    /// which sync the store themselves, such as the sync manager, should
+    /// call this once the sync is done, whether or not it succeeded.
+    pub fn notify_sync_applied(&self, store: &LoginStore<'_>) {
+        let applied = store.take_applied_guids();
+        if !applied.is_empty() {
+            self.observers
+                .notify(LoginChangeEvent::SyncApplied(applied));
         }
     }
 
@@ -159,11 +659,23 @@ impl PasswordEngine {
     }
 }
 
+fn is_network_error(e: &Error) -> bool {
+    match e.kind() {
+        ErrorKind::SyncAdapterError(e) => match e.kind() {
+            sync15::ErrorKind::RequestError(_) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::encryption::EncryptorDecryptor;
     use crate::util;
     use more_asserts::*;
+    use sql_support::ConnExt;
     use std::time::SystemTime;
     use sync_guid::Guid;
     // Doesn't check metadata fields
@@ -294,12 +806,222 @@ mod test {
     }
 
     #[test]
-    fn test_rekey() {
+    fn test_observers() {
+        use std::sync::{Arc, Mutex};
+        use sync15_traits::BridgedEngine as _;
+        struct Recorder(Arc<Mutex<Vec<LoginChangeEvent>>>);
+        impl LoginChangeObserver for Recorder {
+            fn on_change(&self, event: &LoginChangeEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
         let engine = PasswordEngine::new_in_memory(Some("secret")).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        engine.register_observer(Box::new(Recorder(events.clone())));
+
+        let guid = engine
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                http_realm: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "pass".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        let mut login = engine.get(&guid).unwrap().unwrap();
+        login.password = "new pass".into();
+        engine.update(login).unwrap();
+        engine.set_local_only(&guid, true).unwrap();
+        // Failed operations don't notify.
+        assert!(engine.update(Login::default()).is_err());
+        assert!(engine.set_local_only("missing", true).is_err());
+        assert!(engine.delete(&guid).unwrap());
+        assert!(!engine.delete(&guid).unwrap());
+        engine.wipe_local().unwrap();
+
+        // Imported logins are reported as added.
+        let imported = Login {
+            guid: Guid::new("imported_001"),
+            hostname: "https://www.example.org".into(),
+            http_realm: Some("https://www.example.org".into()),
+            username: "user".into(),
+            password: "pass".into(),
+            ..Login::default()
+        };
+        engine.import_multiple(&[imported]).unwrap();
+
+        // So are records applied and wiped by the bridged engine.
+        let bridged = engine.bridged_engine();
+        let incoming: sync15_traits::IncomingEnvelope = serde_json::from_value(serde_json::json!({
+            "id": "remote_00001",
+            "modified": 1.0,
+            "cleartext": serde_json::json!({
+                "id": "remote_00001",
+                "hostname": "https://remote.example.com",
+                "formSubmitURL": "https://remote.example.com",
+                "username": "remote",
+                "password": "password",
+            }).to_string(),
+        }))
+        .unwrap();
+        bridged.store_incoming(&[incoming]).unwrap();
+        bridged.apply().unwrap();
+        bridged.wipe().unwrap();
+
+        let guid = Guid::from(guid);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                LoginChangeEvent::Added(guid.clone()),
+                LoginChangeEvent::Updated(guid.clone()),
+                LoginChangeEvent::Updated(guid.clone()),
+                LoginChangeEvent::Deleted(guid),
+                LoginChangeEvent::Wiped,
+                LoginChangeEvent::Added(Guid::new("imported_001")),
+                LoginChangeEvent::SyncApplied(vec![Guid::new("remote_00001")]),
+                LoginChangeEvent::Wiped,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undo_delete() {
+        let engine = PasswordEngine::new_in_memory(Some("secret")).unwrap();
+        let guid = engine
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                http_realm: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "pass".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        let login = engine.get(&guid).unwrap().unwrap();
+
+        assert!(engine.delete_with_undo(&guid).unwrap());
+        assert!(!engine.delete_with_undo(&guid).unwrap());
+        assert!(engine.get(&guid).unwrap().is_none());
+        engine.undo_delete(&guid).unwrap();
+        assert_eq!(engine.get(&guid).unwrap().unwrap(), login);
+
+        // Each deletion can only be undone once.
+        match engine.undo_delete(&guid).unwrap_err().kind() {
+            ErrorKind::CannotUndoDelete(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+        // Deletions which weren't made with `delete_with_undo` can't be.
+        assert!(engine.delete(&guid).unwrap());
+        match engine.undo_delete(&guid).unwrap_err().kind() {
+            ErrorKind::CannotUndoDelete(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_reader() {
+        struct Prefix;
+        impl EncryptorDecryptor for Prefix {
+            fn encrypt(&self, cleartext: &str) -> std::result::Result<String, failure::Error> {
+                Ok(format!("enc:{}", cleartext))
+            }
+            fn decrypt(&self, ciphertext: &str) -> std::result::Result<String, failure::Error> {
+                if ciphertext.starts_with("enc:") {
+                    Ok(ciphertext[4..].to_owned())
+                } else {
+                    Err(failure::err_msg("not encrypted"))
+                }
+            }
+        }
+
+        let dir = tempdir::TempDir::new("engine_reader").unwrap();
+        let mut engine = PasswordEngine::new(dir.path().join("logins.sqlite"), None).unwrap();
+        assert!(engine.reader.is_some());
+        let guid = engine
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "pass".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        assert_eq!(engine.list().unwrap().len(), 1);
+
+        // Reads see the last committed state while a write transaction is in
+        // progress, rather than waiting for it.
+        let tx = engine.db.unchecked_transaction_imm().unwrap();
+        engine
+            .db
+            .execute("DELETE FROM loginsL", rusqlite::NO_PARAMS)
+            .unwrap();
+        assert!(engine.get(&guid).unwrap().is_some());
+        tx.commit().unwrap();
+        assert!(engine.get(&guid).unwrap().is_none());
+
+        // The reader decrypts with the writer's key.
+        engine.set_encryptor_decryptor(Box::new(Prefix)).unwrap();
+        let guid = engine
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "secret".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        assert_eq!(engine.get(&guid).unwrap().unwrap().password, "secret");
+
+        // Interrupting the engine interrupts reads too.
+        let scope = engine.reader().begin_interrupt_scope();
+        engine.new_interrupt_handle().interrupt();
+        assert!(scope.err_if_interrupted().is_err());
+        match engine
+            .reader()
+            .get_by_id_in_scope(&guid, &scope)
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::Interrupted(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_rekey() {
+        let mut engine = PasswordEngine::new_in_memory(Some("secret")).unwrap();
         engine.rekey_database("new_encryption_key").unwrap();
         let list = engine.list().expect("Grabbing Empty list to work");
         assert_eq!(list.len(), 0);
     }
+
+    #[test]
+    fn test_rekey_with_reader() {
+        let dir = tempdir::TempDir::new("engine_rekey").unwrap();
+        let path = dir.path().join("logins.sqlite");
+        let mut engine = PasswordEngine::new(&path, Some("secret")).unwrap();
+        let guid = engine
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "pass".into(),
+                ..Login::default()
+            })
+            .unwrap();
+
+        engine.rekey_database("new_encryption_key").unwrap();
+        assert!(engine.reader.is_some());
+        // Reads go through the reader, which must use the new key.
+        assert_eq!(engine.list().unwrap().len(), 1);
+        assert_eq!(engine.get(&guid).unwrap().unwrap().password, "pass");
+        engine.delete(&guid).unwrap();
+        assert!(engine.get(&guid).unwrap().is_none());
+        drop(engine);
+
+        let engine = PasswordEngine::new(&path, Some("new_encryption_key")).unwrap();
+        assert!(engine.list().unwrap().is_empty());
+    }
 }
 
 #[test]
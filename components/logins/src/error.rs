@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use failure::Fail;
+use sql_support::{open_database, MaybeBusy};
 
 // TODO: this is (IMO) useful and was dropped from `failure`, consider moving it
 // into `error_support`.
@@ -39,15 +40,69 @@ pub enum ErrorKind {
     #[fail(display = "The provided salt is invalid")]
     InvalidSalt,
 
+    #[fail(
+        display = "The deletion of {:?} can't be undone, as it was synced or changed",
+        _0
+    )]
+    CannotUndoDelete(String),
+
+    #[fail(display = "The database was opened read-only")]
+    ReadOnly,
+
+    #[fail(display = "Database version {} is not supported", _0)]
+    UnsupportedDatabaseVersion(i64),
+
+    #[fail(display = "Failed to encrypt a login field: {}", _0)]
+    EncryptionFailed(String),
+
+    // Most likely the key used to encrypt the fields was lost or changed.
+    #[fail(display = "Failed to decrypt a login field: {}", _0)]
+    DecryptionFailed(String),
+
+    #[fail(display = "Invalid export file: {}", _0)]
+    InvalidExportFile(String),
+
+    #[fail(display = "The CSV file has no {:?} column", _0)]
+    MissingCsvColumn(String),
+
+    #[fail(display = "Breach check failed: {}", _0)]
+    BreachCheckFailed(String),
+
+    #[fail(display = "Too many breach checks have been made recently")]
+    BreachCheckRateLimited,
+
+    #[fail(display = "The store at {} is already open with a different key", _0)]
+    StoreOpenWithDifferentKey(String),
+
+    #[fail(display = "Invalid sync config: {}", _0)]
+    InvalidSyncConfig(String),
+
     #[fail(display = "Error synchronizing: {}", _0)]
     SyncAdapterError(#[fail(cause)] sync15::Error),
 
+    #[fail(display = "Network error: {}", _0)]
+    RequestError(#[fail(cause)] viaduct::Error),
+
     #[fail(display = "Error parsing JSON data: {}", _0)]
     JsonError(#[fail(cause)] serde_json::Error),
 
+    #[fail(display = "Error reading CSV data: {}", _0)]
+    CsvError(#[fail(cause)] csv::Error),
+
     #[fail(display = "Error executing SQL: {}", _0)]
     SqlError(#[fail(cause)] rusqlite::Error),
 
+    #[fail(display = "Error opening database: {}", _0)]
+    OpenDatabaseError(#[fail(cause)] open_database::Error),
+
+    /// Another connection holds a lock on the database, so the operation can
+    /// be tried again later.
+    #[fail(display = "The database is busy: {}", _0)]
+    DatabaseBusy(#[fail(cause)] rusqlite::Error),
+
+    #[fail(display = "IO error: {}", _0)]
+    IoError(#[fail(cause)] std::io::Error),
+
     #[fail(display = "Error parsing URL: {}", _0)]
     UrlParseError(#[fail(cause)] url::ParseError),
 
@@ -56,17 +111,63 @@ pub enum ErrorKind {
 
     #[fail(display = "Protobuf decode error: {}", _0)]
     ProtobufDecodeError(#[fail(cause)] prost::DecodeError),
+
+    #[fail(display = "{}", _0)]
+    IncomingPayloadError(#[fail(cause)] sync15_traits::bridged_engine::PayloadError),
 }
 
 error_support::define_error! {
     ErrorKind {
         (SyncAdapterError, sync15::Error),
+        (RequestError, viaduct::Error),
         (JsonError, serde_json::Error),
+        (CsvError, csv::Error),
         (UrlParseError, url::ParseError),
-        (SqlError, rusqlite::Error),
+        (IoError, std::io::Error),
         (InvalidLogin, InvalidLogin),
         (Interrupted, interrupt_support::Interrupted),
         (ProtobufDecodeError, prost::DecodeError),
+        (IncomingPayloadError, sync15_traits::bridged_engine::PayloadError),
+    }
+}
+
+// SQL errors are converted by hand, so that lock contention becomes
+// `DatabaseBusy`.
+impl From<rusqlite::Error> for ErrorKind {
+    #[cold]
+    fn from(e: rusqlite::Error) -> ErrorKind {
+        if e.is_busy() {
+            ErrorKind::DatabaseBusy(e)
+        } else {
+            ErrorKind::SqlError(e)
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    #[cold]
+    fn from(e: rusqlite::Error) -> Self {
+        ErrorKind::from(e).into()
+    }
+}
+
+// SQL errors from opening the database are unwrapped, so that a wrong key is
+// still a `SqlError`.
+impl From<open_database::Error> for ErrorKind {
+    #[cold]
+    fn from(e: open_database::Error) -> ErrorKind {
+        match e {
+            open_database::Error::SqlError(e) => e.into(),
+            open_database::Error::IoError(e) => ErrorKind::IoError(e),
+            e => ErrorKind::OpenDatabaseError(e),
+        }
+    }
+}
+
+impl From<open_database::Error> for Error {
+    #[cold]
+    fn from(e: open_database::Error) -> Self {
+        ErrorKind::from(e).into()
     }
 }
 
@@ -97,10 +198,26 @@ impl Error {
             ErrorKind::NoSuchRecord(_) => "NoSuchRecord",
             ErrorKind::NonEmptyTable => "NonEmptyTable",
             ErrorKind::InvalidSalt => "InvalidSalt",
+            ErrorKind::CannotUndoDelete(_) => "CannotUndoDelete",
+            ErrorKind::ReadOnly => "ReadOnly",
+            ErrorKind::UnsupportedDatabaseVersion(_) => "UnsupportedDatabaseVersion",
+            ErrorKind::EncryptionFailed(_) => "EncryptionFailed",
+            ErrorKind::DecryptionFailed(_) => "DecryptionFailed",
+            ErrorKind::InvalidExportFile(_) => "InvalidExportFile",
+            ErrorKind::MissingCsvColumn(_) => "MissingCsvColumn",
+            ErrorKind::BreachCheckFailed(_) => "BreachCheckFailed",
+            ErrorKind::BreachCheckRateLimited => "BreachCheckRateLimited",
+            ErrorKind::StoreOpenWithDifferentKey(_) => "StoreOpenWithDifferentKey",
+            ErrorKind::InvalidSyncConfig(_) => "InvalidSyncConfig",
             ErrorKind::SyncAdapterError(_) => "SyncAdapterError",
+            ErrorKind::RequestError(_) => "RequestError",
             ErrorKind::JsonError(_) => "JsonError",
+            ErrorKind::CsvError(_) => "CsvError",
             ErrorKind::UrlParseError(_) => "UrlParseError",
+            ErrorKind::IoError(_) => "IoError",
             ErrorKind::SqlError(_) => "SqlError",
+            ErrorKind::OpenDatabaseError(_) => "OpenDatabaseError",
+            ErrorKind::DatabaseBusy(_) => "DatabaseBusy",
             ErrorKind::Interrupted(_) => "Interrupted",
             ErrorKind::InvalidLogin(desc) => match desc {
                 InvalidLogin::EmptyOrigin => "InvalidLogin::EmptyOrigin",
@@ -111,6 +228,40 @@ impl Error {
                 InvalidLogin::IllegalFieldValue { .. } => "InvalidLogin::IllegalFieldValue",
             },
             ErrorKind::ProtobufDecodeError(_) => "BufDecodeError",
+            ErrorKind::IncomingPayloadError(_) => "IncomingPayloadError",
+        }
+    }
+}
+
+impl MaybeBusy for Error {
+    fn is_busy(&self) -> bool {
+        match self.kind() {
+            ErrorKind::DatabaseBusy(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlite_error(code: std::os::raw::c_int) -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None)
+    }
+
+    #[test]
+    fn test_busy_errors() {
+        for code in &[rusqlite::ffi::SQLITE_BUSY, rusqlite::ffi::SQLITE_LOCKED] {
+            let err = Error::from(sqlite_error(*code));
+            assert!(matches!(err.kind(), ErrorKind::DatabaseBusy(_)));
+            assert!(err.is_busy());
+            let err = Error::from(open_database::Error::SqlError(sqlite_error(*code)));
+            assert!(err.is_busy());
         }
+
+        let err = Error::from(sqlite_error(rusqlite::ffi::SQLITE_CORRUPT));
+        assert!(matches!(err.kind(), ErrorKind::SqlError(_)));
+        assert!(!err.is_busy());
     }
 }
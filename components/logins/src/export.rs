@@ -0,0 +1,405 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Export of logins to an encrypted file which can be imported into another
+//! store, on this device or another one.
+//!
+//! The file is JSON, and looks like:
+//!
+//! ```json
+//! {
+//!   "format": "logins-export",
+//!   "version": 2,
+//!   "kdf": "...",
+//!   "cipher": { "algorithm": "aes-256-gcm", "nonce": "..." },
+//!   "ciphertext": "..."
+//! }
+//! ```
+//!
+//! The ciphertext is the (base64 encoded) encryption of the logins, as JSON,
+//! with a key derived from the passphrase. Everything but the ciphertext is
+//! authenticated too, so the header can't be changed without the import
+//! failing. `kdf` is a (base64 encoded) `rc_crypto::kdf::KdfHeader`, which
+//! records how the key was derived, so later versions can change the KDF or
+//! its parameters and still read older files. Version 1 files, which spelled
+//! out the PBKDF2 parameters as
+//! `{ "algorithm": "pbkdf2-hmac-sha256", "iterations": 100000, "salt": "..." }`,
+//! can still be imported.
+
+use crate::db::LoginDb;
+use crate::engine::PasswordEngine;
+use crate::error::*;
+use crate::login::Login;
+use crate::metrics::Operation;
+use crate::migrate_desktop::{import_login, tally, ImportReport, Outcome};
+use rc_crypto::kdf::{Algorithm, KdfHeader};
+use rc_crypto::{aead, digest, pbkdf2, rand};
+use serde_derive::*;
+use std::path::Path;
+use sync_guid::Guid;
+
+const EXPORT_FORMAT: &str = "logins-export";
+const EXPORT_VERSION: u32 = 2;
+
+const KDF_PBKDF2_HMAC_SHA256: &str = "pbkdf2-hmac-sha256";
+const CIPHER_AES_256_GCM: &str = "aes-256-gcm";
+
+const DEFAULT_ITERATIONS: u32 = 100_000;
+// Iteration counts above this are refused when importing a version 1 file,
+// as a file which asks for more would take an unreasonable time to derive the
+// key for. `KdfHeader` has its own limits.
+const MAX_ITERATIONS: u32 = 10_000_000;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Kdf {
+    /// An encoded `KdfHeader`, as written since version 2.
+    Header(String),
+    /// The PBKDF2 parameters written by version 1.
+    Params(KdfParams),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    iterations: u32,
+    salt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CipherParams {
+    algorithm: String,
+    nonce: String,
+}
+
+// The part of the file which is passed to AES-GCM as additional data.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    kdf: Kdf,
+    cipher: CipherParams,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportFile {
+    #[serde(flatten)]
+    header: Header,
+    ciphertext: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedLogins<T> {
+    logins: T,
+}
+
+/// Writes every login in `db` to a new file at `path`, encrypted with
+/// `passphrase`. Returns the number of logins exported.
+pub fn export(db: &LoginDb, path: impl AsRef<Path>, passphrase: &str) -> Result<usize> {
+    export_with_iterations(db, path.as_ref(), passphrase, DEFAULT_ITERATIONS)
+}
+
+fn export_with_iterations(
+    db: &LoginDb,
+    path: &Path,
+    passphrase: &str,
+    iterations: u32,
+) -> Result<usize> {
+    rc_crypto::ensure_initialized();
+    let logins = db.get_all(&db.begin_interrupt_scope())?;
+    let plaintext = serde_json::to_vec(&ExportedLogins { logins: &logins })?;
+
+    let kdf = KdfHeader::new(Algorithm::Pbkdf2HmacSha256 { iterations }).map_err(crypto_error)?;
+    let mut key = [0u8; KEY_LEN];
+    kdf.derive(passphrase.as_bytes(), &mut key)
+        .map_err(crypto_error)?;
+    let kdf = Kdf::Header(base64::encode(&kdf.to_bytes().map_err(crypto_error)?));
+    write_file(path, EXPORT_VERSION, kdf, &key, &plaintext)?;
+    log::info!("Exported {} logins", logins.len());
+    Ok(logins.len())
+}
+
+// Encrypts `plaintext` with `key`, and writes it and the header to `path`.
+fn write_file(path: &Path, version: u32, kdf: Kdf, key: &[u8], plaintext: &[u8]) -> Result<()> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::fill(&mut nonce).map_err(crypto_error)?;
+    let header = Header {
+        format: EXPORT_FORMAT.into(),
+        version,
+        kdf,
+        cipher: CipherParams {
+            algorithm: CIPHER_AES_256_GCM.into(),
+            nonce: base64::encode(&nonce),
+        },
+    };
+
+    let key = aead::SealingKey::new(&aead::AES_256_GCM, key).map_err(crypto_error)?;
+    let nonce =
+        aead::Nonce::try_assume_unique_for_key(&aead::AES_256_GCM, &nonce).map_err(crypto_error)?;
+    let aad = serde_json::to_vec(&header)?;
+    let ciphertext =
+        aead::seal(&key, nonce, aead::Aad::from(&aad), plaintext).map_err(crypto_error)?;
+
+    let file = ExportFile {
+        header,
+        ciphertext: base64::encode(&ciphertext),
+    };
+    std::fs::write(path, serde_json::to_vec_pretty(&file)?)?;
+    Ok(())
+}
+
+/// Imports the logins in a file written by `export`. As with
+/// `migrate_desktop::import_logins_json`, the store doesn't need to be empty;
+/// logins which duplicate an existing one are skipped.
+pub fn import_exported_file(
+    db: &LoginDb,
+    path: impl AsRef<Path>,
+    passphrase: &str,
+) -> Result<ImportReport> {
+    import_exported_file_recording(db, path, passphrase, &mut Vec::new())
+}
+
+// Like `import_exported_file`, but also adds the GUIDs of the imported logins
+// to `imported`, so the engine can tell observers about them.
+pub(crate) fn import_exported_file_recording(
+    db: &LoginDb,
+    path: impl AsRef<Path>,
+    passphrase: &str,
+    imported: &mut Vec<Guid>,
+) -> Result<ImportReport> {
+    rc_crypto::ensure_initialized();
+    let file: ExportFile = serde_json::from_slice(&std::fs::read(path)?)?;
+    let header = &file.header;
+    if header.format != EXPORT_FORMAT {
+        throw!(ErrorKind::InvalidExportFile(format!(
+            "unknown format {:?}",
+            header.format
+        )));
+    }
+    if header.version > EXPORT_VERSION {
+        throw!(ErrorKind::InvalidExportFile(format!(
+            "version {} is newer than this version supports",
+            header.version
+        )));
+    }
+    if header.cipher.algorithm != CIPHER_AES_256_GCM {
+        throw!(ErrorKind::InvalidExportFile(format!(
+            "unknown cipher {:?}",
+            header.cipher.algorithm
+        )));
+    }
+
+    let key = derive_key(&header.kdf, passphrase)?;
+    let nonce = decode_field("nonce", &header.cipher.nonce)?;
+    let ciphertext = decode_field("ciphertext", &file.ciphertext)?;
+    let key = aead::OpeningKey::new(&aead::AES_256_GCM, &key).map_err(crypto_error)?;
+    let nonce = aead::Nonce::try_assume_unique_for_key(&aead::AES_256_GCM, &nonce)
+        .map_err(|_| ErrorKind::InvalidExportFile("invalid nonce".into()))?;
+    let aad = serde_json::to_vec(header)?;
+    // A wrong passphrase and a damaged file look the same to AES-GCM.
+    let plaintext = aead::open(&key, nonce, aead::Aad::from(&aad), &ciphertext).map_err(|_| {
+        ErrorKind::DecryptionFailed("wrong passphrase, or the file was changed".into())
+    })?;
+
+    let exported: ExportedLogins<Vec<serde_json::Value>> = serde_json::from_slice(&plaintext)?;
+    let outcomes = exported.logins.into_iter().map(|value| -> Result<Outcome> {
+        let login: Login = serde_json::from_value(value)?;
+        let outcome = import_login(db, login.fixup()?)?;
+        if let Outcome::Imported(guid) = &outcome {
+            imported.push(guid.clone());
+        }
+        Ok(outcome)
+    });
+    Ok(tally(outcomes, "Exported"))
+}
+
+fn derive_key(kdf: &Kdf, passphrase: &str) -> Result<[u8; KEY_LEN]> {
+    match kdf {
+        Kdf::Header(encoded) => {
+            let bytes = decode_field("KDF header", encoded)?;
+            let (header, rest) = KdfHeader::from_bytes(&bytes)
+                .map_err(|e| ErrorKind::InvalidExportFile(format!("invalid KDF header: {}", e)))?;
+            if !rest.is_empty() {
+                throw!(ErrorKind::InvalidExportFile(
+                    "trailing data after the KDF header".into()
+                ));
+            }
+            let mut key = [0u8; KEY_LEN];
+            header
+                .derive(passphrase.as_bytes(), &mut key)
+                .map_err(crypto_error)?;
+            Ok(key)
+        }
+        Kdf::Params(params) => {
+            if params.algorithm != KDF_PBKDF2_HMAC_SHA256 {
+                throw!(ErrorKind::InvalidExportFile(format!(
+                    "unknown KDF {:?}",
+                    params.algorithm
+                )));
+            }
+            if params.iterations == 0 || params.iterations > MAX_ITERATIONS {
+                throw!(ErrorKind::InvalidExportFile(format!(
+                    "unsupported iteration count {}",
+                    params.iterations
+                )));
+            }
+            let salt = decode_field("salt", &params.salt)?;
+            pbkdf2_hmac_sha256(passphrase.as_bytes(), &salt, params.iterations)
+        }
+    }
+}
+
+fn decode_field(name: &str, value: &str) -> Result<Vec<u8>> {
+    Ok(base64::decode(value)
+        .map_err(|e| ErrorKind::InvalidExportFile(format!("invalid {}: {}", name, e)))?)
+}
+
+fn crypto_error(e: rc_crypto::Error) -> Error {
+    ErrorKind::EncryptionFailed(e.to_string()).into()
+}
+
+fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32) -> Result<[u8; KEY_LEN]> {
+    let mut key = [0u8; KEY_LEN];
+    pbkdf2::derive(&digest::SHA256, iterations, salt, password, &mut key).map_err(crypto_error)?;
+    Ok(key)
+}
+
+impl PasswordEngine {
+    /// See the `export` function.
+    pub fn export(&self, path: impl AsRef<Path>, passphrase: &str) -> Result<usize> {
+        export(&self.db, path, passphrase)
+    }
+
+    /// See the `import_exported_file` function.
+    pub fn import_exported_file(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+    ) -> Result<ImportReport> {
+        let mut imported = Vec::new();
+        let report = self.metrics.measure(Operation::Write, || {
+            import_exported_file_recording(&self.db, path, passphrase, &mut imported)
+        });
+        // Logins are added one at a time, so some may have been imported even
+        // if the file couldn't be read to the end.
+        self.notify_added(imported);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn login(i: usize) -> Login {
+        Login {
+            guid: format!("dummy_{:06}", i).into(),
+            hostname: format!("https://{}.example.com", i),
+            form_submit_url: Some(format!("https://{}.example.com", i)),
+            username: "user".into(),
+            password: "hunter2".into(),
+            ..Login::default()
+        }
+    }
+
+    #[test]
+    fn test_pbkdf2() {
+        rc_crypto::ensure_initialized();
+        // From RFC 7914, section 11.
+        let key = pbkdf2_hmac_sha256(b"passwd", b"salt", 1).unwrap();
+        assert_eq!(
+            key,
+            [
+                0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f, 0xec, 0x16, 0x91, 0xc2, 0x25, 0x44,
+                0xb6, 0x05, 0xf9, 0x41, 0x85, 0x21, 0x6d, 0xde, 0x04, 0x65, 0xe6, 0x8b, 0x9d, 0x57,
+                0xc2, 0x0d, 0xac, 0xbc,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let tmpdir = TempDir::new("test_export_and_import").unwrap();
+        let path = tmpdir.path().join("logins.json");
+
+        let source = LoginDb::open_in_memory(Some("testing")).unwrap();
+        for i in 0..3 {
+            source.add(login(i)).unwrap();
+        }
+        assert_eq!(
+            export_with_iterations(&source, &path, "correct horse", 10).unwrap(),
+            3
+        );
+
+        let dest = LoginDb::open_in_memory(Some("testing")).unwrap();
+        dest.add(login(1)).unwrap();
+        let err = import_exported_file(&dest, &path, "battery staple").unwrap_err();
+        assert_eq!(err.label(), "DecryptionFailed");
+
+        let report = import_exported_file(&dest, &path, "correct horse").unwrap();
+        assert_eq!(report.num_imported, 2);
+        assert_eq!(report.num_skipped, 1);
+        assert_eq!(report.num_failed, 0);
+        let imported = dest.get_by_id("dummy_000002").unwrap().unwrap();
+        assert_eq!(imported.password, "hunter2");
+
+        // Changing the header, even in a way that doesn't affect decryption,
+        // is detected.
+        let mut file: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        file["format"] = "something-else".into();
+        std::fs::write(&path, file.to_string()).unwrap();
+        let err = import_exported_file(&dest, &path, "correct horse").unwrap_err();
+        assert_eq!(err.label(), "InvalidExportFile");
+        file["format"] = EXPORT_FORMAT.into();
+        let mut kdf = base64::decode(file["kdf"].as_str().unwrap()).unwrap();
+        // The low byte of the iteration count.
+        assert_eq!(kdf[5], 10);
+        kdf[5] = 11;
+        file["kdf"] = base64::encode(&kdf).into();
+        std::fs::write(&path, file.to_string()).unwrap();
+        let err = import_exported_file(&dest, &path, "correct horse").unwrap_err();
+        assert_eq!(err.label(), "DecryptionFailed");
+        file["kdf"] = "AQ==".into();
+        std::fs::write(&path, file.to_string()).unwrap();
+        let err = import_exported_file(&dest, &path, "correct horse").unwrap_err();
+        assert_eq!(err.label(), "InvalidExportFile");
+    }
+
+    #[test]
+    fn test_import_version_1() {
+        rc_crypto::ensure_initialized();
+        let tmpdir = TempDir::new("test_import_version_1").unwrap();
+        let path = tmpdir.path().join("logins.json");
+
+        let logins = vec![login(1), login(2)];
+        let plaintext = serde_json::to_vec(&ExportedLogins { logins: &logins }).unwrap();
+        let key = pbkdf2_hmac_sha256(b"correct horse", b"saltsaltsaltsalt", 10).unwrap();
+        let kdf = Kdf::Params(KdfParams {
+            algorithm: KDF_PBKDF2_HMAC_SHA256.into(),
+            iterations: 10,
+            salt: base64::encode(b"saltsaltsaltsalt"),
+        });
+        write_file(&path, 1, kdf, &key, &plaintext).unwrap();
+
+        let dest = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let report = import_exported_file(&dest, &path, "correct horse").unwrap();
+        assert_eq!(report.num_imported, 2);
+        assert!(dest.get_by_id("dummy_000002").unwrap().is_some());
+
+        let mut file: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        file["kdf"]["iterations"] = 11.into();
+        std::fs::write(&path, file.to_string()).unwrap();
+        let err = import_exported_file(&dest, &path, "correct horse").unwrap_err();
+        assert_eq!(err.label(), "DecryptionFailed");
+        file["kdf"]["iterations"] = 0.into();
+        std::fs::write(&path, file.to_string()).unwrap();
+        let err = import_exported_file(&dest, &path, "correct horse").unwrap_err();
+        assert_eq!(err.label(), "InvalidExportFile");
+    }
+}
@@ -27,7 +27,7 @@ pub mod error_codes {
     pub const DUPLICATE_GUID: i32 = 3;
 
     /// Either the file is not a database, or it is not encrypted with the
-    /// provided encryption key.
+    /// provided encryption key (or, the login fields could not be decrypted).
     pub const INVALID_KEY: i32 = 4;
 
     /// A request to the sync server failed.
@@ -39,6 +39,14 @@ pub mod error_codes {
     /// An invalid salt was provided.
     pub const INVALID_SALT: i32 = 7;
 
+    /// The database is locked by another connection, and stayed locked for
+    /// longer than the busy timeout. Retrying later may succeed.
+    pub const DATABASE_BUSY: i32 = 8;
+
+    /// Returned from an `undo_delete()` call where the deletion can no longer
+    /// be undone.
+    pub const CANNOT_UNDO_DELETE: i32 = 9;
+
     // Skip a bunch of spaces to make it clear these are part of a group,
     // even as more and more errors get added. We're only exposing the
     // InvalidLogin items that can actually be triggered, the others
@@ -101,6 +109,16 @@ fn get_code(err: &Error) -> ErrorCode {
             ErrorCode::new(error_codes::INTERRUPTED)
         }
 
+        ErrorKind::DatabaseBusy(_) => {
+            log::warn!("Database busy");
+            ErrorCode::new(error_codes::DATABASE_BUSY)
+        }
+
+        ErrorKind::DecryptionFailed(_) => {
+            log::error!("Failed to decrypt login fields / invalid key error");
+            ErrorCode::new(error_codes::INVALID_KEY)
+        }
+
         ErrorKind::Interrupted(_) => {
             log::warn!("Operation interrupted (Outside SQL)");
             ErrorCode::new(error_codes::INTERRUPTED)
@@ -111,6 +129,11 @@ fn get_code(err: &Error) -> ErrorCode {
             ErrorCode::new(error_codes::INVALID_SALT)
         }
 
+        ErrorKind::CannotUndoDelete(id) => {
+            log::warn!("Can't undo the deletion of {}", id);
+            ErrorCode::new(error_codes::CANNOT_UNDO_DELETE)
+        }
+
         err => {
             log::error!("Unexpected error: {:?}", err);
             ErrorCode::new(error_codes::UNEXPECTED)
@@ -0,0 +1,282 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A report on the health of the stored passwords: which are reused across
+//! sites, and how strong each one is. This is computed here, rather than by
+//! the application, so that the passwords never need to cross the FFI to be
+//! analyzed; the report only contains GUIDs, hosts and scores.
+
+use crate::engine::PasswordEngine;
+use crate::error::*;
+use crate::login::Login;
+use serde_derive::*;
+use std::collections::{BTreeSet, HashMap};
+use sync_guid::Guid;
+use url::Url;
+
+/// Passwords which are only worth their position in this list (plus any
+/// digits and symbols added to the end). This is nowhere near as thorough as
+/// the lists zxcvbn uses, but catches the most common, and keyboard pattern,
+/// passwords.
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456",
+    "12345678",
+    "123456789",
+    "1234567890",
+    "111111",
+    "000000",
+    "password",
+    "passw0rd",
+    "qwerty",
+    "qwertyuiop",
+    "asdf",
+    "asdfgh",
+    "asdfghjkl",
+    "zxcvbn",
+    "zxcvbnm",
+    "1q2w3e4r",
+    "abc123",
+    "letmein",
+    "welcome",
+    "monkey",
+    "dragon",
+    "iloveyou",
+    "football",
+    "baseball",
+    "sunshine",
+    "princess",
+    "admin",
+    "login",
+    "master",
+    "secret",
+    "trustno1",
+    "hello",
+    "shadow",
+    "superman",
+    "batman",
+    "starwars",
+    "whatever",
+    "freedom",
+    "qazwsx",
+    "mustang",
+];
+
+// Entropy (in bits) needed for each score above 0.
+const SCORE_THRESHOLDS: [f64; 4] = [28.0, 36.0, 60.0, 80.0];
+
+/// Logins with the same password, on more than one site.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ReusedPassword {
+    pub guids: Vec<Guid>,
+    pub hosts: Vec<String>,
+}
+
+/// An estimate of how hard the password of a login would be to guess.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PasswordStrength {
+    pub guid: Guid,
+    /// From 0 (very weak) to 4 (very strong), like zxcvbn's score.
+    pub score: u8,
+    pub entropy_bits: f64,
+}
+
+/// Returned by `PasswordEngine::password_health_report`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct PasswordHealthReport {
+    pub reused: Vec<ReusedPassword>,
+    pub strength: Vec<PasswordStrength>,
+}
+
+pub fn password_health_report(logins: &[Login]) -> PasswordHealthReport {
+    let mut by_password: HashMap<&str, Vec<&Login>> = HashMap::new();
+    for login in logins {
+        by_password
+            .entry(login.password.as_str())
+            .or_default()
+            .push(login);
+    }
+    let mut reused = by_password
+        .values()
+        .filter_map(|logins| {
+            let hosts = logins
+                .iter()
+                .map(|login| site(&login.hostname))
+                .collect::<BTreeSet<_>>();
+            if hosts.len() < 2 {
+                return None;
+            }
+            let mut guids = logins.iter().map(|l| l.guid.clone()).collect::<Vec<_>>();
+            guids.sort();
+            Some(ReusedPassword {
+                guids,
+                hosts: hosts.into_iter().collect(),
+            })
+        })
+        .collect::<Vec<_>>();
+    reused.sort_by(|a, b| a.guids.cmp(&b.guids));
+
+    let strength = logins
+        .iter()
+        .map(|login| {
+            let entropy_bits = estimate_entropy(&login.password);
+            PasswordStrength {
+                guid: login.guid.clone(),
+                score: score(entropy_bits),
+                entropy_bits,
+            }
+        })
+        .collect();
+    PasswordHealthReport { reused, strength }
+}
+
+// We don't have a public suffix list, so use the host, without any "www.",
+// to decide which logins are for the same site. This means that other
+// subdomains of a site count as different sites.
+fn site(hostname: &str) -> String {
+    let host = Url::parse(hostname)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_else(|| hostname.to_owned());
+    if host.starts_with("www.") {
+        host[4..].to_owned()
+    } else {
+        host
+    }
+}
+
+/// Returns the zxcvbn-style score for a password with `entropy_bits`.
+pub fn score(entropy_bits: f64) -> u8 {
+    SCORE_THRESHOLDS
+        .iter()
+        .take_while(|&&threshold| entropy_bits >= threshold)
+        .count() as u8
+}
+
+/// A rough estimate of the entropy of `password`, in bits. Each character is
+/// worth `log2` of the size of the character classes used, except that
+/// repeats and runs (like "aaa" or "1234") only add a bit each, and a common
+/// password only counts for its position in `COMMON_PASSWORDS`.
+pub fn estimate_entropy(password: &str) -> f64 {
+    let chars = password.chars().collect::<Vec<_>>();
+    let base_len = chars
+        .iter()
+        .rposition(|c| c.is_alphabetic())
+        .map_or(0, |i| i + 1);
+    let base = chars[..base_len].iter().collect::<String>().to_lowercase();
+    if let Some(rank) = COMMON_PASSWORDS.iter().position(|&p| p == base) {
+        let suffix = &chars[base_len..];
+        return ((rank + 2) as f64).log2() + char_bits(suffix) * suffix.len() as f64;
+    }
+    if COMMON_PASSWORDS.contains(&password.to_lowercase().as_str()) {
+        return (COMMON_PASSWORDS.len() as f64).log2();
+    }
+    let bits_per_char = char_bits(&chars);
+    let mut bits = 0.0;
+    for (i, c) in chars.iter().enumerate() {
+        let continues_pattern = i > 0 && {
+            let prev = chars[i - 1] as i64;
+            let delta = *c as i64 - prev;
+            delta == 0 || (c.is_ascii_alphanumeric() && (delta == 1 || delta == -1))
+        };
+        bits += if continues_pattern {
+            1.0
+        } else {
+            bits_per_char
+        };
+    }
+    bits
+}
+
+// The bits of entropy per character, for the character classes in `chars`.
+fn char_bits(chars: &[char]) -> f64 {
+    let mut pool = 0;
+    if chars.iter().any(char::is_ascii_lowercase) {
+        pool += 26;
+    }
+    if chars.iter().any(char::is_ascii_uppercase) {
+        pool += 26;
+    }
+    if chars.iter().any(char::is_ascii_digit) {
+        pool += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool == 0 {
+        0.0
+    } else {
+        f64::from(pool).log2()
+    }
+}
+
+impl PasswordEngine {
+    /// See the `password_health_report` function.
+    pub fn password_health_report(&self) -> Result<PasswordHealthReport> {
+        Ok(password_health_report(&self.list_unlogged()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(guid: &str, hostname: &str, password: &str) -> Login {
+        Login {
+            guid: guid.into(),
+            hostname: hostname.into(),
+            form_submit_url: Some(hostname.into()),
+            username: "user".into(),
+            password: password.into(),
+            ..Login::default()
+        }
+    }
+
+    #[test]
+    fn test_strength() {
+        let score_of = |password| score(estimate_entropy(password));
+        assert_eq!(score_of(""), 0);
+        assert_eq!(score_of("password"), 0);
+        assert_eq!(score_of("Password1!"), 0);
+        assert_eq!(score_of("qwerty"), 0);
+        assert_eq!(score_of("aaaaaaaaaaaaaaaa"), 0);
+        assert_eq!(score_of("abcdefghijklmnop"), 0);
+        assert_eq!(score_of("hunter2"), 2);
+        assert_eq!(score_of("Tr0ub4dor&3"), 3);
+        assert_eq!(score_of("correct horse battery staple"), 4);
+    }
+
+    #[test]
+    fn test_password_health_report() {
+        let logins = vec![
+            login("dummy_000001", "https://www.example.com", "hunter2"),
+            login("dummy_000002", "https://example.com", "hunter2"),
+            login("dummy_000003", "https://example.org", "hunter2"),
+            login("dummy_000004", "https://example.net", "correct horse"),
+            // Same site, so not reused.
+            login("dummy_000005", "https://example.net", "Tr0ub4dor&3"),
+            login("dummy_000006", "https://www.example.net", "Tr0ub4dor&3"),
+        ];
+        let report = password_health_report(&logins);
+        assert_eq!(
+            report.reused,
+            vec![ReusedPassword {
+                guids: vec![
+                    "dummy_000001".into(),
+                    "dummy_000002".into(),
+                    "dummy_000003".into()
+                ],
+                hosts: vec!["example.com".into(), "example.org".into()],
+            }]
+        );
+        assert_eq!(report.strength.len(), 6);
+        assert_eq!(report.strength[0].guid, "dummy_000001");
+        assert_eq!(report.strength[0].score, 2);
+        // The passwords themselves aren't in the report.
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(!json.contains("hunter2"));
+    }
+}
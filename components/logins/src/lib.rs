@@ -9,20 +9,47 @@
 mod error;
 mod login;
 
+pub mod breach;
+mod bridge;
 mod db;
+mod encryption;
 mod engine;
+pub mod export;
+pub mod health;
+pub mod metrics;
+pub mod migrate_csv;
+pub mod migrate_desktop;
+pub mod migrate_fennec;
+mod observer;
+pub mod origin;
+pub mod psl;
+mod query;
 pub mod schema;
+mod store_api;
+mod store_manager;
+mod sync_config;
 mod update_plan;
 mod util;
 
 mod ffi;
 
+pub use crate::bridge::BridgedEngine;
 // Mostly exposed for the sync manager.
-pub use crate::db::LoginDb;
 pub use crate::db::LoginStore;
+pub use crate::db::{
+    AccessLogEntry, ChangeSource, DeletedLogin, HistoryEntry, LoginDb, LoginOperation, LoginStats,
+    MaintenanceReport, MergeReport, OriginUpgradeReport, SuspiciousAccess, ValidationReport,
+    ACCESS_LOG_RETENTION, DEFAULT_TOMBSTONE_RETENTION, UNDO_DELETE_GRACE_PERIOD,
+};
+pub use crate::encryption::{EncryptorDecryptor, KeyStatus};
 pub use crate::engine::*;
 pub use crate::error::*;
 pub use crate::login::*;
+pub use crate::observer::{LoginChangeEvent, LoginChangeObserver};
+pub use crate::query::{LoginQuery, LoginSortOrder};
+pub use crate::store_api::PasswordStoreApi;
+pub use crate::store_manager::{SharedStore, StoreManager};
+pub use crate::sync_config::{SyncConfig, DEFAULT_COLLECTION_NAME};
 
 pub mod msg_types {
     include!("mozilla.appservices.logins.protobuf.rs");
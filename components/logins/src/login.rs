@@ -94,6 +94,7 @@
 //!   - truncating full URLs to just their origin component
 //!   - converting origins with non-ascii characters into punycode
 //!   - replacing invalid values with null if a valid 'httpRealm' field is present
+//!   - replacing the wildcard values ("" and ".") with null if 'httpRealm' is also present
 //!
 //!   **XXX TODO**:
 //!   - return a "display" field (exact name TBD) in the serialized
@@ -228,9 +229,15 @@
 
 use crate::error::*;
 use crate::msg_types::PasswordInfo;
+use crate::origin::{normalize_origin, OriginMode};
 use crate::util;
-use rusqlite::Row;
+use rusqlite::{
+    types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef},
+    Row,
+};
 use serde_derive::*;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::time::{self, SystemTime};
 use sync15::ServerTimestamp;
 use sync_guid::Guid;
@@ -278,6 +285,69 @@ pub struct Login {
 
     #[serde(default)]
     pub times_used: i64,
+
+    /// Free-form notes about the login. Like the username and password, this
+    /// is encrypted when field encryption is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+
+    /// Any fields in the record from the server which this version doesn't
+    /// know about, typically because a newer client added them.
+    #[serde(flatten)]
+    pub unknown_fields: UnknownFields,
+}
+
+/// The fields of a sync record which aren't otherwise part of `Login`. These
+/// are kept in both tables and included whenever the record is uploaded, so
+/// that older clients don't drop the fields newer clients add. Local changes
+/// never modify them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UnknownFields(pub serde_json::Map<String, serde_json::Value>);
+
+impl UnknownFields {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl PartialEq for UnknownFields {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+// `serde_json::Map` isn't `Hash`, but it's ordered, so its JSON is stable.
+impl Hash for UnknownFields {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        serde_json::to_string(&self.0)
+            .unwrap_or_default()
+            .hash(state)
+    }
+}
+
+// Stored as a JSON object, or NULL if there aren't any.
+impl ToSql for UnknownFields {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        if self.is_empty() {
+            return Ok(ToSqlOutput::from(rusqlite::types::Null));
+        }
+        let json = serde_json::to_string(&self.0)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        Ok(ToSqlOutput::from(json))
+    }
+}
+
+impl FromSql for UnknownFields {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Null => Ok(UnknownFields::default()),
+            _ => serde_json::from_str(value.as_str()?)
+                .map(UnknownFields)
+                .map_err(|e| FromSqlError::Other(Box::new(e))),
+        }
+    }
 }
 
 fn deserialize_timestamp<'de, D>(deserializer: D) -> std::result::Result<i64, D::Error>
@@ -336,50 +406,13 @@ impl Login {
     /// a string.
     fn validate_and_fixup_origin(origin: &str) -> Result<Option<String>> {
         // Check we can parse the origin, then use the normalized version of it.
-        match Url::parse(&origin) {
-            Ok(mut u) => {
-                // Presumably this is a faster path than always setting?
-                if u.path() != "/"
-                    || u.fragment().is_some()
-                    || u.query().is_some()
-                    || u.username() != "/"
-                    || u.password().is_some()
-                {
-                    // Not identical - we only want the origin part, so kill
-                    // any other parts which may exist.
-                    // But first special case `file://` URLs which always
-                    // resolve to `file://`
-                    if u.scheme() == "file" {
-                        return Ok(if origin == "file://" {
-                            None
-                        } else {
-                            Some("file://".into())
-                        });
-                    }
-                    u.set_path("");
-                    u.set_fragment(None);
-                    u.set_query(None);
-                    let _ = u.set_username("");
-                    let _ = u.set_password(None);
-                    let mut href = u.into_string();
-                    // We always store without the trailing "/" which Urls have.
-                    if href.ends_with('/') {
-                        href.pop().expect("url must have a length");
-                    }
-                    if origin != href {
-                        // Needs to be fixed up.
-                        return Ok(Some(href));
-                    }
-                }
-                Ok(None)
-            }
-            Err(_) => {
-                // We can't fixup completely invalid records, so always throw.
-                throw!(InvalidLogin::IllegalFieldValue {
-                    field_info: "Origin is Malformed".into()
-                });
-            }
-        }
+        let normalized = normalize_origin(origin, OriginMode::Strict)?;
+        Ok(if normalized != origin {
+            // Needs to be fixed up.
+            Some(normalized)
+        } else {
+            None
+        })
     }
 
     /// Internal helper for doing validation and fixups.
@@ -413,15 +446,30 @@ impl Login {
             throw!(InvalidLogin::EmptyPassword);
         }
 
-        if self.form_submit_url.is_some() && self.http_realm.is_some() {
-            get_fixed_or_throw!(InvalidLogin::BothTargets)?.http_realm = None;
+        if let (Some(href), Some(_)) = (&self.form_submit_url, &self.http_realm) {
+            // Prefer the realm when the form target is a wildcard or
+            // unusable, as it's the more specific of the two.
+            let href_is_useless = href.is_empty()
+                || href == "."
+                || (href != "javascript:" && Url::parse(href).is_err());
+            let fixed = get_fixed_or_throw!(InvalidLogin::BothTargets)?;
+            if href_is_useless {
+                fixed.form_submit_url = None;
+            } else {
+                fixed.http_realm = None;
+            }
         }
 
         if self.form_submit_url.is_none() && self.http_realm.is_none() {
             throw!(InvalidLogin::NoTarget);
         }
 
-        let form_submit_url = self.form_submit_url.clone().unwrap_or_default();
+        let form_submit_url = maybe_fixed
+            .as_ref()
+            .unwrap_or(self)
+            .form_submit_url
+            .clone()
+            .unwrap_or_default();
         let http_realm = maybe_fixed
             .as_ref()
             .unwrap_or(self)
@@ -459,6 +507,17 @@ impl Login {
             }
         }
 
+        // Unlike the other fields, notes may contain newlines.
+        if self
+            .notes
+            .as_ref()
+            .map_or(false, |notes| notes.contains('\0'))
+        {
+            throw!(InvalidLogin::IllegalFieldValue {
+                field_info: "`notes` contains Nul".into()
+            });
+        }
+
         // Desktop doesn't like fields with the below patterns
         if self.username_field == "." {
             throw!(InvalidLogin::IllegalFieldValue {
@@ -537,6 +596,9 @@ impl Login {
 
             time_password_changed: row.get("timePasswordChanged")?,
             times_used: row.get("timesUsed")?,
+
+            notes: row.get("notes")?,
+            unknown_fields: row.get("unknown_fields")?,
         };
         // For now, we want to apply fixups but still return the record if
         // there is unfixably invalid data in the db.
@@ -559,6 +621,7 @@ impl From<Login> for PasswordInfo {
             time_created: login.time_created,
             time_last_used: login.time_last_used,
             time_password_changed: login.time_password_changed,
+            notes: login.notes,
         }
     }
 }
@@ -578,6 +641,8 @@ impl From<PasswordInfo> for Login {
             time_created: info.time_created,
             time_last_used: info.time_last_used,
             time_password_changed: info.time_password_changed,
+            notes: info.notes,
+            ..Login::default()
         }
     }
 }
@@ -631,6 +696,10 @@ pub(crate) struct LocalLogin {
     pub sync_status: SyncStatus,
     pub is_deleted: bool,
     pub local_modified: SystemTime,
+    // When each field was last changed locally, keyed by the names used by
+    // `LoginDelta::merge_fields`. Fields which aren't here fall back to
+    // `local_modified`.
+    pub field_modified: HashMap<String, i64>,
 }
 
 impl LocalLogin {
@@ -645,8 +714,20 @@ impl LocalLogin {
             sync_status: SyncStatus::from_u8(row.get("sync_status")?)?,
             is_deleted: row.get("is_deleted")?,
             local_modified: util::system_time_millis_from_row(row, "local_modified")?,
+            field_modified: match row.get::<_, Option<String>>("field_modified")? {
+                Some(json) => serde_json::from_str(&json)?,
+                None => HashMap::new(),
+            },
         })
     }
+
+    /// When `field` was last changed locally.
+    pub(crate) fn field_modified(&self, field: &str) -> SystemTime {
+        match self.field_modified.get(field) {
+            Some(&ms) => util::system_time_from_ms_i64(ms),
+            None => self.local_modified,
+        }
+    }
 }
 
 macro_rules! impl_login {
@@ -684,7 +765,8 @@ macro_rules! impl_login {
 impl_login!(LocalLogin {
     sync_status: SyncStatus::New,
     is_deleted: false,
-    local_modified: time::UNIX_EPOCH
+    local_modified: time::UNIX_EPOCH,
+    field_modified: HashMap::new()
 });
 
 impl_login!(MirrorLogin {
@@ -771,6 +853,54 @@ macro_rules! impl_login_setter {
 impl_login_setter!(set_local, local, LocalLogin);
 impl_login_setter!(set_mirror, mirror, MirrorLogin);
 
+/// How conflicts between local and remote changes to a login are resolved
+/// during a sync. Changes to different fields are always kept, and the
+/// `timesUsed` counters are always summed; the policy only decides which
+/// side wins when both changed the same field.
+///
+/// When there's no common ancestor to merge against (for example, the first
+/// time a login is synced), the whole record is taken from the winning side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergePolicy {
+    /// Local changes always win.
+    PreferLocal,
+    /// Remote changes always win.
+    PreferRemote,
+    /// The most recently modified side wins. Without a common ancestor, the
+    /// side whose password changed most recently wins, with ties going to
+    /// the remote record. This is the default.
+    PreferNewer,
+    /// The side whose password changed most recently wins, even if the other
+    /// side was modified more recently. Ties go to the remote record.
+    PasswordNewestTimestamp,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy::PreferNewer
+    }
+}
+
+impl MergePolicy {
+    /// Whether a conflict between `local` and `remote` should be resolved in
+    /// favor of `remote`. `remote_is_newer` is what `PreferNewer` uses.
+    pub(crate) fn prefer_remote(
+        self,
+        local: &Login,
+        remote: &Login,
+        remote_is_newer: bool,
+    ) -> bool {
+        match self {
+            MergePolicy::PreferLocal => false,
+            MergePolicy::PreferRemote => true,
+            MergePolicy::PreferNewer => remote_is_newer,
+            MergePolicy::PasswordNewestTimestamp => {
+                remote.time_password_changed >= local.time_password_changed
+            }
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub(crate) struct LoginDelta {
     // "non-commutative" fields
@@ -779,6 +909,8 @@ pub(crate) struct LoginDelta {
     pub username: Option<String>,
     pub http_realm: Option<String>,
     pub form_submit_url: Option<String>,
+    pub notes: Option<String>,
+    pub unknown_fields: Option<UnknownFields>,
 
     pub time_created: Option<i64>,
     pub time_last_used: Option<i64>,
@@ -793,11 +925,11 @@ pub(crate) struct LoginDelta {
 }
 
 macro_rules! merge_field {
-    ($merged:ident, $b:ident, $prefer_b:expr, $field:ident) => {
+    ($merged:ident, $b:ident, $prefer_b:ident, $field:ident, $name:expr) => {
         if let Some($field) = $b.$field.take() {
             if $merged.$field.is_some() {
                 log::warn!("Collision merging login field {}", stringify!($field));
-                if $prefer_b {
+                if $prefer_b($name) {
                     $merged.$field = Some($field);
                 }
             } else {
@@ -808,21 +940,32 @@ macro_rules! merge_field {
 }
 
 impl LoginDelta {
+    /// Merges two deltas, deciding which side wins separately for each field
+    /// changed by both. `prefer_b` is passed the field's name as serialized
+    /// in a `Login` (such as `"formSubmitURL"`).
     #[allow(clippy::cognitive_complexity)] // Looks like clippy considers this after macro-expansion...
-    pub fn merge(self, mut b: LoginDelta, b_is_newer: bool) -> LoginDelta {
+    pub fn merge_fields(self, mut b: LoginDelta, prefer_b: impl Fn(&str) -> bool) -> LoginDelta {
         let mut merged = self;
-        merge_field!(merged, b, b_is_newer, hostname);
-        merge_field!(merged, b, b_is_newer, password);
-        merge_field!(merged, b, b_is_newer, username);
-        merge_field!(merged, b, b_is_newer, http_realm);
-        merge_field!(merged, b, b_is_newer, form_submit_url);
-
-        merge_field!(merged, b, b_is_newer, time_created);
-        merge_field!(merged, b, b_is_newer, time_last_used);
-        merge_field!(merged, b, b_is_newer, time_password_changed);
-
-        merge_field!(merged, b, b_is_newer, password_field);
-        merge_field!(merged, b, b_is_newer, username_field);
+        merge_field!(merged, b, prefer_b, hostname, "hostname");
+        merge_field!(merged, b, prefer_b, password, "password");
+        merge_field!(merged, b, prefer_b, username, "username");
+        merge_field!(merged, b, prefer_b, http_realm, "httpRealm");
+        merge_field!(merged, b, prefer_b, form_submit_url, "formSubmitURL");
+        merge_field!(merged, b, prefer_b, notes, "notes");
+        merge_field!(merged, b, prefer_b, unknown_fields, "unknownFields");
+
+        merge_field!(merged, b, prefer_b, time_created, "timeCreated");
+        merge_field!(merged, b, prefer_b, time_last_used, "timeLastUsed");
+        merge_field!(
+            merged,
+            b,
+            prefer_b,
+            time_password_changed,
+            "timePasswordChanged"
+        );
+
+        merge_field!(merged, b, prefer_b, password_field, "passwordField");
+        merge_field!(merged, b, prefer_b, username_field, "usernameField");
 
         // commutative fields
         merged.times_used += b.times_used;
@@ -862,6 +1005,12 @@ impl Login {
             self.form_submit_url = if url.is_empty() { None } else { Some(url) };
         }
 
+        if let Some(notes) = delta.notes.take() {
+            self.notes = if notes.is_empty() { None } else { Some(notes) };
+        }
+
+        apply_field!(self, delta, unknown_fields);
+
         self.times_used += delta.times_used;
     }
 
@@ -876,6 +1025,14 @@ impl Login {
             delta.http_realm = Some(self.http_realm.clone().unwrap_or_default());
         }
 
+        if self.notes != older.notes {
+            delta.notes = Some(self.notes.clone().unwrap_or_default());
+        }
+
+        if self.unknown_fields != older.unknown_fields {
+            delta.unknown_fields = Some(self.unknown_fields.clone());
+        }
+
         if self.hostname != older.hostname {
             delta.hostname = Some(self.hostname.clone());
         }
@@ -923,6 +1080,77 @@ impl Login {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_merge_policy_prefer_remote() {
+        let older = Login {
+            time_password_changed: 1000,
+            ..Login::default()
+        };
+        let newer = Login {
+            time_password_changed: 2000,
+            ..Login::default()
+        };
+        assert!(!MergePolicy::PreferLocal.prefer_remote(&older, &newer, true));
+        assert!(MergePolicy::PreferRemote.prefer_remote(&newer, &older, false));
+        assert!(MergePolicy::PreferNewer.prefer_remote(&newer, &older, true));
+        assert!(!MergePolicy::PreferNewer.prefer_remote(&older, &newer, false));
+        assert!(MergePolicy::PasswordNewestTimestamp.prefer_remote(&older, &newer, false));
+        assert!(!MergePolicy::PasswordNewestTimestamp.prefer_remote(&newer, &older, true));
+        // Ties go to the remote record.
+        assert!(MergePolicy::PasswordNewestTimestamp.prefer_remote(&older, &older, false));
+        assert_eq!(MergePolicy::default(), MergePolicy::PreferNewer);
+    }
+
+    #[test]
+    fn test_notes_delta() {
+        let parent = Login {
+            password: "hunter2".into(),
+            notes: Some("first".into()),
+            ..Login::default()
+        };
+        let local = Login {
+            password: "hunter3".into(),
+            ..parent.clone()
+        };
+        let remote = Login {
+            notes: Some("second\nline".into()),
+            ..parent.clone()
+        };
+        // Changes to different fields are both kept.
+        let merged = local
+            .delta(&parent)
+            .merge_fields(remote.delta(&parent), |_| false);
+        let mut result = parent.clone();
+        result.apply_delta(merged);
+        assert_eq!(result.password, "hunter3");
+        assert_eq!(result.notes.as_deref(), Some("second\nline"));
+
+        // Removing the notes is a change too.
+        let cleared = Login {
+            notes: None,
+            ..parent.clone()
+        };
+        let mut result = parent.clone();
+        result.apply_delta(cleared.delta(&parent));
+        assert_eq!(result.notes, None);
+
+        // Newlines are fine in notes, but Nul isn't.
+        let login = Login {
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            password: "hunter2".into(),
+            notes: Some("one\ntwo".into()),
+            ..Login::default()
+        };
+        assert!(login.check_valid().is_ok());
+        let login = Login {
+            notes: Some("one\0two".into()),
+            ..login
+        };
+        assert!(login.check_valid().is_err());
+    }
+
     #[test]
     fn test_invalid_payload_timestamps() {
         #[allow(clippy::unreadable_literal)]
@@ -1354,6 +1582,30 @@ mod tests {
             ..Login::default()
         };
 
+        let login_with_spaces_and_default_port = Login {
+            hostname: " https://www.example.com:443 ".into(),
+            form_submit_url: Some("https://www.example.com:443/login".into()),
+            username: "test".into(),
+            password: "test".into(),
+            ..Login::default()
+        };
+
+        let login_with_wildcard_form_submit_and_http_realm = Login {
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("".into()),
+            http_realm: Some("realm".into()),
+            password: "test".into(),
+            ..Login::default()
+        };
+
+        let login_with_invalid_form_submit_and_http_realm = Login {
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("not a url".into()),
+            http_realm: Some("realm".into()),
+            password: "test".into(),
+            ..Login::default()
+        };
+
         let test_cases = [
             TestCase {
                 login: login_with_full_url,
@@ -1375,6 +1627,21 @@ mod tests {
                 fixedup_form_submit_url: Some("https://www.example.com".into()),
                 ..TestCase::default()
             },
+            TestCase {
+                login: login_with_spaces_and_default_port,
+                fixedup_host: "https://www.example.com".into(),
+                fixedup_form_submit_url: Some("https://www.example.com".into()),
+            },
+            TestCase {
+                login: login_with_wildcard_form_submit_and_http_realm,
+                fixedup_form_submit_url: None,
+                ..TestCase::default()
+            },
+            TestCase {
+                login: login_with_invalid_form_submit_and_http_realm,
+                fixedup_form_submit_url: None,
+                ..TestCase::default()
+            },
         ];
 
         for tc in &test_cases {
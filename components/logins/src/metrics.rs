@@ -0,0 +1,116 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Counts, timings and errors for the operations performed through a
+//! `PasswordEngine`, reported to a sink the embedder provides.
+//!
+//! The operations are grouped the same way as the `logins_store` metrics in
+//! `android/metrics.yaml`, so a sink can map each call directly onto the
+//! matching Glean metric (for example, `count(Operation::Read)` onto
+//! `read_query_count`).
+
+use crate::error::*;
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// The kind of operation being reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Reading logins, such as `list`, `get` or `search`.
+    Read,
+    /// Changing logins, such as `add`, `update` or `delete`.
+    Write,
+    /// A call to `PasswordEngine::sync`.
+    Sync,
+}
+
+/// Implemented by the embedding application. Like observers, sinks are
+/// called synchronously, on the thread which performed the operation, so
+/// they should return quickly.
+pub trait MetricsSink: Send {
+    /// Called once for every operation, whether or not it succeeds.
+    fn count(&self, operation: Operation);
+    /// Called with how long each operation took, including failed ones.
+    fn timing(&self, operation: Operation, elapsed: Duration);
+    /// Called when an operation fails, with the `Error::label` of the error.
+    fn error(&self, operation: Operation, label: &'static str);
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    sink: RefCell<Option<Box<dyn MetricsSink>>>,
+}
+
+impl Metrics {
+    pub fn set_sink(&self, sink: Option<Box<dyn MetricsSink>>) {
+        self.sink.replace(sink);
+    }
+
+    /// Runs `f`, reporting it to the sink as an `operation`.
+    pub fn measure<T>(&self, operation: Operation, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let start = Instant::now();
+        let result = f();
+        if let Some(sink) = self.sink.borrow().as_ref() {
+            sink.count(operation);
+            sink.timing(operation, start.elapsed());
+            if let Err(e) = &result {
+                sink.error(operation, e.label());
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Each count, and the label of each error.
+    type Events = Arc<Mutex<Vec<(Operation, Option<&'static str>)>>>;
+
+    #[derive(Default)]
+    struct TestSink {
+        events: Events,
+    }
+
+    impl MetricsSink for TestSink {
+        fn count(&self, operation: Operation) {
+            self.events.lock().unwrap().push((operation, None));
+        }
+        fn timing(&self, _operation: Operation, _elapsed: Duration) {}
+        fn error(&self, operation: Operation, label: &'static str) {
+            self.events.lock().unwrap().push((operation, Some(label)));
+        }
+    }
+
+    #[test]
+    fn test_measure() {
+        let metrics = Metrics::default();
+        // Nothing is reported without a sink.
+        assert_eq!(metrics.measure(Operation::Read, || Ok(1)).unwrap(), 1);
+
+        let sink = TestSink::default();
+        let events = sink.events.clone();
+        metrics.set_sink(Some(Box::new(sink)));
+        metrics.measure(Operation::Read, || Ok(())).unwrap();
+        metrics
+            .measure(Operation::Write, || -> Result<()> {
+                throw!(ErrorKind::NoSuchRecord("dummy_000001".into()))
+            })
+            .unwrap_err();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (Operation::Read, None),
+                (Operation::Write, None),
+                (Operation::Write, Some("NoSuchRecord")),
+            ]
+        );
+
+        metrics.set_sink(None);
+        metrics.measure(Operation::Sync, || Ok(())).unwrap();
+        assert_eq!(events.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,239 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Import of the CSV files exported by other password managers.
+//!
+//! Each password manager names its columns differently, so the caller
+//! passes a `CsvMapping` saying which column holds which field. Mappings for
+//! the formats used by Chrome, LastPass, Bitwarden and 1Password are built
+//! in. Rows which aren't logins (such as secure notes or cards, which these
+//! managers export in the same file) have no URL or password, and are
+//! skipped.
+
+use crate::db::LoginDb;
+use crate::error::*;
+use crate::login::Login;
+use crate::migrate_desktop::{tally, ImportReport, Outcome};
+use crate::origin::{normalize_origin, OriginMode};
+use std::io::Read;
+
+/// The names of the columns holding each field of a login. Names are
+/// matched case-insensitively. The optional columns don't need to be present
+/// in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvMapping {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    pub http_realm: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl CsvMapping {
+    fn new(url: &str, username: &str, password: &str, notes: Option<&str>) -> Self {
+        Self {
+            url: url.into(),
+            username: username.into(),
+            password: password.into(),
+            http_realm: None,
+            notes: notes.map(Into::into),
+        }
+    }
+
+    /// Chrome's "Export passwords": `name,url,username,password,note`.
+    pub fn chrome() -> Self {
+        Self::new("url", "username", "password", Some("note"))
+    }
+
+    /// LastPass: `url,username,password,totp,extra,name,grouping,fav`.
+    pub fn lastpass() -> Self {
+        Self::new("url", "username", "password", Some("extra"))
+    }
+
+    /// Bitwarden's unencrypted CSV export, whose login columns are
+    /// `login_uri`, `login_username` and `login_password`.
+    pub fn bitwarden() -> Self {
+        Self::new(
+            "login_uri",
+            "login_username",
+            "login_password",
+            Some("notes"),
+        )
+    }
+
+    /// 1Password: `Title,Url,Username,Password,OTPAuth,...,Notes`.
+    pub fn one_password() -> Self {
+        Self::new("url", "username", "password", Some("notes"))
+    }
+}
+
+// The index of each mapped column in a particular file.
+struct Columns {
+    url: usize,
+    username: usize,
+    password: usize,
+    http_realm: Option<usize>,
+    notes: Option<usize>,
+}
+
+impl Columns {
+    fn find(headers: &csv::StringRecord, mapping: &CsvMapping) -> Result<Self> {
+        let position = |name: &str| {
+            headers.iter().position(|h| {
+                h.trim_start_matches('\u{feff}')
+                    .trim()
+                    .eq_ignore_ascii_case(name)
+            })
+        };
+        let required = |name: &str| {
+            position(name).ok_or_else(|| Error::from(ErrorKind::MissingCsvColumn(name.into())))
+        };
+        Ok(Self {
+            url: required(&mapping.url)?,
+            username: required(&mapping.username)?,
+            password: required(&mapping.password)?,
+            http_realm: mapping.http_realm.as_deref().and_then(position),
+            notes: mapping.notes.as_deref().and_then(position),
+        })
+    }
+}
+
+/// Imports the logins in `reader`, a CSV file with a header row, using
+/// `mapping` to find each field. As with `migrate_desktop::import_logins_json`,
+/// the store doesn't need to be empty; logins which duplicate an existing one
+/// (or an earlier row) are skipped. Everything in the report is prefixed
+/// with the (1-based, excluding the header) number of the row it's about.
+pub fn import_csv(db: &LoginDb, reader: impl Read, mapping: &CsvMapping) -> Result<ImportReport> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let columns = Columns::find(reader.headers()?, mapping)?;
+    let outcomes = reader.records().enumerate().map(|(i, record)| {
+        let row = i + 1;
+        record
+            .map_err(Error::from)
+            .and_then(|record| import_row(db, &record, &columns))
+            .map(|outcome| match outcome {
+                Outcome::Skipped(reason) => Outcome::Skipped(format!("row {}: {}", row, reason)),
+                imported => imported,
+            })
+            .map_err(|e| format!("row {}: {}", row, e))
+    });
+    Ok(tally(outcomes, "CSV"))
+}
+
+fn import_row(db: &LoginDb, record: &csv::StringRecord, columns: &Columns) -> Result<Outcome> {
+    let field = |index: usize| record.get(index).unwrap_or_default().trim();
+    let optional =
+        |index: Option<usize>| index.map(field).filter(|v| !v.is_empty()).map(String::from);
+    let url = field(columns.url);
+    if url.is_empty() {
+        return Ok(Outcome::Skipped("no URL".into()));
+    }
+    // Passwords can legitimately start or end with spaces.
+    let password = record.get(columns.password).unwrap_or_default();
+    if password.is_empty() {
+        return Ok(Outcome::Skipped("no password".into()));
+    }
+    // Some managers store bare hosts, such as "example.com".
+    let origin = normalize_origin(url, OriginMode::Loose)?;
+    let http_realm = optional(columns.http_realm);
+    let login = Login {
+        form_submit_url: if http_realm.is_none() {
+            Some(origin.clone())
+        } else {
+            None
+        },
+        hostname: origin,
+        http_realm,
+        username: field(columns.username).into(),
+        password: password.into(),
+        notes: optional(columns.notes),
+        ..Login::default()
+    }
+    .fixup()?;
+    if db.dupe_exists(&login)? {
+        return Ok(Outcome::Skipped("duplicates an existing login".into()));
+    }
+    Ok(Outcome::Imported(db.add(login)?.guid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_csv() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.add(Login {
+            hostname: "https://existing.example.com".into(),
+            form_submit_url: Some("https://existing.example.com".into()),
+            username: "existing".into(),
+            password: "hunter2".into(),
+            ..Login::default()
+        })
+        .unwrap();
+
+        let csv = "\
+name,url,username,password,note
+Example,https://www.example.com/login?next=/,alice,hunter2,
+Bare host,example.org,bob,s3cret,Work account
+No password,https://nopassword.example.com,carol,,
+Existing,https://existing.example.com,existing,hunter2,
+Bad,https://[::1,dave,hunter2,
+";
+        let report = import_csv(&db, csv.as_bytes(), &CsvMapping::chrome()).unwrap();
+        assert_eq!(report.num_imported, 2);
+        assert_eq!(report.num_skipped, 2);
+        assert_eq!(report.num_failed, 1);
+        assert_eq!(
+            report.skipped,
+            vec![
+                "row 3: no password".to_string(),
+                "row 4: duplicates an existing login".to_string(),
+            ]
+        );
+        assert!(report.errors[0].starts_with("row 5: "));
+
+        let logins = db
+            .get_by_base_domain("example.org", &db.begin_interrupt_scope())
+            .unwrap();
+        assert_eq!(logins.len(), 1);
+        assert_eq!(logins[0].hostname, "https://example.org");
+        assert_eq!(logins[0].username, "bob");
+        assert_eq!(logins[0].notes.as_deref(), Some("Work account"));
+        let logins = db
+            .get_by_base_domain("example.com", &db.begin_interrupt_scope())
+            .unwrap();
+        assert!(logins
+            .iter()
+            .any(|l| l.hostname == "https://www.example.com" && l.notes.is_none()));
+
+        // Importing the same file again only finds duplicates.
+        let report = import_csv(&db, csv.as_bytes(), &CsvMapping::chrome()).unwrap();
+        assert_eq!(report.num_imported, 0);
+        assert_eq!(report.num_skipped, 4);
+    }
+
+    #[test]
+    fn test_import_csv_mappings() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let bitwarden = "\
+folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp
+,,login,Example,,,0,https://bitwarden.example.com,alice,hunter2,
+,,note,A note,Some text,,0,,,,
+";
+        let report = import_csv(&db, bitwarden.as_bytes(), &CsvMapping::bitwarden()).unwrap();
+        assert_eq!((report.num_imported, report.num_skipped), (1, 1));
+
+        let one_password = "\
+\"Title\",\"Url\",\"Username\",\"Password\",\"OTPAuth\",\"Favorite\",\"Archived\",\"Tags\",\"Notes\"
+\"Example\",\"https://1password.example.com\",\"alice\",\"hunter2\",\"\",\"false\",\"false\",\"\",\"\"
+";
+        let report = import_csv(&db, one_password.as_bytes(), &CsvMapping::one_password()).unwrap();
+        assert_eq!(report.num_imported, 1);
+
+        // A file without the mapped columns fails as a whole.
+        let err = import_csv(&db, bitwarden.as_bytes(), &CsvMapping::lastpass()).unwrap_err();
+        assert_eq!(err.label(), "MissingCsvColumn");
+    }
+}
@@ -0,0 +1,304 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Import of the `logins.json` file used by desktop Firefox.
+//!
+//! Desktop stores the username and password of each login encrypted with
+//! NSS's "secret decoder ring", whose key lives in `key4.db`. We don't link
+//! the parts of NSS required to read that, so callers who can decrypt these
+//! values (for example, because they have access to the desktop profile's
+//! key) pass a function to do so. Without one, only logins using the legacy
+//! base64 "encryption" can be imported.
+
+use crate::db::LoginDb;
+use crate::error::*;
+use crate::login::Login;
+use serde_derive::*;
+use sync_guid::Guid;
+
+// `encType` values from desktop's `nsILoginManagerCrypto`.
+const ENCTYPE_BASE64: i64 = 0;
+const ENCTYPE_SDR: i64 = 1;
+
+/// Decrypts an SDR encrypted (and base64 encoded) value from `logins.json`.
+pub type SdrDecryptor<'a> = &'a dyn Fn(&str) -> std::result::Result<String, failure::Error>;
+
+#[derive(Debug, Deserialize)]
+struct LoginsJson {
+    #[serde(default)]
+    logins: Vec<serde_json::Value>,
+}
+
+// A login as stored by Gecko, both in desktop's `logins.json` and in the
+// `moz_logins` table used by Fennec.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DesktopLogin {
+    pub guid: Guid,
+    pub hostname: String,
+    #[serde(default)]
+    pub http_realm: Option<String>,
+    #[serde(default, rename = "formSubmitURL")]
+    pub form_submit_url: Option<String>,
+    #[serde(default)]
+    pub username_field: String,
+    #[serde(default)]
+    pub password_field: String,
+    pub encrypted_username: String,
+    pub encrypted_password: String,
+    #[serde(default = "default_enc_type")]
+    pub enc_type: i64,
+    #[serde(default)]
+    pub time_created: i64,
+    #[serde(default)]
+    pub time_last_used: i64,
+    #[serde(default)]
+    pub time_password_changed: i64,
+    #[serde(default)]
+    pub times_used: i64,
+}
+
+fn default_enc_type() -> i64 {
+    ENCTYPE_SDR
+}
+
+/// The outcome of importing logins from desktop or Fennec. Each entry in
+/// `skipped` and `errors` describes a single record which wasn't imported.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct ImportReport {
+    pub num_imported: u64,
+    pub num_skipped: u64,
+    pub num_failed: u64,
+    pub skipped: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+pub(crate) enum Outcome {
+    /// The login was added, with this GUID.
+    Imported(Guid),
+    Skipped(String),
+}
+
+/// Imports the logins in `json`, the contents of a desktop `logins.json`.
+/// Unlike `LoginDb::import_multiple`, the store doesn't need to be empty;
+/// logins which duplicate an existing one are skipped.
+pub fn import_logins_json(
+    db: &LoginDb,
+    json: &str,
+    decrypt: Option<SdrDecryptor<'_>>,
+) -> Result<ImportReport> {
+    let file: LoginsJson = serde_json::from_str(json)?;
+    let records = file
+        .logins
+        .into_iter()
+        .map(|value| Ok(serde_json::from_value(value)?));
+    Ok(import_records(db, records, decrypt, "Desktop"))
+}
+
+pub(crate) fn import_records(
+    db: &LoginDb,
+    records: impl Iterator<Item = Result<DesktopLogin>>,
+    decrypt: Option<SdrDecryptor<'_>>,
+    source: &str,
+) -> ImportReport {
+    tally(
+        records.map(|record| record.and_then(|r| import_one(db, r, decrypt))),
+        source,
+    )
+}
+
+/// Builds the report for an import from the outcome of each record.
+pub(crate) fn tally<E: std::fmt::Display>(
+    outcomes: impl Iterator<Item = std::result::Result<Outcome, E>>,
+    source: &str,
+) -> ImportReport {
+    let mut report = ImportReport::default();
+    for outcome in outcomes {
+        match outcome {
+            Ok(Outcome::Imported(_)) => report.num_imported += 1,
+            Ok(Outcome::Skipped(reason)) => {
+                report.num_skipped += 1;
+                report.skipped.push(reason);
+            }
+            Err(e) => {
+                log::warn!("Failed to import {} login: {}", source, e);
+                report.num_failed += 1;
+                report.errors.push(e.to_string());
+            }
+        }
+    }
+    log::info!(
+        "{} import: {} imported, {} skipped, {} failed",
+        source,
+        report.num_imported,
+        report.num_skipped,
+        report.num_failed
+    );
+    report
+}
+
+fn import_one(
+    db: &LoginDb,
+    desktop: DesktopLogin,
+    decrypt: Option<SdrDecryptor<'_>>,
+) -> Result<Outcome> {
+    let decode = |value: &str| -> Result<String> {
+        match desktop.enc_type {
+            ENCTYPE_BASE64 => {
+                let bytes = base64::decode(value)
+                    .map_err(|e| ErrorKind::DecryptionFailed(e.to_string()))?;
+                Ok(String::from_utf8(bytes)
+                    .map_err(|e| ErrorKind::DecryptionFailed(e.to_string()))?)
+            }
+            ENCTYPE_SDR => match decrypt {
+                Some(decrypt) => {
+                    Ok(decrypt(value).map_err(|e| ErrorKind::DecryptionFailed(e.to_string()))?)
+                }
+                None => throw!(ErrorKind::DecryptionFailed(format!(
+                    "{}: no SDR decryptor was provided",
+                    desktop.guid
+                ))),
+            },
+            other => throw!(ErrorKind::DecryptionFailed(format!(
+                "{}: unknown encType {}",
+                desktop.guid, other
+            ))),
+        }
+    };
+    let login = Login {
+        guid: desktop.guid.clone(),
+        hostname: desktop.hostname.clone(),
+        http_realm: desktop.http_realm.clone(),
+        form_submit_url: desktop.form_submit_url.clone(),
+        username_field: desktop.username_field.clone(),
+        password_field: desktop.password_field.clone(),
+        username: decode(&desktop.encrypted_username)?,
+        password: decode(&desktop.encrypted_password)?,
+        time_created: desktop.time_created.max(0),
+        time_last_used: desktop.time_last_used.max(0),
+        time_password_changed: desktop.time_password_changed.max(0),
+        times_used: desktop.times_used.max(0),
+        ..Login::default()
+    }
+    .fixup()?;
+    import_login(db, login)
+}
+
+/// Adds `login`, unless it has the same GUID as, or duplicates, an existing
+/// login.
+pub(crate) fn import_login(db: &LoginDb, login: Login) -> Result<Outcome> {
+    if db.get_by_id(login.guid_str())?.is_some() {
+        return Ok(Outcome::Skipped(format!(
+            "{}: a login with this GUID already exists",
+            login.guid
+        )));
+    }
+    if db.dupe_exists(&login)? {
+        return Ok(Outcome::Skipped(format!(
+            "{}: duplicates an existing login",
+            login.guid
+        )));
+    }
+    Ok(Outcome::Imported(db.add(login)?.guid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_logins_json() {
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.add(Login {
+            hostname: "https://existing.example.com".into(),
+            form_submit_url: Some("https://existing.example.com".into()),
+            username: "existing".into(),
+            password: "hunter2".into(),
+            ..Login::default()
+        })
+        .unwrap();
+        let json = serde_json::json!({
+            "nextId": 5,
+            "logins": [
+                {
+                    "id": 1,
+                    "guid": "{11111111-1111-1111-1111-111111111111}",
+                    "hostname": "https://www.example.com",
+                    "formSubmitURL": "https://www.example.com",
+                    "httpRealm": null,
+                    "usernameField": "user",
+                    "passwordField": "pass",
+                    "encryptedUsername": "ENC(alice)",
+                    "encryptedPassword": "ENC(secret)",
+                    "encType": 1,
+                    "timeCreated": 1000,
+                    "timeLastUsed": 2000,
+                    "timePasswordChanged": 1500,
+                    "timesUsed": 3,
+                },
+                {
+                    "id": 2,
+                    "guid": "{22222222-2222-2222-2222-222222222222}",
+                    "hostname": "https://other.example.com",
+                    "httpRealm": "Some Realm",
+                    "encryptedUsername": base64::encode("bob"),
+                    "encryptedPassword": base64::encode("password"),
+                    "encType": 0,
+                },
+                {
+                    "id": 3,
+                    "guid": "{33333333-3333-3333-3333-333333333333}",
+                    "hostname": "https://existing.example.com",
+                    "formSubmitURL": "https://existing.example.com",
+                    "encryptedUsername": "ENC(existing)",
+                    "encryptedPassword": "ENC(other)",
+                    "encType": 1,
+                },
+                {
+                    "id": 4,
+                    "guid": "{44444444-4444-4444-4444-444444444444}",
+                    "hostname": "https://broken.example.com",
+                    "formSubmitURL": "https://broken.example.com",
+                    "encryptedUsername": "garbage",
+                    "encryptedPassword": "garbage",
+                    "encType": 1,
+                },
+            ],
+        })
+        .to_string();
+        let decrypt = |value: &str| -> std::result::Result<String, failure::Error> {
+            if value.starts_with("ENC(") && value.ends_with(')') {
+                Ok(value[4..value.len() - 1].to_owned())
+            } else {
+                Err(failure::err_msg("bad ciphertext"))
+            }
+        };
+        let report = import_logins_json(&db, &json, Some(&decrypt)).unwrap();
+        assert_eq!(report.num_imported, 2);
+        assert_eq!(report.num_skipped, 1);
+        assert_eq!(report.num_failed, 1);
+
+        let login = db
+            .get_by_id("{11111111-1111-1111-1111-111111111111}")
+            .unwrap()
+            .unwrap();
+        assert_eq!(login.username, "alice");
+        assert_eq!(login.password, "secret");
+        assert_eq!(login.username_field, "user");
+        assert_eq!(login.times_used, 3);
+        let login = db
+            .get_by_id("{22222222-2222-2222-2222-222222222222}")
+            .unwrap()
+            .unwrap();
+        assert_eq!(login.username, "bob");
+        assert_eq!(login.http_realm.as_deref(), Some("Some Realm"));
+
+        // Nothing is importable without a decryptor, and importing twice
+        // skips everything which was already imported.
+        let report = import_logins_json(&db, &json, None).unwrap();
+        assert_eq!(report.num_imported, 0);
+        assert_eq!(report.num_skipped, 1);
+        assert_eq!(report.num_failed, 3);
+    }
+}
@@ -0,0 +1,141 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Import of the logins stored by Fennec, the legacy Firefox for Android.
+//!
+//! Fennec kept logins in the Gecko profile's `signons.sqlite`, in a
+//! `moz_logins` table with the same fields (and the same encryption) as
+//! desktop's `logins.json`, so see `migrate_desktop` for how the values are
+//! decrypted.
+//!
+//! Fennec didn't track the sync state of individual logins, so everything is
+//! imported as a new local record. Logins which were synced are matched up
+//! with their server records by GUID on the first sync.
+
+use crate::db::LoginDb;
+use crate::error::*;
+use crate::migrate_desktop::{import_records, DesktopLogin, ImportReport, SdrDecryptor};
+use rusqlite::{Connection, OpenFlags, Row, NO_PARAMS};
+use std::path::Path;
+
+// The version of `signons.sqlite` which added `timesUsed` and friends. Fennec
+// hasn't shipped anything older for a very long time.
+const MIN_FENNEC_DB_VERSION: i64 = 3;
+
+/// Imports the logins in the Fennec `signons.sqlite` at `path`. Logins which
+/// duplicate an existing one are skipped.
+pub fn migrate_from_fennec(
+    db: &LoginDb,
+    path: impl AsRef<Path>,
+    decrypt: Option<SdrDecryptor<'_>>,
+) -> Result<ImportReport> {
+    let fennec = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let db_version: i64 = fennec.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+    if db_version < MIN_FENNEC_DB_VERSION {
+        throw!(ErrorKind::UnsupportedDatabaseVersion(db_version));
+    }
+    let mut stmt = fennec.prepare(
+        "SELECT guid, hostname, httpRealm, formSubmitURL, usernameField,
+                passwordField, encryptedUsername, encryptedPassword, encType,
+                timeCreated, timeLastUsed, timePasswordChanged, timesUsed
+         FROM moz_logins",
+    )?;
+    let records = stmt
+        .query_and_then(NO_PARAMS, fennec_login_from_row)?
+        .collect::<Vec<_>>();
+    Ok(import_records(db, records.into_iter(), decrypt, "Fennec"))
+}
+
+fn fennec_login_from_row(row: &Row<'_>) -> Result<DesktopLogin> {
+    Ok(DesktopLogin {
+        guid: row.get::<_, String>("guid")?.into(),
+        hostname: row.get("hostname")?,
+        http_realm: row.get("httpRealm")?,
+        form_submit_url: row.get("formSubmitURL")?,
+        username_field: row
+            .get::<_, Option<String>>("usernameField")?
+            .unwrap_or_default(),
+        password_field: row
+            .get::<_, Option<String>>("passwordField")?
+            .unwrap_or_default(),
+        encrypted_username: row
+            .get::<_, Option<String>>("encryptedUsername")?
+            .unwrap_or_default(),
+        encrypted_password: row.get("encryptedPassword")?,
+        enc_type: row.get::<_, Option<i64>>("encType")?.unwrap_or(1),
+        time_created: row
+            .get::<_, Option<i64>>("timeCreated")?
+            .unwrap_or_default(),
+        time_last_used: row
+            .get::<_, Option<i64>>("timeLastUsed")?
+            .unwrap_or_default(),
+        time_password_changed: row
+            .get::<_, Option<i64>>("timePasswordChanged")?
+            .unwrap_or_default(),
+        times_used: row.get::<_, Option<i64>>("timesUsed")?.unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_migrate_from_fennec() {
+        let tmpdir = TempDir::new("fennec_logins").unwrap();
+        let path = tmpdir.path().join("signons.sqlite");
+        let fennec = Connection::open(&path).unwrap();
+        fennec
+            .execute_batch(
+                "CREATE TABLE moz_logins (
+                    id INTEGER PRIMARY KEY,
+                    hostname TEXT NOT NULL,
+                    httpRealm TEXT,
+                    formSubmitURL TEXT,
+                    usernameField TEXT NOT NULL,
+                    passwordField TEXT NOT NULL,
+                    encryptedUsername TEXT NOT NULL,
+                    encryptedPassword TEXT NOT NULL,
+                    guid TEXT,
+                    encType INTEGER,
+                    timeCreated INTEGER,
+                    timeLastUsed INTEGER,
+                    timePasswordChanged INTEGER,
+                    timesUsed INTEGER
+                );
+                INSERT INTO moz_logins VALUES
+                    (1, 'https://www.example.com', NULL, 'https://www.example.com',
+                     'user', 'pass', 'ENC(alice)', 'ENC(secret)',
+                     '{11111111-1111-1111-1111-111111111111}', 1, 1000, 3000, 2000, 7),
+                    (2, 'https://www.example.com', NULL, 'https://www.example.com',
+                     'user', 'pass', 'garbage', 'garbage',
+                     '{22222222-2222-2222-2222-222222222222}', 1, 1000, 3000, 2000, 7);
+                PRAGMA user_version = 6;",
+            )
+            .unwrap();
+        drop(fennec);
+
+        let db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        let decrypt = |value: &str| -> std::result::Result<String, failure::Error> {
+            if value.starts_with("ENC(") && value.ends_with(')') {
+                Ok(value[4..value.len() - 1].to_owned())
+            } else {
+                Err(failure::err_msg("bad ciphertext"))
+            }
+        };
+        let report = migrate_from_fennec(&db, &path, Some(&decrypt)).unwrap();
+        assert_eq!(report.num_imported, 1);
+        assert_eq!(report.num_failed, 1);
+        let login = db
+            .get_by_id("{11111111-1111-1111-1111-111111111111}")
+            .unwrap()
+            .unwrap();
+        assert_eq!(login.username, "alice");
+        assert_eq!(login.password, "secret");
+        assert_eq!(login.time_created, 1000);
+        assert_eq!(login.time_password_changed, 2000);
+        assert_eq!(login.times_used, 7);
+    }
+}
@@ -24,6 +24,8 @@ pub struct PasswordInfo {
     pub time_last_used: i64,
     #[prost(int64, required, tag="12")]
     pub time_password_changed: i64,
+    #[prost(string, optional, tag="13")]
+    pub notes: ::std::option::Option<std::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PasswordInfos {
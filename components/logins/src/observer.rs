@@ -0,0 +1,48 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Change notifications for consumers who want to keep a view of the logins
+//! store up to date without re-querying everything after every operation.
+
+use std::cell::RefCell;
+use sync_guid::Guid;
+
+/// A change made to the logins store.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginChangeEvent {
+    /// A new login was added locally.
+    Added(Guid),
+    /// An existing login was updated locally.
+    Updated(Guid),
+    /// A login was deleted locally.
+    Deleted(Guid),
+    /// Incoming records touching these GUIDs were applied during a sync.
+    SyncApplied(Vec<Guid>),
+    /// Every login was removed (for example, by `wipe` or `wipe_local`).
+    Wiped,
+}
+
+/// Implemented by consumers who want to be notified of changes. Observers are
+/// called synchronously, after the change has been committed, on the thread
+/// which made the change.
+pub trait LoginChangeObserver: Send {
+    fn on_change(&self, event: &LoginChangeEvent);
+}
+
+#[derive(Default)]
+pub(crate) struct Observers {
+    observers: RefCell<Vec<Box<dyn LoginChangeObserver>>>,
+}
+
+impl Observers {
+    pub fn register(&self, observer: Box<dyn LoginChangeObserver>) {
+        self.observers.borrow_mut().push(observer);
+    }
+
+    pub fn notify(&self, event: LoginChangeEvent) {
+        for observer in self.observers.borrow().iter() {
+            observer.on_change(&event);
+        }
+    }
+}
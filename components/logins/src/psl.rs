@@ -0,0 +1,141 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Finds the base domain (eTLD+1) of a host using the [public suffix
+//! list](https://publicsuffix.org/). A copy of the list is compiled in, both
+//! the ICANN and the private sections, as Firefox uses both. It should be
+//! updated from time to time from
+//! https://publicsuffix.org/list/public_suffix_list.dat.
+
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+use url::Host;
+
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+#[derive(Default)]
+struct Rules {
+    /// Normal rules, like `co.uk`.
+    suffixes: HashSet<String>,
+    /// Wildcard rules, without the leading `*.`, so `*.ck` is stored as `ck`.
+    wildcards: HashSet<String>,
+    /// Exception rules, without the leading `!`.
+    exceptions: HashSet<String>,
+}
+
+lazy_static! {
+    static ref RULES: Rules = parse_rules(PUBLIC_SUFFIX_LIST);
+}
+
+fn parse_rules(list: &str) -> Rules {
+    let mut rules = Rules::default();
+    for line in list.lines() {
+        // Rules end at the first whitespace, and comments are on lines of
+        // their own.
+        let rule = match line.split_whitespace().next() {
+            Some(rule) if !rule.starts_with("//") => rule,
+            _ => continue,
+        };
+        let (set, rule) = if rule.starts_with("*.") {
+            (&mut rules.wildcards, &rule[2..])
+        } else if rule.starts_with('!') {
+            (&mut rules.exceptions, &rule[1..])
+        } else {
+            (&mut rules.suffixes, rule)
+        };
+        // Rules are in Unicode, but hosts in URLs are punycoded.
+        match Host::parse(rule) {
+            Ok(Host::Domain(domain)) => {
+                set.insert(domain);
+            }
+            _ => log::warn!("Skipping invalid public suffix rule {:?}", rule),
+        }
+    }
+    rules
+}
+
+/// Returns the base domain of `domain`, which must already be lowercase and
+/// punycoded, as the host of a parsed `Url` is. This is the public suffix of
+/// the domain plus one more label, so "www.example.co.uk" and
+/// "example.co.uk" both have the base domain "example.co.uk". Domains which
+/// are themselves a public suffix, like "co.uk", don't have one.
+pub fn base_domain(domain: &str) -> Option<&str> {
+    let domain = domain.trim_end_matches('.');
+    if domain.is_empty() {
+        return None;
+    }
+    let rules = &*RULES;
+    // The start of each label, from the longest candidate suffix (the whole
+    // domain) to the shortest (the last label).
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(domain.match_indices('.').map(|(i, _)| i + 1))
+        .collect();
+    for (i, &start) in starts.iter().enumerate() {
+        let candidate = &domain[start..];
+        // An exception means its parent is the public suffix, so it's the
+        // base domain itself.
+        if rules.exceptions.contains(candidate) {
+            return Some(candidate);
+        }
+        let parent = starts.get(i + 1).map(|&next| &domain[next..]);
+        let is_suffix = rules.suffixes.contains(candidate)
+            || parent.map_or(false, |parent| rules.wildcards.contains(parent));
+        if is_suffix {
+            // The first match is the longest, which is the one that
+            // applies.
+            return match i {
+                0 => None,
+                _ => Some(&domain[starts[i - 1]..]),
+            };
+        }
+    }
+    // Suffixes which aren't in the list are a single label.
+    match starts.len() {
+        1 => None,
+        len => Some(&domain[starts[len - 2]..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_domain() {
+        for (domain, expected) in &[
+            ("example.com", Some("example.com")),
+            ("www.example.com", Some("example.com")),
+            ("a.b.example.com", Some("example.com")),
+            ("example.co.uk", Some("example.co.uk")),
+            ("login.www.example.co.uk", Some("example.co.uk")),
+            ("com", None),
+            ("co.uk", None),
+            // Domains which aren't in the list have a one label suffix.
+            ("example.notatld", Some("example.notatld")),
+            ("notatld", None),
+            // Wildcards and exceptions.
+            ("ck", None),
+            ("foo.ck", None),
+            ("bar.foo.ck", Some("bar.foo.ck")),
+            ("www.ck", Some("www.ck")),
+            ("a.www.ck", Some("www.ck")),
+            // Private domains.
+            ("github.io", None),
+            ("example.github.io", Some("example.github.io")),
+            // Unicode rules are punycoded.
+            (
+                "xn--85x722f.xn--55qx5d.cn",
+                Some("xn--85x722f.xn--55qx5d.cn"),
+            ),
+            (
+                "www.xn--85x722f.xn--55qx5d.cn",
+                Some("xn--85x722f.xn--55qx5d.cn"),
+            ),
+            ("example.com.", Some("example.com")),
+            ("", None),
+        ] {
+            assert_eq!(base_domain(domain), *expected, "{}", domain);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// How the results of a `LoginQuery` are ordered. Ties are broken by GUID so
+/// that paging through results is stable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoginSortOrder {
+    /// Alphabetically by hostname.
+    Hostname,
+    /// Most recently used first.
+    TimeLastUsed,
+    /// Most frequently used first.
+    TimesUsed,
+}
+
+impl LoginSortOrder {
+    pub(crate) fn order_by_sql(self) -> &'static str {
+        match self {
+            LoginSortOrder::Hostname => "hostname ASC, guid ASC",
+            LoginSortOrder::TimeLastUsed => "timeLastUsed DESC, guid ASC",
+            LoginSortOrder::TimesUsed => "timesUsed DESC, guid ASC",
+        }
+    }
+}
+
+impl Default for LoginSortOrder {
+    fn default() -> Self {
+        LoginSortOrder::Hostname
+    }
+}
+
+/// A page of logins to fetch. The default query returns every login, sorted
+/// by hostname.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoginQuery {
+    pub sort_order: LoginSortOrder,
+    /// The maximum number of logins to return, or `None` for all of them.
+    pub limit: Option<u32>,
+    /// The number of logins to skip before returning results.
+    pub offset: u32,
+}
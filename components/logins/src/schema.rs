@@ -46,6 +46,12 @@
 //!     - `2` (`SyncStatus::New`): Indicating that the record has never been
 //!       synced, or we have been reset since the last time it synced.
 //!
+//! - `field_modified`: A JSON object mapping the name of each field which
+//!   was changed locally to when it was last changed, also in milliseconds.
+//!   `local_modified` is only the time of the most recent change, so without
+//!   this, every field changed between syncs would look as if it was changed
+//!   then. Added in version 12.
+//!
 //! ## `loginsM`
 //!
 //! This stores server-side login information, also known as the "mirror".
@@ -72,13 +78,25 @@
 //! - `is_overridden`: A boolean indicating whether or not the mirror contents
 //!   are invalid, and that we should defer to the data stored in `loginsL`.
 //!
+//! ### `rev_host`
+//!
+//! Both tables have an indexed `rev_host` column, with the host of
+//! `hostname` reversed and followed by a dot (so "www.example.com" is
+//! "moc.elpmaxe.www."), like `moz_places.rev_host` in places. A domain and
+//! all its subdomains share a prefix, which lets `get_by_base_domain` use the
+//! index. It's set by temporary triggers which writable connections create
+//! when they're opened, rather than by every query that writes a hostname,
+//! and it's recalculated for every row whose value is out of date at the
+//! same time, in case an older version of this component changed the row.
+//! Added in version 14.
+//!
 //! ## `loginsSyncMeta`
 //!
 //! This is a simple key-value table based on the `moz_meta` table in places.
 //! This table was added (by this rust crate) in version 4, and so is not
 //! present in firefox-ios.
 //!
-//! Currently it is used to store two items:
+//! Currently it is used to store the following items:
 //!
 //! 1. The last sync timestamp is stored under [LAST_SYNC_META_KEY], a
 //!    `sync15::ServerTimestamp` stored in integer milliseconds.
@@ -87,15 +105,51 @@
 //!    [GLOBAL_STATE_META_KEY]. This is a `sync15::GlobalState` stored as
 //!    JSON.
 //!
+//! It is also used to record whether the `username` and `password` columns
+//! of both tables have been encrypted by an `EncryptorDecryptor`, under
+//! [FIELDS_ENCRYPTED_META_KEY], and a known value encrypted with the same key
+//! under [FIELDS_CANARY_META_KEY], which `LoginDb::check_encryption_key` uses
+//! to tell a wrong key from corrupt data.
+//!
+//! When a sync fails because the server couldn't be reached, the time of the
+//! first failed attempt is stored under [PENDING_SYNC_META_KEY] until a sync
+//! succeeds.
+//!
+//! ## `loginsStaging`
+//!
+//! Incoming records are written here before being applied, so that a large
+//! sync can be applied in several smaller transactions. Records are removed
+//! as they're applied, so anything left over after a crash is applied by the
+//! next sync. This table was added in version 11.
+//!
 
-use crate::error::*;
 use lazy_static::lazy_static;
-use rusqlite::Connection;
+use rusqlite::{Connection, ErrorCode, Transaction};
+use sql_support::open_database::{self, ConnectionInitializer};
 use sql_support::ConnExt;
 
-/// Note that firefox-ios is currently on version 3. Version 4 is this version,
-/// which adds a metadata table and changes timestamps to be in milliseconds
-pub const VERSION: i64 = 4;
+/// Note that firefox-ios is currently on version 3.
+///
+/// - Version 4 adds a metadata table and changes timestamps to be in
+///   milliseconds.
+/// - Version 5 adds indices on `timeCreated`.
+/// - Version 6 adds `local_only` to `loginsL`.
+/// - Version 7 adds the `loginsHistory` table.
+/// - Version 8 adds `notes` to both tables.
+/// - Version 9 adds `weak_upload` to `loginsL`.
+/// - Version 10 adds `unknown_fields` to both tables.
+/// - Version 11 adds the `loginsStaging` table.
+/// - Version 12 adds `field_modified` to `loginsL`.
+/// - Version 13 adds the `loginsAccessLog` table.
+/// - Version 14 adds `rev_host` to both tables.
+pub const VERSION: i64 = 14;
+
+/// The version which added the `rev_host` column.
+pub(crate) const REV_HOST_VERSION: i64 = 14;
+
+/// The oldest version `LoginDb::open_readonly` can read without migrating,
+/// as every read uses the `unknown_fields` column.
+pub(crate) const MIN_READ_ONLY_VERSION: i64 = 10;
 
 /// Every column shared by both tables except for `id`
 ///
@@ -126,7 +180,9 @@ pub const COMMON_COLS: &str = "
     timeCreated,
     timeLastUsed,
     timePasswordChanged,
-    timesUsed
+    timesUsed,
+    notes,
+    unknown_fields
 ";
 
 const COMMON_SQL: &str = "
@@ -143,7 +199,12 @@ const COMMON_SQL: &str = "
     timePasswordChanged INTEGER NOT NULL,
     username            TEXT,
     password            TEXT NOT NULL,
-    guid                TEXT NOT NULL UNIQUE
+    guid                TEXT NOT NULL UNIQUE,
+    notes               TEXT,
+    -- The fields of the server record we don't know about, as a JSON object.
+    unknown_fields      TEXT,
+    -- The host of `hostname`, reversed and followed by a dot.
+    rev_host            TEXT
 ";
 
 lazy_static! {
@@ -154,7 +215,15 @@ lazy_static! {
             local_modified INTEGER,
 
             is_deleted     TINYINT NOT NULL DEFAULT 0,
-            sync_status    TINYINT NOT NULL DEFAULT 0
+            sync_status    TINYINT NOT NULL DEFAULT 0,
+            -- Never uploaded, and never changed by incoming records.
+            local_only     TINYINT NOT NULL DEFAULT 0,
+            -- Set when only the usage counters changed. Such records are
+            -- uploaded without being marked as changed, and the change is
+            -- lost if an incoming record arrives first.
+            weak_upload    TINYINT NOT NULL DEFAULT 0,
+            -- Milliseconds, keyed by field name, or NULL if unknown.
+            field_modified TEXT
         )",
         common_sql = COMMON_SQL
     );
@@ -168,8 +237,6 @@ lazy_static! {
         )",
         common_sql = COMMON_SQL
     );
-    static ref SET_VERSION_SQL: String =
-        format!("PRAGMA user_version = {version}", version = VERSION);
 }
 
 const CREATE_META_TABLE_SQL: &str = "
@@ -189,6 +256,149 @@ const CREATE_DELETED_HOSTNAME_INDEX_SQL: &str = "
     ON loginsL (is_deleted, hostname)
 ";
 
+// Used by `LoginDb::delete_between`.
+const CREATE_LOCAL_TIME_CREATED_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsL_timeCreated
+    ON loginsL (timeCreated)
+";
+
+const CREATE_MIRROR_TIME_CREATED_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsM_timeCreated
+    ON loginsM (timeCreated)
+";
+
+const ADD_LOCAL_NOTES_COLUMN_SQL: &str = "
+    ALTER TABLE loginsL ADD COLUMN notes TEXT
+";
+
+const ADD_MIRROR_NOTES_COLUMN_SQL: &str = "
+    ALTER TABLE loginsM ADD COLUMN notes TEXT
+";
+
+// Only written to when history is enabled with `LoginDb::set_history_limit`.
+const CREATE_HISTORY_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsHistory (
+        id        INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid      TEXT NOT NULL,
+        field     TEXT NOT NULL,
+        -- Milliseconds.
+        timestamp INTEGER NOT NULL,
+        -- A `ChangeSource`.
+        source    TINYINT NOT NULL
+    )
+";
+
+const CREATE_STAGING_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsStaging (
+        guid            TEXT PRIMARY KEY,
+        -- The `sync15::Payload`, as JSON.
+        payload         TEXT NOT NULL,
+        -- Milliseconds.
+        server_modified INTEGER NOT NULL
+    )
+";
+
+// Only written to when access logging is enabled with
+// `LoginDb::set_access_logging`.
+const CREATE_ACCESS_LOG_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsAccessLog (
+        id        INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid      TEXT NOT NULL,
+        caller    TEXT NOT NULL,
+        -- Milliseconds.
+        timestamp INTEGER NOT NULL
+    )
+";
+
+const CREATE_ACCESS_LOG_TIMESTAMP_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsAccessLog_timestamp
+    ON loginsAccessLog (timestamp)
+";
+
+const CREATE_HISTORY_GUID_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsHistory_guid
+    ON loginsHistory (guid)
+";
+
+// Used by `LoginDb::get_by_base_domain`.
+const CREATE_LOCAL_REV_HOST_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsL_rev_host
+    ON loginsL (rev_host)
+";
+
+const CREATE_MIRROR_REV_HOST_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsM_rev_host
+    ON loginsM (rev_host)
+";
+
+const ADD_LOCAL_REV_HOST_COLUMN_SQL: &str = "
+    ALTER TABLE loginsL ADD COLUMN rev_host TEXT
+";
+
+const ADD_MIRROR_REV_HOST_COLUMN_SQL: &str = "
+    ALTER TABLE loginsM ADD COLUMN rev_host TEXT
+";
+
+// These are temporary, so that versions of this component which don't
+// define `logins_reverse_host` can still write to the tables.
+const CREATE_REV_HOST_TRIGGERS_SQL: &str = "
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsL_afterinsert_rev_host
+    AFTER INSERT ON loginsL
+    BEGIN
+        UPDATE loginsL SET rev_host = logins_reverse_host(NEW.hostname)
+        WHERE id = NEW.id;
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsL_afterupdate_rev_host
+    AFTER UPDATE OF hostname ON loginsL
+    BEGIN
+        UPDATE loginsL SET rev_host = logins_reverse_host(NEW.hostname)
+        WHERE id = NEW.id;
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsM_afterinsert_rev_host
+    AFTER INSERT ON loginsM
+    BEGIN
+        UPDATE loginsM SET rev_host = logins_reverse_host(NEW.hostname)
+        WHERE id = NEW.id;
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsM_afterupdate_rev_host
+    AFTER UPDATE OF hostname ON loginsM
+    BEGIN
+        UPDATE loginsM SET rev_host = logins_reverse_host(NEW.hostname)
+        WHERE id = NEW.id;
+    END;
+";
+
+const UPDATE_REV_HOSTS_SQL: &str = "
+    UPDATE loginsL SET rev_host = logins_reverse_host(hostname)
+    WHERE rev_host IS NOT logins_reverse_host(hostname);
+
+    UPDATE loginsM SET rev_host = logins_reverse_host(hostname)
+    WHERE rev_host IS NOT logins_reverse_host(hostname);
+";
+
+const ADD_LOCAL_ONLY_COLUMN_SQL: &str = "
+    ALTER TABLE loginsL ADD COLUMN local_only TINYINT NOT NULL DEFAULT 0
+";
+
+const ADD_LOCAL_UNKNOWN_FIELDS_COLUMN_SQL: &str = "
+    ALTER TABLE loginsL ADD COLUMN unknown_fields TEXT
+";
+
+const ADD_MIRROR_UNKNOWN_FIELDS_COLUMN_SQL: &str = "
+    ALTER TABLE loginsM ADD COLUMN unknown_fields TEXT
+";
+
+const ADD_WEAK_UPLOAD_COLUMN_SQL: &str = "
+    ALTER TABLE loginsL ADD COLUMN weak_upload TINYINT NOT NULL DEFAULT 0
+";
+
+const ADD_FIELD_MODIFIED_COLUMN_SQL: &str = "
+    ALTER TABLE loginsL ADD COLUMN field_modified TEXT
+";
+
 // As noted above, we use these when updating from schema v3 (firefox-ios's
 // last schema) to convert from microsecond timestamps to milliseconds.
 const UPDATE_LOCAL_TIMESTAMPS_TO_MILLIS_SQL: &str = "
@@ -209,99 +419,218 @@ pub(crate) static LAST_SYNC_META_KEY: &str = "last_sync_time";
 pub(crate) static GLOBAL_STATE_META_KEY: &str = "global_state_v2";
 pub(crate) static GLOBAL_SYNCID_META_KEY: &str = "global_sync_id";
 pub(crate) static COLLECTION_SYNCID_META_KEY: &str = "passwords_sync_id";
+pub(crate) static FIELDS_ENCRYPTED_META_KEY: &str = "fields_encrypted";
+pub(crate) static FIELDS_CANARY_META_KEY: &str = "fields_canary";
+pub(crate) static PENDING_SYNC_META_KEY: &str = "pending_sync_since";
+
+/// Sets up a logins database: keys it, creates or migrates the schema, and
+/// decides which errors mean it's corrupt.
+pub(crate) struct LoginsConnectionInitializer<'a> {
+    pub encryption_key: Option<&'a str>,
+    pub salt: Option<&'a str>,
+}
+
+impl<'a> ConnectionInitializer for LoginsConnectionInitializer<'a> {
+    const NAME: &'static str = "logins";
+    const END_VERSION: u32 = VERSION as u32;
+
+    fn prepare(&self, conn: &Connection) -> open_database::Result<()> {
+        set_key_pragmas(conn, self.encryption_key, self.salt)?;
+        let initial_pragmas = "
+            -- `temp_store = 2` is required on Android to force the DB to keep temp
+            -- files in memory, since on Android there's no tmp partition. See
+            -- https://github.com/mozilla/mentat/issues/505. Ideally we'd only
+            -- do this on Android, or allow caller to configure it.
+            PRAGMA temp_store = 2;
 
-pub(crate) fn init(db: &Connection) -> Result<()> {
-    let user_version = db.query_one::<i64>("PRAGMA user_version")?;
-    if user_version == 0 {
+            -- We want foreign-key support.
+            PRAGMA foreign_keys = ON;
+        ";
+        conn.execute_batch(initial_pragmas)?;
+        define_functions(conn)?;
+        Ok(())
+    }
+
+    fn init(&self, tx: &Transaction<'_>) -> open_database::Result<()> {
         // This logic is largely taken from firefox-ios. AFAICT at some point
         // they went from having schema versions tracked using a table named
-        // `tableList` to using `PRAGMA user_version`. This leads to the
-        // following logic:
-        //
-        // - If `tableList` exists, we're hopelessly far in the past, drop any
-        //   tables we have (to ensure we avoid name collisions/stale data) and
-        //   recreate. (This is captured by the `upgrade` case where from == 0)
-        //
-        // - If `tableList` doesn't exist and `PRAGMA user_version` is 0, it's
-        //   the first time through, just create the new tables.
-        //
-        // - Otherwise, it's a normal schema upgrade from an earlier
-        //   `PRAGMA user_version`.
-        let table_list_exists = db.query_one::<i64>(
+        // `tableList` to using `PRAGMA user_version`. If `tableList` exists,
+        // we're hopelessly far in the past, so drop any tables we have (to
+        // ensure we avoid name collisions/stale data) and recreate them.
+        let table_list_exists = tx.query_one::<i64>(
             "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'tableList'",
         )? != 0;
-
         if table_list_exists {
-            drop(db)?;
+            drop(tx)?;
         }
-        return create(db);
+        create(tx)
     }
-    if user_version != VERSION {
-        if user_version < VERSION {
-            upgrade(db, user_version)?;
-        } else {
-            log::warn!(
-                "Loaded future schema version {} (we only understand version {}). \
-                 Optimistically ",
-                user_version,
-                VERSION
-            )
+
+    // https://github.com/mozilla-mobile/firefox-ios/blob/master/Storage/SQL/LoginsSchema.swift#L100
+    fn upgrade_from(&self, tx: &Transaction<'_>, version: u32) -> open_database::Result<()> {
+        match version {
+            1 => {}
+            // These indices were added in v3 (apparently)
+            2 => tx.execute_all(&[
+                CREATE_OVERRIDE_HOSTNAME_INDEX_SQL,
+                CREATE_DELETED_HOSTNAME_INDEX_SQL,
+            ])?,
+            // This is the update from the firefox-ios schema to our schema.
+            // The `loginsSyncMeta` table was added in v4, and we moved
+            // from using microseconds to milliseconds for `timeCreated`,
+            // `timeLastUsed`, and `timePasswordChanged`.
+            3 => tx.execute_all(&[
+                CREATE_META_TABLE_SQL,
+                UPDATE_LOCAL_TIMESTAMPS_TO_MILLIS_SQL,
+                UPDATE_MIRROR_TIMESTAMPS_TO_MILLIS_SQL,
+            ])?,
+            4 => tx.execute_all(&[
+                CREATE_LOCAL_TIME_CREATED_INDEX_SQL,
+                CREATE_MIRROR_TIME_CREATED_INDEX_SQL,
+            ])?,
+            5 => tx.execute_batch(ADD_LOCAL_ONLY_COLUMN_SQL)?,
+            6 => tx.execute_all(&[CREATE_HISTORY_TABLE_SQL, CREATE_HISTORY_GUID_INDEX_SQL])?,
+            7 => tx.execute_all(&[ADD_LOCAL_NOTES_COLUMN_SQL, ADD_MIRROR_NOTES_COLUMN_SQL])?,
+            8 => tx.execute_batch(ADD_WEAK_UPLOAD_COLUMN_SQL)?,
+            9 => tx.execute_all(&[
+                ADD_LOCAL_UNKNOWN_FIELDS_COLUMN_SQL,
+                ADD_MIRROR_UNKNOWN_FIELDS_COLUMN_SQL,
+            ])?,
+            10 => tx.execute_batch(CREATE_STAGING_TABLE_SQL)?,
+            11 => tx.execute_batch(ADD_FIELD_MODIFIED_COLUMN_SQL)?,
+            12 => tx.execute_all(&[
+                CREATE_ACCESS_LOG_TABLE_SQL,
+                CREATE_ACCESS_LOG_TIMESTAMP_INDEX_SQL,
+            ])?,
+            // The new columns are filled in by `prepare_writer`.
+            13 => tx.execute_all(&[
+                ADD_LOCAL_REV_HOST_COLUMN_SQL,
+                ADD_MIRROR_REV_HOST_COLUMN_SQL,
+                CREATE_LOCAL_REV_HOST_INDEX_SQL,
+                CREATE_MIRROR_REV_HOST_INDEX_SQL,
+            ])?,
+            _ => return Err(open_database::Error::IncompatibleVersion(version)),
         }
+        Ok(())
     }
-    Ok(())
-}
 
-// https://github.com/mozilla-mobile/firefox-ios/blob/master/Storage/SQL/LoginsSchema.swift#L100
-fn upgrade(db: &Connection, from: i64) -> Result<()> {
-    log::debug!("Upgrading schema from {} to {}", from, VERSION);
-    if from == VERSION {
-        return Ok(());
+    // Newer versions only add tables and columns, so we optimistically keep
+    // using the database.
+    fn can_use_future_version(&self, _version: u32) -> bool {
+        true
     }
-    assert_ne!(
-        from, 0,
-        "Upgrading from user_version = 0 should already be handled (in `init`)"
-    );
-    if from < 3 {
-        // These indices were added in v3 (apparently)
-        db.execute_all(&[
-            CREATE_OVERRIDE_HOSTNAME_INDEX_SQL,
-            CREATE_DELETED_HOSTNAME_INDEX_SQL,
-        ])?;
+
+    // SQLCipher reports a wrong key as `SQLITE_NOTADB`, the same as a file
+    // which isn't a database, so only a failed integrity check on a page we
+    // could decrypt means that an encrypted database is corrupt.
+    fn is_corruption_error(&self, e: &rusqlite::Error) -> bool {
+        if self.encryption_key.is_none() {
+            return open_database::is_corruption_error(e);
+        }
+        match e {
+            rusqlite::Error::SqliteFailure(err, _) => err.code == ErrorCode::DatabaseCorrupt,
+            _ => false,
+        }
     }
-    if from < 4 {
-        // This is the update from the firefox-ios schema to our schema.
-        // The `loginsSyncMeta` table was added in v4, and we moved
-        // from using microseconds to milliseconds for `timeCreated`,
-        // `timeLastUsed`, and `timePasswordChanged`.
-        db.execute_all(&[
-            CREATE_META_TABLE_SQL,
-            UPDATE_LOCAL_TIMESTAMPS_TO_MILLIS_SQL,
-            UPDATE_MIRROR_TIMESTAMPS_TO_MILLIS_SQL,
-            &*SET_VERSION_SQL,
-        ])?;
+}
+
+fn define_functions(conn: &Connection) -> rusqlite::Result<()> {
+    use rusqlite::functions::FunctionFlags;
+    conn.create_scalar_function(
+        "logins_reverse_host",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(reverse_host_of_origin(&ctx.get::<String>(0)?)),
+    )
+}
+
+/// The value of the `rev_host` column for a login with `hostname`, or
+/// `None` if it isn't a URL with a host.
+pub(crate) fn reverse_host_of_origin(hostname: &str) -> Option<String> {
+    let url = url::Url::parse(hostname).ok()?;
+    url.host_str().map(reverse_host)
+}
+
+/// Reverses `host`, which must already be normalized, and adds a trailing
+/// dot, as stored in the `rev_host` column.
+pub(crate) fn reverse_host(host: &str) -> String {
+    let mut rev_host: String = host.chars().rev().collect();
+    rev_host.push('.');
+    rev_host
+}
+
+/// Sets up a writable connection after its schema is created or migrated:
+/// creates the triggers which maintain `rev_host`, and fixes any values
+/// which are out of date.
+pub(crate) fn prepare_writer(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(CREATE_REV_HOST_TRIGGERS_SQL)?;
+    conn.execute_batch(UPDATE_REV_HOSTS_SQL)
+}
+
+pub(crate) fn set_key_pragmas(
+    db: &Connection,
+    encryption_key: Option<&str>,
+    salt: Option<&str>,
+) -> rusqlite::Result<()> {
+    if let Some(key) = encryption_key {
+        db.set_pragma("key", key)?
+            .set_pragma("secure_delete", true)?;
+
+        sqlcipher_3_compat(db)?;
+
+        if let Some(s) = salt {
+            // If a salt is also provided, this means the consumer does not want the salt stored
+            // in the database header. Currently only iOS uses this.
+            db.set_pragma("cipher_plaintext_header_size", 32)?;
+            db.set_pragma("cipher_salt", format!("x'{}'", s))?;
+        }
     }
     Ok(())
 }
 
-pub(crate) fn create(db: &Connection) -> Result<()> {
+pub(crate) fn sqlcipher_3_compat(conn: &Connection) -> rusqlite::Result<()> {
+    // SQLcipher pre-4.0.0 compatibility. Using SHA1 still
+    // is less than ideal, but should be fine. Real uses of
+    // this (lockwise, etc) use a real random string for the
+    // encryption key, so the reduced KDF iteration count
+    // is fine.
+    conn.set_pragma("cipher_page_size", 1024)?
+        .set_pragma("kdf_iter", 64000)?
+        .set_pragma("cipher_hmac_algorithm", "HMAC_SHA1")?
+        .set_pragma("cipher_kdf_algorithm", "PBKDF2_HMAC_SHA1")?;
+    Ok(())
+}
+
+fn create(db: &Connection) -> open_database::Result<()> {
     log::debug!("Creating schema");
     db.execute_all(&[
         &*CREATE_LOCAL_TABLE_SQL,
         &*CREATE_MIRROR_TABLE_SQL,
         CREATE_OVERRIDE_HOSTNAME_INDEX_SQL,
         CREATE_DELETED_HOSTNAME_INDEX_SQL,
+        CREATE_LOCAL_TIME_CREATED_INDEX_SQL,
+        CREATE_MIRROR_TIME_CREATED_INDEX_SQL,
         CREATE_META_TABLE_SQL,
-        &*SET_VERSION_SQL,
+        CREATE_HISTORY_TABLE_SQL,
+        CREATE_HISTORY_GUID_INDEX_SQL,
+        CREATE_STAGING_TABLE_SQL,
+        CREATE_ACCESS_LOG_TABLE_SQL,
+        CREATE_ACCESS_LOG_TIMESTAMP_INDEX_SQL,
+        CREATE_LOCAL_REV_HOST_INDEX_SQL,
+        CREATE_MIRROR_REV_HOST_INDEX_SQL,
     ])?;
     Ok(())
 }
 
-pub(crate) fn drop(db: &Connection) -> Result<()> {
+fn drop(db: &Connection) -> open_database::Result<()> {
     log::debug!("Dropping schema");
     db.execute_all(&[
         "DROP TABLE IF EXISTS loginsM",
         "DROP TABLE IF EXISTS loginsL",
         "DROP TABLE IF EXISTS loginsSyncMeta",
+        "DROP TABLE IF EXISTS loginsHistory",
+        "DROP TABLE IF EXISTS loginsStaging",
+        "DROP TABLE IF EXISTS loginsAccessLog",
         "PRAGMA user_version = 0",
     ])?;
     Ok(())
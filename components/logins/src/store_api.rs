@@ -0,0 +1,193 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A handle to a `PasswordEngine` which doesn't block its callers on writes.
+//!
+//! Writes made through a `PasswordStoreApi` are queued, and performed one at
+//! a time on a single background thread, which calls back with the result.
+//! As there's only ever one writer, concurrent callers never fail with
+//! "database is locked", and the order of their writes is preserved.
+
+use crate::engine::PasswordEngine;
+use crate::error::*;
+use crate::login::Login;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+
+type Job = Box<dyn FnOnce(&PasswordEngine) + Send>;
+
+pub struct PasswordStoreApi {
+    engine: Arc<Mutex<PasswordEngine>>,
+    // `None` once we're being dropped.
+    queue: Mutex<Option<mpsc::Sender<Job>>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl PasswordStoreApi {
+    pub fn new(engine: PasswordEngine) -> Result<Self> {
+        let engine = Arc::new(Mutex::new(engine));
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let writer_engine = engine.clone();
+        let writer = thread::Builder::new()
+            .name("logins-writer".into())
+            .spawn(move || {
+                for job in receiver {
+                    job(&lock(&writer_engine));
+                }
+            })?;
+        Ok(Self {
+            engine,
+            queue: Mutex::new(Some(sender)),
+            writer: Some(writer),
+        })
+    }
+
+    /// Queues `op` to run on the writer thread, and calls `callback` there
+    /// with its result once it's done. Callbacks should return quickly, as
+    /// later writes wait for them.
+    pub fn write<T, F, C>(&self, op: F, callback: C)
+    where
+        F: FnOnce(&PasswordEngine) -> Result<T> + Send + 'static,
+        C: FnOnce(Result<T>) + Send + 'static,
+    {
+        let job: Job = Box::new(move |engine| callback(op(engine)));
+        let unsent = match self.queue.lock().unwrap().as_ref() {
+            Some(sender) => sender.send(job).err().map(|e| e.0),
+            None => Some(job),
+        };
+        // The writer only goes away if an earlier job panicked. Rather than
+        // dropping the write, make it on this thread.
+        if let Some(job) = unsent {
+            log::warn!("Logins writer thread is gone; writing on the calling thread");
+            job(&lock(&self.engine));
+        }
+    }
+
+    /// Runs `op` on the calling thread, once any write in progress is done.
+    /// Writes which are still queued aren't waited for; use `flush` first
+    /// to see their results.
+    pub fn read<T>(&self, op: impl FnOnce(&PasswordEngine) -> T) -> T {
+        op(&lock(&self.engine))
+    }
+
+    /// Blocks until every write queued so far has been made.
+    pub fn flush(&self) {
+        let (sender, receiver) = mpsc::channel();
+        self.write(
+            |_| Ok(()),
+            move |_: Result<()>| {
+                let _ = sender.send(());
+            },
+        );
+        let _ = receiver.recv();
+    }
+
+    pub fn add(&self, login: Login, callback: impl FnOnce(Result<String>) + Send + 'static) {
+        self.write(move |engine| engine.add(login), callback);
+    }
+
+    pub fn update(&self, login: Login, callback: impl FnOnce(Result<()>) + Send + 'static) {
+        self.write(move |engine| engine.update(login), callback);
+    }
+
+    pub fn delete(&self, id: &str, callback: impl FnOnce(Result<bool>) + Send + 'static) {
+        let id = id.to_owned();
+        self.write(move |engine| engine.delete(&id), callback);
+    }
+
+    pub fn touch(&self, id: &str, callback: impl FnOnce(Result<()>) + Send + 'static) {
+        let id = id.to_owned();
+        self.write(move |engine| engine.touch(&id), callback);
+    }
+
+    pub fn wipe_local(&self, callback: impl FnOnce(Result<()>) + Send + 'static) {
+        self.write(|engine| engine.wipe_local(), callback);
+    }
+}
+
+impl Drop for PasswordStoreApi {
+    fn drop(&mut self) {
+        // Closing the queue lets the writer finish what's already queued, and
+        // then exit.
+        self.queue.lock().unwrap().take();
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                log::error!("Logins writer thread panicked");
+            }
+        }
+    }
+}
+
+// A job which panicked poisons the engine's mutex, but the engine is still
+// usable: every write it started was in a transaction, which was rolled back.
+fn lock(engine: &Mutex<PasswordEngine>) -> MutexGuard<'_, PasswordEngine> {
+    engine.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(i: usize) -> Login {
+        Login {
+            guid: format!("dummy_{:06}", i).into(),
+            hostname: format!("https://{}.example.com", i),
+            form_submit_url: Some(format!("https://{}.example.com", i)),
+            username: "user".into(),
+            password: "hunter2".into(),
+            ..Login::default()
+        }
+    }
+
+    #[test]
+    fn test_store_api() {
+        let api =
+            Arc::new(PasswordStoreApi::new(PasswordEngine::new_in_memory(None).unwrap()).unwrap());
+        let (sender, receiver) = mpsc::channel();
+        let threads = (0..4)
+            .map(|t| {
+                let api = api.clone();
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for i in 0..10 {
+                        let sender = sender.clone();
+                        api.add(login(t * 10 + i), move |result| {
+                            sender.send(result.map(|_| ())).unwrap();
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        api.flush();
+        drop(sender);
+        let results = receiver.iter().collect::<Vec<_>>();
+        assert_eq!(results.len(), 40);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(api.read(|engine| engine.list()).unwrap().len(), 40);
+
+        // Errors are passed to the callback, and writes stay in order.
+        let (sender, receiver) = mpsc::channel();
+        let s = sender.clone();
+        api.add(login(0), move |result| {
+            s.send(result.map(|_| ())).unwrap();
+        });
+        let s = sender.clone();
+        api.delete("dummy_000000", move |result| {
+            s.send(result.map(|existed| assert!(existed))).unwrap();
+        });
+        api.delete("dummy_000000", move |result| {
+            sender
+                .send(result.map(|existed| assert!(!existed)))
+                .unwrap();
+        });
+        let results = receiver.iter().collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_err());
+        assert!(results[1].is_ok());
+        assert!(results[2].is_ok());
+    }
+}
@@ -0,0 +1,212 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Sharing one `PasswordEngine` per database file.
+//!
+//! Each engine has its own writer connection, so opening the same file twice
+//! means two writers, which block (and eventually fail with "database is
+//! locked") whenever both want to write. Apps with several profiles, or
+//! several components using the same profile, can instead get their stores
+//! from a `StoreManager`, which only opens each file once within the process.
+
+use crate::engine::PasswordEngine;
+use crate::error::*;
+use rc_crypto::digest;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A handle to a store shared through a `StoreManager`.
+pub type SharedStore = Arc<Mutex<PasswordEngine>>;
+
+struct Entry {
+    store: SharedStore,
+    // A digest of the encryption key the store was opened with, so that a
+    // caller with the wrong key can't use it.
+    key_digest: Option<Vec<u8>>,
+    last_used: Instant,
+}
+
+#[derive(Default)]
+pub struct StoreManager {
+    stores: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+impl StoreManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the store for the database at `path`, opening it if it isn't
+    /// already open. Fails if it's open with a different `encryption_key`.
+    pub fn get_or_open(
+        &self,
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+    ) -> Result<SharedStore> {
+        self.get_or_open_with(path.as_ref(), encryption_key, |path| {
+            PasswordEngine::new(path, encryption_key)
+        })
+    }
+
+    /// Like `get_or_open`, for databases with a plaintext header.
+    pub fn get_or_open_with_salt(
+        &self,
+        path: impl AsRef<Path>,
+        encryption_key: &str,
+        salt: &str,
+    ) -> Result<SharedStore> {
+        self.get_or_open_with(path.as_ref(), Some(encryption_key), |path| {
+            PasswordEngine::new_with_salt(path, encryption_key, salt)
+        })
+    }
+
+    fn get_or_open_with(
+        &self,
+        path: &Path,
+        encryption_key: Option<&str>,
+        open: impl FnOnce(&Path) -> Result<PasswordEngine>,
+    ) -> Result<SharedStore> {
+        let path = canonical_path(path)?;
+        let key_digest = encryption_key.map(key_digest).transpose()?;
+        let mut stores = self.stores.lock().unwrap();
+        if let Some(entry) = stores.get_mut(&path) {
+            if rc_crypto::constant_time::verify_slices_are_equal(
+                entry.key_digest.as_deref().unwrap_or_default(),
+                key_digest.as_deref().unwrap_or_default(),
+            )
+            .is_err()
+            {
+                throw!(ErrorKind::StoreOpenWithDifferentKey(
+                    path.display().to_string()
+                ));
+            }
+            entry.last_used = Instant::now();
+            return Ok(entry.store.clone());
+        }
+        let store = Arc::new(Mutex::new(open(&path)?));
+        stores.insert(
+            path,
+            Entry {
+                store: store.clone(),
+                key_digest,
+                last_used: Instant::now(),
+            },
+        );
+        Ok(store)
+    }
+
+    /// Closes the stores that haven't been handed out for `idle_for`, and
+    /// which aren't being used (no handles to them are left outside the
+    /// manager). Returns how many were closed.
+    pub fn close_idle(&self, idle_for: Duration) -> usize {
+        let mut stores = self.stores.lock().unwrap();
+        let before = stores.len();
+        stores.retain(|path, entry| {
+            let idle =
+                entry.last_used.elapsed() >= idle_for && Arc::strong_count(&entry.store) == 1;
+            if idle {
+                log::debug!("Closing idle logins store {}", path.display());
+            }
+            !idle
+        });
+        before - stores.len()
+    }
+
+    /// Stops sharing the store at `path`. It's closed once every handle to it
+    /// is dropped. Returns whether it was open.
+    pub fn close(&self, path: impl AsRef<Path>) -> Result<bool> {
+        let path = canonical_path(path.as_ref())?;
+        Ok(self.stores.lock().unwrap().remove(&path).is_some())
+    }
+
+    /// The number of stores the manager has open.
+    pub fn num_open(&self) -> usize {
+        self.stores.lock().unwrap().len()
+    }
+}
+
+// The same file can be reached by different paths, such as through a
+// symlink, or relative to a different directory. The file itself may not
+// exist yet, but its directory must.
+fn canonical_path(path: &Path) -> Result<PathBuf> {
+    let file_name = match path.file_name() {
+        Some(name) => name,
+        None => return Ok(std::fs::canonicalize(path)?),
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(std::fs::canonicalize(dir)?.join(file_name))
+}
+
+fn key_digest(key: &str) -> Result<Vec<u8>> {
+    rc_crypto::ensure_initialized();
+    let digest = digest::digest(&digest::SHA256, key.as_bytes())
+        .map_err(|e| ErrorKind::EncryptionFailed(e.to_string()))?;
+    Ok(digest.as_ref().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::login::Login;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_store_manager() {
+        let tmpdir = TempDir::new("test_store_manager").unwrap();
+        let path = tmpdir.path().join("logins.sqlite");
+        let manager = StoreManager::new();
+
+        let store = manager.get_or_open(&path, Some("secret")).unwrap();
+        // A different path to the same file gets the same store.
+        let other_path = tmpdir.path().join(".").join("logins.sqlite");
+        let same = manager.get_or_open(&other_path, Some("secret")).unwrap();
+        assert!(Arc::ptr_eq(&store, &same));
+        assert_eq!(manager.num_open(), 1);
+        match manager.get_or_open(&path, Some("wrong")) {
+            Err(e) => assert_eq!(e.label(), "StoreOpenWithDifferentKey"),
+            Ok(_) => panic!("Should fail with a different key"),
+        }
+        assert!(manager.get_or_open(&path, None).is_err());
+
+        store
+            .lock()
+            .unwrap()
+            .add(Login {
+                hostname: "https://www.example.com".into(),
+                form_submit_url: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "hunter2".into(),
+                ..Login::default()
+            })
+            .unwrap();
+        assert_eq!(same.lock().unwrap().list().unwrap().len(), 1);
+
+        let profile2 = manager
+            .get_or_open(tmpdir.path().join("profile2.sqlite"), Some("secret"))
+            .unwrap();
+        assert_eq!(manager.num_open(), 2);
+
+        // Stores which are still in use aren't closed.
+        assert_eq!(manager.close_idle(Duration::from_secs(0)), 0);
+        drop(store);
+        drop(same);
+        assert_eq!(manager.close_idle(Duration::from_secs(60 * 60)), 0);
+        assert_eq!(manager.close_idle(Duration::from_secs(0)), 1);
+        assert_eq!(manager.num_open(), 1);
+        drop(profile2);
+        assert!(manager
+            .close(tmpdir.path().join("profile2.sqlite"))
+            .unwrap());
+        assert_eq!(manager.num_open(), 0);
+
+        // Reopening after closing sees what was written before.
+        let store = manager.get_or_open(&path, Some("secret")).unwrap();
+        assert_eq!(store.lock().unwrap().list().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,207 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Which collection logins are synced to, and how their fields are named in
+//! the server's records.
+//!
+//! Everyone syncing with Mozilla's servers should use the default. Other
+//! values are for exercising staging or self-hosted servers which use a
+//! different collection, or a different record format, without rebuilding
+//! the crate.
+
+use crate::error::*;
+use serde_json::{Map, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
+use sync15::Payload;
+
+/// The collection logins are synced to by default.
+pub const DEFAULT_COLLECTION_NAME: &str = "passwords";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncConfig {
+    pub collection_name: String,
+    /// Maps the name of a field in a serialized `Login` (such as `hostname`
+    /// or `formSubmitURL`) to the name the server's records use for it.
+    /// Fields which aren't in the map have the same name in both.
+    pub field_names: HashMap<String, String>,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            collection_name: DEFAULT_COLLECTION_NAME.into(),
+            field_names: HashMap::new(),
+        }
+    }
+}
+
+impl SyncConfig {
+    /// Checks that the collection name isn't empty, and that no two fields
+    /// would have the same name in the server's records.
+    pub fn check_valid(&self) -> Result<()> {
+        if self.collection_name.is_empty() {
+            throw!(ErrorKind::InvalidSyncConfig(
+                "The collection name is empty".into()
+            ));
+        }
+        let mut server_names = HashSet::with_capacity(self.field_names.len());
+        for (local, server) in &self.field_names {
+            if local == "id" || server == "id" || local == "deleted" || server == "deleted" {
+                throw!(ErrorKind::InvalidSyncConfig(
+                    "`id` and `deleted` can't be renamed".into()
+                ));
+            }
+            if !server_names.insert(server.as_str()) {
+                throw!(ErrorKind::InvalidSyncConfig(format!(
+                    "More than one field is named `{}`",
+                    server
+                )));
+            }
+            // A field which isn't renamed keeps its name, so it can't also be
+            // the new name of another.
+            if server != local && self.field_names.get(server).is_none() && is_login_field(server) {
+                throw!(ErrorKind::InvalidSyncConfig(format!(
+                    "`{}` is already the name of a field",
+                    server
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renames the fields of an incoming record to the names `Login` uses.
+    pub(crate) fn to_local(&self, payload: Payload) -> Payload {
+        if self.field_names.is_empty() {
+            return payload;
+        }
+        let local_names: HashMap<&str, &str> = self
+            .field_names
+            .iter()
+            .map(|(local, server)| (server.as_str(), local.as_str()))
+            .collect();
+        rename_fields(payload, |name| local_names.get(name).copied())
+    }
+
+    /// Renames the fields of an outgoing record to the names the server's
+    /// records use.
+    pub(crate) fn to_server(&self, payload: Payload) -> Payload {
+        if self.field_names.is_empty() {
+            return payload;
+        }
+        rename_fields(payload, |name| {
+            self.field_names.get(name).map(String::as_str)
+        })
+    }
+}
+
+fn rename_fields<'a>(mut payload: Payload, new_name: impl Fn(&str) -> Option<&'a str>) -> Payload {
+    payload.data = std::mem::replace(&mut payload.data, Map::new())
+        .into_iter()
+        .map(|(name, value): (String, JsonValue)| match new_name(&name) {
+            Some(new_name) => (new_name.to_owned(), value),
+            None => (name, value),
+        })
+        .collect();
+    payload
+}
+
+fn is_login_field(name: &str) -> bool {
+    [
+        "hostname",
+        "httpRealm",
+        "formSubmitURL",
+        "usernameField",
+        "passwordField",
+        "username",
+        "password",
+        "notes",
+        "timeCreated",
+        "timePasswordChanged",
+        "timeLastUsed",
+        "timesUsed",
+    ]
+    .contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::LoginDb;
+    use crate::login::Login;
+    use sync15::{telemetry, IncomingChangeset, ServerTimestamp};
+
+    #[test]
+    fn test_sync_config() {
+        let renamed = |pairs: &[(&str, &str)]| SyncConfig {
+            collection_name: "addresses-v2".into(),
+            field_names: pairs
+                .iter()
+                .map(|(local, server)| ((*local).to_owned(), (*server).to_owned()))
+                .collect(),
+        };
+        assert!(SyncConfig::default().check_valid().is_ok());
+        assert!(SyncConfig {
+            collection_name: "".into(),
+            ..SyncConfig::default()
+        }
+        .check_valid()
+        .is_err());
+        assert!(renamed(&[("hostname", "id")]).check_valid().is_err());
+        assert!(
+            renamed(&[("hostname", "origin"), ("formSubmitURL", "origin")])
+                .check_valid()
+                .is_err()
+        );
+        assert!(renamed(&[("hostname", "username")]).check_valid().is_err());
+        // Swapping two fields is fine.
+        assert!(
+            renamed(&[("hostname", "username"), ("username", "hostname")])
+                .check_valid()
+                .is_ok()
+        );
+
+        let mut db = LoginDb::open_in_memory(Some("testing")).unwrap();
+        db.set_sync_config(renamed(&[
+            ("hostname", "origin"),
+            ("formSubmitURL", "formActionOrigin"),
+        ]))
+        .unwrap();
+        let scope = db.begin_interrupt_scope();
+        let login = Login {
+            guid: "dummy_000001".into(),
+            hostname: "https://www.example.com".into(),
+            form_submit_url: Some("https://www.example.com".into()),
+            username: "user".into(),
+            password: "hunter2".into(),
+            ..Login::default()
+        };
+        let payload = Payload::from_record(login.clone()).unwrap();
+        let server_payload = db.sync_config().to_server(payload.clone());
+        assert_eq!(server_payload.data["origin"], "https://www.example.com");
+        assert!(!server_payload.data.contains_key("hostname"));
+        assert_eq!(db.sync_config().to_local(server_payload.clone()), payload);
+
+        let mut inbound = IncomingChangeset::new("addresses-v2", ServerTimestamp(10000));
+        inbound
+            .changes
+            .push((server_payload, ServerTimestamp(10000)));
+        let outgoing = db
+            .do_apply_incoming(inbound, &mut telemetry::Engine::new("addresses-v2"), &scope)
+            .unwrap();
+        assert_eq!(outgoing.collection, "addresses-v2");
+        let applied = db.get_by_id("dummy_000001").unwrap().unwrap();
+        assert_eq!(applied.hostname, login.hostname);
+        assert_eq!(applied.form_submit_url, login.form_submit_url);
+
+        let mut changed = applied;
+        changed.password = "hunter3".into();
+        db.update(changed).unwrap();
+        let outgoing = db.fetch_outgoing(ServerTimestamp(20000), &scope).unwrap();
+        assert_eq!(outgoing.changes.len(), 1);
+        let data = &outgoing.changes[0].data;
+        assert_eq!(data["formActionOrigin"], "https://www.example.com");
+        assert_eq!(data["password"], "hunter3");
+        assert!(!data.contains_key("formSubmitURL"));
+    }
+}
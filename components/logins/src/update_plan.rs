@@ -2,11 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::db::LoginDb;
 use crate::error::*;
-use crate::login::{LocalLogin, Login, MirrorLogin, SyncStatus};
+use crate::login::{LocalLogin, Login, MergePolicy, MirrorLogin, SyncStatus};
 use crate::util;
-use rusqlite::{named_params, Connection};
-use sql_support::SqlInterruptScope;
+use rusqlite::named_params;
+use sql_support::{ConnExt, SqlInterruptScope};
 use std::time::SystemTime;
 use sync15::ServerTimestamp;
 use sync_guid::Guid;
@@ -19,11 +20,20 @@ pub(crate) struct UpdatePlan {
     // the bool is the `is_overridden` flag, the i64 is ServerTimestamp in millis
     pub mirror_inserts: Vec<(Login, i64, bool)>,
     pub mirror_updates: Vec<(Login, i64)>,
+    // Local logins which take on the GUID of the incoming record they
+    // duplicate, as (old, new).
+    pub local_guid_changes: Vec<(Guid, Guid)>,
 }
 
 impl UpdatePlan {
-    pub fn plan_two_way_merge(&mut self, local: &Login, upstream: (Login, ServerTimestamp)) {
-        let is_override = local.time_password_changed > upstream.0.time_password_changed;
+    pub fn plan_two_way_merge(
+        &mut self,
+        local: &Login,
+        upstream: (Login, ServerTimestamp),
+        policy: MergePolicy,
+    ) {
+        let upstream_is_newer = upstream.0.time_password_changed >= local.time_password_changed;
+        let is_override = !policy.prefer_remote(local, &upstream.0, upstream_is_newer);
         self.mirror_inserts
             .push((upstream.0, upstream.1.as_millis() as i64, is_override));
         if !is_override {
@@ -31,6 +41,23 @@ impl UpdatePlan {
         }
     }
 
+    // Like `plan_two_way_merge`, but `local` was matched with `upstream` by
+    // content rather than GUID. If the local login wins, it takes on the GUID
+    // of `upstream`, so that it replaces the record on the server rather than
+    // being uploaded as a duplicate of it.
+    pub fn plan_content_merge(
+        &mut self,
+        local: &Login,
+        upstream: (Login, ServerTimestamp),
+        policy: MergePolicy,
+    ) {
+        let (old, new) = (local.guid.clone(), upstream.0.guid.clone());
+        self.plan_two_way_merge(local, upstream, policy);
+        if self.delete_local.last() != Some(&old) {
+            self.local_guid_changes.push((old, new));
+        }
+    }
+
     pub fn plan_three_way_merge(
         &mut self,
         local: LocalLogin,
@@ -38,16 +65,23 @@ impl UpdatePlan {
         upstream: Login,
         upstream_time: ServerTimestamp,
         server_now: ServerTimestamp,
+        policy: MergePolicy,
     ) {
-        let local_age = SystemTime::now()
-            .duration_since(local.local_modified)
-            .unwrap_or_default();
+        let now = SystemTime::now();
         let remote_age = server_now.duration_since(upstream_time).unwrap_or_default();
 
         let local_delta = local.login.delta(&shared.login);
         let upstream_delta = upstream.delta(&shared.login);
 
-        let merged_delta = local_delta.merge(upstream_delta, remote_age < local_age);
+        // Several local changes may have been made since the last sync, so
+        // compare the remote change with when each field was last changed,
+        // rather than with the most recent change to the whole login.
+        let merged_delta = local_delta.merge_fields(upstream_delta, |field| {
+            let local_age = now
+                .duration_since(local.field_modified(field))
+                .unwrap_or_default();
+            policy.prefer_remote(&local.login, &upstream, remote_age < local_age)
+        });
 
         // Update mirror to upstream
         self.mirror_updates
@@ -64,6 +98,13 @@ impl UpdatePlan {
         self.delete_mirror.push(id);
     }
 
+    // The local record only has usage changes waiting for a weak upload,
+    // which are dropped in favor of the upstream record.
+    pub fn plan_replace_weak_local(&mut self, upstream: Login, time: ServerTimestamp) {
+        self.plan_delete(upstream.guid.clone());
+        self.plan_mirror_insert(upstream, time, false);
+    }
+
     pub fn plan_mirror_update(&mut self, login: Login, time: ServerTimestamp) {
         self.mirror_updates.push((login, time.as_millis() as i64));
     }
@@ -73,9 +114,9 @@ impl UpdatePlan {
             .push((login, time.as_millis() as i64, is_override));
     }
 
-    fn perform_deletes(&self, conn: &Connection, scope: &SqlInterruptScope) -> Result<()> {
+    fn perform_deletes(&self, db: &LoginDb, scope: &SqlInterruptScope) -> Result<()> {
         sql_support::each_chunk(&self.delete_local, |chunk, _| -> Result<()> {
-            conn.execute(
+            db.execute(
                 &format!(
                     "DELETE FROM loginsL WHERE guid IN ({vars})",
                     vars = sql_support::repeat_sql_vars(chunk.len())
@@ -87,7 +128,7 @@ impl UpdatePlan {
         })?;
 
         sql_support::each_chunk(&self.delete_mirror, |chunk, _| {
-            conn.execute(
+            db.execute(
                 &format!(
                     "DELETE FROM loginsM WHERE guid IN ({vars})",
                     vars = sql_support::repeat_sql_vars(chunk.len())
@@ -98,8 +139,32 @@ impl UpdatePlan {
         })
     }
 
+    fn perform_local_guid_changes(&self, db: &LoginDb, scope: &SqlInterruptScope) -> Result<()> {
+        let local_ms: i64 = util::system_time_ms_i64(SystemTime::now());
+        for (old, new) in &self.local_guid_changes {
+            log::trace!("Changing local {:?} to {:?}", old, new);
+            db.execute_named_cached(
+                &format!(
+                    "UPDATE loginsL
+                     SET guid = :new,
+                         local_modified = :local_modified,
+                         sync_status = {changed}
+                     WHERE guid = :old",
+                    changed = SyncStatus::Changed as u8
+                ),
+                named_params! { ":old": old, ":new": new, ":local_modified": local_ms },
+            )?;
+            db.execute_named_cached(
+                "UPDATE loginsHistory SET guid = :new WHERE guid = :old",
+                named_params! { ":old": old, ":new": new },
+            )?;
+            scope.err_if_interrupted()?;
+        }
+        Ok(())
+    }
+
     // These aren't batched but probably should be.
-    fn perform_mirror_updates(&self, conn: &Connection, scope: &SqlInterruptScope) -> Result<()> {
+    fn perform_mirror_updates(&self, db: &LoginDb, scope: &SqlInterruptScope) -> Result<()> {
         let sql = "
             UPDATE loginsM
             SET server_modified = :server_modified,
@@ -110,6 +175,8 @@ impl UpdatePlan {
                 password        = :password,
                 hostname        = :hostname,
                 username        = :username,
+                notes           = :notes,
+                unknown_fields  = :unknown_fields,
                 -- Avoid zeroes if the remote has been overwritten by an older client.
                 timesUsed           = coalesce(nullif(:times_used,            0), timesUsed),
                 timeLastUsed        = coalesce(nullif(:time_last_used,        0), timeLastUsed),
@@ -117,7 +184,7 @@ impl UpdatePlan {
                 timeCreated         = coalesce(nullif(:time_created,          0), timeCreated)
             WHERE guid = :guid
         ";
-        let mut stmt = conn.prepare_cached(sql)?;
+        let mut stmt = db.prepare_cached(sql)?;
         for (login, timestamp) in &self.mirror_updates {
             log::trace!("Updating mirror {:?}", login.guid_str());
             stmt.execute_named(named_params! {
@@ -126,9 +193,11 @@ impl UpdatePlan {
                 ":form_submit_url": login.form_submit_url,
                 ":username_field": login.username_field,
                 ":password_field": login.password_field,
-                ":password": login.password,
+                ":password": db.encrypt_field(&login.password)?,
                 ":hostname": login.hostname,
-                ":username": login.username,
+                ":username": db.encrypt_field(&login.username)?,
+                ":notes": db.encrypt_optional_field(login.notes.as_deref())?,
+                ":unknown_fields": login.unknown_fields,
                 ":times_used": login.times_used,
                 ":time_last_used": login.time_last_used,
                 ":time_password_changed": login.time_password_changed,
@@ -140,7 +209,7 @@ impl UpdatePlan {
         Ok(())
     }
 
-    fn perform_mirror_inserts(&self, conn: &Connection, scope: &SqlInterruptScope) -> Result<()> {
+    fn perform_mirror_inserts(&self, db: &LoginDb, scope: &SqlInterruptScope) -> Result<()> {
         let sql = "
             INSERT OR IGNORE INTO loginsM (
                 is_overridden,
@@ -153,6 +222,8 @@ impl UpdatePlan {
                 password,
                 hostname,
                 username,
+                notes,
+                unknown_fields,
 
                 timesUsed,
                 timeLastUsed,
@@ -171,6 +242,8 @@ impl UpdatePlan {
                 :password,
                 :hostname,
                 :username,
+                :notes,
+                :unknown_fields,
 
                 :times_used,
                 :time_last_used,
@@ -179,7 +252,7 @@ impl UpdatePlan {
 
                 :guid
             )";
-        let mut stmt = conn.prepare_cached(&sql)?;
+        let mut stmt = db.prepare_cached(&sql)?;
 
         for (login, timestamp, is_overridden) in &self.mirror_inserts {
             log::trace!("Inserting mirror {:?}", login.guid_str());
@@ -190,9 +263,11 @@ impl UpdatePlan {
                 ":form_submit_url": login.form_submit_url,
                 ":username_field": login.username_field,
                 ":password_field": login.password_field,
-                ":password": login.password,
+                ":password": db.encrypt_field(&login.password)?,
                 ":hostname": login.hostname,
-                ":username": login.username,
+                ":username": db.encrypt_field(&login.username)?,
+                ":notes": db.encrypt_optional_field(login.notes.as_deref())?,
+                ":unknown_fields": login.unknown_fields,
                 ":times_used": login.times_used,
                 ":time_last_used": login.time_last_used,
                 ":time_password_changed": login.time_password_changed,
@@ -204,7 +279,7 @@ impl UpdatePlan {
         Ok(())
     }
 
-    fn perform_local_updates(&self, conn: &Connection, scope: &SqlInterruptScope) -> Result<()> {
+    fn perform_local_updates(&self, db: &LoginDb, scope: &SqlInterruptScope) -> Result<()> {
         let sql = format!(
             "UPDATE loginsL
              SET local_modified      = :local_modified,
@@ -218,11 +293,13 @@ impl UpdatePlan {
                  password            = :password,
                  hostname            = :hostname,
                  username            = :username,
+                 notes               = :notes,
+                 unknown_fields      = :unknown_fields,
                  sync_status         = {changed}
              WHERE guid = :guid",
             changed = SyncStatus::Changed as u8
         );
-        let mut stmt = conn.prepare_cached(&sql)?;
+        let mut stmt = db.prepare_cached(&sql)?;
         // XXX OutgoingChangeset should no longer have timestamp.
         let local_ms: i64 = util::system_time_ms_i64(SystemTime::now());
         for l in &self.local_updates {
@@ -233,9 +310,11 @@ impl UpdatePlan {
                 ":form_submit_url": l.login.form_submit_url,
                 ":username_field": l.login.username_field,
                 ":password_field": l.login.password_field,
-                ":password": l.login.password,
+                ":password": db.encrypt_field(&l.login.password)?,
                 ":hostname": l.login.hostname,
-                ":username": l.login.username,
+                ":username": db.encrypt_field(&l.login.username)?,
+                ":notes": db.encrypt_optional_field(l.login.notes.as_deref())?,
+                ":unknown_fields": l.login.unknown_fields,
                 ":time_last_used": l.login.time_last_used,
                 ":time_password_changed": l.login.time_password_changed,
                 ":times_used": l.login.times_used,
@@ -246,15 +325,17 @@ impl UpdatePlan {
         Ok(())
     }
 
-    pub fn execute(&self, conn: &Connection, scope: &SqlInterruptScope) -> Result<()> {
+    pub fn execute(&self, db: &LoginDb, scope: &SqlInterruptScope) -> Result<()> {
         log::debug!("UpdatePlan: deleting records...");
-        self.perform_deletes(conn, scope)?;
+        self.perform_deletes(db, scope)?;
+        log::debug!("UpdatePlan: Changing the GUIDs of local duplicates...");
+        self.perform_local_guid_changes(db, scope)?;
         log::debug!("UpdatePlan: Updating existing mirror records...");
-        self.perform_mirror_updates(conn, scope)?;
+        self.perform_mirror_updates(db, scope)?;
         log::debug!("UpdatePlan: Inserting new mirror records...");
-        self.perform_mirror_inserts(conn, scope)?;
+        self.perform_mirror_inserts(db, scope)?;
         log::debug!("UpdatePlan: Updating reconciled local records...");
-        self.perform_local_updates(conn, scope)?;
+        self.perform_local_updates(db, scope)?;
         Ok(())
     }
 }
@@ -30,6 +30,10 @@ pub fn system_time_ms_i64(t: time::SystemTime) -> i64 {
     duration_ms_i64(t.duration_since(time::UNIX_EPOCH).unwrap_or_default())
 }
 
+pub fn system_time_from_ms_i64(ms: i64) -> time::SystemTime {
+    time::UNIX_EPOCH + time::Duration::from_millis(ms.max(0) as u64)
+}
+
 // Unfortunately, there's not a better way to turn on logging in tests AFAICT
 #[cfg(test)]
 pub(crate) fn init_test_logging() {
@@ -0,0 +1,18 @@
+-- This Source Code Form is subject to the terms of the Mozilla Public
+-- License, v. 2.0. If a copy of the MPL was not distributed with this
+-- file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+-- The experiments this client is enrolled in, and the branch it was put in.
+-- Enrollments are sticky: we only leave an experiment when it ends or its
+-- branch goes away.
+CREATE TABLE IF NOT EXISTS enrollments (
+    experiment_slug TEXT NOT NULL PRIMARY KEY,
+    branch_slug TEXT NOT NULL,
+    -- In ms.
+    enrolled_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value NOT NULL
+) WITHOUT ROWID;
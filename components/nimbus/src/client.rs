@@ -0,0 +1,322 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::*;
+use crate::experiments::{parse_experiments, Experiment};
+use crate::schema;
+use remote_settings::{Client, RemoteSettingsConfig};
+use rusqlite::{Connection, OpenFlags, Row};
+use serde_derive::*;
+use sql_support::ConnExt;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sync_guid::Guid;
+
+const USER_ID_META_KEY: &str = "user_id";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EnrollmentChangeEventType {
+    Enrollment,
+    Unenrollment,
+}
+
+/// Reported when we enroll in, or unenroll from, an experiment, so that the
+/// app can record it in telemetry.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EnrollmentChangeEvent {
+    pub experiment_slug: String,
+    pub branch_slug: String,
+    pub change: EnrollmentChangeEventType,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EnrolledExperiment {
+    pub slug: String,
+    pub branch_slug: String,
+    /// In milliseconds since the Unix epoch.
+    pub enrolled_at: i64,
+}
+
+impl EnrolledExperiment {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            slug: row.get("experiment_slug")?,
+            branch_slug: row.get("branch_slug")?,
+            enrolled_at: row.get("enrolled_at")?,
+        })
+    }
+}
+
+pub struct NimbusClient {
+    client: Client,
+    db: Connection,
+    user_id: String,
+}
+
+impl NimbusClient {
+    /// Opens the enrollments database at `db_path`. Clients are bucketed using
+    /// `user_id` if one is given; otherwise, we use a random ID that's
+    /// generated the first time the database is opened, and persisted in it.
+    pub fn new(
+        config: RemoteSettingsConfig,
+        db_path: impl AsRef<Path>,
+        user_id: Option<String>,
+    ) -> Result<Self> {
+        rc_crypto::ensure_initialized();
+        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_CREATE
+            | OpenFlags::SQLITE_OPEN_READ_WRITE;
+        let db = Connection::open_with_flags(db_path, flags)?;
+        db.execute_batch("PRAGMA journal_mode=WAL;")?;
+        schema::init(&db)?;
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => get_or_create_user_id(&db)?,
+        };
+        Ok(Self {
+            client: Client::new(config)?,
+            db,
+            user_id,
+        })
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Fetches the latest experiments from Remote Settings, and updates our
+    /// enrollments to match.
+    pub fn update_experiments(&self) -> Result<Vec<EnrollmentChangeEvent>> {
+        let experiments = parse_experiments(self.client.get_records()?.records);
+        self.apply_experiments(&experiments)
+    }
+
+    /// Updates our enrollments to match `experiments`, which should be all the
+    /// experiments that are currently live.
+    ///
+    /// Enrollments are sticky: once we're in a branch, we stay in it even if
+    /// the experiment's buckets change, until the experiment or the branch is
+    /// removed. Experiments that are paused don't enroll anyone new, and
+    /// invalid experiments are skipped.
+    pub fn apply_experiments(
+        &self,
+        experiments: &[Experiment],
+    ) -> Result<Vec<EnrollmentChangeEvent>> {
+        let tx = self.db.unchecked_transaction()?;
+        let enrolled: HashMap<String, String> = self
+            .get_active_experiments()?
+            .into_iter()
+            .map(|e| (e.slug, e.branch_slug))
+            .collect();
+        let available: HashMap<&str, &Experiment> =
+            experiments.iter().map(|e| (e.slug.as_str(), e)).collect();
+        let mut events = Vec::new();
+
+        for (slug, branch_slug) in &enrolled {
+            let still_live = available.get(slug.as_str()).map_or(false, |experiment| {
+                experiment.branches.iter().any(|b| &b.slug == branch_slug)
+            });
+            if !still_live {
+                log::info!("Unenrolling from experiment {}", slug);
+                tx.execute_named_cached(
+                    "DELETE FROM enrollments WHERE experiment_slug = :slug",
+                    &[(":slug", slug)],
+                )?;
+                events.push(EnrollmentChangeEvent {
+                    experiment_slug: slug.clone(),
+                    branch_slug: branch_slug.clone(),
+                    change: EnrollmentChangeEventType::Unenrollment,
+                });
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        for experiment in experiments {
+            if enrolled.contains_key(&experiment.slug) || experiment.is_enrollment_paused {
+                continue;
+            }
+            let branch = match experiment.choose_branch(&self.user_id) {
+                Ok(Some(branch)) => branch,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::warn!("Not enrolling in experiment {}: {}", experiment.slug, e);
+                    continue;
+                }
+            };
+            log::info!(
+                "Enrolling in branch {} of experiment {}",
+                branch.slug,
+                experiment.slug
+            );
+            tx.execute_named_cached(
+                "INSERT INTO enrollments (experiment_slug, branch_slug, enrolled_at)
+                 VALUES (:experiment_slug, :branch_slug, :enrolled_at)",
+                &[
+                    (":experiment_slug", &experiment.slug),
+                    (":branch_slug", &branch.slug),
+                    (":enrolled_at", &now),
+                ],
+            )?;
+            events.push(EnrollmentChangeEvent {
+                experiment_slug: experiment.slug.clone(),
+                branch_slug: branch.slug.clone(),
+                change: EnrollmentChangeEventType::Enrollment,
+            });
+        }
+        tx.commit()?;
+        Ok(events)
+    }
+
+    /// Returns the slug of the branch we're enrolled in for the experiment
+    /// `slug`, or `None` if we aren't enrolled in it.
+    pub fn get_experiment_branch(&self, slug: &str) -> Result<Option<String>> {
+        Ok(self.db.try_query_row(
+            "SELECT branch_slug FROM enrollments WHERE experiment_slug = :slug",
+            &[(":slug", &slug)],
+            |row| row.get::<_, String>(0),
+            true,
+        )?)
+    }
+
+    pub fn get_active_experiments(&self) -> Result<Vec<EnrolledExperiment>> {
+        self.db.query_rows_and_then_named_cached(
+            "SELECT experiment_slug, branch_slug, enrolled_at
+             FROM enrollments
+             ORDER BY enrolled_at, experiment_slug",
+            &[],
+            EnrolledExperiment::from_row,
+        )
+    }
+}
+
+fn get_or_create_user_id(db: &Connection) -> Result<String> {
+    let user_id = db.try_query_row(
+        "SELECT value FROM meta WHERE key = :key",
+        &[(":key", &USER_ID_META_KEY)],
+        |row| row.get::<_, String>(0),
+        true,
+    )?;
+    Ok(match user_id {
+        Some(user_id) => user_id,
+        None => {
+            let user_id = Guid::random().into_string();
+            db.execute_named_cached(
+                "INSERT INTO meta (key, value) VALUES (:key, :value)",
+                &[(":key", &USER_ID_META_KEY), (":value", &user_id)],
+            )?;
+            user_id
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::tests::experiment;
+    use crate::experiments::Branch;
+    use url::Url;
+
+    fn new_client(dir: &tempfile::TempDir, user_id: Option<&str>) -> NimbusClient {
+        let mut config = RemoteSettingsConfig::new(
+            Url::parse("https://settings.example.com").unwrap(),
+            "nimbus-mobile",
+        );
+        config.allow_untrusted_server = true;
+        NimbusClient::new(
+            config,
+            dir.path().join("nimbus.db"),
+            user_id.map(Into::into),
+        )
+        .expect("should open client")
+    }
+
+    #[test]
+    fn test_user_id_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_id = new_client(&dir, None).user_id().to_string();
+        assert_eq!(new_client(&dir, None).user_id(), user_id);
+        assert_eq!(new_client(&dir, Some("app-id")).user_id(), "app-id");
+    }
+
+    #[test]
+    fn test_enrollment() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir, Some("user"));
+        let mut experiments = vec![
+            experiment("everyone", 0, 10_000),
+            experiment("nobody", 0, 0),
+            experiment("paused", 0, 10_000),
+        ];
+        experiments[2].is_enrollment_paused = true;
+
+        let events = client.apply_experiments(&experiments).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].experiment_slug, "everyone");
+        assert_eq!(events[0].change, EnrollmentChangeEventType::Enrollment);
+        let branch = client.get_experiment_branch("everyone").unwrap().unwrap();
+        assert_eq!(events[0].branch_slug, branch);
+        assert_eq!(client.get_experiment_branch("nobody").unwrap(), None);
+        assert_eq!(client.get_experiment_branch("paused").unwrap(), None);
+
+        // Enrollments are sticky, even if the buckets change or enrollment is
+        // paused.
+        experiments[0].bucket_config.count = 0;
+        experiments[0].is_enrollment_paused = true;
+        assert!(client.apply_experiments(&experiments).unwrap().is_empty());
+        assert_eq!(
+            client.get_experiment_branch("everyone").unwrap(),
+            Some(branch.clone())
+        );
+
+        // And they persist.
+        drop(client);
+        let client = new_client(&dir, Some("user"));
+        let active = client.get_active_experiments().unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].slug, "everyone");
+        assert_eq!(active[0].branch_slug, branch);
+    }
+
+    #[test]
+    fn test_unenrollment() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = new_client(&dir, Some("user"));
+        let mut experiments = vec![
+            experiment("removed", 0, 10_000),
+            experiment("branch-removed", 0, 10_000),
+        ];
+        experiments[1].branches = vec![Branch {
+            slug: "only".into(),
+            ratio: 1,
+        }];
+        assert_eq!(client.apply_experiments(&experiments).unwrap().len(), 2);
+
+        experiments.remove(0);
+        experiments[0].branches[0].slug = "renamed".into();
+        experiments[0].is_enrollment_paused = true;
+        let mut events = client.apply_experiments(&experiments).unwrap();
+        events.sort_by(|a, b| a.experiment_slug.cmp(&b.experiment_slug));
+        assert_eq!(
+            events,
+            vec![
+                EnrollmentChangeEvent {
+                    experiment_slug: "branch-removed".into(),
+                    branch_slug: "only".into(),
+                    change: EnrollmentChangeEventType::Unenrollment,
+                },
+                EnrollmentChangeEvent {
+                    experiment_slug: "removed".into(),
+                    branch_slug: events[1].branch_slug.clone(),
+                    change: EnrollmentChangeEventType::Unenrollment,
+                },
+            ]
+        );
+        assert!(client.get_active_experiments().unwrap().is_empty());
+    }
+}
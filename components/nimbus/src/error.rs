@@ -0,0 +1,28 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use failure::Fail;
+
+#[derive(Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "Invalid experiment {}: {}", _0, _1)]
+    InvalidExperiment(String, &'static str),
+
+    #[fail(display = "Error fetching experiments: {}", _0)]
+    RemoteSettingsError(#[fail(cause)] remote_settings::Error),
+
+    #[fail(display = "Error executing SQL: {}", _0)]
+    SqlError(#[fail(cause)] rusqlite::Error),
+
+    #[fail(display = "Crypto error: {}", _0)]
+    CryptoError(#[fail(cause)] rc_crypto::Error),
+}
+
+error_support::define_error! {
+    ErrorKind {
+        (RemoteSettingsError, remote_settings::Error),
+        (SqlError, rusqlite::Error),
+        (CryptoError, rc_crypto::Error),
+    }
+}
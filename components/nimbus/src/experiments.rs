@@ -0,0 +1,245 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Experiment definitions, as published to Remote Settings, and the
+//! deterministic bucketing that decides whether a client is enrolled in an
+//! experiment, and in which branch.
+
+use crate::error::*;
+use rc_crypto::digest;
+use remote_settings::RemoteSettingsRecord;
+use serde_derive::*;
+
+/// An experiment definition. Only the fields we need for enrollment are
+/// parsed; the rest of the record is ignored.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Experiment {
+    pub slug: String,
+    #[serde(default)]
+    pub user_facing_name: String,
+    /// If set, no new clients are enrolled, but existing enrollments are
+    /// kept.
+    #[serde(default)]
+    pub is_enrollment_paused: bool,
+    pub bucket_config: BucketConfig,
+    pub branches: Vec<Branch>,
+}
+
+/// Which of the `total` buckets in a namespace are enrolled in an
+/// experiment: the `count` buckets starting at `start`. Experiments in the same
+/// namespace with buckets that don't overlap never enroll the same client.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BucketConfig {
+    pub namespace: String,
+    pub start: u32,
+    pub count: u32,
+    pub total: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Branch {
+    pub slug: String,
+    /// The share of enrolled clients put in this branch, relative to the
+    /// ratios of the other branches.
+    pub ratio: u32,
+}
+
+impl Experiment {
+    pub(crate) fn validate(&self) -> Result<()> {
+        let config = &self.bucket_config;
+        if config.total == 0 {
+            return Err(ErrorKind::InvalidExperiment(self.slug.clone(), "no buckets").into());
+        }
+        if u64::from(config.start) + u64::from(config.count) > u64::from(config.total) {
+            return Err(
+                ErrorKind::InvalidExperiment(self.slug.clone(), "buckets out of range").into(),
+            );
+        }
+        if self.branches.iter().all(|b| b.ratio == 0) {
+            return Err(ErrorKind::InvalidExperiment(self.slug.clone(), "no branches").into());
+        }
+        Ok(())
+    }
+
+    /// Returns the branch `user_id` should be enrolled in, or `None` if it
+    /// isn't in the experiment's buckets.
+    pub(crate) fn choose_branch(&self, user_id: &str) -> Result<Option<&Branch>> {
+        self.validate()?;
+        let config = &self.bucket_config;
+        let bucket =
+            truncated_hash(&format!("{}-{}", config.namespace, user_id))? % u64::from(config.total);
+        if bucket < u64::from(config.start) || bucket >= u64::from(config.start + config.count) {
+            return Ok(None);
+        }
+        // Use a different hash for the branch, so that the branch doesn't
+        // depend on which bucket we're in.
+        let total_ratio: u64 = self.branches.iter().map(|b| u64::from(b.ratio)).sum();
+        let mut point = truncated_hash(&format!("{}-branch-{}", self.slug, user_id))? % total_ratio;
+        for branch in &self.branches {
+            let ratio = u64::from(branch.ratio);
+            if point < ratio {
+                return Ok(Some(branch));
+            }
+            point -= ratio;
+        }
+        unreachable!("point is less than the sum of the ratios")
+    }
+}
+
+/// Parses the experiments in a collection. Records which aren't experiments we
+/// understand are logged and skipped, so that one bad record doesn't stop us
+/// from enrolling in the others.
+pub(crate) fn parse_experiments(records: Vec<RemoteSettingsRecord>) -> Vec<Experiment> {
+    records
+        .into_iter()
+        .filter_map(|record| {
+            match serde_json::from_value::<Experiment>(serde_json::Value::Object(record.fields)) {
+                Ok(experiment) => Some(experiment),
+                Err(e) => {
+                    log::warn!("Skipping malformed experiment {}: {}", record.id, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The first 6 bytes of the SHA-256 hash of `input`, as a number. This is
+/// plenty to spread clients evenly across buckets, and fits in an `f64` for
+/// platforms that need to do the same calculation.
+fn truncated_hash(input: &str) -> Result<u64> {
+    let hash = digest::digest(&digest::SHA256, input.as_bytes())?;
+    Ok(hash.as_ref()[..6]
+        .iter()
+        .fold(0, |acc, &byte| (acc << 8) | u64::from(byte)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub fn experiment(slug: &str, start: u32, count: u32) -> Experiment {
+        Experiment {
+            slug: slug.into(),
+            user_facing_name: "".into(),
+            is_enrollment_paused: false,
+            bucket_config: BucketConfig {
+                namespace: slug.into(),
+                start,
+                count,
+                total: 10_000,
+            },
+            branches: vec![
+                Branch {
+                    slug: "control".into(),
+                    ratio: 1,
+                },
+                Branch {
+                    slug: "treatment".into(),
+                    ratio: 1,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_experiment() {
+        let experiment: Experiment = serde_json::from_str(
+            r#"{
+                "id": "secure-gold",
+                "slug": "secure-gold",
+                "userFacingName": "Secure Gold",
+                "isEnrollmentPaused": true,
+                "bucketConfig": {
+                    "randomizationUnit": "nimbus_id",
+                    "namespace": "secure-gold",
+                    "start": 0,
+                    "count": 2000,
+                    "total": 10000
+                },
+                "branches": [
+                    {"slug": "control", "ratio": 1},
+                    {"slug": "treatment", "ratio": 2}
+                ],
+                "probeSets": []
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(experiment.slug, "secure-gold");
+        assert!(experiment.is_enrollment_paused);
+        assert_eq!(experiment.bucket_config.count, 2000);
+        assert_eq!(experiment.branches[1].ratio, 2);
+    }
+
+    #[test]
+    fn test_parse_experiments() {
+        let records: Vec<RemoteSettingsRecord> = serde_json::from_str(
+            r#"[
+                {"id": "bad", "last_modified": 1, "slug": "bad"},
+                {
+                    "id": "good",
+                    "last_modified": 2,
+                    "slug": "good",
+                    "bucketConfig": {"namespace": "good", "start": 0, "count": 1, "total": 1},
+                    "branches": [{"slug": "control", "ratio": 1}]
+                }
+            ]"#,
+        )
+        .unwrap();
+        let experiments = parse_experiments(records);
+        assert_eq!(experiments.len(), 1);
+        assert_eq!(experiments[0].slug, "good");
+    }
+
+    #[test]
+    fn test_truncated_hash() {
+        rc_crypto::ensure_initialized();
+        // The first 6 bytes of sha256("") are e3 b0 c4 42 98 fc.
+        assert_eq!(truncated_hash("").unwrap(), 0xe3b0_c442_98fc);
+    }
+
+    #[test]
+    fn test_bucketing() {
+        rc_crypto::ensure_initialized();
+        let full = experiment("full", 0, 10_000);
+        let half = experiment("half", 0, 5_000);
+        let none = experiment("none", 0, 0);
+
+        let mut in_half = 0;
+        let mut in_treatment = 0;
+        for i in 0..1000 {
+            let user_id = format!("user-{}", i);
+            let branch = full.choose_branch(&user_id).unwrap().unwrap();
+            // The choice is deterministic.
+            assert_eq!(full.choose_branch(&user_id).unwrap().unwrap(), branch);
+            if branch.slug == "treatment" {
+                in_treatment += 1;
+            }
+            if half.choose_branch(&user_id).unwrap().is_some() {
+                in_half += 1;
+            }
+            assert!(none.choose_branch(&user_id).unwrap().is_none());
+        }
+        assert!(in_half > 400 && in_half < 600, "{} in half", in_half);
+        assert!(
+            in_treatment > 400 && in_treatment < 600,
+            "{} in treatment",
+            in_treatment
+        );
+    }
+
+    #[test]
+    fn test_invalid_experiments() {
+        rc_crypto::ensure_initialized();
+        let mut out_of_range = experiment("out-of-range", 9_000, 2_000);
+        assert!(out_of_range.choose_branch("user").is_err());
+        out_of_range.bucket_config.total = 0;
+        assert!(out_of_range.choose_branch("user").is_err());
+
+        let mut no_branches = experiment("no-branches", 0, 10_000);
+        no_branches.branches.clear();
+        assert!(no_branches.choose_branch("user").is_err());
+    }
+}
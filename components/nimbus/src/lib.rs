@@ -0,0 +1,23 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![allow(unknown_lints)]
+#![warn(rust_2018_idioms)]
+
+//! A client for Nimbus experiments. Experiment definitions are fetched from
+//! Remote Settings, using the `remote_settings` component, and each client is deterministically bucketed into them
+//! using a hash of its user ID. Enrollments are stored in SQLite, so a client
+//! stays in the same branch across restarts.
+
+mod client;
+pub mod error;
+mod experiments;
+mod schema;
+
+pub use crate::client::{
+    EnrolledExperiment, EnrollmentChangeEvent, EnrollmentChangeEventType, NimbusClient,
+};
+pub use crate::experiments::{Branch, BucketConfig, Experiment};
+pub use error::{Error, ErrorKind, Result};
+pub use remote_settings::RemoteSettingsConfig;
@@ -0,0 +1,36 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::Result;
+use rusqlite::{Connection, NO_PARAMS};
+use sql_support::ConnExt;
+
+const VERSION: i64 = 1;
+
+const CREATE_SCHEMA_SQL: &str = include_str!("../sql/create_schema.sql");
+
+pub fn init(db: &Connection) -> Result<()> {
+    let user_version = db.query_one::<i64>("PRAGMA user_version")?;
+    if user_version > VERSION {
+        // Enrollments are recomputed from the experiment definitions on the
+        // next update, so it's fine to throw away what a newer version stored.
+        // The generated user ID is kept, so we'll end up in the same branches.
+        log::warn!(
+            "Loaded future schema version {} (we only understand version {}). \
+             Recreating the schema",
+            user_version,
+            VERSION
+        );
+        db.execute_batch("DROP TABLE IF EXISTS enrollments")?;
+    }
+    if user_version != VERSION {
+        log::debug!("Creating schema");
+        db.execute_batch(CREATE_SCHEMA_SQL)?;
+        db.execute(
+            &format!("PRAGMA user_version = {version}", version = VERSION),
+            NO_PARAMS,
+        )?;
+    }
+    Ok(())
+}
@@ -1119,7 +1119,8 @@ enum class SearchResultReason {
     URL,
     PREVIOUS_USE,
     BOOKMARK,
-    TAG;
+    TAG,
+    TITLE;
 
     companion object {
         fun fromMessage(reason: MsgTypes.SearchResultReason): SearchResultReason {
@@ -1130,6 +1131,7 @@ enum class SearchResultReason {
                 MsgTypes.SearchResultReason.PREVIOUS_USE -> PREVIOUS_USE
                 MsgTypes.SearchResultReason.BOOKMARK -> BOOKMARK
                 MsgTypes.SearchResultReason.TAG -> TAG
+                MsgTypes.SearchResultReason.TITLE -> TITLE
             }
         }
     }
@@ -17,12 +17,14 @@ use places::msg_types::{BookmarkNodeList, SearchResultList};
 use places::storage::bookmarks;
 use places::types::VisitTransitionSet;
 use places::{storage, ConnectionType, PlacesApi, PlacesDb};
-use sql_support::SqlInterruptHandle;
+use sql_support::{retry_if_busy, SqlInterruptHandle};
 use std::os::raw::c_char;
 use std::sync::Arc;
 use sync_guid::Guid as SyncGuid;
 
-use places::api::matcher::{self, match_url, search_frecent, SearchParams};
+use places::api::matcher::{
+    self, match_url, search_frecent, search_frecent_weighted, MatchWeights, SearchParams,
+};
 
 // indirection to help `?` figure out the target error type
 fn parse_url(url: &str) -> places::Result<url::Url> {
@@ -131,6 +133,27 @@ pub extern "C" fn places_history_import_from_fennec(
     })
 }
 
+/// Like `places_history_import_from_fennec`, but only imports the `max_visits`
+/// most recent visits.
+#[no_mangle]
+pub extern "C" fn places_history_import_from_fennec_with_visit_limit(
+    api_handle: u64,
+    db_path: FfiStr<'_>,
+    max_visits: u32,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_history_import_from_fennec_with_visit_limit");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<String> {
+        let import_metrics = places::import::import_fennec_history_with_visit_limit(
+            api,
+            db_path.as_str(),
+            max_visits,
+        )?;
+        let result = serde_json::to_string(&import_metrics)?;
+        Ok(result)
+    })
+}
+
 // Best effort, ignores failure.
 #[no_mangle]
 pub extern "C" fn places_api_return_write_conn(
@@ -184,6 +207,215 @@ pub extern "C" fn places_note_observation(
     })
 }
 
+/// Add a batch of observations to the database, in a single transaction. The
+/// observations are a JSON array of VisitObservations. If any can't be applied,
+/// none are.
+#[no_mangle]
+pub extern "C" fn places_note_observations(
+    handle: u64,
+    json_observations: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_note_observations");
+    CONNECTIONS.call_with_result_mut(error, handle, |conn| {
+        let json = json_observations.as_str();
+        let visits: Vec<places::VisitObservation> = serde_json::from_str(&json)?;
+        places::api::apply_observations(conn, visits)
+    })
+}
+
+/// Records a `HistoryMetadataObservation`, passed as JSON.
+#[no_mangle]
+pub extern "C" fn places_note_history_metadata(
+    handle: u64,
+    json_observation: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_note_history_metadata");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let observation: storage::history_metadata::HistoryMetadataObservation =
+            serde_json::from_str(json_observation.as_str())?;
+        storage::history_metadata::note_history_metadata_observation(conn, &observation)
+    })
+}
+
+/// Returns the latest `HistoryMetadata` for the URL as JSON, or "null" if it
+/// has none.
+#[no_mangle]
+pub extern "C" fn places_get_latest_history_metadata_for_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_get_latest_history_metadata_for_url");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        let metadata = retry_if_busy(|| {
+            storage::history_metadata::get_latest_history_metadata_for_url(conn, &url)
+        })?;
+        Ok(serde_json::to_string(&metadata)?)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_history_metadata_between(
+    handle: u64,
+    start: i64,
+    end: i64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_get_history_metadata_between");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let metadata = retry_if_busy(|| {
+            storage::history_metadata::get_history_metadata_between(
+                conn,
+                places::Timestamp(start.max(0) as u64),
+                places::Timestamp(end.max(0) as u64),
+            )
+        })?;
+        Ok(serde_json::to_string(&metadata)?)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_query_history_metadata(
+    handle: u64,
+    query: FfiStr<'_>,
+    limit: i32,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_query_history_metadata");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let metadata = retry_if_busy(|| {
+            storage::history_metadata::query_history_metadata(
+                conn,
+                query.as_str(),
+                limit.max(0) as u32,
+            )
+        })?;
+        Ok(serde_json::to_string(&metadata)?)
+    })
+}
+
+/// Sets the favicon and preview image URLs for a page, from a `PageImageInfo`
+/// passed as JSON.
+#[no_mangle]
+pub extern "C" fn places_set_page_image_info(
+    handle: u64,
+    url: FfiStr<'_>,
+    json_info: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_set_page_image_info");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        let info: storage::PageImageInfo = serde_json::from_str(json_info.as_str())?;
+        storage::set_page_image_info(conn, &url, &info)
+    })
+}
+
+/// Returns the `PageImageInfo` for a page as JSON, or "null" if the page
+/// isn't known.
+#[no_mangle]
+pub extern "C" fn places_get_page_image_info(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_get_page_image_info");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        let info = retry_if_busy(|| storage::get_page_image_info(conn, &url))?;
+        Ok(serde_json::to_string(&info)?)
+    })
+}
+
+/// Pins a site to the top sites. `title` may be null, and a negative
+/// `position` pins it after the last pinned site.
+#[no_mangle]
+pub extern "C" fn places_pin_site(
+    handle: u64,
+    url: FfiStr<'_>,
+    title: FfiStr<'_>,
+    position: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("places_pin_site");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        let position = if position < 0 {
+            None
+        } else {
+            Some(position as u32)
+        };
+        storage::top_sites::pin_site(conn, &url, title.as_opt_str(), position)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_unpin_site(handle: u64, url: FfiStr<'_>, error: &mut ExternError) -> u8 {
+    log::debug!("places_unpin_site");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        storage::top_sites::unpin_site(conn, &url)
+    })
+}
+
+/// Returns the pinned sites as a JSON array of `PinnedSite`s.
+#[no_mangle]
+pub extern "C" fn places_get_pinned_sites(handle: u64, error: &mut ExternError) -> *mut c_char {
+    log::debug!("places_get_pinned_sites");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let sites = retry_if_busy(|| storage::top_sites::get_pinned_sites(conn))?;
+        Ok(serde_json::to_string(&sites)?)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_block_top_site_domain(
+    handle: u64,
+    domain: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_block_top_site_domain");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        storage::top_sites::block_top_site_domain(conn, domain.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_unblock_top_site_domain(
+    handle: u64,
+    domain: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("places_unblock_top_site_domain");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        storage::top_sites::unblock_top_site_domain(conn, domain.as_str())
+    })
+}
+
+/// Returns the top sites as a JSON array of `TopFrecentSiteInfo`s.
+#[no_mangle]
+pub extern "C" fn places_get_top_frecent_site_infos(
+    handle: u64,
+    limit: i32,
+    frecency_threshold: i64,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_get_top_frecent_site_infos");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let sites = retry_if_busy(|| {
+            storage::top_sites::get_top_frecent_site_infos(
+                conn,
+                limit.max(0) as u32,
+                frecency_threshold,
+            )
+        })?;
+        Ok(serde_json::to_string(&sites)?)
+    })
+}
+
 /// Execute a query, returning a `Vec<SearchResult>` as a JSON string. Returned string must be freed
 /// using `places_destroy_string`. Returns null and logs on errors (for now).
 #[no_mangle]
@@ -195,13 +427,51 @@ pub extern "C" fn places_query_autocomplete(
 ) -> ByteBuffer {
     log::debug!("places_query_autocomplete");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        let results = search_frecent(
-            conn,
-            SearchParams {
-                search_string: search.into_string(),
-                limit,
-            },
-        )?
+        let search_string = search.into_string();
+        let results = retry_if_busy(|| {
+            search_frecent(
+                conn,
+                SearchParams {
+                    search_string: search_string.clone(),
+                    limit,
+                },
+            )
+        })?
+        .into_iter()
+        .map(|r| r.into())
+        .collect();
+        Ok(SearchResultList { results })
+    })
+}
+
+/// Like `places_query_autocomplete`, but ranks the results using `weights`,
+/// a JSON-serialized `MatchWeights`. Any weights which aren't given, or all
+/// of them if `weights` is null, take their default values.
+#[no_mangle]
+pub extern "C" fn places_query_autocomplete_weighted(
+    handle: u64,
+    search: FfiStr<'_>,
+    limit: u32,
+    weights: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_query_autocomplete_weighted");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let weights: MatchWeights = match weights.as_opt_str() {
+            Some(json) => serde_json::from_str(json)?,
+            None => MatchWeights::default(),
+        };
+        let search_string = search.into_string();
+        let results = retry_if_busy(|| {
+            search_frecent_weighted(
+                conn,
+                SearchParams {
+                    search_string: search_string.clone(),
+                    limit,
+                },
+                &weights,
+            )
+        })?
         .into_iter()
         .map(|r| r.into())
         .collect();
@@ -218,7 +488,9 @@ pub extern "C" fn places_match_url(
     error: &mut ExternError,
 ) -> *mut c_char {
     log::debug!("places_match_url");
-    CONNECTIONS.call_with_result(error, handle, |conn| match_url(conn, search.as_str()))
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        retry_if_busy(|| match_url(conn, search.as_str()))
+    })
 }
 
 /// # Safety
@@ -253,7 +525,7 @@ pub unsafe extern "C" fn places_get_visited(
                 url::Url::parse(s).ok().map(|url| (idx, url))
             })
             .collect::<Vec<_>>();
-        storage::history::get_visited_into(conn, &urls, output)?;
+        retry_if_busy(|| storage::history::get_visited_into(conn, &urls, output))?;
         Ok(())
     })
 }
@@ -268,17 +540,32 @@ pub extern "C" fn places_get_visited_urls_in_range(
 ) -> *mut c_char {
     log::debug!("places_get_visited_in_range");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        let visited = storage::history::get_visited_urls(
-            conn,
-            // Probably should allow into()...
-            places::Timestamp(start.max(0) as u64),
-            places::Timestamp(end.max(0) as u64),
-            include_remote != 0,
-        )?;
+        let visited = retry_if_busy(|| {
+            storage::history::get_visited_urls(
+                conn,
+                // Probably should allow into()...
+                places::Timestamp(start.max(0) as u64),
+                places::Timestamp(end.max(0) as u64),
+                include_remote != 0,
+            )
+        })?;
         Ok(serde_json::to_string(&visited)?)
     })
 }
 
+/// Deletes the history of every page on the domain, or its subdomains.
+#[no_mangle]
+pub extern "C" fn places_delete_visits_for_base_domain(
+    handle: u64,
+    domain: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_delete_visits_for_base_domain");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        storage::history::delete_visits_for_base_domain(conn, domain.as_str())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_delete_visits_for(handle: u64, url: FfiStr<'_>, error: &mut ExternError) {
     log::debug!("places_delete_visits_for");
@@ -358,6 +645,21 @@ pub extern "C" fn places_run_maintenance(handle: u64, error: &mut ExternError) {
     CONNECTIONS.call_with_result(error, handle, |conn| storage::run_maintenance(conn))
 }
 
+/// Recalculates stale frecencies for up to `budget_ms`, returning a
+/// `FrecencyMaintenanceResult` as JSON.
+#[no_mangle]
+pub extern "C" fn places_run_frecency_maintenance(
+    handle: u64,
+    budget_ms: u32,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_run_frecency_maintenance");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let result = storage::run_frecency_maintenance(conn, budget_ms)?;
+        Ok(serde_json::to_string(&result)?)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_prune_destructively(handle: u64, error: &mut ExternError) {
     log::debug!("places_prune_destructively");
@@ -384,13 +686,16 @@ pub extern "C" fn places_get_visit_infos(
 ) -> ByteBuffer {
     log::debug!("places_get_visit_infos");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        Ok(storage::history::get_visit_infos(
-            conn,
-            places::Timestamp(start_date.max(0) as u64),
-            places::Timestamp(end_date.max(0) as u64),
-            VisitTransitionSet::from_u16(exclude_types as u16)
-                .expect("Bug: Invalid VisitTransitionSet"),
-        )?)
+        let exclude_types = VisitTransitionSet::from_u16(exclude_types as u16)
+            .expect("Bug: Invalid VisitTransitionSet");
+        retry_if_busy(|| {
+            storage::history::get_visit_infos(
+                conn,
+                places::Timestamp(start_date.max(0) as u64),
+                places::Timestamp(end_date.max(0) as u64),
+                exclude_types,
+            )
+        })
     })
 }
 
@@ -402,13 +707,11 @@ pub extern "C" fn places_get_visit_count(
 ) -> i64 {
     log::debug!("places_get_visit_count");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        storage::history::get_visit_count(
-            conn,
-            // Note: it's a bug in our FFI android (or swift, eventually) code
-            // if this expect fires.
-            VisitTransitionSet::from_u16(exclude_types as u16)
-                .expect("Bug: Invalid VisitTransitionSet"),
-        )
+        // Note: it's a bug in our FFI android (or swift, eventually) code
+        // if this expect fires.
+        let exclude_types = VisitTransitionSet::from_u16(exclude_types as u16)
+            .expect("Bug: Invalid VisitTransitionSet");
+        retry_if_busy(|| storage::history::get_visit_count(conn, exclude_types))
     })
 }
 
@@ -422,15 +725,11 @@ pub extern "C" fn places_get_visit_page(
 ) -> ByteBuffer {
     log::debug!("places_get_visit_page");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        storage::history::get_visit_page(
-            conn,
-            offset,
-            count,
-            // Note: it's a bug in our FFI android (or swift, eventually) code
-            // if this expect fires.
-            VisitTransitionSet::from_u16(exclude_types as u16)
-                .expect("Bug: Invalid VisitTransitionSet"),
-        )
+        // Note: it's a bug in our FFI android (or swift, eventually) code
+        // if this expect fires.
+        let exclude_types = VisitTransitionSet::from_u16(exclude_types as u16)
+            .expect("Bug: Invalid VisitTransitionSet");
+        retry_if_busy(|| storage::history::get_visit_page(conn, offset, count, exclude_types))
     })
 }
 
@@ -445,14 +744,11 @@ pub extern "C" fn places_get_visit_page_with_bound(
 ) -> ByteBuffer {
     log::debug!("places_get_visit_page");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        storage::history::get_visit_page_with_bound(
-            conn,
-            bound,
-            offset,
-            count,
-            VisitTransitionSet::from_u16(exclude_types as u16)
-                .expect("Bug: Invalid VisitTransitionSet"),
-        )
+        let exclude_types = VisitTransitionSet::from_u16(exclude_types as u16)
+            .expect("Bug: Invalid VisitTransitionSet");
+        retry_if_busy(|| {
+            storage::history::get_visit_page_with_bound(conn, bound, offset, count, exclude_types)
+        })
     })
 }
 
@@ -550,7 +846,7 @@ pub extern "C" fn bookmarks_get_tree(
     log::debug!("bookmarks_get_tree");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
         let root_id = SyncGuid::from(guid.as_str());
-        Ok(bookmarks::public_node::fetch_public_tree(conn, &root_id)?)
+        retry_if_busy(|| bookmarks::public_node::fetch_public_tree(conn, &root_id))
     })
 }
 
@@ -573,11 +869,9 @@ pub extern "C" fn bookmarks_get_by_guid(
     log::debug!("bookmarks_get_by_guid");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
         let guid = SyncGuid::from(guid.as_str());
-        Ok(bookmarks::public_node::fetch_bookmark(
-            conn,
-            &guid,
-            get_direct_children != 0,
-        )?)
+        retry_if_busy(|| {
+            bookmarks::public_node::fetch_bookmark(conn, &guid, get_direct_children != 0)
+        })
     })
 }
 
@@ -611,6 +905,51 @@ pub unsafe extern "C" fn bookmarks_insert(
     })
 }
 
+/// Inserts a bookmark, and if it's a folder, all of its `child_nodes`, in a
+/// single transaction. Returns the GUID of the inserted root.
+///
+/// # Safety
+/// Deref pointer, thus unsafe
+#[no_mangle]
+pub unsafe extern "C" fn bookmarks_insert_tree(
+    handle: u64,
+    data: *const u8,
+    len: i32,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("bookmarks_insert_tree");
+    use places::msg_types::BookmarkNode;
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let buffer = get_buffer(data, len);
+        let bookmark: BookmarkNode = prost::Message::decode(buffer)?;
+        let (parent_guid, position, root) = bookmark.into_insertable_tree()?;
+        let guid = bookmarks::insert_bookmark_tree(conn, &parent_guid, position, &root)?;
+        Ok(guid.into_string())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bookmarks_import_html(
+    handle: u64,
+    html: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("bookmarks_import_html");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let import_metrics = places::import::import_bookmarks_html(conn, html.as_str())?;
+        let result = serde_json::to_string(&import_metrics)?;
+        Ok(result)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bookmarks_export_html(handle: u64, error: &mut ExternError) -> *mut c_char {
+    log::debug!("bookmarks_export_html");
+    CONNECTIONS.call_with_result(error, handle, |conn| {
+        places::import::export_bookmarks_html(conn)
+    })
+}
+
 /// # Safety
 /// Deref pointer, thus unsafe
 #[no_mangle]
@@ -649,9 +988,9 @@ pub extern "C" fn bookmarks_get_all_with_url(
     log::debug!("bookmarks_get_all_with_url");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
         Ok(match parse_url(url.as_str()) {
-            Ok(url) => {
-                BookmarkNodeList::from(bookmarks::public_node::fetch_bookmarks_by_url(conn, &url)?)
-            }
+            Ok(url) => BookmarkNodeList::from(retry_if_busy(|| {
+                bookmarks::public_node::fetch_bookmarks_by_url(conn, &url)
+            })?),
             Err(e) => {
                 // There are no bookmarks with the URL if it's invalid.
                 log::warn!("Invalid URL passed to bookmarks_get_all_with_url, {}", e);
@@ -671,11 +1010,67 @@ pub unsafe extern "C" fn bookmarks_get_url_for_keyword(
 ) -> *mut c_char {
     log::debug!("bookmarks_get_url_for_keyword");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        let url = bookmarks::bookmarks_get_url_for_keyword(conn, keyword.as_str())?;
+        let url =
+            retry_if_busy(|| bookmarks::bookmarks_get_url_for_keyword(conn, keyword.as_str()))?;
         Ok(url.map(url::Url::into_string))
     })
 }
 
+#[no_mangle]
+pub extern "C" fn places_tag_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    tag: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_tag_url");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        storage::tags::tag_url(conn, &url, tag.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_untag_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    tag: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_untag_url");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        storage::tags::untag_url(conn, &url, tag.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_urls_with_tag(
+    handle: u64,
+    tag: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_get_urls_with_tag");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let urls = retry_if_busy(|| storage::tags::get_urls_with_tag(conn, tag.as_str()))?;
+        Ok(serde_json::to_string(&urls)?)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_tags_for_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> *mut c_char {
+    log::debug!("places_get_tags_for_url");
+    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        let tags = retry_if_busy(|| storage::tags::get_tags_for_url(conn, &url))?;
+        Ok(serde_json::to_string(&tags)?)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_search(
     handle: u64,
@@ -685,9 +1080,9 @@ pub extern "C" fn bookmarks_search(
 ) -> ByteBuffer {
     log::debug!("bookmarks_search");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        Ok(BookmarkNodeList::from(
-            bookmarks::public_node::search_bookmarks(conn, query.as_str(), limit as u32)?,
-        ))
+        Ok(BookmarkNodeList::from(retry_if_busy(|| {
+            bookmarks::public_node::search_bookmarks(conn, query.as_str(), limit as u32)
+        })?))
     })
 }
 
@@ -699,9 +1094,9 @@ pub extern "C" fn bookmarks_get_recent(
 ) -> ByteBuffer {
     log::debug!("bookmarks_get_recent");
     CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        Ok(BookmarkNodeList::from(
-            bookmarks::public_node::recent_bookmarks(conn, limit as u32)?,
-        ))
+        Ok(BookmarkNodeList::from(retry_if_busy(|| {
+            bookmarks::public_node::recent_bookmarks(conn, limit as u32)
+        })?))
     })
 }
 
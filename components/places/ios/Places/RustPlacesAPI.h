@@ -60,11 +60,74 @@ void places_note_observation(PlacesConnectionHandle handle,
                              const char *_Nonnull observation_json,
                              PlacesRustError *_Nonnull out_err);
 
+void places_note_observations(PlacesConnectionHandle handle,
+                              const char *_Nonnull observations_json,
+                              PlacesRustError *_Nonnull out_err);
+
+void places_note_history_metadata(PlacesConnectionHandle handle,
+                                  const char *_Nonnull observation_json,
+                                  PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_get_latest_history_metadata_for_url(PlacesConnectionHandle handle,
+                                                           const char *_Nonnull url,
+                                                           PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_get_history_metadata_between(PlacesConnectionHandle handle,
+                                                    int64_t start,
+                                                    int64_t end,
+                                                    PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_query_history_metadata(PlacesConnectionHandle handle,
+                                              const char *_Nonnull query,
+                                              int32_t limit,
+                                              PlacesRustError *_Nonnull out_err);
+
+void places_set_page_image_info(PlacesConnectionHandle handle,
+                                const char *_Nonnull url,
+                                const char *_Nonnull info_json,
+                                PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_get_page_image_info(PlacesConnectionHandle handle,
+                                           const char *_Nonnull url,
+                                           PlacesRustError *_Nonnull out_err);
+
+void places_pin_site(PlacesConnectionHandle handle,
+                     const char *_Nonnull url,
+                     const char *_Nullable title,
+                     int32_t position,
+                     PlacesRustError *_Nonnull out_err);
+
+uint8_t places_unpin_site(PlacesConnectionHandle handle,
+                          const char *_Nonnull url,
+                          PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_get_pinned_sites(PlacesConnectionHandle handle,
+                                        PlacesRustError *_Nonnull out_err);
+
+void places_block_top_site_domain(PlacesConnectionHandle handle,
+                                  const char *_Nonnull domain,
+                                  PlacesRustError *_Nonnull out_err);
+
+uint8_t places_unblock_top_site_domain(PlacesConnectionHandle handle,
+                                       const char *_Nonnull domain,
+                                       PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_get_top_frecent_site_infos(PlacesConnectionHandle handle,
+                                                  int32_t limit,
+                                                  int64_t frecency_threshold,
+                                                  PlacesRustError *_Nonnull out_err);
+
 char *_Nullable places_query_autocomplete(PlacesConnectionHandle handle,
                                           const char *_Nonnull search,
                                           int32_t limit,
                                           PlacesRustError *_Nonnull out_err);
 
+char *_Nullable places_query_autocomplete_weighted(PlacesConnectionHandle handle,
+                                                   const char *_Nonnull search,
+                                                   int32_t limit,
+                                                   const char *_Nullable weights,
+                                                   PlacesRustError *_Nonnull out_err);
+
 char *_Nullable places_match_url(PlacesConnectionHandle handle,
                                  const char *_Nonnull search,
                                  PlacesRustError *_Nonnull out_err);
@@ -97,6 +160,10 @@ void places_delete_visits_for(PlacesConnectionHandle handle,
                               const char *_Nonnull place_url,
                               PlacesRustError *_Nonnull out_err);
 
+void places_delete_visits_for_base_domain(PlacesConnectionHandle handle,
+                                          const char *_Nonnull domain,
+                                          PlacesRustError *_Nonnull out_err);
+
 void places_delete_visit(PlacesConnectionHandle handle,
                          const char *_Nonnull place_url,
                          int64_t visit_timestamp,
@@ -113,6 +180,10 @@ void places_wipe_local(PlacesConnectionHandle handle,
 void places_run_maintenance(PlacesConnectionHandle handle,
                             PlacesRustError *_Nonnull out_err);
 
+char *_Nullable places_run_frecency_maintenance(PlacesConnectionHandle handle,
+                                                uint32_t budget_ms,
+                                                PlacesRustError *_Nonnull out_err);
+
 void places_prune_destructively(PlacesConnectionHandle handle,
                                 PlacesRustError *_Nonnull out_err);
 
@@ -161,6 +232,24 @@ char *_Nullable bookmarks_get_url_for_keyword(PlacesConnectionHandle handle,
                                               char const *_Nonnull keyword,
                                               PlacesRustError *_Nonnull out_err);
 
+void places_tag_url(PlacesConnectionHandle handle,
+                    char const *_Nonnull url,
+                    char const *_Nonnull tag,
+                    PlacesRustError *_Nonnull out_err);
+
+void places_untag_url(PlacesConnectionHandle handle,
+                      char const *_Nonnull url,
+                      char const *_Nonnull tag,
+                      PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_get_urls_with_tag(PlacesConnectionHandle handle,
+                                         char const *_Nonnull tag,
+                                         PlacesRustError *_Nonnull out_err);
+
+char *_Nullable places_get_tags_for_url(PlacesConnectionHandle handle,
+                                        char const *_Nonnull url,
+                                        PlacesRustError *_Nonnull out_err);
+
 PlacesRustBuffer bookmarks_search(PlacesConnectionHandle handle,
                                   char const *_Nonnull query,
                                   int32_t limit,
@@ -179,6 +268,18 @@ char *_Nullable bookmarks_insert(PlacesConnectionHandle handle,
                                  int32_t len,
                                  PlacesRustError *_Nonnull out_err);
 
+char *_Nullable bookmarks_insert_tree(PlacesConnectionHandle handle,
+                                      uint8_t const *_Nonnull data,
+                                      int32_t len,
+                                      PlacesRustError *_Nonnull out_err);
+
+char *_Nullable bookmarks_import_html(PlacesConnectionHandle handle,
+                                      const char *_Nonnull html,
+                                      PlacesRustError *_Nonnull out_err);
+
+char *_Nullable bookmarks_export_html(PlacesConnectionHandle handle,
+                                      PlacesRustError *_Nonnull out_err);
+
 void bookmarks_update(PlacesConnectionHandle handle,
                       uint8_t const *_Nonnull data,
                       int32_t len,
@@ -24,6 +24,7 @@ CREATE TABLE IF NOT EXISTS moz_places (
     url_hash INTEGER DEFAULT 0 NOT NULL,
     description TEXT, -- XXXX - title above?
     preview_image_url TEXT,
+    favicon_url TEXT,
     -- origin_id would ideally be NOT NULL, but we use a trigger to keep
     -- it up to date, so do perform the initial insert with a null.
     origin_id INTEGER,
@@ -167,6 +168,43 @@ CREATE TABLE IF NOT EXISTS moz_tags_relation(
     PRIMARY KEY(tag_id, place_id)
 ) WITHOUT ROWID;
 
+-- Metadata about visits to a page, which isn't synced: how long the page
+-- was looked at, and the search term and referring origin which led to it.
+-- Observations with the same search term and referrer are added to the same
+-- row for a while, rather than creating a row for each visit.
+CREATE TABLE IF NOT EXISTS moz_places_metadata(
+    id INTEGER PRIMARY KEY,
+    place_id INTEGER NOT NULL REFERENCES moz_places(id) ON DELETE CASCADE,
+    created_at INTEGER NOT NULL, -- In milliseconds.
+    updated_at INTEGER NOT NULL, -- In milliseconds.
+    total_view_time INTEGER NOT NULL DEFAULT 0, -- In milliseconds.
+    search_term TEXT,
+    referrer_origin TEXT
+);
+
+CREATE INDEX IF NOT EXISTS moz_places_metadata_place_id
+ON moz_places_metadata(place_id);
+
+CREATE INDEX IF NOT EXISTS moz_places_metadata_updated_at
+ON moz_places_metadata(updated_at);
+
+-- Sites the user has pinned to their top sites. These aren't necessarily in
+-- history, so they're keyed by URL rather than place ID. Positions can have
+-- gaps, which are filled by frecent sites.
+CREATE TABLE IF NOT EXISTS moz_pinned_sites(
+    id INTEGER PRIMARY KEY,
+    url LONGVARCHAR NOT NULL UNIQUE,
+    title TEXT,
+    position INTEGER NOT NULL,
+    created_at INTEGER NOT NULL -- In milliseconds.
+);
+
+-- Domains the user has removed from their top sites. Subdomains are blocked
+-- too.
+CREATE TABLE IF NOT EXISTS moz_blocked_top_site_domains(
+    domain TEXT PRIMARY KEY
+) WITHOUT ROWID;
+
 -- This table holds synced items, including tombstones. It's unused if Sync
 -- isn't configured. At the end of a sync, this table's contents should match
 -- both what's on the server, and the local tree in `moz_bookmarks`.
@@ -51,6 +51,33 @@ pub struct SearchParams {
 /// A provider can be anything that returns URL suggestions: Places history
 /// and bookmarks, synced tabs, search engine suggestions, and search keywords.
 pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<SearchResult>> {
+    let mut matches = search_all_providers(conn, &params)?;
+    matches.sort_unstable_by(|a, b| a.url.cmp(&b.url));
+    Ok(matches)
+}
+
+/// Like `search_frecent`, but ranks the matches according to why they
+/// matched, as well as their frecency, using `weights`. The best match comes
+/// first.
+pub fn search_frecent_weighted(
+    conn: &PlacesDb,
+    params: SearchParams,
+    weights: &MatchWeights,
+) -> Result<Vec<SearchResult>> {
+    let mut matches = search_all_providers(conn, &params)?;
+    matches.sort_by(|a, b| {
+        weights
+            .rank(b)
+            .partial_cmp(&weights.rank(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| b.frecency.cmp(&a.frecency))
+            .then_with(|| a.url.cmp(&b.url))
+    });
+    Ok(matches)
+}
+
+// Returns the matches from each provider, with each URL only appearing once.
+fn search_all_providers(conn: &PlacesDb, params: &SearchParams) -> Result<Vec<SearchResult>> {
     // TODO: Tokenize the query.
 
     // Try to find the first heuristic result. Desktop tries extensions,
@@ -59,7 +86,7 @@ pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<Searc
     // and a search if all else fails. We only try origins and URLs for
     // heuristic matches, since that's all we support.
 
-    let mut matches = match_with_limit(
+    let matches = match_with_limit(
         conn,
         &[
             // Try to match on the origin, or the full URL.
@@ -79,10 +106,27 @@ pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<Searc
         params.limit,
     )?;
 
-    matches.sort_unstable_by(|a, b| a.url.cmp(&b.url));
-    matches.dedup_by(|a, b| a.url == b.url);
+    Ok(merge_duplicates(matches))
+}
 
-    Ok(matches)
+// A URL can be matched by more than one provider, for different reasons, so
+// combine the reasons for each URL into its first match.
+fn merge_duplicates(matches: Vec<SearchResult>) -> Vec<SearchResult> {
+    let mut merged: Vec<SearchResult> = Vec::with_capacity(matches.len());
+    for m in matches {
+        match merged.iter_mut().find(|existing| existing.url == m.url) {
+            Some(existing) => {
+                existing.frecency = existing.frecency.max(m.frecency);
+                for reason in m.reasons {
+                    if !existing.reasons.contains(&reason) {
+                        existing.reasons.push(reason);
+                    }
+                }
+            }
+            None => merged.push(m),
+        }
+    }
+    merged
 }
 
 pub fn match_url(conn: &PlacesDb, query: impl AsRef<str>) -> Result<Option<String>> {
@@ -184,6 +228,7 @@ pub enum MatchReason {
     Bookmark,
     // Hrm... This will probably make this all serialize weird...
     Tags(String),
+    Title,
 }
 
 #[derive(Debug, Clone, Serialize, Eq, PartialEq)]
@@ -210,29 +255,97 @@ pub struct SearchResult {
     pub reasons: Vec<MatchReason>,
 }
 
+/// How much each reason for a match counts towards its rank, for
+/// `search_frecent_weighted`. A result's rank is its frecency, multiplied by
+/// one plus the weights of all the reasons it matched for, so setting every
+/// weight to zero ranks by frecency alone.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchWeights {
+    pub keyword: f64,
+    pub origin: f64,
+    pub url: f64,
+    pub previous_use: f64,
+    pub bookmark: f64,
+    pub tags: f64,
+    pub title: f64,
+}
+
+impl Default for MatchWeights {
+    fn default() -> Self {
+        MatchWeights {
+            keyword: 1.0,
+            origin: 1.0,
+            url: 1.0,
+            previous_use: 1.0,
+            bookmark: 0.5,
+            tags: 0.25,
+            title: 0.25,
+        }
+    }
+}
+
+impl MatchWeights {
+    pub fn weight(&self, reason: &MatchReason) -> f64 {
+        match reason {
+            MatchReason::Keyword => self.keyword,
+            MatchReason::Origin => self.origin,
+            MatchReason::Url => self.url,
+            MatchReason::PreviousUse => self.previous_use,
+            MatchReason::Bookmark => self.bookmark,
+            MatchReason::Tags(_) => self.tags,
+            MatchReason::Title => self.title,
+        }
+    }
+
+    pub fn rank(&self, result: &SearchResult) -> f64 {
+        let boost = result.reasons.iter().map(|r| self.weight(r)).sum::<f64>();
+        result.frecency.max(0) as f64 * (1.0 + boost)
+    }
+}
+
+// The reasons for a match found by `AUTOCOMPLETE_MATCH`, which looks for the
+// search string in the URL, title and tags of a page.
+fn field_reasons(row: &rusqlite::Row<'_>, url: &str, title: &str) -> Result<Vec<MatchReason>> {
+    let search_string = row.get::<_, String>("searchString")?;
+    let match_behavior = row.get::<_, MatchBehavior>("matchBehavior")?;
+    let tags = row.get::<_, Option<String>>("tags")?;
+    let bookmarked = row.get::<_, bool>("bookmarked")?;
+
+    let mut reasons = Vec::new();
+    let (_, stripped_url) = split_after_prefix(url);
+    if match_behavior.matches_any_token(&search_string, stripped_url) {
+        reasons.push(MatchReason::Url);
+    }
+    if match_behavior.matches_any_token(&search_string, title) {
+        reasons.push(MatchReason::Title);
+    }
+    if let Some(tags) = tags {
+        if match_behavior.matches_any_token(&search_string, &tags) {
+            reasons.push(MatchReason::Tags(tags));
+        }
+    }
+    if bookmarked {
+        reasons.push(MatchReason::Bookmark);
+    }
+    Ok(reasons)
+}
+
 impl SearchResult {
     /// Default search behaviors from Desktop: HISTORY, BOOKMARK, OPENPAGE, SEARCHES.
     /// Default match behavior: MATCH_BOUNDARY_ANYWHERE.
     pub fn from_adaptive_row(row: &rusqlite::Row<'_>) -> Result<Self> {
-        let mut reasons = vec![MatchReason::PreviousUse];
-
         let search_string = row.get::<_, String>("searchString")?;
         let _place_id = row.get::<_, i64>("id")?;
         let url = row.get::<_, String>("url")?;
         let history_title = row.get::<_, Option<String>>("title")?;
-        let bookmarked = row.get::<_, bool>("bookmarked")?;
         let bookmark_title = row.get::<_, Option<String>>("btitle")?;
         let frecency = row.get::<_, i64>("frecency")?;
 
         let title = bookmark_title.or_else(|| history_title).unwrap_or_default();
 
-        let tags = row.get::<_, Option<String>>("tags")?;
-        if let Some(tags) = tags {
-            reasons.push(MatchReason::Tags(tags));
-        }
-        if bookmarked {
-            reasons.push(MatchReason::Bookmark);
-        }
+        let mut reasons = vec![MatchReason::PreviousUse];
+        reasons.extend(field_reasons(row, &url, &title)?);
         let url = Url::parse(&url)?;
 
         Ok(Self {
@@ -246,8 +359,6 @@ impl SearchResult {
     }
 
     pub fn from_suggestion_row(row: &rusqlite::Row<'_>) -> Result<Self> {
-        let mut reasons = vec![MatchReason::Bookmark];
-
         let search_string = row.get::<_, String>("searchString")?;
         let url = row.get::<_, String>("url")?;
 
@@ -255,10 +366,7 @@ impl SearchResult {
         let bookmark_title = row.get::<_, Option<String>>("btitle")?;
         let title = bookmark_title.or_else(|| history_title).unwrap_or_default();
 
-        let tags = row.get::<_, Option<String>>("tags")?;
-        if let Some(tags) = tags {
-            reasons.push(MatchReason::Tags(tags));
-        }
+        let reasons = field_reasons(row, &url, &title)?;
         let url = Url::parse(&url)?;
 
         let frecency = row.get::<_, i64>("frecency")?;
@@ -357,6 +465,7 @@ impl From<MatchReason> for SearchResultReason {
             MatchReason::PreviousUse => SearchResultReason::PreviousUse,
             MatchReason::Bookmark => SearchResultReason::Bookmark,
             MatchReason::Tags(_) => SearchResultReason::Tag,
+            MatchReason::Title => SearchResultReason::Title,
         }
     }
 }
@@ -512,13 +621,17 @@ impl<'query> Matcher for Adaptive<'query> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   (SELECT GROUP_CONCAT(t.tag, ', ')
+                    FROM moz_tags t
+                    JOIN moz_tags_relation r ON r.tag_id = t.id
+                    WHERE r.place_id = h.id) AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
                    NULL AS open_count,
                    h.frecency as frecency,
-                   :searchString AS searchString
+                   :searchString AS searchString,
+                   :matchBehavior AS matchBehavior
             FROM (
               SELECT ROUND(MAX(use_count) * (1 + (input = :searchString)), 1) AS rank,
                      place_id
@@ -577,11 +690,15 @@ impl<'query> Matcher for Suggestions<'query> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   (SELECT GROUP_CONCAT(t.tag, ', ')
+                    FROM moz_tags t
+                    JOIN moz_tags_relation r ON r.tag_id = t.id
+                    WHERE r.place_id = h.id) AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
-                   NULL AS open_count, h.frecency, :searchString AS searchString
+                   NULL AS open_count, h.frecency, :searchString AS searchString,
+                   :matchBehavior AS matchBehavior
             FROM moz_places h
             WHERE h.frecency > 0
               AND AUTOCOMPLETE_MATCH(:searchString, h.url,
@@ -707,7 +824,12 @@ mod tests {
             .iter()
             .any(|result| result.search_string == "ample"
                 && result.url == url
-                && result.reasons == [MatchReason::PreviousUse]));
+                && result.reasons
+                    == [
+                        MatchReason::PreviousUse,
+                        MatchReason::Url,
+                        MatchReason::Title
+                    ]));
 
         let with_limit = search_frecent(
             &conn,
@@ -729,6 +851,100 @@ mod tests {
             }]
         );
     }
+
+    #[test]
+    fn search_weighted() {
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+        use crate::storage::tags::tag_url;
+
+        let conn = new_mem_connection();
+        let tagged = Url::parse("https://www.mozilla.org/firefox/").unwrap();
+        let bookmarked = Url::parse("https://developer.mozilla.org/docs/browser").unwrap();
+        for _ in 0..3 {
+            let visit = VisitObservation::new(tagged.clone())
+                .with_title("Download Firefox".to_string())
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Timestamp::now());
+            apply_observation(&conn, visit).expect("Should apply visit");
+        }
+        let visit = VisitObservation::new(bookmarked.clone())
+            .with_title("Browser compatibility".to_string())
+            .with_visit_type(VisitTransition::Link)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit).expect("Should apply visit");
+        tag_url(&conn, &tagged, "browser").expect("Should tag");
+        insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: bookmarked.clone(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("Should insert bookmark");
+
+        let params = SearchParams {
+            search_string: "browser".into(),
+            limit: 10,
+        };
+        let results = search_frecent(&conn, params.clone()).expect("Should search");
+        let reasons_for = |url: &Url| {
+            results
+                .iter()
+                .find(|result| &result.url == url)
+                .map(|result| result.reasons.clone())
+        };
+        assert_eq!(
+            reasons_for(&tagged),
+            Some(vec![MatchReason::Tags("browser".into())])
+        );
+        assert_eq!(
+            reasons_for(&bookmarked),
+            Some(vec![
+                MatchReason::Url,
+                MatchReason::Title,
+                MatchReason::Bookmark
+            ])
+        );
+
+        // With no weights, the results are ranked by frecency alone.
+        let unweighted = MatchWeights {
+            keyword: 0.0,
+            origin: 0.0,
+            url: 0.0,
+            previous_use: 0.0,
+            bookmark: 0.0,
+            tags: 0.0,
+            title: 0.0,
+        };
+        let results =
+            search_frecent_weighted(&conn, params.clone(), &unweighted).expect("Should search");
+        assert_eq!(results.len(), 2);
+        assert!(results[0].frecency >= results[1].frecency);
+
+        let prefer_tags = MatchWeights {
+            tags: 1000.0,
+            ..unweighted
+        };
+        let results =
+            search_frecent_weighted(&conn, params.clone(), &prefer_tags).expect("Should search");
+        assert_eq!(results[0].url, tagged);
+        let prefer_bookmarks = MatchWeights {
+            bookmark: 1000.0,
+            ..unweighted
+        };
+        let results =
+            search_frecent_weighted(&conn, params, &prefer_bookmarks).expect("Should search");
+        assert_eq!(results[0].url, bookmarked);
+    }
+
     #[test]
     fn search_unicode() {
         let conn = new_mem_connection();
@@ -14,3 +14,8 @@ pub fn apply_observation(conn: &mut PlacesDb, visit_obs: VisitObservation) -> Re
     storage::history::apply_observation(conn, visit_obs)?;
     Ok(())
 }
+
+pub fn apply_observations(conn: &mut PlacesDb, visit_obs: Vec<VisitObservation>) -> Result<()> {
+    storage::history::apply_observations(conn, visit_obs)?;
+    Ok(())
+}
@@ -5,12 +5,13 @@
 use super::schema;
 use crate::api::places_api::ConnectionType;
 use crate::error::*;
+use interrupt_support::Interruptable;
 use rusqlite::Connection;
 use sql_support::{ConnExt, SqlInterruptHandle, SqlInterruptScope};
 use std::ops::Deref;
 use std::path::Path;
 
-use std::sync::{atomic::AtomicUsize, Arc, Mutex};
+use std::sync::{atomic::AtomicUsize, Arc, Mutex, Weak};
 
 pub const MAX_VARIABLE_NUMBER: usize = 999;
 
@@ -19,6 +20,9 @@ pub struct PlacesDb {
     pub db: Connection,
     conn_type: ConnectionType,
     interrupt_counter: Arc<AtomicUsize>,
+    // Registered with `interrupt_support::register_interrupt`, which only
+    // keeps a weak reference, so this keeps it alive as long as we are.
+    shutdown_handle: Arc<SqlInterruptHandle>,
     api_id: usize,
     pub(super) coop_tx_lock: Arc<Mutex<()>>,
 }
@@ -69,12 +73,21 @@ impl PlacesDb {
         db.execute_batch(initial_pragmas)?;
         define_functions(&db)?;
         db.set_prepared_statement_cache_capacity(128);
+        let interrupt_counter = Arc::new(AtomicUsize::new(0));
+        let shutdown_handle = Arc::new(SqlInterruptHandle::new(
+            db.get_interrupt_handle(),
+            interrupt_counter.clone(),
+        ));
+        interrupt_support::register_interrupt(
+            Arc::downgrade(&shutdown_handle) as Weak<dyn Interruptable>
+        );
         let res = Self {
             db,
             conn_type,
             // The API sets this explicitly.
             api_id,
-            interrupt_counter: Arc::new(AtomicUsize::new(0)),
+            interrupt_counter,
+            shutdown_handle,
             coop_tx_lock,
         };
         match res.conn_type() {
@@ -18,7 +18,7 @@ use crate::types::SyncStatus;
 use rusqlite::NO_PARAMS;
 use sql_support::ConnExt;
 
-const VERSION: i64 = 11;
+const VERSION: i64 = 14;
 
 // Shared schema and temp tables for the read-write and Sync connections.
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
@@ -255,6 +255,24 @@ fn upgrade(db: &PlacesDb, from: i64) -> Result<()> {
         ],
         || Ok(()),
     )?;
+    // Add the history metadata table.
+    migration(db, 11, 12, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?;
+    migration(db, 12, 13, &[], || {
+        // `ADD COLUMN` isn't idempotent, and a downgraded database may
+        // already have it.
+        let has_favicon_url: bool = db.query_row_and_then(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('moz_places')
+                           WHERE name = 'favicon_url')",
+            NO_PARAMS,
+            |row| row.get(0),
+        )?;
+        if !has_favicon_url {
+            db.execute_batch("ALTER TABLE moz_places ADD COLUMN favicon_url TEXT")?;
+        }
+        Ok(())
+    })?;
+    // Add the pinned sites table.
+    migration(db, 13, 14, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?;
     // Add more migrations here...
 
     if get_current_schema_version(db)? == VERSION {
@@ -7,6 +7,7 @@ use crate::types::BookmarkType;
 use failure::Fail;
 use interrupt_support::Interrupted;
 use serde_json::Value as JsonValue;
+use sql_support::MaybeBusy;
 
 // Note: If you add new error types that should be returned to consumers on the other side of the
 // FFI, update `get_code` in `ffi.rs`
@@ -30,6 +31,11 @@ pub enum ErrorKind {
     #[fail(display = "Error executing SQL: {}", _0)]
     SqlError(#[fail(cause)] rusqlite::Error),
 
+    /// Another connection holds a lock on the database, so the operation can
+    /// be tried again later.
+    #[fail(display = "The database is busy: {}", _0)]
+    DatabaseBusy(#[fail(cause)] rusqlite::Error),
+
     #[fail(display = "Error parsing URL: {}", _0)]
     UrlParseError(#[fail(cause)] url::ParseError),
 
@@ -84,7 +90,6 @@ error_support::define_error! {
         (SyncAdapterError, sync15::Error),
         (JsonError, serde_json::Error),
         (UrlParseError, url::ParseError),
-        (SqlError, rusqlite::Error),
         (InvalidPlaceInfo, InvalidPlaceInfo),
         (Corruption, Corruption),
         (IoError, std::io::Error),
@@ -95,6 +100,35 @@ error_support::define_error! {
     }
 }
 
+// SQL errors are converted by hand, so that lock contention becomes
+// `DatabaseBusy`.
+impl From<rusqlite::Error> for ErrorKind {
+    #[cold]
+    fn from(e: rusqlite::Error) -> ErrorKind {
+        if e.is_busy() {
+            ErrorKind::DatabaseBusy(e)
+        } else {
+            ErrorKind::SqlError(e)
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    #[cold]
+    fn from(e: rusqlite::Error) -> Self {
+        ErrorKind::from(e).into()
+    }
+}
+
+impl MaybeBusy for Error {
+    fn is_busy(&self) -> bool {
+        match self.kind() {
+            ErrorKind::DatabaseBusy(_) => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Fail)]
 pub enum InvalidPlaceInfo {
     #[fail(display = "No url specified")]
@@ -130,6 +164,9 @@ pub enum InvalidPlaceInfo {
     // Like Urls, a tag is considered private info, so the value isn't in the error.
     #[fail(display = "The tag value is invalid")]
     InvalidTag,
+    // Like tags, the domain isn't included in the error.
+    #[fail(display = "The domain is invalid")]
+    InvalidDomain,
     #[fail(
         display = "Cannot change the '{}' property of a bookmark of type {:?}",
         _0, _1
@@ -164,3 +201,23 @@ pub enum Corruption {
     )]
     NonRootWithoutParent(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busy_errors() {
+        for code in &[rusqlite::ffi::SQLITE_BUSY, rusqlite::ffi::SQLITE_LOCKED] {
+            let err = Error::from(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(*code),
+                None,
+            ));
+            assert!(matches!(err.kind(), ErrorKind::DatabaseBusy(_)));
+            assert!(err.is_busy());
+        }
+        let err = Error::from(rusqlite::Error::QueryReturnedNoRows);
+        assert!(matches!(err.kind(), ErrorKind::SqlError(_)));
+        assert!(!err.is_busy());
+    }
+}
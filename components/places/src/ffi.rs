@@ -79,14 +79,12 @@ fn get_code(err: &Error) -> ErrorCode {
             log::error!("URL parse error: {}", e);
             ErrorCode::new(error_codes::URL_PARSE_ERROR)
         }
-        // Can't pattern match on `err` without adding a dep on the sqlite3-sys crate,
-        // so we just use a `if` guard.
-        ErrorKind::SqlError(rusqlite::Error::SqliteFailure(err, msg))
-            if err.code == rusqlite::ErrorCode::DatabaseBusy =>
-        {
-            log::error!("Database busy: {:?} {:?}", err, msg);
+        ErrorKind::DatabaseBusy(e) => {
+            log::error!("Database busy: {:?}", e);
             ErrorCode::new(error_codes::DATABASE_BUSY)
         }
+        // Can't pattern match on `err` without adding a dep on the sqlite3-sys crate,
+        // so we just use a `if` guard.
         ErrorKind::SqlError(rusqlite::Error::SqliteFailure(err, _))
             if err.code == rusqlite::ErrorCode::OperationInterrupted =>
         {
@@ -0,0 +1,825 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Import and export of the "Netscape bookmark file" format (the
+//! `bookmarks.html` that every browser can read and write).
+//!
+//! The format is only loosely HTML: a `<DL>` for each folder, holding a
+//! `<DT>` for each item. A folder is an `<H3>` followed by its own `<DL>`, a
+//! bookmark is an `<A HREF>`, and a separator is an `<HR>`. Keywords and tags
+//! are stored in the `SHORTCUTURL` and `TAGS` attributes of the `<A>`, and
+//! dates (in seconds) in `ADD_DATE` and `LAST_MODIFIED`. Files written by
+//! other browsers are often not well-formed, so the parser here is very
+//! forgiving, and only looks at the tags it knows about.
+//!
+//! Like desktop, the items at the top level of the file belong to the
+//! bookmarks menu, and the folders marked `PERSONAL_TOOLBAR_FOLDER` and
+//! `UNFILED_BOOKMARKS_FOLDER` hold the items of the toolbar and "Other
+//! Bookmarks" roots. There's no marker for the mobile root, so it's exported
+//! as an ordinary folder in the menu.
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::bookmarks::{
+    fetch_tree, insert_bookmark_tree_in_tx, BookmarkNode, BookmarkPosition, BookmarkRootGuid,
+    BookmarkTreeNode, FetchDepth, FolderNode, SeparatorNode,
+};
+use crate::storage::{delete_pending_temp_tables, tags, URL_LENGTH_MAX};
+use crate::types::Timestamp;
+use serde_derive::*;
+use sql_support::ConnExt;
+use std::collections::HashMap;
+use std::time::Instant;
+use url::Url;
+
+const HEADER: &str = "<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+";
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct BookmarksHtmlImportResult {
+    pub num_total: u32,
+    pub num_succeeded: u32,
+    /// Bookmarks which were skipped because their URL is invalid.
+    pub num_failed: u32,
+    pub total_duration: u128,
+}
+
+/// Imports the bookmarks in `html`, a Netscape bookmark file, appending them
+/// to the existing bookmarks. Tags are added, and keywords are added unless
+/// the URL or keyword already has one. Either everything is imported, or
+/// nothing is.
+pub fn import_bookmarks_html(db: &PlacesDb, html: &str) -> Result<BookmarksHtmlImportResult> {
+    let import_start = Instant::now();
+    let parsed = parse(html);
+    let tx = db.begin_transaction()?;
+    let result = insert_parsed(db, &parsed);
+    delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result?;
+    let metrics = BookmarksHtmlImportResult {
+        num_total: parsed.num_items + parsed.num_failed,
+        num_succeeded: parsed.num_items,
+        num_failed: parsed.num_failed,
+        total_duration: import_start.elapsed().as_millis(),
+    };
+    log::info!("Bookmarks HTML import finished: {:?}", metrics);
+    Ok(metrics)
+}
+
+fn insert_parsed(db: &PlacesDb, parsed: &ParsedBookmarks) -> Result<()> {
+    for (root, children) in &parsed.roots {
+        for child in children {
+            insert_bookmark_tree_in_tx(db, root.guid(), BookmarkPosition::Append, child)?;
+        }
+    }
+    for (url, tag) in &parsed.tags {
+        // Tags which aren't valid are skipped, rather than failing the
+        // whole import.
+        if tags::validate_tag(tag).ensure_valid().is_ok() {
+            tags::tag_url_in_tx(db, url, tag)?;
+        }
+    }
+    for (url, keyword) in &parsed.keywords {
+        db.execute_named_cached(
+            "INSERT OR IGNORE INTO moz_keywords(keyword, place_id)
+             SELECT :keyword, id FROM moz_places
+             WHERE url_hash = hash(:url) AND url = :url",
+            &[(":keyword", keyword), (":url", &url.as_str())],
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns all the bookmarks as a Netscape bookmark file.
+pub fn export_bookmarks_html(db: &PlacesDb) -> Result<String> {
+    let mut keywords: HashMap<String, String> = HashMap::new();
+    let mut stmt = db.prepare(
+        "SELECT h.url, k.keyword FROM moz_keywords k
+         JOIN moz_places h ON h.id = k.place_id",
+    )?;
+    let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+    while let Some(row) = rows.next()? {
+        keywords.insert(row.get("url")?, row.get("keyword")?);
+    }
+    drop(rows);
+    let mut tags: HashMap<String, String> = HashMap::new();
+    let mut stmt = db.prepare(
+        "SELECT h.url, GROUP_CONCAT(t.tag, ',') AS tags FROM moz_tags_relation r
+         JOIN moz_tags t ON t.id = r.tag_id
+         JOIN moz_places h ON h.id = r.place_id
+         GROUP BY h.id",
+    )?;
+    let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+    while let Some(row) = rows.next()? {
+        tags.insert(row.get("url")?, row.get("tags")?);
+    }
+    drop(rows);
+
+    let mut exporter = Exporter {
+        out: String::from(HEADER),
+        keywords,
+        tags,
+    };
+    exporter.out.push_str("<DL><p>\n");
+    if let Some(FolderNode { children, .. }) = fetch_root(db, BookmarkRootGuid::Menu)? {
+        for child in &children {
+            exporter.write_node(child, 1);
+        }
+    }
+    for &(root, title, attr) in &[
+        (
+            BookmarkRootGuid::Toolbar,
+            "Bookmarks Toolbar",
+            Some("PERSONAL_TOOLBAR_FOLDER"),
+        ),
+        (
+            BookmarkRootGuid::Unfiled,
+            "Other Bookmarks",
+            Some("UNFILED_BOOKMARKS_FOLDER"),
+        ),
+        (BookmarkRootGuid::Mobile, "Mobile Bookmarks", None),
+    ] {
+        let mut folder = match fetch_root(db, root)? {
+            Some(folder) => folder,
+            None => continue,
+        };
+        if attr.is_none() && folder.children.is_empty() {
+            continue;
+        }
+        // The dates of the roots are when the database was created, and
+        // aren't imported.
+        folder.title = Some(title.into());
+        folder.date_added = None;
+        folder.last_modified = None;
+        exporter.write_folder(&folder, attr, 1);
+    }
+    exporter.out.push_str("</DL>\n");
+    Ok(exporter.out)
+}
+
+fn fetch_root(db: &PlacesDb, root: BookmarkRootGuid) -> Result<Option<FolderNode>> {
+    Ok(
+        match fetch_tree(db, &root.as_guid(), &FetchDepth::Deepest)? {
+            Some((BookmarkTreeNode::Folder(folder), _, _)) => Some(folder),
+            _ => None,
+        },
+    )
+}
+
+struct Exporter {
+    out: String,
+    // The keyword and the comma-separated tags of each URL that has them.
+    keywords: HashMap<String, String>,
+    tags: HashMap<String, String>,
+}
+
+impl Exporter {
+    fn indent(&mut self, level: usize) {
+        for _ in 0..level {
+            self.out.push_str("    ");
+        }
+    }
+
+    fn write_dates(&mut self, date_added: Option<Timestamp>, last_modified: Option<Timestamp>) {
+        if let Some(date_added) = date_added {
+            self.out
+                .push_str(&format!(" ADD_DATE=\"{}\"", date_added.0 / 1000));
+        }
+        if let Some(last_modified) = last_modified {
+            self.out
+                .push_str(&format!(" LAST_MODIFIED=\"{}\"", last_modified.0 / 1000));
+        }
+    }
+
+    fn write_node(&mut self, node: &BookmarkTreeNode, level: usize) {
+        match node {
+            BookmarkTreeNode::Bookmark(b) => {
+                self.indent(level);
+                self.out.push_str("<DT><A HREF=\"");
+                self.out.push_str(&escape(b.url.as_str()));
+                self.out.push('"');
+                self.write_dates(b.date_added, b.last_modified);
+                if let Some(keyword) = self.keywords.get(b.url.as_str()) {
+                    let attr = format!(" SHORTCUTURL=\"{}\"", escape(keyword));
+                    self.out.push_str(&attr);
+                }
+                if let Some(tags) = self.tags.get(b.url.as_str()) {
+                    let attr = format!(" TAGS=\"{}\"", escape(tags));
+                    self.out.push_str(&attr);
+                }
+                self.out.push('>');
+                self.out
+                    .push_str(&escape(b.title.as_deref().unwrap_or_default()));
+                self.out.push_str("</A>\n");
+            }
+            BookmarkTreeNode::Separator(_) => {
+                self.indent(level);
+                self.out.push_str("<HR>\n");
+            }
+            BookmarkTreeNode::Folder(f) => self.write_folder(f, None, level),
+        }
+    }
+
+    fn write_folder(&mut self, folder: &FolderNode, attr: Option<&str>, level: usize) {
+        self.indent(level);
+        self.out.push_str("<DT><H3");
+        self.write_dates(folder.date_added, folder.last_modified);
+        if let Some(attr) = attr {
+            self.out.push_str(&format!(" {}=\"true\"", attr));
+        }
+        self.out.push('>');
+        self.out
+            .push_str(&escape(folder.title.as_deref().unwrap_or_default()));
+        self.out.push_str("</H3>\n");
+        self.indent(level);
+        self.out.push_str("<DL><p>\n");
+        for child in &folder.children {
+            self.write_node(child, level + 1);
+        }
+        self.indent(level);
+        self.out.push_str("</DL><p>\n");
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                }
+                _ if entity.starts_with('#') => {
+                    entity[1..].parse().ok().and_then(std::char::from_u32)
+                }
+                _ => None,
+            };
+            c.map(|c| (c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                unescaped.push(c);
+                rest = &rest[len..];
+            }
+            // Not an entity we know, so it's a literal "&".
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+// Dates are in seconds, but some browsers use microseconds (or
+// milliseconds), which are told apart by their size.
+fn parse_date(value: &str) -> Option<Timestamp> {
+    let value = value.trim().parse::<u64>().ok().filter(|&v| v > 0)?;
+    Some(Timestamp(if value < 100_000_000_000 {
+        value * 1000
+    } else if value < 100_000_000_000_000 {
+        value
+    } else {
+        value / 1000
+    }))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Start {
+        name: String,
+        attrs: HashMap<String, String>,
+    },
+    End(String),
+    Text(&'a str),
+}
+
+struct Tokenizer<'a> {
+    html: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(html: &'a str) -> Self {
+        Self { html, pos: 0 }
+    }
+
+    fn text(&mut self, start: usize) -> Token<'a> {
+        let rest = &self.html[self.pos..];
+        let end = rest[start..].find('<').map_or(rest.len(), |i| start + i);
+        self.pos += end;
+        Token::Text(&rest[..end])
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let rest = &self.html[self.pos..];
+            if rest.is_empty() {
+                return None;
+            }
+            if !rest.starts_with('<') {
+                return Some(self.text(0));
+            }
+            if rest.starts_with("<!--") {
+                self.pos += rest[4..].find("-->").map_or(rest.len(), |i| i + 7);
+                continue;
+            }
+            match rest[1..].chars().next() {
+                Some(c) if c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?' => {}
+                // A "<" that doesn't start a tag.
+                _ => return Some(self.text(1)),
+            }
+            let end = match find_tag_end(rest) {
+                Some(end) => end,
+                None => {
+                    self.pos = self.html.len();
+                    return None;
+                }
+            };
+            self.pos += end + 1;
+            let tag = &rest[1..end];
+            if tag.starts_with('!') || tag.starts_with('?') {
+                continue;
+            }
+            if tag.starts_with('/') {
+                return Some(Token::End(tag[1..].trim().to_ascii_lowercase()));
+            }
+            return Some(parse_start_tag(tag));
+        }
+    }
+}
+
+// Returns the index of the ">" ending the tag at the start of `s`, ignoring
+// any in quoted attribute values.
+fn find_tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(i),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_start_tag(tag: &str) -> Token<'_> {
+    let is_separator = |c: char| c.is_whitespace() || c == '/' || c == '=';
+    let name_end = tag.find(is_separator).unwrap_or_else(|| tag.len());
+    let name = tag[..name_end].to_ascii_lowercase();
+    let mut attrs = HashMap::new();
+    let mut rest = &tag[name_end..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        let attr_end = rest.find(is_separator).unwrap_or_else(|| rest.len()).max(1);
+        let attr = rest[..attr_end].to_ascii_lowercase();
+        rest = rest[attr_end..].trim_start();
+        let value = if rest.starts_with('=') {
+            rest = rest[1..].trim_start();
+            let (value, len) = match rest.chars().next() {
+                Some(q) if q == '"' || q == '\'' => match rest[1..].find(q) {
+                    Some(end) => (&rest[1..=end], end + 2),
+                    None => (&rest[1..], rest.len()),
+                },
+                _ => {
+                    let end = rest.find(char::is_whitespace).unwrap_or_else(|| rest.len());
+                    (&rest[..end], end)
+                }
+            };
+            rest = &rest[len..];
+            unescape(value)
+        } else {
+            String::new()
+        };
+        attrs.entry(attr).or_insert(value);
+    }
+    Token::Start { name, attrs }
+}
+
+#[derive(Debug, Default)]
+struct ParsedBookmarks {
+    // The items to append to each root.
+    roots: Vec<(BookmarkRootGuid, Vec<BookmarkTreeNode>)>,
+    tags: Vec<(Url, String)>,
+    keywords: Vec<(Url, String)>,
+    num_items: u32,
+    num_failed: u32,
+}
+
+struct OpenFolder {
+    folder: FolderNode,
+    // The root the children of the folder belong to, for the top level and
+    // the special folders.
+    root: Option<BookmarkRootGuid>,
+}
+
+struct PendingBookmark {
+    url: Option<Url>,
+    attrs: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct Parser {
+    result: ParsedBookmarks,
+    folders: Vec<OpenFolder>,
+    // Whether each open <DL> started a folder.
+    lists: Vec<bool>,
+    // A folder whose <H3> we've seen, but whose <DL> (if any) we haven't.
+    pending_folder: Option<OpenFolder>,
+    pending_bookmark: Option<PendingBookmark>,
+    // The text of the <H3> or <A> being parsed, if any.
+    title: Option<String>,
+}
+
+impl Parser {
+    fn current_folder(&mut self) -> &mut FolderNode {
+        &mut self.folders.last_mut().unwrap().folder
+    }
+
+    // Called at the start of anything which ends the item being parsed: a
+    // folder without a <DL> is empty, and a bookmark without an </A> ends
+    // at the next item.
+    fn finish_pending(&mut self) {
+        if self.pending_folder.is_some() {
+            self.end_folder_title();
+            self.folders.push(self.pending_folder.take().unwrap());
+            self.close_folder();
+        }
+        self.end_bookmark();
+    }
+
+    fn start_folder(&mut self, attrs: &HashMap<String, String>) {
+        self.finish_pending();
+        let root = if attrs.contains_key("personal_toolbar_folder") {
+            Some(BookmarkRootGuid::Toolbar)
+        } else if attrs.contains_key("unfiled_bookmarks_folder") {
+            Some(BookmarkRootGuid::Unfiled)
+        } else {
+            None
+        };
+        self.pending_folder = Some(OpenFolder {
+            folder: FolderNode {
+                date_added: attrs.get("add_date").and_then(|d| parse_date(d)),
+                last_modified: attrs.get("last_modified").and_then(|d| parse_date(d)),
+                ..FolderNode::default()
+            },
+            root,
+        });
+        self.title = Some(String::new());
+    }
+
+    fn end_folder_title(&mut self) {
+        if let Some(open) = &mut self.pending_folder {
+            if let Some(title) = self.title.take() {
+                open.folder.title = Some(title.trim().to_owned());
+            }
+        }
+    }
+
+    fn start_list(&mut self) {
+        self.end_bookmark();
+        self.end_folder_title();
+        match self.pending_folder.take() {
+            Some(open) => {
+                self.folders.push(open);
+                self.lists.push(true);
+            }
+            None => self.lists.push(false),
+        }
+    }
+
+    fn end_list(&mut self) {
+        self.finish_pending();
+        if self.lists.pop() == Some(true) {
+            self.close_folder();
+        }
+    }
+
+    fn close_folder(&mut self) {
+        // The top level is never closed.
+        if self.folders.len() < 2 {
+            return;
+        }
+        let open = self.folders.pop().unwrap();
+        match open.root {
+            Some(root) => self.result.roots.push((root, open.folder.children)),
+            None => {
+                self.result.num_items += 1;
+                self.current_folder().children.push(open.folder.into());
+            }
+        }
+    }
+
+    fn start_bookmark(&mut self, attrs: HashMap<String, String>) {
+        self.finish_pending();
+        let url = attrs
+            .get("href")
+            .and_then(|href| Url::parse(href.trim()).ok())
+            .filter(|url| url.as_str().len() <= URL_LENGTH_MAX);
+        self.pending_bookmark = Some(PendingBookmark { url, attrs });
+        self.title = Some(String::new());
+    }
+
+    fn end_bookmark(&mut self) {
+        let pending = match self.pending_bookmark.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let title = self.title.take().unwrap_or_default();
+        let url = match pending.url {
+            Some(url) => url,
+            None => {
+                self.result.num_failed += 1;
+                return;
+            }
+        };
+        let attrs = pending.attrs;
+        if let Some(keyword) = attrs.get("shortcuturl") {
+            let keyword = keyword.trim();
+            if !keyword.is_empty() {
+                self.result.keywords.push((url.clone(), keyword.to_owned()));
+            }
+        }
+        if let Some(tags) = attrs.get("tags") {
+            for tag in tags.split(',') {
+                self.result.tags.push((url.clone(), tag.to_owned()));
+            }
+        }
+        let title = title.trim();
+        let node = BookmarkNode {
+            guid: None,
+            date_added: attrs.get("add_date").and_then(|d| parse_date(d)),
+            last_modified: attrs.get("last_modified").and_then(|d| parse_date(d)),
+            title: if title.is_empty() {
+                None
+            } else {
+                Some(title.to_owned())
+            },
+            url,
+        };
+        self.result.num_items += 1;
+        self.current_folder().children.push(node.into());
+    }
+
+    fn separator(&mut self) {
+        self.finish_pending();
+        self.result.num_items += 1;
+        self.current_folder()
+            .children
+            .push(SeparatorNode::default().into());
+    }
+}
+
+fn parse(html: &str) -> ParsedBookmarks {
+    let mut parser = Parser::default();
+    parser.folders.push(OpenFolder {
+        folder: FolderNode::default(),
+        root: Some(BookmarkRootGuid::Menu),
+    });
+    for token in Tokenizer::new(html) {
+        match token {
+            Token::Start { name, attrs } => match name.as_str() {
+                "h3" => parser.start_folder(&attrs),
+                "a" => parser.start_bookmark(attrs),
+                "hr" => parser.separator(),
+                "dl" => parser.start_list(),
+                "dt" => parser.finish_pending(),
+                _ => {}
+            },
+            Token::End(name) => match name.as_str() {
+                "h3" => parser.end_folder_title(),
+                "a" => parser.end_bookmark(),
+                "dl" => parser.end_list(),
+                _ => {}
+            },
+            Token::Text(text) => {
+                if let Some(title) = &mut parser.title {
+                    title.push_str(&unescape(text));
+                }
+            }
+        }
+    }
+    parser.finish_pending();
+    while parser.folders.len() > 1 {
+        parser.close_folder();
+    }
+    let menu = parser.folders.pop().unwrap();
+    let mut result = parser.result;
+    result
+        .roots
+        .insert(0, (BookmarkRootGuid::Menu, menu.folder.children));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::bookmarks_get_url_for_keyword;
+    use crate::tests::assert_json_tree;
+    use serde_json::json;
+
+    const HTML: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file. -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+<DL><p>
+    <DT><A HREF="https://www.example.com/" ADD_DATE="1500000000" LAST_MODIFIED="1500000001" SHORTCUTURL="ex" TAGS="a,b">Example &amp; co</A>
+    <HR>
+    <DT><H3 ADD_DATE="1500000000">Folder</H3>
+    <DD>A description, which isn't imported
+    <DL><p>
+        <DT><A HREF="https://www.example2.com/">Unterminated
+        <DT><A HREF="not a url">Invalid</A>
+        <DT><H3>Empty</H3>
+        <DT><A HREF='https://www.example3.com/' ADD_DATE=1500000000000000>Microseconds</A>
+    </DL><p>
+    <DT><H3 PERSONAL_TOOLBAR_FOLDER="true">Bookmarks Toolbar</H3>
+    <DL><p>
+        <DT><A HREF="https://toolbar.example.com/">Toolbar</A>
+    </DL><p>
+</DL><p>
+"#;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            unescape("&lt;&#65;&#x42;&bogus; & &amp;&gt;"),
+            "<AB&bogus; & &>"
+        );
+        assert_eq!(parse_date("1500000000"), Some(Timestamp(1_500_000_000_000)));
+        assert_eq!(
+            parse_date("1500000000000"),
+            Some(Timestamp(1_500_000_000_000))
+        );
+        assert_eq!(
+            parse_date("1500000000000000"),
+            Some(Timestamp(1_500_000_000_000))
+        );
+        assert_eq!(parse_date("0"), None);
+        let tokens = Tokenizer::new("a < b<A HREF=\"x>y\" b>c</a >").collect::<Vec<_>>();
+        let mut attrs = HashMap::new();
+        attrs.insert("href".to_string(), "x>y".to_string());
+        attrs.insert("b".to_string(), "".to_string());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Text("a "),
+                Token::Text("< b"),
+                Token::Start {
+                    name: "a".into(),
+                    attrs
+                },
+                Token::Text("c"),
+                Token::End("a".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let metrics = import_bookmarks_html(&conn, HTML)?;
+        assert_eq!(metrics.num_total, 8);
+        assert_eq!(metrics.num_succeeded, 7);
+        assert_eq!(metrics.num_failed, 1);
+
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Menu.into(),
+            json!({
+                "guid": &BookmarkRootGuid::Menu.as_guid(),
+                "children": [
+                    {
+                        "title": "Example & co",
+                        "url": "https://www.example.com/",
+                        "date_added": 1_500_000_000_000u64,
+                        "last_modified": 1_500_000_001_000u64,
+                    },
+                    {
+                        "type": 3,
+                    },
+                    {
+                        "title": "Folder",
+                        "date_added": 1_500_000_000_000u64,
+                        "children": [
+                            {
+                                "title": "Unterminated",
+                                "url": "https://www.example2.com/",
+                            },
+                            {
+                                "title": "Empty",
+                                "children": [],
+                            },
+                            {
+                                "title": "Microseconds",
+                                "url": "https://www.example3.com/",
+                                "date_added": 1_500_000_000_000u64,
+                            },
+                        ],
+                    },
+                ]
+            }),
+        );
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Toolbar.into(),
+            json!({
+                "guid": &BookmarkRootGuid::Toolbar.as_guid(),
+                "children": [
+                    {
+                        "title": "Toolbar",
+                        "url": "https://toolbar.example.com/",
+                    },
+                ]
+            }),
+        );
+        let url = Url::parse("https://www.example.com/")?;
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&conn, "ex")?,
+            Some(url.clone())
+        );
+        let mut tags = tags::get_tags_for_url(&conn, &url)?;
+        tags.sort();
+        assert_eq!(tags, vec!["a".to_string(), "b".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_export() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        import_bookmarks_html(&conn, HTML)?;
+        let exported = export_bookmarks_html(&conn)?;
+        assert!(exported.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>"));
+        assert!(exported.contains(
+            "    <DT><A HREF=\"https://www.example.com/\" ADD_DATE=\"1500000000\" \
+             LAST_MODIFIED=\"1500000001\" SHORTCUTURL=\"ex\" TAGS=\"a,b\">Example &amp; co</A>\n"
+        ));
+        assert!(exported.contains("    <HR>\n"));
+        assert!(exported.contains("        <DT><H3"));
+        assert!(exported.contains(" PERSONAL_TOOLBAR_FOLDER=\"true\">Bookmarks Toolbar</H3>\n"));
+        assert!(exported.contains(" UNFILED_BOOKMARKS_FOLDER=\"true\">Other Bookmarks</H3>\n"));
+        // The mobile root is empty, so isn't exported.
+        assert!(!exported.contains("Mobile Bookmarks"));
+
+        // Importing the export gives the same bookmarks, to the second.
+        let other = new_mem_connection();
+        let metrics = import_bookmarks_html(&other, &exported)?;
+        assert_eq!(metrics.num_failed, 0);
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&other, "ex")?,
+            Some(Url::parse("https://www.example.com/")?)
+        );
+        assert_eq!(export_bookmarks_html(&other)?, exported);
+        Ok(())
+    }
+}
@@ -7,3 +7,4 @@ pub mod history;
 pub use bookmarks::import as import_bookmarks;
 pub use bookmarks::import_pinned_sites;
 pub use history::import as import_history;
+pub use history::import_with_visit_limit as import_history_with_visit_limit;
@@ -22,15 +22,36 @@ pub struct HistoryMigrationResult {
     pub num_total: u32,
     pub num_succeeded: u32,
     pub num_failed: u32,
+    /// The number of visits which weren't imported because of the visit limit.
+    pub num_skipped: u32,
+    /// The number of visits with a type we don't know, imported as links.
+    pub num_unknown_visit_types: u32,
+    // The phase durations, and the total, are in milliseconds.
+    pub places_duration: u128,
+    pub visits_duration: u128,
+    pub frecencies_duration: u128,
     pub total_duration: u128,
 }
 
+/// Imports every visit in the Fennec database at `path`.
 pub fn import(
     places_api: &PlacesApi,
     path: impl AsRef<std::path::Path>,
 ) -> Result<HistoryMigrationResult> {
     let url = crate::util::ensure_url_path(path)?;
-    do_import(places_api, url)
+    do_import(places_api, url, None)
+}
+
+/// Like `import`, but only imports the `max_visits` most recent visits, so
+/// that importing a very large history doesn't take too long. Pages without
+/// any imported visits are still imported.
+pub fn import_with_visit_limit(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+    max_visits: u32,
+) -> Result<HistoryMigrationResult> {
+    let url = crate::util::ensure_url_path(path)?;
+    do_import(places_api, url, Some(max_visits))
 }
 
 pub fn select_count(conn: &PlacesDb, stmt: &str) -> u32 {
@@ -39,7 +60,11 @@ pub fn select_count(conn: &PlacesDb, stmt: &str) -> u32 {
     count.unwrap().unwrap()
 }
 
-fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<HistoryMigrationResult> {
+fn do_import(
+    places_api: &PlacesApi,
+    android_db_file_url: Url,
+    max_visits: Option<u32>,
+) -> Result<HistoryMigrationResult> {
     let conn = places_api.open_sync_connection()?;
 
     let scope = conn.begin_interrupt_scope();
@@ -64,7 +89,11 @@ fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<History
 
     log::debug!("Counting Fennec history visits");
     let num_total = select_count(&conn, &COUNT_FENNEC_HISTORY_VISITS);
+    let num_skipped = max_visits.map_or(0, |max| num_total.saturating_sub(max));
+    let num_unknown_visit_types = select_count(&conn, &COUNT_UNKNOWN_VISIT_TYPES);
+    let num_existing = select_count(&conn, &COUNT_FENIX_HISTORY_VISITS);
 
+    let places_start = Instant::now();
     log::debug!("Creating and populating staging table");
     conn.execute_batch(&CREATE_STAGING_TABLE)?;
     conn.execute_batch(&FILL_STAGING)?;
@@ -72,25 +101,38 @@ fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<History
     log::debug!("Populating missing entries in moz_places");
     conn.execute_batch(&FILL_MOZ_PLACES)?;
     scope.err_if_interrupted()?;
+    let places_duration = places_start.elapsed().as_millis();
 
+    let visits_start = Instant::now();
     log::debug!("Inserting the history visits");
-    conn.execute_batch(&INSERT_HISTORY_VISITS)?;
+    // A negative limit means there's no limit.
+    let limit = max_visits.map_or(-1, i64::from);
+    conn.execute_named(&INSERT_HISTORY_VISITS, &[(":maxVisits", &limit)])?;
     scope.err_if_interrupted()?;
 
+    log::debug!("Flagging frecencies for recalculation");
+    conn.execute_batch(&MARK_FRECENCIES_STALE)?;
+
     log::debug!("Committing...");
     tx.commit()?;
+    let visits_duration = visits_start.elapsed().as_millis();
 
     // Note: update_frecencies manages its own transaction, which is fine,
     // since nothing that bad will happen if it is aborted.
+    let frecencies_start = Instant::now();
     log::debug!("Updating frecencies");
     let store = BookmarksStore::new(&conn, &scope);
     store.update_frecencies()?;
+    let frecencies_duration = frecencies_start.elapsed().as_millis();
 
     log::info!("Successfully imported history visits!");
 
     log::debug!("Counting Fenix history visits");
-    let num_succeeded = select_count(&conn, &COUNT_FENIX_HISTORY_VISITS);
-    let num_failed = num_total - num_succeeded;
+    let num_succeeded =
+        select_count(&conn, &COUNT_FENIX_HISTORY_VISITS).saturating_sub(num_existing);
+    let num_failed = num_total
+        .saturating_sub(num_skipped)
+        .saturating_sub(num_succeeded);
 
     auto_detach.execute_now()?;
 
@@ -98,8 +140,14 @@ fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<History
         num_total,
         num_succeeded,
         num_failed,
+        num_skipped,
+        num_unknown_visit_types,
+        places_duration,
+        visits_duration,
+        frecencies_duration,
         total_duration: import_start.elapsed().as_millis(),
     };
+    log::info!("History import metrics: {:?}", metrics);
 
     Ok(metrics)
 }
@@ -144,21 +192,34 @@ lazy_static::lazy_static! {
             FROM temp.fennecHistoryStaging t"
     ;
 
-    // Insert history visits
+    // Insert history visits, most recent first, so that a limit keeps the
+    // most recent ones.
     static ref INSERT_HISTORY_VISITS: &'static str =
         "INSERT OR IGNORE INTO main.moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
             SELECT
                 NULL, -- Fenec does not store enough information to rebuild redirect chains.
                 (SELECT p.id FROM main.moz_places p WHERE p.url_hash = t.url_hash AND p.url = t.url),
-                sanitize_timestamp(v.date),
-                v.visit_type, -- Fennec stores visit types maps 1:1 to ours.
+                sanitize_timestamp(v.date) AS date,
+                -- Fennec's visit types map 1:1 to ours. Anything else
+                -- couldn't be read back, so it's imported as a link.
+                CASE WHEN v.visit_type BETWEEN 1 AND 9 THEN v.visit_type ELSE 1 END,
                 v.is_local
             FROM fennec.visits v
             -- Note that we *do not* `sanitize_utf8(v.history_guid)` here due to
             -- perf concerns. It just means if there happens to be non-utf8
             -- guids in both tables we will not migrate their visits - which
             -- seems fine as it should impact ~ 0 users.
-            LEFT JOIN temp.fennecHistoryStaging t on v.history_guid = t.guid"
+            LEFT JOIN temp.fennecHistoryStaging t on v.history_guid = t.guid
+            ORDER BY date DESC
+            LIMIT :maxVisits"
+    ;
+
+    // The imported pages all have a frecency of -1 until it's recalculated.
+    static ref MARK_FRECENCIES_STALE: &'static str =
+        "REPLACE INTO main.moz_places_stale_frecencies(place_id, stale_at)
+            SELECT p.id, now()
+            FROM main.moz_places p
+            JOIN temp.fennecHistoryStaging t ON p.url_hash = t.url_hash AND p.url = t.url"
     ;
 
     // Count Fennec history visits
@@ -166,6 +227,12 @@ lazy_static::lazy_static! {
         "SELECT COUNT(*) FROM fennec.visits"
     ;
 
+    // Count Fennec history visits with a type we don't know
+    static ref COUNT_UNKNOWN_VISIT_TYPES: &'static str =
+        "SELECT COUNT(*) FROM fennec.visits
+         WHERE visit_type IS NULL OR visit_type NOT BETWEEN 1 AND 9"
+    ;
+
     // Count Fenix history visits
     static ref COUNT_FENIX_HISTORY_VISITS: &'static str =
         "SELECT COUNT(*) FROM main.moz_historyvisits"
@@ -2,10 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+pub mod bookmarks_html;
+pub use bookmarks_html::{export_bookmarks_html, import_bookmarks_html};
 pub mod common;
 pub mod fennec;
 pub use fennec::import_bookmarks as import_fennec_bookmarks;
 pub use fennec::import_history as import_fennec_history;
+pub use fennec::import_history_with_visit_limit as import_fennec_history_with_visit_limit;
 pub use fennec::import_pinned_sites as import_fennec_pinned_sites;
 pub mod ios_bookmarks;
 pub use ios_bookmarks::import_ios_bookmarks;
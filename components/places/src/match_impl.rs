@@ -55,6 +55,29 @@ impl ToSql for MatchBehavior {
     }
 }
 
+impl MatchBehavior {
+    fn search_fn(self) -> fn(&str, &str) -> bool {
+        match self {
+            MatchBehavior::Anywhere | MatchBehavior::AnywhereUnmodified => find_anywhere,
+            MatchBehavior::Beginning => find_beginning,
+            MatchBehavior::BeginningCaseSensitive => find_beginning_case_sensitive,
+            _ => find_on_boundary,
+        }
+    }
+
+    /// Returns true if any of the whitespace-separated tokens in `search_str`
+    /// match `source`. Unlike `AutocompleteMatch::invoke`, this doesn't fix up
+    /// URLs, so it's meant for telling which of the fields of an existing
+    /// match a token was found in.
+    pub fn matches_any_token(self, search_str: &str, source: &str) -> bool {
+        let search_fn = self.search_fn();
+        let source = util::slice_up_to(source, MAX_CHARS_TO_SEARCH_THROUGH);
+        search_str
+            .split_ascii_whitespace()
+            .any(|token| search_fn(token, source))
+    }
+}
+
 bitflags! {
     pub struct SearchBehavior: u32 {
         /// Search through history.
@@ -342,15 +365,6 @@ pub struct AutocompleteMatch<'search, 'url, 'title, 'tags> {
 }
 
 impl<'search, 'url, 'title, 'tags> AutocompleteMatch<'search, 'url, 'title, 'tags> {
-    fn get_search_fn(&self) -> fn(&str, &str) -> bool {
-        match self.match_behavior {
-            MatchBehavior::Anywhere | MatchBehavior::AnywhereUnmodified => find_anywhere,
-            MatchBehavior::Beginning => find_beginning,
-            MatchBehavior::BeginningCaseSensitive => find_beginning_case_sensitive,
-            _ => find_on_boundary,
-        }
-    }
-
     fn fixup_url_str<'a>(&self, mut s: &'a str) -> Cow<'a, str> {
         if self.match_behavior != MatchBehavior::AnywhereUnmodified {
             if s.starts_with("http://") {
@@ -407,7 +421,7 @@ impl<'search, 'url, 'title, 'tags> AutocompleteMatch<'search, 'url, 'title, 'tag
             return false;
         }
         let fixed_url = self.fixup_url_str(self.url_str);
-        let search_fn = self.get_search_fn();
+        let search_fn = self.match_behavior.search_fn();
 
         let trimmed_url = util::slice_up_to(fixed_url.as_ref(), MAX_CHARS_TO_SEARCH_THROUGH);
         let trimmed_title = util::slice_up_to(self.title_str, MAX_CHARS_TO_SEARCH_THROUGH);
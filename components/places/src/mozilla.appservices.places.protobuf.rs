@@ -10,6 +10,10 @@ pub struct HistoryVisitInfo {
     pub visit_type: i32,
     #[prost(bool, required, tag="5")]
     pub is_hidden: bool,
+    #[prost(string, optional, tag="6")]
+    pub favicon_url: ::std::option::Option<std::string::String>,
+    #[prost(string, optional, tag="7")]
+    pub preview_image_url: ::std::option::Option<std::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HistoryVisitInfos {
@@ -154,6 +158,16 @@ pub struct BookmarkNode {
     /// Leaving this out is equivalent to false.
     #[prost(bool, optional, tag="11")]
     pub have_child_nodes: ::std::option::Option<bool>,
+    ///*
+    /// The favicon and preview image URLs of the bookmarked page, set with
+    /// `set_page_image_info`. Only present for type = `BookmarkType::Bookmark`.
+    ///
+    /// - Returned on reads, except for the children in `child_nodes`.
+    /// - Ignored for insertions and updates.
+    #[prost(string, optional, tag="12")]
+    pub favicon_url: ::std::option::Option<std::string::String>,
+    #[prost(string, optional, tag="13")]
+    pub preview_image_url: ::std::option::Option<std::string::String>,
 }
 ///* An array of bookmark nodes, since we can't represent that directly 
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -190,4 +204,5 @@ pub enum SearchResultReason {
     /// If we get real tag support, just add `optional string tags` to SearchResult below, but
     /// for now expose that it was because of tags.
     Tag = 6,
+    Title = 7,
 }
@@ -4,7 +4,7 @@
 
 use super::RowId;
 use super::{delete_meta, put_meta};
-use super::{fetch_page_info, new_page_info};
+use super::{fetch_page_info, mark_frecency_stale, new_page_info};
 use crate::bookmark_sync::store::{
     COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY, LAST_SYNC_META_KEY,
 };
@@ -363,6 +363,11 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
         WHERE id = :parent_id";
     db.execute_named_cached(sql_counter, &[(":parent_id", &parent.row_id)])?;
 
+    // Bookmarked pages get a frecency bonus.
+    if let Some(place_id) = fk {
+        mark_frecency_stale(db, place_id)?;
+    }
+
     Ok(guid)
 }
 
@@ -398,6 +403,20 @@ fn delete_bookmark_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
         .ok_or_else(|| Corruption::NonRootWithoutParent(guid.to_string()))?;
     // must reorder existing children.
     update_pos_for_deletion(db, record.position, record_parent_id)?;
+    // The pages of the bookmark, or of everything in the folder, lose their
+    // bookmark bonus.
+    db.execute_named_cached(
+        "WITH RECURSIVE
+         descendants(id, fk) AS (
+             SELECT id, fk FROM moz_bookmarks WHERE id = :id
+             UNION ALL
+             SELECT b.id, b.fk FROM moz_bookmarks b
+             JOIN descendants d ON b.parent = d.id
+         )
+         REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+         SELECT fk, :now FROM descendants WHERE fk NOT NULL",
+        &[(":id", &record.row_id), (":now", &Timestamp::now())],
+    )?;
     // and delete - children are recursively deleted.
     db.execute_named_cached(
         "DELETE from moz_bookmarks WHERE id = :id",
@@ -610,6 +629,11 @@ fn update_bookmark_in_tx(
     };
 
     let change_incr = title != raw.title || place_id != raw.place_id;
+    if place_id != raw.place_id {
+        for id in raw.place_id.iter().chain(place_id.iter()) {
+            mark_frecency_stale(db, *id)?;
+        }
+    }
 
     let now = Timestamp::now();
 
@@ -1028,48 +1052,84 @@ fn add_subtree_infos(parent: &SyncGuid, tree: &FolderNode, insert_infos: &mut Ve
     // the default values passed in so the entire tree has consistent
     // timestamps.
     let default_when = Some(Timestamp::now());
+    add_children_infos(parent, tree, default_when, insert_infos);
+}
+
+fn add_children_infos(
+    parent: &SyncGuid,
+    tree: &FolderNode,
+    default_when: Option<Timestamp>,
+    insert_infos: &mut Vec<InsertableItem>,
+) {
     insert_infos.reserve(tree.children.len());
     for child in &tree.children {
-        match child {
-            BookmarkTreeNode::Bookmark(b) => insert_infos.push(
+        add_node_infos(
+            parent,
+            BookmarkPosition::Append,
+            child,
+            default_when,
+            insert_infos,
+        );
+    }
+}
+
+// Adds the infos for inserting `node`, and all of its descendants, and
+// returns its guid (generating one if it doesn't have one).
+fn add_node_infos(
+    parent: &SyncGuid,
+    position: BookmarkPosition,
+    node: &BookmarkTreeNode,
+    default_when: Option<Timestamp>,
+    insert_infos: &mut Vec<InsertableItem>,
+) -> SyncGuid {
+    match node {
+        BookmarkTreeNode::Bookmark(b) => {
+            let my_guid = b.guid.clone().unwrap_or_else(SyncGuid::random);
+            insert_infos.push(
                 InsertableBookmark {
                     parent_guid: parent.clone(),
-                    position: BookmarkPosition::Append,
+                    position,
                     date_added: b.date_added.or(default_when),
                     last_modified: b.last_modified.or(default_when),
-                    guid: b.guid.clone(),
+                    guid: Some(my_guid.clone()),
                     url: b.url.clone(),
                     title: b.title.clone(),
                 }
                 .into(),
-            ),
-            BookmarkTreeNode::Separator(s) => insert_infos.push(
+            );
+            my_guid
+        }
+        BookmarkTreeNode::Separator(s) => {
+            let my_guid = s.guid.clone().unwrap_or_else(SyncGuid::random);
+            insert_infos.push(
                 InsertableSeparator {
                     parent_guid: parent.clone(),
-                    position: BookmarkPosition::Append,
+                    position,
                     date_added: s.date_added.or(default_when),
                     last_modified: s.last_modified.or(default_when),
-                    guid: s.guid.clone(),
+                    guid: Some(my_guid.clone()),
                 }
                 .into(),
-            ),
-            BookmarkTreeNode::Folder(f) => {
-                let my_guid = f.guid.clone().unwrap_or_else(SyncGuid::random);
-                // must add the folder before we recurse into children.
-                insert_infos.push(
-                    InsertableFolder {
-                        parent_guid: parent.clone(),
-                        position: BookmarkPosition::Append,
-                        date_added: f.date_added.or(default_when),
-                        last_modified: f.last_modified.or(default_when),
-                        guid: Some(my_guid.clone()),
-                        title: f.title.clone(),
-                    }
-                    .into(),
-                );
-                add_subtree_infos(&my_guid, &f, insert_infos);
-            }
-        };
+            );
+            my_guid
+        }
+        BookmarkTreeNode::Folder(f) => {
+            let my_guid = f.guid.clone().unwrap_or_else(SyncGuid::random);
+            // must add the folder before we recurse into children.
+            insert_infos.push(
+                InsertableFolder {
+                    parent_guid: parent.clone(),
+                    position,
+                    date_added: f.date_added.or(default_when),
+                    last_modified: f.last_modified.or(default_when),
+                    guid: Some(my_guid.clone()),
+                    title: f.title.clone(),
+                }
+                .into(),
+            );
+            add_children_infos(&my_guid, f, default_when, insert_infos);
+            my_guid
+        }
     }
 }
 
@@ -1083,7 +1143,10 @@ pub fn delete_everything(db: &PlacesDb) -> Result<()> {
 
 fn delete_everything_in_tx(db: &PlacesDb) -> Result<()> {
     db.execute_batch(&format!(
-        "DELETE FROM moz_bookmarks_synced;
+        "REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+         SELECT fk, {} FROM moz_bookmarks WHERE fk NOT NULL;
+
+         DELETE FROM moz_bookmarks_synced;
 
          DELETE FROM moz_bookmarks_deleted;
 
@@ -1093,6 +1156,7 @@ fn delete_everything_in_tx(db: &PlacesDb) -> Result<()> {
          UPDATE moz_bookmarks
          SET syncChangeCounter = 1,
              syncStatus = {}",
+        Timestamp::now(),
         BookmarkRootGuid::Root.as_str(),
         BookmarkRootGuid::Menu.as_str(),
         BookmarkRootGuid::Mobile.as_str(),
@@ -1126,6 +1190,47 @@ pub fn insert_tree(db: &PlacesDb, tree: &FolderNode) -> Result<()> {
     Ok(())
 }
 
+/// Inserts `root`, which may be a folder with a whole subtree of bookmarks
+/// (such as one imported from a file), at `position` in the folder
+/// `parent_guid`. Unlike `insert_tree`, the root itself is inserted, rather
+/// than only its children. Nodes without a GUID are given a new one, and the
+/// children of each folder are inserted in order. Either everything is
+/// inserted, or nothing is. Returns the GUID of the root.
+pub fn insert_bookmark_tree(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    position: BookmarkPosition,
+    root: &BookmarkTreeNode,
+) -> Result<SyncGuid> {
+    let tx = db.begin_transaction()?;
+    let result = insert_bookmark_tree_in_tx(db, parent_guid, position, root);
+    super::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+pub(crate) fn insert_bookmark_tree_in_tx(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    position: BookmarkPosition,
+    root: &BookmarkTreeNode,
+) -> Result<SyncGuid> {
+    let mut insert_infos: Vec<InsertableItem> = Vec::new();
+    let default_when = Some(Timestamp::now());
+    let root_guid = add_node_infos(parent_guid, position, root, default_when, &mut insert_infos);
+    log::info!(
+        "insert_bookmark_tree inserting {} records",
+        insert_infos.len()
+    );
+    for insertable in &insert_infos {
+        insert_bookmark_in_tx(db, insertable)?;
+    }
+    Ok(root_guid)
+}
+
 #[derive(Debug)]
 struct FetchedTreeRow {
     level: u32,
@@ -1365,6 +1470,8 @@ pub(crate) struct RawBookmark {
     pub sync_change_counter: u32,
     pub child_count: u32,
     pub grandparent_id: Option<RowId>,
+    pub favicon_url: Option<Url>,
+    pub preview_image_url: Option<Url>,
 }
 
 impl RawBookmark {
@@ -1393,6 +1500,14 @@ impl RawBookmark {
                 .unwrap_or_default(),
             child_count: row.get("_childCount")?,
             grandparent_id: row.get("_grandparentId")?,
+            favicon_url: match row.get::<_, Option<String>>("favicon_url")? {
+                Some(s) => Some(Url::parse(&s)?),
+                None => None,
+            },
+            preview_image_url: match row.get::<_, Option<String>>("preview_image_url")? {
+                Some(s) => Some(Url::parse(&s)?),
+                None => None,
+            },
         })
     }
 }
@@ -1417,7 +1532,9 @@ const RAW_BOOKMARK_SQL: &str = "
         b.syncStatus AS _syncStatus,
         -- the columns below don't appear in the desktop query
         b.fk,
-        b.syncChangeCounter
+        b.syncChangeCounter,
+        h.favicon_url,
+        h.preview_image_url
     FROM moz_bookmarks b
     LEFT JOIN moz_bookmarks p ON p.id = b.parent
     LEFT JOIN moz_places h ON h.id = b.fk
@@ -2302,6 +2419,118 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_insert_bookmark_tree() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "title": "existing",
+                        "url": "https://www.example.com/"
+                    },
+                ]
+            }),
+        );
+
+        let tree: BookmarkTreeNode = serde_json::from_value(json!({
+            "title": "Imported",
+            "children": [
+                {
+                    "title": "bookmark 1",
+                    "url": "https://www.example1.com/"
+                },
+                {
+                    "type": BookmarkType::Separator as u8
+                },
+                {
+                    "title": "subfolder",
+                    "children": [
+                        {
+                            "title": "bookmark 2",
+                            "url": "https://www.example2.com/"
+                        },
+                    ]
+                },
+            ]
+        }))
+        .expect("should be valid");
+        let root_guid = insert_bookmark_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            BookmarkPosition::Specific(0),
+            &tree,
+        )?;
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "guid": &root_guid,
+                        "title": "Imported",
+                        "children": [
+                            {
+                                "title": "bookmark 1",
+                                "url": "https://www.example1.com/"
+                            },
+                            {
+                                "type": BookmarkType::Separator as u8
+                            },
+                            {
+                                "title": "subfolder",
+                                "children": [
+                                    {
+                                        "title": "bookmark 2",
+                                        "url": "https://www.example2.com/"
+                                    },
+                                ]
+                            },
+                        ]
+                    },
+                    {
+                        "guid": "bookmarkAAAA",
+                        "title": "existing",
+                        "url": "https://www.example.com/"
+                    },
+                ]
+            }),
+        );
+
+        // If any node can't be inserted, nothing is.
+        let tree: BookmarkTreeNode = serde_json::from_value(json!({
+            "title": "Duplicate",
+            "children": [
+                {
+                    "title": "bookmark 3",
+                    "url": "https://www.example3.com/"
+                },
+                {
+                    "guid": "bookmarkAAAA",
+                    "url": "https://www.example.com/"
+                },
+            ]
+        }))
+        .expect("should be valid");
+        insert_bookmark_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            BookmarkPosition::Append,
+            &tree,
+        )
+        .expect_err("should fail to insert a duplicate GUID");
+        let unfiled =
+            get_raw_bookmark(&conn, &BookmarkRootGuid::Unfiled.into())?.expect("should exist");
+        assert_eq!(unfiled.child_count, 2);
+        assert!(fetch_page_info(&conn, &Url::parse("https://www.example3.com/")?)?.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_insert_tree_and_fetch_level() -> Result<()> {
         let _ = env_logger::try_init();
@@ -3,9 +3,10 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use super::{
-    BookmarkPosition, BookmarkRootGuid, BookmarkTreeNode, InsertableBookmark, InsertableFolder,
-    InsertableItem, InsertableSeparator, PublicNode, RawBookmark, UpdatableBookmark,
-    UpdatableFolder, UpdatableItem, UpdatableSeparator, UpdateTreeLocation,
+    BookmarkNode, BookmarkPosition, BookmarkRootGuid, BookmarkTreeNode, FolderNode,
+    InsertableBookmark, InsertableFolder, InsertableItem, InsertableSeparator, PublicNode,
+    RawBookmark, SeparatorNode, UpdatableBookmark, UpdatableFolder, UpdatableItem,
+    UpdatableSeparator, UpdateTreeLocation,
 };
 
 use crate::error::{InvalidPlaceInfo, Result};
@@ -87,6 +88,8 @@ impl From<PublicNode> for msg_types::BookmarkNode {
                     .collect()
             }),
             have_child_nodes,
+            favicon_url: n.favicon_url.map(url::Url::into_string),
+            preview_image_url: n.preview_image_url.map(url::Url::into_string),
         }
     }
 }
@@ -105,6 +108,8 @@ impl From<RawBookmark> for PublicNode {
             title: rb.title,
             child_guids: None,
             child_nodes: None,
+            favicon_url: rb.favicon_url,
+            preview_image_url: rb.preview_image_url,
         }
     }
 }
@@ -132,18 +137,66 @@ impl msg_types::BookmarkNode {
         BookmarkType::from_u8(value as u8).expect("Invalid node_type")
     }
 
-    /// Convert the protobuf bookmark into information for insertion.
-    pub fn into_insertable(self) -> Result<InsertableItem> {
-        let ty = self.get_node_type();
-
+    // Where to insert the bookmark: at the end of the unfiled bookmarks,
+    // unless the parent and position are given.
+    fn insert_location(&self) -> (SyncGuid, BookmarkPosition) {
         let parent_guid = self
             .parent_guid
+            .clone()
             .map(SyncGuid::from)
             .unwrap_or_else(|| BookmarkRootGuid::Unfiled.into());
 
         let position = self
             .position
             .map_or(BookmarkPosition::Append, BookmarkPosition::Specific);
+        (parent_guid, position)
+    }
+
+    /// Convert the protobuf bookmark, and its `child_nodes`, into a tree to
+    /// insert with `insert_bookmark_tree`, and the parent and position to
+    /// insert it at. As with `into_insertable`, GUIDs and timestamps are
+    /// ignored, and the `parent_guid` and `position` of children are ignored,
+    /// as they're inserted in order.
+    pub fn into_insertable_tree(self) -> Result<(SyncGuid, BookmarkPosition, BookmarkTreeNode)> {
+        let (parent_guid, position) = self.insert_location();
+        Ok((parent_guid, position, self.into_tree_node()?))
+    }
+
+    fn into_tree_node(self) -> Result<BookmarkTreeNode> {
+        Ok(match self.get_node_type() {
+            BookmarkType::Bookmark => BookmarkNode {
+                guid: None,
+                date_added: None,
+                last_modified: None,
+                title: self.title,
+                url: Url::parse(&self.url.unwrap_or_default())?,
+            }
+            .into(),
+            BookmarkType::Separator => SeparatorNode {
+                guid: None,
+                date_added: None,
+                last_modified: None,
+            }
+            .into(),
+            BookmarkType::Folder => FolderNode {
+                guid: None,
+                date_added: None,
+                last_modified: None,
+                title: self.title,
+                children: self
+                    .child_nodes
+                    .into_iter()
+                    .map(msg_types::BookmarkNode::into_tree_node)
+                    .collect::<Result<_>>()?,
+            }
+            .into(),
+        })
+    }
+
+    /// Convert the protobuf bookmark into information for insertion.
+    pub fn into_insertable(self) -> Result<InsertableItem> {
+        let ty = self.get_node_type();
+        let (parent_guid, position) = self.insert_location();
 
         Ok(match ty {
             BookmarkType::Bookmark => InsertableItem::Bookmark(InsertableBookmark {
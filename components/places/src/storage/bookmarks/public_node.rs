@@ -5,6 +5,7 @@
 use super::super::bookmarks::FetchDepth;
 use super::*;
 use crate::msg_types::BookmarkNode as ProtoBookmark;
+use crate::storage::get_page_image_info;
 
 /// This type basically exists to become a msg_types::BookmarkNode, but is
 /// slightly less of a pain to deal with in rust.
@@ -21,6 +22,8 @@ pub struct PublicNode {
     pub title: Option<String>,
     pub child_guids: Option<Vec<SyncGuid>>,
     pub child_nodes: Option<Vec<PublicNode>>,
+    pub favicon_url: Option<Url>,
+    pub preview_image_url: Option<Url>,
 }
 
 impl Default for PublicNode {
@@ -38,6 +41,8 @@ impl Default for PublicNode {
             title: None,
             child_guids: None,
             child_nodes: None,
+            favicon_url: None,
+            preview_image_url: None,
         }
     }
 }
@@ -51,6 +56,8 @@ impl PartialEq for PublicNode {
             && self.url == other.url
             && self.child_guids == other.child_guids
             && self.child_nodes == other.child_nodes
+            && self.favicon_url == other.favicon_url
+            && self.preview_image_url == other.preview_image_url
     }
 }
 
@@ -74,6 +81,8 @@ pub fn fetch_bookmarks_by_url(db: &PlacesDb, url: &Url) -> Result<Vec<PublicNode
                 title: rb.title,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: rb.favicon_url,
+                preview_image_url: rb.preview_image_url,
             }
         })
         .collect::<Vec<_>>();
@@ -158,6 +167,12 @@ pub fn fetch_public_tree_with_depth(
     // `item_guid` by `PublicNode::from` automatically, however we
     // still need to fill in it's own `parent_guid` and `position`.
     let mut proto = PublicNode::from(tree);
+    if let Some(url) = &proto.url {
+        if let Some(images) = get_page_image_info(db, url)? {
+            proto.favicon_url = images.favicon_url;
+            proto.preview_image_url = images.preview_image_url;
+        }
+    }
 
     if item_guid != BookmarkRootGuid::Root {
         proto.parent_guid = parent_guid;
@@ -187,6 +202,14 @@ pub fn search_bookmarks(db: &PlacesDb, search: &str, limit: u32) -> Result<Vec<P
                     .transpose()?,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: row
+                    .get::<_, Option<String>>("favicon_url")?
+                    .map(|href| url::Url::parse(&href))
+                    .transpose()?,
+                preview_image_url: row
+                    .get::<_, Option<String>>("preview_image_url")?
+                    .map(|href| url::Url::parse(&href))
+                    .transpose()?,
             })
         },
     )?)
@@ -202,7 +225,9 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
             b.dateAdded,
             b.lastModified,
             NULLIF(b.title, '') AS title,
-            h.url AS url
+            h.url AS url,
+            h.favicon_url,
+            h.preview_image_url
         FROM moz_bookmarks b
         JOIN moz_bookmarks p ON p.id = b.parent
         JOIN moz_places h ON h.id = b.fk
@@ -228,6 +253,14 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
                     .transpose()?,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: row
+                    .get::<_, Option<String>>("favicon_url")?
+                    .map(|href| url::Url::parse(&href))
+                    .transpose()?,
+                preview_image_url: row
+                    .get::<_, Option<String>>("preview_image_url")?
+                    .map(|href| url::Url::parse(&href))
+                    .transpose()?,
             })
         })?,
     )
@@ -243,7 +276,9 @@ lazy_static::lazy_static! {
             b.lastModified,
             -- Note we return null for titles with an empty string.
             NULLIF(b.title, '') AS title,
-            h.url AS url
+            h.url AS url,
+            h.favicon_url,
+            h.preview_image_url
         FROM moz_bookmarks b
         JOIN moz_bookmarks p ON p.id = b.parent
         JOIN moz_places h ON h.id = b.fk
@@ -323,6 +358,8 @@ mod test {
                 position: 1,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: None,
+                preview_image_url: None,
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -339,6 +376,8 @@ mod test {
                 position: 3,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: None,
+                preview_image_url: None,
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -613,6 +652,8 @@ mod test {
                 position: 5,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: None,
+                preview_image_url: None,
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -629,6 +670,8 @@ mod test {
                 position: 3,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: None,
+                preview_image_url: None,
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -645,6 +688,8 @@ mod test {
                 position: 2,
                 child_guids: None,
                 child_nodes: None,
+                favicon_url: None,
+                preview_image_url: None,
                 // Ignored by our PartialEq
                 date_added: Timestamp(0),
                 last_modified: Timestamp(0),
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{fetch_page_info, new_page_info, PageInfo, RowId};
+use super::{fetch_page_info, new_page_info, normalize_domain, PageInfo, RowId};
 use crate::db::PlacesDb;
 use crate::error::Result;
 use crate::frecency;
@@ -18,6 +18,8 @@ use rusqlite::types::ToSql;
 use rusqlite::Result as RusqliteResult;
 use rusqlite::{Row, NO_PARAMS};
 use sql_support::{self, ConnExt};
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::BTreeMap;
 use sync_guid::Guid as SyncGuid;
 use url::Url;
 
@@ -51,6 +53,76 @@ pub fn apply_observation_direct(
         Some(info) => info.page,
         None => new_page_info(db, &url, None)?,
     };
+    let (visit_row_id, update_frec) = apply_observation_to_page(db, &mut page_info, &visit_ob)?;
+    // This needs to happen after the other updates.
+    if update_frec {
+        update_frecency(
+            &db,
+            page_info.row_id,
+            Some(visit_ob.get_redirect_frecency_boost()),
+        )?;
+    }
+    delete_pending_temp_tables(db)?;
+    Ok(visit_row_id)
+}
+
+/// Applies a batch of observations, such as when restoring session history,
+/// in a single transaction. Each URL is only looked up (or inserted) once, and
+/// the frecency of each page is only recalculated once, after all of its
+/// visits have been added. If any observation fails, none are applied.
+/// Returns the RowId of the new visit for each observation, as for
+/// `apply_observation`.
+pub fn apply_observations(
+    db: &PlacesDb,
+    visit_obs: Vec<VisitObservation>,
+) -> Result<Vec<Option<RowId>>> {
+    let scope = db.begin_interrupt_scope();
+    let tx = db.begin_transaction()?;
+    let mut pages: HashMap<String, PageInfo> = HashMap::new();
+    // The pages whose frecency needs recalculating, each with the redirect
+    // boost of the last of its visits to change it.
+    let mut stale_frecencies: BTreeMap<RowId, bool> = BTreeMap::new();
+    let mut visit_row_ids = Vec::with_capacity(visit_obs.len());
+    for visit_ob in visit_obs {
+        scope.err_if_interrupted()?;
+        let url = Url::parse(&visit_ob.url)?;
+        if url.as_str().len() > super::URL_LENGTH_MAX {
+            visit_row_ids.push(None);
+            continue;
+        }
+        let page_info = match pages.entry(url.as_str().to_owned()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let page_info = match fetch_page_info(db, &url)? {
+                    Some(info) => info.page,
+                    None => new_page_info(db, &url, None)?,
+                };
+                entry.insert(page_info)
+            }
+        };
+        let (visit_row_id, update_frec) = apply_observation_to_page(db, page_info, &visit_ob)?;
+        if update_frec {
+            stale_frecencies.insert(page_info.row_id, visit_ob.get_redirect_frecency_boost());
+        }
+        visit_row_ids.push(visit_row_id);
+    }
+    for (row_id, redirect_boost) in stale_frecencies {
+        scope.err_if_interrupted()?;
+        update_frecency(db, row_id, Some(redirect_boost))?;
+    }
+    delete_pending_temp_tables(db)?;
+    tx.commit()?;
+    Ok(visit_row_ids)
+}
+
+// Updates `page_info` and the page in the database for `visit_ob`, without
+// updating its frecency. Returns the RowId of the new visit, if any, and
+// whether the frecency now needs updating.
+fn apply_observation_to_page(
+    db: &PlacesDb,
+    page_info: &mut PageInfo,
+    visit_ob: &VisitObservation,
+) -> Result<(Option<RowId>, bool)> {
     let mut update_change_counter = false;
     let mut update_frec = false;
     let mut updates: Vec<(&str, &str, &dyn ToSql)> = Vec::new();
@@ -111,16 +183,7 @@ pub fn apply_observation_direct(
         );
         db.execute_named_cached(&sql, &params)?;
     }
-    // This needs to happen after the other updates.
-    if update_frec {
-        update_frecency(
-            &db,
-            page_info.row_id,
-            Some(visit_ob.get_redirect_frecency_boost()),
-        )?;
-    }
-    delete_pending_temp_tables(db)?;
-    Ok(visit_row_id)
+    Ok((visit_row_id, update_frec))
 }
 
 pub fn update_frecency(db: &PlacesDb, id: RowId, redirect_boost: Option<bool>) -> Result<()> {
@@ -138,6 +201,10 @@ pub fn update_frecency(db: &PlacesDb, id: RowId, redirect_boost: Option<bool>) -
         WHERE id = :page_id",
         &[(":frecency", &score), (":page_id", &id.0)],
     )?;
+    db.execute_named_cached(
+        "DELETE FROM moz_places_stale_frecencies WHERE place_id = :page_id",
+        &[(":page_id", &id)],
+    )?;
 
     Ok(())
 }
@@ -298,6 +365,11 @@ fn delete_all_visits_for_page(db: &PlacesDb, page_id: RowId) -> Result<()> {
          WHERE place_id = :page_id",
         &[(":page_id", &page_id)],
     )?;
+    db.execute_named_cached(
+        "DELETE FROM moz_places_metadata
+         WHERE place_id = :page_id",
+        &[(":page_id", &page_id)],
+    )?;
     Ok(())
 }
 
@@ -331,6 +403,47 @@ pub fn delete_visits_for(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     result
 }
 
+/// Deletes the history, metadata and images of every page on `domain` or one of
+/// its subdomains, creating tombstones if necessary, to "forget" a site.
+/// `domain` should be a base domain (eTLD+1), like `example.com`; we don't
+/// know the public suffixes, so passing `co.uk` forgets every site under it.
+/// Bookmarked pages are kept, without their visits.
+pub fn delete_visits_for_base_domain(db: &PlacesDb, domain: &str) -> Result<()> {
+    let domain = normalize_domain(domain)?;
+    let subdomain_suffix = format!(".{}", domain);
+    let tx = db.begin_transaction()?;
+    // moz_origins hosts include the port, so find the candidates in SQL, and
+    // check their hosts properly here.
+    let pages = db.query_rows_and_then_named(
+        "SELECT h.guid, h.url
+         FROM moz_places h
+         JOIN moz_origins o ON o.id = h.origin_id
+         WHERE INSTR(o.host, :domain) > 0",
+        &[(":domain", &domain)],
+        |row| -> rusqlite::Result<_> { Ok((row.get::<_, SyncGuid>(0)?, row.get::<_, String>(1)?)) },
+    )?;
+    for (guid, url) in pages {
+        let on_domain = Url::parse(&url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .map_or(false, |host| {
+                host == domain || host.ends_with(&subdomain_suffix)
+            });
+        if !on_domain {
+            continue;
+        }
+        // Bookmarked pages stay around, so their images need to be cleared.
+        db.execute_named_cached(
+            "UPDATE moz_places SET favicon_url = NULL, preview_image_url = NULL
+             WHERE guid = :guid",
+            &[(":guid", &guid)],
+        )?;
+        delete_visits_for_in_tx(db, &guid)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
 /// Delete all visits in a date range.
 pub fn delete_visits_between(db: &PlacesDb, start: Timestamp, end: Timestamp) -> Result<()> {
     let tx = db.begin_transaction()?;
@@ -373,6 +486,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
     db.execute_all(&[
         "DELETE FROM moz_places WHERE foreign_count == 0",
         "DELETE FROM moz_historyvisits",
+        "DELETE FROM moz_places_metadata",
         "DELETE FROM moz_places_tombstones",
         "DELETE FROM moz_inputhistory AS i WHERE NOT EXISTS(
              SELECT 1 FROM moz_places h
@@ -537,6 +651,14 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
         db.conn().execute(&sql, NO_PARAMS)?;
     }
 
+    // Metadata for the same time goes too, even for visits which weren't
+    // recorded.
+    db.execute_named_cached(
+        "DELETE FROM moz_places_metadata
+         WHERE created_at <= :end AND updated_at >= :start",
+        &[(":start", &start), (":end", &end)],
+    )?;
+
     // Find out which pages have been possibly orphaned and clean them up.
     sql_support::each_chunk_mapped(
         &visits,
@@ -1185,7 +1307,8 @@ pub fn get_visit_infos(
 ) -> Result<HistoryVisitInfos> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                h.favicon_url, h.preview_image_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
@@ -1230,7 +1353,8 @@ pub fn get_visit_page(
 ) -> Result<HistoryVisitInfos> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                h.favicon_url, h.preview_image_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
@@ -1258,7 +1382,8 @@ pub fn get_visit_page_with_bound(
 ) -> Result<HistoryVisitInfosWithBound> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                h.favicon_url, h.preview_image_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
@@ -1526,6 +1651,73 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_apply_observations() -> Result<()> {
+        let _ = env_logger::try_init();
+        let one_by_one = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let batched = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let url1 = Url::parse("https://www.example.com/1").expect("it's a valid url");
+        let url2 = Url::parse("https://www.example.com/2").expect("it's a valid url");
+        let now = SystemTime::now();
+        let observations = || {
+            vec![
+                VisitObservation::new(url1.clone())
+                    .with_visit_type(VisitTransition::Typed)
+                    .with_at(Some((now - Duration::new(120, 0)).into())),
+                VisitObservation::new(url2.clone())
+                    .with_title("Page 2".to_string())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Some((now - Duration::new(60, 0)).into())),
+                VisitObservation::new(url1.clone())
+                    .with_title("Page 1".to_string())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Some(now.into())),
+            ]
+        };
+        for observation in observations() {
+            apply_observation(&one_by_one, observation)?;
+        }
+        let row_ids = apply_observations(&batched, observations())?;
+        assert_eq!(row_ids.len(), 3);
+        assert!(row_ids.iter().all(Option::is_some));
+
+        for url in &[&url1, &url2] {
+            let expected = fetch_page_info(&one_by_one, url)?.expect("should have the page");
+            let actual = fetch_page_info(&batched, url)?.expect("should have the page");
+            assert_eq!(actual.page.title, expected.page.title);
+            assert_eq!(actual.page.typed, expected.page.typed);
+            assert_eq!(actual.page.frecency, expected.page.frecency);
+            assert_eq!(
+                actual.page.visit_count_local,
+                expected.page.visit_count_local
+            );
+            assert_eq!(
+                actual.page.last_visit_date_local,
+                expected.page.last_visit_date_local
+            );
+            assert_eq!(
+                actual.page.sync_change_counter,
+                expected.page.sync_change_counter
+            );
+        }
+
+        // A bad observation means none of the batch is applied.
+        let err = apply_observations(
+            &batched,
+            vec![
+                VisitObservation::new(url1.clone()).with_visit_type(VisitTransition::Link),
+                VisitObservation {
+                    url: "not a url".into(),
+                    ..VisitObservation::new(url2)
+                },
+            ],
+        );
+        assert!(err.is_err());
+        let page = fetch_page_info(&batched, &url1)?.expect("should have the page");
+        assert_eq!(page.page.visit_count_local, 2);
+        Ok(())
+    }
+
     #[test]
     fn test_get_visited() -> Result<()> {
         let _ = env_logger::try_init();
@@ -2072,6 +2264,80 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_delete_visits_for_base_domain() -> Result<()> {
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+        use crate::storage::{get_page_image_info, set_page_image_info, PageImageInfo};
+
+        let _ = env_logger::try_init();
+        let db = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let urls = [
+            "https://example.com/",
+            "https://www.example.com/a",
+            "http://sub.example.com:8080/b",
+            "https://notexample.com/",
+            "https://example.org/example.com",
+        ];
+        for url in &urls {
+            apply_observation(
+                &db,
+                VisitObservation::new(Url::parse(url)?).with_visit_type(VisitTransition::Link),
+            )?;
+        }
+        // A synced page gets a tombstone.
+        db.execute_named_cached(
+            &format!(
+                "UPDATE moz_places SET sync_status = {}
+                 WHERE url = 'https://www.example.com/a'",
+                (SyncStatus::Normal as u8)
+            ),
+            &[],
+        )?;
+        // And a bookmarked page is kept, without its visits or images.
+        let bookmarked = Url::parse(urls[2])?;
+        insert_bookmark(
+            &db,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: bookmarked.clone(),
+                title: None,
+            }
+            .into(),
+        )?;
+        set_page_image_info(
+            &db,
+            &bookmarked,
+            &PageImageInfo {
+                favicon_url: Some(Url::parse("http://sub.example.com:8080/favicon.ico")?),
+                preview_image_url: None,
+            },
+        )?;
+
+        delete_visits_for_base_domain(&db, " Example.COM ")?;
+
+        assert_eq!(get_tombstone_count(&db), 1);
+        assert!(url_to_guid(&db, &Url::parse(urls[0])?)?.is_none());
+        assert!(url_to_guid(&db, &Url::parse(urls[1])?)?.is_none());
+        let page = fetch_page_info(&db, &bookmarked)?.expect("bookmarked page should exist");
+        assert_eq!(page.page.visit_count_local, 0);
+        assert_eq!(
+            get_page_image_info(&db, &bookmarked)?,
+            Some(PageImageInfo::default())
+        );
+        // Other sites which only look similar are untouched.
+        assert!(url_to_guid(&db, &Url::parse(urls[3])?)?.is_some());
+        assert!(url_to_guid(&db, &Url::parse(urls[4])?)?.is_some());
+
+        assert!(delete_visits_for_base_domain(&db, "").is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_sync_reset() -> Result<()> {
         let _ = env_logger::try_init();
@@ -0,0 +1,326 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Metadata about visits to pages: how long they were looked at, and the
+//! search term and referring origin that led to them. This is used for
+//! features like "recently searched" and "recap", and isn't synced.
+//!
+//! Observations for the same page, with the same search term and referrer,
+//! are added together for `METADATA_ENTRY_WINDOW`, so that going back and
+//! forth between a page and its search results doesn't record a new entry each
+//! time.
+
+use super::{fetch_page_info, new_page_info};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::types::Timestamp;
+use rusqlite::Row;
+use serde_derive::*;
+use sql_support::ConnExt;
+use std::time::Duration;
+use url::Url;
+
+/// How long observations are added to an existing entry for.
+pub const METADATA_ENTRY_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+const SELECT_METADATA_SQL: &str = "
+    SELECT h.url, h.title, m.created_at, m.updated_at, m.total_view_time,
+           m.search_term, m.referrer_origin
+    FROM moz_places_metadata m
+    JOIN moz_places h ON h.id = m.place_id";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryMetadata {
+    pub url: Url,
+    pub title: Option<String>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    /// In milliseconds.
+    pub total_view_time: i64,
+    pub search_term: Option<String>,
+    /// The origin of the page which linked to this one, like
+    /// `https://www.example.com`.
+    pub referrer_origin: Option<String>,
+}
+
+impl HistoryMetadata {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            title: row.get("title")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+            total_view_time: row.get("total_view_time")?,
+            search_term: row.get("search_term")?,
+            referrer_origin: row.get("referrer_origin")?,
+        })
+    }
+}
+
+/// What the app knows about a visit to `url`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryMetadataObservation {
+    pub url: Url,
+    #[serde(default)]
+    pub search_term: Option<String>,
+    /// Only the origin of the referrer is stored.
+    #[serde(default)]
+    pub referrer_url: Option<Url>,
+    /// The time spent on the page since the last observation, in
+    /// milliseconds.
+    #[serde(default)]
+    pub view_time: Option<i64>,
+}
+
+/// Records `observation`, adding it to the latest entry for the page with the
+/// same search term and referrer if there's a recent one, or creating a new
+/// entry otherwise. The page is added to `moz_places` if it isn't there yet.
+pub fn note_history_metadata_observation(
+    db: &PlacesDb,
+    observation: &HistoryMetadataObservation,
+) -> Result<()> {
+    let now = Timestamp::now();
+    let search_term = observation
+        .search_term
+        .as_ref()
+        .map(|term| term.trim())
+        .filter(|term| !term.is_empty());
+    let referrer_origin = observation
+        .referrer_url
+        .as_ref()
+        .map(Url::origin)
+        .filter(url::Origin::is_tuple)
+        .map(|origin| origin.ascii_serialization());
+    let view_time = observation.view_time.unwrap_or(0).max(0);
+    let window_start = Timestamp(
+        now.0
+            .saturating_sub(METADATA_ENTRY_WINDOW.as_millis() as u64),
+    );
+
+    let tx = db.begin_transaction()?;
+    let place_id = match fetch_page_info(db, &observation.url)? {
+        Some(info) => info.page.row_id,
+        None => new_page_info(db, &observation.url, None)?.row_id,
+    };
+    let updated = db.execute_named_cached(
+        "UPDATE moz_places_metadata SET
+             updated_at = :now,
+             total_view_time = total_view_time + :view_time
+         WHERE id = (SELECT id FROM moz_places_metadata
+                     WHERE place_id = :place_id
+                       AND search_term IS :search_term
+                       AND referrer_origin IS :referrer_origin
+                       AND updated_at >= :window_start
+                     ORDER BY updated_at DESC
+                     LIMIT 1)",
+        &[
+            (":now", &now),
+            (":view_time", &view_time),
+            (":place_id", &place_id),
+            (":search_term", &search_term),
+            (":referrer_origin", &referrer_origin),
+            (":window_start", &window_start),
+        ],
+    )?;
+    if updated == 0 {
+        db.execute_named_cached(
+            "INSERT INTO moz_places_metadata
+                 (place_id, created_at, updated_at, total_view_time, search_term,
+                  referrer_origin)
+             VALUES
+                 (:place_id, :now, :now, :view_time, :search_term, :referrer_origin)",
+            &[
+                (":place_id", &place_id),
+                (":now", &now),
+                (":view_time", &view_time),
+                (":search_term", &search_term),
+                (":referrer_origin", &referrer_origin),
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Returns the most recently updated entry for `url`, if it has any.
+pub fn get_latest_history_metadata_for_url(
+    db: &PlacesDb,
+    url: &Url,
+) -> Result<Option<HistoryMetadata>> {
+    db.try_query_row(
+        &format!(
+            "{}
+             WHERE h.url_hash = hash(:url) AND h.url = :url
+             ORDER BY m.updated_at DESC
+             LIMIT 1",
+            SELECT_METADATA_SQL
+        ),
+        &[(":url", &url.as_str())],
+        HistoryMetadata::from_row,
+        true,
+    )
+}
+
+/// Returns the entries updated between `start` and `end`, most recently
+/// updated first.
+pub fn get_history_metadata_between(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+) -> Result<Vec<HistoryMetadata>> {
+    db.query_rows_and_then_named_cached(
+        &format!(
+            "{}
+             WHERE m.updated_at BETWEEN :start AND :end
+             ORDER BY m.updated_at DESC",
+            SELECT_METADATA_SQL
+        ),
+        &[(":start", &start), (":end", &end)],
+        HistoryMetadata::from_row,
+    )
+}
+
+/// Returns up to `limit` entries whose search term, URL or title contains
+/// `query` (ignoring case), most recently updated first.
+pub fn query_history_metadata(
+    db: &PlacesDb,
+    query: &str,
+    limit: u32,
+) -> Result<Vec<HistoryMetadata>> {
+    db.query_rows_and_then_named_cached(
+        &format!(
+            "{}
+             WHERE INSTR(LOWER(m.search_term), LOWER(:query)) > 0
+                OR INSTR(LOWER(h.url), LOWER(:query)) > 0
+                OR INSTR(LOWER(h.title), LOWER(:query)) > 0
+             ORDER BY m.updated_at DESC
+             LIMIT :limit",
+            SELECT_METADATA_SQL
+        ),
+        &[(":query", &query), (":limit", &limit)],
+        HistoryMetadata::from_row,
+    )
+}
+
+/// Deletes the entries which haven't been updated since `older_than`.
+pub fn delete_history_metadata_older_than(db: &PlacesDb, older_than: Timestamp) -> Result<()> {
+    db.execute_named_cached(
+        "DELETE FROM moz_places_metadata WHERE updated_at < :older_than",
+        &[(":older_than", &older_than)],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::{apply_observation, delete_visits_between, wipe_local};
+    use crate::types::VisitTransition;
+
+    fn observe(
+        db: &PlacesDb,
+        url: &str,
+        search_term: Option<&str>,
+        referrer_url: Option<&str>,
+        view_time: i64,
+    ) {
+        note_history_metadata_observation(
+            db,
+            &HistoryMetadataObservation {
+                url: Url::parse(url).unwrap(),
+                search_term: search_term.map(Into::into),
+                referrer_url: referrer_url.map(|url| Url::parse(url).unwrap()),
+                view_time: Some(view_time),
+            },
+        )
+        .expect("should note observation");
+    }
+
+    #[test]
+    fn test_history_metadata() -> Result<()> {
+        let conn = new_mem_connection();
+        let start = Timestamp::now();
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://www.example.com/a")?)
+                .with_title("Example A".to_string())
+                .with_visit_type(VisitTransition::Link),
+        )?;
+        observe(
+            &conn,
+            "https://www.example.com/a",
+            Some("cute cats"),
+            Some("https://www.search.com/search?q=cute+cats"),
+            1000,
+        );
+        // The same search term and referrer origin add to the entry.
+        observe(
+            &conn,
+            "https://www.example.com/a",
+            Some(" cute cats "),
+            Some("https://www.search.com/other"),
+            500,
+        );
+        // But a different search term starts a new one.
+        observe(&conn, "https://www.example.com/a", None, None, 200);
+        observe(&conn, "https://www.example.com/b", Some("dogs"), None, 100);
+
+        let all = get_history_metadata_between(&conn, start, Timestamp::now())?;
+        assert_eq!(all.len(), 3);
+        let cats = all
+            .iter()
+            .find(|m| m.search_term.as_deref() == Some("cute cats"))
+            .expect("should have an entry for the search");
+        assert_eq!(cats.url.as_str(), "https://www.example.com/a");
+        assert_eq!(cats.title.as_deref(), Some("Example A"));
+        assert_eq!(cats.total_view_time, 1500);
+        assert_eq!(
+            cats.referrer_origin.as_deref(),
+            Some("https://www.search.com")
+        );
+
+        let latest =
+            get_latest_history_metadata_for_url(&conn, &Url::parse("https://www.example.com/b")?)?
+                .expect("should have an entry for b");
+        assert_eq!(latest.search_term.as_deref(), Some("dogs"));
+        assert_eq!(latest.total_view_time, 100);
+        // The page didn't exist, so it was added.
+        assert!(fetch_page_info(&conn, &latest.url)?.is_some());
+        assert!(
+            get_latest_history_metadata_for_url(&conn, &Url::parse("https://example.org/")?)?
+                .is_none()
+        );
+
+        let cats = query_history_metadata(&conn, "CATS", 10)?;
+        assert_eq!(cats.len(), 1);
+        assert_eq!(cats[0].search_term.as_deref(), Some("cute cats"));
+        assert_eq!(query_history_metadata(&conn, "Example A", 10)?.len(), 2);
+        assert_eq!(query_history_metadata(&conn, "example.com", 1)?.len(), 1);
+        assert!(query_history_metadata(&conn, "birds", 10)?.is_empty());
+
+        assert!(get_history_metadata_between(&conn, Timestamp(0), Timestamp(1))?.is_empty());
+        delete_history_metadata_older_than(&conn, Timestamp(0))?;
+        assert_eq!(
+            get_history_metadata_between(&conn, start, Timestamp::now())?.len(),
+            3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_metadata_deleted_with_history() -> Result<()> {
+        let conn = new_mem_connection();
+        let start = Timestamp::now();
+        observe(&conn, "https://www.example.com/a", Some("cats"), None, 100);
+        delete_visits_between(&conn, start, Timestamp::now())?;
+        assert!(get_history_metadata_between(&conn, start, Timestamp::now())?.is_empty());
+
+        observe(&conn, "https://www.example.com/a", Some("cats"), None, 100);
+        wipe_local(&conn)?;
+        assert!(get_history_metadata_between(&conn, start, Timestamp::now())?.is_empty());
+        Ok(())
+    }
+}
@@ -7,7 +7,9 @@
 
 pub mod bookmarks;
 pub mod history;
+pub mod history_metadata;
 pub mod tags;
+pub mod top_sites;
 
 use crate::db::PlacesDb;
 use crate::error::{ErrorKind, InvalidPlaceInfo, Result};
@@ -19,6 +21,7 @@ use rusqlite::Row;
 use serde_derive::*;
 use sql_support::{self, ConnExt};
 use std::fmt;
+use std::time::{Duration, Instant};
 use sync_guid::Guid as SyncGuid;
 use url::Url;
 
@@ -28,6 +31,14 @@ pub const TITLE_LENGTH_MAX: usize = 4096;
 pub const TAG_LENGTH_MAX: usize = 100;
 // pub const DESCRIPTION_LENGTH_MAX: usize = 256;
 
+// Like desktop, frecencies are decayed daily, so that pages which haven't
+// been visited in a long time drop down the rankings even though their
+// stored frecency was never recalculated.
+const FRECENCY_DECAY_RATE: f64 = 0.975;
+const FRECENCY_DECAY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const FRECENCY_LAST_DECAY_META_KEY: &str = "frecency_last_decay";
+const MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK: usize = 100;
+
 // Typesafe way to manage RowIds. Does it make sense? A better way?
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Deserialize, Serialize, Default)]
 pub struct RowId(pub i64);
@@ -174,6 +185,77 @@ fn new_page_info(db: &PlacesDb, url: &Url, new_guid: Option<SyncGuid>) -> Result
     })
 }
 
+/// The images the app has found for a page, which are returned along with the
+/// page from history and bookmark queries.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PageImageInfo {
+    #[serde(default)]
+    pub favicon_url: Option<Url>,
+    #[serde(default)]
+    pub preview_image_url: Option<Url>,
+}
+
+impl PageImageInfo {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        let parse = |col| -> Result<Option<Url>> {
+            Ok(match row.get::<_, Option<String>>(col)? {
+                Some(s) => Some(Url::parse(&s)?),
+                None => None,
+            })
+        };
+        Ok(Self {
+            favicon_url: parse("favicon_url")?,
+            preview_image_url: parse("preview_image_url")?,
+        })
+    }
+}
+
+/// Sets the images for `url`, adding the page if it isn't there yet. URLs
+/// which aren't in `info` are left as they were.
+pub fn set_page_image_info(db: &PlacesDb, url: &Url, info: &PageImageInfo) -> Result<()> {
+    let image_urls = [&info.favicon_url, &info.preview_image_url];
+    if image_urls
+        .iter()
+        .filter_map(|url| url.as_ref())
+        .any(|url| url.as_str().len() > URL_LENGTH_MAX)
+    {
+        return Err(ErrorKind::InvalidPlaceInfo(InvalidPlaceInfo::UrlTooLong).into());
+    }
+    let tx = db.begin_transaction()?;
+    let place_id = match fetch_page_info(db, url)? {
+        Some(info) => info.page.row_id,
+        None => new_page_info(db, url, None)?.row_id,
+    };
+    db.execute_named_cached(
+        "UPDATE moz_places SET
+             favicon_url = IFNULL(:favicon_url, favicon_url),
+             preview_image_url = IFNULL(:preview_image_url, preview_image_url)
+         WHERE id = :place_id",
+        &[
+            (":favicon_url", &info.favicon_url.as_ref().map(Url::as_str)),
+            (
+                ":preview_image_url",
+                &info.preview_image_url.as_ref().map(Url::as_str),
+            ),
+            (":place_id", &place_id),
+        ],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Returns the images for `url`, or `None` if the page isn't known.
+pub fn get_page_image_info(db: &PlacesDb, url: &Url) -> Result<Option<PageImageInfo>> {
+    db.try_query_row(
+        "SELECT favicon_url, preview_image_url
+         FROM moz_places
+         WHERE url_hash = hash(:url) AND url = :url",
+        &[(":url", &url.as_str())],
+        PageImageInfo::from_row,
+        true,
+    )
+}
+
 impl HistoryVisitInfo {
     pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
         let visit_type = VisitTransition::from_primitive(row.get::<_, u8>("visit_type")?)
@@ -188,6 +270,8 @@ impl HistoryVisitInfo {
             timestamp: visit_date.0 as i64,
             visit_type: visit_type as i32,
             is_hidden: row.get("hidden")?,
+            favicon_url: row.get("favicon_url")?,
+            preview_image_url: row.get("preview_image_url")?,
         })
     }
 }
@@ -201,6 +285,106 @@ pub fn run_maintenance(conn: &PlacesDb) -> Result<()> {
     Ok(())
 }
 
+/// Returned by `run_frecency_maintenance`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct FrecencyMaintenanceResult {
+    pub num_recalculated: u32,
+    /// The number of stale frecencies left for the next run.
+    pub num_remaining: u32,
+    /// Whether the daily decay was applied.
+    pub decayed: bool,
+}
+
+/// Recalculates stale frecencies, most recently marked first, for up to
+/// `budget_ms`, and decays all frecencies if a day has passed since the last
+/// decay. Apps should call this when idle; anything left over is done on the
+/// next call.
+pub fn run_frecency_maintenance(
+    db: &PlacesDb,
+    budget_ms: u32,
+) -> Result<FrecencyMaintenanceResult> {
+    let start = Instant::now();
+    let budget = Duration::from_millis(budget_ms.into());
+    let scope = db.begin_interrupt_scope();
+    let mut result = FrecencyMaintenanceResult::default();
+
+    let tx = db.begin_transaction()?;
+    let now = Timestamp::now();
+    match get_meta::<Timestamp>(db, FRECENCY_LAST_DECAY_META_KEY)? {
+        Some(last_decay) => {
+            let periods =
+                now.0.saturating_sub(last_decay.0) / FRECENCY_DECAY_INTERVAL.as_millis() as u64;
+            if periods > 0 {
+                // After a year, everything has decayed to nothing anyway.
+                let factor = FRECENCY_DECAY_RATE.powi(periods.min(365) as i32);
+                db.execute_named(
+                    "UPDATE moz_places SET frecency = ROUND(frecency * :factor)
+                     WHERE frecency > 0",
+                    &[(":factor", &factor)],
+                )?;
+                put_meta(db, FRECENCY_LAST_DECAY_META_KEY, &now)?;
+                result.decayed = true;
+            }
+        }
+        // Nothing to decay yet, so just start counting.
+        None => put_meta(db, FRECENCY_LAST_DECAY_META_KEY, &now)?,
+    }
+
+    'chunks: while start.elapsed() < budget {
+        let place_ids = db.query_rows_and_then_named_cached(
+            &format!(
+                "SELECT place_id FROM moz_places_stale_frecencies
+                 ORDER BY stale_at DESC
+                 LIMIT {}",
+                MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK
+            ),
+            &[],
+            |row| row.get::<_, RowId>(0),
+        )?;
+        if place_ids.is_empty() {
+            break;
+        }
+        for place_id in place_ids {
+            if start.elapsed() >= budget {
+                break 'chunks;
+            }
+            scope.err_if_interrupted()?;
+            // Also removes it from the stale table.
+            history::update_frecency(db, place_id, None)?;
+            result.num_recalculated += 1;
+        }
+    }
+
+    delete_pending_temp_tables(db)?;
+    result.num_remaining = db.query_one("SELECT COUNT(*) FROM moz_places_stale_frecencies")?;
+    tx.commit()?;
+    Ok(result)
+}
+
+/// Lowercases and punycodes `domain`, failing with `InvalidDomain` if it
+/// isn't a valid host.
+pub(crate) fn normalize_domain(domain: &str) -> Result<String> {
+    let domain = match url::Host::parse(&domain.trim().to_ascii_lowercase()) {
+        Ok(host) => host.to_string(),
+        Err(_) => String::new(),
+    };
+    if domain.is_empty() {
+        return Err(InvalidPlaceInfo::InvalidDomain.into());
+    }
+    Ok(domain)
+}
+
+/// Flags the frecency of a page for recalculation by
+/// `run_frecency_maintenance`, or the next bookmark sync.
+pub(crate) fn mark_frecency_stale(db: &PlacesDb, place_id: RowId) -> Result<()> {
+    db.execute_named_cached(
+        "REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+         VALUES(:place_id, :now)",
+        &[(":place_id", &place_id), (":now", &Timestamp::now())],
+    )?;
+    Ok(())
+}
+
 pub(crate) fn put_meta(db: &PlacesDb, key: &str, value: &dyn ToSql) -> Result<()> {
     db.execute_named_cached(
         "REPLACE INTO moz_meta (key, value) VALUES (:key, :value)",
@@ -259,4 +443,141 @@ mod tests {
             .is_none());
         delete_meta(&conn, "foo").expect("delete non-existing should work");
     }
+
+    #[test]
+    fn test_page_image_info() -> Result<()> {
+        use crate::observation::VisitObservation;
+        use crate::storage::bookmarks::public_node::{fetch_bookmark, fetch_bookmarks_by_url};
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark, InsertableItem,
+        };
+        use crate::storage::history::{apply_observation, get_visit_page};
+        use crate::types::VisitTransitionSet;
+
+        let conn = new_mem_connection();
+        let url = Url::parse("https://www.example.com/")?;
+        let favicon_url = Url::parse("https://www.example.com/favicon.ico")?;
+        let preview_image_url = Url::parse("https://www.example.com/preview.png")?;
+        assert!(get_page_image_info(&conn, &url)?.is_none());
+
+        // Setting the images for an unknown page adds it.
+        set_page_image_info(
+            &conn,
+            &url,
+            &PageImageInfo {
+                favicon_url: Some(favicon_url.clone()),
+                preview_image_url: None,
+            },
+        )?;
+        assert!(fetch_page_info(&conn, &url)?.is_some());
+        // URLs which aren't given are left alone.
+        set_page_image_info(
+            &conn,
+            &url,
+            &PageImageInfo {
+                favicon_url: None,
+                preview_image_url: Some(preview_image_url.clone()),
+            },
+        )?;
+        let expected = PageImageInfo {
+            favicon_url: Some(favicon_url.clone()),
+            preview_image_url: Some(preview_image_url.clone()),
+        };
+        assert_eq!(get_page_image_info(&conn, &url)?, Some(expected));
+
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Link),
+        )?;
+        let visits = get_visit_page(&conn, 0, 10, VisitTransitionSet::empty())?.infos;
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits[0].favicon_url.as_deref(), Some(favicon_url.as_str()));
+        assert_eq!(
+            visits[0].preview_image_url.as_deref(),
+            Some(preview_image_url.as_str())
+        );
+
+        let guid = insert_bookmark(
+            &conn,
+            &InsertableItem::Bookmark(InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: None,
+            }),
+        )?;
+        let bookmark = fetch_bookmark(&conn, &guid, false)?.expect("should exist");
+        assert_eq!(bookmark.favicon_url, Some(favicon_url.clone()));
+        assert_eq!(bookmark.preview_image_url, Some(preview_image_url));
+        let by_url = fetch_bookmarks_by_url(&conn, &url)?;
+        assert_eq!(by_url[0].favicon_url, Some(favicon_url));
+        Ok(())
+    }
+
+    #[test]
+    fn test_frecency_maintenance() -> Result<()> {
+        use crate::storage::bookmarks::{
+            delete_bookmark, insert_bookmark, BookmarkPosition, BookmarkRootGuid,
+            InsertableBookmark, InsertableItem,
+        };
+        use crate::storage::history::frecency_stale_at;
+
+        let conn = new_mem_connection();
+        let url = Url::parse("https://www.example.com/")?;
+        let frecency = || -> Result<i32> {
+            Ok(fetch_page_info(&conn, &url)?
+                .expect("page should exist")
+                .page
+                .frecency)
+        };
+
+        let guid = insert_bookmark(
+            &conn,
+            &InsertableItem::Bookmark(InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: None,
+            }),
+        )?;
+        assert!(frecency_stale_at(&conn, &url)?.is_some());
+        assert_eq!(frecency()?, -1);
+
+        // Nothing is recalculated without a budget, and there's nothing to
+        // decay the first time.
+        let result = run_frecency_maintenance(&conn, 0)?;
+        assert_eq!(
+            result,
+            FrecencyMaintenanceResult {
+                num_recalculated: 0,
+                num_remaining: 1,
+                decayed: false,
+            }
+        );
+
+        let result = run_frecency_maintenance(&conn, 10_000)?;
+        assert_eq!(result.num_recalculated, 1);
+        assert_eq!(result.num_remaining, 0);
+        assert!(!result.decayed);
+        assert!(frecency_stale_at(&conn, &url)?.is_none());
+        assert_eq!(frecency()?, 140);
+
+        let two_days_ago = Timestamp(Timestamp::now().0 - 2 * 24 * 60 * 60 * 1000);
+        put_meta(&conn, FRECENCY_LAST_DECAY_META_KEY, &two_days_ago)?;
+        assert!(run_frecency_maintenance(&conn, 10_000)?.decayed);
+        assert_eq!(frecency()?, 133);
+        assert!(!run_frecency_maintenance(&conn, 10_000)?.decayed);
+
+        delete_bookmark(&conn, &guid)?;
+        assert!(frecency_stale_at(&conn, &url)?.is_some());
+        run_frecency_maintenance(&conn, 10_000)?;
+        assert_eq!(frecency()?, 0);
+        Ok(())
+    }
 }
@@ -69,9 +69,14 @@ pub fn validate_tag(tag: &str) -> ValidatedTag<'_> {
 ///
 /// There is no success return value.
 pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
-    let tag = validate_tag(&tag).ensure_valid()?;
     let tx = db.begin_transaction()?;
+    tag_url_in_tx(db, url, tag)?;
+    tx.commit()?;
+    Ok(())
+}
 
+pub(crate) fn tag_url_in_tx(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
+    let tag = validate_tag(&tag).ensure_valid()?;
     // This function will not create a new place.
     // Fetch the place id, so we (a) avoid creating a new tag when we aren't
     // going to reference it and (b) to avoid a sub-query.
@@ -91,7 +96,6 @@ pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
          VALUES((SELECT id FROM moz_tags WHERE tag = :tag), :place_id)",
         &[(":tag", &tag), (":place_id", &place_id)],
     )?;
-    tx.commit()?;
     Ok(())
 }
 
@@ -158,6 +162,7 @@ pub fn remove_all_tags_from_url(db: &PlacesDb, url: &Url) -> Result<()> {
 ///
 /// There is no success return value.
 pub fn remove_tag(db: &PlacesDb, tag: &str) -> Result<()> {
+    let tag = validate_tag(&tag).ensure_valid()?;
     db.execute_named_cached(
         "DELETE FROM moz_tags
          WHERE tag = :tag",
@@ -232,7 +237,11 @@ pub fn get_tags_for_url(db: &PlacesDb, url: &Url) -> Result<Vec<String>> {
 mod tests {
     use super::*;
     use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    };
     use crate::storage::new_page_info;
+    use rusqlite::NO_PARAMS;
 
     fn check_tags_for_url(db: &PlacesDb, url: &Url, mut expected: Vec<String>) {
         let mut tags = get_tags_for_url(&db, &url).expect("should work");
@@ -364,4 +373,60 @@ mod tests {
             .expect("should work")
             .expect("should exist");
     }
+
+    #[test]
+    fn test_tags_change_bookmarks() -> Result<()> {
+        let conn = new_mem_connection();
+        let url1 = Url::parse("http://example.com")?;
+        let url2 = Url::parse("http://example2.com")?;
+        for (guid, url) in &[("bookmark1___", &url1), ("bookmark2___", &url2)] {
+            insert_bookmark(
+                &conn,
+                &InsertableBookmark {
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: Some((*guid).into()),
+                    url: (*url).clone(),
+                    title: None,
+                }
+                .into(),
+            )?;
+        }
+        let change_counter = |guid: &str| -> u32 {
+            conn.query_row_named(
+                "SELECT syncChangeCounter FROM moz_bookmarks WHERE guid = :guid",
+                &[(":guid", &guid)],
+                |row| row.get(0),
+            )
+            .expect("should work")
+        };
+        conn.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", NO_PARAMS)?;
+
+        // Sync stores tags with each bookmark, so changing the tags of a URL
+        // means its bookmarks need to be uploaded again.
+        tag_url(&conn, &url1, "common")?;
+        tag_url(&conn, &url2, "common")?;
+        assert_eq!(change_counter("bookmark1___"), 1);
+        assert_eq!(change_counter("bookmark2___"), 1);
+        // Adding a tag the URL already has doesn't change anything.
+        tag_url(&conn, &url1, " common ")?;
+        assert_eq!(change_counter("bookmark1___"), 1);
+
+        untag_url(&conn, &url1, "common")?;
+        assert_eq!(change_counter("bookmark1___"), 2);
+        assert_eq!(change_counter("bookmark2___"), 1);
+        untag_url(&conn, &url1, "common")?;
+        assert_eq!(change_counter("bookmark1___"), 2);
+
+        tag_url(&conn, &url1, "tag-1")?;
+        remove_all_tags_from_url(&conn, &url1)?;
+        assert_eq!(change_counter("bookmark1___"), 4);
+        remove_tag(&conn, " common")?;
+        assert_eq!(change_counter("bookmark1___"), 4);
+        assert_eq!(change_counter("bookmark2___"), 2);
+        check_urls_with_tag(&conn, "common", vec![]);
+        Ok(())
+    }
 }
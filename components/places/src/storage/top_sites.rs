@@ -0,0 +1,373 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Top sites for the home screen: the sites the user has pinned, with the
+//! gaps filled by the most frecent origins, one per site. The user can also
+//! block domains, which keeps their origins out of the frecent sites. Neither
+//! pins nor blocks are synced.
+
+use super::{normalize_domain, URL_LENGTH_MAX};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::types::Timestamp;
+use rusqlite::Row;
+use serde_derive::*;
+use sql_support::ConnExt;
+use std::collections::HashSet;
+use url::Url;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PinnedSite {
+    pub url: Url,
+    pub title: Option<String>,
+    pub position: u32,
+}
+
+impl PinnedSite {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            title: row.get("title")?,
+            position: row.get("position")?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopFrecentSiteInfo {
+    pub url: Url,
+    pub title: Option<String>,
+    /// The frecency of the origin, or of the page for pinned sites (0 if it's
+    /// not in history).
+    pub frecency: i64,
+    pub is_pinned: bool,
+}
+
+/// Pins `url` at `position`, moving the site there and the ones after it
+/// along by one, or after the last pinned site if `position` is `None`.
+/// Pinning a site which is already pinned moves it, and replaces its title.
+pub fn pin_site(
+    db: &PlacesDb,
+    url: &Url,
+    title: Option<&str>,
+    position: Option<u32>,
+) -> Result<()> {
+    if url.as_str().len() > URL_LENGTH_MAX {
+        return Err(InvalidPlaceInfo::UrlTooLong.into());
+    }
+    let tx = db.begin_transaction()?;
+    let position = match position {
+        Some(position) => {
+            db.execute_named_cached(
+                "UPDATE moz_pinned_sites SET position = position + 1
+                 WHERE position >= :position AND url <> :url AND
+                       EXISTS(SELECT 1 FROM moz_pinned_sites
+                              WHERE position = :position AND url <> :url)",
+                &[(":position", &position), (":url", &url.as_str())],
+            )?;
+            position
+        }
+        None => db.query_row_and_then_named(
+            "SELECT IFNULL(MAX(position) + 1, 0) FROM moz_pinned_sites
+             WHERE url <> :url",
+            &[(":url", &url.as_str())],
+            |row| row.get(0),
+            true,
+        )?,
+    };
+    db.execute_named_cached(
+        "INSERT INTO moz_pinned_sites(url, title, position, created_at)
+         VALUES(:url, :title, :position, :now)
+         ON CONFLICT(url) DO UPDATE SET
+             title = excluded.title,
+             position = excluded.position",
+        &[
+            (":url", &url.as_str()),
+            (":title", &title),
+            (":position", &position),
+            (":now", &Timestamp::now()),
+        ],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Unpins `url`, leaving a gap where it was. Returns whether it was pinned.
+pub fn unpin_site(db: &PlacesDb, url: &Url) -> Result<bool> {
+    let changes = db.execute_named_cached(
+        "DELETE FROM moz_pinned_sites WHERE url = :url",
+        &[(":url", &url.as_str())],
+    )?;
+    Ok(changes > 0)
+}
+
+pub fn get_pinned_sites(db: &PlacesDb) -> Result<Vec<PinnedSite>> {
+    db.query_rows_and_then_named_cached(
+        "SELECT url, title, position FROM moz_pinned_sites
+         ORDER BY position, id",
+        &[],
+        PinnedSite::from_row,
+    )
+}
+
+/// Keeps `domain` and its subdomains out of the frecent top sites. Pinned
+/// sites on the domain are still shown.
+pub fn block_top_site_domain(db: &PlacesDb, domain: &str) -> Result<()> {
+    db.execute_named_cached(
+        "INSERT OR IGNORE INTO moz_blocked_top_site_domains(domain)
+         VALUES(:domain)",
+        &[(":domain", &normalize_domain(domain)?)],
+    )?;
+    Ok(())
+}
+
+/// Returns whether `domain` was blocked.
+pub fn unblock_top_site_domain(db: &PlacesDb, domain: &str) -> Result<bool> {
+    let changes = db.execute_named_cached(
+        "DELETE FROM moz_blocked_top_site_domains WHERE domain = :domain",
+        &[(":domain", &normalize_domain(domain)?)],
+    )?;
+    Ok(changes > 0)
+}
+
+/// Returns up to `limit` top sites. Pinned sites are at their positions, and
+/// the rest are filled, in order of frecency, by the most frecent page of
+/// each origin with a frecency of at least `frecency_threshold`. Only one
+/// origin is used per site (ignoring the scheme, port and "www."), and none
+/// for sites which are pinned or blocked.
+pub fn get_top_frecent_site_infos(
+    db: &PlacesDb,
+    limit: u32,
+    frecency_threshold: i64,
+) -> Result<Vec<TopFrecentSiteInfo>> {
+    let limit = limit as usize;
+    let pinned = db.query_rows_and_then_named_cached(
+        "SELECT p.url, IFNULL(p.title, h.title) AS title, p.position,
+                IFNULL(h.frecency, 0) AS frecency
+         FROM moz_pinned_sites p
+         LEFT JOIN moz_places h ON h.url_hash = hash(p.url) AND h.url = p.url
+         ORDER BY p.position, p.id",
+        &[],
+        |row| -> Result<_> {
+            Ok((
+                row.get::<_, u32>("position")?,
+                TopFrecentSiteInfo {
+                    url: Url::parse(&row.get::<_, String>("url")?)?,
+                    title: row.get("title")?,
+                    frecency: row.get::<_, i64>("frecency")?.max(0),
+                    is_pinned: true,
+                },
+            ))
+        },
+    )?;
+    let blocked = db.query_rows_and_then_named_cached(
+        "SELECT domain FROM moz_blocked_top_site_domains",
+        &[],
+        |row| row.get::<_, String>(0),
+    )?;
+    let mut seen_sites = pinned
+        .iter()
+        .filter_map(|(_, info)| site_key(&info.url))
+        .collect::<HashSet<_>>();
+
+    let mut stmt = db.prepare_maybe_cached(
+        "SELECT h.url, h.title, o.frecency
+         FROM moz_origins o
+         JOIN moz_places h ON h.id = (SELECT id FROM moz_places
+                                      WHERE origin_id = o.id AND NOT hidden
+                                      ORDER BY frecency DESC
+                                      LIMIT 1)
+         WHERE o.frecency >= :threshold
+         ORDER BY o.frecency DESC",
+        true,
+    )?;
+    let mut rows =
+        stmt.query_and_then_named(&[(":threshold", &frecency_threshold)], |row| -> Result<_> {
+            Ok((
+                row.get::<_, String>("url")?,
+                row.get::<_, Option<String>>("title")?,
+                row.get::<_, i64>("frecency")?,
+            ))
+        })?;
+    let mut next_frecent = || -> Result<Option<TopFrecentSiteInfo>> {
+        for row in rows.by_ref() {
+            let (url, title, frecency) = row?;
+            let url = match Url::parse(&url) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+                _ => continue,
+            };
+            let host = url.host_str().unwrap_or_default();
+            let is_blocked = blocked.iter().any(|domain| {
+                host == domain
+                    || (host.ends_with(domain.as_str())
+                        && host[..host.len() - domain.len()].ends_with('.'))
+            });
+            if is_blocked {
+                continue;
+            }
+            if !site_key(&url).map_or(false, |key| seen_sites.insert(key)) {
+                continue;
+            }
+            return Ok(Some(TopFrecentSiteInfo {
+                url,
+                title,
+                frecency,
+                is_pinned: false,
+            }));
+        }
+        Ok(None)
+    };
+
+    let mut pinned = pinned.into_iter().peekable();
+    let mut sites = Vec::with_capacity(limit);
+    while sites.len() < limit {
+        let pinned_here = pinned
+            .peek()
+            .map_or(false, |(position, _)| *position as usize <= sites.len());
+        let next = if pinned_here {
+            pinned.next().map(|(_, info)| info)
+        } else {
+            // Once we run out of frecent sites, pinned sites after the gap
+            // move up.
+            match next_frecent()? {
+                Some(info) => Some(info),
+                None => pinned.next().map(|(_, info)| info),
+            }
+        };
+        match next {
+            Some(info) => sites.push(info),
+            None => break,
+        }
+    }
+    Ok(sites)
+}
+
+// Origins for the same host, with or without "www.", count as the same site.
+fn site_key(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    Some(if host.starts_with("www.") {
+        host[4..].to_owned()
+    } else {
+        host.to_owned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::apply_observation;
+    use crate::types::VisitTransition;
+
+    fn visit(db: &PlacesDb, url: &str, title: &str, count: usize) {
+        for _ in 0..count {
+            apply_observation(
+                db,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_title(title.to_string())
+                    .with_visit_type(VisitTransition::Typed),
+            )
+            .expect("should apply visit");
+        }
+    }
+
+    fn urls(sites: &[TopFrecentSiteInfo]) -> Vec<&str> {
+        sites.iter().map(|site| site.url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pinned_sites() -> Result<()> {
+        let conn = new_mem_connection();
+        let a = Url::parse("https://a.example.com/")?;
+        let b = Url::parse("https://b.example.com/")?;
+        let c = Url::parse("https://c.example.com/")?;
+        pin_site(&conn, &a, Some("A"), None)?;
+        pin_site(&conn, &b, None, None)?;
+        // Pinning at a taken position moves the sites from there along.
+        pin_site(&conn, &c, None, Some(0))?;
+        let positions = |conn| -> Result<Vec<(String, u32)>> {
+            Ok(get_pinned_sites(conn)?
+                .into_iter()
+                .map(|site| (site.url.into_string(), site.position))
+                .collect())
+        };
+        assert_eq!(
+            positions(&conn)?,
+            vec![(c.to_string(), 0), (a.to_string(), 1), (b.to_string(), 2)]
+        );
+        // But a free one doesn't, and re-pinning moves a site.
+        pin_site(&conn, &c, Some("C"), Some(5))?;
+        assert_eq!(
+            positions(&conn)?,
+            vec![(a.to_string(), 1), (b.to_string(), 2), (c.to_string(), 5)]
+        );
+        assert_eq!(get_pinned_sites(&conn)?[0].title.as_deref(), Some("A"));
+        assert!(unpin_site(&conn, &b)?);
+        assert!(!unpin_site(&conn, &b)?);
+        assert_eq!(get_pinned_sites(&conn)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_frecent_site_infos() -> Result<()> {
+        let conn = new_mem_connection();
+        visit(&conn, "https://www.example.com/", "Example", 5);
+        // The same site as above, so it's left out.
+        visit(&conn, "https://example.com/other", "Example Other", 4);
+        visit(&conn, "https://www.mozilla.org/", "Mozilla", 4);
+        visit(&conn, "https://news.blocked.com/", "Blocked", 3);
+        visit(&conn, "https://www.example.org/a", "Example A", 2);
+        visit(&conn, "https://www.example.org/b", "Example B", 1);
+        visit(&conn, "http://pinned.com/", "Pinned", 1);
+        block_top_site_domain(&conn, "BLOCKED.com")?;
+
+        assert_eq!(
+            urls(&get_top_frecent_site_infos(&conn, 10, 0)?),
+            vec![
+                "https://www.example.com/",
+                "https://www.mozilla.org/",
+                "https://www.example.org/a",
+                "http://pinned.com/",
+            ]
+        );
+
+        pin_site(&conn, &Url::parse("http://pinned.com/")?, None, Some(1))?;
+        pin_site(&conn, &Url::parse("https://unvisited.com/")?, None, Some(8))?;
+        let sites = get_top_frecent_site_infos(&conn, 10, 0)?;
+        assert_eq!(
+            urls(&sites),
+            vec![
+                "https://www.example.com/",
+                "http://pinned.com/",
+                "https://www.mozilla.org/",
+                "https://www.example.org/a",
+                "https://unvisited.com/",
+            ]
+        );
+        assert!(sites[1].is_pinned);
+        assert_eq!(sites[1].title.as_deref(), Some("Pinned"));
+        assert!(!sites[2].is_pinned);
+        assert_eq!(sites[4].frecency, 0);
+        assert_eq!(urls(&get_top_frecent_site_infos(&conn, 2, 0)?).len(), 2);
+
+        // The threshold only applies to frecent sites.
+        let threshold = sites[2].frecency + 1;
+        assert_eq!(
+            urls(&get_top_frecent_site_infos(&conn, 10, threshold)?),
+            vec![
+                "https://www.example.com/",
+                "http://pinned.com/",
+                "https://unvisited.com/",
+            ]
+        );
+
+        assert!(unblock_top_site_domain(&conn, "blocked.com")?);
+        assert!(
+            urls(&get_top_frecent_site_infos(&conn, 10, 0)?).contains(&"https://news.blocked.com/")
+        );
+        assert!(block_top_site_domain(&conn, "").is_err());
+        Ok(())
+    }
+}
@@ -294,6 +294,7 @@ fn test_import() -> Result<()> {
         total_duration: 4,
         num_failed: 0,
         num_total: 9,
+        ..HistoryMigrationResult::default()
     };
     assert_eq!(metrics.num_succeeded, expected_metrics.num_succeeded);
     assert_eq!(metrics.num_failed, expected_metrics.num_failed);
@@ -321,6 +322,82 @@ fn test_import() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_import_with_visit_limit() -> Result<()> {
+    use places::storage::history::history_sync::fetch_visits;
+    use url::Url;
+
+    let tmpdir = tempdir().unwrap();
+    let fennec_path = tmpdir.path().join("browser.db");
+    let fennec_db = empty_fennec_db(&fennec_path)?;
+
+    let history = [
+        FennecHistory {
+            url: "https://example.com/old".to_owned(),
+            ..Default::default()
+        },
+        FennecHistory {
+            url: "https://example.com/new".to_owned(),
+            ..Default::default()
+        },
+    ];
+    let visits = [
+        FennecVisit {
+            history: &history[0],
+            visit_type: VisitTransition::Link,
+            date: Timestamp::from(1_565_117_000_000),
+            is_local: true,
+        },
+        FennecVisit {
+            history: &history[1],
+            visit_type: VisitTransition::Typed,
+            date: Timestamp::from(1_565_117_200_000),
+            is_local: true,
+        },
+        FennecVisit {
+            history: &history[1],
+            visit_type: VisitTransition::Reload,
+            date: Timestamp::from(1_565_117_100_000),
+            is_local: true,
+        },
+    ];
+    insert_history_and_visits(&fennec_db, &history, &visits)?;
+    // A visit type we don't know.
+    fennec_db.execute_named(
+        "INSERT INTO visits(history_guid, visit_type, date, is_local)
+         VALUES (:guid, 42, 1565117300000, 1)",
+        rusqlite::named_params! { ":guid": history[1].guid },
+    )?;
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let metrics =
+        places::import::import_fennec_history_with_visit_limit(&places_api, fennec_path, 3)?;
+    assert_eq!(metrics.num_total, 4);
+    assert_eq!(metrics.num_succeeded, 3);
+    assert_eq!(metrics.num_skipped, 1);
+    assert_eq!(metrics.num_failed, 0);
+    assert_eq!(metrics.num_unknown_visit_types, 1);
+
+    let conn = places_api.open_connection(places::ConnectionType::ReadOnly)?;
+    // The oldest visit is dropped, but its page is still imported.
+    let (_, old_visits) =
+        fetch_visits(&conn, &Url::parse("https://example.com/old")?, 10)?.expect("has page");
+    assert!(old_visits.is_empty());
+    let (_, new_visits) =
+        fetch_visits(&conn, &Url::parse("https://example.com/new")?, 10)?.expect("has page");
+    let mut visit_types = new_visits.iter().map(|v| v.visit_type).collect::<Vec<_>>();
+    visit_types.sort_by_key(|t| t.map(|t| t as u8));
+    assert_eq!(
+        visit_types,
+        vec![
+            Some(VisitTransition::Link),
+            Some(VisitTransition::Typed),
+            Some(VisitTransition::Reload)
+        ]
+    );
+    Ok(())
+}
+
 #[test]
 fn test_invalid_utf8() -> Result<()> {
     use places::api::places_api::ConnectionType;
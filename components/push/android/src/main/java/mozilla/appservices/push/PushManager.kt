@@ -375,10 +375,11 @@ interface PushAPI : AutoCloseable {
     fun update(registrationToken: String): Boolean
 
     /**
-     * Verifies the connection state.
+     * Verifies the connection state. If the server's subscriptions don't match ours, they are
+     * all dropped, and need to be subscribed to again.
      *
-     * @return bool indicating if connection state is valid (true) or if channels should get a
-     * `pushsubscriptionchange` event (false).
+     * @return the subscriptions that were dropped, which should get a
+     * `pushsubscriptionchange` event. Empty if the connection state is valid.
      */
     fun verifyConnection(): List<PushSubscriptionChanged>
 
@@ -42,7 +42,11 @@ impl PushManager {
         scope: &str,
         server_key: Option<&str>,
     ) -> Result<(RegisterResponse, Key)> {
-        let reg_token = self.config.registration_id.clone().unwrap();
+        let reg_token = self
+            .config
+            .registration_id
+            .clone()
+            .ok_or_else(|| ErrorKind::MissingRegistrationTokenError)?;
         let subscription_key: Key;
         if let Some(uaid) = self.conn.uaid.clone() {
             // Don't fetch the connection from the server if we've already got one.
@@ -67,7 +71,7 @@ impl PushManager {
                 "LsuUOBKVQRY6-l7_Ajo-Ag"
             )
         } else {
-            subscription_key = Crypto::generate_key().unwrap();
+            subscription_key = Crypto::generate_key()?;
         }
         // store the channel_id => auth + subscription_key
         let mut record = crate::storage::PushRecord::new(
@@ -121,9 +125,15 @@ impl PushManager {
         let result = self.conn.update(&new_token)?;
         self.store
             .update_native_id(self.conn.uaid.as_ref().unwrap(), new_token)?;
+        // Later subscriptions should be recorded against the new token too.
+        self.config.registration_id = Some(new_token.to_owned());
         Ok(result)
     }
 
+    /// Checks that the server has the same subscriptions as we do. If it
+    /// doesn't, every subscription is dropped, along with our UAID, and the
+    /// dropped records are returned so that the caller can subscribe again
+    /// (getting new endpoints) and tell the affected sites.
     pub fn verify_connection(&mut self) -> Result<Vec<PushRecord>> {
         let uaid = self
             .conn
@@ -144,6 +154,11 @@ impl PushManager {
                 subscriptions.push(record);
             }
         }
+        // The server has already dropped our channels, so forget them too.
+        // Otherwise `subscribe` would keep handing out the stale endpoints.
+        self.store.delete_all_records(&uaid)?;
+        self.conn.uaid = None;
+        self.conn.auth = None;
         Ok(subscriptions)
     }
 
@@ -206,6 +221,66 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn verify_connection_drops_stale_subscriptions() -> Result<()> {
+        let test_channel_id = "deadbeef00000000decafbad00000000";
+        let test_config = PushConfiguration {
+            sender_id: "test".to_owned(),
+            ..Default::default()
+        };
+        let mut pm = PushManager::new(test_config)?;
+        pm.subscribe(test_channel_id, "https://example.com/push", None)?;
+
+        // The test connection always reports that the channels don't match.
+        let changed = pm.verify_connection()?;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].channel_id, test_channel_id);
+        assert_eq!(changed[0].scope, "https://example.com/push");
+        assert!(pm.get_record_by_chid(test_channel_id)?.is_none());
+        assert!(pm.store.get_meta("uaid")?.is_none());
+        assert!(pm.conn.uaid.is_none());
+
+        // Subscribing again registers afresh.
+        pm.subscribe(test_channel_id, "https://example.com/push", None)?;
+        assert!(pm.get_record_by_chid(test_channel_id)?.is_some());
+        assert!(pm.store.get_meta("uaid")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn update_registration_token() -> Result<()> {
+        let test_channel_id = "deadbeef00000000decafbad00000000";
+        let test_config = PushConfiguration {
+            sender_id: "test".to_owned(),
+            ..Default::default()
+        };
+        // Without a bridge, the connection doesn't need a registration token,
+        // but subscribing does.
+        let mut pm = PushManager::new(PushConfiguration {
+            bridge_type: None,
+            registration_id: None,
+            ..test_config.clone()
+        })?;
+        match pm.subscribe(test_channel_id, "", None) {
+            Err(e) => match e.kind() {
+                ErrorKind::MissingRegistrationTokenError => {}
+                _ => panic!("unexpected error {:?}", e),
+            },
+            Ok(_) => panic!("subscribing without a registration token should fail"),
+        }
+
+        let mut pm = PushManager::new(PushConfiguration {
+            registration_id: Some("old_token".to_owned()),
+            ..test_config
+        })?;
+        pm.subscribe(test_channel_id, "", None)?;
+        assert!(pm.update("new_token")?);
+        let record = pm.get_record_by_chid(test_channel_id)?.unwrap();
+        assert_eq!(record.native_id.as_deref(), Some("new_token"));
+        assert_eq!(pm.config.registration_id.as_deref(), Some("new_token"));
+        Ok(())
+    }
+
     #[test]
     fn full() -> Result<()> {
         use rc_crypto::ece;
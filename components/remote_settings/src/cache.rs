@@ -0,0 +1,394 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::client::{Attachment, Client, RemoteSettingsConfig, RemoteSettingsRecord};
+use crate::error::*;
+use crate::schema;
+use rusqlite::{Connection, OpenFlags};
+use sql_support::ConnExt;
+use std::collections::HashSet;
+use std::path::Path;
+
+const LAST_MODIFIED_META_KEY: &str = "last_modified";
+const RECORDS_URL_META_KEY: &str = "records_url";
+
+/// What changed in the cache after a sync.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RemoteSettingsChanges {
+    /// Records which were added or changed.
+    pub updated: Vec<RemoteSettingsRecord>,
+    /// The IDs of records which were deleted.
+    pub deleted: Vec<String>,
+}
+
+/// Keeps a copy of a collection in SQLite, so that it's available offline,
+/// and only fetches what changed since the last sync.
+pub struct RemoteSettingsCache {
+    client: Client,
+    db: Connection,
+}
+
+impl RemoteSettingsCache {
+    pub fn new(config: RemoteSettingsConfig, db_path: impl AsRef<Path>) -> Result<Self> {
+        rc_crypto::ensure_initialized();
+        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_CREATE
+            | OpenFlags::SQLITE_OPEN_READ_WRITE;
+        let db = Connection::open_with_flags(db_path, flags)?;
+        db.execute_batch("PRAGMA journal_mode=WAL;")?;
+        schema::init(&db)?;
+        let cache = Self {
+            client: Client::new(config)?,
+            db,
+        };
+        cache.clear_if_collection_changed()?;
+        Ok(cache)
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// If the cache was filled from a different collection, throws it away,
+    /// so that we don't mix records from both.
+    fn clear_if_collection_changed(&self) -> Result<()> {
+        let records_url = self.client.config().records_url()?.into_string();
+        if self.get_meta::<String>(RECORDS_URL_META_KEY)?.as_ref() == Some(&records_url) {
+            return Ok(());
+        }
+        let tx = self.db.unchecked_transaction()?;
+        tx.execute_batch("DELETE FROM records; DELETE FROM meta;")?;
+        self.put_meta(RECORDS_URL_META_KEY, &records_url)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The timestamp of the collection as of the last sync, or `None` if we
+    /// haven't synced yet.
+    pub fn last_modified(&self) -> Result<Option<u64>> {
+        Ok(self
+            .get_meta::<i64>(LAST_MODIFIED_META_KEY)?
+            .map(|ts| ts as u64))
+    }
+
+    /// Fetches the records that changed since the last sync, and applies them
+    /// to the cache, once the collection matches its signature.
+    ///
+    /// If the merged records don't match, our copy might be out of step with
+    /// the server's, so we fetch the whole collection again. If that doesn't
+    /// match either, the sync fails with `InvalidSignature`, and the cache is
+    /// left alone.
+    pub fn sync(&self) -> Result<RemoteSettingsChanges> {
+        let since = self.last_modified()?;
+        match self.sync_since(since) {
+            Err(e) if since.is_some() && matches!(e.kind(), ErrorKind::InvalidSignature(_)) => {
+                log::warn!(
+                    "Refetching {} after a signature mismatch",
+                    self.client.config().collection_name
+                );
+                self.sync_since(None)
+            }
+            result => result,
+        }
+    }
+
+    fn sync_since(&self, since: Option<u64>) -> Result<RemoteSettingsChanges> {
+        let resp = self.client.fetch_records(since)?;
+        let mut changes = RemoteSettingsChanges::default();
+        if resp.records.is_empty() && since == Some(resp.last_modified) {
+            // Nothing changed since we last checked the signature.
+            return Ok(changes);
+        }
+        let tx = self.db.unchecked_transaction()?;
+        let mut removed = HashSet::new();
+        if since.is_none() {
+            // A full fetch replaces everything we had.
+            removed = tx
+                .query_rows_and_then_named_cached("SELECT id FROM records", &[], |row| {
+                    row.get::<_, String>(0)
+                })?
+                .into_iter()
+                .collect();
+            tx.execute_batch("DELETE FROM records")?;
+        }
+        for raw in resp.records {
+            let record: RemoteSettingsRecord = serde_json::from_value(raw.clone())?;
+            removed.remove(&record.id);
+            if record.deleted {
+                tx.execute_named_cached(
+                    "DELETE FROM records WHERE id = :id",
+                    &[(":id", &record.id)],
+                )?;
+                changes.deleted.push(record.id);
+            } else {
+                // We store the record as the server sent it, because that's
+                // what the signature is for.
+                tx.execute_named_cached(
+                    "INSERT OR REPLACE INTO records (id, last_modified, record)
+                     VALUES (:id, :last_modified, :record)",
+                    &[
+                        (":id", &record.id),
+                        (":last_modified", &(record.last_modified as i64)),
+                        (":record", &raw.to_string()),
+                    ],
+                )?;
+                changes.updated.push(record);
+            }
+        }
+        changes.deleted.extend(removed);
+        let records = tx.query_rows_and_then_named_cached(
+            "SELECT record FROM records",
+            &[],
+            |row| -> Result<serde_json::Value> {
+                Ok(serde_json::from_str(&row.get::<_, String>(0)?)?)
+            },
+        )?;
+        self.client.verify_signature(&records, resp.last_modified)?;
+        self.put_meta(LAST_MODIFIED_META_KEY, &(resp.last_modified as i64))?;
+        tx.commit()?;
+        log::info!(
+            "Synced {}: {} updated, {} deleted",
+            self.client.config().collection_name,
+            changes.updated.len(),
+            changes.deleted.len()
+        );
+        Ok(changes)
+    }
+
+    /// Returns the cached records, without fetching anything.
+    pub fn get_records(&self) -> Result<Vec<RemoteSettingsRecord>> {
+        self.db.query_rows_and_then_named_cached(
+            "SELECT record FROM records ORDER BY id",
+            &[],
+            |row| -> Result<_> { Ok(serde_json::from_str(&row.get::<_, String>(0)?)?) },
+        )
+    }
+
+    /// Downloads and checks an attachment. Attachments aren't cached.
+    pub fn get_attachment(&self, attachment: &Attachment) -> Result<Vec<u8>> {
+        self.client.get_attachment(attachment)
+    }
+
+    fn get_meta<T: rusqlite::types::FromSql>(&self, key: &str) -> Result<Option<T>> {
+        Ok(self.db.try_query_row(
+            "SELECT value FROM meta WHERE key = :key",
+            &[(":key", &key)],
+            |row| row.get::<_, T>(0),
+            true,
+        )?)
+    }
+
+    fn put_meta(&self, key: &str, value: &dyn rusqlite::ToSql) -> Result<()> {
+        self.db.execute_named_cached(
+            "REPLACE INTO meta (key, value) VALUES (:key, :value)",
+            &[(":key", &key), (":value", value)],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{mock_signature, test_config};
+    use mockito::{mock, Matcher, Mock};
+
+    fn new_cache(dir: &tempfile::TempDir, collection_name: &str) -> RemoteSettingsCache {
+        RemoteSettingsCache::new(
+            test_config(collection_name),
+            dir.path().join("remote-settings.db"),
+        )
+        .expect("should open cache")
+    }
+
+    // The signature for
+    // `{"data":[{"id":"b","last_modified":30,"name":"B2"},{"id":"c","last_modified":35,"name":"C"}],"last_modified":"40"}`.
+    const SIGNATURE_40: &str = "8iT2c3LcoDt3CuSezHE0wAb5ed3AKqbogBGFNYmAcQMZXCZVtEr3-nhWwS8FBmZr\
+                                cnJ2rmr41suhHdlL7Qx_A9IAQDyKikryuKw1VsiJ3FrVfi530AghMg_0Q_0ZWfwb";
+
+    #[test]
+    fn test_sync() {
+        viaduct_reqwest::use_reqwest_backend();
+        let dir = tempfile::tempdir().unwrap();
+        let records_path = "/v1/buckets/main/collections/search-config/records";
+        {
+            let cache = new_cache(&dir, "search-config");
+            assert_eq!(cache.last_modified().unwrap(), None);
+            let m = mock("GET", records_path)
+                .match_query(Matcher::Missing)
+                .with_status(200)
+                .with_header("etag", "\"20\"")
+                .with_body(
+                    r#"{"data": [
+                        {"id": "a", "last_modified": 10, "name": "A"},
+                        {"id": "b", "last_modified": 20, "name": "B"}
+                    ]}"#,
+                )
+                .create();
+            let signature_mocks = mock_signature(
+                "search-config",
+                "0-D10INNRbtFX9HJnkyhll9qwnLQK8skQ3W87ZE_OlnK9N8mq0PEtNNf7c5AIjIfXeqYt\
+                 QtnAdb8a0UxJXMp7PwzgEYX5eEAVlZqHbKEnrmZzK0LetFLw4Hxq91ytKmr",
+                1,
+            );
+            let changes = cache.sync().unwrap();
+            m.assert();
+            signature_mocks.iter().for_each(Mock::assert);
+            assert_eq!(changes.updated.len(), 2);
+            assert!(changes.deleted.is_empty());
+            assert_eq!(cache.last_modified().unwrap(), Some(20));
+        }
+        {
+            // Reopening the cache keeps the records, and the next sync only
+            // fetches what changed.
+            let cache = new_cache(&dir, "search-config");
+            let m = mock("GET", records_path)
+                .match_query(Matcher::UrlEncoded("_since".into(), "20".into()))
+                .with_status(200)
+                .with_header("etag", "\"40\"")
+                .with_body(
+                    r#"{"data": [
+                        {"id": "a", "last_modified": 40, "deleted": true},
+                        {"id": "b", "last_modified": 30, "name": "B2"},
+                        {"id": "c", "last_modified": 35, "name": "C"}
+                    ]}"#,
+                )
+                .create();
+            // The signature is for all the records, not just the changes.
+            let signature_mocks = mock_signature("search-config", SIGNATURE_40, 1);
+            let changes = cache.sync().unwrap();
+            m.assert();
+            signature_mocks.iter().for_each(Mock::assert);
+            assert_eq!(changes.deleted, vec!["a".to_string()]);
+            let records = cache.get_records().unwrap();
+            assert_eq!(records, changes.updated);
+            assert_eq!(records[0].id, "b");
+            assert_eq!(records[0].fields["name"], "B2");
+            assert_eq!(records[1].id, "c");
+            assert_eq!(cache.last_modified().unwrap(), Some(40));
+        }
+        {
+            // A failed sync leaves the cache alone.
+            let cache = new_cache(&dir, "search-config");
+            let m = mock("GET", records_path)
+                .match_query(Matcher::Any)
+                .with_status(500)
+                .create();
+            assert!(cache.sync().is_err());
+            m.assert();
+            assert_eq!(cache.get_records().unwrap().len(), 2);
+            assert_eq!(cache.last_modified().unwrap(), Some(40));
+        }
+        {
+            // But a different collection starts from scratch.
+            let cache = new_cache(&dir, "regions");
+            assert!(cache.get_records().unwrap().is_empty());
+            assert_eq!(cache.last_modified().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_sync_invalid_signature() {
+        viaduct_reqwest::use_reqwest_backend();
+        let dir = tempfile::tempdir().unwrap();
+        let records_path = "/v1/buckets/main/collections/search-config/records";
+        {
+            let cache = new_cache(&dir, "search-config");
+            let m = mock("GET", records_path)
+                .match_query(Matcher::Missing)
+                .with_status(200)
+                .with_header("etag", "\"40\"")
+                .with_body(
+                    r#"{"data": [
+                        {"id": "b", "last_modified": 30, "name": "B2"},
+                        {"id": "c", "last_modified": 35, "name": "C"}
+                    ]}"#,
+                )
+                .create();
+            let _signature_mocks = mock_signature("search-config", SIGNATURE_40, 1);
+            cache.sync().unwrap();
+            m.assert();
+        }
+        {
+            // If the records were changed after they were signed, we try
+            // again from scratch, and then give up, leaving the cache alone.
+            let cache = new_cache(&dir, "search-config");
+            let changes = mock("GET", records_path)
+                .match_query(Matcher::UrlEncoded("_since".into(), "40".into()))
+                .with_status(200)
+                .with_header("etag", "\"45\"")
+                .with_body(r#"{"data": [{"id": "c", "last_modified": 45, "name": "Evil"}]}"#)
+                .create();
+            let all = mock("GET", records_path)
+                .match_query(Matcher::Missing)
+                .with_status(200)
+                .with_header("etag", "\"45\"")
+                .with_body(
+                    r#"{"data": [
+                        {"id": "b", "last_modified": 30, "name": "B2"},
+                        {"id": "c", "last_modified": 45, "name": "Evil"}
+                    ]}"#,
+                )
+                .create();
+            let signature_mocks = mock_signature("search-config", SIGNATURE_40, 2);
+            match cache.sync().unwrap_err().kind() {
+                ErrorKind::InvalidSignature(collection) => assert_eq!(collection, "search-config"),
+                e => panic!("Unexpected error {:?}", e),
+            }
+            changes.assert();
+            all.assert();
+            signature_mocks.iter().for_each(Mock::assert);
+            let records = cache.get_records().unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[1].fields["name"], "C");
+            assert_eq!(cache.last_modified().unwrap(), Some(40));
+        }
+        {
+            // If our copy was changed, refetching everything fixes it.
+            let cache = new_cache(&dir, "search-config");
+            cache
+                .db
+                .execute_batch(
+                    r#"UPDATE records SET record = '{"id":"c","last_modified":35,"name":"X"}'
+                       WHERE id = 'c'"#,
+                )
+                .unwrap();
+            let changes = mock("GET", records_path)
+                .match_query(Matcher::UrlEncoded("_since".into(), "40".into()))
+                .with_status(200)
+                .with_header("etag", "\"50\"")
+                .with_body(r#"{"data": [{"id": "d", "last_modified": 50, "name": "D"}]}"#)
+                .create();
+            let all = mock("GET", records_path)
+                .match_query(Matcher::Missing)
+                .with_status(200)
+                .with_header("etag", "\"50\"")
+                .with_body(
+                    r#"{"data": [
+                        {"id": "b", "last_modified": 30, "name": "B2"},
+                        {"id": "c", "last_modified": 35, "name": "C"},
+                        {"id": "d", "last_modified": 50, "name": "D"}
+                    ]}"#,
+                )
+                .create();
+            let signature_mocks = mock_signature(
+                "search-config",
+                "lrDRF4tDmIrWZAcidV0e3e0E0K5uDL7Fuj3mTx5F5iXufbdatZhNB-yayF8YVDk9qzil\
+                 4laeLPazfsFJbFqxPPa-jV1WvOp2RswzKC2l2WecHlJcUcQ8KAtJRj1MDjhX",
+                2,
+            );
+            let changes_resp = cache.sync().unwrap();
+            changes.assert();
+            all.assert();
+            signature_mocks.iter().for_each(Mock::assert);
+            assert_eq!(changes_resp.updated.len(), 3);
+            assert!(changes_resp.deleted.is_empty());
+            let records = cache.get_records().unwrap();
+            assert_eq!(records, changes_resp.updated);
+            assert_eq!(records[1].fields["name"], "C");
+            assert_eq!(cache.last_modified().unwrap(), Some(50));
+        }
+    }
+}
@@ -0,0 +1,759 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::*;
+use rc_crypto::{contentsignature, digest};
+use serde_derive::*;
+use std::cmp::Ordering;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+use viaduct::{header_names, Request, Response};
+
+/// The production, staging and dev Remote Settings servers. These are the only
+/// servers a `Client` fetches from, unless the config allows others.
+pub const TRUSTED_SERVERS: &[&str] = &[
+    "https://firefox.settings.services.mozilla.com",
+    "https://firefox.settings.services.allizom.org",
+    "https://settings.dev.mozaws.net",
+];
+
+/// The SHA-256 fingerprint of the root certificate that signs the collections
+/// on the production server. The staging and dev servers use other roots.
+pub const PRODUCTION_ROOT_HASH: &str = "97:E8:BA:9C:F1:2F:B3:DE:53:CC:42:A4:E6:57:7E:D6:4D:F4:93:C2:47:B4:14:FE:A0:36:81:8D:38:23:56:0E";
+
+/// The name in the certificate that signs the collections in the `main`
+/// bucket.
+pub const DEFAULT_SIGNER_NAME: &str = "remote-settings.content-signature.mozilla.org";
+
+/// Which collection to fetch, and from where.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteSettingsConfig {
+    pub server_url: Url,
+    pub bucket_name: String,
+    pub collection_name: String,
+    /// Allows fetching from a server that isn't in `TRUSTED_SERVERS`, like a
+    /// local one for tests. The collection still has to be signed by a chain
+    /// that ends with `root_hash`.
+    pub allow_untrusted_server: bool,
+    /// The SHA-256 fingerprint of the root certificate that the collection's
+    /// signing chain must end with, as colon-separated hex.
+    pub root_hash: String,
+    /// The DNS name in the certificate that signs the collection.
+    pub signer_name: String,
+}
+
+impl RemoteSettingsConfig {
+    /// A collection in the `main` bucket, which is where most collections
+    /// live.
+    pub fn new(server_url: Url, collection_name: impl Into<String>) -> Self {
+        Self {
+            server_url,
+            bucket_name: "main".into(),
+            collection_name: collection_name.into(),
+            allow_untrusted_server: false,
+            root_hash: PRODUCTION_ROOT_HASH.into(),
+            signer_name: DEFAULT_SIGNER_NAME.into(),
+        }
+    }
+
+    fn check_trusted(&self) -> Result<()> {
+        if self.allow_untrusted_server {
+            return Ok(());
+        }
+        let origin = self.server_url.origin();
+        for server in TRUSTED_SERVERS {
+            if Url::parse(server)?.origin() == origin {
+                return Ok(());
+            }
+        }
+        Err(ErrorKind::UntrustedServer(self.server_url.to_string()).into())
+    }
+
+    pub(crate) fn records_url(&self) -> Result<Url> {
+        self.api_url(&[
+            "buckets",
+            &self.bucket_name,
+            "collections",
+            &self.collection_name,
+            "records",
+        ])
+    }
+
+    fn collection_url(&self) -> Result<Url> {
+        self.api_url(&[
+            "buckets",
+            &self.bucket_name,
+            "collections",
+            &self.collection_name,
+        ])
+    }
+
+    fn api_url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = self.server_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+            .pop_if_empty()
+            .push("v1")
+            .extend(segments);
+        Ok(url)
+    }
+}
+
+/// A record in a collection. Besides the fields that every record has, the
+/// contents are up to whoever defines the collection, and are kept in
+/// `fields`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RemoteSettingsRecord {
+    pub id: String,
+    pub last_modified: u64,
+    /// Set for records which were deleted since the `_since` timestamp. They
+    /// don't have any other fields.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    #[serde(flatten)]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A file attached to a record, which is downloaded separately with
+/// `Client::get_attachment`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Attachment {
+    pub filename: String,
+    pub mimetype: String,
+    /// Relative to the server's attachments base URL.
+    pub location: String,
+    /// The hex-encoded SHA-256 hash of the file.
+    pub hash: String,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteSettingsResponse {
+    pub records: Vec<RemoteSettingsRecord>,
+    /// The timestamp of the collection, which the signature is for.
+    pub last_modified: u64,
+}
+
+/// Records as the server sent them, which we need to check the signature:
+/// `RemoteSettingsRecord` doesn't keep fields that it doesn't know about in
+/// attachments.
+#[derive(Debug)]
+pub(crate) struct RawRecords {
+    pub(crate) records: Vec<serde_json::Value>,
+    pub(crate) last_modified: u64,
+}
+
+#[derive(Deserialize)]
+struct RecordsResponse {
+    data: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct CollectionResponse {
+    data: CollectionMetadata,
+}
+
+#[derive(Deserialize)]
+struct CollectionMetadata {
+    signature: Option<Signature>,
+}
+
+#[derive(Deserialize)]
+struct Signature {
+    /// The URL of the PEM certificate chain.
+    x5u: String,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct ServerInfo {
+    #[serde(default)]
+    capabilities: Capabilities,
+}
+
+#[derive(Default, Deserialize)]
+struct Capabilities {
+    attachments: Option<AttachmentsCapability>,
+}
+
+#[derive(Deserialize)]
+struct AttachmentsCapability {
+    base_url: String,
+}
+
+/// Fetches records and attachments from a collection, without storing them.
+/// See `RemoteSettingsCache` for a client that keeps a local copy, and only
+/// fetches what changed: the signature covers the whole collection, so it
+/// needs the records we already have to check the changes.
+#[derive(Clone, Debug)]
+pub struct Client {
+    config: RemoteSettingsConfig,
+}
+
+impl Client {
+    /// Fails with `UntrustedServer` if the config is for a server that isn't
+    /// in `TRUSTED_SERVERS`, and doesn't allow untrusted ones.
+    pub fn new(config: RemoteSettingsConfig) -> Result<Self> {
+        config.check_trusted()?;
+        Ok(Self { config })
+    }
+
+    pub fn config(&self) -> &RemoteSettingsConfig {
+        &self.config
+    }
+
+    /// Fetches all the records in the collection, and checks its signature.
+    /// Fails with `InvalidSignature` if it doesn't match.
+    pub fn get_records(&self) -> Result<RemoteSettingsResponse> {
+        let raw = self.fetch_records(None)?;
+        self.verify_signature(&raw.records, raw.last_modified)?;
+        Ok(RemoteSettingsResponse {
+            records: raw
+                .records
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect::<serde_json::Result<_>>()?,
+            last_modified: raw.last_modified,
+        })
+    }
+
+    /// Fetches the records changed since `since`, including tombstones for
+    /// the ones that were deleted, or all of them if it's `None`. These aren't
+    /// checked: use `verify_signature` once they're merged.
+    pub(crate) fn fetch_records(&self, since: Option<u64>) -> Result<RawRecords> {
+        let mut request = Request::get(self.config.records_url()?);
+        if let Some(since) = since {
+            request = request.query(&[("_since", &since.to_string())]);
+        }
+        let resp = send(request)?;
+        let records = resp.json::<RecordsResponse>()?.data;
+        // The ETag is the quoted timestamp of the collection, which can be
+        // newer than any of the records we got back.
+        let last_modified = resp
+            .headers
+            .get(header_names::ETAG)
+            .and_then(|etag| etag.trim_matches('"').parse().ok())
+            .into_iter()
+            .chain(records.iter().filter_map(|r| r["last_modified"].as_u64()))
+            .chain(since)
+            .max()
+            .unwrap_or(0);
+        Ok(RawRecords {
+            records,
+            last_modified,
+        })
+    }
+
+    /// Checks that `records`, which should be every record in the collection
+    /// as of `last_modified`, match the collection's signature. Tombstones are
+    /// skipped.
+    pub(crate) fn verify_signature(
+        &self,
+        records: &[serde_json::Value],
+        last_modified: u64,
+    ) -> Result<()> {
+        let metadata: CollectionResponse =
+            send(Request::get(self.config.collection_url()?))?.json()?;
+        let signature = match metadata.data.signature {
+            Some(signature) => signature,
+            None => return Err(self.invalid_signature("the collection isn't signed")),
+        };
+        let chain = send(Request::get(Url::parse(&signature.x5u)?))?.body;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let content = signed_content(records, last_modified);
+        match contentsignature::verify(
+            content.as_bytes(),
+            signature.signature.as_bytes(),
+            &chain,
+            now,
+            &self.config.root_hash,
+            &self.config.signer_name,
+        ) {
+            Ok(()) => Ok(()),
+            Err(e) => match e.kind() {
+                rc_crypto::ErrorKind::InvalidContentSignature(reason) => {
+                    Err(self.invalid_signature(reason))
+                }
+                _ => Err(e.into()),
+            },
+        }
+    }
+
+    fn invalid_signature(&self, reason: &str) -> Error {
+        log::warn!(
+            "Signature check for {} failed: {}",
+            self.config.collection_name,
+            reason
+        );
+        ErrorKind::InvalidSignature(self.config.collection_name.clone()).into()
+    }
+
+    /// Downloads `attachment`, and checks that it matches the size and hash
+    /// in its record.
+    pub fn get_attachment(&self, attachment: &Attachment) -> Result<Vec<u8>> {
+        // The server info, which has the attachments base URL, is at `/v1/`.
+        let server_info: ServerInfo = send(Request::get(self.config.api_url(&[""])?))?.json()?;
+        let base_url = match server_info.capabilities.attachments {
+            Some(capability) => Url::parse(&capability.base_url)?,
+            None => return Err(ErrorKind::AttachmentsUnsupported.into()),
+        };
+        let resp = Request::get(base_url.join(&attachment.location)?).send_streaming()?;
+        if !resp.is_success() {
+            return Err(ErrorKind::RemoteSettingsHttpError(resp.status).into());
+        }
+        // Read at most one byte more than we expect, so that a bad server
+        // can't make us buffer more than that.
+        let mut body = Vec::new();
+        resp.take(attachment.size.saturating_add(1))
+            .read_to_end(&mut body)
+            .map_err(|e| viaduct::Error::NetworkError(e.to_string()))?;
+        verify_attachment(attachment, &body)?;
+        Ok(body)
+    }
+}
+
+fn send(request: Request) -> Result<Response> {
+    let resp = request.send()?;
+    if !resp.is_success() {
+        return Err(ErrorKind::RemoteSettingsHttpError(resp.status).into());
+    }
+    Ok(resp)
+}
+
+/// The content that the collection's signature is for: the canonical JSON of
+/// the records, sorted by ID, and the timestamp, in the same form that the
+/// server and Firefox serialize it.
+fn signed_content(records: &[serde_json::Value], last_modified: u64) -> String {
+    let mut records: Vec<_> = records
+        .iter()
+        .filter(|r| !r["deleted"].as_bool().unwrap_or(false))
+        .collect();
+    records.sort_by(|a, b| cmp_utf16(a["id"].as_str(), b["id"].as_str()));
+    let mut content = String::from("{\"data\":[");
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            content.push(',');
+        }
+        write_canonical_json(&mut content, record);
+    }
+    content.push_str("],\"last_modified\":\"");
+    content.push_str(&last_modified.to_string());
+    content.push_str("\"}");
+    content
+}
+
+/// Writes `value` as JSON with sorted keys and no whitespace, escaping
+/// everything outside of printable ASCII. This is what Firefox's
+/// `CanonicalJSON` does, so strings and keys are compared like JavaScript
+/// strings, and whole floats are written as integers.
+fn write_canonical_json(out: &mut String, value: &serde_json::Value) {
+    use serde_json::Value;
+    match value {
+        Value::Null | Value::Bool(_) => out.push_str(&value.to_string()),
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if !n.is_i64() && !n.is_u64() && f.fract() == 0.0 && f.abs() < 1e21 => {
+                out.push_str(&format!("{}", f));
+            }
+            _ => out.push_str(&n.to_string()),
+        },
+        Value::String(s) => write_canonical_string(out, s),
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical_json(out, value);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| cmp_utf16(Some(a), Some(b)));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical_string(out, key);
+                out.push(':');
+                write_canonical_json(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_canonical_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out.push('"');
+}
+
+/// Compares strings by their UTF-16 code units, like JavaScript does.
+fn cmp_utf16(a: Option<&str>, b: Option<&str>) -> Ordering {
+    let a = a.unwrap_or_default().encode_utf16();
+    let b = b.unwrap_or_default().encode_utf16();
+    a.cmp(b)
+}
+
+fn verify_attachment(attachment: &Attachment, body: &[u8]) -> Result<()> {
+    let hash = digest::digest(&digest::SHA256, body)?;
+    if body.len() as u64 != attachment.size
+        || !hex::encode(hash.as_ref()).eq_ignore_ascii_case(&attachment.hash)
+    {
+        return Err(ErrorKind::AttachmentCorrupt(attachment.filename.clone()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use mockito::{mock, server_address, Matcher, Mock};
+
+    /// A chain like the production one, with an RSA root, and a P-384
+    /// intermediate and signer. The tests for `rc_crypto::contentsignature`
+    /// use the same one.
+    const TEST_CHAIN: &str = "-----BEGIN CERTIFICATE-----\n\
+MIICMjCCAbmgAwIBAgIBAzAKBggqhkjOPQQDAzAsMSowKAYDVQQDDCFUZXN0IENv\n\
+bnRlbnQgU2lnbmluZyBJbnRlcm1lZGlhdGUwHhcNMjAwMTAxMDAwMDAwWhcNNDAw\n\
+MTAxMDAwMDAwWjA4MTYwNAYDVQQDDC1yZW1vdGUtc2V0dGluZ3MuY29udGVudC1z\n\
+aWduYXR1cmUubW96aWxsYS5vcmcwdjAQBgcqhkjOPQIBBgUrgQQAIgNiAASKid6G\n\
+buO7TIrFTxCqWq1A4+iddnVYKjt5tNBp/H7gnz8hixBUkLOhcnN/DacgCWgE5kjq\n\
+knAqnHdPTXPBr19y9MlwA8iWC1kylynic5pUDa4BtI3ILNPZhF2C6Pxpi76jgaIw\n\
+gZ8wDgYDVR0PAQH/BAQDAgeAMBMGA1UdJQQMMAoGCCsGAQUFBwMDMDgGA1UdEQQx\n\
+MC+CLXJlbW90ZS1zZXR0aW5ncy5jb250ZW50LXNpZ25hdHVyZS5tb3ppbGxhLm9y\n\
+ZzAdBgNVHQ4EFgQUsn7kSHIS0uXjSNkJrCOQJaI+qhswHwYDVR0jBBgwFoAUteQ7\n\
+EzguKKiVI+Hg8NsCDW/7Wb8wCgYIKoZIzj0EAwMDZwAwZAIwQr1Ta7R8yjEmrTib\n\
+0U5Ug+egX/T2tyg3uvA3Z2fI6hQAP1nPPrSD9AsBMVZIGHceAjAeTTYkRD2ByPu7\n\
+J2IOHWDvJfuKxdM9OHn1wyUBkew+RUIHqQB8kl4HWiChc7+s/lw=\n\
+-----END CERTIFICATE-----\n\
+-----BEGIN CERTIFICATE-----\n\
+MIICmjCCAYKgAwIBAgIBAjANBgkqhkiG9w0BAQwFADAkMSIwIAYDVQQDDBlUZXN0\n\
+IENvbnRlbnQgU2lnbmluZyBSb290MCAXDTIwMDEwMTAwMDAwMFoYDzIxMjAwMTAx\n\
+MDAwMDAwWjAsMSowKAYDVQQDDCFUZXN0IENvbnRlbnQgU2lnbmluZyBJbnRlcm1l\n\
+ZGlhdGUwdjAQBgcqhkjOPQIBBgUrgQQAIgNiAAThK/7kF+hQwxr7N7MVRn3PzBIe\n\
+77TAiYZCjtwL915MzTMDffEowSFSKAmSvqrCoGKYZ2RQ6j/3XzzMIrS/Hbp3v+d1\n\
+doODDURWtMdaGUF4jrOdAHJfxTQXsI0tV6vAOLyjezB5MBIGA1UdEwEB/wQIMAYB\n\
+Af8CAQAwDgYDVR0PAQH/BAQDAgIEMBMGA1UdJQQMMAoGCCsGAQUFBwMDMB0GA1Ud\n\
+DgQWBBS15DsTOC4oqJUj4eDw2wINb/tZvzAfBgNVHSMEGDAWgBTFkXVA+0wvVoy7\n\
+ONaFV71p1hl7mjANBgkqhkiG9w0BAQwFAAOCAQEAlTRVyX7HDlw1l67+uxkdssga\n\
+uNiHjmeMy/rZogtydncVT4PsfcF4FtvBIXCTui3RQq62EHHbJkN55BYke2eExgjH\n\
+7jG5/xQyrtg+N6NtZVHoNIrZmGDppcLn5KGIVbw4RYufcJcIR+y751PXRMupbMqW\n\
+D6j5xapO/C61O04GQR/0AaW8VGzuiktstH6vaIZXNHFXEpttyl2Yzy9mA9sa3WQi\n\
+9CKNgT0JaKjtH4OvD/A0s3GyQh0UzZcBMuJer/5MM5qGHuDk58Lg0/IJfUoMB//m\n\
+X04YtqrKeIje9fm8q4/1gRNN/zakYY+qtb3ojAKhsDWFND4H1KQopcVp/9CcBg==\n\
+-----END CERTIFICATE-----\n\
+-----BEGIN CERTIFICATE-----\n\
+MIIDGjCCAgKgAwIBAgIUGAl5srZYS3+SArne4ACHu6DBugUwDQYJKoZIhvcNAQEM\n\
+BQAwJDEiMCAGA1UEAwwZVGVzdCBDb250ZW50IFNpZ25pbmcgUm9vdDAgFw0yMDAx\n\
+MDEwMDAwMDBaGA8yMTIwMDEwMTAwMDAwMFowJDEiMCAGA1UEAwwZVGVzdCBDb250\n\
+ZW50IFNpZ25pbmcgUm9vdDCCASIwDQYJKoZIhvcNAQEBBQADggEPADCCAQoCggEB\n\
+AMkqaLqLk0LpZvQKXC4cpkFtQz/mZOLz439PNhbW9qX9Cbrlgo47YIzbgAGr47od\n\
+Qen/1yXm4Apy/w7EdEFxN9lE/pYqXLUWeZrDXASKEv8VxWLibvkN52hQTPi2Nzmz\n\
++0q08txDksWemxOqpreDHZepaezHrd5TDq3kIW79Aear4O4v4NO7wWPuc+SEBfdC\n\
+cUN6B3j+ere6+T9Hl2NZHCPwp2Cy6SgsGIllxOR3n2d2cMdG72H5TbuHg7ys/IJt\n\
+qH/C5xoWocEXBi2DF7O3bl5PhhEEV0c9DDmxmxQMNIabEd1f5vRMzW5yKSQzsOMG\n\
+G1tGvLy5bmcu2X8QE1GTuJcCAwEAAaNCMEAwDwYDVR0TAQH/BAUwAwEB/zAOBgNV\n\
+HQ8BAf8EBAMCAQYwHQYDVR0OBBYEFMWRdUD7TC9WjLs41oVXvWnWGXuaMA0GCSqG\n\
+SIb3DQEBDAUAA4IBAQAfIdo2eXxm2lkFaUW9btDPmudOjVLl0B/pvMiG1oqaRMfw\n\
+donCbNMIJa4P8r7sXQqS/ymxT44+khwkKn1UAZnikMGWlbAIlqMJI00MlVU67UCq\n\
+dffwragiyabpv/SOZFFDlC+2VIKYBofBnxmyqoKD6ekhpduoUCWnfzf4MCMv8yNr\n\
+7BlNtBJllMdWKCSzFT3uzeQ8AnsFkSzb6I2h/OrwJicojmxPCjsK+lpxHC7PhHH9\n\
+kDohEMNNW0Okpnbz/HNoaTVkoQZlu+ZkNG4ee5dXbIl593auqB3hai0wfGKDqbhk\n\
+x1eAfKfDP+l9jrtuaOjayrW+DwayiIuQeZVlFAZH\n\
+-----END CERTIFICATE-----\n";
+    const TEST_ROOT_HASH: &str = "A4:0E:E0:6B:1B:8F:87:99:F3:C5:26:A9:1E:48:A9:72:E6:F5:F1:D6:61:11:CD:B1:FC:6C:29:71:B0:CC:78:07";
+
+    /// A config for the mock server, with the test root.
+    pub(crate) fn test_config(collection_name: &str) -> RemoteSettingsConfig {
+        let server_url = Url::parse(&format!("http://{}", server_address())).unwrap();
+        let mut config = RemoteSettingsConfig::new(server_url, collection_name);
+        config.allow_untrusted_server = true;
+        config.root_hash = TEST_ROOT_HASH.into();
+        config
+    }
+
+    /// Serves the collection's metadata with `signature`, and the test chain,
+    /// expecting `hits` requests for each.
+    pub(crate) fn mock_signature(collection_name: &str, signature: &str, hits: usize) -> [Mock; 2] {
+        let metadata = mock(
+            "GET",
+            &*format!("/v1/buckets/main/collections/{}", collection_name),
+        )
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "data": {
+                    "id": collection_name,
+                    "signature": {
+                        "mode": "p384ecdsa",
+                        "signature": signature,
+                        "x5u": format!("http://{}/chains/test.pem", server_address()),
+                    },
+                },
+            })
+            .to_string(),
+        )
+        .expect(hits)
+        .create();
+        let chain = mock("GET", "/chains/test.pem")
+            .with_status(200)
+            .with_body(TEST_CHAIN)
+            .expect(hits)
+            .create();
+        [metadata, chain]
+    }
+
+    #[test]
+    fn test_records_url() {
+        for server in &[
+            "https://settings.example.com/",
+            "https://settings.example.com",
+        ] {
+            let config = RemoteSettingsConfig::new(Url::parse(server).unwrap(), "regions");
+            assert_eq!(
+                config.records_url().unwrap().as_str(),
+                "https://settings.example.com/v1/buckets/main/collections/regions/records"
+            );
+        }
+    }
+
+    #[test]
+    fn test_trusted_servers() {
+        for server in &[
+            "https://firefox.settings.services.mozilla.com",
+            "https://firefox.settings.services.mozilla.com/v1/",
+        ] {
+            let config = RemoteSettingsConfig::new(Url::parse(server).unwrap(), "regions");
+            Client::new(config).unwrap();
+        }
+        for server in &[
+            "http://firefox.settings.services.mozilla.com",
+            "https://firefox.settings.services.mozilla.com:8443",
+            "https://settings.example.com",
+        ] {
+            let mut config = RemoteSettingsConfig::new(Url::parse(server).unwrap(), "regions");
+            match Client::new(config.clone()).unwrap_err().kind() {
+                ErrorKind::UntrustedServer(_) => {}
+                e => panic!("Unexpected error {:?}", e),
+            }
+            config.allow_untrusted_server = true;
+            Client::new(config).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let records: RecordsResponse = serde_json::from_str(
+            r#"{"data": [
+                {"id": "a", "last_modified": 20, "deleted": true},
+                {
+                    "id": "b",
+                    "last_modified": 10,
+                    "name": "B",
+                    "attachment": {
+                        "filename": "b.bin",
+                        "mimetype": "application/octet-stream",
+                        "location": "main-workspace/regions/b.bin",
+                        "hash": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                        "size": 0
+                    }
+                }
+            ]}"#,
+        )
+        .unwrap();
+        let records: Vec<RemoteSettingsRecord> = records
+            .data
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<serde_json::Result<_>>()
+            .unwrap();
+        assert!(records[0].deleted);
+        assert!(records[0].fields.is_empty());
+        let record = &records[1];
+        assert!(!record.deleted);
+        assert_eq!(record.fields["name"], "B");
+        assert!(!record.fields.contains_key("attachment"));
+        let attachment = record.attachment.as_ref().unwrap();
+
+        rc_crypto::ensure_initialized();
+        verify_attachment(attachment, b"").unwrap();
+        assert!(verify_attachment(attachment, b"x").is_err());
+        let mut wrong_hash = attachment.clone();
+        wrong_hash.hash = "00".repeat(32);
+        wrong_hash.size = 1;
+        assert!(verify_attachment(&wrong_hash, b"x").is_err());
+    }
+
+    #[test]
+    fn test_signed_content() {
+        let records: Vec<serde_json::Value> = serde_json::from_str(
+            r#"[
+                {"last_modified": 2, "id": "b", "name": "é\n\"\\ 🦊", "size": 2.0},
+                {"id": "c", "deleted": true, "last_modified": 3},
+                {"id": "a", "last_modified": 1, "nested": {"z": [null, true, -0.5], "y": {}}}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            signed_content(&records, 3),
+            concat!(
+                r#"{"data":["#,
+                r#"{"id":"a","last_modified":1,"nested":{"y":{},"z":[null,true,-0.5]}},"#,
+                r#"{"id":"b","last_modified":2,"name":"\u00e9\n\"\\ \ud83e\udd8a","size":2}"#,
+                r#"],"last_modified":"3"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_client() {
+        viaduct_reqwest::use_reqwest_backend();
+        rc_crypto::ensure_initialized();
+        // mockito forces task serialization, so we test everything in one go.
+        let config = test_config("regions");
+        let server_url = config.server_url.clone();
+        let client = Client::new(config).unwrap();
+        let records_path = "/v1/buckets/main/collections/regions/records";
+        // The signature for `{"data":[{"id":"a","last_modified":10}],"last_modified":"30"}`.
+        let signature = "qfcYXEsEJhbcrmCeU3ofvuDr2wgV9vO5_yUhbDppkioOUR92s63FoBsbvEkPcrklwSYY\
+                         0YCgzK8aRr_ZfU6vA0WlozZL-rW4_Vzy91xVTejxekjJ3yVJB2wDC7R8Qz1H";
+        {
+            let m = mock("GET", records_path)
+                .match_query(Matcher::Missing)
+                .with_status(200)
+                .with_header("etag", "\"30\"")
+                .with_body(r#"{"data": [{"id": "a", "last_modified": 10}]}"#)
+                .create();
+            let signature_mocks = mock_signature("regions", signature, 1);
+            let resp = client.get_records().unwrap();
+            m.assert();
+            signature_mocks.iter().for_each(Mock::assert);
+            assert_eq!(resp.records.len(), 1);
+            assert_eq!(resp.last_modified, 30);
+        }
+        {
+            // Records that were changed after they were signed are rejected.
+            let m = mock("GET", records_path)
+                .match_query(Matcher::Missing)
+                .with_status(200)
+                .with_header("etag", "\"30\"")
+                .with_body(r#"{"data": [{"id": "a", "last_modified": 10, "name": "A"}]}"#)
+                .create();
+            let signature_mocks = mock_signature("regions", signature, 1);
+            match client.get_records().unwrap_err().kind() {
+                ErrorKind::InvalidSignature(collection) => assert_eq!(collection, "regions"),
+                e => panic!("Unexpected error {:?}", e),
+            }
+            m.assert();
+            signature_mocks.iter().for_each(Mock::assert);
+        }
+        {
+            // So is a collection signed by a different root.
+            let m = mock("GET", records_path)
+                .match_query(Matcher::Missing)
+                .with_status(200)
+                .with_header("etag", "\"30\"")
+                .with_body(r#"{"data": [{"id": "a", "last_modified": 10}]}"#)
+                .create();
+            let _signature_mocks = mock_signature("regions", signature, 1);
+            let mut config = client.config().clone();
+            config.root_hash = PRODUCTION_ROOT_HASH.into();
+            match Client::new(config)
+                .unwrap()
+                .get_records()
+                .unwrap_err()
+                .kind()
+            {
+                ErrorKind::InvalidSignature(_) => {}
+                e => panic!("Unexpected error {:?}", e),
+            }
+            m.assert();
+        }
+        {
+            let m = mock("GET", records_path)
+                .match_query(Matcher::UrlEncoded("_since".into(), "30".into()))
+                .with_status(200)
+                .with_body(r#"{"data": []}"#)
+                .create();
+            let resp = client.fetch_records(Some(30)).unwrap();
+            m.assert();
+            assert!(resp.records.is_empty());
+            // Without an ETag, we stay where we were.
+            assert_eq!(resp.last_modified, 30);
+        }
+        {
+            let m = mock("GET", records_path)
+                .match_query(Matcher::Any)
+                .with_status(503)
+                .create();
+            match client.fetch_records(Some(30)).unwrap_err().kind() {
+                ErrorKind::RemoteSettingsHttpError(503) => {}
+                e => panic!("Unexpected error {:?}", e),
+            }
+            m.assert();
+        }
+        {
+            let server_info = mock("GET", "/v1/")
+                .with_status(200)
+                .with_body(
+                    serde_json::json!({
+                        "capabilities": {
+                            "attachments": {
+                                "base_url": server_url.join("/attachments/").unwrap().as_str(),
+                            },
+                        },
+                    })
+                    .to_string(),
+                )
+                .expect(2)
+                .create();
+            let file = mock("GET", "/attachments/regions/a.txt")
+                .with_status(200)
+                .with_body("hello")
+                .expect(2)
+                .create();
+            let mut attachment = Attachment {
+                filename: "a.txt".into(),
+                mimetype: "text/plain".into(),
+                location: "regions/a.txt".into(),
+                hash: "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".into(),
+                size: 5,
+            };
+            assert_eq!(client.get_attachment(&attachment).unwrap(), b"hello");
+            attachment.size = 6;
+            match client.get_attachment(&attachment).unwrap_err().kind() {
+                ErrorKind::AttachmentCorrupt(filename) => assert_eq!(filename, "a.txt"),
+                e => panic!("Unexpected error {:?}", e),
+            }
+            server_info.assert();
+            file.assert();
+        }
+    }
+}
@@ -0,0 +1,48 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use failure::Fail;
+
+#[derive(Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "Remote Settings server returned HTTP status {}", _0)]
+    RemoteSettingsHttpError(u16),
+
+    #[fail(display = "{} isn't a trusted Remote Settings server", _0)]
+    UntrustedServer(String),
+
+    #[fail(display = "Server doesn't support attachments")]
+    AttachmentsUnsupported,
+
+    #[fail(display = "Attachment {} doesn't match its size or hash", _0)]
+    AttachmentCorrupt(String),
+
+    #[fail(display = "Collection {} doesn't match its content signature", _0)]
+    InvalidSignature(String),
+
+    #[fail(display = "Network error: {}", _0)]
+    RequestError(#[fail(cause)] viaduct::Error),
+
+    #[fail(display = "Error parsing JSON data: {}", _0)]
+    JsonError(#[fail(cause)] serde_json::Error),
+
+    #[fail(display = "Error parsing URL: {}", _0)]
+    UrlParseError(#[fail(cause)] url::ParseError),
+
+    #[fail(display = "Error executing SQL: {}", _0)]
+    SqlError(#[fail(cause)] rusqlite::Error),
+
+    #[fail(display = "Crypto error: {}", _0)]
+    CryptoError(#[fail(cause)] rc_crypto::Error),
+}
+
+error_support::define_error! {
+    ErrorKind {
+        (RequestError, viaduct::Error),
+        (JsonError, serde_json::Error),
+        (UrlParseError, url::ParseError),
+        (SqlError, rusqlite::Error),
+        (CryptoError, rc_crypto::Error),
+    }
+}
@@ -0,0 +1,24 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![allow(unknown_lints)]
+#![warn(rust_2018_idioms)]
+
+//! A client for Remote Settings collections, for components that need data
+//! which is published by Mozilla and updated independently of releases.
+//! `Client` fetches records and attachments, and `RemoteSettingsCache` keeps a
+//! local copy of a collection that's updated incrementally. Both check the
+//! collection's content signature before returning any records.
+
+mod cache;
+mod client;
+pub mod error;
+mod schema;
+
+pub use crate::cache::{RemoteSettingsCache, RemoteSettingsChanges};
+pub use crate::client::{
+    Attachment, Client, RemoteSettingsConfig, RemoteSettingsRecord, RemoteSettingsResponse,
+    DEFAULT_SIGNER_NAME, PRODUCTION_ROOT_HASH, TRUSTED_SERVERS,
+};
+pub use error::{Error, ErrorKind, Result};
@@ -5,9 +5,8 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
-// Note that in the future it might make sense to also add a trait for
-// an Interruptable, but we don't need this abstraction now and it's unclear
-// if we ever will.
+mod shutdown;
+pub use shutdown::{in_shutdown, register_interrupt, shutdown, Interruptable, ShutdownInterruptee};
 
 /// Represents the state of something that may be interrupted. Decoupled from
 /// the interrupt mechanics so that things which want to check if they have been
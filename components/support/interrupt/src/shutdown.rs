@@ -0,0 +1,124 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A process-wide shutdown controller. Components register the things that
+//! can interrupt their in-flight work, like their database connections, and
+//! when the application is shutting down it calls `shutdown()` once to
+//! interrupt all of them, instead of needing a handle to every component.
+//!
+//! Shutdown can't be undone: anything registered afterwards is interrupted
+//! immediately, and `ShutdownInterruptee` keeps reporting that it was
+//! interrupted.
+
+use crate::Interruptee;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, Weak,
+};
+
+/// Something which can be asked to stop what it's doing, like a database
+/// connection running a long transaction for a sync.
+pub trait Interruptable: Send + Sync {
+    fn interrupt(&self);
+}
+
+static IN_SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    // Weak, so that registering doesn't keep a closed connection alive.
+    static ref REGISTERED: Mutex<Vec<Weak<dyn Interruptable>>> = Mutex::new(Vec::new());
+}
+
+/// Registers `interruptable` to be interrupted by `shutdown()`. It's dropped
+/// from the registry once the last strong reference to it goes away.
+pub fn register_interrupt(interruptable: Weak<dyn Interruptable>) {
+    if in_shutdown() {
+        if let Some(interruptable) = interruptable.upgrade() {
+            interruptable.interrupt();
+        }
+        return;
+    }
+    let mut registered = REGISTERED.lock().unwrap();
+    registered.retain(|weak| weak.upgrade().is_some());
+    registered.push(interruptable);
+}
+
+/// Interrupts everything which has been registered, and everything which
+/// will be. Safe to call from any thread, and more than once.
+pub fn shutdown() {
+    IN_SHUTDOWN.store(true, Ordering::SeqCst);
+    // Take the registrations out so the lock isn't held while interrupting.
+    let registered = std::mem::replace(&mut *REGISTERED.lock().unwrap(), Vec::new());
+    log::info!("Shutting down; interrupting registered connections");
+    for interruptable in registered.iter().filter_map(Weak::upgrade) {
+        interruptable.interrupt();
+    }
+}
+
+/// Returns true once `shutdown()` has been called.
+pub fn in_shutdown() -> bool {
+    IN_SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// An `Interruptee` which is interrupted once `shutdown()` has been called,
+/// for work which doesn't otherwise have a way to be interrupted.
+pub struct ShutdownInterruptee;
+
+impl Interruptee for ShutdownInterruptee {
+    #[inline]
+    fn was_interrupted(&self) -> bool {
+        in_shutdown()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{atomic::AtomicUsize, Arc};
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl Interruptable for Counter {
+        fn interrupt(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl Counter {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    // This is the only test which shuts down, since shutdown is global to the
+    // process.
+    #[test]
+    fn test_shutdown() {
+        let first = Arc::new(Counter::default());
+        let second = Arc::new(Counter::default());
+        let dropped = Arc::new(Counter::default());
+        register_interrupt(Arc::downgrade(&first) as Weak<dyn Interruptable>);
+        register_interrupt(Arc::downgrade(&second) as Weak<dyn Interruptable>);
+        register_interrupt(Arc::downgrade(&dropped) as Weak<dyn Interruptable>);
+        drop(dropped);
+        assert!(!in_shutdown());
+        assert!(!ShutdownInterruptee.was_interrupted());
+
+        shutdown();
+        assert!(in_shutdown());
+        assert!(ShutdownInterruptee.err_if_interrupted().is_err());
+        assert_eq!(first.count(), 1);
+        assert_eq!(second.count(), 1);
+
+        // Shutting down again doesn't interrupt the same things twice.
+        shutdown();
+        assert_eq!(first.count(), 1);
+
+        // Things registered after shutdown are interrupted right away.
+        let late = Arc::new(Counter::default());
+        register_interrupt(Arc::downgrade(&late) as Weak<dyn Interruptable>);
+        assert_eq!(late.count(), 1);
+    }
+}
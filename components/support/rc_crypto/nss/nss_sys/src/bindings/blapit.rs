@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 pub const EC_POINT_FORM_UNCOMPRESSED: u32 = 4;
+pub const SHA1_LENGTH: u32 = 20;
 pub const SHA256_LENGTH: u32 = 32;
 pub const HASH_LENGTH_MAX: u32 = 64;
 pub const AES_BLOCK_SIZE: u32 = 16;
@@ -0,0 +1,19 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+pub use crate::*;
+use std::os::raw::{c_int, c_uchar, c_void};
+
+extern "C" {
+    pub fn VFY_VerifyDataDirect(
+        buf: *const c_uchar,
+        len: c_int,
+        key: *const SECKEYPublicKey,
+        sig: *const SECItem,
+        pubkAlg: u32, /* SECOidTag */
+        hashAlg: u32, /* SECOidTag */
+        hash: *mut u32, /* SECOidTag */
+        wincx: *mut c_void,
+    ) -> SECStatus;
+}
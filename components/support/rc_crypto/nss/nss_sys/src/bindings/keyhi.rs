@@ -9,4 +9,9 @@ extern "C" {
     pub fn SECKEY_ConvertToPublicKey(privateKey: *mut SECKEYPrivateKey) -> *mut SECKEYPublicKey;
     pub fn SECKEY_DestroyPrivateKey(key: *mut SECKEYPrivateKey);
     pub fn SECKEY_DestroyPublicKey(key: *mut SECKEYPublicKey);
+    pub fn SECKEY_DecodeDERSubjectPublicKeyInfo(
+        spkider: *const SECItem,
+    ) -> *mut CERTSubjectPublicKeyInfo;
+    pub fn SECKEY_DestroySubjectPublicKeyInfo(spki: *mut CERTSubjectPublicKeyInfo);
+    pub fn SECKEY_ExtractPublicKey(spki: *const CERTSubjectPublicKeyInfo) -> *mut SECKEYPublicKey;
 }
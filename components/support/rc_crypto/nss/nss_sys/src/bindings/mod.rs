@@ -4,6 +4,10 @@
 
 mod blapit;
 pub use blapit::*;
+mod certt;
+pub use certt::*;
+mod cryptohi;
+pub use cryptohi::*;
 mod keyhi;
 pub use keyhi::*;
 mod keythi;
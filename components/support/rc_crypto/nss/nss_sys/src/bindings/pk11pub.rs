@@ -40,6 +40,21 @@ extern "C" {
         sharedData: *mut SECItem,
         wincx: *mut c_void,
     ) -> *mut PK11SymKey;
+    pub fn PK11_CreatePBEV2AlgorithmID(
+        pbeAlgTag: u32,    /* SECOidTag */
+        cipherAlgTag: u32, /* SECOidTag */
+        prfAlgTag: u32,    /* SECOidTag */
+        keyLength: c_int,
+        iteration: c_int,
+        salt: *mut SECItem,
+    ) -> *mut SECAlgorithmID;
+    pub fn PK11_PBEKeyGen(
+        slot: *mut PK11SlotInfo,
+        algid: *mut SECAlgorithmID,
+        pwitem: *mut SECItem,
+        faulty3DES: PRBool,
+        wincx: *mut c_void,
+    ) -> *mut PK11SymKey;
     pub fn PK11_ExtractKeyValue(symKey: *mut PK11SymKey) -> SECStatus;
     pub fn PK11_GetKeyData(symKey: *mut PK11SymKey) -> *mut SECItem;
     pub fn PK11_GenerateKeyPair(
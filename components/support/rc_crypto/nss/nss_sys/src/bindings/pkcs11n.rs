@@ -4,6 +4,7 @@
 
 pub use crate::*;
 
+pub const CKM_NSS_HKDF_SHA1: u32 = 3_461_563_219; // (CKM_NSS + 3)
 pub const CKM_NSS_HKDF_SHA256: u32 = 3_461_563_220; // (CKM_NSS + 4)
 
 pub type CK_GCM_PARAMS = CK_GCM_PARAMS_V3;
@@ -39,6 +39,7 @@ pub const CKA_WRAP: u32 = 262;
 pub const CKA_SIGN: u32 = 264;
 pub const CKA_EC_PARAMS: u32 = 384;
 pub const CKA_EC_POINT: u32 = 385;
+pub const CKM_SHA_1_HMAC: u32 = 545;
 pub const CKM_SHA256_HMAC: u32 = 593;
 pub const CKM_SHA512_HMAC: u32 = 625;
 pub const CKM_EC_KEY_PAIR_GEN: u32 = 4160;
@@ -16,6 +16,14 @@ pub struct SECOidDataStr {
 }
 pub type SECOidData = SECOidDataStr;
 
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SECAlgorithmIDStr {
+    pub algorithm: SECItem,
+    pub parameters: SECItem,
+}
+pub type SECAlgorithmID = SECAlgorithmIDStr;
+
 pub enum SECSupportExtenTag {
     INVALID_CERT_EXTENSION = 0,
     UNSUPPORTED_CERT_EXTENSION = 1,
@@ -12,5 +12,6 @@ pub mod ecdh;
 mod error;
 pub mod pk11;
 pub mod secport;
+pub mod signature;
 pub use crate::error::{Error, ErrorKind, Result};
 pub use util::ensure_nss_initialized as ensure_initialized;
@@ -4,7 +4,11 @@
 
 use crate::{
     error::*,
-    pk11::{context::HashAlgorithm, slot, types::SymKey},
+    pk11::{
+        context::HashAlgorithm,
+        slot,
+        types::{AlgorithmID, SymKey},
+    },
     util::{ensure_nss_initialized, map_nss_secstatus, sec_item_as_slice, ScopedPtr},
 };
 use std::{
@@ -65,6 +69,60 @@ pub fn hkdf_expand(
     Ok(buf.to_vec())
 }
 
+/// Derives `len` bytes from `password` with PBKDF2 (RFC 8018, section 5.2),
+/// using HMAC with `digest_alg` as the pseudorandom function.
+pub fn pbkdf2_key_derive(
+    digest_alg: &HashAlgorithm,
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    len: usize,
+) -> Result<Vec<u8>> {
+    ensure_nss_initialized();
+    // Like `hkdf_expand`, this follows the Firefox WebCrypto implementation
+    // (see `DerivePbkdfBitsTask` in dom/crypto/WebCryptoTask.cpp).
+    let mut salt_item = nss_sys::SECItem {
+        type_: nss_sys::SECItemType::siBuffer as u32,
+        data: salt.as_ptr() as *mut c_uchar,
+        len: c_uint::try_from(salt.len())?,
+    };
+    let derived_len = i32::try_from(len)?;
+    let algorithm_id = unsafe {
+        // The cipher doesn't matter, as we only use the derived key's bytes.
+        AlgorithmID::from_ptr(nss_sys::PK11_CreatePBEV2AlgorithmID(
+            nss_sys::SECOidTag::SEC_OID_PKCS5_PBKDF2 as u32,
+            nss_sys::SECOidTag::SEC_OID_HMAC_SHA1 as u32,
+            digest_alg.as_hmac_oid() as u32,
+            derived_len,
+            i32::try_from(iterations)?,
+            &mut salt_item,
+        ))?
+    };
+    let mut password_item = nss_sys::SECItem {
+        type_: nss_sys::SECItemType::siBuffer as u32,
+        data: password.as_ptr() as *mut c_uchar,
+        len: c_uint::try_from(password.len())?,
+    };
+    let slot = slot::get_internal_slot()?;
+    let sym_key = unsafe {
+        SymKey::from_ptr(nss_sys::PK11_PBEKeyGen(
+            slot.as_mut_ptr(),
+            algorithm_id.as_mut_ptr(),
+            &mut password_item,
+            nss_sys::PR_FALSE,
+            ptr::null_mut(),
+        ))?
+    };
+    map_nss_secstatus(|| unsafe { nss_sys::PK11_ExtractKeyValue(sym_key.as_mut_ptr()) })?;
+    // As in `hkdf_expand`, `key_data` is owned by `sym_key`.
+    let mut key_data = unsafe { *nss_sys::PK11_GetKeyData(sym_key.as_mut_ptr()) };
+    if u32::try_from(len)? > key_data.len {
+        return Err(ErrorKind::InternalError.into());
+    }
+    let buf = unsafe { sec_item_as_slice(&mut key_data)? };
+    Ok(buf[..len].to_vec())
+}
+
 /// Safe wrapper around PK11_ImportSymKey that
 /// de-allocates memory when the key goes out of
 /// scope.
@@ -32,12 +32,22 @@ scoped_ptr!(
 );
 scoped_ptr!(Context, nss_sys::PK11Context, pk11_destroy_context_true);
 scoped_ptr!(Slot, nss_sys::PK11SlotInfo, nss_sys::PK11_FreeSlot);
+scoped_ptr!(
+    AlgorithmID,
+    nss_sys::SECAlgorithmID,
+    secoid_destroy_algorithm_id_true
+);
 
 #[inline]
 unsafe fn pk11_destroy_context_true(context: *mut nss_sys::PK11Context) {
     nss_sys::PK11_DestroyContext(context, nss_sys::PR_TRUE);
 }
 
+#[inline]
+unsafe fn secoid_destroy_algorithm_id_true(algorithm_id: *mut nss_sys::SECAlgorithmID) {
+    nss_sys::SECOID_DestroyAlgorithmID(algorithm_id, nss_sys::PR_TRUE);
+}
+
 // Trait for types that have PCKS#11 attributes that are readable. See
 // https://searchfox.org/mozilla-central/rev/8ed8474757695cdae047150a0eaf94a5f1c96dbe/security/nss/lib/pk11wrap/pk11pub.h#842-864
 pub(crate) unsafe trait Pkcs11Object: ScopedPtr {
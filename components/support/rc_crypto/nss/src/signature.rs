@@ -0,0 +1,80 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{
+    error::*,
+    pk11::types::PublicKey,
+    util::{ensure_nss_initialized, map_nss_secstatus, ScopedPtr},
+};
+use std::{
+    convert::TryFrom,
+    os::raw::{c_int, c_uchar, c_uint},
+    ptr,
+};
+
+scoped_ptr!(
+    SubjectPublicKeyInfo,
+    nss_sys::CERTSubjectPublicKeyInfo,
+    nss_sys::SECKEY_DestroySubjectPublicKeyInfo
+);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignatureAlgorithm {
+    EcdsaSha256,
+    EcdsaSha384,
+    RsaPkcs1Sha256,
+    RsaPkcs1Sha384,
+}
+
+impl SignatureAlgorithm {
+    fn oid_tags(self) -> (nss_sys::SECOidTag, nss_sys::SECOidTag) {
+        use nss_sys::SECOidTag::*;
+        match self {
+            SignatureAlgorithm::EcdsaSha256 => (SEC_OID_ANSIX962_EC_PUBLIC_KEY, SEC_OID_SHA256),
+            SignatureAlgorithm::EcdsaSha384 => (SEC_OID_ANSIX962_EC_PUBLIC_KEY, SEC_OID_SHA384),
+            SignatureAlgorithm::RsaPkcs1Sha256 => (SEC_OID_PKCS1_RSA_ENCRYPTION, SEC_OID_SHA256),
+            SignatureAlgorithm::RsaPkcs1Sha384 => (SEC_OID_PKCS1_RSA_ENCRYPTION, SEC_OID_SHA384),
+        }
+    }
+}
+
+/// Verifies `signature` over `data`, with the public key in `spki`, which is a
+/// DER-encoded `SubjectPublicKeyInfo`, like the one in an X.509 certificate.
+/// ECDSA signatures are DER-encoded too, as they are in certificates.
+pub fn verify(
+    spki: &[u8],
+    algorithm: SignatureAlgorithm,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    ensure_nss_initialized();
+    let spki_item = nss_sys::SECItem {
+        type_: nss_sys::SECItemType::siBuffer as u32,
+        data: spki.as_ptr() as *mut c_uchar,
+        len: c_uint::try_from(spki.len())?,
+    };
+    let spki = unsafe {
+        SubjectPublicKeyInfo::from_ptr(nss_sys::SECKEY_DecodeDERSubjectPublicKeyInfo(&spki_item))?
+    };
+    let key = unsafe { PublicKey::from_ptr(nss_sys::SECKEY_ExtractPublicKey(spki.as_ptr()))? };
+    let signature_item = nss_sys::SECItem {
+        type_: nss_sys::SECItemType::siBuffer as u32,
+        data: signature.as_ptr() as *mut c_uchar,
+        len: c_uint::try_from(signature.len())?,
+    };
+    let data_len = c_int::try_from(data.len())?;
+    let (key_tag, hash_tag) = algorithm.oid_tags();
+    map_nss_secstatus(|| unsafe {
+        nss_sys::VFY_VerifyDataDirect(
+            data.as_ptr(),
+            data_len,
+            key.as_ptr(),
+            &signature_item,
+            key_tag as u32,
+            hash_tag as u32,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    })
+}
@@ -16,6 +16,8 @@ fn main() {
 
     let mut cfg = ctest::TestGenerator::new();
     cfg.header("blapit.h")
+        .header("certt.h")
+        .header("cryptohi.h")
         .header("keyhi.h")
         .header("keythi.h")
         .header("nss.h")
@@ -51,6 +53,7 @@ fn main() {
             || s == "NSSInitContext"
             || s == "NSSInitParameters"
             || s == "PK11GenericObject"
+            || s == "CERTSubjectPublicKeyInfo"
     });
     cfg.skip_field_type(|s, field| {
         s == "SECKEYPublicKeyStr" && field == "u" // inline union
@@ -34,6 +34,33 @@ pub static ECDH_P256: Algorithm = Algorithm {
     curve_id: ec::Curve::P256,
 };
 
+impl Algorithm {
+    /// The length of each coordinate of a public key, in bytes.
+    fn coordinate_len(&self) -> usize {
+        match self.curve_id {
+            ec::Curve::P256 => 32,
+        }
+    }
+}
+
+/// The first byte of a public key in uncompressed form (see SECG SEC1 section
+/// 2.3.3), followed by the `x` and `y` coordinates.
+const UNCOMPRESSED_POINT_TAG: u8 = 0x04;
+
+/// Builds the public key with coordinates `x` and `y`, like the ones in a JWK,
+/// in the uncompressed form that `agree` and `agree_static` take.
+pub fn public_key_from_coordinates(alg: &Algorithm, x: &[u8], y: &[u8]) -> Result<Vec<u8>> {
+    let len = alg.coordinate_len();
+    if x.len() != len || y.len() != len {
+        return Err(ErrorKind::InternalError.into());
+    }
+    let mut bytes = Vec::with_capacity(1 + 2 * len);
+    bytes.push(UNCOMPRESSED_POINT_TAG);
+    bytes.extend_from_slice(x);
+    bytes.extend_from_slice(y);
+    Ok(bytes)
+}
+
 /// How many times the key may be used.
 pub trait Lifetime {}
 
@@ -116,6 +143,16 @@ impl PublicKey {
     pub fn algorithm(&self) -> &'static Algorithm {
         self.alg
     }
+
+    /// The `x` and `y` coordinates of the key, like the ones in a JWK.
+    pub fn to_coordinates(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let bytes = self.to_bytes()?;
+        let len = self.alg.coordinate_len();
+        if bytes.len() != 1 + 2 * len || bytes[0] != UNCOMPRESSED_POINT_TAG {
+            return Err(ErrorKind::InternalError.into());
+        }
+        Ok((bytes[1..=len].to_vec(), bytes[1 + len..].to_vec()))
+    }
 }
 
 /// A private key for key agreement.
@@ -348,6 +385,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_public_key_coordinates() {
+        let x = base64::decode_config(PRIV_KEY_1_JWK_X, base64::URL_SAFE_NO_PAD).unwrap();
+        let y = base64::decode_config(PRIV_KEY_1_JWK_Y, base64::URL_SAFE_NO_PAD).unwrap();
+        let pub_key = public_key_from_coordinates(&ECDH_P256, &x, &y).unwrap();
+        assert_eq!(
+            pub_key,
+            base64::decode_config(PUB_KEY_1_B64, base64::URL_SAFE_NO_PAD).unwrap()
+        );
+        assert!(public_key_from_coordinates(&ECDH_P256, &x[1..], &y).is_err());
+        assert!(public_key_from_coordinates(&ECDH_P256, &x, &[]).is_err());
+
+        let computed_pub_key = load_priv_key_1().compute_public_key().unwrap();
+        assert_eq!(computed_pub_key.to_coordinates().unwrap(), (x, y));
+    }
+
     #[test]
     fn test_agreement_rejects_invalid_pubkeys() {
         let prv_key = load_priv_key_2();
@@ -0,0 +1,646 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Verifies the content signatures that Mozilla's Autograph service makes
+//! for Remote Settings collections, and other signed content.
+//!
+//! A content signature is a P-384 ECDSA signature, with SHA-384, over the
+//! signed content prefixed by `Content-Signature:\0`. The signer's
+//! certificate comes with its issuers in a PEM chain, which ends with a root
+//! whose SHA-256 fingerprint we know in advance. We only parse as much of the
+//! certificates as we need to check the chain: the issuer and subject names,
+//! the validity period, the signer's key and `dNSName`s, and the signatures.
+
+use crate::digest;
+use crate::error::*;
+use nss::signature::SignatureAlgorithm;
+
+const CONTENT_SIGNATURE_PREFIX: &[u8] = b"Content-Signature:\x00";
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OBJECT_ID: u8 = 0x06;
+const BOOLEAN: u8 = 0x01;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const VERSION: u8 = 0xa0;
+const ISSUER_UNIQUE_ID: u8 = 0x81;
+const SUBJECT_UNIQUE_ID: u8 = 0x82;
+const EXTENSIONS: u8 = 0xa3;
+const DNS_NAME: u8 = 0x82;
+
+// The DER contents of the object identifiers we look for.
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_SECP384R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// The length of each of the `r` and `s` values in a P-384 signature.
+const P384_SCALAR_LEN: usize = 48;
+
+/// Verifies that `signature`, which is base64url-encoded, is a valid content
+/// signature for `input`.
+///
+/// `pem_bytes` is the certificate chain, starting with the signer and ending
+/// with the root, whose SHA-256 fingerprint must be `root_sha256_hash` (as
+/// colon-separated hex, like `openssl x509 -fingerprint -sha256` prints it).
+/// Every certificate must be valid at `seconds_now`, and the signer's must be
+/// for `hostname`.
+pub fn verify(
+    input: &[u8],
+    signature: &[u8],
+    pem_bytes: &[u8],
+    seconds_now: u64,
+    root_sha256_hash: &str,
+    hostname: &str,
+) -> Result<()> {
+    let ders = decode_pem_chain(pem_bytes)?;
+    if ders.len() < 2 {
+        return Err(invalid("the certificate chain is too short"));
+    }
+    let certs = ders
+        .iter()
+        .map(|der| Certificate::parse(der))
+        .collect::<Result<Vec<_>>>()?;
+
+    let root = &certs[certs.len() - 1];
+    let fingerprint = digest::digest(&digest::SHA256, root.der)?;
+    if !fingerprint_matches(fingerprint.as_ref(), root_sha256_hash) {
+        return Err(invalid("the root certificate isn't the one we expected"));
+    }
+
+    let now = seconds_now as i64;
+    for cert in &certs {
+        if now < cert.not_before || now > cert.not_after {
+            return Err(invalid("a certificate in the chain isn't valid now"));
+        }
+    }
+    for pair in certs.windows(2) {
+        let (cert, issuer) = (&pair[0], &pair[1]);
+        if cert.issuer != issuer.subject {
+            return Err(invalid("the certificate chain is out of order"));
+        }
+        nss::signature::verify(
+            issuer.spki,
+            cert.signature_algorithm,
+            cert.tbs,
+            cert.signature,
+        )
+        .map_err(|_| invalid("a certificate isn't signed by its issuer"))?;
+    }
+
+    let leaf = &certs[0];
+    if !leaf
+        .dns_names
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(hostname.as_bytes()))
+    {
+        return Err(invalid("the signing certificate is for a different host"));
+    }
+    if !leaf.has_p384_key()? {
+        return Err(invalid("the signing certificate doesn't have a P-384 key"));
+    }
+
+    let signature = decode_base64url(signature)?;
+    if signature.len() != 2 * P384_SCALAR_LEN {
+        return Err(invalid("the signature has the wrong length"));
+    }
+    let mut message = Vec::with_capacity(CONTENT_SIGNATURE_PREFIX.len() + input.len());
+    message.extend_from_slice(CONTENT_SIGNATURE_PREFIX);
+    message.extend_from_slice(input);
+    nss::signature::verify(
+        leaf.spki,
+        SignatureAlgorithm::EcdsaSha384,
+        &message,
+        &encode_ecdsa_signature(&signature),
+    )
+    .map_err(|_| invalid("the signature doesn't match the content"))
+}
+
+fn invalid(reason: &str) -> Error {
+    ErrorKind::InvalidContentSignature(reason.into()).into()
+}
+
+fn malformed() -> Error {
+    invalid("a certificate is malformed")
+}
+
+fn decode_pem_chain(pem_bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut pem = std::str::from_utf8(pem_bytes).map_err(|_| malformed())?;
+    let mut ders = Vec::new();
+    while let Some(begin) = pem.find(BEGIN) {
+        let rest = &pem[begin + BEGIN.len()..];
+        let end = rest.find(END).ok_or_else(malformed)?;
+        let base64: String = rest[..end]
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect();
+        ders.push(base64::decode(&base64).map_err(|_| malformed())?);
+        pem = &rest[end + END.len()..];
+    }
+    Ok(ders)
+}
+
+fn decode_base64url(signature: &[u8]) -> Result<Vec<u8>> {
+    // A P-384 signature doesn't need padding, but allow it anyway.
+    let unpadded = match signature.iter().position(|&b| b == b'=') {
+        Some(pos) => &signature[..pos],
+        None => signature,
+    };
+    base64::decode_config(unpadded, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| invalid("the signature isn't base64url-encoded"))
+}
+
+fn fingerprint_matches(fingerprint: &[u8], expected: &str) -> bool {
+    let expected = expected.replace(':', "");
+    expected.len() == 2 * fingerprint.len()
+        && hex_encode(fingerprint).eq_ignore_ascii_case(&expected)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Converts a raw `r || s` signature, which is what Autograph makes, into the
+/// DER `ECDSA-Sig-Value` that NSS verifies.
+fn encode_ecdsa_signature(raw: &[u8]) -> Vec<u8> {
+    let (r, s) = raw.split_at(raw.len() / 2);
+    let mut ints = Vec::with_capacity(raw.len() + 6);
+    for scalar in &[r, s] {
+        // DER integers are minimal and signed, so we strip the leading
+        // zeros, and add one back if the high bit is set.
+        let start = scalar
+            .iter()
+            .position(|&b| b != 0)
+            .unwrap_or(scalar.len() - 1);
+        let scalar = &scalar[start..];
+        let pad = scalar[0] & 0x80 != 0;
+        ints.push(INTEGER);
+        ints.push((scalar.len() + pad as usize) as u8);
+        if pad {
+            ints.push(0);
+        }
+        ints.extend_from_slice(scalar);
+    }
+    // P-384 scalars are short enough that both lengths fit in one byte.
+    let mut der = vec![SEQUENCE, ints.len() as u8];
+    der.extend_from_slice(&ints);
+    der
+}
+
+/// The parts of an X.509 certificate that we check.
+struct Certificate<'a> {
+    der: &'a [u8],
+    /// The encoded `TBSCertificate`, which is what the issuer signs.
+    tbs: &'a [u8],
+    signature_algorithm: SignatureAlgorithm,
+    signature: &'a [u8],
+    /// The encoded issuer and subject names, which we compare byte for byte.
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    not_before: i64,
+    not_after: i64,
+    /// The encoded `SubjectPublicKeyInfo`.
+    spki: &'a [u8],
+    dns_names: Vec<&'a [u8]>,
+}
+
+impl<'a> Certificate<'a> {
+    fn parse(der: &'a [u8]) -> Result<Self> {
+        let mut outer = Reader::new(der);
+        let mut cert = outer.nested(SEQUENCE)?;
+        outer.finish()?;
+        let tbs = cert.read_encoded(SEQUENCE)?;
+        let signature_algorithm = cert.read_encoded(SEQUENCE)?;
+        let signature = read_bit_string(&mut cert)?;
+        cert.finish()?;
+
+        let mut tbs_reader = Reader::new(tbs).nested(SEQUENCE)?;
+        tbs_reader.read_optional(VERSION)?;
+        tbs_reader.read(INTEGER)?;
+        // The algorithm in the signed part must match the outer one.
+        if tbs_reader.read_encoded(SEQUENCE)? != signature_algorithm {
+            return Err(malformed());
+        }
+        let issuer = tbs_reader.read_encoded(SEQUENCE)?;
+        let mut validity = tbs_reader.nested(SEQUENCE)?;
+        let not_before = read_time(&mut validity)?;
+        let not_after = read_time(&mut validity)?;
+        validity.finish()?;
+        let subject = tbs_reader.read_encoded(SEQUENCE)?;
+        let spki = tbs_reader.read_encoded(SEQUENCE)?;
+        tbs_reader.read_optional(ISSUER_UNIQUE_ID)?;
+        tbs_reader.read_optional(SUBJECT_UNIQUE_ID)?;
+        let dns_names = match tbs_reader.read_optional(EXTENSIONS)? {
+            Some(extensions) => read_dns_names(extensions)?,
+            None => Vec::new(),
+        };
+        tbs_reader.finish()?;
+
+        Ok(Certificate {
+            der,
+            tbs,
+            signature_algorithm: parse_signature_algorithm(signature_algorithm)?,
+            signature,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            spki,
+            dns_names,
+        })
+    }
+
+    fn has_p384_key(&self) -> Result<bool> {
+        let mut spki = Reader::new(self.spki).nested(SEQUENCE)?;
+        let mut algorithm = spki.nested(SEQUENCE)?;
+        Ok(algorithm.read(OBJECT_ID)? == OID_EC_PUBLIC_KEY
+            && algorithm.read(OBJECT_ID)? == OID_SECP384R1)
+    }
+}
+
+fn parse_signature_algorithm(encoded: &[u8]) -> Result<SignatureAlgorithm> {
+    let mut algorithm = Reader::new(encoded).nested(SEQUENCE)?;
+    let oid = algorithm.read(OBJECT_ID)?;
+    // RSA signatures have NULL parameters, which are sometimes left out;
+    // ECDSA ones have none.
+    algorithm.read_optional(NULL)?;
+    algorithm.finish()?;
+    Ok(match oid {
+        OID_ECDSA_WITH_SHA256 => SignatureAlgorithm::EcdsaSha256,
+        OID_ECDSA_WITH_SHA384 => SignatureAlgorithm::EcdsaSha384,
+        OID_SHA256_WITH_RSA => SignatureAlgorithm::RsaPkcs1Sha256,
+        OID_SHA384_WITH_RSA => SignatureAlgorithm::RsaPkcs1Sha384,
+        _ => {
+            return Err(invalid(
+                "a certificate uses an unsupported signature algorithm",
+            ))
+        }
+    })
+}
+
+fn read_bit_string<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8]> {
+    let bits = reader.read(BIT_STRING)?;
+    // The first byte is the number of unused bits at the end, which is
+    // always 0 for signatures.
+    match bits.split_first() {
+        Some((0, bytes)) => Ok(bytes),
+        _ => Err(malformed()),
+    }
+}
+
+fn read_dns_names(extensions: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut names = Vec::new();
+    let mut extensions = Reader::new(extensions).nested(SEQUENCE)?;
+    while !extensions.is_empty() {
+        let mut extension = extensions.nested(SEQUENCE)?;
+        let oid = extension.read(OBJECT_ID)?;
+        extension.read_optional(BOOLEAN)?;
+        let value = extension.read(OCTET_STRING)?;
+        extension.finish()?;
+        if oid != OID_SUBJECT_ALT_NAME {
+            continue;
+        }
+        let mut general_names = Reader::new(value).nested(SEQUENCE)?;
+        while !general_names.is_empty() {
+            let (tag, contents) = general_names.read_any()?;
+            if tag == DNS_NAME {
+                names.push(contents);
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Reads a `UTCTime` or `GeneralizedTime`, as seconds since the epoch.
+fn read_time(reader: &mut Reader<'_>) -> Result<i64> {
+    let (tag, contents) = reader.read_any()?;
+    let (year, rest) = match (tag, contents.len()) {
+        (UTC_TIME, 13) => {
+            let year = parse_digits(&contents[..2])?;
+            // Two-digit years are 1950 to 2049.
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &contents[2..],
+            )
+        }
+        (GENERALIZED_TIME, 15) => (parse_digits(&contents[..4])?, &contents[4..]),
+        _ => return Err(malformed()),
+    };
+    if rest[10] != b'Z' {
+        return Err(malformed());
+    }
+    let month = parse_digits(&rest[0..2])?;
+    let day = parse_digits(&rest[2..4])?;
+    let hour = parse_digits(&rest[4..6])?;
+    let minute = parse_digits(&rest[6..8])?;
+    let second = parse_digits(&rest[8..10])?;
+    if month < 1 || month > 12 || day < 1 || day > 31 || hour > 23 || minute > 59 || second > 59 {
+        return Err(malformed());
+    }
+    Ok(days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+fn parse_digits(digits: &[u8]) -> Result<i64> {
+    digits.iter().try_fold(0, |value, &digit| {
+        if digit.is_ascii_digit() {
+            Ok(value * 10 + i64::from(digit - b'0'))
+        } else {
+            Err(malformed())
+        }
+    })
+}
+
+/// The number of days from 1970-01-01 to the given date, in the proleptic
+/// Gregorian calendar. This is Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Reads DER values with single-byte tags, which is all that certificates
+/// use.
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Self { input }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    fn finish(&self) -> Result<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(malformed())
+        }
+    }
+
+    /// Reads the next value, returning its tag, its contents, and the whole
+    /// encoding.
+    fn read_value(&mut self) -> Result<(u8, &'a [u8], &'a [u8])> {
+        let input = self.input;
+        if input.len() < 2 {
+            return Err(malformed());
+        }
+        let tag = input[0];
+        let (len, header_len) = match input[1] {
+            len @ 0..=0x7f => (usize::from(len), 2),
+            // The long form, with the length in the next 1 to 4 bytes.
+            len_len @ 0x81..=0x84 => {
+                let len_len = usize::from(len_len & 0x7f);
+                let len_bytes = input.get(2..2 + len_len).ok_or_else(malformed)?;
+                if len_bytes[0] == 0 {
+                    return Err(malformed());
+                }
+                let len = len_bytes
+                    .iter()
+                    .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+                if len < 0x80 {
+                    return Err(malformed());
+                }
+                (len, 2 + len_len)
+            }
+            _ => return Err(malformed()),
+        };
+        let end = header_len.checked_add(len).ok_or_else(malformed)?;
+        if end > input.len() {
+            return Err(malformed());
+        }
+        self.input = &input[end..];
+        Ok((tag, &input[header_len..end], &input[..end]))
+    }
+
+    fn read_any(&mut self) -> Result<(u8, &'a [u8])> {
+        let (tag, contents, _) = self.read_value()?;
+        Ok((tag, contents))
+    }
+
+    fn read(&mut self, expected_tag: u8) -> Result<&'a [u8]> {
+        match self.read_value()? {
+            (tag, contents, _) if tag == expected_tag => Ok(contents),
+            _ => Err(malformed()),
+        }
+    }
+
+    fn read_encoded(&mut self, expected_tag: u8) -> Result<&'a [u8]> {
+        match self.read_value()? {
+            (tag, _, encoded) if tag == expected_tag => Ok(encoded),
+            _ => Err(malformed()),
+        }
+    }
+
+    fn read_optional(&mut self, expected_tag: u8) -> Result<Option<&'a [u8]>> {
+        match self.input.first() {
+            Some(&tag) if tag == expected_tag => Ok(Some(self.read(expected_tag)?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn nested(&mut self, expected_tag: u8) -> Result<Reader<'a>> {
+        Ok(Reader::new(self.read(expected_tag)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAIN: &str = "-----BEGIN CERTIFICATE-----\n\
+MIICMjCCAbmgAwIBAgIBAzAKBggqhkjOPQQDAzAsMSowKAYDVQQDDCFUZXN0IENv\n\
+bnRlbnQgU2lnbmluZyBJbnRlcm1lZGlhdGUwHhcNMjAwMTAxMDAwMDAwWhcNNDAw\n\
+MTAxMDAwMDAwWjA4MTYwNAYDVQQDDC1yZW1vdGUtc2V0dGluZ3MuY29udGVudC1z\n\
+aWduYXR1cmUubW96aWxsYS5vcmcwdjAQBgcqhkjOPQIBBgUrgQQAIgNiAASKid6G\n\
+buO7TIrFTxCqWq1A4+iddnVYKjt5tNBp/H7gnz8hixBUkLOhcnN/DacgCWgE5kjq\n\
+knAqnHdPTXPBr19y9MlwA8iWC1kylynic5pUDa4BtI3ILNPZhF2C6Pxpi76jgaIw\n\
+gZ8wDgYDVR0PAQH/BAQDAgeAMBMGA1UdJQQMMAoGCCsGAQUFBwMDMDgGA1UdEQQx\n\
+MC+CLXJlbW90ZS1zZXR0aW5ncy5jb250ZW50LXNpZ25hdHVyZS5tb3ppbGxhLm9y\n\
+ZzAdBgNVHQ4EFgQUsn7kSHIS0uXjSNkJrCOQJaI+qhswHwYDVR0jBBgwFoAUteQ7\n\
+EzguKKiVI+Hg8NsCDW/7Wb8wCgYIKoZIzj0EAwMDZwAwZAIwQr1Ta7R8yjEmrTib\n\
+0U5Ug+egX/T2tyg3uvA3Z2fI6hQAP1nPPrSD9AsBMVZIGHceAjAeTTYkRD2ByPu7\n\
+J2IOHWDvJfuKxdM9OHn1wyUBkew+RUIHqQB8kl4HWiChc7+s/lw=\n\
+-----END CERTIFICATE-----\n\
+-----BEGIN CERTIFICATE-----\n\
+MIICmjCCAYKgAwIBAgIBAjANBgkqhkiG9w0BAQwFADAkMSIwIAYDVQQDDBlUZXN0\n\
+IENvbnRlbnQgU2lnbmluZyBSb290MCAXDTIwMDEwMTAwMDAwMFoYDzIxMjAwMTAx\n\
+MDAwMDAwWjAsMSowKAYDVQQDDCFUZXN0IENvbnRlbnQgU2lnbmluZyBJbnRlcm1l\n\
+ZGlhdGUwdjAQBgcqhkjOPQIBBgUrgQQAIgNiAAThK/7kF+hQwxr7N7MVRn3PzBIe\n\
+77TAiYZCjtwL915MzTMDffEowSFSKAmSvqrCoGKYZ2RQ6j/3XzzMIrS/Hbp3v+d1\n\
+doODDURWtMdaGUF4jrOdAHJfxTQXsI0tV6vAOLyjezB5MBIGA1UdEwEB/wQIMAYB\n\
+Af8CAQAwDgYDVR0PAQH/BAQDAgIEMBMGA1UdJQQMMAoGCCsGAQUFBwMDMB0GA1Ud\n\
+DgQWBBS15DsTOC4oqJUj4eDw2wINb/tZvzAfBgNVHSMEGDAWgBTFkXVA+0wvVoy7\n\
+ONaFV71p1hl7mjANBgkqhkiG9w0BAQwFAAOCAQEAlTRVyX7HDlw1l67+uxkdssga\n\
+uNiHjmeMy/rZogtydncVT4PsfcF4FtvBIXCTui3RQq62EHHbJkN55BYke2eExgjH\n\
+7jG5/xQyrtg+N6NtZVHoNIrZmGDppcLn5KGIVbw4RYufcJcIR+y751PXRMupbMqW\n\
+D6j5xapO/C61O04GQR/0AaW8VGzuiktstH6vaIZXNHFXEpttyl2Yzy9mA9sa3WQi\n\
+9CKNgT0JaKjtH4OvD/A0s3GyQh0UzZcBMuJer/5MM5qGHuDk58Lg0/IJfUoMB//m\n\
+X04YtqrKeIje9fm8q4/1gRNN/zakYY+qtb3ojAKhsDWFND4H1KQopcVp/9CcBg==\n\
+-----END CERTIFICATE-----\n\
+-----BEGIN CERTIFICATE-----\n\
+MIIDGjCCAgKgAwIBAgIUGAl5srZYS3+SArne4ACHu6DBugUwDQYJKoZIhvcNAQEM\n\
+BQAwJDEiMCAGA1UEAwwZVGVzdCBDb250ZW50IFNpZ25pbmcgUm9vdDAgFw0yMDAx\n\
+MDEwMDAwMDBaGA8yMTIwMDEwMTAwMDAwMFowJDEiMCAGA1UEAwwZVGVzdCBDb250\n\
+ZW50IFNpZ25pbmcgUm9vdDCCASIwDQYJKoZIhvcNAQEBBQADggEPADCCAQoCggEB\n\
+AMkqaLqLk0LpZvQKXC4cpkFtQz/mZOLz439PNhbW9qX9Cbrlgo47YIzbgAGr47od\n\
+Qen/1yXm4Apy/w7EdEFxN9lE/pYqXLUWeZrDXASKEv8VxWLibvkN52hQTPi2Nzmz\n\
++0q08txDksWemxOqpreDHZepaezHrd5TDq3kIW79Aear4O4v4NO7wWPuc+SEBfdC\n\
+cUN6B3j+ere6+T9Hl2NZHCPwp2Cy6SgsGIllxOR3n2d2cMdG72H5TbuHg7ys/IJt\n\
+qH/C5xoWocEXBi2DF7O3bl5PhhEEV0c9DDmxmxQMNIabEd1f5vRMzW5yKSQzsOMG\n\
+G1tGvLy5bmcu2X8QE1GTuJcCAwEAAaNCMEAwDwYDVR0TAQH/BAUwAwEB/zAOBgNV\n\
+HQ8BAf8EBAMCAQYwHQYDVR0OBBYEFMWRdUD7TC9WjLs41oVXvWnWGXuaMA0GCSqG\n\
+SIb3DQEBDAUAA4IBAQAfIdo2eXxm2lkFaUW9btDPmudOjVLl0B/pvMiG1oqaRMfw\n\
+donCbNMIJa4P8r7sXQqS/ymxT44+khwkKn1UAZnikMGWlbAIlqMJI00MlVU67UCq\n\
+dffwragiyabpv/SOZFFDlC+2VIKYBofBnxmyqoKD6ekhpduoUCWnfzf4MCMv8yNr\n\
+7BlNtBJllMdWKCSzFT3uzeQ8AnsFkSzb6I2h/OrwJicojmxPCjsK+lpxHC7PhHH9\n\
+kDohEMNNW0Okpnbz/HNoaTVkoQZlu+ZkNG4ee5dXbIl593auqB3hai0wfGKDqbhk\n\
+x1eAfKfDP+l9jrtuaOjayrW+DwayiIuQeZVlFAZH\n\
+-----END CERTIFICATE-----\n";
+    const ROOT_HASH: &str = "A4:0E:E0:6B:1B:8F:87:99:F3:C5:26:A9:1E:48:A9:72:E6:F5:F1:D6:61:11:CD:B1:FC:6C:29:71:B0:CC:78:07";
+    const HOSTNAME: &str = "remote-settings.content-signature.mozilla.org";
+    const INPUT: &[u8] = br#"{"data":[],"last_modified":"1603992731957"}"#;
+    const SIGNATURE: &[u8] = b"GEVVuRIPMf76CC-7tG6rnk_MzSm6FJwPeRpJx3kRmZB37rtFYt3Qk0UuwoztJ6Bg4hbRN5DKGhlsPhi_wcGir7rrmbQkjyN53jYa9plaMly_wC4dGo8M9bM9SiZENMiG";
+    // 2020-11-14, while all the certificates are valid.
+    const NOW: u64 = 1_605_312_000;
+
+    fn assert_invalid(result: Result<()>) {
+        match result.unwrap_err().kind() {
+            ErrorKind::InvalidContentSignature(_) => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        verify(INPUT, SIGNATURE, CHAIN.as_bytes(), NOW, ROOT_HASH, HOSTNAME).unwrap();
+        // The fingerprint can be in either case.
+        verify(
+            INPUT,
+            SIGNATURE,
+            CHAIN.as_bytes(),
+            NOW,
+            &ROOT_HASH.to_lowercase(),
+            HOSTNAME,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_verify_tampered() {
+        let tampered = br#"{"data":[],"last_modified":"1603992731958"}"#;
+        assert_invalid(verify(
+            tampered,
+            SIGNATURE,
+            CHAIN.as_bytes(),
+            NOW,
+            ROOT_HASH,
+            HOSTNAME,
+        ));
+        let mut signature = SIGNATURE.to_vec();
+        signature[0] = if signature[0] == b'A' { b'B' } else { b'A' };
+        assert_invalid(verify(
+            INPUT,
+            &signature,
+            CHAIN.as_bytes(),
+            NOW,
+            ROOT_HASH,
+            HOSTNAME,
+        ));
+        assert_invalid(verify(
+            INPUT,
+            b"not a signature",
+            CHAIN.as_bytes(),
+            NOW,
+            ROOT_HASH,
+            HOSTNAME,
+        ));
+    }
+
+    #[test]
+    fn test_verify_bad_chain() {
+        // A different root.
+        assert_invalid(verify(
+            INPUT,
+            SIGNATURE,
+            CHAIN.as_bytes(),
+            NOW,
+            &ROOT_HASH.replace("A4", "A5"),
+            HOSTNAME,
+        ));
+        // Before the certificates are valid, and after the signer's expires.
+        for &now in &[1_500_000_000, 2_300_000_000] {
+            assert_invalid(verify(
+                INPUT,
+                SIGNATURE,
+                CHAIN.as_bytes(),
+                now,
+                ROOT_HASH,
+                HOSTNAME,
+            ));
+        }
+        // A different host.
+        assert_invalid(verify(
+            INPUT,
+            SIGNATURE,
+            CHAIN.as_bytes(),
+            NOW,
+            ROOT_HASH,
+            "example.com",
+        ));
+        // Missing the intermediate, or the root.
+        let certs: Vec<_> = CHAIN.split("-----END CERTIFICATE-----\n").collect();
+        for chain in &[
+            format!("{}-----END CERTIFICATE-----\n{}", certs[0], certs[2]),
+            format!("{}-----END CERTIFICATE-----\n{}", certs[0], certs[1]),
+            certs[0].to_string(),
+        ] {
+            assert_invalid(verify(
+                INPUT,
+                SIGNATURE,
+                chain.as_bytes(),
+                NOW,
+                ROOT_HASH,
+                HOSTNAME,
+            ));
+        }
+        // A corrupted certificate.
+        let corrupted = CHAIN.replacen("MIICMjCCAbmgAwIBAgIBAz", "MIICMjCCAbmgAwIBAgIBBz", 1);
+        assert_invalid(verify(
+            INPUT,
+            SIGNATURE,
+            corrupted.as_bytes(),
+            NOW,
+            ROOT_HASH,
+            HOSTNAME,
+        ));
+    }
+
+    #[test]
+    fn test_read_time() {
+        let mut reader = Reader::new(b"\x17\x0d491231235959Z\x18\x0f20500101000000Z");
+        assert_eq!(read_time(&mut reader).unwrap(), 2_524_607_999);
+        assert_eq!(read_time(&mut reader).unwrap(), 2_524_608_000);
+        let mut reader = Reader::new(b"\x17\x0d700101000000Z");
+        assert_eq!(read_time(&mut reader).unwrap(), 0);
+        let mut reader = Reader::new(b"\x17\x0d701301000000Z");
+        assert!(read_time(&mut reader).is_err());
+    }
+}
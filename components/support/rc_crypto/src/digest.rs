@@ -67,6 +67,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sha1_digest() {
+        assert_eq!(
+            hex::encode(&digest(&SHA1, b"abc").unwrap()),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+
     #[test]
     fn digest_cleanly_rejects_gigantic_messages() {
         let message = vec![0; (std::i32::MAX as usize) + 1];
@@ -111,12 +111,15 @@ impl Cryptographer for RcCryptoCryptographer {
         remote: &dyn RemotePublicKey,
         local: &dyn LocalKeyPair,
     ) -> Result<Vec<u8>, ece::Error> {
-        let local_any = local.as_any();
-        let local = local_any.downcast_ref::<RcCryptoLocalKeyPair>().unwrap();
-        let remote_any = remote.as_any();
-        let remote = remote_any
+        // Keys made by another cryptographer can't be used with this one.
+        let local = local
+            .as_any()
+            .downcast_ref::<RcCryptoLocalKeyPair>()
+            .ok_or_else(|| ece::Error::from(ece::ErrorKind::CryptoError))?;
+        let remote = remote
+            .as_any()
             .downcast_ref::<RcCryptoRemotePublicKey>()
-            .unwrap();
+            .ok_or_else(|| ece::Error::from(ece::ErrorKind::CryptoError))?;
         Ok(local.agree(&remote)?)
     }
 
@@ -12,6 +12,10 @@ pub enum ErrorKind {
     InternalError,
     #[fail(display = "Conversion error: {}", _0)]
     ConversionError(#[fail(cause)] std::num::TryFromIntError),
+    #[fail(display = "Invalid key derivation parameters: {}", _0)]
+    InvalidKdfParams(String),
+    #[fail(display = "Invalid content signature: {}", _0)]
+    InvalidContentSignature(String),
 }
 
 error_support::define_error! {
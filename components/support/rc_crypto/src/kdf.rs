@@ -0,0 +1,249 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A header recording how a key was derived from a passphrase, for storing
+//! alongside the data it encrypts. Since the algorithm and its parameters
+//! are read back from the header, the defaults can change without making
+//! existing data unreadable.
+//!
+//! The encoded header is:
+//!
+//! | Length    | Field                                                  |
+//! |-----------|--------------------------------------------------------|
+//! | 1         | The version of the encoding, currently 1.              |
+//! | 1         | The algorithm: 1 for PBKDF2-HMAC-SHA256, 2 for scrypt. |
+//! | 4 or 9    | For PBKDF2, the iteration count. For scrypt, `log_n`, then `r` and `p`. |
+//! | 1         | The length of the salt.                                |
+//! | 0 to 255  | The salt.                                              |
+//!
+//! Integers wider than a byte are big endian.
+
+use crate::{digest, error::*, pbkdf2, rand, scrypt};
+use std::convert::TryFrom;
+
+/// The version of the encoding written by `KdfHeader::to_bytes`.
+pub const VERSION: u8 = 1;
+/// The length of the salts generated by `KdfHeader::new`.
+pub const SALT_LEN: usize = 16;
+
+const ALGORITHM_PBKDF2_HMAC_SHA256: u8 = 1;
+const ALGORITHM_SCRYPT: u8 = 2;
+
+// Parameters above these are refused, as a header which asks for more would
+// take an unreasonable time, or amount of memory, to derive the key for.
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const MAX_SCRYPT_MEMORY_LEN: usize = 1 << 30;
+const MAX_SCRYPT_P: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Pbkdf2HmacSha256 { iterations: u32 },
+    Scrypt(scrypt::Params),
+}
+
+impl Algorithm {
+    fn validate(&self) -> Result<()> {
+        match self {
+            Algorithm::Pbkdf2HmacSha256 { iterations } => {
+                if *iterations == 0 || *iterations > MAX_PBKDF2_ITERATIONS {
+                    return Err(ErrorKind::InvalidKdfParams(format!(
+                        "unsupported iteration count {}",
+                        iterations
+                    ))
+                    .into());
+                }
+            }
+            Algorithm::Scrypt(params) => {
+                if params.memory_len()? > MAX_SCRYPT_MEMORY_LEN || params.p > MAX_SCRYPT_P {
+                    return Err(ErrorKind::InvalidKdfParams(format!(
+                        "unsupported scrypt parameters {:?}",
+                        params
+                    ))
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The algorithm, parameters and salt used to derive a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfHeader {
+    pub algorithm: Algorithm,
+    pub salt: Vec<u8>,
+}
+
+impl KdfHeader {
+    /// Creates a header for `algorithm`, with a new random salt.
+    pub fn new(algorithm: Algorithm) -> Result<Self> {
+        algorithm.validate()?;
+        let mut salt = vec![0u8; SALT_LEN];
+        rand::fill(&mut salt)?;
+        Ok(KdfHeader { algorithm, salt })
+    }
+
+    /// Fills `out` with the key derived from `passphrase`.
+    pub fn derive(&self, passphrase: &[u8], out: &mut [u8]) -> Result<()> {
+        self.algorithm.validate()?;
+        match &self.algorithm {
+            Algorithm::Pbkdf2HmacSha256 { iterations } => {
+                pbkdf2::derive(&digest::SHA256, *iterations, &self.salt, passphrase, out)
+            }
+            Algorithm::Scrypt(params) => scrypt::derive(params, &self.salt, passphrase, out),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![VERSION];
+        match &self.algorithm {
+            Algorithm::Pbkdf2HmacSha256 { iterations } => {
+                bytes.push(ALGORITHM_PBKDF2_HMAC_SHA256);
+                bytes.extend_from_slice(&iterations.to_be_bytes());
+            }
+            Algorithm::Scrypt(params) => {
+                bytes.push(ALGORITHM_SCRYPT);
+                bytes.push(params.log_n);
+                bytes.extend_from_slice(&params.r.to_be_bytes());
+                bytes.extend_from_slice(&params.p.to_be_bytes());
+            }
+        }
+        bytes.push(u8::try_from(self.salt.len())?);
+        bytes.extend_from_slice(&self.salt);
+        Ok(bytes)
+    }
+
+    /// Decodes a header written by `to_bytes` from the start of `bytes`,
+    /// returning it along with the bytes which follow it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8])> {
+        let mut reader = Reader(bytes);
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(ErrorKind::InvalidKdfParams(format!(
+                "unsupported header version {}",
+                version
+            ))
+            .into());
+        }
+        let algorithm = match reader.u8()? {
+            ALGORITHM_PBKDF2_HMAC_SHA256 => Algorithm::Pbkdf2HmacSha256 {
+                iterations: reader.u32()?,
+            },
+            ALGORITHM_SCRYPT => {
+                let log_n = reader.u8()?;
+                let r = reader.u32()?;
+                let p = reader.u32()?;
+                Algorithm::Scrypt(scrypt::Params::new(log_n, r, p)?)
+            }
+            algorithm => {
+                return Err(
+                    ErrorKind::InvalidKdfParams(format!("unknown algorithm {}", algorithm)).into(),
+                )
+            }
+        };
+        algorithm.validate()?;
+        let salt_len = usize::from(reader.u8()?);
+        let salt = reader.take(salt_len)?.to_vec();
+        Ok((KdfHeader { algorithm, salt }, reader.0))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(ErrorKind::InvalidKdfParams("truncated header".into()).into());
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let algorithms = [
+            Algorithm::Pbkdf2HmacSha256 { iterations: 10 },
+            Algorithm::Scrypt(scrypt::Params::new(4, 1, 1).unwrap()),
+        ];
+        for algorithm in &algorithms {
+            let header = KdfHeader::new(*algorithm).unwrap();
+            assert_eq!(header.salt.len(), SALT_LEN);
+            let mut bytes = header.to_bytes().unwrap();
+            bytes.extend_from_slice(b"ciphertext");
+            let (decoded, rest) = KdfHeader::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded, header);
+            assert_eq!(rest, b"ciphertext");
+
+            let mut key = [0u8; 32];
+            let mut decoded_key = [0u8; 32];
+            header.derive(b"passphrase", &mut key).unwrap();
+            decoded.derive(b"passphrase", &mut decoded_key).unwrap();
+            assert_eq!(key, decoded_key);
+            decoded
+                .derive(b"other passphrase", &mut decoded_key)
+                .unwrap();
+            assert_ne!(key, decoded_key);
+        }
+    }
+
+    #[test]
+    fn test_header_encoding() {
+        let header = KdfHeader {
+            algorithm: Algorithm::Pbkdf2HmacSha256 { iterations: 1 },
+            salt: b"salt".to_vec(),
+        };
+        let bytes = header.to_bytes().unwrap();
+        assert_eq!(hex::encode(&bytes), "0101000000010473616c74");
+        // The same vector as in `pbkdf2`.
+        let mut key = [0u8; 32];
+        KdfHeader::from_bytes(&bytes)
+            .unwrap()
+            .0
+            .derive(b"passwd", &mut key)
+            .unwrap();
+        assert_eq!(
+            hex::encode(&key),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        for bytes in &[
+            // Empty.
+            "",
+            // A newer version.
+            "0201000000010473616c74",
+            // An unknown algorithm.
+            "0103000000010473616c74",
+            // Too many iterations.
+            "0101ffffffff0473616c74",
+            // Invalid scrypt parameters.
+            "0102000000000100000001",
+            // Too much memory for scrypt.
+            "0102180000000800000001",
+            // A truncated salt.
+            "010100000001047361",
+        ] {
+            let bytes = hex::decode(bytes).unwrap();
+            assert!(KdfHeader::from_bytes(&bytes).is_err(), "{:?}", bytes);
+        }
+        assert!(KdfHeader::new(Algorithm::Pbkdf2HmacSha256 { iterations: 0 }).is_err());
+    }
+}
@@ -27,6 +27,7 @@
 pub mod aead;
 pub mod agreement;
 pub mod constant_time;
+pub mod contentsignature;
 pub mod digest;
 #[cfg(feature = "ece")]
 pub mod ece_crypto;
@@ -35,7 +36,10 @@ mod error;
 mod hawk_crypto;
 pub mod hkdf;
 pub mod hmac;
+pub mod kdf;
+pub mod pbkdf2;
 pub mod rand;
+pub mod scrypt;
 
 // Expose `hawk` if the hawk feature is on. This avoids consumers needing to
 // configure this separately, which is more or less trivial to do incorrectly.
@@ -0,0 +1,72 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{digest, error::*};
+
+/// The longest output NSS will derive.
+pub const MAX_OUTPUT_LEN: usize = 256;
+
+/// Fills `out` with a key derived from `password` using PBKDF2
+/// (RFC 8018, section 5.2), with HMAC-`digest_alg` as the pseudorandom
+/// function. `out` can't be longer than `MAX_OUTPUT_LEN`.
+pub fn derive(
+    digest_alg: &digest::Algorithm,
+    iterations: u32,
+    salt: &[u8],
+    password: &[u8],
+    out: &mut [u8],
+) -> Result<()> {
+    if iterations == 0 {
+        return Err(ErrorKind::InvalidKdfParams("iterations must be at least 1".into()).into());
+    }
+    if out.len() > MAX_OUTPUT_LEN {
+        return Err(ErrorKind::InvalidKdfParams("output is too long".into()).into());
+    }
+    let mut derived =
+        nss::pk11::sym_key::pbkdf2_key_derive(digest_alg, password, salt, iterations, out.len())?;
+    out.swap_with_slice(&mut derived[0..out.len()]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbkdf2_hmac_sha256_produces_correct_result() {
+        // From RFC 7914, section 11.
+        let mut out = [0u8; 64];
+        derive(&digest::SHA256, 1, b"salt", b"passwd", &mut out).unwrap();
+        assert_eq!(
+            hex::encode(&out[..]),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+        derive(&digest::SHA256, 80000, b"NaCl", b"Password", &mut out).unwrap();
+        assert_eq!(
+            hex::encode(&out[..]),
+            "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56\
+             a1d425a1225833549adb841b51c9b3176a272bdebba1d078478f62b397f33c8d"
+        );
+    }
+
+    #[test]
+    fn pbkdf2_hmac_sha1_produces_correct_result() {
+        // From RFC 6070.
+        let mut out = [0u8; 20];
+        derive(&digest::SHA1, 2, b"salt", b"password", &mut out).unwrap();
+        assert_eq!(
+            hex::encode(&out[..]),
+            "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957"
+        );
+    }
+
+    #[test]
+    fn pbkdf2_rejects_invalid_params() {
+        let mut out = [0u8; 32];
+        assert!(derive(&digest::SHA256, 0, b"salt", b"passwd", &mut out).is_err());
+        let mut out = [0u8; MAX_OUTPUT_LEN + 1];
+        assert!(derive(&digest::SHA256, 1, b"salt", b"passwd", &mut out).is_err());
+    }
+}
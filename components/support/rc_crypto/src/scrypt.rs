@@ -0,0 +1,235 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The scrypt key derivation function, from RFC 7914. NSS doesn't implement
+//! scrypt, so it's done here, on top of NSS's HMAC.
+
+use crate::{digest, error::*, hmac};
+use std::convert::TryFrom;
+
+const SHA256_OUTPUT_LEN: usize = 32;
+
+/// The cost parameters for scrypt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// The (base 2) log of the CPU/memory cost `N`.
+    pub log_n: u8,
+    /// The block size, `r`.
+    pub r: u32,
+    /// The parallelization parameter, `p`.
+    pub p: u32,
+}
+
+impl Params {
+    pub fn new(log_n: u8, r: u32, p: u32) -> Result<Self> {
+        let params = Params { log_n, r, p };
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// The number of bytes of memory needed to derive a key with these
+    /// parameters.
+    pub fn memory_len(&self) -> Result<usize> {
+        self.validate()?;
+        // `validate` checks that this can't overflow.
+        Ok((1usize << self.log_n) * 128 * self.r as usize)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.log_n == 0 || self.log_n >= 32 {
+            return Err(
+                ErrorKind::InvalidKdfParams("log_n must be between 1 and 31".into()).into(),
+            );
+        }
+        if self.r == 0 || self.p == 0 {
+            return Err(ErrorKind::InvalidKdfParams("r and p must be at least 1".into()).into());
+        }
+        // RFC 7914, section 2: `p * r` must be less than 2^30, and `N` less
+        // than `2^(128 * r / 8)`.
+        if u64::from(self.r) * u64::from(self.p) >= 1 << 30
+            || u64::from(self.log_n) >= 16 * u64::from(self.r)
+        {
+            return Err(ErrorKind::InvalidKdfParams("r, p or log_n is too large".into()).into());
+        }
+        // The blocks and the scratch memory both need to be addressable.
+        let block_len = 128usize.checked_mul(usize::try_from(self.r)?);
+        let p = usize::try_from(self.p)?;
+        if block_len.and_then(|len| len.checked_mul(p)).is_none()
+            || block_len
+                .and_then(|len| len.checked_mul(1 << self.log_n))
+                .is_none()
+        {
+            return Err(ErrorKind::InvalidKdfParams("r, p or log_n is too large".into()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Fills `out` with a key derived from `password` using scrypt.
+pub fn derive(params: &Params, salt: &[u8], password: &[u8], out: &mut [u8]) -> Result<()> {
+    let memory_len = params.memory_len()?;
+    let block_len = 128 * params.r as usize;
+    let mut b = vec![0u8; block_len * params.p as usize];
+    pbkdf2_hmac_sha256_once(password, salt, &mut b)?;
+    let mut v = vec![0u32; memory_len / 4];
+    for chunk in b.chunks_mut(block_len) {
+        ro_mix(chunk, &mut v, 1u32 << params.log_n);
+    }
+    pbkdf2_hmac_sha256_once(password, &b, out)
+}
+
+// PBKDF2-HMAC-SHA256 with a single iteration. NSS limits how long the output
+// of its PBKDF2 can be, which is too short for scrypt's blocks, but with one
+// iteration each block of the output is just one HMAC.
+fn pbkdf2_hmac_sha256_once(password: &[u8], salt: &[u8], out: &mut [u8]) -> Result<()> {
+    let key = hmac::SigningKey::new(&digest::SHA256, password);
+    let mut input = salt.to_vec();
+    input.extend_from_slice(&[0u8; 4]);
+    for (i, chunk) in out.chunks_mut(SHA256_OUTPUT_LEN).enumerate() {
+        input[salt.len()..].copy_from_slice(&u32::try_from(i + 1)?.to_be_bytes());
+        let block = hmac::sign(&key, &input)?;
+        chunk.copy_from_slice(&block.as_ref()[..chunk.len()]);
+    }
+    Ok(())
+}
+
+// `scryptROMix` from RFC 7914, section 5. `scratch` must hold `cost` blocks.
+fn ro_mix(block: &mut [u8], scratch: &mut [u32], cost: u32) {
+    let words = block.len() / 4;
+    let mut state: Vec<u32> = block
+        .chunks(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    let mut mixed = vec![0u32; words];
+    for i in 0..cost as usize {
+        scratch[i * words..(i + 1) * words].copy_from_slice(&state);
+        block_mix(&state, &mut mixed);
+        std::mem::swap(&mut state, &mut mixed);
+    }
+    for _ in 0..cost {
+        // `Integerify` takes the first word of the last 64 byte block.
+        let index = (state[words - 16] & (cost - 1)) as usize;
+        let saved = &scratch[index * words..(index + 1) * words];
+        for (word, saved_word) in state.iter_mut().zip(saved) {
+            *word ^= saved_word;
+        }
+        block_mix(&state, &mut mixed);
+        std::mem::swap(&mut state, &mut mixed);
+    }
+    for (bytes, word) in block.chunks_mut(4).zip(&state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+// `scryptBlockMix` from RFC 7914, section 4, with Salsa20/8 as the hash.
+fn block_mix(input: &[u32], output: &mut [u32]) {
+    let blocks = input.len() / 16;
+    let mut x = [0u32; 16];
+    x.copy_from_slice(&input[(blocks - 1) * 16..]);
+    for i in 0..blocks {
+        for (word, input_word) in x.iter_mut().zip(&input[i * 16..(i + 1) * 16]) {
+            *word ^= input_word;
+        }
+        salsa20_8(&mut x);
+        // The even blocks go in the first half of the output, and the odd
+        // ones in the second.
+        let out = (i / 2 + (i % 2) * (blocks / 2)) * 16;
+        output[out..out + 16].copy_from_slice(&x);
+    }
+}
+
+// The Salsa20/8 core, from RFC 7914, section 3.
+fn salsa20_8(block: &mut [u32; 16]) {
+    let mut x = *block;
+    macro_rules! quarter_round {
+        ($a:expr, $b:expr, $c:expr, $d:expr) => {
+            x[$b] ^= x[$a].wrapping_add(x[$d]).rotate_left(7);
+            x[$c] ^= x[$b].wrapping_add(x[$a]).rotate_left(9);
+            x[$d] ^= x[$c].wrapping_add(x[$b]).rotate_left(13);
+            x[$a] ^= x[$d].wrapping_add(x[$c]).rotate_left(18);
+        };
+    }
+    for _ in 0..4 {
+        // Columns.
+        quarter_round!(0, 4, 8, 12);
+        quarter_round!(5, 9, 13, 1);
+        quarter_round!(10, 14, 2, 6);
+        quarter_round!(15, 3, 7, 11);
+        // Rows.
+        quarter_round!(0, 1, 2, 3);
+        quarter_round!(5, 6, 7, 4);
+        quarter_round!(10, 11, 8, 9);
+        quarter_round!(15, 12, 13, 14);
+    }
+    for (word, mixed) in block.iter_mut().zip(&x) {
+        *word = word.wrapping_add(*mixed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn salsa20_8_produces_correct_result() {
+        // From RFC 7914, section 8.
+        let input = hex::decode(
+            "7e879a214f3ec9867ca940e641718f26baee555b8c61c1b50df846116dcd3b1d\
+             ee24f319df9b3d8514121e4b5ac5aa3276021d2909c74829edebc68db8b8c25e",
+        )
+        .unwrap();
+        let mut block = [0u32; 16];
+        for (word, bytes) in block.iter_mut().zip(input.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        salsa20_8(&mut block);
+        let output: Vec<u8> = block
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(
+            hex::encode(output),
+            "a41f859c6608cc993b81cacb020cef05044b2181a2fd337dfd7b1c6396682f29\
+             b4393168e3c9e6bcfe6bc5b7a06d96bae424cc102c91745c24ad673dc7618f81"
+        );
+    }
+
+    #[test]
+    fn scrypt_produces_correct_result() {
+        // From RFC 7914, section 12.
+        let mut out = [0u8; 64];
+        derive(&Params::new(4, 1, 1).unwrap(), b"", b"", &mut out).unwrap();
+        assert_eq!(
+            hex::encode(&out[..]),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+        derive(
+            &Params::new(10, 8, 16).unwrap(),
+            b"NaCl",
+            b"password",
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(&out[..]),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+             2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+    }
+
+    #[test]
+    fn scrypt_rejects_invalid_params() {
+        assert!(Params::new(0, 8, 1).is_err());
+        assert!(Params::new(32, 8, 1).is_err());
+        assert!(Params::new(14, 0, 1).is_err());
+        assert!(Params::new(14, 8, 0).is_err());
+        assert!(Params::new(16, 1, 1).is_err());
+        assert!(Params::new(14, 1 << 15, 1 << 15).is_err());
+        assert_eq!(
+            Params::new(14, 8, 1).unwrap().memory_len().unwrap(),
+            16 << 20
+        );
+    }
+}
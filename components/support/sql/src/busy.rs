@@ -0,0 +1,146 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use rusqlite::ErrorCode;
+use std::time::Duration;
+
+/// How many times `retry_if_busy` runs an operation before giving up.
+pub const BUSY_RETRY_ATTEMPTS: u32 = 3;
+
+/// How long `retry_if_busy` waits before the first retry. The wait doubles
+/// after each attempt.
+pub const BUSY_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Errors which can tell whether they were caused by lock contention, like
+/// another connection holding a write lock for a sync.
+pub trait MaybeBusy {
+    fn is_busy(&self) -> bool;
+}
+
+impl MaybeBusy for rusqlite::Error {
+    fn is_busy(&self) -> bool {
+        is_busy_error(self)
+    }
+}
+
+impl MaybeBusy for crate::open_database::Error {
+    fn is_busy(&self) -> bool {
+        match self {
+            crate::open_database::Error::SqlError(e) => is_busy_error(e),
+            _ => false,
+        }
+    }
+}
+
+/// Returns true if `e` is `SQLITE_BUSY` or `SQLITE_LOCKED`, which mean that
+/// the database, or a table in it, is locked by another connection.
+pub fn is_busy_error(e: &rusqlite::Error) -> bool {
+    match e {
+        rusqlite::Error::SqliteFailure(err, _) => match err.code {
+            ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Runs `op`, and runs it again, up to `BUSY_RETRY_ATTEMPTS` times in all,
+/// if it fails because the database is busy. This is meant for reads:
+/// retrying a write which isn't in a transaction could run the statements
+/// which succeeded before the failure again.
+pub fn retry_if_busy<T, E: MaybeBusy>(mut op: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let mut delay = BUSY_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if e.is_busy() && attempt < BUSY_RETRY_ATTEMPTS => {
+                log::warn!(
+                    "Database is busy; retrying in {:?} (attempt {} of {})",
+                    delay,
+                    attempt,
+                    BUSY_RETRY_ATTEMPTS
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConnExt;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_is_busy_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let writer = Connection::open(&path).unwrap();
+        writer
+            .execute_batch(
+                "CREATE TABLE items (id INTEGER PRIMARY KEY);
+                 BEGIN EXCLUSIVE;",
+            )
+            .unwrap();
+        let reader = Connection::open(&path).unwrap();
+        reader.busy_timeout(Duration::from_millis(0)).unwrap();
+        let err = reader
+            .query_one::<i64>("SELECT COUNT(*) FROM items")
+            .unwrap_err();
+        assert!(is_busy_error(&err));
+        assert!(err.is_busy());
+
+        writer.execute_batch("COMMIT").unwrap();
+        assert_eq!(
+            reader
+                .query_one::<i64>("SELECT COUNT(*) FROM items")
+                .unwrap(),
+            0
+        );
+        let err = reader
+            .query_one::<i64>("SELECT * FROM missing")
+            .unwrap_err();
+        assert!(!is_busy_error(&err));
+    }
+
+    fn busy() -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY), None)
+    }
+
+    #[test]
+    fn test_retry_if_busy() {
+        let mut attempts = 0;
+        let result = retry_if_busy(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(busy())
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Gives up after `BUSY_RETRY_ATTEMPTS`.
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_if_busy(|| {
+            attempts += 1;
+            Err(busy())
+        });
+        assert!(result.unwrap_err().is_busy());
+        assert_eq!(attempts, BUSY_RETRY_ATTEMPTS);
+
+        // Other errors aren't retried.
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_if_busy(|| {
+            attempts += 1;
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        });
+        assert!(!result.unwrap_err().is_busy());
+        assert_eq!(attempts, 1);
+    }
+}
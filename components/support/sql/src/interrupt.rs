@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use ffi_support::implement_into_ffi_by_pointer;
-use interrupt_support::Interruptee;
+use interrupt_support::{Interruptable, Interruptee};
 use rusqlite::InterruptHandle;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
@@ -17,6 +17,9 @@ use std::sync::{
 /// executing.
 pub struct SqlInterruptHandle {
     db_handle: InterruptHandle,
+    // Other connections which share `interrupt_counter`, like a component's
+    // read-only connection.
+    other_db_handles: Vec<InterruptHandle>,
     interrupt_counter: Arc<AtomicUsize>,
 }
 
@@ -27,13 +30,38 @@ impl SqlInterruptHandle {
     ) -> SqlInterruptHandle {
         SqlInterruptHandle {
             db_handle,
+            other_db_handles: Vec::new(),
             interrupt_counter,
         }
     }
 
+    /// Makes this handle also interrupt the connection `db_handle` belongs
+    /// to, which must share our interrupt counter.
+    pub fn with_connection(mut self, db_handle: InterruptHandle) -> Self {
+        self.other_db_handles.push(db_handle);
+        self
+    }
+
     pub fn interrupt(&self) {
         self.interrupt_counter.fetch_add(1, Ordering::SeqCst);
         self.db_handle.interrupt();
+        for db_handle in &self.other_db_handles {
+            db_handle.interrupt();
+        }
+    }
+}
+
+impl Interruptable for SqlInterruptHandle {
+    fn interrupt(&self) {
+        SqlInterruptHandle::interrupt(self)
+    }
+}
+
+impl std::fmt::Debug for SqlInterruptHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqlInterruptHandle")
+            .field("interrupt_counter", &self.interrupt_counter)
+            .finish()
     }
 }
 
@@ -44,7 +72,8 @@ implement_into_ffi_by_pointer!(SqlInterruptHandle);
 /// queries when the request to stop comes in, but we're still not done (for example,
 /// maybe we've run some of the autocomplete matchers, and are about to start
 /// running the others. If we rely solely on sqlite3_interrupt(), we'd miss
-/// the message that we should stop).
+/// the message that we should stop). Scopes also count as interrupted once
+/// `interrupt_support::shutdown()` has been called.
 #[derive(Debug)]
 pub struct SqlInterruptScope {
     // The value of the interrupt counter when the scope began
@@ -70,7 +99,7 @@ impl SqlInterruptScope {
 impl Interruptee for SqlInterruptScope {
     #[inline]
     fn was_interrupted(&self) -> bool {
-        self.ptr.load(Ordering::SeqCst) != self.start_value
+        self.ptr.load(Ordering::SeqCst) != self.start_value || interrupt_support::in_shutdown()
     }
 }
 
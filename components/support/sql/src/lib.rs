@@ -5,13 +5,16 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+mod busy;
 mod conn_ext;
 mod each_chunk;
 mod interrupt;
 mod maybe_cached;
+pub mod open_database;
 mod query_plan;
 mod repeat;
 
+pub use crate::busy::*;
 pub use crate::conn_ext::*;
 pub use crate::each_chunk::*;
 pub use crate::interrupt::*;
@@ -0,0 +1,507 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Opening a database the same way across components: with WAL and a busy
+//! timeout, and with the schema created or migrated based on
+//! `PRAGMA user_version`.
+//!
+//! Components describe their schema by implementing `ConnectionInitializer`,
+//! with a function which migrates the schema from each older version to the
+//! next one. If the database is corrupt, it's moved aside to
+//! `<name>.corrupt`, and a new, empty one is created in its place.
+//! Components with encrypted databases, where a wrong key looks the same to
+//! SQLite as a corrupt file, decide which errors mean corruption with
+//! `ConnectionInitializer::is_corruption_error`.
+//!
+//! Read-only connections are never migrated or recovered: the component is
+//! responsible for checking that the schema is one it can read.
+
+use failure::Fail;
+use rusqlite::{Connection, ErrorCode, OpenFlags, Transaction, NO_PARAMS};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait for another connection's lock to be released before
+/// failing with `SQLITE_BUSY`.
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Incompatible database version: {}", _0)]
+    IncompatibleVersion(u32),
+
+    #[fail(display = "Error migrating the database: {}", _0)]
+    MigrationError(String),
+
+    #[fail(display = "Error executing SQL: {}", _0)]
+    SqlError(#[fail(cause)] rusqlite::Error),
+
+    #[fail(display = "IO error: {}", _0)]
+    IoError(#[fail(cause)] std::io::Error),
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::SqlError(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A corrupt database which was moved aside when opening it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuarantinedDatabase {
+    /// Where the corrupt database was moved to.
+    pub path: PathBuf,
+    /// The error which showed that the database was corrupt.
+    pub reason: String,
+}
+
+/// A database opened by `open_database_with_recovery`.
+pub struct OpenedDatabase {
+    pub conn: Connection,
+    /// Set if the database was corrupt, and had to be recreated. Anything
+    /// which was stored in it is gone, so components which sync should
+    /// download everything again.
+    pub quarantined: Option<QuarantinedDatabase>,
+}
+
+/// Describes how to set up a component's database.
+pub trait ConnectionInitializer {
+    /// A name for the database, for logging.
+    const NAME: &'static str;
+
+    /// The current schema version.
+    const END_VERSION: u32;
+
+    /// Runs every time the database is opened, before the schema is checked.
+    /// This is the place for `PRAGMA`s which aren't persisted, and for
+    /// registering SQL functions.
+    fn prepare(&self, _conn: &Connection) -> Result<()> {
+        Ok(())
+    }
+
+    /// Creates the schema for `END_VERSION` in a new database.
+    fn init(&self, tx: &Transaction<'_>) -> Result<()>;
+
+    /// Migrates the schema from `version` to `version + 1`.
+    fn upgrade_from(&self, tx: &Transaction<'_>, version: u32) -> Result<()>;
+
+    /// Called when the database was last used by a newer version of the
+    /// component, with a schema at `version`. By default, this fails with
+    /// `IncompatibleVersion`. Components which can afford to throw data away,
+    /// like caches, can instead recreate the schema for `END_VERSION`.
+    fn downgrade_from(&self, _tx: &Transaction<'_>, version: u32) -> Result<()> {
+        Err(Error::IncompatibleVersion(version))
+    }
+
+    /// Whether a database last used by a newer version of the component,
+    /// with a schema at `version`, can be used as it is. If so,
+    /// `downgrade_from` isn't called, and the version is left alone so that
+    /// the newer version doesn't run its migrations again.
+    fn can_use_future_version(&self, _version: u32) -> bool {
+        false
+    }
+
+    /// Whether `e`, which happened while opening the database, means that
+    /// it's corrupt, and should be moved aside. By default, this is
+    /// `is_corruption_error`.
+    fn is_corruption_error(&self, e: &rusqlite::Error) -> bool {
+        is_corruption_error(e)
+    }
+
+    /// Runs every time the database is opened, after the schema has been
+    /// created or migrated.
+    fn finish(&self, _conn: &Connection) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs after a corrupt database was moved aside, and a new one created
+    /// in its place.
+    fn recovered(&self, _conn: &Connection, _quarantined: &QuarantinedDatabase) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens, creating it if needed, the database at `path`.
+pub fn open_database<CI: ConnectionInitializer, P: AsRef<Path>>(
+    path: P,
+    initializer: &CI,
+) -> Result<Connection> {
+    open_database_with_flags(path, OpenFlags::default(), initializer)
+}
+
+/// Like `open_database`, but with `flags` instead of the default ones.
+pub fn open_database_with_flags<CI: ConnectionInitializer, P: AsRef<Path>>(
+    path: P,
+    flags: OpenFlags,
+    initializer: &CI,
+) -> Result<Connection> {
+    Ok(open_database_with_recovery(path, flags, initializer)?.conn)
+}
+
+/// Like `open_database_with_flags`, but also reports whether the database
+/// was corrupt, and had to be recreated. Read-only databases are never
+/// recreated.
+pub fn open_database_with_recovery<CI: ConnectionInitializer, P: AsRef<Path>>(
+    path: P,
+    flags: OpenFlags,
+    initializer: &CI,
+) -> Result<OpenedDatabase> {
+    let path = path.as_ref();
+    let read_only = is_read_only(flags);
+    let reason = match open_and_init(
+        Connection::open_with_flags(path, flags)?,
+        true,
+        read_only,
+        initializer,
+    ) {
+        Err(Error::SqlError(e))
+            if !read_only && initializer.is_corruption_error(&e) && path.is_file() =>
+        {
+            e.to_string()
+        }
+        result => {
+            return Ok(OpenedDatabase {
+                conn: result?,
+                quarantined: None,
+            })
+        }
+    };
+    log::error!(
+        "{} database is corrupt ({}). Moving it aside and starting over",
+        CI::NAME,
+        reason
+    );
+    let quarantined = QuarantinedDatabase {
+        path: quarantine(path)?,
+        reason,
+    };
+    let conn = open_and_init(
+        Connection::open_with_flags(path, flags)?,
+        true,
+        false,
+        initializer,
+    )?;
+    initializer.recovered(&conn, &quarantined)?;
+    Ok(OpenedDatabase {
+        conn,
+        quarantined: Some(quarantined),
+    })
+}
+
+/// Opens a new in-memory database, for tests.
+pub fn open_memory_database<CI: ConnectionInitializer>(initializer: &CI) -> Result<Connection> {
+    open_and_init(Connection::open_in_memory()?, false, false, initializer)
+}
+
+/// Sets up a writable connection the caller already opened, the same way
+/// as `open_database`, except that a corrupt database isn't recovered.
+pub fn init_connection<CI: ConnectionInitializer>(
+    conn: Connection,
+    initializer: &CI,
+) -> Result<Connection> {
+    open_and_init(conn, true, false, initializer)
+}
+
+fn is_read_only(flags: OpenFlags) -> bool {
+    flags.contains(OpenFlags::SQLITE_OPEN_READ_ONLY)
+}
+
+fn open_and_init<CI: ConnectionInitializer>(
+    mut conn: Connection,
+    is_file: bool,
+    read_only: bool,
+    initializer: &CI,
+) -> Result<Connection> {
+    conn.busy_timeout(DEFAULT_BUSY_TIMEOUT)?;
+    // The journal mode is persistent, so there's no need to (and we can't)
+    // set it for read-only connections.
+    if is_file && !read_only {
+        // `journal_mode` returns the mode, so it can't be set with
+        // `execute_batch`.
+        conn.query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |_| Ok(()))?;
+    }
+    initializer.prepare(&conn)?;
+    if !read_only {
+        migrate_schema(&mut conn, initializer)?;
+    }
+    initializer.finish(&conn)?;
+    Ok(conn)
+}
+
+/// Creates or migrates the schema of an open connection, based on
+/// `PRAGMA user_version`. `open_database` does this already; this is for
+/// connections whose contents were replaced, such as by restoring a backup.
+pub fn migrate_schema<CI: ConnectionInitializer>(
+    conn: &mut Connection,
+    initializer: &CI,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let user_version =
+        tx.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get::<_, u32>(0))?;
+    if user_version == 0 {
+        log::debug!("Creating {} schema", CI::NAME);
+        initializer.init(&tx)?;
+    } else if user_version < CI::END_VERSION {
+        for version in user_version..CI::END_VERSION {
+            log::debug!("Upgrading {} schema from version {}", CI::NAME, version);
+            initializer.upgrade_from(&tx, version)?;
+        }
+    } else if user_version > CI::END_VERSION {
+        log::warn!(
+            "Loaded future {} schema version {} (we only understand version {})",
+            CI::NAME,
+            user_version,
+            CI::END_VERSION
+        );
+        if initializer.can_use_future_version(user_version) {
+            return Ok(tx.commit()?);
+        }
+        initializer.downgrade_from(&tx, user_version)?;
+    }
+    if user_version != CI::END_VERSION {
+        tx.execute_batch(&format!("PRAGMA user_version = {}", CI::END_VERSION))?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Returns true if `e` means that the database file is corrupt, or isn't a
+/// database at all.
+pub fn is_corruption_error(e: &rusqlite::Error) -> bool {
+    match e {
+        rusqlite::Error::SqliteFailure(err, _) => match err.code {
+            ErrorCode::NotADatabase | ErrorCode::DatabaseCorrupt => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The path a corrupt database at `path` is moved to.
+pub fn quarantine_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".corrupt")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Moves the database at `path` aside, replacing any database quarantined
+// before, and returns where it was moved to. Its WAL and shared memory files
+// are removed, as they can't be applied to a new database.
+fn quarantine(path: &Path) -> Result<PathBuf> {
+    let quarantine_path = quarantine_path(path);
+    if quarantine_path.exists() {
+        std::fs::remove_file(&quarantine_path)?;
+    }
+    std::fs::rename(path, &quarantine_path)?;
+    for suffix in &["-wal", "-shm"] {
+        let path = with_suffix(path, suffix);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(quarantine_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ConnExt;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct TestInitializer {
+        upgrades: RefCell<Vec<u32>>,
+    }
+
+    impl ConnectionInitializer for TestInitializer {
+        const NAME: &'static str = "test";
+        const END_VERSION: u32 = 3;
+
+        fn init(&self, tx: &Transaction<'_>) -> Result<()> {
+            tx.execute_batch("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, kind TEXT)")?;
+            Ok(())
+        }
+
+        fn upgrade_from(&self, tx: &Transaction<'_>, version: u32) -> Result<()> {
+            self.upgrades.borrow_mut().push(version);
+            match version {
+                1 => tx.execute_batch("ALTER TABLE items ADD COLUMN name TEXT")?,
+                2 => tx.execute_batch("ALTER TABLE items ADD COLUMN kind TEXT")?,
+                _ => {
+                    return Err(Error::MigrationError(format!(
+                        "no upgrade from {}",
+                        version
+                    )))
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_one::<i64>("PRAGMA user_version").unwrap()
+    }
+
+    #[test]
+    fn test_init() {
+        let conn = open_memory_database(&TestInitializer::default()).unwrap();
+        assert_eq!(user_version(&conn), 3);
+        conn.execute_batch("INSERT INTO items (name, kind) VALUES ('a', 'b')")
+            .unwrap();
+        assert_eq!(conn.query_one::<i64>("PRAGMA busy_timeout").unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_upgrade_and_downgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE items (id INTEGER PRIMARY KEY);
+                 PRAGMA user_version = 1;",
+            )
+            .unwrap();
+        }
+        let initializer = TestInitializer::default();
+        let conn = open_database(&path, &initializer).unwrap();
+        assert_eq!(*initializer.upgrades.borrow(), vec![1, 2]);
+        assert_eq!(user_version(&conn), 3);
+        assert_eq!(
+            conn.query_one::<String>("PRAGMA journal_mode").unwrap(),
+            "wal"
+        );
+        conn.execute_batch("INSERT INTO items (name, kind) VALUES ('a', 'b')")
+            .unwrap();
+
+        conn.execute_batch("PRAGMA user_version = 4").unwrap();
+        drop(conn);
+        match open_database(&path, &initializer) {
+            Err(Error::IncompatibleVersion(4)) => {}
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_failed_upgrade_is_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+                 PRAGMA user_version = 2;",
+            )
+            .unwrap();
+        }
+        struct FailingInitializer;
+        impl ConnectionInitializer for FailingInitializer {
+            const NAME: &'static str = "failing";
+            const END_VERSION: u32 = 3;
+
+            fn init(&self, _tx: &Transaction<'_>) -> Result<()> {
+                unreachable!()
+            }
+
+            fn upgrade_from(&self, tx: &Transaction<'_>, _version: u32) -> Result<()> {
+                tx.execute_batch("ALTER TABLE items ADD COLUMN kind TEXT")?;
+                Err(Error::MigrationError("oops".into()))
+            }
+        }
+        assert!(open_database(&path, &FailingInitializer).is_err());
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(user_version(&conn), 2);
+        assert!(conn.execute_batch("SELECT kind FROM items").is_err());
+    }
+
+    #[test]
+    fn test_corrupt_database_is_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        std::fs::write(&path, vec![0xaa; 4096]).unwrap();
+        let opened =
+            open_database_with_recovery(&path, OpenFlags::default(), &TestInitializer::default())
+                .unwrap();
+        assert_eq!(user_version(&opened.conn), 3);
+        let quarantined = opened.quarantined.expect("should quarantine the database");
+        assert_eq!(quarantined.path, dir.path().join("test.db.corrupt"));
+        assert_eq!(std::fs::read(&quarantined.path).unwrap(), vec![0xaa; 4096]);
+        drop(opened.conn);
+
+        // The new database is fine.
+        let opened =
+            open_database_with_recovery(&path, OpenFlags::default(), &TestInitializer::default())
+                .unwrap();
+        assert!(opened.quarantined.is_none());
+    }
+
+    #[test]
+    fn test_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE items (id INTEGER PRIMARY KEY);
+                 PRAGMA user_version = 1;",
+            )
+            .unwrap();
+        }
+        let initializer = TestInitializer::default();
+        let conn = open_database_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY, &initializer)
+            .unwrap();
+        assert!(initializer.upgrades.borrow().is_empty());
+        assert_eq!(user_version(&conn), 1);
+        drop(conn);
+
+        // A corrupt database is left alone.
+        std::fs::write(&path, vec![0xaa; 4096]).unwrap();
+        let conn = open_database_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY, &initializer)
+            .unwrap();
+        assert!(conn.query_one::<i64>("PRAGMA user_version").is_err());
+        assert!(!quarantine_path(&path).exists());
+    }
+
+    #[test]
+    fn test_future_version() {
+        struct OptimisticInitializer;
+        impl ConnectionInitializer for OptimisticInitializer {
+            const NAME: &'static str = "optimistic";
+            const END_VERSION: u32 = 3;
+
+            fn init(&self, tx: &Transaction<'_>) -> Result<()> {
+                tx.execute_batch("CREATE TABLE items (id INTEGER PRIMARY KEY)")?;
+                Ok(())
+            }
+
+            fn upgrade_from(&self, _tx: &Transaction<'_>, _version: u32) -> Result<()> {
+                unreachable!()
+            }
+
+            fn can_use_future_version(&self, version: u32) -> bool {
+                version == 4
+            }
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let conn = open_database(&path, &OptimisticInitializer).unwrap();
+        conn.execute_batch("PRAGMA user_version = 4").unwrap();
+        drop(conn);
+        let conn = open_database(&path, &OptimisticInitializer).unwrap();
+        assert_eq!(user_version(&conn), 4);
+    }
+}
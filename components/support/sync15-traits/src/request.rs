@@ -15,6 +15,7 @@ pub struct CollectionRequest {
     pub order: Option<RequestOrder>,
     pub commit: bool,
     pub batch: Option<String>,
+    pub offset: Option<String>,
 }
 
 impl CollectionRequest {
@@ -33,6 +34,7 @@ impl CollectionRequest {
             order: None,
             commit: false,
             batch: None,
+            offset: None,
         }
     }
 
@@ -88,6 +90,14 @@ impl CollectionRequest {
         self
     }
 
+    /// Continue from the `X-Weave-Next-Offset` returned by a previous request
+    /// with a `limit`.
+    #[inline]
+    pub fn offset(mut self, offset: Option<String>) -> CollectionRequest {
+        self.offset = offset;
+        self
+    }
+
     fn build_query(&self, pairs: &mut form::Serializer<'_, UrlQuery<'_>>) {
         if self.full {
             pairs.append_pair("full", "1");
@@ -121,6 +131,9 @@ impl CollectionRequest {
         if let Some(o) = self.order {
             pairs.append_pair("sort", o.as_str());
         }
+        if let Some(offset) = &self.offset {
+            pairs.append_pair("offset", offset);
+        }
         pairs.finish();
     }
 
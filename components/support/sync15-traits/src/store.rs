@@ -48,12 +48,25 @@ pub trait Store {
         Ok(())
     }
 
+    /// Called with each page of incoming records as it's downloaded, before
+    /// `apply_incoming`. Stores that can stage records (for example, in a
+    /// temp table) should do so and return `None`, so that a large download
+    /// is never held in memory all at once. By default, the page is handed
+    /// back, and is passed to `apply_incoming` along with the rest.
+    ///
+    /// The download starts again if the collection changes while it's being
+    /// paged through, so the same records can be staged more than once, and
+    /// later ones should replace earlier ones.
+    fn stage_incoming(&self, page: IncomingChangeset) -> Result<Option<IncomingChangeset>, Error> {
+        Ok(Some(page))
+    }
+
     /// `inbound` is a vector to support the case where
     /// `get_collection_requests` returned multiple requests. The changesets are
     /// in the same order as the requests were -- e.g. if `vec![req_a, req_b]`
     /// was returned from `get_collection_requests`, `inbound` will have the
     /// results from `req_a` as its first index, and those from `req_b` as it's
-    /// second.
+    /// second. Records which were staged by `stage_incoming` aren't included.
     fn apply_incoming(
         &self,
         inbound: Vec<IncomingChangeset>,
@@ -89,6 +102,14 @@ pub trait Store {
     /// `reset()` with the new IDs.
     fn get_sync_assoc(&self) -> Result<StoreSyncAssociation, Error>;
 
+    /// Whether the store's outgoing records must all be committed together.
+    /// If so, an upload which doesn't fit in a single batch fails with
+    /// `BatchTooLargeError`, and nothing is committed. By default, a large
+    /// upload is committed in several batches instead.
+    fn requires_single_batch(&self) -> bool {
+        false
+    }
+
     /// Reset the store without wiping local data, ready for a "first sync".
     /// `assoc` defines how this store is to be associated with sync.
     fn reset(&self, assoc: &StoreSyncAssociation) -> Result<(), Error>;
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::{io::Read, sync::Once};
+use std::sync::Once;
 use viaduct::{settings::GLOBAL_SETTINGS, Backend};
 
 // Note: we don't `use` things from reqwest or the viaduct crate because
@@ -62,20 +62,22 @@ fn into_reqwest(request: viaduct::Request) -> Result<reqwest::blocking::Request,
 pub struct ReqwestBackend;
 impl Backend for ReqwestBackend {
     fn send(&self, request: viaduct::Request) -> Result<viaduct::Response, viaduct::Error> {
+        self.send_streaming(request)?.into_response()
+    }
+
+    fn send_streaming(
+        &self,
+        request: viaduct::Request,
+    ) -> Result<viaduct::StreamingResponse, viaduct::Error> {
         viaduct::note_backend("reqwest (untrusted)");
         let request_method = request.method;
         let req = into_reqwest(request)?;
-        let mut resp = CLIENT.execute(req).map_err(|e| {
+        let resp = CLIENT.execute(req).map_err(|e| {
             log::error!("Reqwest error: {:?}", e);
             viaduct::Error::NetworkError(e.to_string())
         })?;
         let status = resp.status().as_u16();
         let url = resp.url().clone();
-        let mut body = Vec::with_capacity(resp.content_length().unwrap_or_default() as usize);
-        resp.read_to_end(&mut body).map_err(|e| {
-            log::error!("Failed to get body from response: {:?}", e);
-            viaduct::Error::NetworkError(e.to_string())
-        })?;
         let mut headers = viaduct::Headers::with_capacity(resp.headers().len());
         for (k, v) in resp.headers() {
             let val = String::from_utf8_lossy(v.as_bytes()).to_string();
@@ -90,13 +92,14 @@ impl Backend for ReqwestBackend {
             // Not using Header::new since the error it returns is for request headers.
             headers.insert_header(viaduct::Header::new_unchecked(hname, val));
         }
-        Ok(viaduct::Response {
+        // `reqwest::blocking::Response` reads the body as it arrives.
+        Ok(viaduct::StreamingResponse::new(
             request_method,
-            body,
             url,
             status,
             headers,
-        })
+            resp,
+        ))
     }
 }
 
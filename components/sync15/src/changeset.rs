@@ -25,34 +25,141 @@ pub fn encrypt_outgoing(o: OutgoingChangeset, key: &KeyBundle) -> Result<Vec<Enc
         .collect()
 }
 
+/// The most records we ask for in a single request. Larger downloads are
+/// fetched a page at a time, so that stores which stage incoming records
+/// only need to hold one page in memory.
+pub const DOWNLOAD_PAGE_SIZE: usize = 1000;
+
+/// The size of the next page to request, given the `limit` of the original
+/// request (where 0 means no limit) and how many records we've already got.
+fn next_page_size(limit: usize, fetched: usize) -> usize {
+    if limit == 0 {
+        DOWNLOAD_PAGE_SIZE
+    } else {
+        (limit - fetched).min(DOWNLOAD_PAGE_SIZE)
+    }
+}
+
+/// How many times we start a download again because the collection changed
+/// while we were paging through it, before giving up until the next sync.
+const MAX_DOWNLOAD_RESTARTS: usize = 3;
+
+/// Fetches all the records matching `collection_request`, a page at a time.
+/// The records are held in memory until they're all downloaded; use
+/// `fetch_incoming_with` to hand each page off as it arrives instead.
 pub fn fetch_incoming(
     client: &Sync15StorageClient,
     state: &mut CollState,
     collection_request: &CollectionRequest,
 ) -> Result<IncomingChangeset> {
+    fetch_incoming_with(client, state, collection_request, |page| Ok(Some(page)))
+}
+
+/// Like `fetch_incoming`, but passes each page of decrypted records to
+/// `stage` as soon as it's downloaded. `stage` returns `None` if it took care
+/// of the page, so that we don't keep it, or hands it back to be included in
+/// the returned changeset.
+///
+/// Pages after the first are fetched with `X-If-Unmodified-Since`, so that if
+/// the collection changes while we're paging, the download starts again from
+/// the first page instead of mixing records from before and after the change.
+/// This means `stage` can be passed the same records more than once.
+pub fn fetch_incoming_with<F>(
+    client: &Sync15StorageClient,
+    state: &mut CollState,
+    collection_request: &CollectionRequest,
+    mut stage: F,
+) -> Result<IncomingChangeset>
+where
+    F: FnMut(IncomingChangeset) -> Result<Option<IncomingChangeset>>,
+{
+    let mut restarts = 0;
+    loop {
+        match fetch_pages(client, state, collection_request, &mut stage) {
+            Err(e) if is_precondition_failed(&e) && restarts < MAX_DOWNLOAD_RESTARTS => {
+                restarts += 1;
+                log::info!(
+                    "{} changed while we were downloading it, starting again",
+                    collection_request.collection
+                );
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_precondition_failed(e: &error::Error) -> bool {
+    match e.kind() {
+        ErrorKind::StorageHttpError(ErrorResponse::PreconditionFailed { .. }) => true,
+        _ => false,
+    }
+}
+
+fn fetch_pages<F>(
+    client: &Sync15StorageClient,
+    state: &mut CollState,
+    collection_request: &CollectionRequest,
+    stage: &mut F,
+) -> Result<IncomingChangeset>
+where
+    F: FnMut(IncomingChangeset) -> Result<Option<IncomingChangeset>>,
+{
     let collection = collection_request.collection.clone();
-    let (records, timestamp) = match client.get_encrypted_records(collection_request)? {
-        Sync15ClientResponse::Success {
-            record,
-            last_modified,
-            ..
-        } => (record, last_modified),
-        other => return Err(other.create_storage_error().into()),
-    };
-    // xxx - duplication below of `timestamp` smells wrong
-    state.last_modified = timestamp;
-    let mut result = IncomingChangeset::new(collection, timestamp);
-    result.changes.reserve(records.len());
-    for record in records {
-        // if we see a HMAC error, we've made an explicit decision to
-        // NOT handle it here, but restart the global state machine.
-        // That should cause us to re-read crypto/keys and things should
-        // work (although if for some reason crypto/keys was updated but
-        // not all storage was wiped we are probably screwed.)
-        let decrypted = record.decrypt(&state.key)?;
-        result.changes.push(decrypted.into_timestamped_payload());
+    let mut result: Option<IncomingChangeset> = None;
+    let mut fetched = 0;
+    let mut offset = None;
+    loop {
+        let page_request = collection_request
+            .clone()
+            .limit(next_page_size(collection_request.limit, fetched))
+            .offset(offset.take());
+        // The first page tells us the collection's timestamp, and later pages
+        // must have been fetched from the same version of the collection.
+        let xius = result.as_ref().map(|r| r.timestamp);
+        let (page, timestamp) = match client.get_encrypted_records(&page_request, xius)? {
+            Sync15ClientResponse::Success {
+                record,
+                last_modified,
+                ..
+            } => (record, last_modified),
+            other => return Err(other.create_storage_error().into()),
+        };
+        let changeset = result.get_or_insert_with(|| {
+            // xxx - duplication below of `timestamp` smells wrong
+            state.last_modified = timestamp;
+            IncomingChangeset::new(collection.clone(), timestamp)
+        });
+        let mut decrypted = IncomingChangeset::new(collection.clone(), timestamp);
+        decrypted.changes.reserve(page.records.len());
+        for record in page.records {
+            // if we see a HMAC error, we've made an explicit decision to
+            // NOT handle it here, but restart the global state machine.
+            // That should cause us to re-read crypto/keys and things should
+            // work (although if for some reason crypto/keys was updated but
+            // not all storage was wiped we are probably screwed.)
+            let decrypted_record = record.decrypt(&state.key)?;
+            decrypted
+                .changes
+                .push(decrypted_record.into_timestamped_payload());
+        }
+        fetched += decrypted.changes.len();
+        if let Some(kept) = stage(decrypted)? {
+            changeset.changes.extend(kept.changes);
+        }
+        let limit_reached = collection_request.limit > 0 && fetched >= collection_request.limit;
+        match page.next_offset {
+            Some(next) if !limit_reached => {
+                log::debug!(
+                    "Fetched {} records from {}, getting the next page",
+                    fetched,
+                    collection
+                );
+                offset = Some(next);
+            }
+            _ => break,
+        }
     }
-    Ok(result)
+    Ok(result.expect("must have fetched at least one page"))
 }
 
 #[derive(Debug, Clone)]
@@ -63,6 +170,7 @@ pub struct CollectionUpdate<'a> {
     xius: ServerTimestamp,
     to_update: Vec<EncryptedBso>,
     fully_atomic: bool,
+    single_batch: bool,
 }
 
 impl<'a> CollectionUpdate<'a> {
@@ -81,9 +189,16 @@ impl<'a> CollectionUpdate<'a> {
             xius,
             to_update: records,
             fully_atomic,
+            single_batch: false,
         }
     }
 
+    /// Fails the upload with `BatchTooLargeError`, rather than committing the
+    /// records in several batches, if they don't fit in a single one.
+    pub fn require_single_batch(&mut self) {
+        self.single_batch = true;
+    }
+
     pub fn new_from_changeset(
         client: &'a Sync15StorageClient,
         state: &'a CollState,
@@ -122,6 +237,9 @@ impl<'a> CollectionUpdate<'a> {
             self.xius,
             NormalResponseHandler::new(!self.fully_atomic),
         )?;
+        if self.single_batch {
+            q.require_single_batch();
+        }
 
         for record in self.to_update.into_iter() {
             let enqueued = q.enqueue(&record)?;
@@ -143,3 +261,26 @@ impl<'a> CollectionUpdate<'a> {
         Ok(info)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_page_size() {
+        assert_eq!(next_page_size(0, 0), DOWNLOAD_PAGE_SIZE);
+        assert_eq!(
+            next_page_size(0, 5 * DOWNLOAD_PAGE_SIZE),
+            DOWNLOAD_PAGE_SIZE
+        );
+        assert_eq!(next_page_size(10, 0), 10);
+        assert_eq!(
+            next_page_size(DOWNLOAD_PAGE_SIZE + 10, 0),
+            DOWNLOAD_PAGE_SIZE
+        );
+        assert_eq!(
+            next_page_size(DOWNLOAD_PAGE_SIZE + 10, DOWNLOAD_PAGE_SIZE),
+            10
+        );
+    }
+}
@@ -44,10 +44,54 @@ fn parse_seconds(seconds_str: &str) -> Option<u32> {
     }
 }
 
+/// A page of records from a collection, and the offset to continue from if
+/// the request had a `limit` and there are more.
+#[derive(Debug, Clone)]
+pub struct RecordsPage {
+    pub records: Vec<EncryptedBso>,
+    pub next_offset: Option<String>,
+}
+
+impl RecordsPage {
+    fn from_response(resp: &Response) -> error::Result<Self> {
+        // We ask for `application/newlines`, but servers are free to ignore
+        // that and send a JSON array instead.
+        let is_newlines = resp
+            .headers
+            .get(header_names::CONTENT_TYPE)
+            .map_or(false, |ct| ct.starts_with("application/newlines"));
+        let records = if is_newlines {
+            serde_json::Deserializer::from_slice(&resp.body)
+                .into_iter::<EncryptedBso>()
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            resp.json()?
+        };
+        Ok(RecordsPage {
+            records,
+            next_offset: resp
+                .headers
+                .get(header_names::X_WEAVE_NEXT_OFFSET)
+                .map(ToOwned::to_owned),
+        })
+    }
+}
+
 impl<T> Sync15ClientResponse<T> {
     pub fn from_response(resp: Response, backoff_listener: &BackoffListener) -> error::Result<Self>
     where
         for<'a> T: serde::de::Deserialize<'a>,
+    {
+        Self::from_response_with(resp, backoff_listener, |resp| Ok(resp.json()?))
+    }
+
+    fn from_response_with<F>(
+        resp: Response,
+        backoff_listener: &BackoffListener,
+        parse: F,
+    ) -> error::Result<Self>
+    where
+        F: FnOnce(&Response) -> error::Result<T>,
     {
         let route: String = resp.url.path().into();
         // Android seems to respect retry_after even on success requests, so we
@@ -71,7 +115,7 @@ impl<T> Sync15ClientResponse<T> {
         }
 
         Ok(if resp.is_success() {
-            let record: T = resp.json()?;
+            let record = parse(&resp)?;
             let last_modified = resp
                 .headers
                 .get(header_names::X_LAST_MODIFIED)
@@ -278,11 +322,25 @@ impl Sync15StorageClient {
         })
     }
 
+    /// Fetches the records matching `collection_request`. The server sends
+    /// them one per line, so a page can be parsed without first building the
+    /// whole JSON array; use a `limit` and the returned `next_offset` to fetch
+    /// large collections a page at a time, passing the timestamp of the first
+    /// page as `xius` for the rest, so that the server fails them with a 412
+    /// if the collection has changed since.
     pub fn get_encrypted_records(
         &self,
         collection_request: &CollectionRequest,
-    ) -> error::Result<Sync15ClientResponse<Vec<EncryptedBso>>> {
-        self.collection_request(Method::Get, collection_request)
+        xius: Option<ServerTimestamp>,
+    ) -> error::Result<Sync15ClientResponse<RecordsPage>> {
+        let url = collection_request.build_url(Url::parse(&self.tsc.api_endpoint()?)?)?;
+        let mut req =
+            Request::new(Method::Get, url).header(header_names::ACCEPT, "application/newlines")?;
+        if let Some(xius) = xius {
+            req = req.header(header_names::X_IF_UNMODIFIED_SINCE, format!("{}", xius))?;
+        }
+        let req = self.authorized(req)?;
+        self.exec_request_with(req, RecordsPage::from_response)
     }
 
     #[inline]
@@ -319,15 +377,7 @@ impl Sync15StorageClient {
     where
         for<'a> T: serde::de::Deserialize<'a>,
     {
-        log::trace!(
-            "request: {} {} ({:?})",
-            req.method,
-            req.url.path(),
-            req.url.query()
-        );
-        let resp = req.send()?;
-
-        let result = Sync15ClientResponse::from_response(resp, &self.backoff)?;
+        let result = self.exec_request_with(req, |resp| Ok(resp.json()?))?;
         match result {
             Sync15ClientResponse::Success { .. } => Ok(result),
             _ => {
@@ -340,16 +390,22 @@ impl Sync15StorageClient {
         }
     }
 
-    fn collection_request<T>(
+    fn exec_request_with<T, F>(
         &self,
-        method: Method,
-        r: &CollectionRequest,
+        req: Request,
+        parse: F,
     ) -> error::Result<Sync15ClientResponse<T>>
     where
-        for<'a> T: serde::de::Deserialize<'a>,
+        F: FnOnce(&Response) -> error::Result<T>,
     {
-        let url = r.build_url(Url::parse(&self.tsc.api_endpoint()?)?)?;
-        self.exec_request(self.build_request(method, url)?, false)
+        log::trace!(
+            "request: {} {} ({:?})",
+            req.method,
+            req.url.path(),
+            req.url.query()
+        );
+        let resp = req.send()?;
+        Sync15ClientResponse::from_response_with(resp, &self.backoff, parse)
     }
 
     pub fn new_post_queue<'a, F: PostResponseHandler>(
@@ -397,6 +453,11 @@ impl Sync15StorageClient {
         self.tsc.hashed_uid()
     }
 
+    /// The URL of the storage node we're syncing with, from the token.
+    pub(crate) fn node_url(&self) -> error::Result<String> {
+        self.tsc.api_endpoint()
+    }
+
     pub(crate) fn wipe_remote_engine(&self, engine: &str) -> error::Result<()> {
         let s = self.tsc.api_endpoint()? + "/";
         let url = Url::parse(&s)?.join(&format!("storage/{}", engine))?;
@@ -449,4 +510,66 @@ mod test {
         // Compile will fail if not send.
         ensure_send::<Sync15StorageClient>();
     }
+
+    fn records_response(content_type: &str, body: &str, next_offset: Option<&str>) -> Response {
+        let mut headers = viaduct::Headers::new();
+        headers
+            .insert(header_names::CONTENT_TYPE, content_type)
+            .unwrap();
+        if let Some(offset) = next_offset {
+            headers
+                .insert(header_names::X_WEAVE_NEXT_OFFSET, offset)
+                .unwrap();
+        }
+        Response {
+            request_method: Method::Get,
+            url: Url::parse("https://example.com/sync/storage/bookmarks").unwrap(),
+            status: 200,
+            headers,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_records_page_from_response() {
+        let payload = r#"{\"IV\":\"aaa\",\"hmac\":\"bbb\",\"ciphertext\":\"ccc\"}"#;
+        let lines = format!(
+            "{{\"id\":\"a\",\"modified\":1.5,\"payload\":\"{p}\"}}\n{{\"id\":\"b\",\"modified\":2.5,\"payload\":\"{p}\"}}\n",
+            p = payload
+        );
+        let page = RecordsPage::from_response(&records_response(
+            "application/newlines",
+            &lines,
+            Some("2"),
+        ))
+        .unwrap();
+        assert_eq!(
+            page.records
+                .iter()
+                .map(|r| r.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert_eq!(page.records[1].modified, ServerTimestamp(2_500));
+        assert_eq!(page.records[0].payload.hmac, "bbb");
+        assert_eq!(page.next_offset.as_deref(), Some("2"));
+
+        // Servers that ignore our `Accept` header send an array.
+        let array = format!(
+            "[{{\"id\":\"c\",\"modified\":1.5,\"payload\":\"{}\"}}]",
+            payload
+        );
+        let page = RecordsPage::from_response(&records_response("application/json", &array, None))
+            .unwrap();
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.records[0].id, "c");
+        assert!(page.next_offset.is_none());
+
+        assert!(RecordsPage::from_response(&records_response(
+            "application/newlines",
+            "{\"id\":\"d\"",
+            None
+        ))
+        .is_err());
+    }
 }
@@ -33,6 +33,7 @@ struct Driver<'a> {
     interruptee: &'a dyn Interruptee,
     config: &'a InfoConfiguration,
     recent_clients: HashMap<String, RemoteClient>,
+    sent_client_commands: HashMap<String, HashSet<Command>>,
 }
 
 impl<'a> Driver<'a> {
@@ -46,6 +47,7 @@ impl<'a> Driver<'a> {
             interruptee,
             config,
             recent_clients: HashMap::new(),
+            sent_client_commands: HashMap::new(),
         }
     }
 
@@ -63,6 +65,7 @@ impl<'a> Driver<'a> {
 
         self.interruptee.err_if_interrupted()?;
         let outgoing_commands = self.command_processor.fetch_outgoing_commands()?;
+        let outgoing_client_commands = self.command_processor.fetch_outgoing_client_commands()?;
 
         let mut has_own_client_record = false;
 
@@ -133,9 +136,20 @@ impl<'a> Driver<'a> {
                 // Add the other client to our map of recently synced clients.
                 self.note_recent_client(&client);
 
+                // Commands for this client can be keyed by either its record
+                // ID or its FxA device ID.
+                let client_ids =
+                    || std::iter::once(&client.id).chain(client.fxa_device_id.as_ref());
+                let mut client_commands = outgoing_commands.clone();
+                for id in client_ids() {
+                    if let Some(commands) = outgoing_client_commands.get(id) {
+                        client_commands.extend(commands.iter().cloned());
+                    }
+                }
+
                 // Bail if we don't have any outgoing commands to write into
                 // the other client's record.
-                if outgoing_commands.is_empty() {
+                if client_commands.is_empty() {
                     continue;
                 }
 
@@ -146,7 +160,7 @@ impl<'a> Driver<'a> {
                     .iter()
                     .filter_map(|c| c.as_command())
                     .collect();
-                let mut new_outgoing_commands = outgoing_commands
+                let mut new_outgoing_commands = client_commands
                     .difference(&current_commands)
                     .cloned()
                     .collect::<Vec<_>>();
@@ -157,6 +171,12 @@ impl<'a> Driver<'a> {
                     .commands
                     .extend(new_outgoing_commands.into_iter().map(CommandRecord::from));
                 if new_client.commands.len() == client.commands.len() {
+                    // The record already has all our commands.
+                    self.note_sent_client_commands(
+                        client_ids(),
+                        &outgoing_client_commands,
+                        &current_commands,
+                    );
                     continue;
                 }
 
@@ -168,6 +188,17 @@ impl<'a> Driver<'a> {
                     self.memcache_max_record_payload_size(),
                 )?;
 
+                let written_commands: HashSet<Command> = new_client
+                    .commands
+                    .iter()
+                    .filter_map(|c| c.as_command())
+                    .collect();
+                self.note_sent_client_commands(
+                    client_ids(),
+                    &outgoing_client_commands,
+                    &written_commands,
+                );
+
                 // We want to ensure the TTL for all records we write, which
                 // may not be true for incoming ones - so make sure it is.
                 new_client.ttl = CLIENTS_TTL;
@@ -187,6 +218,30 @@ impl<'a> Driver<'a> {
         Ok(outgoing)
     }
 
+    /// Records which of the commands queued for a client, under any of its
+    /// `ids`, are in its record.
+    fn note_sent_client_commands<'b>(
+        &mut self,
+        ids: impl Iterator<Item = &'b String>,
+        outgoing_client_commands: &HashMap<String, HashSet<Command>>,
+        written_commands: &HashSet<Command>,
+    ) {
+        for id in ids {
+            if let Some(commands) = outgoing_client_commands.get(id) {
+                let sent = commands
+                    .intersection(written_commands)
+                    .cloned()
+                    .collect::<HashSet<_>>();
+                if !sent.is_empty() {
+                    self.sent_client_commands
+                        .entry(id.clone())
+                        .or_default()
+                        .extend(sent);
+                }
+            }
+        }
+    }
+
     /// Builds a fresh client record for this device.
     fn current_client_record(&self) -> ClientRecord {
         let settings = self.command_processor.settings();
@@ -295,6 +350,7 @@ impl<'a> Engine<'a> {
 
         let outgoing = driver.sync(inbound, should_refresh_client)?;
         self.recent_clients = driver.recent_clients;
+        let sent_client_commands = driver.sent_client_commands;
 
         coll_state.last_modified = outgoing.timestamp;
 
@@ -308,6 +364,8 @@ impl<'a> Engine<'a> {
             upload_info.successful_ids.len(),
             upload_info.failed_ids.len()
         );
+        self.command_processor
+            .note_sent_client_commands(sent_client_commands)?;
 
         log::info!("Finished syncing clients");
         Ok(())
@@ -356,6 +414,7 @@ mod tests {
     struct TestProcessor {
         settings: Settings,
         outgoing_commands: HashSet<Command>,
+        outgoing_client_commands: HashMap<String, HashSet<Command>>,
     }
 
     impl CommandProcessor for TestProcessor {
@@ -381,6 +440,12 @@ mod tests {
         fn fetch_outgoing_commands(&self) -> result::Result<HashSet<Command>, failure::Error> {
             Ok(self.outgoing_commands.clone())
         }
+
+        fn fetch_outgoing_client_commands(
+            &self,
+        ) -> result::Result<HashMap<String, HashSet<Command>>, failure::Error> {
+            Ok(self.outgoing_client_commands.clone())
+        }
     }
 
     fn inbound_from_clients(clients: Value) -> IncomingChangeset {
@@ -414,6 +479,25 @@ mod tests {
             .iter()
             .cloned()
             .collect(),
+            outgoing_client_commands: [
+                (
+                    "iPhooooooone".to_string(),
+                    [Command::CloseTab("https://example.com/".into())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                ),
+                (
+                    "deviceZZZZZZ".to_string(),
+                    [Command::CloseTab("https://example.org/".into())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
         };
 
         let config = InfoConfiguration::default();
@@ -493,15 +577,25 @@ mod tests {
             .collect::<Vec<RemoteClient>>();
         assert_eq!(actual_remote_clients, expected_remote_clients);
 
+        // Only the command for the client we saw was sent; the other should
+        // stay queued.
+        let expected_sent: HashMap<String, HashSet<Command>> = [(
+            "iPhooooooone".to_string(),
+            [Command::CloseTab("https://example.com/".into())]
+                .iter()
+                .cloned()
+                .collect(),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        assert_eq!(driver.sent_client_commands, expected_sent);
+
         let expected = json!([{
             "id": "deviceAAAAAA",
             "name": "Laptop",
             "type": "desktop",
             "commands": [{
-                "command": "displayURI",
-                "args": ["http://example.com", "Fennec", "Example page"],
-                "flowID": "flooooooooow",
-            }, {
                 "command": "resetEngine",
                 "args": ["forms"],
             }, {
@@ -521,6 +615,9 @@ mod tests {
             }, {
                 "command": "wipeEngine",
                 "args": ["bookmarks"],
+            }, {
+                "command": "closeTab",
+                "args": ["https://example.com/"],
             }],
             "fxaDeviceId": "iPhooooooone",
             "protocols": ["1.5"],
@@ -558,6 +655,7 @@ mod tests {
                 device_type: DeviceType::Desktop,
             },
             outgoing_commands: [].iter().cloned().collect(),
+            outgoing_client_commands: HashMap::new(),
         };
 
         let config = InfoConfiguration::default();
@@ -627,6 +725,7 @@ mod tests {
                 device_type: DeviceType::Desktop,
             },
             outgoing_commands: HashSet::new(),
+            outgoing_client_commands: HashMap::new(),
         };
 
         let config = InfoConfiguration::default();
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 mod engine;
 mod record;
@@ -33,6 +33,27 @@ pub trait CommandProcessor {
     /// commands couldn't be fetched, and halts the sync.
     fn fetch_outgoing_commands(&self) -> Result<HashSet<Command>, failure::Error>;
 
+    /// Fetches commands to send to specific clients, keyed by the client's
+    /// record ID or FxA device ID. These are sent in addition to the commands
+    /// from `fetch_outgoing_commands`, which go to every client.
+    fn fetch_outgoing_client_commands(
+        &self,
+    ) -> Result<HashMap<String, HashSet<Command>>, failure::Error> {
+        Ok(HashMap::new())
+    }
+
+    /// Called once the clients collection has been uploaded, with the
+    /// commands from `fetch_outgoing_client_commands` that are now in their
+    /// client's record, keyed the same way. Commands for clients we didn't
+    /// see, or which were dropped to keep the record small enough, aren't
+    /// included, and should be sent again on the next sync.
+    fn note_sent_client_commands(
+        &self,
+        _commands: HashMap<String, HashSet<Command>>,
+    ) -> Result<(), failure::Error> {
+        Ok(())
+    }
+
     /// Applies a command sent to this client from another client. This method
     /// should return a `CommandStatus` indicating whether the command was
     /// processed.
@@ -90,4 +111,15 @@ pub enum Command {
     ResetAll,
     /// Resets local sync state for a specific engine.
     Reset(String),
+    /// Closes the open tabs with this URL.
+    CloseTab(String),
+    /// Shows a URL sent from another client.
+    DisplayUri {
+        uri: String,
+        /// The record ID of the client that sent the URL.
+        sender: String,
+        /// The title of the page, or an empty string if the sender didn't
+        /// include one.
+        title: String,
+    },
 }
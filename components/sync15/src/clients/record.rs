@@ -95,6 +95,15 @@ impl CommandRecord {
             "wipeAll" => Some(Command::WipeAll),
             "resetEngine" => self.args.get(0).map(|e| Command::Reset(e.into())),
             "resetAll" => Some(Command::ResetAll),
+            "closeTab" => self.args.get(0).map(|url| Command::CloseTab(url.into())),
+            "displayURI" => match self.args.as_slice() {
+                [uri, sender, rest @ ..] => Some(Command::DisplayUri {
+                    uri: uri.clone(),
+                    sender: sender.clone(),
+                    title: rest.get(0).cloned().unwrap_or_default(),
+                }),
+                _ => None,
+            },
             _ => None,
         }
     }
@@ -123,6 +132,16 @@ impl From<Command> for CommandRecord {
                 args: Vec::new(),
                 flow_id: None,
             },
+            Command::CloseTab(url) => CommandRecord {
+                name: "closeTab".into(),
+                args: vec![url],
+                flow_id: None,
+            },
+            Command::DisplayUri { uri, sender, title } => CommandRecord {
+                name: "displayURI".into(),
+                args: vec![uri, sender, title],
+                flow_id: None,
+            },
         }
     }
 }
@@ -155,4 +174,46 @@ mod tests {
         let bso = crate::CleartextBso::from_payload(p, "clients");
         assert_eq!(bso.ttl, Some(123));
     }
+
+    #[test]
+    fn test_display_uri_command() {
+        let record = CommandRecord {
+            name: "displayURI".into(),
+            args: vec![
+                "https://example.com/".into(),
+                "sender-id".into(),
+                "Example".into(),
+            ],
+            flow_id: None,
+        };
+        let command = Command::DisplayUri {
+            uri: "https://example.com/".into(),
+            sender: "sender-id".into(),
+            title: "Example".into(),
+        };
+        assert_eq!(record.as_command(), Some(command.clone()));
+        assert_eq!(CommandRecord::from(command), record);
+
+        // Older clients don't send a title.
+        let untitled = CommandRecord {
+            name: "displayURI".into(),
+            args: vec!["https://example.com/".into(), "sender-id".into()],
+            flow_id: None,
+        };
+        assert_eq!(
+            untitled.as_command(),
+            Some(Command::DisplayUri {
+                uri: "https://example.com/".into(),
+                sender: "sender-id".into(),
+                title: "".into(),
+            })
+        );
+
+        let missing_sender = CommandRecord {
+            name: "displayURI".into(),
+            args: vec!["https://example.com/".into()],
+            flow_id: None,
+        };
+        assert_eq!(missing_sender.as_command(), None);
+    }
 }
@@ -46,6 +46,9 @@ pub enum ErrorKind {
     #[fail(display = "Outgoing record is too large to upload")]
     RecordTooLargeError,
 
+    #[fail(display = "Outgoing records don't fit in a single batch")]
+    BatchTooLargeError,
+
     // Do we want to record the concrete problems?
     #[fail(display = "Not all records were successfully uploaded")]
     RecordUploadFailed,
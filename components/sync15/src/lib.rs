@@ -28,7 +28,8 @@ mod util;
 pub use crate::bso_record::{BsoRecord, CleartextBso, EncryptedBso, EncryptedPayload, Payload};
 pub use crate::changeset::{IncomingChangeset, OutgoingChangeset, RecordChangeset};
 pub use crate::client::{
-    SetupStorageClient, Sync15ClientResponse, Sync15StorageClient, Sync15StorageClientInit,
+    RecordsPage, SetupStorageClient, Sync15ClientResponse, Sync15StorageClient,
+    Sync15StorageClientInit,
 };
 pub use crate::coll_state::{CollState, CollSyncIds, StoreSyncAssociation};
 pub use crate::collection_keys::CollectionKeys;
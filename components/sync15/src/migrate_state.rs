@@ -46,6 +46,9 @@ pub fn extract_v1_state(
     };
     let pgs = PersistedGlobalState::V2 {
         declined: Some(meta_global.declined),
+        next_sync_after: None,
+        retry_after: None,
+        cached_global_state: None,
     };
     let new_global_state = serde_json::to_string(&pgs).ok();
 
@@ -136,6 +139,9 @@ mod tests {
         // state reflects that.
         let expected_state = serde_json::to_string(&PersistedGlobalState::V2 {
             declined: Some(Vec::<String>::new()),
+            next_sync_after: None,
+            retry_after: None,
+            cached_global_state: None,
         })
         .expect("should stringify");
         assert_eq!(new_state, Some(expected_state));
@@ -161,6 +167,9 @@ mod tests {
         let s = get_state_with_engine_changes_and_declined("", "\\\"foo\\\"");
         let expected_state = serde_json::to_string(&PersistedGlobalState::V2 {
             declined: Some(vec!["foo".to_string()]),
+            next_sync_after: None,
+            retry_after: None,
+            cached_global_state: None,
         })
         .unwrap();
         assert_eq!(
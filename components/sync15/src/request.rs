@@ -160,6 +160,7 @@ pub struct PostQueue<Post, OnResponse> {
     queued: Vec<u8>,
     batch: BatchState,
     last_modified: ServerTimestamp,
+    single_batch: bool,
 }
 
 pub trait BatchPoster {
@@ -248,9 +249,18 @@ where
             max_payload_bytes: config.max_record_payload_bytes,
             max_request_bytes: config.max_request_bytes,
             queued: Vec::new(),
+            single_batch: false,
         }
     }
 
+    /// Makes `enqueue` fail with `BatchTooLargeError`, rather than committing
+    /// the current batch and starting another, if the records don't fit
+    /// within the server's batch limits. As long as the server supports
+    /// batches, this means the records are either all committed or none are.
+    pub fn require_single_batch(&mut self) {
+        self.single_batch = true;
+    }
+
     #[inline]
     fn in_batch(&self) -> bool {
         match &self.batch {
@@ -314,6 +324,12 @@ where
         let can_batch_record = self.batch_limits.can_add_record(payload_length);
         let can_send_record = self.queued.len() < self.max_request_bytes;
 
+        if !can_batch_record && self.single_batch && self.batch != BatchState::Unsupported {
+            self.queued.truncate(item_start);
+            log::warn!("Outgoing records exceed the server's batch limits");
+            return Err(ErrorKind::BatchTooLargeError.into());
+        }
+
         if !can_post_record || !can_send_record || !can_batch_record {
             log::debug!(
                 "PostQueue flushing! (can_post = {}, can_send = {}, can_batch = {})",
@@ -542,10 +558,21 @@ mod test {
             .sort_by(RequestOrder::Oldest)
             .older_than(ServerTimestamp(9_876_540))
             .newer_than(ServerTimestamp(1_234_560))
-            .build_url(base)
+            .build_url(base.clone())
             .unwrap();
         assert_eq!(complex.as_str(),
             "https://example.com/sync/storage/specific?full=1&limit=10&older=9876.54&newer=1234.56&sort=oldest");
+
+        let paged = CollectionRequest::new("paged")
+            .full()
+            .limit(100)
+            .offset(Some("100".into()))
+            .build_url(base)
+            .unwrap();
+        assert_eq!(
+            paged.as_str(),
+            "https://example.com/sync/storage/paged?full=1&limit=100&offset=100"
+        );
     }
 
     #[derive(Debug, Clone)]
@@ -1153,6 +1180,83 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_pq_require_single_batch() {
+        let cfg = InfoConfiguration {
+            max_post_records: 3,
+            max_total_records: 5,
+            ..InfoConfiguration::default()
+        };
+        let time = 11_111_111_000;
+        let (mut pq, tester) = pq_test_setup(
+            cfg,
+            time,
+            vec![fake_response(status_codes::ACCEPTED, time, Some("1234"))],
+        );
+        pq.require_single_batch();
+
+        pq.enqueue(&make_record(100)).unwrap();
+        pq.enqueue(&make_record(100)).unwrap();
+        pq.enqueue(&make_record(100)).unwrap();
+        // POST
+        pq.enqueue(&make_record(100)).unwrap();
+        pq.enqueue(&make_record(100)).unwrap();
+        // Doesn't fit in the batch, so nothing is committed.
+        match pq.enqueue(&make_record(100)).unwrap_err().kind() {
+            ErrorKind::BatchTooLargeError => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+
+        let t = tester.borrow();
+        assert!(t.cur_batch.is_some());
+        assert_eq!(t.all_posts.len(), 1);
+        assert!(t.batches.is_empty());
+        assert_eq!(t.all_posts[0].commit, false);
+    }
+
+    #[test]
+    fn test_pq_atomic_upload_without_single_batch() {
+        // Uploads from `sync_multiple` don't allow failed records, but unless
+        // the store requires a single batch, records which don't fit in one
+        // are still committed in several.
+        let cfg = InfoConfiguration {
+            max_post_records: 3,
+            max_total_records: 5,
+            ..InfoConfiguration::default()
+        };
+        let time = 11_111_111_000;
+        let tester = TestPoster::new(
+            &cfg,
+            vec![
+                fake_response(status_codes::ACCEPTED, time, Some("1234")),
+                fake_response(status_codes::ACCEPTED, time + 100_000, Some("1234")),
+                fake_response(status_codes::ACCEPTED, time + 100_000, Some("abcd")),
+            ],
+        );
+        let mut pq = PostQueue::new(
+            &cfg,
+            ServerTimestamp(time),
+            tester.clone(),
+            NormalResponseHandler::new(false),
+        );
+
+        for _ in 0..6 {
+            assert!(pq.enqueue(&make_record(100)).unwrap());
+        }
+        pq.flush(true).unwrap();
+
+        let info = pq.completed_upload_info();
+        assert!(info.failed_ids.is_empty());
+        assert_eq!(info.modified_timestamp.0, time + 100_000);
+
+        let t = tester.borrow();
+        assert!(t.cur_batch.is_none());
+        assert_eq!(t.batches.len(), 2);
+        assert_eq!(t.batches[0].records, 5);
+        assert_eq!(t.batches[1].records, 1);
+        assert!(t.batches.iter().all(|b| b.posts.last().unwrap().commit));
+    }
+
     #[test]
     #[allow(clippy::cognitive_complexity)]
     fn test_pq_multi_post_multi_batch_bytes() {
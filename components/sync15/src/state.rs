@@ -14,6 +14,7 @@ use crate::request::{InfoCollections, InfoConfiguration};
 use crate::util::ServerTimestamp;
 use interrupt_support::Interruptee;
 use serde_derive::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use sync_guid::Guid;
 
 use self::SetupState::*;
@@ -40,10 +41,16 @@ const DEFAULT_ENGINES: &[(&str, usize)] = &[
 // Declined engines to include in a fresh `meta/global` record.
 const DEFAULT_DECLINED: &[&str] = &[];
 
+// A persisted backoff further in the future than this is cut short, so that a
+// bogus value (or a clock change) can't stop us from syncing for good.
+const MAX_PERSISTED_BACKOFF: Duration = Duration::from_secs(24 * 60 * 60);
+
 /// State that we require the app to persist to storage for us.
-/// It's a little unfortunate we need this, because it's only tracking
+/// It's a little unfortunate we need this, because it's mostly tracking
 /// "declined engines", and even then, only needed in practice when there's
-/// no meta/global so we need to create one. It's extra unfortunate because we
+/// no meta/global so we need to create one. It also remembers when the
+/// server asked us to back off until, so that restarting the app doesn't
+/// clear the backoff. It's extra unfortunate because we
 /// want to move away from "globally declined" engines anyway, moving towards
 /// allowing engines to be enabled or disabled per client rather than globally.
 ///
@@ -56,16 +63,49 @@ const DEFAULT_DECLINED: &[&str] = &[];
 pub enum PersistedGlobalState {
     /// V1 was when we persisted the entire GlobalState, keys and all!
 
-    /// V2 is just tracking the globally declined list.
+    /// V2 is tracking the globally declined list. It can also hold the
+    /// `GlobalState` from the last sync (with the keys still encrypted), so
+    /// that the first sync after a restart doesn't need to fetch
+    /// `meta/global` and `crypto/keys` again if they haven't changed.
     /// None means "I've no idea" and theoretically should only happen on the
     /// very first sync for an app.
-    V2 { declined: Option<Vec<String>> },
+    V2 {
+        declined: Option<Vec<String>>,
+        /// The time before which we shouldn't sync, in milliseconds since
+        /// the Unix epoch.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_sync_after: Option<u64>,
+        /// The part of `next_sync_after` that the server asked for with
+        /// `Retry-After`, which is respected even for a user action.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+        /// The `GlobalState` from the last successful sync.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cached_global_state: Option<CachedGlobalState>,
+    },
+}
+
+/// A `GlobalState` persisted by a previous sync, and the storage node it
+/// came from. It's only reused for the same node, as a node reassignment
+/// means the server's data has (or will soon have) been wiped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedGlobalState {
+    node_url: String,
+    state: GlobalState,
+    // `BsoRecord` doesn't serialize `modified`, so the timestamp of the keys
+    // is kept separately.
+    keys_modified: ServerTimestamp,
 }
 
 impl Default for PersistedGlobalState {
     #[inline]
     fn default() -> PersistedGlobalState {
-        PersistedGlobalState::V2 { declined: None }
+        PersistedGlobalState::V2 {
+            declined: None,
+            next_sync_after: None,
+            retry_after: None,
+            cached_global_state: None,
+        }
     }
 }
 
@@ -146,23 +186,117 @@ fn compute_engine_states(input: EngineStateInput) -> EngineStateOutput {
 impl PersistedGlobalState {
     fn set_declined(&mut self, new_declined: Vec<String>) {
         match self {
-            Self::V2 { ref mut declined } => *declined = Some(new_declined),
+            Self::V2 {
+                ref mut declined, ..
+            } => *declined = Some(new_declined),
         }
     }
     pub(crate) fn get_declined(&self) -> &[String] {
         match self {
-            Self::V2 { declined: Some(d) } => &d,
-            Self::V2 { declined: None } => &[],
+            Self::V2 {
+                declined: Some(d), ..
+            } => &d,
+            Self::V2 { declined: None, .. } => &[],
+        }
+    }
+    pub(crate) fn set_next_sync_after(&mut self, time: Option<SystemTime>) {
+        match self {
+            Self::V2 {
+                ref mut next_sync_after,
+                ..
+            } => *next_sync_after = to_millis(time),
+        }
+    }
+    /// Returns the persisted backoff, if it's still in effect.
+    pub(crate) fn get_next_sync_after(&self) -> Option<SystemTime> {
+        match self {
+            Self::V2 {
+                next_sync_after, ..
+            } => persisted_backoff(*next_sync_after),
+        }
+    }
+    pub(crate) fn set_retry_after(&mut self, time: Option<SystemTime>) {
+        match self {
+            Self::V2 {
+                ref mut retry_after,
+                ..
+            } => *retry_after = to_millis(time),
+        }
+    }
+    /// Returns the persisted `Retry-After`, if it's still in effect.
+    pub(crate) fn get_retry_after(&self) -> Option<SystemTime> {
+        match self {
+            Self::V2 { retry_after, .. } => persisted_backoff(*retry_after),
+        }
+    }
+    pub(crate) fn set_cached_global_state(&mut self, node_url: &str, state: &GlobalState) {
+        match self {
+            Self::V2 {
+                ref mut cached_global_state,
+                ..
+            } => {
+                *cached_global_state = Some(CachedGlobalState {
+                    node_url: node_url.to_owned(),
+                    state: state.clone(),
+                    keys_modified: state.keys.modified,
+                })
+            }
+        }
+    }
+    pub(crate) fn clear_cached_global_state(&mut self) {
+        match self {
+            Self::V2 {
+                ref mut cached_global_state,
+                ..
+            } => *cached_global_state = None,
+        }
+    }
+    /// Removes the persisted `GlobalState`, returning it if it came from the
+    /// storage node at `node_url`.
+    pub(crate) fn take_cached_global_state(&mut self, node_url: &str) -> Option<GlobalState> {
+        match self {
+            Self::V2 {
+                ref mut cached_global_state,
+                ..
+            } => match cached_global_state.take() {
+                Some(mut cached) if cached.node_url == node_url => {
+                    cached.state.keys.modified = cached.keys_modified;
+                    Some(cached.state)
+                }
+                Some(_) => {
+                    log::info!("Discarding the persisted global state for a different node");
+                    None
+                }
+                None => None,
+            },
         }
     }
 }
 
+fn to_millis(time: Option<SystemTime>) -> Option<u64> {
+    time.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+}
+
+fn persisted_backoff(millis: Option<u64>) -> Option<SystemTime> {
+    let until = UNIX_EPOCH + Duration::from_millis(millis?);
+    let now = SystemTime::now();
+    if until <= now {
+        None
+    } else if until > now + MAX_PERSISTED_BACKOFF {
+        log::warn!("Persisted backoff too far in the future, only waiting a day");
+        Some(now + MAX_PERSISTED_BACKOFF)
+    } else {
+        Some(until)
+    }
+}
+
 /// Holds global Sync state, including server upload limits, the
 /// last-fetched collection modified times, `meta/global` record, and
 /// encrypted copies of the crypto/keys resourse (which we hold as encrypted
 /// both to avoid keeping them in memory longer than necessary, and guard against
 /// the wrong (ie, a different user's) root key being passed in.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalState {
     pub config: InfoConfiguration,
     pub collections: InfoCollections,
@@ -190,7 +324,9 @@ fn new_global(pgs: &PersistedGlobalState) -> error::Result<MetaGlobalRecord> {
     // we previously saw a meta/global then we would have updated it with what
     // it was at the time.
     let declined = match pgs {
-        PersistedGlobalState::V2 { declined: Some(d) } => d.clone(),
+        PersistedGlobalState::V2 {
+            declined: Some(d), ..
+        } => d.clone(),
         _ => DEFAULT_DECLINED.iter().map(ToString::to_string).collect(),
     };
 
@@ -507,6 +643,8 @@ impl<'a> SetupStateMachine<'a> {
             // We've got old state that's likely to be OK.
             // We keep things simple here - if there's evidence of a new/missing
             // meta/global or new/missing keys we just restart from scratch.
+            // Any other error is as fatal as it would be when starting from
+            // scratch, so there's no point in fetching everything again.
             WithPreviousState { old_state } => match self.client.fetch_info_collections()? {
                 Sync15ClientResponse::Success {
                     record: collections,
@@ -528,9 +666,12 @@ impl<'a> SetupStateMachine<'a> {
                         }
                     },
                 ),
-                _ => Ok(InitialWithConfig {
-                    config: old_state.config,
-                }),
+                Sync15ClientResponse::Error(ErrorResponse::NotFound { .. }) => {
+                    Ok(InitialWithConfig {
+                        config: old_state.config,
+                    })
+                }
+                other => Err(other.create_storage_error().into()),
             },
 
             Ready { state } => Ok(Ready { state }),
@@ -832,7 +973,7 @@ mod tests {
                 888_000,
             ),
         };
-        let mut pgs = PersistedGlobalState::V2 { declined: None };
+        let mut pgs = PersistedGlobalState::default();
 
         let mut state_machine =
             SetupStateMachine::for_full_sync(&client, &root_key, &mut pgs, None, &NeverInterrupts);
@@ -935,7 +1076,7 @@ mod tests {
 
         // First a test where the "previous" global state is OK to reuse.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::default();
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -958,7 +1099,7 @@ mod tests {
 
         // Now where the meta/global record on the server is later.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::default();
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -981,7 +1122,7 @@ mod tests {
 
         // Where keys on the server is later.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::default();
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -1002,9 +1143,48 @@ mod tests {
             );
         }
 
+        // Where info/collections fails, we don't start from scratch.
+        {
+            let failing_client = InMemoryClient {
+                info_configuration: mocked_success(InfoConfiguration::default()),
+                info_collections: Err(ErrorKind::SetupRequired.into()),
+                meta_global: mocked_success_ts(mg.clone(), ts_metaglobal),
+                crypto_keys: mocked_success_ts(
+                    keys.to_encrypted_bso_with_timestamp(&root_key, ServerTimestamp(ts_keys))
+                        .expect("should always work in this test"),
+                    ts_keys,
+                ),
+            };
+            let mut pgs = PersistedGlobalState::default();
+            let old_state = GlobalState {
+                config: InfoConfiguration::default(),
+                collections: collections.clone(),
+                global: mg.clone(),
+                global_timestamp: ServerTimestamp(ts_metaglobal),
+                keys: keys
+                    .to_encrypted_bso_with_timestamp(&root_key, ServerTimestamp(ts_keys))
+                    .expect("should always work in this test"),
+            };
+            let mut state_machine = SetupStateMachine::for_full_sync(
+                &failing_client,
+                &root_key,
+                &mut pgs,
+                None,
+                &NeverInterrupts,
+            );
+            let err = state_machine
+                .run_to_ready(Some(old_state))
+                .expect_err("should fail");
+            match err.kind() {
+                ErrorKind::StorageHttpError(ErrorResponse::ServerError { status: 500, .. }) => {}
+                _ => panic!("unexpected error {:?}", err),
+            }
+            assert_eq!(state_machine.sequence, vec!["WithPreviousState"]);
+        }
+
         // Where there are engine-state changes.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::default();
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -1026,7 +1206,7 @@ mod tests {
                 &sm_seq_restarted,
             );
             let declined = match pgs {
-                PersistedGlobalState::V2 { declined: d } => d,
+                PersistedGlobalState::V2 { declined: d, .. } => d,
             };
             // and check we now consider logins as declined.
             assert_eq!(declined, Some(vec!["logins".to_string()]));
@@ -1127,4 +1307,119 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_cached_global_state() {
+        let root_key = KeyBundle::new_random().unwrap();
+        let keys = CollectionKeys {
+            timestamp: ServerTimestamp(145_000),
+            default: KeyBundle::new_random().unwrap(),
+            collections: HashMap::new(),
+        };
+        let encrypted_keys = keys
+            .to_encrypted_bso_with_timestamp(&root_key, ServerTimestamp(145_000))
+            .expect("should always work in this test");
+        let mg = MetaGlobalRecord {
+            sync_id: "syncIDAAAAAA".into(),
+            storage_version: 5usize,
+            engines: DEFAULT_ENGINES
+                .iter()
+                .map(|&(name, version)| {
+                    (
+                        name.to_owned(),
+                        MetaGlobalEngine {
+                            version,
+                            sync_id: Guid::random(),
+                        },
+                    )
+                })
+                .collect(),
+            declined: vec![],
+        };
+        let collections = InfoCollections::new(
+            vec![("meta", 123_456), ("crypto", 145_000)]
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), ServerTimestamp(value)))
+                .collect(),
+        );
+        let client = InMemoryClient {
+            info_configuration: mocked_success(InfoConfiguration::default()),
+            info_collections: mocked_success(collections.clone()),
+            meta_global: mocked_success_ts(mg.clone(), 123_456),
+            crypto_keys: mocked_success_ts(encrypted_keys.clone(), 145_000),
+        };
+        let state = GlobalState {
+            config: InfoConfiguration::default(),
+            collections,
+            global: mg,
+            global_timestamp: ServerTimestamp(123_456),
+            keys: encrypted_keys,
+        };
+        let node = "https://sync-1.example.com/1.5/12345";
+        let mut pgs = PersistedGlobalState::default();
+
+        // The state is only reused for the node it came from.
+        pgs.set_cached_global_state(node, &state);
+        let mut pgs: PersistedGlobalState =
+            serde_json::from_str(&serde_json::to_string(&pgs).unwrap()).unwrap();
+        assert!(pgs
+            .take_cached_global_state("https://sync-2.example.com/1.5/12345")
+            .is_none());
+        assert!(pgs.take_cached_global_state(node).is_none());
+
+        pgs.set_cached_global_state(node, &state);
+        let mut pgs: PersistedGlobalState =
+            serde_json::from_str(&serde_json::to_string(&pgs).unwrap()).unwrap();
+        let cached = pgs
+            .take_cached_global_state(node)
+            .expect("should be cached");
+        assert!(pgs.take_cached_global_state(node).is_none());
+
+        // Nothing changed on the server, so `meta/global` and `crypto/keys`
+        // aren't fetched again.
+        let mut state_machine =
+            SetupStateMachine::for_full_sync(&client, &root_key, &mut pgs, None, &NeverInterrupts);
+        let state = state_machine
+            .run_to_ready(Some(cached))
+            .expect("should be ready");
+        assert_eq!(state_machine.sequence, vec!["WithPreviousState", "Ready"]);
+        assert_eq!(state.keys.modified, ServerTimestamp(145_000));
+    }
+
+    #[test]
+    fn test_persisted_next_sync_after() {
+        let mut pgs = PersistedGlobalState::default();
+        assert_eq!(pgs.get_next_sync_after(), None);
+        // Older state without a backoff can still be read.
+        let pgs_from_json: PersistedGlobalState =
+            serde_json::from_str(r#"{"schema_version":"V2","declined":["foo"]}"#).unwrap();
+        assert_eq!(pgs_from_json.get_next_sync_after(), None);
+        assert_eq!(pgs_from_json.get_declined(), &["foo".to_string()]);
+
+        let until = SystemTime::now() + Duration::from_secs(60);
+        pgs.set_next_sync_after(Some(until));
+        let pgs: PersistedGlobalState =
+            serde_json::from_str(&serde_json::to_string(&pgs).unwrap()).unwrap();
+        let next_sync_after = pgs.get_next_sync_after().expect("should be in effect");
+        // We only keep milliseconds.
+        assert!(until.duration_since(next_sync_after).unwrap() < Duration::from_millis(1));
+
+        let mut pgs = PersistedGlobalState::default();
+        pgs.set_next_sync_after(Some(SystemTime::now() - Duration::from_secs(1)));
+        assert_eq!(pgs.get_next_sync_after(), None);
+        // A backoff too far in the future is cut short, not dropped.
+        let too_far = SystemTime::now() + MAX_PERSISTED_BACKOFF + Duration::from_secs(60);
+        pgs.set_next_sync_after(Some(too_far));
+        let capped = pgs.get_next_sync_after().expect("should be in effect");
+        assert!(capped < too_far);
+        assert!(capped > SystemTime::now() + MAX_PERSISTED_BACKOFF - Duration::from_secs(60));
+        pgs.set_retry_after(Some(too_far));
+        assert!(pgs.get_retry_after().expect("should be in effect") < too_far);
+        pgs.set_retry_after(None);
+        pgs.set_next_sync_after(None);
+        assert_eq!(
+            serde_json::to_string(&pgs).unwrap(),
+            r#"{"schema_version":"V2","declined":null}"#
+        );
+    }
 }
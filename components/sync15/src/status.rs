@@ -96,6 +96,11 @@ impl SyncResult {
     pub(crate) fn set_sync_after(&mut self, backoff_duration: Duration) {
         let now = SystemTime::now();
         let toplevel = advance_backoff(now + backoff_duration, &self.result);
+        // Keep any backoff we already know about, such as one that stopped
+        // us from syncing at all.
+        let toplevel = self
+            .next_sync_after
+            .map_or(toplevel, |t| std::cmp::max(t, toplevel));
         let sync_after = self
             .engine_results
             .values()
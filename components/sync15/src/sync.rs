@@ -80,12 +80,20 @@ pub fn synchronize_with_clients_engine(
             .enumerate()
             .map(|(idx, collection_request)| {
                 interruptee.err_if_interrupted()?;
-                let incoming_changes =
-                    crate::changeset::fetch_incoming(client, &mut coll_state, &collection_request)?;
+                let mut downloaded = 0;
+                let incoming_changes = crate::changeset::fetch_incoming_with(
+                    client,
+                    &mut coll_state,
+                    &collection_request,
+                    |page| {
+                        downloaded += page.changes.len();
+                        Ok(store.stage_incoming(page)?)
+                    },
+                )?;
 
                 log::info!(
                     "Downloaded {} remote changes (request {} of {})",
-                    incoming_changes.changes.len(),
+                    downloaded,
                     idx,
                     count,
                 );
@@ -104,9 +112,12 @@ pub fn synchronize_with_clients_engine(
     coll_state.last_modified = new_timestamp;
 
     log::info!("Uploading {} outgoing changes", outgoing.changes.len());
-    let upload_info =
-        CollectionUpdate::new_from_changeset(client, &coll_state, outgoing, fully_atomic)?
-            .upload()?;
+    let mut update =
+        CollectionUpdate::new_from_changeset(client, &coll_state, outgoing, fully_atomic)?;
+    if store.requires_single_batch() {
+        update.require_single_batch();
+    }
+    let upload_info = update.upload()?;
 
     log::info!(
         "Upload success ({} records success, {} records failed)",
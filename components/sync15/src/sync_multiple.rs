@@ -8,7 +8,7 @@
 use crate::client::{BackoffListener, Sync15StorageClient, Sync15StorageClientInit};
 use crate::clients::{self, CommandProcessor, CLIENTS_TTL_REFRESH};
 use crate::coll_state::StoreSyncAssociation;
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
 use crate::key_bundle::KeyBundle;
 use crate::state::{EngineChangesNeeded, GlobalState, PersistedGlobalState, SetupStateMachine};
 use crate::status::{ServiceStatus, SyncResult};
@@ -47,9 +47,12 @@ impl ClientInfo {
 pub struct MemoryCachedState {
     last_client_info: Option<ClientInfo>,
     last_global_state: Option<GlobalState>,
-    // These are just stored in memory, as persisting an invalid value far in the
-    // future has the potential to break sync for good.
+    // The backoff is also persisted, in `PersistedGlobalState`, but cut
+    // short from there if it's too far in the future.
     next_sync_after: Option<SystemTime>,
+    // When the server's last `Retry-After` ends. Unlike the rest of the
+    // backoff, it's respected even for a user action.
+    retry_after: Option<SystemTime>,
     next_client_refresh_after: Option<SystemTime>,
 }
 
@@ -174,10 +177,81 @@ pub fn sync_multiple_with_command_processor(
     // ignoring it during the sync
     sync_result.set_sync_after(backoff.get_required_wait(false).unwrap_or_default());
     mem_cached_state.next_sync_after = sync_result.next_sync_after;
+    let now = SystemTime::now();
+    let retry_after = Some(backoff.get_retry_after_secs())
+        .filter(|secs| *secs > 0)
+        .map(|secs| now + Duration::from_secs(secs.into()));
+    mem_cached_state.retry_after = std::cmp::max(
+        mem_cached_state.retry_after.filter(|t| *t > now),
+        retry_after,
+    );
+    persist_next_sync_after(
+        persisted_global_state,
+        sync_result.next_sync_after,
+        mem_cached_state.retry_after,
+    );
     log::trace!("Sync result: {:?}", sync_result);
     sync_result
 }
 
+// Records the backoff in the persisted state, so that it survives the app
+// being restarted. A `Retry-After` that's still in effect is kept, even if we
+// synced anyway.
+fn persist_next_sync_after(
+    persisted_global_state: &mut Option<String>,
+    next_sync_after: Option<SystemTime>,
+    retry_after: Option<SystemTime>,
+) {
+    let mut pgs = match persisted_global_state {
+        Some(persisted_string) => {
+            match serde_json::from_str::<PersistedGlobalState>(&persisted_string) {
+                Ok(state) => state,
+                Err(_) => {
+                    // `prepare_persisted_state` has already complained about this.
+                    return;
+                }
+            }
+        }
+        None if next_sync_after.is_none() && retry_after.is_none() => return,
+        None => PersistedGlobalState::default(),
+    };
+    pgs.set_retry_after(std::cmp::max(retry_after, pgs.get_retry_after()));
+    pgs.set_next_sync_after(next_sync_after);
+    match serde_json::to_string(&pgs) {
+        Ok(s) => *persisted_global_state = Some(s),
+        Err(e) => log::error!("Failed to persist the backoff: {}", e),
+    }
+}
+
+// Syncs a store by calling `sync`. If a record failed to decrypt, the keys
+// might have been changed by another client since we fetched them, so we call
+// `refetch_keys`, and sync again if it says they changed. We only refetch the
+// keys once per sync, which `refetched_keys` tracks across stores.
+fn sync_with_key_refetch<S>(
+    state: &mut S,
+    refetched_keys: &mut bool,
+    mut sync: impl FnMut(&S) -> result::Result<(), Error>,
+    refetch_keys: impl FnOnce(&mut S) -> bool,
+) -> result::Result<(), Error> {
+    let result = sync(state);
+    let is_hmac_error = match &result {
+        Err(e) => match e.kind() {
+            ErrorKind::HmacMismatch => true,
+            _ => false,
+        },
+        Ok(()) => false,
+    };
+    if !is_hmac_error || *refetched_keys {
+        return result;
+    }
+    *refetched_keys = true;
+    if refetch_keys(state) {
+        sync(state)
+    } else {
+        result
+    }
+}
+
 /// This is essentially a bag of information that the sync manager knows, but
 /// otherwise we won't. It should probably be rethought if it gains many more
 /// fields.
@@ -209,6 +283,13 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
         log::info!("Loading/initializing persisted state");
         let mut pgs = self.prepare_persisted_state();
 
+        if let Some(until) = self.backed_off_until(&pgs) {
+            log::warn!("Backoff still in effect, not syncing");
+            self.result.service_status = ServiceStatus::BackedOff;
+            self.result.next_sync_after = Some(until);
+            return Ok(());
+        }
+
         log::info!("Preparing client info");
         let client_info = self.prepare_client_info()?;
 
@@ -264,13 +345,22 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
 
         log::info!("Synchronizing stores");
 
-        let telem_sync = self.sync_stores(&client_info, &mut global_state, clients_engine.as_ref());
+        let telem_sync = self.sync_stores(
+            &client_info,
+            &mut pgs,
+            &mut global_state,
+            clients_engine.as_ref(),
+        );
         self.result.telemetry.sync(telem_sync);
 
         log::info!("Finished syncing stores.");
 
         if !self.saw_auth_error {
             log::trace!("Updating persisted global state");
+            if let Ok(node_url) = client_info.client.node_url() {
+                pgs.set_cached_global_state(&node_url, &global_state);
+                *self.persisted_global_state = Some(serde_json::to_string(&pgs)?);
+            }
             self.mem_cached_state.last_client_info = Some(client_info);
             self.mem_cached_state.last_global_state = Some(global_state);
         }
@@ -278,6 +368,42 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
         Ok(())
     }
 
+    // Returns when we can sync again, if the server asked us to back off,
+    // either in this process or in a previous one, and there's no compelling
+    // reason to ignore it. A `Retry-After` is never ignored, just like
+    // `get_required_wait` only ignores the soft backoff.
+    fn backed_off_until(&self, pgs: &PersistedGlobalState) -> Option<SystemTime> {
+        let now = SystemTime::now();
+        let retry_after = std::cmp::max(self.mem_cached_state.retry_after, pgs.get_retry_after())
+            .filter(|t| *t > now);
+        let until = std::cmp::max(
+            std::cmp::max(
+                self.mem_cached_state.next_sync_after,
+                pgs.get_next_sync_after(),
+            ),
+            retry_after,
+        )?;
+        if until <= now {
+            return None;
+        }
+        let reason = if self.ignore_soft_backoff {
+            "for a user action"
+        } else if self
+            .engines_to_state_change
+            .map_or(false, |changes| !changes.is_empty())
+        {
+            "because we have enabled state changes"
+        } else {
+            return Some(until);
+        };
+        if retry_after.is_some() {
+            log::info!("Not syncing {}, the server said to retry later", reason);
+            return retry_after;
+        }
+        log::info!("Still under backoff, but syncing anyway {}", reason);
+        None
+    }
+
     fn was_interrupted(&mut self) -> bool {
         if self.interruptee.was_interrupted() {
             log::info!("Interrupted, bailing out");
@@ -291,10 +417,13 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
     fn sync_stores(
         &mut self,
         client_info: &ClientInfo,
+        pgs: &mut PersistedGlobalState,
         global_state: &mut GlobalState,
         clients: Option<&clients::Engine<'_>>,
     ) -> telemetry::SyncTelemetry {
         let mut telem_sync = telemetry::SyncTelemetry::new();
+        // We only refetch the keys once per sync.
+        let mut refetched_keys = false;
         for store in self.stores {
             let name = store.collection_name();
             if self
@@ -312,15 +441,31 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
             log::info!("Syncing {} engine!", name);
 
             let mut telem_engine = telemetry::Engine::new(&*name);
-            let result = sync::synchronize_with_clients_engine(
-                &client_info.client,
-                &global_state,
-                self.root_sync_key,
-                clients,
-                *store,
-                true,
-                &mut telem_engine,
-                self.interruptee,
+            let root_sync_key = self.root_sync_key;
+            let interruptee = self.interruptee;
+            let result = sync_with_key_refetch(
+                global_state,
+                &mut refetched_keys,
+                |global_state| {
+                    telem_engine = telemetry::Engine::new(&*name);
+                    sync::synchronize_with_clients_engine(
+                        &client_info.client,
+                        global_state,
+                        root_sync_key,
+                        clients,
+                        *store,
+                        true,
+                        &mut telem_engine,
+                        interruptee,
+                    )
+                },
+                |global_state| {
+                    let changed = self.refetch_keys(client_info, pgs, global_state);
+                    if changed {
+                        log::info!("The keys changed, syncing {} again", name);
+                    }
+                    changed
+                },
             );
 
             match result {
@@ -352,12 +497,50 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
         telem_sync
     }
 
+    // Runs the state machine again from scratch, to fetch `crypto/keys`, and
+    // returns whether the keys changed. If they did, `global_state` is
+    // replaced with the new state.
+    fn refetch_keys(
+        &mut self,
+        client_info: &ClientInfo,
+        pgs: &mut PersistedGlobalState,
+        global_state: &mut GlobalState,
+    ) -> bool {
+        log::warn!("Got an HMAC error, refetching keys");
+        self.mem_cached_state.last_global_state = None;
+        match self.run_state_machine(client_info, pgs) {
+            Ok(new_state) => {
+                let changed = new_state.keys.modified != global_state.keys.modified;
+                *global_state = new_state;
+                if !changed {
+                    log::warn!("The keys haven't changed, so can't fix the HMAC error");
+                }
+                changed
+            }
+            Err(e) => {
+                log::warn!("Failed to refetch keys: {}", e);
+                false
+            }
+        }
+    }
+
     fn run_state_machine(
         &mut self,
         client_info: &ClientInfo,
         pgs: &mut PersistedGlobalState,
     ) -> result::Result<GlobalState, Error> {
-        let last_state = mem::replace(&mut self.mem_cached_state.last_global_state, None);
+        // Use the state from the last sync in this process if we have it, or
+        // the one persisted by an earlier process if it's for the same node.
+        // Both are taken, so that we start from scratch on error.
+        let persisted_state = match client_info.client.node_url() {
+            Ok(node_url) => pgs.take_cached_global_state(&node_url),
+            Err(_) => {
+                pgs.clear_cached_global_state();
+                None
+            }
+        };
+        let last_state =
+            mem::replace(&mut self.mem_cached_state.last_global_state, None).or(persisted_state);
 
         let mut state_machine = SetupStateMachine::for_full_sync(
             &client_info.client,
@@ -492,3 +675,158 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interrupt_support::NeverInterrupts;
+
+    // The keys are represented by a version number, and syncing fails with an
+    // HMAC error unless they're the latest.
+    fn sync_with_keys(keys: u32, latest: u32, syncs: &mut Vec<u32>) -> result::Result<(), Error> {
+        syncs.push(keys);
+        if keys == latest {
+            Ok(())
+        } else {
+            Err(ErrorKind::HmacMismatch.into())
+        }
+    }
+
+    #[test]
+    fn test_hmac_error_keys_changed() {
+        let mut keys = 1;
+        let mut refetched_keys = false;
+        let mut syncs = vec![];
+        sync_with_key_refetch(
+            &mut keys,
+            &mut refetched_keys,
+            |keys| sync_with_keys(*keys, 2, &mut syncs),
+            |keys| {
+                *keys = 2;
+                true
+            },
+        )
+        .expect("should sync with the new keys");
+        assert!(refetched_keys);
+        assert_eq!(syncs, vec![1, 2]);
+
+        // The next store syncs with the new keys straight away.
+        let mut syncs = vec![];
+        sync_with_key_refetch(
+            &mut keys,
+            &mut refetched_keys,
+            |keys| sync_with_keys(*keys, 2, &mut syncs),
+            |_| unreachable!("keys shouldn't be refetched"),
+        )
+        .expect("should sync");
+        assert_eq!(syncs, vec![2]);
+    }
+
+    #[test]
+    fn test_hmac_error_keys_unchanged() {
+        let mut keys = 1;
+        let mut refetched_keys = false;
+        let mut syncs = vec![];
+        let mut refetches = 0;
+        let err = sync_with_key_refetch(
+            &mut keys,
+            &mut refetched_keys,
+            |keys| sync_with_keys(*keys, 2, &mut syncs),
+            |_| {
+                refetches += 1;
+                false
+            },
+        )
+        .expect_err("should fail");
+        match err.kind() {
+            ErrorKind::HmacMismatch => {}
+            e => panic!("Unexpected error {:?}", e),
+        }
+        // We don't sync again with the same keys.
+        assert_eq!(syncs, vec![1]);
+        assert_eq!(refetches, 1);
+
+        // Nor do we refetch them again for the next store.
+        let mut syncs = vec![];
+        sync_with_key_refetch(
+            &mut keys,
+            &mut refetched_keys,
+            |keys| sync_with_keys(*keys, 2, &mut syncs),
+            |_| unreachable!("keys shouldn't be refetched"),
+        )
+        .expect_err("should fail");
+        assert_eq!(syncs, vec![1]);
+    }
+
+    #[test]
+    fn test_persisted_backoff_stops_sync() {
+        let until = SystemTime::now() + Duration::from_secs(600);
+        let mut pgs = PersistedGlobalState::default();
+        pgs.set_next_sync_after(Some(until));
+        let mut persisted_global_state = Some(serde_json::to_string(&pgs).unwrap());
+        let storage_init = Sync15StorageClientInit {
+            key_id: "key_id".into(),
+            access_token: "access_token".into(),
+            // We shouldn't get as far as talking to the server.
+            tokenserver_url: url::Url::parse("http://localhost:1/token/1.0/sync/1.5").unwrap(),
+        };
+        let root_sync_key = KeyBundle::new_random().unwrap();
+
+        // A fresh memory cached state, as if the app had been restarted.
+        let result = sync_multiple(
+            &[],
+            &mut persisted_global_state,
+            &mut MemoryCachedState::default(),
+            &storage_init,
+            &root_sync_key,
+            &NeverInterrupts,
+            None,
+        );
+        assert_eq!(result.service_status, ServiceStatus::BackedOff);
+        assert!(result.result.is_ok());
+        let next_sync_after = result.next_sync_after.expect("should have a backoff");
+        assert!(until.duration_since(next_sync_after).unwrap() < Duration::from_millis(1));
+
+        // The backoff is still persisted for next time.
+        let pgs: PersistedGlobalState =
+            serde_json::from_str(persisted_global_state.as_ref().unwrap()).unwrap();
+        assert_eq!(pgs.get_next_sync_after(), Some(next_sync_after));
+    }
+
+    #[test]
+    fn test_persisted_retry_after_stops_user_sync() {
+        let until = SystemTime::now() + Duration::from_secs(600);
+        let mut pgs = PersistedGlobalState::default();
+        pgs.set_next_sync_after(Some(until));
+        pgs.set_retry_after(Some(until));
+        let mut persisted_global_state = Some(serde_json::to_string(&pgs).unwrap());
+        let storage_init = Sync15StorageClientInit {
+            key_id: "key_id".into(),
+            access_token: "access_token".into(),
+            // We shouldn't get as far as talking to the server.
+            tokenserver_url: url::Url::parse("http://localhost:1/token/1.0/sync/1.5").unwrap(),
+        };
+        let root_sync_key = KeyBundle::new_random().unwrap();
+
+        // A user action ignores a soft backoff, but not a `Retry-After`.
+        let result = sync_multiple(
+            &[],
+            &mut persisted_global_state,
+            &mut MemoryCachedState::default(),
+            &storage_init,
+            &root_sync_key,
+            &NeverInterrupts,
+            Some(SyncRequestInfo {
+                engines_to_state_change: None,
+                is_user_action: true,
+            }),
+        );
+        assert_eq!(result.service_status, ServiceStatus::BackedOff);
+        let next_sync_after = result.next_sync_after.expect("should have a backoff");
+        assert!(until.duration_since(next_sync_after).unwrap() < Duration::from_millis(1));
+
+        let pgs: PersistedGlobalState =
+            serde_json::from_str(persisted_global_state.as_ref().unwrap()).unwrap();
+        assert_eq!(pgs.get_retry_after(), Some(next_sync_after));
+    }
+}
@@ -63,12 +63,41 @@ struct TokenServerFetcher {
     key_id: String,
 }
 
+// Self-hosters can point us at their own tokenserver, so we check the URL up
+// front instead of failing in confusing ways on the first sync. Plain HTTP is
+// only allowed for servers on this machine, for local development.
+fn check_server_url(url: &Url) -> Result<()> {
+    if url.cannot_be_a_base() {
+        return Err(
+            ErrorKind::UnacceptableUrl(format!("Tokenserver URL {} is not a base", url)).into(),
+        );
+    }
+    let is_loopback = match url.host() {
+        Some(url::Host::Domain(domain)) => domain == "localhost",
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => {
+            return Err(
+                ErrorKind::UnacceptableUrl(format!("Tokenserver URL {} has no host", url)).into(),
+            );
+        }
+    };
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if is_loopback => Ok(()),
+        scheme => Err(ErrorKind::UnacceptableUrl(format!(
+            "Tokenserver URL {} uses {}, but must use https",
+            url, scheme
+        ))
+        .into()),
+    }
+}
+
 fn fixup_server_url(mut url: Url) -> Result<Url> {
+    check_server_url(&url)?;
     // base_url is the end-point as returned by .well-known/fxa-client-configuration,
     // or as directly specified by self-hosters. As a result, it may or may not have
     // the sync 1.5 suffix of "/1.0/sync/1.5" - so add it on here if it does not.
-    // (Note that base_url must end in a slash, or the last path element in
-    // the base url will be replaced with this suffix.)
     if url.as_str().ends_with("1.0/sync/1.5") {
         Ok(url)
     } else if url.as_str().ends_with("1.0/sync/1.5/") {
@@ -79,6 +108,13 @@ fn fixup_server_url(mut url: Url) -> Result<Url> {
         }
         Ok(url)
     } else {
+        // Joining replaces the last path segment unless the path ends in a
+        // slash, so `https://example.com/token` needs one added first.
+        if !url.path().ends_with('/') {
+            if let Ok(mut path) = url.path_segments_mut() {
+                path.push("");
+            }
+        }
         Ok(url.join("1.0/sync/1.5")?)
     }
 }
@@ -580,5 +616,40 @@ mod tests {
                 .as_str(),
             "https://token.services.mozilla.com/1.0/sync/1.5"
         );
+        // Self-hosted servers are often under a path.
+        assert_eq!(
+            fixup_server_url(Url::parse("https://example.com/token").unwrap())
+                .unwrap()
+                .as_str(),
+            "https://example.com/token/1.0/sync/1.5"
+        );
+        assert_eq!(
+            fixup_server_url(Url::parse("http://localhost:5000/token/").unwrap())
+                .unwrap()
+                .as_str(),
+            "http://localhost:5000/token/1.0/sync/1.5"
+        );
+        assert_eq!(
+            fixup_server_url(Url::parse("http://127.0.0.1:5000").unwrap())
+                .unwrap()
+                .as_str(),
+            "http://127.0.0.1:5000/1.0/sync/1.5"
+        );
+    }
+
+    #[test]
+    fn test_unacceptable_server_url() {
+        for url in &[
+            "http://example.com/token/1.0/sync/1.5",
+            "ftp://example.com/",
+            "data:text/plain,hello",
+            "file:///tmp/token",
+        ] {
+            let err = fixup_server_url(Url::parse(url).unwrap()).unwrap_err();
+            match err.kind() {
+                ErrorKind::UnacceptableUrl(_) => {}
+                other => panic!("Unexpected error for {}: {:?}", url, other),
+            }
+        }
     }
 }
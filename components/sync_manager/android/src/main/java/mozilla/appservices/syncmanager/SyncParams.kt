@@ -112,7 +112,13 @@ data class SyncParams(
     /**
      * The information used to populate a client record for this device.
      */
-    val deviceSettings: DeviceSettings
+    val deviceSettings: DeviceSettings,
+    /**
+     * Engines that every other client should wipe their local data for, for
+     * example "bookmarks". The commands are sent if this sync succeeds; if it
+     * fails, pass the same engines again next time.
+     */
+    val wipeEnginesOnOtherClients: List<String> = listOf()
 ) {
     @Suppress("ComplexMethod")
     internal fun toProtobuf(): MsgTypes.SyncParams {
@@ -135,6 +141,7 @@ data class SyncParams(
         }
 
         builder.putAllEnginesToChangeState(this.enabledChanges)
+        builder.addAllWipeEnginesOnOtherClients(this.wipeEnginesOnOtherClients)
 
         builder.acctAccessToken = this.authInfo.fxaAccessToken
         builder.acctSyncKey = this.authInfo.syncKey
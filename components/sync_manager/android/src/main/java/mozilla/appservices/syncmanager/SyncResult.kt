@@ -43,6 +43,22 @@ enum class SyncServiceStatus {
     OTHER_ERROR,
 }
 
+/**
+ * A URL sent to this device from another client.
+ */
+data class ReceivedUri(
+    val uri: String,
+    /**
+     * The ID of the client that sent the URL, as in its client record.
+     */
+    val senderId: String,
+    /**
+     * The title of the page, or an empty string if the sender didn't
+     * include one.
+     */
+    val title: String
+)
+
 /**
  * The result of a sync.
  */
@@ -98,7 +114,12 @@ data class SyncResult(
     /**
      * A bundle of telemetry information recorded during this sync.
      */
-    val telemetry: SyncTelemetryPing?
+    val telemetry: SyncTelemetryPing?,
+    /**
+     * URLs sent to this device from other clients, which the app should
+     * show to the user. These are only returned once.
+     */
+    val receivedUris: List<ReceivedUri>
 ) {
     companion object {
         @Suppress("ComplexMethod")
@@ -138,6 +159,9 @@ data class SyncResult(
                 else -> SyncServiceStatus.OTHER_ERROR // impossible *sigh*
             }
 
+            val receivedUris = pb.receivedUrisList.map {
+                ReceivedUri(uri = it.uri, senderId = it.senderId, title = it.title)
+            }
             return SyncResult(
                 status = status,
                 failures = failures,
@@ -145,7 +169,8 @@ data class SyncResult(
                 declined = declined,
                 telemetry = telemetry,
                 nextSyncAllowedAt = nextSyncAllowedAt,
-                persistedState = pb.persistedState
+                persistedState = pb.persistedState,
+                receivedUris = receivedUris
             )
         }
     }
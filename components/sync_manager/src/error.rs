@@ -30,6 +30,8 @@ pub enum ErrorKind {
     LoginsError(#[fail(cause)] logins::Error),
     #[fail(display = "Places error: {}", _0)]
     PlacesError(#[fail(cause)] places::Error),
+    #[fail(display = "Tabs error: {}", _0)]
+    TabsError(#[fail(cause)] tabs::Error),
 }
 
 error_support::define_error! {
@@ -42,5 +44,6 @@ error_support::define_error! {
         (JsonError, serde_json::Error),
         (LoginsError, logins::Error),
         (PlacesError, places::Error),
+        (TabsError, tabs::Error),
     }
 }
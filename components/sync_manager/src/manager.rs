@@ -3,10 +3,13 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::error::*;
-use crate::msg_types::{DeviceType, ServiceStatus, SyncParams, SyncReason, SyncResult};
+use crate::msg_types::{
+    DeviceType, ReceivedUri, ServiceStatus, SyncParams, SyncReason, SyncResult,
+};
 use crate::{reset, reset_all, wipe, wipe_all};
 use logins::PasswordEngine;
 use places::{bookmark_sync::store::BookmarksStore, history_sync::store::HistoryStore, PlacesApi};
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::result;
 use std::sync::{atomic::AtomicUsize, Arc, Mutex, Weak};
@@ -16,7 +19,7 @@ use sync15::{
     clients::{self, Command, CommandProcessor, CommandStatus, Settings},
     MemoryCachedState,
 };
-use tabs::TabsEngine;
+use tabs::{TabsEngine, TabsStorage};
 
 const LOGINS_ENGINE: &str = "passwords";
 const HISTORY_ENGINE: &str = "history";
@@ -94,6 +97,19 @@ impl SyncManager {
                     Err(ErrorKind::ConnectionClosed(engine.into()).into())
                 }
             }
+            "tabs" => {
+                if let Some(tabs) = self
+                    .tabs
+                    .upgrade()
+                    .as_ref()
+                    .map(|t| t.lock().expect("poisoned tabs mutex"))
+                {
+                    tabs.wipe()?;
+                    Ok(())
+                } else {
+                    Err(ErrorKind::ConnectionClosed(engine.into()).into())
+                }
+            }
             _ => Err(ErrorKind::UnknownEngine(engine.into()).into()),
         }
     }
@@ -111,6 +127,14 @@ impl SyncManager {
             places.wipe_bookmarks()?;
             places.wipe_history()?;
         }
+        if let Some(tabs) = self
+            .tabs
+            .upgrade()
+            .as_ref()
+            .map(|t| t.lock().expect("poisoned tabs mutex"))
+        {
+            tabs.wipe()?;
+        }
         Ok(())
     }
 
@@ -141,6 +165,19 @@ impl SyncManager {
                     Err(ErrorKind::ConnectionClosed(engine.into()).into())
                 }
             }
+            "tabs" => {
+                if let Some(tabs) = self
+                    .tabs
+                    .upgrade()
+                    .as_ref()
+                    .map(|t| t.lock().expect("poisoned tabs mutex"))
+                {
+                    tabs.reset()?;
+                    Ok(())
+                } else {
+                    Err(ErrorKind::ConnectionClosed(engine.into()).into())
+                }
+            }
             _ => Err(ErrorKind::UnknownEngine(engine.into()).into()),
         }
     }
@@ -158,6 +195,14 @@ impl SyncManager {
             places.reset_bookmarks()?;
             places.reset_history()?;
         }
+        if let Some(tabs) = self
+            .tabs
+            .upgrade()
+            .as_ref()
+            .map(|t| t.lock().expect("poisoned tabs mutex"))
+        {
+            tabs.reset()?;
+        }
         Ok(())
     }
 
@@ -185,6 +230,19 @@ impl SyncManager {
         } else {
             log::warn!("Unable to reset places, be sure to call set_places before disconnect if this is surprising");
         }
+
+        if let Some(tabs) = self
+            .tabs
+            .upgrade()
+            .as_ref()
+            .map(|t| t.lock().expect("poisoned tabs mutex"))
+        {
+            if let Err(e) = tabs.reset() {
+                log::error!("Failed to reset tabs: {}", e);
+            }
+        } else {
+            log::warn!("Unable to reset tabs, be sure to call set_tabs before disconnect if this is surprising");
+        }
     }
 
     pub fn sync(&mut self, params: SyncParams) -> Result<SyncResult> {
@@ -226,6 +284,7 @@ impl SyncManager {
                 persisted_state: params.persisted_state.unwrap_or_default(),
                 // It would be nice to record telemetry here.
                 telemetry_json: None,
+                received_uris: vec![],
             })
         }
     }
@@ -286,17 +345,25 @@ impl SyncManager {
             }
         }
 
-        if let Some(le) = l.as_ref() {
+        // Kept separately, so that logins observers can be told what the
+        // sync applied.
+        let logins_store = l.as_ref().map(|le| {
             assert!(logins_sync, "Should have already checked");
-            stores.push(Box::new(logins::LoginStore::new(&le.db)));
-        }
+            logins::LoginStore::new(&le.db)
+        });
 
-        if let Some(tbs) = t.as_ref() {
+        let tabs_store = t.as_ref().map(|tbs| {
             assert!(tabs_sync, "Should have already checked");
-            stores.push(Box::new(tabs::TabsStore::new(&tbs.storage)));
-        }
+            tabs::TabsStore::new(&tbs.storage)
+        });
 
-        let store_refs: Vec<&dyn sync15::Store> = stores.iter().map(|s| &**s).collect();
+        let mut store_refs: Vec<&dyn sync15::Store> = stores.iter().map(|s| &**s).collect();
+        if let Some(ls) = logins_store.as_ref() {
+            store_refs.push(ls);
+        }
+        if let Some(ts) = tabs_store.as_ref() {
+            store_refs.push(ts);
+        }
 
         let client_init = sync15::Sync15StorageClientInit {
             key_id: params.acct_key_id.clone(),
@@ -327,7 +394,16 @@ impl SyncManager {
                 }
             },
         };
-        let c = SyncClient::new(settings);
+        let outgoing_commands = params
+            .wipe_engines_on_other_clients
+            .drain(..)
+            .map(Command::Wipe)
+            .collect();
+        let c = SyncClient::new(
+            settings,
+            outgoing_commands,
+            t.as_ref().map(|tbs| &tbs.storage),
+        );
         let result = sync15::sync_multiple_with_command_processor(
             Some(&c),
             &store_refs,
@@ -342,6 +418,9 @@ impl SyncManager {
             }),
         );
         self.mem_cached_state = Some(mem_cached_state);
+        if let (Some(le), Some(ls)) = (l.as_ref(), logins_store.as_ref()) {
+            le.notify_sync_applied(ls);
+        }
 
         log::info!("Sync finished with status {:?}", result.service_status);
         let status = ServiceStatus::from(result.service_status) as i32;
@@ -386,6 +465,7 @@ impl SyncManager {
             next_sync_allowed_at: system_time_to_millis(result.next_sync_after),
             persisted_state: disk_cached_state.unwrap_or_default(),
             telemetry_json: Some(telemetry_json),
+            received_uris: c.take_received_uris(),
         })
     }
 }
@@ -463,17 +543,38 @@ fn check_engine_list(list: &[String], have_engines: &[&str]) -> Result<()> {
     Ok(())
 }
 
-struct SyncClient(Settings);
+struct SyncClient<'a> {
+    settings: Settings,
+    // Commands to send to every other client.
+    outgoing_commands: HashSet<Command>,
+    // Only set if we're syncing tabs.
+    tabs: Option<&'a TabsStorage>,
+    // URLs sent to us, returned to the app in the sync result.
+    received_uris: RefCell<Vec<ReceivedUri>>,
+}
 
-impl SyncClient {
-    pub fn new(settings: Settings) -> SyncClient {
-        SyncClient(settings)
+impl<'a> SyncClient<'a> {
+    pub fn new(
+        settings: Settings,
+        outgoing_commands: HashSet<Command>,
+        tabs: Option<&'a TabsStorage>,
+    ) -> SyncClient<'a> {
+        SyncClient {
+            settings,
+            outgoing_commands,
+            tabs,
+            received_uris: RefCell::default(),
+        }
+    }
+
+    fn take_received_uris(&self) -> Vec<ReceivedUri> {
+        self.received_uris.replace(Vec::new())
     }
 }
 
-impl CommandProcessor for SyncClient {
+impl<'a> CommandProcessor for SyncClient<'a> {
     fn settings(&self) -> &Settings {
-        &self.0
+        &self.settings
     }
 
     fn apply_incoming_command(
@@ -485,6 +586,25 @@ impl CommandProcessor for SyncClient {
             Command::WipeAll => wipe_all(),
             Command::Reset(engine) => reset(&engine),
             Command::ResetAll => reset_all(),
+            Command::CloseTab(url) => {
+                // Keep the command until we sync tabs, so that the app can
+                // be told about it.
+                return Ok(match self.tabs {
+                    Some(tabs) => {
+                        tabs.note_incoming_close_tab(url);
+                        CommandStatus::Applied
+                    }
+                    None => CommandStatus::Unsupported,
+                });
+            }
+            Command::DisplayUri { uri, sender, title } => {
+                self.received_uris.borrow_mut().push(ReceivedUri {
+                    uri,
+                    sender_id: sender,
+                    title,
+                });
+                return Ok(CommandStatus::Applied);
+            }
         };
         match result {
             Ok(()) => Ok(CommandStatus::Applied),
@@ -496,6 +616,25 @@ impl CommandProcessor for SyncClient {
     }
 
     fn fetch_outgoing_commands(&self) -> result::Result<HashSet<Command>, failure::Error> {
-        Ok(HashSet::new())
+        Ok(self.outgoing_commands.clone())
+    }
+
+    fn fetch_outgoing_client_commands(
+        &self,
+    ) -> result::Result<HashMap<String, HashSet<Command>>, failure::Error> {
+        Ok(self
+            .tabs
+            .map(TabsStorage::outgoing_close_tab_commands)
+            .unwrap_or_default())
+    }
+
+    fn note_sent_client_commands(
+        &self,
+        commands: HashMap<String, HashSet<Command>>,
+    ) -> result::Result<(), failure::Error> {
+        if let Some(tabs) = self.tabs {
+            tabs.remove_sent_close_tabs(&commands);
+        }
+        Ok(())
     }
 }
@@ -26,6 +26,9 @@ pub struct SyncParams {
     pub device_name: std::string::String,
     #[prost(enumeration="DeviceType", required, tag="12")]
     pub device_type: i32,
+    /// Engines that other clients should wipe their local data for.
+    #[prost(string, repeated, tag="13")]
+    pub wipe_engines_on_other_clients: ::std::vec::Vec<std::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SyncResult {
@@ -45,6 +48,19 @@ pub struct SyncResult {
     pub persisted_state: std::string::String,
     #[prost(string, optional, tag="7")]
     pub telemetry_json: ::std::option::Option<std::string::String>,
+    #[prost(message, repeated, tag="8")]
+    pub received_uris: ::std::vec::Vec<ReceivedUri>,
+}
+/// A URL sent to this device from another client.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReceivedUri {
+    #[prost(string, required, tag="1")]
+    pub uri: std::string::String,
+    /// The record ID of the client that sent the URL.
+    #[prost(string, required, tag="2")]
+    pub sender_id: std::string::String,
+    #[prost(string, required, tag="3")]
+    pub title: std::string::String,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -6,7 +6,9 @@ package mozilla.appservices.remotetabs
 
 data class ClientTabs(
     val clientId: String, // FxA device ID or the Sync client record ID if unavailable.
-    val tabs: List<RemoteTab>
+    val tabs: List<RemoteTab>, // Most recently used first.
+    val clientName: String?,
+    val deviceType: String? // One of "desktop", "mobile", "tablet", "vr" or "tv".
 ) {
     companion object {
         internal fun fromCollectionMessage(msg: MsgTypes.ClientsTabs): List<ClientTabs> {
@@ -15,7 +17,9 @@ data class ClientTabs(
         private fun fromMessage(msg: MsgTypes.ClientTabs): ClientTabs {
             return ClientTabs(
                     clientId = msg.clientId,
-                    tabs = msg.remoteTabsList.map { RemoteTab.fromMessage(it) }
+                    tabs = msg.remoteTabsList.map { RemoteTab.fromMessage(it) },
+                    clientName = if (msg.hasClientName()) msg.clientName else null,
+                    deviceType = if (msg.hasDeviceType()) msg.deviceType else null
             )
         }
     }
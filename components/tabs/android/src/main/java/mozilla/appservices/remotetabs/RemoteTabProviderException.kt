@@ -15,3 +15,9 @@ class SyncAuthInvalidException(msg: String) : RemoteTabProviderException(msg)
  * This error is emitted if a request to a sync server failed.
  */
 class RequestFailedException(msg: String) : RemoteTabProviderException(msg)
+
+/**
+ * This error is emitted if the database is locked by another connection, like
+ * one in the middle of a sync. The operation can be tried again later.
+ */
+class DatabaseBusyException(msg: String) : RemoteTabProviderException(msg)
@@ -12,12 +12,17 @@ import mozilla.appservices.remotetabs.rust.RustError
 import mozilla.appservices.sync15.SyncTelemetryPing
 import java.util.concurrent.atomic.AtomicLong
 
-class RemoteTabsProvider : AutoCloseable {
+/**
+ * @param dbPath If set, the remote tabs are persisted to the database at this
+ * path, so that they're available before the next sync after a restart.
+ * Otherwise, they're only kept in memory.
+ */
+class RemoteTabsProvider(dbPath: String? = null) : AutoCloseable {
     private var handle: AtomicLong = AtomicLong(0)
 
     init {
         handle.set(rustCall { error ->
-            LibRemoteTabsFFI.INSTANCE.remote_tabs_new(error)
+            LibRemoteTabsFFI.INSTANCE.remote_tabs_new_with_path(dbPath, error)
         })
     }
 
@@ -36,7 +41,8 @@ class RemoteTabsProvider : AutoCloseable {
     }
 
     /**
-     * Get the remote tabs. Might be null if we haven't synced yet.
+     * Get the remote tabs. Might be null if we haven't synced yet. The clients
+     * are ordered by their most recently used tab.
      */
     fun getAll(): List<ClientTabs>? {
         val rustBuf = rustCallWithLock { error ->
@@ -53,6 +59,36 @@ class RemoteTabsProvider : AutoCloseable {
         }
     }
 
+    /**
+     * Ask the client with [clientId] to close its tabs with [url]. The command
+     * is sent on the next sync through the sync manager.
+     */
+    fun closeRemoteTab(clientId: String, url: String) {
+        rustCallWithLock { error ->
+            LibRemoteTabsFFI.INSTANCE.remote_tabs_close_remote_tab(
+                this.handle.get(), clientId, url, error)
+        }
+    }
+
+    /**
+     * Get the URLs of the local tabs that other clients asked us to close since
+     * the last call.
+     */
+    fun takeCloseTabRequests(): List<String> {
+        val rustBuf = rustCallWithLock { error ->
+            LibRemoteTabsFFI.INSTANCE.remote_tabs_take_close_tab_requests(
+                this.handle.get(), error)
+        }
+
+        try {
+            return rustBuf.asCodedInputStream()?.let { stream ->
+                MsgTypes.CloseTabRequests.parseFrom(stream).urlsList
+            } ?: listOf()
+        } finally {
+            LibRemoteTabsFFI.INSTANCE.remote_tabs_destroy_bytebuffer(rustBuf)
+        }
+    }
+
     /**
      * Convenience Sync function.
      */
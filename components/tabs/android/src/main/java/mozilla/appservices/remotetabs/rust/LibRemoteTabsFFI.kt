@@ -22,6 +22,11 @@ internal interface LibRemoteTabsFFI : Library {
         error: RustError.ByReference
     ): TabsApiHandle
 
+    fun remote_tabs_new_with_path(
+        db_path: String?,
+        error: RustError.ByReference
+    ): TabsApiHandle
+
     fun remote_tabs_destroy(handle: TabsApiHandle, error: RustError.ByReference)
 
     fun remote_tabs_update_local(
@@ -36,6 +41,18 @@ internal interface LibRemoteTabsFFI : Library {
         error: RustError.ByReference
     ): RustBuffer.ByValue
 
+    fun remote_tabs_close_remote_tab(
+        handle: TabsApiHandle,
+        client_id: String,
+        url: String,
+        error: RustError.ByReference
+    )
+
+    fun remote_tabs_take_close_tab_requests(
+        handle: TabsApiHandle,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
     // Returns a JSON string containing a sync ping.
     fun remote_tabs_sync(
         handle: TabsApiHandle,
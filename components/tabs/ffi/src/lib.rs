@@ -11,6 +11,7 @@ use ffi_support::{
 };
 use std::{
     os::raw::c_char,
+    path::Path,
     sync::{Arc, Mutex},
 };
 use tabs::{Result, TabsEngine};
@@ -34,6 +35,17 @@ pub extern "C" fn remote_tabs_new(error: &mut ExternError) -> u64 {
     })
 }
 
+/// Like `remote_tabs_new`, but persists the remote tabs to the database at
+/// `db_path`. Passing a null `db_path` keeps them in memory.
+#[no_mangle]
+pub extern "C" fn remote_tabs_new_with_path(db_path: FfiStr<'_>, error: &mut ExternError) -> u64 {
+    log::debug!("remote_tabs_new_with_path");
+    ENGINES.insert_with_result(error, || -> Result<_> {
+        let engine = TabsEngine::new_with_db_path(db_path.as_opt_str().map(Path::new))?;
+        Ok(Arc::new(Mutex::new(engine)))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn remote_tabs_sync(
     handle: u64,
@@ -89,11 +101,42 @@ pub extern "C" fn remote_tabs_get_all(handle: u64, error: &mut ExternError) -> B
         Ok(engine
             .lock()
             .unwrap()
-            .remote_tabs()
+            .get_all()
             .map(|tabs| -> ClientsTabs { tabs.into() }))
     })
 }
 
+#[no_mangle]
+pub extern "C" fn remote_tabs_close_remote_tab(
+    handle: u64,
+    client_id: FfiStr<'_>,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("remote_tabs_close_remote_tab");
+    ENGINES.call_with_result(error, handle, |engine| -> Result<_> {
+        engine
+            .lock()
+            .unwrap()
+            .close_remote_tab(client_id.as_str(), url.as_str());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn remote_tabs_take_close_tab_requests(
+    handle: u64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("remote_tabs_take_close_tab_requests");
+    use tabs::msg_types::CloseTabRequests;
+    ENGINES.call_with_result(error, handle, |engine| -> Result<_> {
+        Ok(CloseTabRequests {
+            urls: engine.lock().unwrap().take_incoming_close_tabs(),
+        })
+    })
+}
+
 unsafe fn get_buffer<'a>(data: *const u8, len: i32) -> &'a [u8] {
     assert!(len >= 0, "Bad buffer len: {}", len);
     if len == 0 {
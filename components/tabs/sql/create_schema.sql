@@ -0,0 +1,17 @@
+-- This Source Code Form is subject to the terms of the Mozilla Public
+-- License, v. 2.0. If a copy of the MPL was not distributed with this
+-- file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+-- The tabs of other clients, as of the last sync, so that they can be shown
+-- before the next sync after a restart. Our own tabs aren't stored, since the
+-- app tells us about them on startup.
+
+CREATE TABLE IF NOT EXISTS remote_tabs (
+    client_id TEXT NOT NULL PRIMARY KEY,
+    client_name TEXT NOT NULL,
+    device_type TEXT NOT NULL,
+    /* A JSON array of the client's tabs. */
+    tabs TEXT NOT NULL,
+    /* The server timestamp of the client's tabs record, in milliseconds. */
+    last_modified INTEGER NOT NULL
+);
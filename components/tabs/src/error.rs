@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use failure::Fail;
+use sql_support::{open_database, MaybeBusy};
 
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
@@ -17,6 +18,17 @@ pub enum ErrorKind {
 
     #[fail(display = "Protobuf decode error: {}", _0)]
     ProtobufDecodeError(#[fail(cause)] prost::DecodeError),
+
+    #[fail(display = "Error executing SQL: {}", _0)]
+    SqlError(#[fail(cause)] rusqlite::Error),
+
+    #[fail(display = "Error opening database: {}", _0)]
+    OpenDatabaseError(#[fail(cause)] open_database::Error),
+
+    /// Another connection holds a lock on the database, so the operation can
+    /// be tried again later.
+    #[fail(display = "The database is busy: {}", _0)]
+    DatabaseBusy(#[fail(cause)] rusqlite::Error),
 }
 
 error_support::define_error! {
@@ -27,3 +39,75 @@ error_support::define_error! {
         (ProtobufDecodeError, prost::DecodeError),
     }
 }
+
+// SQL errors are converted by hand, so that lock contention becomes
+// `DatabaseBusy`.
+impl From<rusqlite::Error> for ErrorKind {
+    #[cold]
+    fn from(e: rusqlite::Error) -> ErrorKind {
+        if e.is_busy() {
+            ErrorKind::DatabaseBusy(e)
+        } else {
+            ErrorKind::SqlError(e)
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    #[cold]
+    fn from(e: rusqlite::Error) -> Self {
+        ErrorKind::from(e).into()
+    }
+}
+
+impl From<open_database::Error> for ErrorKind {
+    #[cold]
+    fn from(e: open_database::Error) -> ErrorKind {
+        match e {
+            open_database::Error::SqlError(e) => e.into(),
+            e => ErrorKind::OpenDatabaseError(e),
+        }
+    }
+}
+
+impl From<open_database::Error> for Error {
+    #[cold]
+    fn from(e: open_database::Error) -> Self {
+        ErrorKind::from(e).into()
+    }
+}
+
+impl MaybeBusy for Error {
+    fn is_busy(&self) -> bool {
+        match self.kind() {
+            ErrorKind::DatabaseBusy(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlite_error(code: std::os::raw::c_int) -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None)
+    }
+
+    #[test]
+    fn test_busy_errors() {
+        for code in &[rusqlite::ffi::SQLITE_BUSY, rusqlite::ffi::SQLITE_LOCKED] {
+            let err = Error::from(sqlite_error(*code));
+            assert!(matches!(err.kind(), ErrorKind::DatabaseBusy(_)));
+            assert!(err.is_busy());
+            let err = Error::from(open_database::Error::SqlError(sqlite_error(*code)));
+            assert!(err.is_busy());
+        }
+
+        let err = Error::from(sqlite_error(rusqlite::ffi::SQLITE_CORRUPT));
+        assert!(matches!(err.kind(), ErrorKind::SqlError(_)));
+        assert!(!err.is_busy());
+        let err = Error::from(open_database::Error::IncompatibleVersion(2));
+        assert!(matches!(err.kind(), ErrorKind::OpenDatabaseError(_)));
+    }
+}
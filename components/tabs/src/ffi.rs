@@ -21,6 +21,9 @@ pub mod error_codes {
 
     /// A request to the sync server failed.
     pub const NETWORK: i32 = 2;
+
+    /// The database is locked by another connection.
+    pub const DATABASE_BUSY: i32 = 3;
 }
 
 fn get_code(err: &Error) -> ErrorCode {
@@ -36,6 +39,11 @@ fn get_code(err: &Error) -> ErrorCode {
             }
         }
 
+        ErrorKind::DatabaseBusy(e) => {
+            log::warn!("Database busy: {:?}", e);
+            ErrorCode::new(error_codes::DATABASE_BUSY)
+        }
+
         err => {
             log::error!("Unexpected error: {:?}", err);
             ErrorCode::new(error_codes::UNEXPECTED)
@@ -56,6 +64,8 @@ impl From<ClientRemoteTabs> for msg_types::ClientTabs {
         Self {
             client_id: client.client_id,
             remote_tabs: client.remote_tabs.into_iter().map(Into::into).collect(),
+            client_name: Some(client.client_name),
+            device_type: Some(client.device_type.as_str().to_owned()),
         }
     }
 }
@@ -95,3 +105,4 @@ impl From<Error> for ExternError {
 }
 
 implement_into_ffi_by_protobuf!(msg_types::ClientsTabs);
+implement_into_ffi_by_protobuf!(msg_types::CloseTabRequests);
@@ -8,6 +8,7 @@
 #[macro_use]
 pub mod error;
 mod ffi;
+mod schema;
 mod storage;
 mod sync;
 
@@ -15,10 +16,11 @@ pub mod msg_types {
     include!("mozilla.appservices.remotetabs.protobuf.rs");
 }
 
-pub use crate::storage::{ClientRemoteTabs, RemoteTab};
+pub use crate::storage::{ClientRemoteTabs, RemoteTab, TabsStorage};
 pub use crate::sync::engine::TabsEngine;
 pub use crate::sync::store::TabsStore;
 pub use error::{Error, ErrorKind, Result};
+pub use sql_support::open_database::QuarantinedDatabase;
 
 // Re-export `DeviceType`, so that it's easier for consumers to make
 // `ClientRemoteTabs` structs without importing `sync15`.
@@ -4,6 +4,11 @@ pub struct ClientTabs {
     pub client_id: std::string::String,
     #[prost(message, repeated, tag="2")]
     pub remote_tabs: ::std::vec::Vec<RemoteTab>,
+    #[prost(string, optional, tag="3")]
+    pub client_name: ::std::option::Option<std::string::String>,
+    /// One of "desktop", "mobile", "tablet", "vr" or "tv".
+    #[prost(string, optional, tag="4")]
+    pub device_type: ::std::option::Option<std::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ClientsTabs {
@@ -26,3 +31,8 @@ pub struct RemoteTabs {
     #[prost(message, repeated, tag="1")]
     pub remote_tabs: ::std::vec::Vec<RemoteTab>,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CloseTabRequests {
+    #[prost(string, repeated, tag="1")]
+    pub urls: ::std::vec::Vec<std::string::String>,
+}
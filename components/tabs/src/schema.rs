@@ -0,0 +1,32 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use rusqlite::Transaction;
+use sql_support::open_database::{ConnectionInitializer, Error, Result};
+
+const CREATE_SCHEMA_SQL: &str = include_str!("../sql/create_schema.sql");
+
+pub struct TabsConnectionInitializer;
+
+impl ConnectionInitializer for TabsConnectionInitializer {
+    const NAME: &'static str = "tabs";
+    const END_VERSION: u32 = 1;
+
+    fn init(&self, tx: &Transaction<'_>) -> Result<()> {
+        tx.execute_batch(CREATE_SCHEMA_SQL)?;
+        Ok(())
+    }
+
+    fn upgrade_from(&self, _tx: &Transaction<'_>, version: u32) -> Result<()> {
+        // There's only ever been one version, so there's nothing to upgrade.
+        Err(Error::IncompatibleVersion(version))
+    }
+
+    fn downgrade_from(&self, tx: &Transaction<'_>, _version: u32) -> Result<()> {
+        // Remote tabs are only a cache of what's on the server, so it's fine
+        // to throw away what a newer version stored.
+        tx.execute_batch("DROP TABLE IF EXISTS remote_tabs")?;
+        self.init(tx)
+    }
+}
@@ -6,11 +6,28 @@
 const URI_LENGTH_MAX: usize = 65536;
 // https://searchfox.org/mozilla-central/rev/ea63a0888d406fae720cf24f4727d87569a8cab5/services/sync/modules/engines/tabs.js#8
 const TAB_ENTRIES_LIMIT: usize = 5;
+// Clients whose tabs record hasn't changed in this long are forgotten. This is
+// the TTL of tabs records on the server.
+// https://searchfox.org/mozilla-central/rev/ea63a0888d406fae720cf24f4727d87569a8cab5/services/sync/modules/engines/tabs.js#16
+const CLIENT_TABS_TTL_MS: i64 = 1_814_400 * 1000; // 21 days
 
-use std::cell::RefCell;
-use sync15::clients::DeviceType;
+use crate::error::*;
+use crate::schema;
+use interrupt_support::Interruptable;
+use rusqlite::{Connection, OpenFlags, Row};
+use serde_derive::{Deserialize, Serialize};
+use sql_support::{
+    open_database::{self, QuarantinedDatabase},
+    ConnExt, SqlInterruptHandle,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{atomic::AtomicUsize, Arc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sync15::clients::{Command, DeviceType};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct RemoteTab {
     pub title: String,
     pub url_history: Vec<String>,
@@ -24,11 +41,48 @@ pub struct ClientRemoteTabs {
     pub client_name: String,
     pub device_type: DeviceType,
     pub remote_tabs: Vec<RemoteTab>,
+    pub last_modified: i64, // In ms, the server timestamp of the client's tabs record.
+}
+
+impl ClientRemoteTabs {
+    /// When the most recently used tab of the client was last used, in ms.
+    pub fn last_used(&self) -> u64 {
+        self.remote_tabs
+            .iter()
+            .map(|tab| tab.last_used)
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            client_id: row.get("client_id")?,
+            client_name: row.get("client_name")?,
+            device_type: DeviceType::try_from_str(row.get::<_, String>("device_type")?)
+                .unwrap_or(DeviceType::Mobile),
+            remote_tabs: serde_json::from_str(&row.get::<_, String>("tabs")?)?,
+            last_modified: row.get("last_modified")?,
+        })
+    }
 }
 
 pub struct TabsStorage {
     local_tabs: RefCell<Option<Vec<RemoteTab>>>,
     remote_tabs: RefCell<Option<Vec<ClientRemoteTabs>>>,
+    // URLs of the tabs to close on other clients, keyed by client ID.
+    outgoing_close_tabs: RefCell<HashMap<String, HashSet<String>>>,
+    // URLs of the local tabs other clients asked us to close.
+    incoming_close_tabs: RefCell<Vec<String>>,
+    // A hash of the local tabs record we last uploaded, so that we only
+    // upload it again if it changes.
+    last_uploaded_hash: Cell<Option<u64>>,
+    // Only set if the remote tabs are persisted to disk.
+    db: Option<Connection>,
+    // Set if the database was corrupt when we opened it.
+    quarantined_db: Option<QuarantinedDatabase>,
+    // Lets `interrupt_support::shutdown` interrupt the database, which only
+    // holds a weak reference to it.
+    shutdown_handle: Option<Arc<SqlInterruptHandle>>,
 }
 
 impl Default for TabsStorage {
@@ -42,9 +96,50 @@ impl TabsStorage {
         Self {
             local_tabs: RefCell::default(),
             remote_tabs: RefCell::default(),
+            outgoing_close_tabs: RefCell::default(),
+            incoming_close_tabs: RefCell::default(),
+            last_uploaded_hash: Cell::default(),
+            db: None,
+            quarantined_db: None,
+            shutdown_handle: None,
         }
     }
 
+    /// Creates a storage which persists the remote tabs to the database at
+    /// `db_path`, so that they're available before the next sync after a
+    /// restart, or only keeps them in memory if `db_path` is `None`.
+    pub fn new_with_db_path(db_path: Option<&Path>) -> Result<Self> {
+        let mut storage = Self::new();
+        if let Some(db_path) = db_path {
+            let opened = open_database::open_database_with_recovery(
+                db_path,
+                OpenFlags::default(),
+                &schema::TabsConnectionInitializer,
+            )?;
+            let remote_tabs = sql_support::retry_if_busy(|| load_remote_tabs(&opened.conn))?;
+            if !remote_tabs.is_empty() {
+                storage.remote_tabs.replace(Some(remote_tabs));
+            }
+            let shutdown_handle = Arc::new(SqlInterruptHandle::new(
+                opened.conn.get_interrupt_handle(),
+                Arc::new(AtomicUsize::new(0)),
+            ));
+            interrupt_support::register_interrupt(
+                Arc::downgrade(&shutdown_handle) as Weak<dyn Interruptable>
+            );
+            storage.db = Some(opened.conn);
+            storage.shutdown_handle = Some(shutdown_handle);
+            storage.quarantined_db = opened.quarantined;
+        }
+        Ok(storage)
+    }
+
+    /// Returns where the database was moved to if it was corrupt when it was
+    /// opened, and had to be recreated.
+    pub fn quarantined_database(&self) -> Option<&QuarantinedDatabase> {
+        self.quarantined_db.as_ref()
+    }
+
     pub fn update_local_state(&mut self, local_state: Vec<RemoteTab>) {
         self.local_tabs.borrow_mut().replace(local_state);
     }
@@ -81,19 +176,143 @@ impl TabsStorage {
         self.remote_tabs.borrow().clone()
     }
 
-    pub(crate) fn replace_remote_tabs(&self, new_remote_tabs: Vec<ClientRemoteTabs>) {
+    /// Like `get_remote_tabs`, but with the most recently used tabs first, and
+    /// the clients ordered by their most recently used tab.
+    pub fn get_remote_tabs_by_recency(&self) -> Option<Vec<ClientRemoteTabs>> {
+        let mut remote_tabs = self.get_remote_tabs()?;
+        for client in &mut remote_tabs {
+            client
+                .remote_tabs
+                .sort_by(|a, b| b.last_used.cmp(&a.last_used));
+        }
+        remote_tabs.sort_by(|a, b| b.last_used().cmp(&a.last_used()));
+        Some(remote_tabs)
+    }
+
+    pub(crate) fn last_uploaded_hash(&self) -> Option<u64> {
+        self.last_uploaded_hash.get()
+    }
+
+    pub(crate) fn set_last_uploaded_hash(&self, hash: u64) {
+        self.last_uploaded_hash.set(Some(hash));
+    }
+
+    pub(crate) fn replace_remote_tabs(&self, new_remote_tabs: Vec<ClientRemoteTabs>) -> Result<()> {
+        if let Some(db) = &self.db {
+            let tx = db.unchecked_transaction()?;
+            db.execute_batch("DELETE FROM remote_tabs")?;
+            for client in &new_remote_tabs {
+                db.execute_named_cached(
+                    "INSERT INTO remote_tabs
+                         (client_id, client_name, device_type, tabs, last_modified)
+                     VALUES
+                         (:client_id, :client_name, :device_type, :tabs, :last_modified)",
+                    &[
+                        (":client_id", &client.client_id),
+                        (":client_name", &client.client_name),
+                        (":device_type", &client.device_type.as_str()),
+                        (":tabs", &serde_json::to_string(&client.remote_tabs)?),
+                        (":last_modified", &client.last_modified),
+                    ],
+                )?;
+            }
+            tx.commit()?;
+        }
         let mut remote_tabs = self.remote_tabs.borrow_mut();
         remote_tabs.replace(new_remote_tabs);
+        Ok(())
     }
 
-    pub fn wipe(&self, delete_local_tabs: bool) {
+    /// Queues a command asking the client with `client_id` to close its tabs
+    /// with `url`. The command is written to the client's record in the
+    /// `clients` collection on the next sync.
+    pub fn queue_close_tab(&self, client_id: &str, url: &str) {
+        self.outgoing_close_tabs
+            .borrow_mut()
+            .entry(client_id.to_owned())
+            .or_default()
+            .insert(url.to_owned());
+    }
+
+    /// Returns the queued close tab commands, for the command processor to
+    /// send to other clients.
+    pub fn outgoing_close_tab_commands(&self) -> HashMap<String, HashSet<Command>> {
+        self.outgoing_close_tabs
+            .borrow()
+            .iter()
+            .map(|(client_id, urls)| {
+                (
+                    client_id.clone(),
+                    urls.iter().cloned().map(Command::CloseTab).collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// Forgets the queued close tab commands in `sent`, once they've been
+    /// written to the `clients` collection. Commands which weren't written,
+    /// or which were queued during the sync, stay queued.
+    pub fn remove_sent_close_tabs(&self, sent: &HashMap<String, HashSet<Command>>) {
+        let mut outgoing = self.outgoing_close_tabs.borrow_mut();
+        for (client_id, commands) in sent {
+            if let Some(urls) = outgoing.get_mut(client_id) {
+                for command in commands {
+                    if let Command::CloseTab(url) = command {
+                        urls.remove(url);
+                    }
+                }
+                if urls.is_empty() {
+                    outgoing.remove(client_id);
+                }
+            }
+        }
+    }
+
+    /// Records that another client asked us to close the tabs with `url`.
+    pub fn note_incoming_close_tab(&self, url: String) {
+        let mut incoming = self.incoming_close_tabs.borrow_mut();
+        if !incoming.contains(&url) {
+            incoming.push(url);
+        }
+    }
+
+    /// Returns the URLs of the tabs other clients asked us to close, in the
+    /// order the commands arrived, and forgets them.
+    pub fn take_incoming_close_tabs(&self) -> Vec<String> {
+        self.incoming_close_tabs.replace(Vec::new())
+    }
+
+    pub fn wipe(&self, delete_local_tabs: bool) -> Result<()> {
+        if let Some(db) = &self.db {
+            db.execute_batch("DELETE FROM remote_tabs")?;
+        }
         self.remote_tabs.replace(None);
+        self.last_uploaded_hash.set(None);
         if delete_local_tabs {
             self.local_tabs.replace(None);
         }
+        Ok(())
     }
 }
 
+// Loads the remote tabs stored by the last sync, after forgetting the clients
+// we haven't heard from in a while.
+fn load_remote_tabs(db: &Connection) -> Result<Vec<ClientRemoteTabs>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    db.execute_named_cached(
+        "DELETE FROM remote_tabs WHERE last_modified < :cutoff",
+        &[(":cutoff", &(now - CLIENT_TABS_TTL_MS))],
+    )?;
+    db.query_rows_and_then_named_cached(
+        "SELECT * FROM remote_tabs ORDER BY client_id",
+        &[],
+        ClientRemoteTabs::from_row,
+    )
+}
+
 fn is_url_syncable(url: &str) -> bool {
     url.len() <= URI_LENGTH_MAX
         && !(url.starts_with("about:")
@@ -118,6 +337,218 @@ mod tests {
         assert!(!is_url_syncable("file:///Users/eoger/bobo"));
     }
 
+    fn client(client_id: &str, last_modified: i64) -> ClientRemoteTabs {
+        ClientRemoteTabs {
+            client_id: client_id.to_owned(),
+            client_name: format!("{} name", client_id),
+            device_type: DeviceType::Desktop,
+            remote_tabs: vec![RemoteTab {
+                title: "Foo".to_owned(),
+                url_history: vec!["https://foo.bar".to_owned()],
+                icon: None,
+                last_used: 1000,
+            }],
+            last_modified,
+        }
+    }
+
+    #[test]
+    fn test_remote_tabs_by_recency() {
+        let storage = TabsStorage::new();
+        assert!(storage.get_remote_tabs_by_recency().is_none());
+        let tab = |url: &str, last_used| RemoteTab {
+            title: url.to_owned(),
+            url_history: vec![url.to_owned()],
+            icon: None,
+            last_used,
+        };
+        let mut client_a = client("device-a", 0);
+        client_a.remote_tabs = vec![tab("https://a1.bar", 1000), tab("https://a2.bar", 3000)];
+        let mut client_b = client("device-b", 0);
+        client_b.remote_tabs = vec![tab("https://b1.bar", 4000), tab("https://b2.bar", 2000)];
+        let client_c = ClientRemoteTabs {
+            remote_tabs: vec![],
+            ..client("device-c", 0)
+        };
+        storage
+            .replace_remote_tabs(vec![client_a, client_c, client_b])
+            .unwrap();
+        let remote_tabs = storage.get_remote_tabs_by_recency().unwrap();
+        assert_eq!(
+            remote_tabs
+                .iter()
+                .map(|client| client.client_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["device-b", "device-a", "device-c"]
+        );
+        assert_eq!(
+            remote_tabs[1]
+                .remote_tabs
+                .iter()
+                .map(|tab| tab.title.as_str())
+                .collect::<Vec<_>>(),
+            vec!["https://a2.bar", "https://a1.bar"]
+        );
+        assert_eq!(remote_tabs[0].last_used(), 4000);
+    }
+
+    #[test]
+    fn test_persisted_remote_tabs() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let db_path = tmpdir.path().join("tabs.db");
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+
+        let storage = TabsStorage::new_with_db_path(Some(&db_path)).unwrap();
+        assert!(storage.get_remote_tabs().is_none());
+        storage
+            .replace_remote_tabs(vec![
+                client("device-a", now),
+                client("device-b", now - CLIENT_TABS_TTL_MS - 1000),
+            ])
+            .unwrap();
+        assert_eq!(storage.get_remote_tabs().unwrap().len(), 2);
+        drop(storage);
+
+        // The stale client is forgotten when loading.
+        let storage = TabsStorage::new_with_db_path(Some(&db_path)).unwrap();
+        let remote_tabs = storage.get_remote_tabs().unwrap();
+        assert_eq!(remote_tabs.len(), 1);
+        assert_eq!(remote_tabs[0].client_id, "device-a");
+        assert_eq!(remote_tabs[0].client_name, "device-a name");
+        assert_eq!(remote_tabs[0].device_type, DeviceType::Desktop);
+        assert_eq!(
+            remote_tabs[0].remote_tabs,
+            client("device-a", now).remote_tabs
+        );
+        assert_eq!(remote_tabs[0].last_modified, now);
+
+        storage.wipe(false).unwrap();
+        drop(storage);
+        let storage = TabsStorage::new_with_db_path(Some(&db_path)).unwrap();
+        assert!(storage.get_remote_tabs().is_none());
+
+        // Without a path, nothing is persisted.
+        let storage = TabsStorage::new_with_db_path(None).unwrap();
+        storage
+            .replace_remote_tabs(vec![client("device-a", now)])
+            .unwrap();
+        assert_eq!(storage.get_remote_tabs().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_corrupt_database_is_recreated() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let db_path = tmpdir.path().join("tabs.db");
+        std::fs::write(&db_path, b"not a database").unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+
+        let storage = TabsStorage::new_with_db_path(Some(&db_path)).unwrap();
+        assert!(storage.get_remote_tabs().is_none());
+        let quarantined = storage
+            .quarantined_database()
+            .expect("should quarantine the database");
+        assert_eq!(quarantined.path, tmpdir.path().join("tabs.db.corrupt"));
+        storage
+            .replace_remote_tabs(vec![client("device-a", now)])
+            .unwrap();
+        drop(storage);
+
+        let storage = TabsStorage::new_with_db_path(Some(&db_path)).unwrap();
+        assert!(storage.quarantined_database().is_none());
+        assert_eq!(storage.get_remote_tabs().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_future_schema_is_recreated() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let db_path = tmpdir.path().join("tabs.db");
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let storage = TabsStorage::new_with_db_path(Some(&db_path)).unwrap();
+        storage
+            .replace_remote_tabs(vec![client("device-a", now)])
+            .unwrap();
+        drop(storage);
+
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch("PRAGMA user_version = 100")
+            .unwrap();
+        let storage = TabsStorage::new_with_db_path(Some(&db_path)).unwrap();
+        assert!(storage.get_remote_tabs().is_none());
+        assert_eq!(
+            storage
+                .db
+                .as_ref()
+                .unwrap()
+                .query_one::<i64>("PRAGMA user_version")
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_close_tab_commands() {
+        let storage = TabsStorage::new();
+        assert!(storage.outgoing_close_tab_commands().is_empty());
+        storage.queue_close_tab("device-a", "https://foo.bar");
+        storage.queue_close_tab("device-a", "https://foo.bar");
+        storage.queue_close_tab("device-a", "https://foo2.bar");
+        storage.queue_close_tab("device-b", "https://foo.bar");
+        let commands = storage.outgoing_close_tab_commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(
+            commands["device-a"],
+            vec![
+                Command::CloseTab("https://foo.bar".into()),
+                Command::CloseTab("https://foo2.bar".into()),
+            ]
+            .into_iter()
+            .collect()
+        );
+
+        // A command queued during the sync isn't removed, nor are the ones
+        // which weren't sent.
+        storage.queue_close_tab("device-b", "https://new.bar");
+        let sent = vec![(
+            "device-a".to_string(),
+            vec![Command::CloseTab("https://foo.bar".into())]
+                .into_iter()
+                .collect(),
+        )]
+        .into_iter()
+        .collect();
+        storage.remove_sent_close_tabs(&sent);
+        let commands = storage.outgoing_close_tab_commands();
+        assert_eq!(
+            commands["device-a"],
+            vec![Command::CloseTab("https://foo2.bar".into())]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(commands["device-b"].len(), 2);
+
+        storage.remove_sent_close_tabs(&commands);
+        assert!(storage.outgoing_close_tab_commands().is_empty());
+
+        storage.note_incoming_close_tab("https://foo2.bar".into());
+        storage.note_incoming_close_tab("https://foo.bar".into());
+        storage.note_incoming_close_tab("https://foo2.bar".into());
+        assert_eq!(
+            storage.take_incoming_close_tabs(),
+            vec!["https://foo2.bar".to_owned(), "https://foo.bar".to_owned()]
+        );
+        assert!(storage.take_incoming_close_tabs().is_empty());
+    }
+
     #[test]
     fn test_prepare_local_tabs_for_upload() {
         let mut storage = TabsStorage::new();
@@ -5,8 +5,10 @@
 use crate::error::*;
 use crate::storage::{ClientRemoteTabs, RemoteTab, TabsStorage};
 use crate::sync::store::TabsStore;
-use interrupt_support::NeverInterrupts;
+use interrupt_support::ShutdownInterruptee;
+use sql_support::open_database::QuarantinedDatabase;
 use std::cell::{Cell, RefCell};
+use std::path::Path;
 use sync15::{sync_multiple, telemetry, KeyBundle, MemoryCachedState, Sync15StorageClientInit};
 
 pub struct TabsEngine {
@@ -28,6 +30,23 @@ impl TabsEngine {
         }
     }
 
+    /// Creates an engine which persists the remote tabs to the database at
+    /// `db_path`, or only keeps them in memory if `db_path` is `None`.
+    pub fn new_with_db_path(db_path: Option<&Path>) -> Result<Self> {
+        Ok(Self {
+            storage: TabsStorage::new_with_db_path(db_path)?,
+            mem_cached_state: Cell::default(),
+        })
+    }
+
+    /// Returns where the database was moved to if it was corrupt when the
+    /// engine was created, and had to be recreated. The remote tabs stored in
+    /// it are lost, but each sync downloads all the remote tabs from the
+    /// server, so they're restored by the next one.
+    pub fn quarantined_database(&self) -> Option<&QuarantinedDatabase> {
+        self.storage.quarantined_database()
+    }
+
     pub fn update_local_state(&mut self, local_state: Vec<RemoteTab>) {
         self.storage.update_local_state(local_state);
     }
@@ -36,6 +55,37 @@ impl TabsEngine {
         self.storage.get_remote_tabs()
     }
 
+    /// Returns the tabs of other clients, most recently used first, or `None`
+    /// if we haven't synced yet. Each client is ordered by its most recently
+    /// used tab.
+    pub fn get_all(&self) -> Option<Vec<ClientRemoteTabs>> {
+        self.storage.get_remote_tabs_by_recency()
+    }
+
+    /// Asks the client with `client_id` to close its tabs with `url`. The
+    /// command is only sent when syncing through the sync manager, which
+    /// also syncs the `clients` collection.
+    pub fn close_remote_tab(&self, client_id: &str, url: &str) {
+        self.storage.queue_close_tab(client_id, url);
+    }
+
+    /// Returns the URLs of the local tabs that other clients asked us to
+    /// close since the last call.
+    pub fn take_incoming_close_tabs(&self) -> Vec<String> {
+        self.storage.take_incoming_close_tabs()
+    }
+
+    /// Forgets the tabs of other clients, and the local tabs.
+    pub fn wipe(&self) -> Result<()> {
+        self.storage.wipe(true)
+    }
+
+    /// Forgets the tabs of other clients, so that they're fetched again on
+    /// the next sync.
+    pub fn reset(&self) -> Result<()> {
+        self.storage.wipe(false)
+    }
+
     /// A convenience wrapper around sync_multiple.
     pub fn sync(
         &self,
@@ -58,7 +108,7 @@ impl TabsEngine {
             &mut mem_cached_state,
             storage_init,
             root_sync_key,
-            &NeverInterrupts,
+            &ShutdownInterruptee,
             None,
         );
 
@@ -6,6 +6,8 @@ use crate::storage::TabsStorage;
 use crate::storage::{ClientRemoteTabs, RemoteTab};
 use crate::sync::record::{TabsRecord, TabsRecordTab};
 use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::{collections::HashMap, result};
 use sync15::{
     clients::{self, DeviceType, RemoteClient},
@@ -38,21 +40,24 @@ impl ClientRemoteTabs {
         client_id: String,
         remote_client: &RemoteClient,
         record: TabsRecord,
+        last_modified: ServerTimestamp,
     ) -> Self {
         Self {
             client_id,
             client_name: remote_client.device_name.clone(),
             device_type: remote_client.device_type.unwrap_or(DeviceType::Mobile),
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
+            last_modified: last_modified.as_millis(),
         }
     }
 
-    fn from_record(client_id: String, record: TabsRecord) -> Self {
+    fn from_record(client_id: String, record: TabsRecord, last_modified: ServerTimestamp) -> Self {
         Self {
             client_id,
             client_name: record.client_name,
             device_type: DeviceType::Mobile,
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
+            last_modified: last_modified.as_millis(),
         }
     }
     fn to_record(&self) -> TabsRecord {
@@ -74,6 +79,9 @@ pub struct TabsStore<'a> {
     last_sync: Cell<Option<ServerTimestamp>>, // We use a cell because `sync_finished` doesn't take a mutable reference to &self.
     sync_store_assoc: RefCell<StoreSyncAssociation>,
     pub(crate) local_id: RefCell<String>,
+    // The hash of the local record we're uploading, saved once the upload
+    // succeeds.
+    pending_upload_hash: Cell<Option<u64>>,
 }
 
 impl<'a> TabsStore<'a> {
@@ -84,11 +92,12 @@ impl<'a> TabsStore<'a> {
             last_sync: Cell::default(),
             sync_store_assoc: RefCell::new(StoreSyncAssociation::Disconnected),
             local_id: RefCell::default(), // Will get replaced in `prepare_for_sync`.
+            pending_upload_hash: Cell::default(),
         }
     }
     fn wipe_reset_helper(&self, is_wipe: bool) -> result::Result<(), failure::Error> {
         self.remote_clients.borrow_mut().clear();
-        self.storage.wipe(is_wipe);
+        self.storage.wipe(is_wipe)?;
         Ok(())
     }
 }
@@ -118,13 +127,11 @@ impl<'a> Store for TabsStore<'a> {
         let mut incoming_telemetry = telemetry::EngineIncoming::new();
         let local_id = self.local_id.borrow().clone();
         let mut remote_tabs = Vec::with_capacity(inbound.changes.len());
+        let mut server_record_hash = None;
 
-        for incoming in inbound.changes {
-            if incoming.0.id() == local_id {
-                // That's our own record, ignore it.
-                continue;
-            }
-            let record = match TabsRecord::from_payload(incoming.0) {
+        for (payload, last_modified) in inbound.changes {
+            let is_own_record = payload.id() == local_id;
+            let record = match TabsRecord::from_payload(payload) {
                 Ok(record) => record,
                 Err(e) => {
                     log::warn!("Error deserializing incoming record: {}", e);
@@ -132,6 +139,11 @@ impl<'a> Store for TabsStore<'a> {
                     continue;
                 }
             };
+            if is_own_record {
+                // That's our own record, only keep track of what's in it.
+                server_record_hash = Some(hash_record(&record));
+                continue;
+            }
             let id = record.id.clone();
             let tab = if let Some(remote_client) = self.remote_clients.borrow().get(&id) {
                 ClientRemoteTabs::from_record_with_remote_client(
@@ -142,13 +154,14 @@ impl<'a> Store for TabsStore<'a> {
                         .to_owned(),
                     remote_client,
                     record,
+                    last_modified,
                 )
             } else {
-                ClientRemoteTabs::from_record(id, record)
+                ClientRemoteTabs::from_record(id, record, last_modified)
             };
             remote_tabs.push(tab);
         }
-        self.storage.replace_remote_tabs(remote_tabs);
+        self.storage.replace_remote_tabs(remote_tabs)?;
         let mut outgoing = OutgoingChangeset::new("tabs", inbound.timestamp);
         if let Some(local_tabs) = self.storage.prepare_local_tabs_for_upload() {
             let (client_name, device_type) = self
@@ -167,10 +180,20 @@ impl<'a> Store for TabsStore<'a> {
                 client_name,
                 device_type,
                 remote_tabs: local_tabs.to_vec(),
+                last_modified: 0, // Not used for the outgoing record.
             };
-            let payload = Payload::from_record(local_record.to_record())?;
-            log::trace!("outgoing {:?}", payload);
-            outgoing.changes.push(payload);
+            let record = local_record.to_record();
+            let hash = hash_record(&record);
+            // If we didn't see our record on the server, assume it's the one
+            // we uploaded last.
+            if server_record_hash.or_else(|| self.storage.last_uploaded_hash()) == Some(hash) {
+                log::info!("Local tabs haven't changed, not uploading them");
+            } else {
+                let payload = Payload::from_record(record)?;
+                log::trace!("outgoing {:?}", payload);
+                outgoing.changes.push(payload);
+                self.pending_upload_hash.set(Some(hash));
+            }
         }
         telem.incoming(incoming_telemetry);
         Ok(outgoing)
@@ -186,6 +209,12 @@ impl<'a> Store for TabsStore<'a> {
             records_synced.len()
         );
         self.last_sync.set(Some(new_timestamp));
+        if let Some(hash) = self.pending_upload_hash.take() {
+            let local_id = self.local_id.borrow();
+            if records_synced.iter().any(|id| id.as_str() == *local_id) {
+                self.storage.set_last_uploaded_hash(hash);
+            }
+        }
         Ok(())
     }
 
@@ -214,3 +243,65 @@ impl<'a> Store for TabsStore<'a> {
         self.wipe_reset_helper(true)
     }
 }
+
+fn hash_record(record: &TabsRecord) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    record.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_incoming(store: &TabsStore<'_>, changes: Vec<Payload>) -> OutgoingChangeset {
+        let mut inbound = IncomingChangeset::new("tabs", ServerTimestamp(0));
+        inbound.changes = changes
+            .into_iter()
+            .map(|payload| (payload, ServerTimestamp(1000)))
+            .collect();
+        store
+            .apply_incoming(vec![inbound], &mut telemetry::Engine::new("tabs"))
+            .expect("should apply incoming")
+    }
+
+    #[test]
+    fn test_only_upload_changed_tabs() {
+        let mut storage = TabsStorage::new();
+        storage.update_local_state(vec![RemoteTab {
+            title: "Foo".to_owned(),
+            url_history: vec!["https://foo.bar".to_owned()],
+            icon: None,
+            last_used: 2000,
+        }]);
+        let store = TabsStore::new(&storage);
+        store.local_id.replace("device-local".to_owned());
+
+        let outgoing = apply_incoming(&store, vec![]);
+        assert_eq!(outgoing.changes.len(), 1);
+        // The upload hasn't succeeded yet, so we'd upload again.
+        assert_eq!(apply_incoming(&store, vec![]).changes.len(), 1);
+        store
+            .sync_finished(ServerTimestamp(2000), vec!["device-local".into()])
+            .unwrap();
+        assert!(apply_incoming(&store, vec![]).changes.is_empty());
+        // Seeing the same record on the server doesn't upload either...
+        let own_payload = outgoing.changes[0].clone();
+        assert!(apply_incoming(&store, vec![own_payload]).changes.is_empty());
+        // ...but a different one does.
+        let other_payload = Payload::from_record(TabsRecord {
+            id: "device-local".to_owned(),
+            client_name: "".to_owned(),
+            tabs: vec![],
+        })
+        .unwrap();
+        assert_eq!(apply_incoming(&store, vec![other_payload]).changes.len(), 1);
+
+        // As does changing the local tabs.
+        drop(store);
+        storage.update_local_state(vec![]);
+        let store = TabsStore::new(&storage);
+        store.local_id.replace("device-local".to_owned());
+        assert_eq!(apply_incoming(&store, vec![]).changes.len(), 1);
+    }
+}
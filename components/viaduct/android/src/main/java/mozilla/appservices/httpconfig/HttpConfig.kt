@@ -28,6 +28,9 @@ object RustHttpConfig {
     // bad things will happen if it does!
     @Volatile
     private var imp: CallbackImpl? = null
+    // The same goes for `streamingImp`.
+    @Volatile
+    private var streamingImp: StreamingCallbackImpl? = null
 
     /**
      * Set the HTTP client to be used by all Rust code.
@@ -41,6 +44,10 @@ object RustHttpConfig {
                 imp = CallbackImpl()
                 LibViaduct.INSTANCE.viaduct_initialize(imp!!)
             }
+            if (streamingImp == null) {
+                streamingImp = StreamingCallbackImpl()
+                LibViaduct.INSTANCE.viaduct_initialize_streaming(streamingImp!!)
+            }
         }
     }
 
@@ -94,28 +101,76 @@ object RustHttpConfig {
                     LibViaduct.INSTANCE.viaduct_log_error("Network error: ${e.message}")
                     MsgTypes.Response.newBuilder().setExceptionMessage(e.message)
                 }
-                val built = rb.build()
-                val needed = built.serializedSize
-                val outputBuf = LibViaduct.INSTANCE.viaduct_alloc_bytebuffer(needed)
-                try {
-                    // This is only null if we passed a negative number or something to
-                    // viaduct_alloc_bytebuffer.
-                    val stream = outputBuf.asCodedOutputStream()!!
-                    built.writeTo(stream)
-                    return outputBuf
+                return writeResponse(rb.build())
+            } finally {
+                LibViaduct.INSTANCE.viaduct_destroy_bytebuffer(b)
+            }
+        }
+    }
+
+    @Suppress("TooGenericExceptionCaught")
+    internal fun doStreamingFetch(streamId: Long, b: RustBuffer.ByValue): RustBuffer.ByValue {
+        lock.read {
+            try {
+                val request = MsgTypes.Request.parseFrom(b.asCodedInputStream())
+                val rb = try {
+                    // Note: `client!!` is fine here, for the same reason as in `doFetch`.
+                    val resp = client!!.value.fetch(convertRequest(request))
+                    resp.use {
+                        val head = MsgTypes.Response.newBuilder()
+                                .setUrl(resp.url)
+                                .setStatus(resp.status)
+                        for (h in resp.headers) {
+                            head.putHeaders(h.name, h.value)
+                        }
+                        val wanted = LibViaduct.INSTANCE.viaduct_stream_head(streamId, writeResponse(head.build()))
+                        if (wanted.toInt() != 0) {
+                            resp.body.useStream { stream ->
+                                val chunk = ByteArray(STREAM_CHUNK_SIZE)
+                                while (true) {
+                                    val read = stream.read(chunk)
+                                    if (read < 0 ||
+                                        LibViaduct.INSTANCE.viaduct_stream_chunk(streamId, chunk, read).toInt() == 0) {
+                                        break
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    MsgTypes.Response.newBuilder()
                 } catch (e: Throwable) {
-                    // Note: we want to clean this up only if we are not returning it to rust.
-                    LibViaduct.INSTANCE.viaduct_destroy_bytebuffer(outputBuf)
-                    LibViaduct.INSTANCE.viaduct_log_error("Failed to write buffer: ${e.message}")
-                    throw e
+                    LibViaduct.INSTANCE.viaduct_log_error("Network error: ${e.message}")
+                    MsgTypes.Response.newBuilder().setExceptionMessage(e.message)
                 }
+                return writeResponse(rb.build())
             } finally {
                 LibViaduct.INSTANCE.viaduct_destroy_bytebuffer(b)
             }
         }
     }
+
+    @Suppress("TooGenericExceptionCaught")
+    private fun writeResponse(built: MsgTypes.Response): RustBuffer.ByValue {
+        val needed = built.serializedSize
+        val outputBuf = LibViaduct.INSTANCE.viaduct_alloc_bytebuffer(needed)
+        try {
+            // This is only null if we passed a negative number or something to
+            // viaduct_alloc_bytebuffer.
+            val stream = outputBuf.asCodedOutputStream()!!
+            built.writeTo(stream)
+            return outputBuf
+        } catch (e: Throwable) {
+            // Note: we want to clean this up only if we are not returning it to rust.
+            LibViaduct.INSTANCE.viaduct_destroy_bytebuffer(outputBuf)
+            LibViaduct.INSTANCE.viaduct_log_error("Failed to write buffer: ${e.message}")
+            throw e
+        }
+    }
 }
 
+// How much of a streamed body is passed to Rust at a time.
+private const val STREAM_CHUNK_SIZE = 64 * 1024
+
 internal fun convertMethod(m: MsgTypes.Request.Method): Request.Method {
     return when (m) {
         MsgTypes.Request.Method.GET -> Request.Method.GET
@@ -142,3 +197,16 @@ internal class CallbackImpl : RawFetchCallback {
         }
     }
 }
+
+internal class StreamingCallbackImpl : RawStreamingFetchCallback {
+    @Suppress("TooGenericExceptionCaught")
+    override fun invoke(streamId: Long, b: RustBuffer.ByValue): RustBuffer.ByValue {
+        try {
+            return RustHttpConfig.doStreamingFetch(streamId, b)
+        } catch (e: Throwable) {
+            LibViaduct.INSTANCE.viaduct_log_error("doStreamingFetch failed: ${e.message}")
+            // As in `CallbackImpl`, we have to return something.
+            return RustBuffer.ByValue()
+        }
+    }
+}
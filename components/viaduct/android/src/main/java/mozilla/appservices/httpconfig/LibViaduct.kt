@@ -26,6 +26,12 @@ internal interface LibViaduct : Library {
     fun viaduct_alloc_bytebuffer(sz: Int): RustBuffer.ByValue
     // Returns 0 to indicate redundant init.
     fun viaduct_initialize(cb: RawFetchCallback): Byte
+    // Returns 0 to indicate redundant init.
+    fun viaduct_initialize_streaming(cb: RawStreamingFetchCallback): Byte
+    // Takes ownership of `head`. Returns 0 if the body is no longer wanted.
+    fun viaduct_stream_head(streamId: Long, head: RustBuffer.ByValue): Byte
+    // Blocks while Rust is behind. Returns 0 if the body is no longer wanted.
+    fun viaduct_stream_chunk(streamId: Long, data: ByteArray, len: Int): Byte
 
     fun viaduct_log_error(s: String)
 }
@@ -33,3 +39,7 @@ internal interface LibViaduct : Library {
 internal interface RawFetchCallback : Callback {
     fun invoke(b: RustBuffer.ByValue): RustBuffer.ByValue
 }
+
+internal interface RawStreamingFetchCallback : Callback {
+    fun invoke(streamId: Long, b: RustBuffer.ByValue): RustBuffer.ByValue
+}
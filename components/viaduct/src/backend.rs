@@ -2,6 +2,9 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::middleware::{with_middleware, MIDDLEWARE};
+use crate::observer::{observe, OBSERVERS};
+use crate::ResponseHead;
 use ffi::FfiBackend;
 use once_cell::sync::OnceCell;
 
@@ -24,6 +27,16 @@ pub fn note_backend(which: &str) {
 
 pub trait Backend: Send + Sync + 'static {
     fn send(&self, request: crate::Request) -> Result<crate::Response, crate::Error>;
+
+    /// Sends `request`, returning a response whose body hasn't been read yet.
+    /// The default implementation buffers the whole body using `send`, for
+    /// backends that can't stream.
+    fn send_streaming(
+        &self,
+        request: crate::Request,
+    ) -> Result<crate::StreamingResponse, crate::Error> {
+        self.send(request).map(crate::StreamingResponse::from)
+    }
 }
 
 static BACKEND: OnceCell<&'static dyn Backend> = OnceCell::new();
@@ -39,8 +52,34 @@ pub(crate) fn get_backend() -> &'static dyn Backend {
 }
 
 pub fn send(request: crate::Request) -> Result<crate::Response, crate::Error> {
-    validate_request(&request)?;
-    get_backend().send(request)
+    send_with(
+        request,
+        |backend, r| backend.send(r),
+        |resp| Some(resp.body.len() as u64),
+    )
+}
+
+pub fn send_streaming(request: crate::Request) -> Result<crate::StreamingResponse, crate::Error> {
+    send_with(request, |backend, r| backend.send_streaming(r), |_| None)
+}
+
+// Sends each attempt through the middleware, validation and observers, and
+// retries as the request's policy says.
+fn send_with<R: ResponseHead>(
+    request: crate::Request,
+    send: impl Fn(&dyn Backend, crate::Request) -> Result<R, crate::Error>,
+    response_bytes: impl Fn(&R) -> Option<u64>,
+) -> Result<R, crate::Error> {
+    crate::retry::send_with_retries(
+        request,
+        |r| {
+            with_middleware(&MIDDLEWARE, r, |r| {
+                validate_request(&r)?;
+                observe(&OBSERVERS, r, |r| send(get_backend(), r), &response_bytes)
+            })
+        },
+        std::thread::sleep,
+    )
 }
 
 pub fn validate_request(request: &crate::Request) -> Result<(), crate::Error> {
@@ -94,4 +133,25 @@ mod tests {
         );
         assert!(validate_request(&localhost_request).is_err());
     }
+
+    #[test]
+    fn test_streaming_response() {
+        use std::io::Read;
+        let response = crate::Response {
+            request_method: crate::Method::Get,
+            url: url::Url::parse("https://www.example.com").unwrap(),
+            status: 200,
+            headers: crate::Headers::new(),
+            body: b"hello world".to_vec(),
+        };
+        let mut streaming = crate::StreamingResponse::from(response.clone());
+        assert!(streaming.is_success());
+        let mut start = [0u8; 6];
+        streaming.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"hello ");
+        let rest = streaming.into_response().unwrap();
+        assert_eq!(rest.body, b"world");
+        assert_eq!(rest.url, response.url);
+        assert_eq!(rest.status, response.status);
+    }
 }
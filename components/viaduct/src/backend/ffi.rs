@@ -41,7 +41,6 @@ pub struct FfiBackend;
 impl Backend for FfiBackend {
     fn send(&self, request: crate::Request) -> Result<crate::Response, Error> {
         use ffi_support::IntoFfi;
-        use prost::Message;
         super::note_backend("FFI (trusted)");
 
         let method = request.method;
@@ -49,64 +48,225 @@ impl Backend for FfiBackend {
         let proto_req: msg_types::Request = request.into();
         let buf = proto_req.into_ffi_value();
         let response = unsafe { fetch(buf) };
-        // This way we'll Drop it if we panic, unlike if we just got a slice into
-        // it. Besides, we already own it.
-        let response_bytes = response.into_vec();
+        convert_response(method, decode_response(response))
+    }
 
-        let response: msg_types::Response = match Message::decode(response_bytes.as_slice()) {
-            Ok(v) => v,
-            Err(e) => {
-                panic!(
-                    "Failed to parse protobuf returned from fetch callback! {}",
-                    e
-                );
-            }
+    fn send_streaming(&self, request: crate::Request) -> Result<crate::StreamingResponse, Error> {
+        use ffi_support::IntoFfi;
+        // Embedders which haven't set up streaming get the whole body at
+        // once.
+        let fetch = match streaming_callback_holder::get_callback() {
+            Some(fetch) => fetch,
+            None => return self.send(request).map(crate::StreamingResponse::from),
         };
+        super::note_backend("FFI (trusted, streaming)");
 
-        if let Some(exn) = response.exception_message {
-            log::error!(
-                // Well, we caught *something* java wanted to tell us about, anyway.
-                "Caught network error (presumably). Message: {:?}",
-                exn
-            );
-            return Err(Error::NetworkError(format!("Java error: {:?}", exn)));
+        let method = request.method;
+        let (stream_id, events) = streams::register();
+        let proto_req: msg_types::Request = request.into();
+        // The callback doesn't return until the whole body has been passed to
+        // `viaduct_stream_chunk`, so it runs on its own thread while we read.
+        std::thread::spawn(move || {
+            let finisher = streams::Finisher(stream_id);
+            let buf = proto_req.into_ffi_value();
+            let response = decode_response(unsafe { fetch(stream_id, buf) });
+            finisher.finish(response.exception_message);
+        });
+        match events.recv() {
+            Ok(StreamEvent::Head(head)) => {
+                let head = convert_response(method, head)?;
+                Ok(crate::StreamingResponse::new(
+                    head.request_method,
+                    head.url,
+                    head.status,
+                    head.headers,
+                    FfiBody {
+                        events,
+                        chunk: std::io::Cursor::new(head.body),
+                        done: false,
+                    },
+                ))
+            }
+            Ok(StreamEvent::Done(Some(exn))) => {
+                log::error!("Caught network error (presumably). Message: {:?}", exn);
+                Err(Error::NetworkError(format!("Java error: {:?}", exn)))
+            }
+            Ok(_) | Err(_) => Err(backend_error!("Stream ended without a response")),
         }
-        let status = response
-            .status
-            .ok_or_else(|| backend_error!("Missing HTTP status"))?;
+    }
+}
 
-        if status < 0 || status > i32::from(u16::max_value()) {
-            return Err(backend_error!("Illegal HTTP status: {}", status));
+fn decode_response(response: ByteBuffer) -> msg_types::Response {
+    use prost::Message;
+    // This way we'll Drop it if we panic, unlike if we just got a slice into
+    // it. Besides, we already own it.
+    let response_bytes = response.into_vec();
+    match Message::decode(response_bytes.as_slice()) {
+        Ok(v) => v,
+        Err(e) => {
+            panic!(
+                "Failed to parse protobuf returned from fetch callback! {}",
+                e
+            );
         }
+    }
+}
+
+fn convert_response(
+    method: crate::Method,
+    response: msg_types::Response,
+) -> Result<crate::Response, Error> {
+    if let Some(exn) = response.exception_message {
+        log::error!(
+            // Well, we caught *something* java wanted to tell us about, anyway.
+            "Caught network error (presumably). Message: {:?}",
+            exn
+        );
+        return Err(Error::NetworkError(format!("Java error: {:?}", exn)));
+    }
+    let status = response
+        .status
+        .ok_or_else(|| backend_error!("Missing HTTP status"))?;
 
-        let mut headers = crate::Headers::with_capacity(response.headers.len());
-        for (name, val) in response.headers {
-            let hname = match crate::HeaderName::new(name) {
-                Ok(name) => name,
-                Err(e) => {
-                    // Ignore headers with invalid names, since nobody can look for them anyway.
-                    log::warn!("Server sent back invalid header name: '{}'", e);
-                    continue;
+    if status < 0 || status > i32::from(u16::max_value()) {
+        return Err(backend_error!("Illegal HTTP status: {}", status));
+    }
+
+    let mut headers = crate::Headers::with_capacity(response.headers.len());
+    for (name, val) in response.headers {
+        let hname = match crate::HeaderName::new(name) {
+            Ok(name) => name,
+            Err(e) => {
+                // Ignore headers with invalid names, since nobody can look for them anyway.
+                log::warn!("Server sent back invalid header name: '{}'", e);
+                continue;
+            }
+        };
+        // Not using Header::new since the error it returns is for request headers.
+        headers.insert_header(crate::Header::new_unchecked(hname, val));
+    }
+
+    let url = url::Url::parse(
+        &response
+            .url
+            .ok_or_else(|| backend_error!("Response has no URL"))?,
+    )
+    .map_err(|e| backend_error!("Response has illegal URL: {}", e))?;
+
+    Ok(crate::Response {
+        url,
+        request_method: method,
+        body: response.body.unwrap_or_default(),
+        status: status as u16,
+        headers,
+    })
+}
+
+/// What the other side of the FFI sends us about a streaming request.
+enum StreamEvent {
+    /// The response, without its body.
+    Head(msg_types::Response),
+    Chunk(Vec<u8>),
+    /// The callback returned, with the exception it caught, if any.
+    Done(Option<String>),
+}
+
+/// A response body which is read as `viaduct_stream_chunk` passes it to us.
+struct FfiBody {
+    events: std::sync::mpsc::Receiver<StreamEvent>,
+    chunk: std::io::Cursor<Vec<u8>>,
+    done: bool,
+}
+
+impl std::io::Read for FfiBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || self.done || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.events.recv() {
+                Ok(StreamEvent::Chunk(chunk)) => self.chunk = std::io::Cursor::new(chunk),
+                Ok(StreamEvent::Head(_)) => {}
+                Ok(StreamEvent::Done(None)) => self.done = true,
+                Ok(StreamEvent::Done(Some(exn))) => {
+                    self.done = true;
+                    return Err(std::io::Error::new(std::io::ErrorKind::Other, exn));
                 }
-            };
-            // Not using Header::new since the error it returns is for request headers.
-            headers.insert_header(crate::Header::new_unchecked(hname, val));
+                Err(_) => {
+                    self.done = true;
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "Stream ended without finishing",
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// The streaming requests in progress, by the ID passed to the streaming
+/// callback.
+mod streams {
+    use super::StreamEvent;
+    use once_cell::sync::Lazy;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+    use std::sync::Mutex;
+
+    /// How many chunks can be waiting to be read before the other side of
+    /// the FFI is made to wait, so that a body which is read slowly isn't
+    /// buffered in memory after all.
+    const MAX_PENDING_CHUNKS: usize = 4;
+
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    static SENDERS: Lazy<Mutex<HashMap<u64, SyncSender<StreamEvent>>>> = Lazy::new(Mutex::default);
+
+    pub(super) fn register() -> (u64, Receiver<StreamEvent>) {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = sync_channel(MAX_PENDING_CHUNKS);
+        SENDERS.lock().unwrap().insert(id, sender);
+        (id, receiver)
+    }
+
+    /// Passes `event` on to the reader of stream `id`, waiting if it's
+    /// behind. Returns false if the stream doesn't exist, or its reader has
+    /// gone away, in which case the other side should stop sending it.
+    pub(super) fn send(id: u64, event: StreamEvent) -> bool {
+        // Clone the sender, so that the lock isn't held while we wait.
+        let sender = match SENDERS.lock().unwrap().get(&id) {
+            Some(sender) => sender.clone(),
+            None => return false,
+        };
+        sender.send(event).is_ok()
+    }
+
+    /// Ends stream `id` once the callback has returned, or when dropped if
+    /// it panicked, so that the reader isn't left waiting forever.
+    pub(super) struct Finisher(pub(super) u64);
+
+    impl Finisher {
+        pub(super) fn finish(self, exception_message: Option<String>) {
+            finish(self.0, exception_message);
+            std::mem::forget(self);
         }
+    }
 
-        let url = url::Url::parse(
-            &response
-                .url
-                .ok_or_else(|| backend_error!("Response has no URL"))?,
-        )
-        .map_err(|e| backend_error!("Response has illegal URL: {}", e))?;
+    impl Drop for Finisher {
+        fn drop(&mut self) {
+            finish(self.0, Some("Fetch callback panicked".into()));
+        }
+    }
 
-        Ok(crate::Response {
-            url,
-            request_method: method,
-            body: response.body.unwrap_or_default(),
-            status: status as u16,
-            headers,
-        })
+    fn finish(id: u64, exception_message: Option<String>) {
+        // Take the sender out first, so the lock isn't held while we send.
+        let sender = SENDERS.lock().unwrap().remove(&id);
+        if let Some(sender) = sender {
+            // The reader may well be gone already.
+            let _ = sender.send(StreamEvent::Done(exception_message));
+        }
     }
 }
 
@@ -171,6 +331,36 @@ mod callback_holder {
     }
 }
 
+/// Type of the callback used for `Request::send_streaming`. It takes the ID
+/// of the stream, and the request.
+///
+/// Once the response's headers have arrived, it passes the response without
+/// its body to `viaduct_stream_head`. Then it passes the body, a chunk at a
+/// time, to `viaduct_stream_chunk`, stopping early if either returns 0. It
+/// returns an empty response once the whole body has been passed, or one
+/// with only an `exception_message` if anything failed.
+type StreamingFetchCallback = unsafe extern "C" fn(u64, ByteBuffer) -> ByteBuffer;
+
+/// Like `callback_holder`, for the `StreamingFetchCallback`.
+mod streaming_callback_holder {
+    use super::StreamingFetchCallback;
+    use once_cell::sync::OnceCell;
+
+    static CALLBACK: OnceCell<StreamingFetchCallback> = OnceCell::new();
+
+    pub(super) fn get_callback() -> Option<StreamingFetchCallback> {
+        CALLBACK.get().copied()
+    }
+
+    pub(super) fn set_callback(h: StreamingFetchCallback) -> bool {
+        let set = CALLBACK.set(h).is_ok();
+        if !set {
+            log::error!("Bug: Initialized the streaming callback multiple times");
+        }
+        set
+    }
+}
+
 /// Return a ByteBuffer of the requested size. This is used to store the
 /// response from the callback.
 #[no_mangle]
@@ -196,4 +386,81 @@ pub extern "C" fn viaduct_initialize(callback: FetchCallback) -> u8 {
     ffi_support::abort_on_panic::call_with_output(|| callback_holder::set_callback(callback))
 }
 
+/// Sets the callback used by `Request::send_streaming`. Until this is
+/// called, streamed responses are read into memory using the callback passed
+/// to `viaduct_initialize`. Returns 0 if it was already set.
+#[no_mangle]
+pub extern "C" fn viaduct_initialize_streaming(callback: StreamingFetchCallback) -> u8 {
+    ffi_support::abort_on_panic::call_with_output(|| {
+        streaming_callback_holder::set_callback(callback)
+    })
+}
+
+/// Passes the response to a streaming request, without its body, which it
+/// takes ownership of. Returns 0 if the request is no longer wanted.
+#[no_mangle]
+pub extern "C" fn viaduct_stream_head(stream_id: u64, head: ByteBuffer) -> u8 {
+    ffi_support::abort_on_panic::call_with_output(|| {
+        streams::send(stream_id, StreamEvent::Head(decode_response(head)))
+    })
+}
+
+/// Passes the next `len` bytes of the body of a streaming request, waiting
+/// if too many are already waiting to be read. Returns 0 if the request is no
+/// longer wanted.
+///
+/// # Safety
+/// `data` must point to at least `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn viaduct_stream_chunk(stream_id: u64, data: *const u8, len: i32) -> u8 {
+    let chunk = if data.is_null() || len <= 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(data, len as usize).to_vec()
+    };
+    ffi_support::abort_on_panic::call_with_output(|| {
+        streams::send(stream_id, StreamEvent::Chunk(chunk))
+    })
+}
+
 ffi_support::define_bytebuffer_destructor!(viaduct_destroy_bytebuffer);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn encode(response: msg_types::Response) -> ByteBuffer {
+        let mut bytes = Vec::new();
+        prost::Message::encode(&response, &mut bytes).unwrap();
+        ByteBuffer::from_vec(bytes)
+    }
+
+    unsafe extern "C" fn fake_streaming_fetch(stream_id: u64, request: ByteBuffer) -> ByteBuffer {
+        request.destroy();
+        let head = msg_types::Response {
+            url: Some("https://www.example.com/".into()),
+            status: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(viaduct_stream_head(stream_id, encode(head)), 1);
+        for chunk in &["hello ", "world"] {
+            assert_eq!(
+                viaduct_stream_chunk(stream_id, chunk.as_ptr(), chunk.len() as i32),
+                1
+            );
+        }
+        encode(msg_types::Response::default())
+    }
+
+    #[test]
+    fn test_send_streaming() {
+        viaduct_initialize_streaming(fake_streaming_fetch);
+        let request = crate::Request::get(url::Url::parse("https://www.example.com/").unwrap());
+        let mut response = FfiBackend.send_streaming(request).unwrap();
+        assert_eq!(response.status, 200);
+        let mut body = String::new();
+        response.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "hello world");
+    }
+}
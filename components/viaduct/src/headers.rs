@@ -380,8 +380,10 @@ pub mod consts {
         (ACCEPT, "accept"),
         (AUTHORIZATION, "authorization"),
         (CONTENT_TYPE, "content-type"),
+        (COOKIE, "cookie"),
         (ETAG, "etag"),
         (IF_NONE_MATCH, "if-none-match"),
+        (SET_COOKIE, "set-cookie"),
         (USER_AGENT, "user-agent"),
         // non-standard, but it's convenient to have these.
         (RETRY_AFTER, "retry-after"),
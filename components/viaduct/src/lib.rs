@@ -11,11 +11,17 @@ mod headers;
 
 mod backend;
 pub mod error;
+mod middleware;
+mod observer;
+mod retry;
 pub mod settings;
 pub use error::*;
 
 pub use backend::{note_backend, set_backend, Backend};
 pub use headers::{consts as header_names, Header, HeaderName, Headers, InvalidHeaderName};
+pub use middleware::{register_middleware, CookieJar, DefaultHeaders, Middleware};
+pub use observer::{register_observer, RequestEvent, RequestObserver};
+pub use retry::RetryPolicy;
 pub use settings::GLOBAL_SETTINGS;
 
 pub(crate) mod msg_types {
@@ -66,6 +72,7 @@ pub struct Request {
     pub url: Url,
     pub headers: Headers,
     pub body: Option<Vec<u8>>,
+    pub retry_policy: Option<RetryPolicy>,
 }
 
 impl Request {
@@ -77,6 +84,7 @@ impl Request {
             url,
             headers: Headers::new(),
             body: None,
+            retry_policy: None,
         }
     }
 
@@ -84,6 +92,17 @@ impl Request {
         crate::backend::send(self)
     }
 
+    /// Like `send()`, but returns as soon as the headers have arrived, so that
+    /// the body can be read as it's downloaded instead of being buffered in
+    /// memory. Use this for responses that can be large, like attachments.
+    ///
+    /// With the FFI backend, the body is only streamed if the embedder has
+    /// called `viaduct_initialize_streaming`; otherwise it's read into memory
+    /// before this returns.
+    pub fn send_streaming(self) -> Result<StreamingResponse, Error> {
+        crate::backend::send_streaming(self)
+    }
+
     /// Alias for `Request::new(Method::Get, url)`, for convenience.
     pub fn get(url: Url) -> Self {
         Self::new(Method::Get, url)
@@ -188,6 +207,13 @@ impl Request {
         Ok(self)
     }
 
+    /// Retry this request if it fails with a network error or a temporary
+    /// server error, as `policy` says. Requests aren't retried by default.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
     /// Set this request's body.
     pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
         self.body = Some(body.into());
@@ -229,7 +255,8 @@ pub struct Response {
     /// The headers returned with this response.
     pub headers: Headers,
     /// The body of the response. Note that responses with binary bodies are
-    /// currently unsupported.
+    /// currently unsupported. Use `Request::send_streaming` to avoid buffering
+    /// large bodies.
     pub body: Vec<u8>,
 }
 
@@ -284,6 +311,121 @@ impl Response {
     }
 }
 
+/// A response from the server whose body hasn't been read yet. Read the body
+/// using the `std::io::Read` implementation, or buffer it with
+/// `into_response()`.
+pub struct StreamingResponse {
+    /// The method used to request this response.
+    pub request_method: Method,
+    /// The URL of this response.
+    pub url: Url,
+    /// The HTTP Status code of this response.
+    pub status: u16,
+    /// The headers returned with this response.
+    pub headers: Headers,
+    body: Box<dyn std::io::Read + Send>,
+}
+
+impl StreamingResponse {
+    pub fn new(
+        request_method: Method,
+        url: Url,
+        status: u16,
+        headers: Headers,
+        body: impl std::io::Read + Send + 'static,
+    ) -> Self {
+        Self {
+            request_method,
+            url,
+            status,
+            headers,
+            body: Box::new(body),
+        }
+    }
+
+    /// Returns true if the status code is in the interval `[200, 300)`.
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        status_codes::is_success_code(self.status)
+    }
+
+    /// Reads the rest of the body into memory.
+    pub fn into_response(mut self) -> Result<Response, Error> {
+        let mut body = Vec::new();
+        std::io::Read::read_to_end(&mut self.body, &mut body).map_err(|e| {
+            log::error!("Failed to get body from response: {:?}", e);
+            Error::NetworkError(e.to_string())
+        })?;
+        Ok(Response {
+            request_method: self.request_method,
+            url: self.url,
+            status: self.status,
+            headers: self.headers,
+            body,
+        })
+    }
+}
+
+impl std::io::Read for StreamingResponse {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl std::fmt::Debug for StreamingResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamingResponse")
+            .field("request_method", &self.request_method)
+            .field("url", &self.url)
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+impl From<Response> for StreamingResponse {
+    fn from(response: Response) -> Self {
+        Self::new(
+            response.request_method,
+            response.url,
+            response.status,
+            response.headers,
+            std::io::Cursor::new(response.body),
+        )
+    }
+}
+
+/// The parts of a response that are available before its body is read.
+pub(crate) trait ResponseHead {
+    fn url(&self) -> &Url;
+    fn status(&self) -> u16;
+    fn headers(&self) -> &Headers;
+}
+
+impl ResponseHead for Response {
+    fn url(&self) -> &Url {
+        &self.url
+    }
+    fn status(&self) -> u16 {
+        self.status
+    }
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
+impl ResponseHead for StreamingResponse {
+    fn url(&self) -> &Url {
+        &self.url
+    }
+    fn status(&self) -> u16 {
+        self.status
+    }
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
 /// A module containing constants for all HTTP status codes.
 pub mod status_codes {
 
@@ -0,0 +1,429 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Middleware can change every request before it's sent, and look at the
+//! headers of every response, whichever component made the request. This is
+//! used to add common headers, like a `User-Agent`, and to share cookies
+//! between components that need them.
+
+use crate::{header_names, Error, Headers, Request, ResponseHead};
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use url::Url;
+
+pub trait Middleware: Send + Sync {
+    /// Called before each request is sent, including retries. Returning an
+    /// error fails the request without sending it.
+    fn on_request(&self, request: &mut Request) -> Result<(), Error>;
+
+    /// Called with each response, before its body is read.
+    fn on_response(&self, _url: &Url, _headers: &Headers) {}
+}
+
+#[derive(Default)]
+pub(crate) struct MiddlewareChain {
+    middleware: RwLock<Vec<Arc<dyn Middleware>>>,
+}
+
+impl MiddlewareChain {
+    pub fn register(&self, middleware: Arc<dyn Middleware>) {
+        self.middleware.write().unwrap().push(middleware);
+    }
+}
+
+pub(crate) static MIDDLEWARE: Lazy<MiddlewareChain> = Lazy::new(MiddlewareChain::default);
+
+/// Add `middleware` to the end of the chain that every request made through
+/// viaduct goes through. Middleware is called in the order it was registered,
+/// and can't be removed.
+pub fn register_middleware(middleware: Arc<dyn Middleware>) {
+    MIDDLEWARE.register(middleware);
+}
+
+/// Passes `request` through each middleware in `chain`, sends it using `send`,
+/// and passes the response back through.
+pub(crate) fn with_middleware<R: ResponseHead>(
+    chain: &MiddlewareChain,
+    mut request: Request,
+    send: impl FnOnce(Request) -> Result<R, Error>,
+) -> Result<R, Error> {
+    // Don't hold the lock while the request is in flight.
+    let middleware = chain.middleware.read().unwrap().clone();
+    for m in &middleware {
+        m.on_request(&mut request)?;
+    }
+    let result = send(request);
+    if let Ok(resp) = &result {
+        for m in &middleware {
+            m.on_response(resp.url(), resp.headers());
+        }
+    }
+    result
+}
+
+/// Adds headers to every request that doesn't already have them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DefaultHeaders {
+    headers: Headers,
+}
+
+impl DefaultHeaders {
+    pub fn new(headers: Headers) -> Self {
+        Self { headers }
+    }
+}
+
+impl Middleware for DefaultHeaders {
+    fn on_request(&self, request: &mut Request) -> Result<(), Error> {
+        for header in self.headers.clone() {
+            request
+                .headers
+                .insert_if_missing(header.name().clone(), header.value())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    // If set, the cookie is only sent to `domain` itself, not its subdomains.
+    host_only: bool,
+    path: String,
+    secure: bool,
+    expires: Option<Instant>,
+}
+
+impl Cookie {
+    // Parses a `Set-Cookie` header sent with a response from `url`. Returns
+    // `None` if it's malformed, or isn't allowed to be set by `url`.
+    fn parse(url: &Url, set_cookie: &str, now: Instant) -> Option<Self> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut parts = set_cookie.split(';');
+        let mut name_value = parts.next()?.splitn(2, '=');
+        let name = name_value.next()?.trim();
+        let value = name_value.next()?.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Cookie {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            secure: false,
+            expires: None,
+        };
+        for attr in parts {
+            let mut attr = attr.splitn(2, '=');
+            let key = attr.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let value = attr.next().unwrap_or_default().trim();
+            match key.as_str() {
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if domain.is_empty() {
+                        continue;
+                    }
+                    // We don't know the public suffixes, but we can at least
+                    // stop cookies being set for a whole top-level domain.
+                    if !domain_matches(&host, &domain) || (domain != host && !domain.contains('.'))
+                    {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_owned(),
+                "secure" => cookie.secure = true,
+                "max-age" => {
+                    if let Ok(secs) = value.parse::<i64>() {
+                        cookie.expires = Some(if secs <= 0 {
+                            now
+                        } else {
+                            now + Duration::from_secs(secs as u64)
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        if cookie.secure && url.scheme() != "https" {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        };
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        domain_ok
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.'))
+}
+
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+// The directory of the request path, which is where cookies without a `Path`
+// apply.
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(i) => url.path()[..i].to_owned(),
+    }
+}
+
+/// Stores cookies from responses, and sends them with later requests to the
+/// same site. Cookies are only kept in memory, and `Max-Age` is the only
+/// expiry that's supported; cookies with only `Expires` last until the jar is
+/// dropped or cleared.
+///
+/// Note that `Headers` only keeps one value for each name, so only the last
+/// `Set-Cookie` header of a response is seen.
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget all the cookies, for example when the user signs out.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    fn store(&self, url: &Url, set_cookie: &str, now: Instant) {
+        let cookie = match Cookie::parse(url, set_cookie, now) {
+            Some(cookie) => cookie,
+            None => {
+                log::warn!(
+                    "Ignoring cookie set by {}",
+                    url.host_str().unwrap_or_default()
+                );
+                return;
+            }
+        };
+        let mut cookies = self.cookies.lock().unwrap();
+        let replaces = |c: &Cookie| {
+            c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path
+        };
+        cookies.retain(|c| !replaces(c) && !c.is_expired(now));
+        if !cookie.is_expired(now) {
+            cookies.push(cookie);
+        }
+    }
+
+    fn cookie_header(&self, url: &Url, now: Instant) -> Option<String> {
+        let cookies = self.cookies.lock().unwrap();
+        let mut matching = cookies
+            .iter()
+            .filter(|c| !c.is_expired(now) && c.matches(url))
+            .collect::<Vec<_>>();
+        // Cookies with longer paths are sent first.
+        matching.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        if matching.is_empty() {
+            return None;
+        }
+        Some(
+            matching
+                .iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+}
+
+impl Middleware for CookieJar {
+    fn on_request(&self, request: &mut Request) -> Result<(), Error> {
+        if let Some(cookies) = self.cookie_header(&request.url, Instant::now()) {
+            let value = match request.headers.get(header_names::COOKIE) {
+                Some(existing) => format!("{}; {}", existing, cookies),
+                None => cookies,
+            };
+            request.headers.insert(header_names::COOKIE, value)?;
+        }
+        Ok(())
+    }
+
+    fn on_response(&self, url: &Url, headers: &Headers) {
+        if let Some(set_cookie) = headers.get(header_names::SET_COOKIE) {
+            self.store(url, set_cookie, Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Method, Response};
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn response(url: Url, set_cookie: Option<&str>) -> Response {
+        let mut headers = Headers::new();
+        if let Some(set_cookie) = set_cookie {
+            headers
+                .insert(header_names::SET_COOKIE, set_cookie)
+                .unwrap();
+        }
+        Response {
+            request_method: Method::Get,
+            url,
+            status: 200,
+            headers,
+            body: vec![],
+        }
+    }
+
+    #[test]
+    fn test_default_headers() {
+        let chain = MiddlewareChain::default();
+        let mut headers = Headers::new();
+        headers
+            .insert(header_names::USER_AGENT, "Firefox/1.0")
+            .unwrap()
+            .insert(header_names::AUTHORIZATION, "Bearer default")
+            .unwrap();
+        chain.register(Arc::new(DefaultHeaders::new(headers)));
+
+        let request = Request::get(url("https://www.example.com"))
+            .header(header_names::AUTHORIZATION, "Bearer mine")
+            .unwrap();
+        with_middleware(&chain, request, |request| {
+            assert_eq!(
+                request.headers.get(header_names::USER_AGENT),
+                Some("Firefox/1.0")
+            );
+            // Headers the request already has are left alone.
+            assert_eq!(
+                request.headers.get(header_names::AUTHORIZATION),
+                Some("Bearer mine")
+            );
+            Ok(response(request.url, None))
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_cookie_jar() {
+        let chain = MiddlewareChain::default();
+        let jar = Arc::new(CookieJar::new());
+        chain.register(jar.clone());
+
+        let send = |request: Request, set_cookie: Option<&str>| -> Option<String> {
+            let mut sent = None;
+            with_middleware(&chain, request, |request| {
+                sent = request.headers.get(header_names::COOKIE).map(Into::into);
+                Ok(response(request.url, set_cookie))
+            })
+            .unwrap();
+            sent
+        };
+
+        assert_eq!(
+            send(
+                Request::get(url("https://accounts.example.com/v1/login")),
+                Some("session=abc; Path=/v1; Secure; HttpOnly")
+            ),
+            None
+        );
+        assert_eq!(
+            send(
+                Request::get(url("https://accounts.example.com/v1/account")),
+                None
+            ),
+            Some("session=abc".into())
+        );
+        // Not for other hosts, paths or schemes.
+        assert_eq!(
+            send(Request::get(url("https://example.com/v1/x")), None),
+            None
+        );
+        assert_eq!(
+            send(Request::get(url("https://accounts.example.com/v10")), None),
+            None
+        );
+        assert_eq!(
+            send(Request::get(url("http://accounts.example.com/v1/x")), None),
+            None
+        );
+
+        // Domain cookies are sent to subdomains, after the existing header.
+        send(
+            Request::get(url("https://accounts.example.com/")),
+            Some("shared=1; Domain=.example.com"),
+        );
+        let request = Request::get(url("https://profile.example.com/v1"))
+            .header(header_names::COOKIE, "mine=2")
+            .unwrap();
+        assert_eq!(send(request, None), Some("mine=2; shared=1".into()));
+        assert_eq!(
+            send(Request::get(url("https://accounts.example.com/v1/x")), None),
+            Some("session=abc; shared=1".into())
+        );
+
+        // Max-Age=0 deletes a cookie.
+        send(
+            Request::get(url("https://accounts.example.com/")),
+            Some("shared=; Domain=example.com; Max-Age=0"),
+        );
+        assert_eq!(
+            send(Request::get(url("https://accounts.example.com/v1/x")), None),
+            Some("session=abc".into())
+        );
+
+        jar.clear();
+        assert_eq!(
+            send(Request::get(url("https://accounts.example.com/v1/x")), None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rejected_cookies() {
+        let now = Instant::now();
+        let from = url("http://accounts.example.com/a/b");
+        // Other sites, top-level domains and insecure secure cookies.
+        assert!(Cookie::parse(&from, "a=1; Domain=example.org", now).is_none());
+        assert!(Cookie::parse(&from, "a=1; Domain=com", now).is_none());
+        assert!(Cookie::parse(&from, "a=1; Secure", now).is_none());
+        assert!(Cookie::parse(&from, "=1", now).is_none());
+        assert!(Cookie::parse(&from, "novalue", now).is_none());
+
+        let cookie = Cookie::parse(&from, "a=1; Max-Age=60", now).unwrap();
+        assert_eq!(cookie.path, "/a");
+        assert!(cookie.host_only);
+        assert!(!cookie.is_expired(now + Duration::from_secs(59)));
+        assert!(cookie.is_expired(now + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,194 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Notifications about the requests we make, for embedders that want to
+//! record network telemetry or show requests in a debug panel. Observers only
+//! see the method, host, status, timing and sizes of a request; never its
+//! path, headers or body, since those can contain personal data.
+
+use crate::{Error, Method, Request, ResponseHead};
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestEvent {
+    /// A request is about to be sent. Each retry is a separate request.
+    Started { method: Method, host: String },
+    /// A request finished. `status` is `None` if it failed without a
+    /// response, and `response_bytes` is `None` if the size of the body isn't
+    /// known yet, because it's being streamed.
+    Finished {
+        method: Method,
+        host: String,
+        status: Option<u16>,
+        duration: Duration,
+        request_bytes: u64,
+        response_bytes: Option<u64>,
+    },
+}
+
+/// Implemented by embedders who want to be notified of requests. Observers
+/// are called synchronously, on the thread making the request, so they
+/// should be quick.
+pub trait RequestObserver: Send + Sync {
+    fn on_event(&self, event: &RequestEvent);
+}
+
+#[derive(Default)]
+pub(crate) struct Observers {
+    observers: RwLock<Vec<Box<dyn RequestObserver>>>,
+}
+
+impl Observers {
+    pub fn register(&self, observer: Box<dyn RequestObserver>) {
+        self.observers.write().unwrap().push(observer);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.observers.read().unwrap().is_empty()
+    }
+
+    fn notify(&self, event: RequestEvent) {
+        for observer in self.observers.read().unwrap().iter() {
+            observer.on_event(&event);
+        }
+    }
+}
+
+pub(crate) static OBSERVERS: Lazy<Observers> = Lazy::new(Observers::default);
+
+/// Register an observer to be notified of every request made through viaduct,
+/// by any component. Observers can't be removed.
+pub fn register_observer(observer: Box<dyn RequestObserver>) {
+    OBSERVERS.register(observer);
+}
+
+/// Sends `request` using `send`, notifying `observers` before and after.
+/// `response_bytes` returns the size of a response's body, if it's known.
+pub(crate) fn observe<R: ResponseHead>(
+    observers: &Observers,
+    request: Request,
+    send: impl FnOnce(Request) -> Result<R, Error>,
+    response_bytes: impl FnOnce(&R) -> Option<u64>,
+) -> Result<R, Error> {
+    if observers.is_empty() {
+        return send(request);
+    }
+    let method = request.method;
+    let host = request.url.host_str().unwrap_or_default().to_owned();
+    let request_bytes = request.body.as_ref().map_or(0, |body| body.len() as u64);
+    observers.notify(RequestEvent::Started {
+        method,
+        host: host.clone(),
+    });
+    let start = Instant::now();
+    let result = send(request);
+    let duration = start.elapsed();
+    let (status, response_bytes) = match &result {
+        Ok(resp) => (Some(resp.status()), response_bytes(resp)),
+        Err(_) => (None, None),
+    };
+    observers.notify(RequestEvent::Finished {
+        method,
+        host,
+        status,
+        duration,
+        request_bytes,
+        response_bytes,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Headers, Response};
+    use std::sync::{Arc, Mutex};
+    use url::Url;
+
+    struct Recorder(Arc<Mutex<Vec<RequestEvent>>>);
+
+    impl RequestObserver for Recorder {
+        fn on_event(&self, event: &RequestEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn send(observers: &Observers, request: Request, result: Result<Response, Error>) {
+        let _ = observe(
+            observers,
+            request,
+            |_| result,
+            |resp| Some(resp.body.len() as u64),
+        );
+    }
+
+    #[test]
+    fn test_observe() {
+        let observers = Observers::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        observers.register(Box::new(Recorder(events.clone())));
+
+        let url = Url::parse("https://www.example.com/private/path?q=secret").unwrap();
+        send(
+            &observers,
+            Request::post(url.clone()).body("hello"),
+            Ok(Response {
+                request_method: Method::Post,
+                url: url.clone(),
+                status: 201,
+                headers: Headers::new(),
+                body: b"created".to_vec(),
+            }),
+        );
+        send(
+            &observers,
+            Request::get(url),
+            Err(Error::NetworkError("offline".into())),
+        );
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[0],
+            RequestEvent::Started {
+                method: Method::Post,
+                host: "www.example.com".into(),
+            }
+        );
+        match &events[1] {
+            RequestEvent::Finished {
+                method,
+                host,
+                status,
+                request_bytes,
+                response_bytes,
+                ..
+            } => {
+                assert_eq!(*method, Method::Post);
+                assert_eq!(host, "www.example.com");
+                assert_eq!(*status, Some(201));
+                assert_eq!(*request_bytes, 5);
+                assert_eq!(*response_bytes, Some(7));
+            }
+            e => panic!("Unexpected event {:?}", e),
+        }
+        match &events[3] {
+            RequestEvent::Finished {
+                method,
+                status,
+                request_bytes,
+                response_bytes,
+                ..
+            } => {
+                assert_eq!(*method, Method::Get);
+                assert_eq!(*status, None);
+                assert_eq!(*request_bytes, 0);
+                assert_eq!(*response_bytes, None);
+            }
+            e => panic!("Unexpected event {:?}", e),
+        }
+    }
+}
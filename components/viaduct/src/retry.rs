@@ -0,0 +1,265 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::{header_names, status_codes, Error, Method, Request, ResponseHead};
+use rand::Rng;
+use std::time::Duration;
+
+/// How to retry a request that fails with a network error, or with a status
+/// that means the server is temporarily unavailable (429, 502, 503 and 504).
+/// Set it with `Request::retry`.
+///
+/// Only requests with idempotent methods are retried, unless `idempotent` is
+/// set. Between attempts, we wait for an exponentially increasing delay with
+/// some random jitter, or for as long as the server asked in its
+/// `Retry-After` header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The most times to send the request, including the first.
+    pub max_attempts: u32,
+    /// The delay before the first retry. It doubles after each one.
+    pub initial_delay: Duration,
+    /// The longest we'll wait between attempts. If the server asks us to wait
+    /// longer than this, we return its response instead of retrying.
+    pub max_delay: Duration,
+    /// Retry the request even if its method isn't idempotent, because the
+    /// caller knows that sending it more than once is safe.
+    pub idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn allows(&self, method: Method) -> bool {
+        self.idempotent
+            || match method {
+                Method::Get
+                | Method::Head
+                | Method::Put
+                | Method::Delete
+                | Method::Options
+                | Method::Trace => true,
+                Method::Post | Method::Connect => false,
+            }
+    }
+
+    /// The delay before retry number `retry` (starting at 1), where `jitter`
+    /// is between 0 and 1. Half of the delay is fixed, and half is random.
+    fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self
+            .initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        delay / 2 + (delay / 2).mul_f64(jitter)
+    }
+}
+
+fn is_retryable_status(status: u16) -> bool {
+    match status {
+        429
+        | status_codes::BAD_GATEWAY
+        | status_codes::SERVICE_UNAVAILABLE
+        | status_codes::GATEWAY_TIMEOUT => true,
+        _ => false,
+    }
+}
+
+/// Sends `request` using `send`, retrying it as its retry policy says. `sleep`
+/// is called to wait between attempts.
+pub(crate) fn send_with_retries<R: ResponseHead>(
+    request: Request,
+    mut send: impl FnMut(Request) -> Result<R, Error>,
+    mut sleep: impl FnMut(Duration),
+) -> Result<R, Error> {
+    let policy = match request.retry_policy {
+        Some(policy) if policy.allows(request.method) => policy,
+        _ => return send(request),
+    };
+    let mut attempt = 1;
+    loop {
+        let result = send(request.clone());
+        if attempt >= policy.max_attempts {
+            return result;
+        }
+        let retry_after = match &result {
+            Ok(resp) if is_retryable_status(resp.status()) => resp
+                .headers()
+                .get_as::<u64, _>(header_names::RETRY_AFTER)
+                .and_then(Result::ok)
+                .map(Duration::from_secs),
+            Err(Error::NetworkError(_)) => None,
+            _ => return result,
+        };
+        let delay = match retry_after {
+            Some(delay) if delay > policy.max_delay => return result,
+            Some(delay) => delay,
+            None => policy.delay(attempt, rand::thread_rng().gen()),
+        };
+        log::warn!(
+            "{} {} failed (attempt {} of {}), retrying in {:?}",
+            request.method,
+            request.url,
+            attempt,
+            policy.max_attempts,
+            delay
+        );
+        sleep(delay);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Headers, Response};
+    use std::cell::RefCell;
+    use url::Url;
+
+    fn response(status: u16, retry_after: Option<&str>) -> Response {
+        let mut headers = Headers::new();
+        if let Some(retry_after) = retry_after {
+            headers
+                .insert(header_names::RETRY_AFTER, retry_after)
+                .unwrap();
+        }
+        Response {
+            request_method: Method::Get,
+            url: Url::parse("https://www.example.com").unwrap(),
+            status,
+            headers,
+            body: vec![],
+        }
+    }
+
+    // Sends `request`, replying with `replies` in order, and returns the
+    // result, how many attempts were made, and how long we slept for.
+    fn send(
+        request: Request,
+        replies: Vec<Result<Response, Error>>,
+    ) -> (Result<Response, Error>, usize, Vec<Duration>) {
+        let replies = RefCell::new(replies.into_iter());
+        let attempts = RefCell::new(0);
+        let mut sleeps = Vec::new();
+        let result = send_with_retries(
+            request,
+            |_| {
+                *attempts.borrow_mut() += 1;
+                replies.borrow_mut().next().expect("should have a reply")
+            },
+            |delay| sleeps.push(delay),
+        );
+        let attempts = *attempts.borrow();
+        (result, attempts, sleeps)
+    }
+
+    fn network_error() -> Result<Response, Error> {
+        Err(Error::NetworkError("offline".into()))
+    }
+
+    fn get() -> Request {
+        Request::get(Url::parse("https://www.example.com").unwrap())
+    }
+
+    #[test]
+    fn test_no_policy() {
+        let (result, attempts, _) = send(get(), vec![Ok(response(503, None))]);
+        assert_eq!(result.unwrap().status, 503);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_retries() {
+        let policy = RetryPolicy::default();
+        let (result, attempts, sleeps) = send(
+            get().retry(policy),
+            vec![
+                network_error(),
+                Ok(response(503, None)),
+                Ok(response(200, None)),
+            ],
+        );
+        assert_eq!(result.unwrap().status, 200);
+        assert_eq!(attempts, 3);
+        assert_eq!(sleeps.len(), 2);
+        assert!(sleeps[0] >= Duration::from_millis(250) && sleeps[0] <= Duration::from_millis(500));
+        assert!(sleeps[1] >= Duration::from_millis(500) && sleeps[1] <= Duration::from_secs(1));
+
+        // We give up after `max_attempts`.
+        let (result, attempts, _) = send(
+            get().retry(policy),
+            vec![network_error(), network_error(), network_error()],
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        // Other errors and statuses aren't retried.
+        let (result, attempts, _) = send(get().retry(policy), vec![Ok(response(500, None))]);
+        assert_eq!(result.unwrap().status, 500);
+        assert_eq!(attempts, 1);
+        let (result, attempts, _) = send(get().retry(policy), vec![Err(Error::NonTlsUrl)]);
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let policy = RetryPolicy::default();
+        let (result, attempts, sleeps) = send(
+            get().retry(policy),
+            vec![Ok(response(429, Some("5"))), Ok(response(200, None))],
+        );
+        assert_eq!(result.unwrap().status, 200);
+        assert_eq!(attempts, 2);
+        assert_eq!(sleeps, vec![Duration::from_secs(5)]);
+
+        // If the server wants us to wait longer than `max_delay`, we don't.
+        let (result, attempts, sleeps) =
+            send(get().retry(policy), vec![Ok(response(503, Some("3600")))]);
+        assert_eq!(result.unwrap().status, 503);
+        assert_eq!(attempts, 1);
+        assert!(sleeps.is_empty());
+    }
+
+    #[test]
+    fn test_idempotency() {
+        let url = Url::parse("https://www.example.com").unwrap();
+        let (_, attempts, _) = send(
+            Request::post(url.clone()).retry(RetryPolicy::default()),
+            vec![network_error()],
+        );
+        assert_eq!(attempts, 1);
+
+        let (result, attempts, _) = send(
+            Request::post(url).retry(RetryPolicy {
+                idempotent: true,
+                ..RetryPolicy::default()
+            }),
+            vec![network_error(), Ok(response(200, None))],
+        );
+        assert_eq!(result.unwrap().status, 200);
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 100,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1, 0.0), Duration::from_millis(250));
+        assert_eq!(policy.delay(1, 1.0), Duration::from_millis(500));
+        assert_eq!(policy.delay(3, 1.0), Duration::from_secs(2));
+        assert_eq!(policy.delay(50, 1.0), policy.max_delay);
+    }
+}
@@ -52,3 +52,11 @@ BEGIN
 END;
 
 DELETE FROM temp.storage_sync_outgoing_staging;
+
+-- The changes made to local data by the last sync, as the JSON for the
+-- `StorageChanges` of each extension. Unlike the other tables, this isn't
+-- cleared after a sync, so the app can fetch the changes once it finishes.
+CREATE TEMP TABLE IF NOT EXISTS storage_sync_applied (
+    ext_id TEXT NOT NULL UNIQUE,
+    changes TEXT NOT NULL
+);
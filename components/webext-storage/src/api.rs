@@ -23,7 +23,7 @@ pub const SYNC_MAX_ITEMS: usize = 512;
 
 type JsonMap = Map<String, JsonValue>;
 
-fn get_from_db(conn: &Connection, ext_id: &str) -> Result<Option<JsonMap>> {
+pub(crate) fn get_from_db(conn: &Connection, ext_id: &str) -> Result<Option<JsonMap>> {
     Ok(
         match conn.try_query_one::<String>(
             "SELECT data FROM storage_sync_data
@@ -84,11 +84,8 @@ fn save_to_db(tx: &Transaction<'_>, ext_id: &str, val: &JsonValue) -> Result<()>
             )?;
         }
     } else {
-        // Convert to bytes so we can enforce the quota.
+        // The quota has already been checked by the caller.
         let sval = val.to_string();
-        if sval.len() > SYNC_QUOTA_BYTES {
-            return Err(ErrorKind::QuotaError(QuotaReason::TotalBytes).into());
-        }
         log::trace!("saving data for '{}': writing", ext_id);
         tx.execute_named_cached(
             "INSERT INTO storage_sync_data(ext_id, data, sync_change_counter)
@@ -131,7 +128,7 @@ pub struct StorageChanges {
 }
 
 impl StorageChanges {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             changes: Vec::new(),
         }
@@ -143,13 +140,43 @@ impl StorageChanges {
         }
     }
 
-    fn is_empty(&self) -> bool {
+    pub(crate) fn is_empty(&self) -> bool {
         self.changes.is_empty()
     }
 
     fn push(&mut self, change: StorageValueChange) {
         self.changes.push(change)
     }
+
+    /// Returns the changes needed to turn `old` into `new`, where `None`
+    /// means the extension has no data. This is used to report changes made
+    /// by a sync.
+    pub(crate) fn between(old: Option<&JsonMap>, new: Option<&JsonMap>) -> Self {
+        let empty = JsonMap::new();
+        let old = old.unwrap_or(&empty);
+        let new = new.unwrap_or(&empty);
+        let mut changes = StorageChanges::new();
+        for (key, old_value) in old {
+            let new_value = new.get(key);
+            if new_value != Some(old_value) {
+                changes.push(StorageValueChange {
+                    key: key.clone(),
+                    old_value: Some(old_value.clone()),
+                    new_value: new_value.cloned(),
+                });
+            }
+        }
+        for (key, new_value) in new {
+            if !old.contains_key(key) {
+                changes.push(StorageValueChange {
+                    key: key.clone(),
+                    old_value: None,
+                    new_value: Some(new_value.clone()),
+                });
+            }
+        }
+        changes
+    }
 }
 
 // and it serializes as a map.
@@ -174,6 +201,12 @@ pub fn get_quota_size_of(key: &str, v: &JsonValue) -> usize {
     key.len() + v.to_string().len()
 }
 
+// The total size of all the items for an extension, counted the same way as
+// getBytesInUse(), so an extension can use exactly `SYNC_QUOTA_BYTES`.
+fn get_total_quota_size(items: &JsonMap) -> usize {
+    items.iter().map(|(k, v)| get_quota_size_of(k, v)).sum()
+}
+
 /// The implementation of `storage[.sync].set()`. On success this returns the
 /// StorageChanges defined by the chrome API - it's assumed the caller will
 /// arrange to deliver this to observers as defined in that API.
@@ -207,6 +240,9 @@ pub fn set(tx: &Transaction<'_>, ext_id: &str, val: JsonValue) -> Result<Storage
         changes.push(change);
         current.insert(k, v);
     }
+    if get_total_quota_size(&current) > SYNC_QUOTA_BYTES {
+        return Err(ErrorKind::QuotaError(QuotaReason::TotalBytes).into());
+    }
 
     save_to_db(tx, ext_id, &JsonValue::Object(current))?;
     Ok(changes)
@@ -571,6 +607,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_quota_totalbytes() -> Result<()> {
+        let mut db = new_mem_db();
+        let tx = db.transaction()?;
+        let ext_id = "xyz";
+        // 20 items of 5120 bytes each, counting the key and the quoted value,
+        // is exactly the quota, even though the stored JSON is larger.
+        let val = "x".repeat(5115);
+        for i in 0..20 {
+            set(&tx, &ext_id, json!({ format!("k{:02}", i): val }))?;
+        }
+        assert_eq!(
+            get_bytes_in_use(&tx, &ext_id, json!(null))?,
+            SYNC_QUOTA_BYTES
+        );
+
+        let e = set(&tx, &ext_id, json!({"z": ""})).unwrap_err();
+        match e.kind() {
+            ErrorKind::QuotaError(QuotaReason::TotalBytes) => {}
+            _ => panic!("unexpected error type"),
+        };
+
+        // Replacing an item with a smaller one frees up space.
+        set(&tx, &ext_id, json!({ "k00": "x".repeat(5110) }))?;
+        set(&tx, &ext_id, json!({"z": ""}))?;
+        Ok(())
+    }
+
     #[test]
     fn test_get_bytes_in_use() -> Result<()> {
         let mut db = new_mem_db();
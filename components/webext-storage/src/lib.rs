@@ -14,7 +14,7 @@ pub mod store;
 mod sync;
 
 // We publish some constants from non-public modules.
-pub use sync::STORAGE_VERSION;
+pub use sync::{SyncedExtensionChange, STORAGE_VERSION};
 
 pub use api::SYNC_MAX_ITEMS;
 pub use api::SYNC_QUOTA_BYTES;
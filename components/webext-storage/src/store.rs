@@ -107,6 +107,13 @@ impl Store {
         api::get_bytes_in_use(&self.db, ext_id, keys)
     }
 
+    /// Returns the changes that the last sync made to each extension's data,
+    /// so that they can be passed to `storage.onChanged` listeners. Each
+    /// change is only returned once.
+    pub fn get_synced_changes(&self) -> Result<Vec<sync::SyncedExtensionChange>> {
+        sync::get_synced_changes(&self.db)
+    }
+
     /// Returns a bridged sync engine for Desktop for this store.
     pub fn bridged_engine(&self) -> sync::BridgedEngine<'_> {
         sync::BridgedEngine::new(&self.db)
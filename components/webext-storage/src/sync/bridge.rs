@@ -81,6 +81,8 @@ impl<'a> sync15_traits::BridgedEngine for BridgedEngine<'a> {
 
     fn sync_started(&self) -> Result<()> {
         schema::create_empty_sync_temp_tables(&self.db)?;
+        self.db
+            .execute_batch("DELETE FROM temp.storage_sync_applied;")?;
         Ok(())
     }
 
@@ -11,6 +11,7 @@ use sql_support::ConnExt;
 use sync15_traits::Payload;
 use sync_guid::Guid as SyncGuid;
 
+use crate::api::{self, StorageChanges};
 use crate::error::*;
 
 use super::{merge, remove_matching_keys, JsonMap, Record};
@@ -295,6 +296,24 @@ pub fn plan_incoming(s: IncomingState) -> IncomingAction {
     }
 }
 
+// Notes the changes a sync made to an extension's data, so that they can be
+// passed to `storage.onChanged` listeners.
+fn record_applied_changes(
+    tx: &Transaction<'_>,
+    ext_id: &str,
+    changes: &StorageChanges,
+) -> Result<()> {
+    tx.execute_named_cached(
+        "INSERT OR REPLACE INTO temp.storage_sync_applied (ext_id, changes)
+         VALUES (:ext_id, :changes)",
+        &[
+            (":ext_id", &ext_id),
+            (":changes", &serde_json::to_string(changes)?),
+        ],
+    )?;
+    Ok(())
+}
+
 // Apply the actions necessary to fully process the incoming items.
 pub fn apply_actions(
     tx: &Transaction<'_>,
@@ -305,6 +324,20 @@ pub fn apply_actions(
         signal.err_if_interrupted()?;
 
         log::trace!("action for '{}': {:?}", item.ext_id, action);
+        let old_data = match action {
+            IncomingAction::Same => None,
+            _ => api::get_from_db(tx, &item.ext_id)?,
+        };
+        let changes = match &action {
+            IncomingAction::DeleteLocally => StorageChanges::between(old_data.as_ref(), None),
+            IncomingAction::TakeRemote { data } | IncomingAction::Merge { data } => {
+                StorageChanges::between(old_data.as_ref(), Some(data))
+            }
+            IncomingAction::Same => StorageChanges::new(),
+        };
+        if !changes.is_empty() {
+            record_applied_changes(tx, &item.ext_id, &changes)?;
+        }
         match action {
             IncomingAction::DeleteLocally => {
                 // Can just nuke it entirely.
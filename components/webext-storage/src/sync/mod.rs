@@ -10,8 +10,12 @@ mod outgoing;
 mod sync_tests;
 
 use serde_derive::*;
+use sql_support::ConnExt;
 use sync_guid::Guid as SyncGuid;
 
+use crate::db::StorageDb;
+use crate::error::*;
+
 pub use bridge::BridgedEngine;
 use incoming::IncomingAction;
 
@@ -35,6 +39,43 @@ pub struct Record {
     data: Option<String>,
 }
 
+/// The changes that the last sync made to an extension's data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncedExtensionChange {
+    pub ext_id: String,
+    /// A JSON object in the same format as the `StorageChanges` returned by
+    /// `set`, `remove` and `clear`, ready to pass to `storage.onChanged`
+    /// listeners.
+    pub changes: String,
+}
+
+/// Returns the changes made by the last sync, and forgets them, so they're
+/// only returned once.
+pub fn get_synced_changes(db: &StorageDb) -> Result<Vec<SyncedExtensionChange>> {
+    // The table is only created when we first sync.
+    let have_changes: bool = db.query_one(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_temp_master
+                       WHERE type = 'table' AND name = 'storage_sync_applied')",
+    )?;
+    if !have_changes {
+        return Ok(Vec::new());
+    }
+    let tx = db.unchecked_transaction()?;
+    let changes = tx.query_rows_and_then_named(
+        "SELECT ext_id, changes FROM temp.storage_sync_applied",
+        &[],
+        |row| -> Result<_> {
+            Ok(SyncedExtensionChange {
+                ext_id: row.get("ext_id")?,
+                changes: row.get("changes")?,
+            })
+        },
+    )?;
+    tx.execute_batch("DELETE FROM temp.storage_sync_applied;")?;
+    tx.commit()?;
+    Ok(changes)
+}
+
 // Perform a 2-way or 3-way merge, where the incoming value wins on confict.
 fn merge(mut other: JsonMap, mut ours: JsonMap, parent: Option<JsonMap>) -> IncomingAction {
     if other == ours {
@@ -115,7 +156,6 @@ pub mod test {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::error::*;
     use serde_json::json;
 
     // a macro for these tests - constructs a serde_json::Value::Object
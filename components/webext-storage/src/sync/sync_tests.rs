@@ -501,3 +501,41 @@ fn test_conflicting_incoming() -> Result<()> {
     )?;
     Ok(())
 }
+
+#[test]
+fn test_synced_changes() -> Result<()> {
+    let mut db = new_syncable_mem_db();
+    assert!(crate::sync::get_synced_changes(&db)?.is_empty());
+
+    let tx = db.transaction()?;
+    set(&tx, "ext-id", json!({"a": 1, "b": 2}))?;
+    let payload = Payload::from_record(Record {
+        guid: Guid::from("guid"),
+        ext_id: "ext-id".to_string(),
+        data: Some(json!({"b": 3, "c": 4}).to_string()),
+    })?;
+    let unchanged = Payload::from_record(Record {
+        guid: Guid::from("other-guid"),
+        ext_id: "other-ext-id".to_string(),
+        data: Some(json!({"d": 5}).to_string()),
+    })?;
+    set(&tx, "other-ext-id", json!({"d": 5}))?;
+    do_sync(&tx, vec![payload, unchanged])?;
+    tx.commit()?;
+
+    // Only the extension whose data changed is reported, and only the keys
+    // that changed.
+    let changes = crate::sync::get_synced_changes(&db)?;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].ext_id, "ext-id");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&changes[0].changes)?,
+        json!({
+            "b": {"oldValue": 2, "newValue": 3},
+            "c": {"newValue": 4},
+        })
+    );
+    // And they're only returned once.
+    assert!(crate::sync::get_synced_changes(&db)?.is_empty());
+    Ok(())
+}